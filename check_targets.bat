@@ -49,6 +49,8 @@ if "%1"=="--help" (
     echo   x86_64-unknown-freebsd
     echo   x86_64-unknown-netbsd
     echo   x86_64-unknown-openbsd
+    echo   aarch64-linux-android
+    echo   aarch64-apple-ios
     exit /b 0
 )
 if not "%1"=="" (
@@ -58,7 +60,7 @@ if not "%1"=="" (
 )
 
 :: Supported targets
-set TARGETS=x86_64-pc-windows-msvc x86_64-apple-darwin aarch64-apple-darwin x86_64-unknown-linux-gnu x86_64-unknown-linux-musl aarch64-unknown-linux-gnu x86_64-unknown-freebsd x86_64-unknown-netbsd x86_64-unknown-openbsd
+set TARGETS=x86_64-pc-windows-msvc x86_64-apple-darwin aarch64-apple-darwin x86_64-unknown-linux-gnu x86_64-unknown-linux-musl aarch64-unknown-linux-gnu x86_64-unknown-freebsd x86_64-unknown-netbsd x86_64-unknown-openbsd aarch64-linux-android aarch64-apple-ios
 
 :: Feature configurations
 set CONFIG_NAMES=default full mio-only
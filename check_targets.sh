@@ -25,6 +25,8 @@ TARGETS=(
     "x86_64-unknown-freebsd"      # FreeBSD 64-bit
     "x86_64-unknown-netbsd"       # NetBSD 64-bit
     "x86_64-unknown-openbsd"      # OpenBSD 64-bit
+    "aarch64-linux-android"       # Android ARM64
+    "aarch64-apple-ios"           # iOS ARM64
 )
 
 # Feature configurations to test
@@ -0,0 +1,96 @@
+//! Socket option value types without platform dependencies
+//!
+//! These are the pieces of `horizon_sockets`' configuration that describe
+//! *what* to set rather than how a platform sets it, so a device can parse,
+//! validate, and exchange them with its peers before (or without) opening
+//! a socket.
+
+use core::time::Duration;
+
+/// Source address preferences for IPv6 sockets, combined with `|`
+///
+/// Each pair (temporary/public, home/care-of, CGA/non-CGA) is exclusive;
+/// setting both members of a pair makes applying the config fail with
+/// `InvalidInput`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddrPreferences(u32);
+
+impl AddrPreferences {
+    /// Prefer temporary (privacy, RFC 8981) addresses
+    pub const TEMPORARY: AddrPreferences = AddrPreferences(0x0001);
+    /// Prefer stable public addresses
+    pub const PUBLIC: AddrPreferences = AddrPreferences(0x0002);
+    /// Prefer the care-of address while roaming (Mobile IPv6)
+    pub const CARE_OF: AddrPreferences = AddrPreferences(0x0004);
+    /// Prefer the home address (Mobile IPv6)
+    pub const HOME: AddrPreferences = AddrPreferences(0x0400);
+    /// Prefer cryptographically generated addresses
+    pub const CGA: AddrPreferences = AddrPreferences(0x0008);
+    /// Prefer addresses that are not cryptographically generated
+    pub const NON_CGA: AddrPreferences = AddrPreferences(0x0800);
+
+    /// Wraps raw `IPV6_PREFER_SRC_*` flags, e.g. as read back from a socket
+    pub const fn from_bits(bits: u32) -> Self {
+        AddrPreferences(bits)
+    }
+
+    /// Returns the `IPV6_PREFER_SRC_*` flags
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if every preference in `other` is set
+    pub fn contains(self, other: AddrPreferences) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for AddrPreferences {
+    type Output = AddrPreferences;
+
+    fn bitor(self, rhs: AddrPreferences) -> AddrPreferences {
+        AddrPreferences(self.0 | rhs.0)
+    }
+}
+
+/// Keepalive probe timing for TCP connections
+///
+/// After `idle` without traffic the kernel sends a probe every `interval`
+/// and drops the connection once `retries` probes go unanswered. Times are
+/// rounded down to whole seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// Idle time before the first probe
+    pub idle: Duration,
+    /// Time between unanswered probes
+    pub interval: Duration,
+    /// Unanswered probes before the connection is dropped
+    pub retries: u32,
+}
+
+/// What happens to unsent data when a TCP stream is dropped or closed
+///
+/// Applied through SO_LINGER; see [`linger`](Self::linger).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Close returns immediately; the kernel sends queued data and FIN in the background
+    #[default]
+    Graceful,
+    /// Discard queued data and send RST, freeing the connection immediately (no TIME_WAIT)
+    Abort,
+    /// Block close for up to the given time while queued data is delivered
+    ///
+    /// Sub-second durations are rounded down to whole seconds.
+    Linger(Duration),
+}
+
+impl DropPolicy {
+    /// Returns the SO_LINGER value implementing this policy; `None` leaves lingering off
+    pub fn linger(self) -> Option<Duration> {
+        match self {
+            DropPolicy::Graceful => None,
+            DropPolicy::Abort => Some(Duration::ZERO),
+            DropPolicy::Linger(d) => Some(d),
+        }
+    }
+}
@@ -0,0 +1,560 @@
+//! Fixed-capacity per-flow state table with LRU and TTL eviction
+//!
+//! [`FlowTable`] maps a flow key (a peer address, a connection id) to its
+//! session state at packet rate:
+//!
+//! - **Open addressing**: a flat index of 8-byte slots (hash tag + entry
+//!   number) probed linearly, so a lookup touches one or two cache lines
+//!   before reaching the entry
+//! - **Stable entries**: keys and values live in a separate array and never
+//!   move, so [`prefetch`](FlowTable::prefetch) can warm the index for a
+//!   whole receive batch before the lookups
+//! - **LRU eviction**: inserting into a full table evicts the least recently
+//!   used flow instead of growing
+//! - **TTL expiry**: with [`ttl`](FlowTable::ttl), flows idle longer than the
+//!   TTL disappear from lookups and can be drained with
+//!   [`pop_expired`](FlowTable::pop_expired)
+//!
+//! Time is a tick count supplied by the caller through
+//! [`advance`](FlowTable::advance), in whatever unit its clock has (a
+//! hardware timer, milliseconds since boot), so lookups never read a clock
+//! and the table needs only `alloc`. `horizon_sockets::flow` wraps it with
+//! `Instant` and `Duration`.
+//!
+//! Requires the `alloc` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets_core::flow::{FlowBuildHasher, FlowTable};
+//!
+//! let mut sessions: FlowTable<u32, &str> = FlowTable::with_hasher(2, FlowBuildHasher::with_seed(7)).ttl(100);
+//! sessions.advance(10);
+//! sessions.insert(1, "a");
+//! sessions.advance(120);
+//! assert_eq!(sessions.pop_expired(), Some((1, "a")));
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash, Hasher};
+
+/// Marks an empty index slot or the end of the LRU list
+const NIL: u32 = u32::MAX;
+const EMPTY: u64 = u64::MAX;
+
+/// Fixed-capacity hash map with LRU and TTL eviction
+///
+/// Capacity is set at construction; the table never reallocates.
+#[derive(Debug)]
+pub struct FlowTable<K, V, S = FlowBuildHasher> {
+    /// Open-addressed index: `tag << 32 | entry`, or `EMPTY`
+    index: Vec<u64>,
+    /// `index.len() - 1`
+    mask: usize,
+    /// Entry storage; `index` refers to entries by position
+    entries: Vec<Entry<K, V>>,
+    /// Unused entry positions below `entries.len()`
+    free: Vec<u32>,
+    capacity: usize,
+    len: usize,
+    /// Most recently used entry
+    head: u32,
+    /// Least recently used entry
+    tail: u32,
+    ttl: Option<u64>,
+    now: u64,
+    hasher: S,
+}
+
+#[derive(Debug)]
+struct Entry<K, V> {
+    kv: Option<(K, V)>,
+    prev: u32,
+    next: u32,
+    stamp: u64,
+}
+
+#[cfg(feature = "std")]
+impl<K: Hash + Eq, V> FlowTable<K, V> {
+    /// Creates a table holding up to `capacity` flows, with a randomly seeded hasher
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0 or above 2^30.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, FlowBuildHasher::default())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> FlowTable<K, V, S> {
+    /// Creates a table with a custom hasher
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0 or above 2^30.
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        assert!(capacity > 0 && capacity <= 1 << 30, "capacity must be in 1..=2^30");
+        // Keep the load factor at or below 1/2 so probe sequences stay short
+        let slots = (capacity * 2).next_power_of_two();
+        Self {
+            index: vec![EMPTY; slots],
+            mask: slots - 1,
+            entries: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            capacity,
+            len: 0,
+            head: NIL,
+            tail: NIL,
+            ttl: None,
+            now: 0,
+            hasher,
+        }
+    }
+
+    /// Expires flows that have not been looked up or inserted for `ttl` ticks
+    pub fn ttl(mut self, ttl: u64) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Advances the table's clock to `now` ticks; earlier times are ignored
+    ///
+    /// Call once per event loop iteration. Lookups and inserts stamp entries
+    /// with this time, and expiry is measured against it.
+    pub fn advance(&mut self, now: u64) {
+        self.now = self.now.max(now);
+    }
+
+    /// Returns the number of flows
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the table holds no flows
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of flows
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Hints the CPU to load the index slot for `key`
+    ///
+    /// Issuing prefetches for a whole batch of keys before looking them up
+    /// overlaps the cache misses instead of serializing them.
+    pub fn prefetch(&self, key: &K) {
+        prefetch(&self.index[self.tag(key) as usize & self.mask]);
+    }
+
+    /// Looks up a flow and marks it most recently used
+    ///
+    /// Expired flows are removed and reported as missing.
+    pub fn get(&mut self, key: &K) -> Option<&mut V> {
+        let (_, entry) = self.find(key)?;
+        if self.is_expired(entry) {
+            self.remove(key);
+            return None;
+        }
+        self.touch(entry);
+        self.entries[entry as usize].kv.as_mut().map(|(_, v)| v)
+    }
+
+    /// Looks up a flow without changing its recency
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let (_, entry) = self.find(key)?;
+        if self.is_expired(entry) {
+            return None;
+        }
+        self.entries[entry as usize].kv.as_ref().map(|(_, v)| v)
+    }
+
+    /// Returns `true` if the table holds an unexpired flow for `key`
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Inserts or replaces a flow, marking it most recently used
+    ///
+    /// # Returns
+    ///
+    /// The entry that made room: the previous entry for `key`, or the least
+    /// recently used flow if the table was full.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some((_, entry)) = self.find(&key) {
+            self.touch(entry);
+            return self.entries[entry as usize].kv.replace((key, value));
+        }
+        let evicted = if self.len == self.capacity { self.pop_lru() } else { None };
+        let tag = self.tag(&key);
+        self.insert_new(tag, key, value);
+        evicted
+    }
+
+    /// Returns the flow for `key`, inserting `f()` if it is missing or expired
+    ///
+    /// If the table is full, the least recently used flow is dropped to make
+    /// room; use [`insert`](Self::insert) to observe evictions.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &mut V {
+        let entry = match self.find(&key) {
+            Some((_, entry)) if !self.is_expired(entry) => {
+                self.touch(entry);
+                entry
+            }
+            found => {
+                if found.is_some() {
+                    self.remove(&key);
+                }
+                if self.len == self.capacity {
+                    self.pop_lru();
+                }
+                let tag = self.tag(&key);
+                self.insert_new(tag, key, f())
+            }
+        };
+        self.entries[entry as usize].kv.as_mut().map(|(_, v)| v).expect("entry is occupied")
+    }
+
+    /// Removes a flow, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (slot, entry) = self.find(key)?;
+        self.delete_slot(slot);
+        self.release(entry).map(|(_, v)| v)
+    }
+
+    /// Removes and returns the least recently used flow if it has expired
+    ///
+    /// Always `None` without a TTL. Call in a loop after [`advance`](Self::advance)
+    /// to close idle sessions.
+    pub fn pop_expired(&mut self) -> Option<(K, V)> {
+        if self.tail == NIL || !self.is_expired(self.tail) {
+            return None;
+        }
+        self.pop_lru()
+    }
+
+    /// Removes and returns the least recently used flow
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        let entry = self.tail;
+        let tag = {
+            let (k, _) = self.entries[entry as usize].kv.as_ref().expect("LRU entries are occupied");
+            self.tag(k)
+        };
+        let slot = self.slot_of(tag, entry);
+        self.delete_slot(slot);
+        self.release(entry)
+    }
+
+    /// Removes all flows
+    pub fn clear(&mut self) {
+        self.index.fill(EMPTY);
+        self.entries.clear();
+        self.free.clear();
+        self.len = 0;
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// Iterates over the flows from most to least recently used
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let mut cur = self.head;
+        core::iter::from_fn(move || {
+            if cur == NIL {
+                return None;
+            }
+            let e = &self.entries[cur as usize];
+            cur = e.next;
+            e.kv.as_ref().map(|(k, v)| (k, v))
+        })
+    }
+
+    fn tag(&self, key: &K) -> u32 {
+        let h = self.hasher.hash_one(key);
+        (h ^ (h >> 32)) as u32
+    }
+
+    /// Returns the index slot and entry holding `key`
+    fn find(&self, key: &K) -> Option<(usize, u32)> {
+        let tag = self.tag(key);
+        let mut slot = tag as usize & self.mask;
+        loop {
+            let v = self.index[slot];
+            if v == EMPTY {
+                return None;
+            }
+            if (v >> 32) as u32 == tag {
+                let entry = v as u32;
+                if matches!(&self.entries[entry as usize].kv, Some((k, _)) if k == key) {
+                    return Some((slot, entry));
+                }
+            }
+            slot = (slot + 1) & self.mask;
+        }
+    }
+
+    /// Returns the index slot pointing at `entry`
+    fn slot_of(&self, tag: u32, entry: u32) -> usize {
+        let want = (u64::from(tag) << 32) | u64::from(entry);
+        let mut slot = tag as usize & self.mask;
+        while self.index[slot] != want {
+            slot = (slot + 1) & self.mask;
+        }
+        slot
+    }
+
+    fn insert_new(&mut self, tag: u32, key: K, value: V) -> u32 {
+        let fresh = Entry { kv: Some((key, value)), prev: NIL, next: NIL, stamp: self.now };
+        let entry = match self.free.pop() {
+            Some(e) => {
+                self.entries[e as usize] = fresh;
+                e
+            }
+            None => {
+                self.entries.push(fresh);
+                (self.entries.len() - 1) as u32
+            }
+        };
+        let mut slot = tag as usize & self.mask;
+        while self.index[slot] != EMPTY {
+            slot = (slot + 1) & self.mask;
+        }
+        self.index[slot] = (u64::from(tag) << 32) | u64::from(entry);
+        self.push_front(entry);
+        self.len += 1;
+        entry
+    }
+
+    /// Empties an index slot, shifting later probe entries back to keep chains intact
+    fn delete_slot(&mut self, mut hole: usize) {
+        let mut slot = hole;
+        loop {
+            slot = (slot + 1) & self.mask;
+            let v = self.index[slot];
+            if v == EMPTY {
+                break;
+            }
+            let home = (v >> 32) as usize & self.mask;
+            // Move the entry into the hole unless its home lies cyclically in (hole, slot]
+            if (slot.wrapping_sub(home) & self.mask) >= (slot.wrapping_sub(hole) & self.mask) {
+                self.index[hole] = v;
+                hole = slot;
+            }
+        }
+        self.index[hole] = EMPTY;
+    }
+
+    fn release(&mut self, entry: u32) -> Option<(K, V)> {
+        self.unlink(entry);
+        self.free.push(entry);
+        self.len -= 1;
+        self.entries[entry as usize].kv.take()
+    }
+
+    fn is_expired(&self, entry: u32) -> bool {
+        self.ttl.is_some_and(|ttl| self.now.saturating_sub(self.entries[entry as usize].stamp) >= ttl)
+    }
+
+    fn touch(&mut self, entry: u32) {
+        self.entries[entry as usize].stamp = self.now;
+        if self.head != entry {
+            self.unlink(entry);
+            self.push_front(entry);
+        }
+    }
+
+    fn push_front(&mut self, entry: u32) {
+        let e = &mut self.entries[entry as usize];
+        e.prev = NIL;
+        e.next = self.head;
+        if self.head != NIL {
+            self.entries[self.head as usize].prev = entry;
+        } else {
+            self.tail = entry;
+        }
+        self.head = entry;
+    }
+
+    fn unlink(&mut self, entry: u32) {
+        let (prev, next) = {
+            let e = &self.entries[entry as usize];
+            (e.prev, e.next)
+        };
+        if prev != NIL {
+            self.entries[prev as usize].next = next;
+        } else {
+            self.head = next;
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        } else {
+            self.tail = prev;
+        }
+    }
+}
+
+/// Default hasher for [`FlowTable`]: a seeded multiply-rotate hash
+///
+/// Much cheaper than SipHash for small keys like `SocketAddr`. With the
+/// `std` feature the default seed is random per table, so peers cannot
+/// precompute colliding addresses; without it, seed from a hardware RNG
+/// with [`with_seed`](Self::with_seed). For stronger guarantees against
+/// adaptive attackers use [`FlowTable::with_hasher`] with `RandomState`.
+#[derive(Clone, Debug)]
+pub struct FlowBuildHasher {
+    seed: u64,
+}
+
+impl FlowBuildHasher {
+    /// Creates a hasher with a caller-chosen seed
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for FlowBuildHasher {
+    fn default() -> Self {
+        use core::hash::BuildHasher as _;
+        Self { seed: std::collections::hash_map::RandomState::new().hash_one(0u64) }
+    }
+}
+
+impl BuildHasher for FlowBuildHasher {
+    type Hasher = FlowHasher;
+
+    fn build_hasher(&self) -> FlowHasher {
+        FlowHasher(self.seed)
+    }
+}
+
+/// Hasher state produced by [`FlowBuildHasher`]
+#[derive(Clone, Debug)]
+pub struct FlowHasher(u64);
+
+impl FlowHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for FlowHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add(u64::from(i));
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        let h = self.0;
+        (h ^ (h >> 31)).wrapping_mul(0xbf58_476d_1ce4_e5b9) ^ (h >> 29)
+    }
+}
+
+/// Hints the CPU to load `value`; a no-op where no stable prefetch instruction exists
+#[inline(always)]
+fn prefetch<T>(value: &T) {
+    let ptr = value as *const T as *const u8;
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: prefetching is a hint and never faults; SSE is part of the x86_64 baseline
+    unsafe {
+        core::arch::x86_64::_mm_prefetch::<{ core::arch::x86_64::_MM_HINT_T0 }>(ptr as *const i8);
+    }
+    #[cfg(target_arch = "aarch64")]
+    // SAFETY: PRFM is a hint and never faults
+    unsafe {
+        core::arch::asm!("prfm pldl1keep, [{0}]", in(reg) ptr, options(nostack, readonly, preserves_flags));
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let _ = ptr;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_lru_eviction_and_ttl() {
+        let mut table = FlowTable::with_hasher(3, FlowBuildHasher::with_seed(3)).ttl(10);
+        table.advance(100);
+        assert_eq!(table.insert(1, "a"), None);
+        assert_eq!(table.insert(2, "b"), None);
+        assert_eq!(table.insert(3, "c"), None);
+
+        // Touching 1 makes 2 the least recently used
+        assert_eq!(table.get(&1), Some(&mut "a"));
+        assert_eq!(table.insert(4, "d"), Some((2, "b")));
+        assert_eq!(table.insert(4, "e"), Some((4, "d")));
+        assert_eq!(table.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![4, 1, 3]);
+
+        table.advance(105);
+        table.get(&1);
+        // A clock stepping back does not revive expired flows
+        table.advance(112);
+        table.advance(50);
+        assert!(!table.contains_key(&3));
+        assert_eq!(table.pop_expired(), Some((3, "c")));
+        assert_eq!(table.pop_expired(), Some((4, "e")));
+        assert_eq!(table.pop_expired(), None);
+        assert_eq!(table.len(), 1);
+
+        *table.get_or_insert_with(5, || "f") = "g";
+        assert_eq!(table.peek(&5), Some(&"g"));
+    }
+
+    #[test]
+    fn test_matches_hashmap_under_churn() {
+        let mut table: FlowTable<SocketAddr, u32> = FlowTable::with_hasher(64, FlowBuildHasher::with_seed(1));
+        let mut model = HashMap::new();
+        let mut seed = 0x2545_f491_u32;
+        for i in 0..20_000u32 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let addr = SocketAddr::from(([10, 0, 0, (seed % 40) as u8], 4000 + (seed % 3) as u16));
+            if seed.is_multiple_of(3) {
+                assert_eq!(table.remove(&addr), model.remove(&addr));
+            } else {
+                // 120 distinct keys never all fit; keep the model in sync with evictions
+                if let Some((k, _)) = table.insert(addr, i) {
+                    if k != addr {
+                        model.remove(&k);
+                    }
+                }
+                model.insert(addr, i);
+            }
+            assert_eq!(table.len(), model.len());
+        }
+        for (k, v) in &model {
+            assert_eq!(table.peek(k), Some(v));
+        }
+    }
+}
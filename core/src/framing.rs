@@ -0,0 +1,59 @@
+//! Message framing inside coalesced datagrams
+//!
+//! A sender that packs several messages into one datagram and the receiver
+//! that unpacks them must agree on how messages are delimited. [`Framing`]
+//! is that agreement: `horizon_sockets::send_queue` frames outgoing
+//! messages with it, and a no_std peer splits received datagrams with
+//! [`Framing::split`] without allocating.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets_core::framing::Framing;
+//!
+//! let datagram = [0, 3, b'a', b'c', b'k', 0, 2, b'h', b'i'];
+//! let mut msgs = Framing::LengthPrefix.split(&datagram);
+//! assert_eq!(msgs.next(), Some(&b"ack"[..]));
+//! assert_eq!(msgs.next(), Some(&b"hi"[..]));
+//! assert_eq!(msgs.next(), None);
+//! ```
+
+/// How messages are delimited inside a coalesced datagram
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Messages are concatenated without delimiters
+    #[default]
+    None,
+    /// Each message is preceded by its length as a big-endian `u16`
+    LengthPrefix,
+}
+
+impl Framing {
+    /// Bytes of framing overhead per message
+    pub fn overhead(self) -> usize {
+        match self {
+            Framing::None => 0,
+            Framing::LengthPrefix => 2,
+        }
+    }
+
+    /// Splits a received datagram back into messages
+    ///
+    /// With [`Framing::None`] the whole datagram is yielded as one message.
+    /// Iteration stops at a truncated length-prefixed message.
+    pub fn split(self, datagram: &[u8]) -> impl Iterator<Item = &[u8]> + '_ {
+        let mut rest = Some(datagram);
+        core::iter::from_fn(move || {
+            let data = rest.take()?;
+            match self {
+                Framing::None => (!data.is_empty()).then_some(data),
+                Framing::LengthPrefix => {
+                    let len = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+                    let msg = data.get(2..2 + len)?;
+                    rest = Some(&data[2 + len..]);
+                    Some(msg)
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,40 @@
+//! # Horizon Sockets Core
+//!
+//! The platform-independent parts of `horizon_sockets`, usable without the
+//! standard library. Embedded and RTOS targets that speak the same
+//! protocols as a hosted server can share the packet-level code instead of
+//! keeping a second copy in step:
+//!
+//! - [`checksum`]: Internet checksum with pseudo-headers and CRC32C
+//! - [`replay`]: RFC 6479 sliding-window anti-replay bitmap
+//! - [`config`]: Socket option value types shared with configuration files and control planes
+//! - [`framing`]: Message delimiting inside coalesced datagrams
+//! - `flow` (`alloc` feature): Fixed-capacity flow table with LRU and TTL eviction on a caller-supplied clock
+//!
+//! Nothing here touches the OS, and only `flow` allocates. The `std`
+//! feature adds runtime CPU detection for hardware CRC32C and a randomly
+//! seeded default flow hasher; `horizon_sockets` enables it and re-exports
+//! these modules under the same paths (`flow` behind an `Instant`-based
+//! wrapper).
+
+#![no_std]
+#![deny(unsafe_op_in_unsafe_fn)]
+#![warn(missing_docs)]
+#![warn(missing_debug_implementations)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+/// Internet checksum and CRC32C utilities
+pub mod checksum;
+/// Socket option value types
+pub mod config;
+/// Flow table with LRU and TTL eviction
+#[cfg(feature = "alloc")]
+pub mod flow;
+/// Message framing inside coalesced datagrams
+pub mod framing;
+/// Sliding-window replay protection for sequence-numbered datagrams
+pub mod replay;
@@ -0,0 +1,176 @@
+//! Sliding-window replay protection for datagram protocols
+//!
+//! Authenticated datagram protocols (DTLS, IPsec ESP, QUIC, game
+//! protocols with signed packets) must also reject packets an attacker
+//! captured and sent again. A valid MAC does not help: a replayed packet
+//! is authentic. The standard defence is a window over packet sequence
+//! numbers: accept each number once, accept numbers a little behind the
+//! highest seen (datagrams reorder), and reject anything older.
+//!
+//! [`ReplayWindow`] is the bitmap from RFC 6479, as used by IPsec
+//! implementations. Bits live in a ring of 64-bit words indexed by
+//! sequence number, so sliding the window forward clears whole words
+//! instead of shifting the bitmap, and checking or marking a sequence
+//! number is a mask and a load with no data-dependent loop. Window size
+//! is `(WORDS - 1) * 64` sequence numbers; the default of 32 words covers
+//! 1984.
+//!
+//! Check before decrypting and mark only after the packet authenticates;
+//! otherwise forged packets with large sequence numbers could slide the
+//! window and lock out real traffic.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets_core::replay::{ReplayStatus, ReplayWindow};
+//!
+//! let mut window: ReplayWindow = ReplayWindow::new();
+//! # let authenticate = |_: u64| true;
+//! for seq in [1u64, 3, 2, 3, 10_000, 5] {
+//!     if !window.check(seq).is_fresh() {
+//!         continue; // drop before spending time on crypto
+//!     }
+//!     if authenticate(seq) {
+//!         window.update(seq);
+//!     }
+//! }
+//! assert_eq!(window.highest(), Some(10_000));
+//! assert_eq!(window.check(5), ReplayStatus::TooOld);
+//! ```
+
+/// Result of checking a sequence number against the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayStatus {
+    /// Not seen before and within or ahead of the window
+    Fresh,
+    /// Already accepted once
+    Duplicate,
+    /// Behind the window; whether it was seen is no longer known
+    TooOld,
+}
+
+impl ReplayStatus {
+    /// Returns `true` for [`ReplayStatus::Fresh`]
+    pub fn is_fresh(self) -> bool {
+        self == ReplayStatus::Fresh
+    }
+}
+
+/// Anti-replay bitmap over the most recent `(WORDS - 1) * 64` sequence numbers
+///
+/// `WORDS` must be a power of two, at least 2.
+#[derive(Clone, Debug)]
+pub struct ReplayWindow<const WORDS: usize = 32> {
+    bitmap: [u64; WORDS],
+    /// Highest sequence number accepted, if any
+    top: Option<u64>,
+}
+
+impl<const WORDS: usize> ReplayWindow<WORDS> {
+    /// Sequence numbers covered behind the highest accepted one
+    pub const SIZE: u64 = (WORDS as u64 - 1) * 64;
+
+    /// Creates an empty window
+    pub fn new() -> Self {
+        const { assert!(WORDS >= 2 && WORDS.is_power_of_two(), "WORDS must be a power of two, at least 2") };
+        Self { bitmap: [0; WORDS], top: None }
+    }
+
+    /// Returns the highest accepted sequence number
+    pub fn highest(&self) -> Option<u64> {
+        self.top
+    }
+
+    /// Checks `seq` without recording it
+    pub fn check(&self, seq: u64) -> ReplayStatus {
+        let Some(top) = self.top else { return ReplayStatus::Fresh };
+        if seq > top {
+            return ReplayStatus::Fresh;
+        }
+        if top - seq >= Self::SIZE {
+            return ReplayStatus::TooOld;
+        }
+        let (word, mask) = Self::slot(seq);
+        if self.bitmap[word] & mask == 0 { ReplayStatus::Fresh } else { ReplayStatus::Duplicate }
+    }
+
+    /// Records `seq` as accepted, sliding the window if it is ahead
+    ///
+    /// Returns the status `seq` had before the call; the window only
+    /// changes for [`ReplayStatus::Fresh`].
+    pub fn update(&mut self, seq: u64) -> ReplayStatus {
+        let status = self.check(seq);
+        if status != ReplayStatus::Fresh {
+            return status;
+        }
+        match self.top {
+            Some(top) if seq <= top => {}
+            Some(top) => {
+                // Clear the words entering the window, at most the whole ring
+                let (old, new) = (top / 64, seq / 64);
+                let clear = (new - old).min(WORDS as u64);
+                for i in 1..=clear {
+                    self.bitmap[((old + i) % WORDS as u64) as usize] = 0;
+                }
+                self.top = Some(seq);
+            }
+            None => self.top = Some(seq),
+        }
+        let (word, mask) = Self::slot(seq);
+        self.bitmap[word] |= mask;
+        ReplayStatus::Fresh
+    }
+
+    /// Forgets all sequence numbers, e.g. after rekeying
+    pub fn reset(&mut self) {
+        self.bitmap = [0; WORDS];
+        self.top = None;
+    }
+
+    fn slot(seq: u64) -> (usize, u64) {
+        (((seq / 64) % WORDS as u64) as usize, 1 << (seq % 64))
+    }
+}
+
+impl<const WORDS: usize> Default for ReplayWindow<WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reordered_duplicate_and_old() {
+        let mut window: ReplayWindow<4> = ReplayWindow::new();
+        assert_eq!(ReplayWindow::<4>::SIZE, 192);
+        assert_eq!(window.update(0), ReplayStatus::Fresh);
+        assert_eq!(window.update(100), ReplayStatus::Fresh);
+        assert_eq!(window.update(50), ReplayStatus::Fresh);
+        assert_eq!(window.update(50), ReplayStatus::Duplicate);
+        assert_eq!(window.check(0), ReplayStatus::Duplicate);
+
+        window.update(300);
+        assert_eq!(window.check(100), ReplayStatus::TooOld);
+        assert_eq!(window.check(109), ReplayStatus::Fresh);
+        assert_eq!(window.check(300), ReplayStatus::Duplicate);
+        assert_eq!(window.highest(), Some(300));
+    }
+
+    #[test]
+    fn test_large_jump_clears_stale_bits() {
+        let mut window: ReplayWindow<4> = ReplayWindow::new();
+        for seq in 0..256 {
+            window.update(seq);
+        }
+        // Jumping by exactly the ring size reuses the same words; none may stay set
+        window.update(256 + 256);
+        for seq in (512 - 191)..512 {
+            assert_eq!(window.check(seq), ReplayStatus::Fresh, "seq {}", seq);
+        }
+        window.reset();
+        assert_eq!(window.check(0), ReplayStatus::Fresh);
+    }
+}
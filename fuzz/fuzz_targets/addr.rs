@@ -0,0 +1,19 @@
+#![no_main]
+
+use horizon_sockets::addr::{fmt_addr_into, parse_addr, MAX_ADDR_LEN};
+use libfuzzer_sys::fuzz_target;
+use std::net::SocketAddr;
+
+fuzz_target!(|data: &[u8]| {
+    // The fast path must accept exactly what the standard parser accepts
+    let parsed = parse_addr(data);
+    let expected = std::str::from_utf8(data).ok().and_then(|s| s.parse::<SocketAddr>().ok());
+    assert_eq!(parsed, expected);
+
+    if let Some(addr) = parsed {
+        let mut buf = [0u8; MAX_ADDR_LEN];
+        let n = fmt_addr_into(&addr, &mut buf).unwrap();
+        assert_eq!(&buf[..n], addr.to_string().as_bytes());
+        assert_eq!(parse_addr(&buf[..n]), Some(addr));
+    }
+});
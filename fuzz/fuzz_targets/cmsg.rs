@@ -0,0 +1,14 @@
+#![no_main]
+
+use horizon_sockets::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut total = 0;
+    for cmsg in parse::cmsgs(data) {
+        total += cmsg.data.len();
+        // Decoders downstream of the walker must also cope with any payload
+        let _ = parse::sockaddr(cmsg.data);
+    }
+    assert!(total <= data.len());
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use horizon_sockets::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    for cpus in [parse::cpu_list(data), parse::cpu_mask(data)].into_iter().flatten() {
+        assert!(cpus.windows(2).all(|w| w[0] <= w[1]));
+        assert!(cpus.iter().all(|&cpu| cpu < parse::MAX_CPUS));
+    }
+});
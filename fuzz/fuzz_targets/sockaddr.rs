@@ -0,0 +1,11 @@
+#![no_main]
+
+use horizon_sockets::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(addr) = parse::sockaddr(data) {
+        // Whatever decodes must be a family the caller can use
+        assert!(addr.is_ipv4() || addr.is_ipv6());
+    }
+});
@@ -0,0 +1,112 @@
+/*
+ * C API for horizon_sockets, built with the `capi` feature:
+ *
+ *     cargo rustc --release --features capi --crate-type cdylib
+ *
+ * Every call returns an HsError; HS_OK is 0. Sockets are non-blocking:
+ * wait for HS_WOULD_BLOCK operations with hs_poll. Handles are freed with
+ * the matching hs_*_free. See src/capi.rs for the full contract.
+ */
+#ifndef HORIZON_SOCKETS_H
+#define HORIZON_SOCKETS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum HsError {
+    HS_OK = 0,
+    HS_WOULD_BLOCK = 1,
+    HS_INVALID_ARGUMENT = 2,
+    HS_ADDR_IN_USE = 3,
+    HS_ADDR_NOT_AVAILABLE = 4,
+    HS_CONNECTION_REFUSED = 5,
+    HS_CONNECTION_RESET = 6,
+    HS_NOT_CONNECTED = 7,
+    HS_TIMED_OUT = 8,
+    HS_PERMISSION_DENIED = 9,
+    HS_UNSUPPORTED = 10,
+    HS_IO = 11,
+    HS_PANIC = 12
+} HsError;
+
+/* family is 4 or 6; port is in host byte order; IPv4 uses ip[0..4] */
+typedef struct HsAddr {
+    uint8_t family;
+    uint16_t port;
+    uint8_t ip[16];
+    uint32_t scope_id;
+} HsAddr;
+
+/* capacity writable bytes at data, len of them in use */
+typedef struct HsBuffer {
+    uint8_t *data;
+    size_t len;
+    size_t capacity;
+} HsBuffer;
+
+typedef enum HsSocketKind {
+    HS_SOCKET_UDP = 0,
+    HS_SOCKET_TCP_STREAM = 1,
+    HS_SOCKET_TCP_LISTENER = 2
+} HsSocketKind;
+
+#define HS_READABLE 1
+#define HS_WRITABLE 2
+#define HS_ERROR 4
+#define HS_HANGUP 8
+
+/* kind holds an HsSocketKind; other values fail with HS_INVALID_ARGUMENT */
+typedef struct HsPollItem {
+    uint32_t kind;
+    const void *socket;
+    uint8_t revents;
+} HsPollItem;
+
+typedef struct HsUdp HsUdp;
+typedef struct HsTcpStream HsTcpStream;
+typedef struct HsTcpListener HsTcpListener;
+typedef struct HsBufferPool HsBufferPool;
+
+int32_t hs_last_os_error(void);
+HsError hs_init(void);
+
+HsError hs_addr_parse(const char *text, HsAddr *out);
+HsError hs_addr_format(const HsAddr *addr, char *buf, size_t cap, size_t *len);
+
+/* profile names a NetConfig profile such as "low-latency"; NULL for the default */
+HsError hs_udp_bind(const HsAddr *addr, const char *profile, HsUdp **out);
+void hs_udp_free(HsUdp *udp);
+HsError hs_udp_local_addr(const HsUdp *udp, HsAddr *out);
+HsError hs_udp_send_to(const HsUdp *udp, const uint8_t *buf, size_t len, const HsAddr *dest, size_t *sent);
+HsError hs_udp_recv_from(const HsUdp *udp, uint8_t *buf, size_t cap, size_t *len, HsAddr *from);
+
+/* waits up to timeout_ms for the handshake (< 0: OS default; 0 is invalid) */
+HsError hs_tcp_connect(const HsAddr *addr, const char *profile, int32_t timeout_ms, HsTcpStream **out);
+void hs_tcp_free(HsTcpStream *stream);
+HsError hs_tcp_send(const HsTcpStream *stream, const uint8_t *buf, size_t len, size_t *sent);
+/* *received == 0 means the peer closed the connection */
+HsError hs_tcp_recv(const HsTcpStream *stream, uint8_t *buf, size_t cap, size_t *received);
+
+HsError hs_tcp_listen(const HsAddr *addr, const char *profile, HsTcpListener **out);
+void hs_tcp_listener_free(HsTcpListener *listener);
+HsError hs_tcp_listener_local_addr(const HsTcpListener *listener, HsAddr *out);
+HsError hs_tcp_accept(const HsTcpListener *listener, HsTcpStream **out, HsAddr *peer);
+
+/* timeout_ms < 0 waits forever */
+HsError hs_poll(HsPollItem *items, size_t n, uint8_t interest, int32_t timeout_ms, size_t *ready);
+
+/* returns NULL on failure */
+HsBufferPool *hs_pool_new(size_t count, size_t capacity);
+void hs_pool_free(HsBufferPool *pool);
+HsError hs_pool_acquire(const HsBufferPool *pool, HsBuffer *out);
+HsError hs_pool_release(const HsBufferPool *pool, HsBuffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,173 @@
+//! Accept backpressure: pausing listener interest while workers are overloaded
+//!
+//! An acceptor that keeps accepting while its workers fall behind only
+//! moves the backlog from the kernel into memory: every accepted
+//! connection gets buffers and queue slots, and under sustained overload
+//! the process eventually runs out of memory. Leaving connections in the
+//! listen backlog sheds load where it is cheap instead. Once the backlog
+//! fills, the kernel refuses or drops new SYNs and clients back off and
+//! retry.
+//!
+//! [`AcceptGate`] does this for a mio event loop.
+//! [`pause_accepting`](AcceptGate::pause_accepting) deregisters the
+//! listener, so its READABLE events stop waking the loop, and
+//! [`resume_accepting`](AcceptGate::resume_accepting) registers it again.
+//! Connections that queued up in the meantime are reported as soon as it
+//! is back. [`update`](AcceptGate::update) applies both from a queue
+//! depth with hysteresis: it pauses at the high watermark and resumes only
+//! once the queue has drained to the low one, so the listener does not
+//! flap around a single threshold.
+//!
+//! Requires the `mio-runtime` feature.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::accept_gate::AcceptGate;
+//! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use mio::{Events, Poll, Token};
+//! use std::collections::VecDeque;
+//!
+//! const LISTENER: Token = Token(0);
+//! let mut poll = Poll::new()?;
+//! let mut events = Events::with_capacity(256);
+//! let mut listener = TcpListener::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+//! let mut gate = AcceptGate::new(LISTENER, 10_000, 5_000);
+//! gate.register(poll.registry(), &mut listener)?;
+//!
+//! let mut work = VecDeque::new();
+//! loop {
+//!     poll.poll(&mut events, None)?;
+//!     for event in events.iter() {
+//!         if event.token() == LISTENER {
+//!             for conn in listener.try_incoming() {
+//!                 work.push_back(conn?.0);
+//!             }
+//!         }
+//!     }
+//!     // ... hand connections to workers, popping from `work` ...
+//!     gate.update(poll.registry(), &mut listener, work.len())?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use std::io;
+
+/// Pauses and resumes a listener's READABLE registration around queue watermarks
+#[derive(Debug)]
+pub struct AcceptGate {
+    token: Token,
+    high: usize,
+    low: usize,
+    registered: bool,
+    paused: bool,
+    pauses: u64,
+}
+
+impl AcceptGate {
+    /// Creates a gate for a listener registered under `token`
+    ///
+    /// Accepting pauses when the queue reaches `high` and resumes when it
+    /// falls to `low`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is not below `high`.
+    pub fn new(token: Token, high: usize, low: usize) -> Self {
+        assert!(low < high, "low watermark must be below the high watermark");
+        Self { token, high, low, registered: false, paused: false, pauses: 0 }
+    }
+
+    /// Registers `listener` for READABLE events under the gate's token
+    pub fn register<S: Source + ?Sized>(&mut self, registry: &Registry, listener: &mut S) -> io::Result<()> {
+        registry.register(listener, self.token, Interest::READABLE)?;
+        self.registered = true;
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Stops watching `listener`, leaving new connections in the kernel backlog
+    ///
+    /// Returns `false` if accepting was already paused.
+    pub fn pause_accepting<S: Source + ?Sized>(&mut self, registry: &Registry, listener: &mut S) -> io::Result<bool> {
+        if self.paused {
+            return Ok(false);
+        }
+        if self.registered {
+            registry.deregister(listener)?;
+            self.registered = false;
+        }
+        self.paused = true;
+        self.pauses += 1;
+        Ok(true)
+    }
+
+    /// Watches `listener` again; connections queued while paused are reported on the next poll
+    ///
+    /// Returns `false` if accepting was not paused.
+    pub fn resume_accepting<S: Source + ?Sized>(&mut self, registry: &Registry, listener: &mut S) -> io::Result<bool> {
+        if !self.paused {
+            return Ok(false);
+        }
+        self.register(registry, listener)?;
+        Ok(true)
+    }
+
+    /// Pauses or resumes according to the current worker queue depth
+    ///
+    /// # Returns
+    ///
+    /// `true` if the listener was paused or resumed by this call
+    pub fn update<S: Source + ?Sized>(&mut self, registry: &Registry, listener: &mut S, queued: usize) -> io::Result<bool> {
+        if !self.paused && queued >= self.high {
+            self.pause_accepting(registry, listener)
+        } else if self.paused && queued <= self.low {
+            self.resume_accepting(registry, listener)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns `true` while accepting is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns how many times accepting has been paused
+    pub fn pauses(&self) -> u64 {
+        self.pauses
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{tcp::TcpListener, NetConfig};
+    use mio::{Events, Poll};
+    use std::time::Duration;
+
+    #[test]
+    fn test_paused_listener_is_silent_until_queue_drains() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        let mut listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let mut gate = AcceptGate::new(Token(7), 4, 1);
+        gate.register(poll.registry(), &mut listener).unwrap();
+
+        assert!(!gate.update(poll.registry(), &mut listener, 3).unwrap());
+        assert!(gate.update(poll.registry(), &mut listener, 4).unwrap());
+        let _client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(100))).unwrap();
+        assert!(events.is_empty());
+
+        // Between the watermarks nothing changes
+        assert!(!gate.update(poll.registry(), &mut listener, 2).unwrap());
+        assert!(gate.update(poll.registry(), &mut listener, 1).unwrap());
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(7) && e.is_readable()));
+        assert!(listener.accept_nonblocking().is_ok());
+        assert_eq!((gate.is_paused(), gate.pauses()), (false, 1));
+    }
+}
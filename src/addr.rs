@@ -0,0 +1,231 @@
+//! Socket address formatting and parsing without heap allocation
+//!
+//! `format!("{}", addr)` allocates a `String` for every call, and
+//! per-packet logging, metric labels keyed by peer, and text protocols
+//! that echo addresses make that show up in profiles. [`fmt_addr_into`]
+//! writes the same text into a caller-provided slice, and [`AddrBuf`]
+//! holds it on the stack for use as a `&str`. IPv4 addresses, the common
+//! case, are formatted digit by digit; IPv6 goes through the standard
+//! formatter into the slice, so zero compression and scope IDs come out
+//! exactly as `Display` writes them.
+//!
+//! [`parse_addr`] goes the other way from bytes, e.g. straight out of a
+//! received header, without a UTF-8 check or `String` first. `ip:port`
+//! IPv4 input takes a hand-written fast path; everything else is handed
+//! to the standard parser, and both accept exactly what
+//! `str::parse::<SocketAddr>` accepts.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::addr::{fmt_addr_into, parse_addr, AddrBuf, MAX_ADDR_LEN};
+//!
+//! let addr = parse_addr(b"192.0.2.7:9000").unwrap();
+//! let mut buf = [0u8; MAX_ADDR_LEN];
+//! let n = fmt_addr_into(&addr, &mut buf).unwrap();
+//! assert_eq!(&buf[..n], b"192.0.2.7:9000");
+//! assert_eq!(AddrBuf::new(&addr).as_str(), "192.0.2.7:9000");
+//! ```
+
+use std::fmt::{self, Write as _};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+/// Longest text form of a `SocketAddr`: `[` IPv6 with embedded IPv4 `%` scope `]:` port
+pub const MAX_ADDR_LEN: usize = 1 + 45 + 1 + 10 + 2 + 5;
+
+/// Writes `addr` as `Display` would into `out`
+///
+/// # Returns
+///
+/// The number of bytes written, or `None` if `out` is too short; a slice
+/// of [`MAX_ADDR_LEN`] bytes always fits.
+pub fn fmt_addr_into(addr: &SocketAddr, out: &mut [u8]) -> Option<usize> {
+    match addr {
+        SocketAddr::V4(v4) => {
+            let mut cursor = Cursor { out, len: 0 };
+            for (i, octet) in v4.ip().octets().into_iter().enumerate() {
+                if i > 0 {
+                    cursor.push(b'.')?;
+                }
+                cursor.number(octet.into())?;
+            }
+            cursor.push(b':')?;
+            cursor.number(v4.port().into())?;
+            Some(cursor.len)
+        }
+        SocketAddr::V6(_) => {
+            let mut cursor = Cursor { out, len: 0 };
+            write!(cursor, "{}", addr).ok()?;
+            Some(cursor.len)
+        }
+    }
+}
+
+/// Parses `ip:port` or `[ipv6]:port` bytes into a `SocketAddr`
+///
+/// Accepts the same input as `str::parse::<SocketAddr>`, including
+/// rejecting IPv4 octets with leading zeros. Returns `None` otherwise.
+pub fn parse_addr(bytes: &[u8]) -> Option<SocketAddr> {
+    if let Some(addr) = parse_v4(bytes) {
+        return Some(SocketAddr::V4(addr));
+    }
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// IPv4 fast path; `None` sends the input to the standard parser
+fn parse_v4(bytes: &[u8]) -> Option<SocketAddrV4> {
+    let mut octets = [0u8; 4];
+    let mut rest = bytes;
+    for (i, octet) in octets.iter_mut().enumerate() {
+        let digits = rest.iter().take(4).take_while(|b| b.is_ascii_digit()).count();
+        // One to three digits, no leading zero unless the octet is zero
+        if digits == 0 || digits > 3 || (digits > 1 && rest[0] == b'0') {
+            return None;
+        }
+        let value = rest[..digits].iter().fold(0u16, |v, b| v * 10 + u16::from(b - b'0'));
+        *octet = u8::try_from(value).ok()?;
+        let sep = if i < 3 { b'.' } else { b':' };
+        if rest.get(digits) != Some(&sep) {
+            return None;
+        }
+        rest = &rest[digits + 1..];
+    }
+    if rest.is_empty() || !rest.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let port = rest.iter().try_fold(0u16, |p, b| p.checked_mul(10)?.checked_add(u16::from(b - b'0')))?;
+    Some(SocketAddrV4::new(Ipv4Addr::from(octets), port))
+}
+
+/// Bounded writer over a byte slice
+struct Cursor<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Cursor<'_> {
+    fn push(&mut self, byte: u8) -> Option<()> {
+        *self.out.get_mut(self.len)? = byte;
+        self.len += 1;
+        Some(())
+    }
+
+    fn number(&mut self, mut n: u32) -> Option<()> {
+        let mut digits = [0u8; 10];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        let end = self.len + digits.len() - i;
+        self.out.get_mut(self.len..end)?.copy_from_slice(&digits[i..]);
+        self.len = end;
+        Some(())
+    }
+}
+
+impl fmt::Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.out.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// A socket address formatted on the stack
+#[derive(Clone, Copy)]
+pub struct AddrBuf {
+    buf: [u8; MAX_ADDR_LEN],
+    len: u8,
+}
+
+impl AddrBuf {
+    /// Formats `addr`
+    pub fn new(addr: &SocketAddr) -> Self {
+        let mut buf = [0u8; MAX_ADDR_LEN];
+        let len = fmt_addr_into(addr, &mut buf).expect("MAX_ADDR_LEN fits every address");
+        Self { buf, len: len as u8 }
+    }
+
+    /// Returns the formatted address
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever written, so this never falls back
+        std::str::from_utf8(&self.buf[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl std::ops::Deref for AddrBuf {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for AddrBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl fmt::Debug for AddrBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_matches_display() {
+        let addrs = [
+            "0.0.0.0:0",
+            "255.255.255.255:65535",
+            "10.20.3.40:8080",
+            "[::1]:443",
+            "[2001:db8::1:0:0:1]:53",
+            "[::ffff:192.0.2.128]:1",
+            "[fe80::1%4294967295]:65535",
+        ];
+        for text in addrs {
+            let addr: SocketAddr = text.parse().unwrap();
+            assert_eq!(AddrBuf::new(&addr).as_str(), addr.to_string());
+        }
+
+        let longest: SocketAddr = "[ffff:ffff:ffff:ffff:ffff:ffff:255.255.255.255%4294967295]:65535".parse().unwrap();
+        let mut buf = [0u8; MAX_ADDR_LEN];
+        assert_eq!(fmt_addr_into(&longest, &mut buf), Some(longest.to_string().len()));
+        assert_eq!(fmt_addr_into(&"1.2.3.4:5".parse().unwrap(), &mut buf[..8]), None);
+    }
+
+    #[test]
+    fn test_parse_agrees_with_std() {
+        let inputs: [&[u8]; 14] = [
+            b"127.0.0.1:80",
+            b"1.2.3.4:0065535",
+            b"1.2.3.4:65536",
+            b"01.2.3.4:1",
+            b"0.0.0.0:0",
+            b"256.0.0.1:1",
+            b"1.2.3:4",
+            b"1.2.3.4",
+            b"1.2.3.4:",
+            b"1.2.3.4:+1",
+            b"1.2.3.4.5:1",
+            b"[::1]:9",
+            b"[::1%3]:9",
+            b"\xff:1",
+        ];
+        for input in inputs {
+            let expected = std::str::from_utf8(input).ok().and_then(|s| s.parse::<SocketAddr>().ok());
+            assert_eq!(parse_addr(input), expected, "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+}
@@ -10,6 +10,8 @@
 //! - Providing more predictable latency characteristics
 //! - Enabling NUMA-aware optimizations
 
+use crate::poll::Pollable;
+use crate::raw as r;
 use std::io;
 
 /// Sets the CPU affinity for the current thread to a specific CPU core
@@ -28,20 +30,24 @@ use std::io;
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```rust,no_run
 /// use horizon_sockets::affinity::pin_to_cpu;
 ///
 /// // Pin the current thread to CPU core 2
 /// pin_to_cpu(2)?;
 ///
 /// // Now this thread will preferentially run on CPU core 2
+/// # Ok::<(), std::io::Error>(())
 /// ```
 ///
 /// # Platform Support
 ///
 /// - **Linux/Unix**: Uses `sched_setaffinity` system call
+/// - **FreeBSD**: Uses `cpuset_setaffinity`
+/// - **NetBSD**: Uses `pthread_setaffinity_np` with a dynamic cpuset
+/// - **illumos/Solaris**: Uses `processor_bind` on the calling LWP
 /// - **Windows**: Uses `SetThreadAffinityMask` Win32 API
-/// - **Other platforms**: No-op (returns success but doesn't pin)
+/// - **Other platforms** (macOS, OpenBSD): No-op (returns success but doesn't pin)
 ///
 /// # Performance Notes
 ///
@@ -51,12 +57,13 @@ use std::io;
 /// - Use with NUMA topology awareness for multi-socket systems
 pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
     cfg_if::cfg_if! {
-        if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))] {
+        if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "illumos", target_os = "solaris"))] {
             pin_to_cpu_unix(cpu)
         } else if #[cfg(target_os = "windows")] {
             pin_to_cpu_windows(cpu)
         } else {
             // Unsupported platform - return success but don't actually pin
+            let _ = cpu;
             Ok(())
         }
     }
@@ -102,13 +109,17 @@ pub fn get_cpu_count() -> usize {
 ///
 /// `Ok(())` on success, or an `io::Error` if the operation fails
 ///
+/// illumos and Solaris can bind a thread to one processor only; a list of
+/// several CPUs fails with `Unsupported` there (use processor sets instead).
+///
 /// # Examples
 ///
-/// ```rust
+/// ```rust,no_run
 /// use horizon_sockets::affinity::pin_to_cpus;
 ///
 /// // Allow thread to run on cores 2, 3, 4, or 5
 /// pin_to_cpus(&[2, 3, 4, 5])?;
+/// # Ok::<(), std::io::Error>(())
 /// ```
 pub fn pin_to_cpus(cpus: &[usize]) -> io::Result<()> {
     if cpus.is_empty() {
@@ -119,7 +130,7 @@ pub fn pin_to_cpus(cpus: &[usize]) -> io::Result<()> {
     }
 
     cfg_if::cfg_if! {
-        if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))] {
+        if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "illumos", target_os = "solaris"))] {
             pin_to_cpus_unix(cpus)
         } else if #[cfg(target_os = "windows")] {
             pin_to_cpus_windows(cpus)
@@ -155,7 +166,7 @@ pub fn get_numa_topology() -> Vec<Vec<usize>> {
         if #[cfg(target_os = "linux")] {
             get_numa_topology_linux().unwrap_or_else(|_| {
                 // Fallback: single NUMA node with all CPUs
-                vec![vec![0; get_cpu_count()]]
+                vec![(0..get_cpu_count()).collect()]
             })
         } else {
             // Default: assume single NUMA node with all CPUs
@@ -164,6 +175,135 @@ pub fn get_numa_topology() -> Vec<Vec<usize>> {
     }
 }
 
+/// Transmit queue to CPU mapping for XPS (Transmit Packet Steering)
+///
+/// XPS picks the NIC transmit queue from the CPU that sends a packet. When
+/// each queue is mapped to the CPUs that use it, transmit completions are
+/// processed on the sending core and queue locks are not shared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XpsQueue {
+    /// Transmit queue index (`tx-<queue>` in sysfs)
+    pub queue: usize,
+    /// CPUs that transmit on this queue
+    pub cpus: Vec<usize>,
+}
+
+impl XpsQueue {
+    /// Formats the CPUs as the hex mask written to `queues/tx-N/xps_cpus`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use horizon_sockets::affinity::XpsQueue;
+    ///
+    /// let q = XpsQueue { queue: 0, cpus: vec![0, 1, 33] };
+    /// assert_eq!(q.mask(), "00000002,00000003");
+    /// // echo 00000002,00000003 > /sys/class/net/eth0/queues/tx-0/xps_cpus
+    /// ```
+    pub fn mask(&self) -> String {
+        let words = self.cpus.iter().max().map_or(1, |&max| max / 32 + 1);
+        let mut mask = vec![0u32; words];
+        for &cpu in &self.cpus {
+            mask[cpu / 32] |= 1 << (cpu % 32);
+        }
+        mask.iter().rev().map(|w| format!("{:08x}", w)).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Suggests an XPS mapping for a NIC with `tx_queues` transmit queues
+///
+/// CPUs are taken NUMA node by node from [`get_numa_topology`] and split
+/// into contiguous blocks, so each queue is served by CPUs of one node where
+/// possible. With more queues than CPUs, CPUs are reused round-robin.
+///
+/// Apply the result by writing [`XpsQueue::mask`] to
+/// `/sys/class/net/<iface>/queues/tx-<queue>/xps_cpus` (requires root).
+pub fn suggest_xps(tx_queues: usize) -> Vec<XpsQueue> {
+    let cpus: Vec<usize> = get_numa_topology().into_iter().flatten().collect();
+    suggest_xps_for(&cpus, tx_queues)
+}
+
+fn suggest_xps_for(cpus: &[usize], tx_queues: usize) -> Vec<XpsQueue> {
+    if cpus.is_empty() {
+        return Vec::new();
+    }
+    (0..tx_queues)
+        .map(|queue| {
+            let cpus = if tx_queues >= cpus.len() {
+                vec![cpus[queue % cpus.len()]]
+            } else {
+                cpus[queue * cpus.len() / tx_queues..(queue + 1) * cpus.len() / tx_queues].to_vec()
+            };
+            XpsQueue { queue, cpus }
+        })
+        .collect()
+}
+
+/// Reads the current XPS mapping of a network interface from sysfs
+///
+/// Queues without an XPS mask (or with XPS disabled) report no CPUs.
+///
+/// # Platform Support
+///
+/// Linux only; other platforms return `Unsupported`.
+pub fn read_xps(interface: &str) -> io::Result<Vec<XpsQueue>> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            let mut out = Vec::new();
+            for queue in 0.. {
+                let path = format!("/sys/class/net/{}/queues/tx-{}", interface, queue);
+                if !std::path::Path::new(&path).exists() {
+                    break;
+                }
+                let cpus = match std::fs::read_to_string(format!("{}/xps_cpus", path)) {
+                    Ok(mask) => parse_cpu_mask(mask.trim())?,
+                    Err(_) => Vec::new(),
+                };
+                out.push(XpsQueue { queue, cpus });
+            }
+            if out.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Interface has no transmit queues"));
+            }
+            Ok(out)
+        } else {
+            let _ = interface;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "XPS is Linux only"))
+        }
+    }
+}
+
+/// Returns the transmit queue XPS selects for packets sent from `cpu`
+pub fn tx_queue_for_cpu(xps: &[XpsQueue], cpu: usize) -> Option<usize> {
+    xps.iter().find(|q| q.cpus.contains(&cpu)).map(|q| q.queue)
+}
+
+/// Aligns a send-heavy socket with the CPU its thread is pinned to
+///
+/// Sets `SO_INCOMING_CPU` so receive steering and `SO_REUSEPORT` selection
+/// favour `cpu`. Combined with [`pin_to_cpu`] on the sending thread and an
+/// XPS mapping from [`suggest_xps`], transmit, completion, and receive work
+/// for the socket all stay on one core.
+///
+/// # Platform Support
+///
+/// Linux only; a no-op elsewhere.
+pub fn align_socket_to_cpu(socket: &dyn Pollable, cpu: usize) -> io::Result<()> {
+    let cpu = i32::try_from(cpu).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "CPU number too large"))?;
+    r::set_incoming_cpu(socket.poll_handle(), cpu)
+}
+
+/// Returns the CPU that processed the socket's most recent packet
+///
+/// `Ok(None)` means no packet has been received yet.
+///
+/// # Platform Support
+///
+/// Linux only; other platforms return `Unsupported`.
+pub fn incoming_cpu(socket: &dyn Pollable) -> io::Result<Option<usize>> {
+    let cpu = r::get_incoming_cpu(socket.poll_handle())?;
+    Ok(usize::try_from(cpu).ok())
+}
+
 // Unix/Linux implementation
 #[cfg(any(target_os = "linux", target_os = "android"))]
 fn pin_to_cpu_unix(cpu: usize) -> io::Result<()> {
@@ -286,6 +426,66 @@ fn pin_to_cpus_unix(cpus: &[usize]) -> io::Result<()> {
     Ok(())
 }
 
+// NetBSD implementation: cpusets are opaque and sized at runtime
+#[cfg(target_os = "netbsd")]
+fn pin_to_cpu_unix(cpu: usize) -> io::Result<()> {
+    pin_to_cpus_unix(&[cpu])
+}
+
+#[cfg(target_os = "netbsd")]
+fn pin_to_cpus_unix(cpus: &[usize]) -> io::Result<()> {
+    use libc::{_cpuset_create, _cpuset_destroy, _cpuset_set, _cpuset_size, pthread_self, pthread_setaffinity_np};
+
+    unsafe {
+        let set = _cpuset_create();
+        if set.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        for &cpu in cpus {
+            if _cpuset_set(cpu as libc::cpuid_t, set) != 0 {
+                _cpuset_destroy(set);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("CPU number {} is out of range", cpu),
+                ));
+            }
+        }
+        let rc = pthread_setaffinity_np(pthread_self(), _cpuset_size(set), set);
+        _cpuset_destroy(set);
+        if rc != 0 {
+            return Err(io::Error::from_raw_os_error(rc));
+        }
+    }
+
+    Ok(())
+}
+
+// illumos/Solaris implementation: binds the calling LWP to one processor
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+fn pin_to_cpu_unix(cpu: usize) -> io::Result<()> {
+    /// `P_MYID` from `<sys/procset.h>`: the calling LWP
+    const P_MYID: libc::id_t = -1;
+
+    let cpu = libc::processorid_t::try_from(cpu)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "CPU number too large"))?;
+    if unsafe { libc::processor_bind(libc::P_LWPID, P_MYID, cpu, std::ptr::null_mut()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+fn pin_to_cpus_unix(cpus: &[usize]) -> io::Result<()> {
+    match cpus {
+        [cpu] => pin_to_cpu_unix(*cpu),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "processor_bind binds to a single CPU; use a processor set for several",
+        )),
+    }
+}
+
 // Windows implementation
 #[cfg(target_os = "windows")]
 fn pin_to_cpu_windows(cpu: usize) -> io::Result<()> {
@@ -353,7 +553,7 @@ fn get_numa_topology_linux() -> io::Result<Vec<Vec<usize>>> {
         // Read CPU list for this NUMA node
         let cpulist_path = format!("{}/cpulist", node_path);
         if let Ok(cpulist) = fs::read_to_string(&cpulist_path) {
-            let cpus = parse_cpu_list(&cpulist.trim())?;
+            let cpus = parse_cpu_list(cpulist.trim())?;
             topology.push(cpus);
         }
 
@@ -370,41 +570,9 @@ fn get_numa_topology_linux() -> io::Result<Vec<Vec<usize>>> {
     Ok(topology)
 }
 
-// Parse Linux CPU list format (e.g., "0-3,8-11")
+// sysfs CPU lists and masks are parsed by the fuzzed decoders in `parse`
 #[cfg(target_os = "linux")]
-fn parse_cpu_list(cpu_list: &str) -> io::Result<Vec<usize>> {
-    let mut cpus = Vec::new();
-
-    for range in cpu_list.split(',') {
-        let range = range.trim();
-        if range.is_empty() {
-            continue;
-        }
-
-        if let Some(dash_pos) = range.find('-') {
-            // Range format: "0-3"
-            let start: usize = range[..dash_pos]
-                .parse()
-                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid CPU range"))?;
-            let end: usize = range[dash_pos + 1..]
-                .parse()
-                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid CPU range"))?;
-
-            for cpu in start..=end {
-                cpus.push(cpu);
-            }
-        } else {
-            // Single CPU: "8"
-            let cpu: usize = range
-                .parse()
-                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid CPU number"))?;
-            cpus.push(cpu);
-        }
-    }
-
-    cpus.sort_unstable();
-    Ok(cpus)
-}
+use crate::parse::{cpu_list as parse_cpu_list, cpu_mask as parse_cpu_mask};
 
 #[cfg(test)]
 mod tests {
@@ -446,4 +614,34 @@ mod tests {
         assert_eq!(parse_cpu_list("0-3").unwrap(), vec![0, 1, 2, 3]);
         assert_eq!(parse_cpu_list("0-2,8-10").unwrap(), vec![0, 1, 2, 8, 9, 10]);
     }
+
+    #[test]
+    fn test_suggest_xps_blocks() {
+        let xps = suggest_xps_for(&[0, 1, 2, 3, 4, 5], 2);
+        assert_eq!(xps[0].cpus, vec![0, 1, 2]);
+        assert_eq!(xps[1].cpus, vec![3, 4, 5]);
+        assert_eq!(tx_queue_for_cpu(&xps, 4), Some(1));
+
+        let xps = suggest_xps_for(&[0, 1], 3);
+        assert_eq!(xps.iter().map(|q| q.cpus[0]).collect::<Vec<_>>(), vec![0, 1, 0]);
+        assert!(suggest_xps_for(&[], 4).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cpu_mask_roundtrip() {
+        let q = XpsQueue { queue: 0, cpus: vec![0, 3, 32, 63] };
+        assert_eq!(q.mask(), "80000001,00000009");
+        assert_eq!(parse_cpu_mask(&q.mask()).unwrap(), q.cpus);
+        assert_eq!(parse_cpu_mask("0").unwrap(), Vec::<usize>::new());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_align_socket_to_cpu() {
+        let cfg = crate::NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        align_socket_to_cpu(&socket, 0).unwrap();
+        let _ = incoming_cpu(&socket).unwrap();
+    }
 }
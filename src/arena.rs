@@ -0,0 +1,227 @@
+//! Contiguous receive arena for batch UDP receives
+//!
+//! [`Udp::recv_batch`](crate::udp::Udp::recv_batch) fills one `Vec` per
+//! packet, scattered across the heap. A [`RecvArena`] is one allocation
+//! split into fixed-size segments; [`Udp::recv_batch_arena`] receives a
+//! packet into each segment and records its offset, length, and sender in
+//! a [`Segment`] descriptor. Consecutive packets sit next to each other in
+//! memory, which helps the prefetcher, and the whole batch can be handed
+//! to a GPU upload, a compressor, or a shared-memory ring as a single
+//! region with the descriptor array alongside.
+//!
+//! Segments start on cache-line boundaries, so the stride between them may
+//! be slightly larger than the requested segment size.
+//!
+//! Every segment also carries a monotonic receive time, on every platform
+//! and without kernel timestamping, so latency accounting (queueing delay
+//! before processing, one-way delay against a synced sender) starts from
+//! the same baseline everywhere. Packets returned by one `recvmmsg` call
+//! share the time the call returned; elsewhere each packet is stamped as it
+//! is read. [`coarse_timestamps`](RecvArena::coarse_timestamps) and
+//! [`clock`](RecvArena::clock) trade precision for a cheaper clock read.
+//!
+//! [`Udp::recv_batch_arena`]: crate::udp::Udp::recv_batch_arena
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::{NetConfig, arena::RecvArena, udp::Udp};
+//!
+//! let cfg = NetConfig::default();
+//! let rx = Udp::bind("127.0.0.1:0".parse()?, &cfg)?;
+//! let tx = Udp::bind("127.0.0.1:0".parse()?, &cfg)?;
+//! tx.send_to(b"first", rx.local_addr()?)?;
+//! tx.send_to(b"second", rx.local_addr()?)?;
+//! std::thread::sleep(std::time::Duration::from_millis(20));
+//!
+//! let mut arena = RecvArena::new(32, 1500);
+//! let count = rx.recv_batch_arena(&mut arena)?;
+//! assert_eq!(count, 2);
+//! assert_eq!(arena.packet(1), Some(&b"second"[..]));
+//! assert_eq!(arena.segments()[1].offset, arena.stride());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::clock::{Clock, ClockSource};
+use crate::hotpath::CACHE_LINE;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Location and sender of one packet in a [`RecvArena`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Byte offset of the packet from the start of the arena
+    pub offset: usize,
+    /// Packet length in bytes
+    pub len: usize,
+    /// Sender address
+    pub addr: SocketAddr,
+    /// Monotonic time the packet was taken off the socket
+    pub(crate) received_at: Instant,
+}
+
+impl Segment {
+    /// Returns the monotonic time the packet was taken off the socket
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Returns the byte range of the packet within the arena
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// One contiguous buffer sliced into fixed-size receive segments
+#[derive(Clone, Debug)]
+pub struct RecvArena {
+    storage: Vec<u8>,
+    segment_size: usize,
+    stride: usize,
+    segments: Vec<Segment>,
+    clock: Clock,
+}
+
+impl RecvArena {
+    /// Allocates an arena of `count` segments of `segment_size` bytes each
+    ///
+    /// Packets longer than `segment_size` are truncated, as with undersized
+    /// buffers passed to `recv_batch`.
+    pub fn new(count: usize, segment_size: usize) -> Self {
+        let segment_size = segment_size.max(1);
+        let stride = segment_size.next_multiple_of(CACHE_LINE);
+        Self { storage: vec![0; count * stride], segment_size, stride, segments: Vec::with_capacity(count), clock: Clock::default() }
+    }
+
+    /// Stamps packets from the coarse monotonic clock
+    ///
+    /// On Linux this reads `CLOCK_MONOTONIC_COARSE`, which costs a few
+    /// nanoseconds but only advances once per scheduler tick (1-4 ms).
+    /// Elsewhere the precise clock is used either way.
+    pub fn coarse_timestamps(self, coarse: bool) -> Self {
+        self.clock(Clock::new(if coarse { ClockSource::Coarse } else { ClockSource::Std }))
+    }
+
+    /// Stamps packets from `clock`, e.g. a calibrated TSC
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the number of segments, the most packets one receive can take
+    pub fn capacity(&self) -> usize {
+        self.storage.len() / self.stride
+    }
+
+    /// Returns the largest packet a segment holds
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    /// Returns the distance in bytes between consecutive segments
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the number of packets received by the last batch
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns `true` if the arena holds no packets
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Returns the descriptors of the received packets, in arrival order
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the payload of packet `index`
+    pub fn packet(&self, index: usize) -> Option<&[u8]> {
+        self.segments.get(index).map(|seg| &self.storage[seg.range()])
+    }
+
+    /// Iterates over the received packets and their senders
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], SocketAddr)> + '_ {
+        self.segments.iter().map(|seg| (&self.storage[seg.range()], seg.addr))
+    }
+
+    /// Returns the whole arena, including unused segment tails
+    ///
+    /// Use [`segments`](Self::segments) to locate packets within it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.storage
+    }
+
+    /// Forgets the received packets; the storage is kept
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    pub(crate) fn storage_mut(&mut self) -> &mut [u8] {
+        &mut self.storage
+    }
+
+    pub(crate) fn push(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    /// Reads the clock used for [`Segment::received_at`](Segment::received_at())
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::Udp;
+    use crate::NetConfig;
+    use std::time::Duration;
+
+    #[test]
+    fn test_packets_land_in_consecutive_segments() {
+        let cfg = NetConfig::default();
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let dest = rx.local_addr().unwrap();
+        for i in 0..5u8 {
+            tx.send_to(&vec![i; 100 + i as usize], dest).unwrap();
+        }
+        // Longer than a segment, so truncated
+        tx.send_to(&[9u8; 300], dest).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let mut arena = RecvArena::new(8, 200);
+        assert_eq!(arena.stride(), 256);
+        let before = std::time::Instant::now();
+        assert_eq!(rx.recv_batch_arena(&mut arena).unwrap(), 6);
+        let after = std::time::Instant::now();
+        assert!(arena.segments().iter().all(|seg| (before..=after).contains(&seg.received_at())));
+        for (i, (packet, from)) in arena.iter().take(5).enumerate() {
+            assert_eq!(packet, &vec![i as u8; 100 + i][..]);
+            assert_eq!(from, tx.local_addr().unwrap());
+            assert_eq!(arena.segments()[i].offset, i * 256);
+        }
+        assert_eq!(arena.packet(5).unwrap().len(), 200);
+
+        // A drained socket reports WouldBlock and leaves the arena empty
+        let err = rx.recv_batch_arena(&mut arena).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn test_coarse_clock_stays_near_precise_clock() {
+        let arena = RecvArena::new(1, 64).coarse_timestamps(true);
+        for _ in 0..3 {
+            let (coarse, precise) = (arena.now(), std::time::Instant::now());
+            // Off by at most a few scheduler ticks in either direction
+            let skew = if coarse > precise { coarse - precise } else { precise - coarse };
+            assert!(skew < Duration::from_millis(50), "{:?}", skew);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Adaptive batch sizing for batch send/receive loops
+//!
+//! The right batch size for `recv_batch`/`send_batch` depends on the traffic:
+//! large batches amortize syscalls under load, small ones waste less buffer
+//! memory and cache when traffic is sparse. [`AdaptiveBatcher`] tracks how
+//! full recent batches were and picks the size for the next call, so
+//! applications do not need to hand-tune the usual 16-64 packet guidance.
+//!
+//! # Algorithm
+//!
+//! - A batch that comes back full doubles the size (up to `max`)
+//! - After `idle_rounds` consecutive batches that used less than a quarter of
+//!   the size, it halves (down to `min`)
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::batch::AdaptiveBatcher;
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut batcher = AdaptiveBatcher::new(8, 256);
+//! let mut bufs = vec![Vec::with_capacity(2048); batcher.max()];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); batcher.max()];
+//!
+//! loop {
+//!     let size = batcher.size();
+//!     match socket.recv_batch(&mut bufs[..size], &mut addrs[..size]) {
+//!         Ok(n) => batcher.record(n),
+//!         Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => batcher.record(0),
+//!         Err(e) => return Err(e.into()),
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Tunes a batch size from the fill level of completed batches
+#[derive(Clone, Debug)]
+pub struct AdaptiveBatcher {
+    /// Current batch size
+    size: usize,
+    /// Lower bound for `size`
+    min: usize,
+    /// Upper bound for `size`
+    max: usize,
+    /// Consecutive underfilled batches required before shrinking
+    idle_rounds: u32,
+    /// Consecutive underfilled batches observed so far
+    idle: u32,
+}
+
+impl AdaptiveBatcher {
+    /// Creates a batcher bounded by `min..=max`, starting at `min`
+    ///
+    /// Both bounds are raised to at least 1 and `max` to at least `min`.
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        Self { size: min, min, max: max.max(min), idle_rounds: 8, idle: 0 }
+    }
+
+    /// Sets how many consecutive underfilled batches trigger a shrink
+    ///
+    /// Lower values react faster to traffic drops; higher values avoid
+    /// oscillating on bursty traffic. Defaults to 8.
+    pub fn idle_rounds(mut self, rounds: u32) -> Self {
+        self.idle_rounds = rounds.max(1);
+        self
+    }
+
+    /// Returns the batch size to use for the next call
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the lower bound on the batch size
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    /// Returns the upper bound on the batch size, for sizing buffer arrays
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Records how many packets the last batch of [`size`](Self::size) completed
+    ///
+    /// Pass `0` when the call returned `WouldBlock`.
+    pub fn record(&mut self, completed: usize) {
+        if completed >= self.size {
+            self.idle = 0;
+            self.size = (self.size * 2).min(self.max);
+        } else if completed < self.size.div_ceil(4) {
+            self.idle += 1;
+            if self.idle >= self.idle_rounds {
+                self.idle = 0;
+                self.size = (self.size / 2).max(self.min);
+            }
+        } else {
+            self.idle = 0;
+        }
+    }
+
+    /// Returns to the minimum size, e.g. after a traffic source disconnects
+    pub fn reset(&mut self) {
+        self.size = self.min;
+        self.idle = 0;
+    }
+}
+
+impl Default for AdaptiveBatcher {
+    /// A batcher ranging over the commonly recommended 16-64 packets
+    fn default() -> Self {
+        Self::new(16, 64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_when_full_and_shrinks_when_idle() {
+        let mut b = AdaptiveBatcher::new(4, 32).idle_rounds(2);
+        assert_eq!(b.size(), 4);
+
+        b.record(4);
+        b.record(8);
+        b.record(16);
+        assert_eq!(b.size(), 32);
+        b.record(32);
+        assert_eq!(b.size(), 32);
+
+        // Moderate fill holds the size
+        b.record(20);
+        b.record(20);
+        assert_eq!(b.size(), 32);
+
+        b.record(0);
+        assert_eq!(b.size(), 32);
+        b.record(1);
+        assert_eq!(b.size(), 16);
+        for _ in 0..10 {
+            b.record(0);
+        }
+        assert_eq!(b.size(), 4);
+
+        b.record(4);
+        b.reset();
+        assert_eq!(b.size(), 4);
+    }
+}
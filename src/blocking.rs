@@ -0,0 +1,310 @@
+//! Blocking calls with timeouts, shaped for Python and other language bindings
+//!
+//! The crate's sockets are non-blocking and expect an event loop. Packet
+//! tooling written in Python (capture replays, load generators, notebook
+//! analysis) wants the opposite: a call that blocks until data arrives or a
+//! timeout passes, like the standard `socket` module. [`UdpHandle`] and
+//! [`TcpHandle`] provide that on top of [`poll::wait`](crate::poll::wait),
+//! without a runtime, and are designed to be wrapped directly by pyo3:
+//!
+//! - Handles are `Send + Sync` and every call takes `&self`, so a
+//!   `#[pyclass]` can hold one without `unsendable` and several Python
+//!   threads can use it at once.
+//! - No call takes a callback or touches Python objects, so each can run
+//!   inside `py.allow_threads`, releasing the GIL for the whole wait.
+//! - A wait that expires fails with `TimedOut`, which pyo3 raises as
+//!   `TimeoutError`; `None` waits forever. Other failures keep their
+//!   `io::ErrorKind` and so map to the matching `OSError` subclass.
+//! - Signals are not checked while blocked. Bindings that must respond to
+//!   Ctrl-C should wait in slices of, say, 100 ms and call
+//!   `py.check_signals()` between them.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::blocking::UdpHandle;
+//! use horizon_sockets::NetConfig;
+//! use std::net::SocketAddr;
+//! use std::time::Duration;
+//!
+//! let sock = UdpHandle::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut bufs = vec![Vec::new(); 64];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 64];
+//!
+//! // In a pyo3 method: py.allow_threads(|| sock.recv_batch_timeout(...))
+//! let n = sock.recv_batch_timeout(&mut bufs, &mut addrs, Some(Duration::from_secs(1)))?;
+//! for (buf, from) in bufs[..n].iter().zip(&addrs) {
+//!     sock.send_to_timeout(buf, *from, None)?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::Error;
+use crate::poll::{self, Interest, Pollable};
+use crate::tcp::TcpStream;
+use crate::udp::Udp;
+use crate::NetConfig;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Waits for `interest` on `sock`, failing with `TimedOut` once `deadline` passes
+fn wait(sock: &dyn Pollable, interest: Interest, deadline: Option<Instant>) -> io::Result<()> {
+    let remaining = match deadline {
+        Some(d) => {
+            let left = d.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+            }
+            Some(left)
+        }
+        None => None,
+    };
+    poll::wait(&[sock], interest, remaining).map(drop)
+}
+
+/// Retries `op` on `WouldBlock`, waiting for `interest` in between
+fn retry<T>(sock: &dyn Pollable, interest: Interest, deadline: Option<Instant>, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match op() {
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {
+                wait(sock, interest, deadline)?
+            }
+            res => return res,
+        }
+    }
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|t| Instant::now() + t)
+}
+
+/// UDP socket with blocking, timeout-bounded calls
+#[derive(Debug)]
+pub struct UdpHandle {
+    sock: Udp,
+}
+
+impl UdpHandle {
+    /// Binds a socket to `addr` with `cfg` applied
+    pub fn bind(addr: SocketAddr, cfg: &NetConfig) -> io::Result<Self> {
+        Udp::bind(addr, cfg).map(Self::from_udp)
+    }
+
+    /// Wraps an existing socket
+    pub fn from_udp(sock: Udp) -> Self {
+        Self { sock }
+    }
+
+    /// Returns the underlying socket, e.g. for options not exposed here
+    pub fn get_ref(&self) -> &Udp {
+        &self.sock
+    }
+
+    /// Returns the underlying socket
+    pub fn into_inner(self) -> Udp {
+        self.sock
+    }
+
+    /// Returns the bound address
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    /// Receives one datagram, waiting up to `timeout`
+    ///
+    /// Bytes that do not fit in `buf` are discarded.
+    pub fn recv_from_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<(usize, SocketAddr)> {
+        retry(&self.sock, Interest::READABLE, deadline(timeout), || self.sock.socket().recv_from(buf))
+    }
+
+    /// Waits up to `timeout` for a datagram, then receives it and whatever else is queued
+    ///
+    /// Fills up to `min(bufs.len(), addrs.len())` datagrams like
+    /// [`Udp::recv_batch`] and returns how many, at least one. Unlike
+    /// [`Udp::recv_batch_budget`], it returns as soon as anything arrives.
+    pub fn recv_batch_timeout(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr], timeout: Option<Duration>) -> io::Result<usize> {
+        let deadline = deadline(timeout);
+        loop {
+            match self.sock.recv_batch(bufs, addrs) {
+                Ok(0) => {}
+                Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {}
+                res => return res,
+            }
+            wait(&self.sock, Interest::READABLE, deadline)?;
+        }
+    }
+
+    /// Sends one datagram, waiting up to `timeout` for send buffer space
+    pub fn send_to_timeout(&self, buf: &[u8], addr: SocketAddr, timeout: Option<Duration>) -> io::Result<usize> {
+        retry(&self.sock, Interest::WRITABLE, deadline(timeout), || self.sock.send_to(buf, addr))
+    }
+
+    /// Sends every datagram in order, waiting for send buffer space as needed
+    ///
+    /// If `timeout` expires or a send fails part way, the error is
+    /// [`Error::PartialBatch`] carrying how many datagrams went out.
+    pub fn send_all(&self, packets: &[(&[u8], SocketAddr)], timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = deadline(timeout);
+        let mut sent = 0;
+        while sent < packets.len() {
+            match self.sock.send_batch(&packets[sent..]) {
+                Ok(0) => {}
+                Ok(n) => {
+                    sent += n;
+                    continue;
+                }
+                Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {}
+                Err(e) => {
+                    return Err(match Error::from(e) {
+                        Error::PartialBatch { sent: n, source } => partial(sent + n, source),
+                        other => partial(sent, other.into()),
+                    })
+                }
+            }
+            wait(&self.sock, Interest::WRITABLE, deadline).map_err(|e| partial(sent, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps `source` as a partial batch of `sent` datagrams, unless none went out
+fn partial(sent: usize, source: io::Error) -> io::Error {
+    if sent == 0 {
+        return source;
+    }
+    Error::PartialBatch { sent, source }.into()
+}
+
+/// Connected TCP stream with blocking, timeout-bounded calls
+#[derive(Debug)]
+pub struct TcpHandle {
+    stream: TcpStream,
+}
+
+impl TcpHandle {
+    /// Connects to `addr` with `cfg` applied, failing with `TimedOut` after `timeout`
+    pub fn connect(addr: SocketAddr, cfg: &NetConfig, timeout: Option<Duration>) -> io::Result<Self> {
+        let stream = match timeout {
+            Some(t) => TcpStream::connect_timeout(addr, cfg, t)?,
+            None => TcpStream::connect(addr, cfg)?,
+        };
+        Self::from_stream(stream)
+    }
+
+    /// Wraps an existing stream, e.g. one accepted by a listener
+    ///
+    /// The stream is switched to non-blocking mode so that every call is
+    /// bounded by its timeout.
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        stream.as_std().set_nonblocking(true)?;
+        Ok(Self { stream })
+    }
+
+    /// Returns the underlying stream, e.g. for options not exposed here
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Returns the underlying stream
+    pub fn into_inner(self) -> TcpStream {
+        self.stream
+    }
+
+    /// Reads what is available, waiting up to `timeout` for the first byte
+    ///
+    /// Returns 0 once the peer has closed its side.
+    pub fn recv_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        retry(&self.stream, Interest::READABLE, deadline(timeout), || (&self.stream).read(buf))
+    }
+
+    /// Writes all of `data`, waiting for send buffer space as needed
+    ///
+    /// As with Python's `sendall`, an unknown prefix of `data` may have been
+    /// sent when this fails, including on `TimedOut`.
+    pub fn send_all(&self, mut data: &[u8], timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = deadline(timeout);
+        while !data.is_empty() {
+            match retry(&self.stream, Interest::WRITABLE, deadline, || (&self.stream).write(data))? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => data = &data[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends FIN after any queued data, leaving the read side open
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.stream.shutdown_write()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::TcpListener;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_udp_timeouts_and_send_all() {
+        assert_send_sync::<UdpHandle>();
+        assert_send_sync::<TcpHandle>();
+
+        let rx = UdpHandle::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let tx = UdpHandle::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let dest = rx.local_addr().unwrap();
+
+        let mut buf = [0u8; 16];
+        let err = rx.recv_from_timeout(&mut buf, Some(Duration::from_millis(20))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let packets: Vec<(&[u8], SocketAddr)> = vec![(b"a", dest), (b"bb", dest), (b"ccc", dest)];
+        tx.send_all(&packets, Some(Duration::from_secs(5))).unwrap();
+
+        let mut bufs = vec![Vec::new(); 8];
+        let mut addrs = vec![dest; 8];
+        let mut got = Vec::new();
+        while got.len() < 3 {
+            let n = rx.recv_batch_timeout(&mut bufs, &mut addrs, Some(Duration::from_secs(5))).unwrap();
+            assert!(n > 0);
+            got.extend(bufs[..n].iter().cloned());
+        }
+        assert_eq!(got, [b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]);
+        assert_eq!(addrs[0], tx.local_addr().unwrap());
+    }
+
+    #[test]
+    fn test_tcp_send_all_and_recv_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let client = TcpHandle::connect(listener.local_addr().unwrap(), &NetConfig::default(), Some(Duration::from_secs(5))).unwrap();
+        wait(&listener, Interest::READABLE, deadline(Some(Duration::from_secs(5)))).unwrap();
+        let server = TcpHandle::from_stream(listener.accept_nonblocking().unwrap().0).unwrap();
+
+        let mut buf = [0u8; 64 * 1024];
+        let err = server.recv_timeout(&mut buf, Some(Duration::from_millis(20))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        // The connecting side must honour the timeout too
+        let started = Instant::now();
+        let err = client.recv_timeout(&mut buf, Some(Duration::from_millis(20))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Larger than a socket buffer, so sending has to wait for the reader
+        let data: Vec<u8> = (0..4 << 20).map(|i| i as u8).collect();
+        let expected = data.clone();
+        let writer = std::thread::spawn(move || {
+            client.send_all(&data, Some(Duration::from_secs(10))).unwrap();
+            client.shutdown_write().unwrap();
+        });
+        let mut received = Vec::new();
+        loop {
+            match server.recv_timeout(&mut buf, Some(Duration::from_secs(10))).unwrap() {
+                0 => break,
+                n => received.extend_from_slice(&buf[..n]),
+            }
+        }
+        writer.join().unwrap();
+        assert!(received == expected);
+    }
+}
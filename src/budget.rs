@@ -0,0 +1,258 @@
+//! Per-connection accounting of pooled buffer memory
+//!
+//! A [`BufferPool`] is shared by every connection on a loop, so nothing
+//! stops one of them from holding most of it: a consumer that reads slowly
+//! keeps its queued output in pooled buffers, and a sender that never
+//! finishes a frame keeps its partial input. Once the pool is empty every
+//! other connection falls back to allocating, and memory grows with the
+//! slowest peer.
+//!
+//! [`MemoryBudget`] hands out the pool's buffers on behalf of a connection
+//! key (a mio `Token`, a peer address) and tracks the bytes each key holds,
+//! counted by buffer capacity. A key at its [`limit`](MemoryBudget::limit)
+//! is refused, and the [`Overrun`] strategy says what the caller does
+//! instead:
+//!
+//! - [`Drop`](Overrun::Drop): discard the data that needed the buffer
+//!   (suits datagrams and state updates superseded by the next one)
+//! - [`Park`](Overrun::Park): stop reading from the connection until
+//!   [`release`](MemoryBudget::release) reports it has drained to half
+//!   its limit, like the driver's output watermarks
+//! - [`Kill`](Overrun::Kill): close the connection
+//!
+//! A budget belongs to one event loop and needs `&mut self`; the pool
+//! behind it may be shared with other loops, each with its own budget.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::budget::{Grant, MemoryBudget, Overrun};
+//! use horizon_sockets::buffer_pool::BufferPool;
+//!
+//! let pool = BufferPool::new(64, 2048);
+//! let mut budget = MemoryBudget::new(pool, 4096).overrun(Overrun::Park);
+//!
+//! let mut held = Vec::new();
+//! while let Grant::Buffer(buf) = budget.acquire(7u64) {
+//!     held.push(buf);
+//! }
+//! assert!(budget.is_parked(&7));
+//!
+//! // Writing the queued buffers out releases them; reading resumes at half the limit
+//! let resumed = held.drain(..).fold(false, |r, buf| budget.release(&7, buf) | r);
+//! assert!(resumed);
+//! ```
+
+use crate::buffer_pool::BufferPool;
+use crate::trace;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// What a connection at its limit must do instead of taking another buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overrun {
+    /// Discard the data the buffer was for
+    #[default]
+    Drop,
+    /// Stop reading from the connection until it drains to half its limit
+    Park,
+    /// Close the connection
+    Kill,
+}
+
+/// Outcome of [`MemoryBudget::acquire`]
+#[derive(Debug)]
+pub enum Grant {
+    /// A pooled buffer, now counted against the connection
+    Buffer(Vec<u8>),
+    /// Over the limit with [`Overrun::Drop`]: discard the data
+    Dropped,
+    /// Over the limit with [`Overrun::Park`]: stop reading until released down to half the limit
+    Parked,
+    /// Over the limit with [`Overrun::Kill`]: close the connection and [`forget`](MemoryBudget::forget) it
+    Kill,
+}
+
+/// Memory counters for one connection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnUsage {
+    /// Bytes of buffer capacity held now
+    pub held: usize,
+    /// Most bytes held at once
+    pub peak: usize,
+    /// Acquires refused because the connection was at its limit
+    pub refused: u64,
+    /// Whether the connection is parked
+    pub parked: bool,
+}
+
+/// Pooled buffer accounting with a byte cap per connection key
+#[derive(Debug)]
+pub struct MemoryBudget<K> {
+    pool: BufferPool,
+    limit: usize,
+    overrun: Overrun,
+    conns: HashMap<K, ConnUsage>,
+    held: usize,
+}
+
+impl<K: Hash + Eq + Clone> MemoryBudget<K> {
+    /// Creates a budget over `pool` letting each connection hold up to `limit` bytes
+    ///
+    /// Over the limit, data is dropped; see [`overrun`](Self::overrun).
+    pub fn new(pool: BufferPool, limit: usize) -> Self {
+        Self { pool, limit, overrun: Overrun::Drop, conns: HashMap::new(), held: 0 }
+    }
+
+    /// Sets what a connection at its limit does instead of taking a buffer
+    pub fn overrun(mut self, overrun: Overrun) -> Self {
+        self.overrun = overrun;
+        self
+    }
+
+    /// Returns the per-connection limit in bytes
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the pool buffers are taken from
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// Takes a buffer from the pool for `key`, unless that would put it over its limit
+    ///
+    /// A parked connection is refused until it has drained, whatever it
+    /// holds.
+    pub fn acquire(&mut self, key: K) -> Grant {
+        let usage = self.conns.entry(key).or_default();
+        let size = self.pool.default_capacity();
+        if usage.parked || usage.held + size > self.limit {
+            usage.refused += 1;
+            trace::event!(debug, held = usage.held, limit = self.limit, overrun = ?self.overrun, "memory budget exceeded");
+            return match self.overrun {
+                Overrun::Drop => Grant::Dropped,
+                Overrun::Park => {
+                    usage.parked = true;
+                    Grant::Parked
+                }
+                Overrun::Kill => Grant::Kill,
+            };
+        }
+        let buffer = self.pool.acquire();
+        usage.held += buffer.capacity();
+        usage.peak = usage.peak.max(usage.held);
+        self.held += buffer.capacity();
+        Grant::Buffer(buffer)
+    }
+
+    /// Returns a buffer taken for `key` to the pool
+    ///
+    /// # Returns
+    ///
+    /// `true` if this unparked the connection, so the caller should resume
+    /// reading from it
+    pub fn release(&mut self, key: &K, buffer: Vec<u8>) -> bool {
+        let bytes = buffer.capacity();
+        self.pool.release(buffer);
+        let Some(usage) = self.conns.get_mut(key) else {
+            return false;
+        };
+        // Buffers may have grown while held; never count below zero
+        let bytes = bytes.min(usage.held);
+        usage.held -= bytes;
+        self.held -= bytes;
+        if usage.parked && usage.held <= self.limit / 2 {
+            usage.parked = false;
+            return true;
+        }
+        false
+    }
+
+    /// Stops tracking `key`, e.g. once its connection is closed
+    ///
+    /// Buffers it still holds may be released to the pool directly.
+    ///
+    /// # Returns
+    ///
+    /// The bytes it was holding
+    pub fn forget(&mut self, key: &K) -> usize {
+        let held = self.conns.remove(key).map_or(0, |u| u.held);
+        self.held -= held;
+        held
+    }
+
+    /// Returns the bytes `key` holds now
+    pub fn held(&self, key: &K) -> usize {
+        self.conns.get(key).map_or(0, |u| u.held)
+    }
+
+    /// Returns the bytes held across all connections
+    pub fn total_held(&self) -> usize {
+        self.held
+    }
+
+    /// Returns `true` while `key` is parked
+    pub fn is_parked(&self, key: &K) -> bool {
+        self.conns.get(key).is_some_and(|u| u.parked)
+    }
+
+    /// Returns the counters for `key`, if it has taken a buffer
+    pub fn usage(&self, key: &K) -> Option<ConnUsage> {
+        self.conns.get(key).copied()
+    }
+
+    /// Returns the tracked connections, largest holders first
+    pub fn top(&self) -> Vec<(K, ConnUsage)> {
+        let mut all: Vec<_> = self.conns.iter().map(|(k, u)| (k.clone(), *u)).collect();
+        all.sort_by_key(|(_, u)| std::cmp::Reverse(u.held));
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_isolates_connections() {
+        let pool = BufferPool::new(8, 1024);
+        let mut budget = MemoryBudget::new(pool, 2048);
+
+        let a: Vec<_> = (0..2).map(|_| budget.acquire(1)).collect();
+        assert!(a.iter().all(|g| matches!(g, Grant::Buffer(_))));
+        assert!(matches!(budget.acquire(1), Grant::Dropped));
+        // Another connection is unaffected by the first one's limit
+        assert!(matches!(budget.acquire(2), Grant::Buffer(_)));
+        assert_eq!(budget.held(&1), 2048);
+        assert_eq!(budget.total_held(), 3072);
+        assert_eq!(budget.usage(&1).unwrap().refused, 1);
+        assert_eq!(budget.top()[0].0, 1);
+
+        assert_eq!(budget.forget(&1), 2048);
+        assert_eq!(budget.total_held(), 1024);
+    }
+
+    #[test]
+    fn test_park_resumes_at_half_and_kill() {
+        let pool = BufferPool::new(8, 1024);
+        let mut budget = MemoryBudget::new(pool.clone(), 4096).overrun(Overrun::Park);
+        let mut held: Vec<Vec<u8>> = (0..4)
+            .map(|_| match budget.acquire("slow") {
+                Grant::Buffer(b) => b,
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert!(matches!(budget.acquire("slow"), Grant::Parked));
+        assert!(!budget.release(&"slow", held.pop().unwrap()));
+        // Still parked above half the limit, even with room for one buffer
+        assert!(matches!(budget.acquire("slow"), Grant::Parked));
+        assert!(budget.release(&"slow", held.pop().unwrap()));
+        assert!(!budget.is_parked(&"slow"));
+        assert!(matches!(budget.acquire("slow"), Grant::Buffer(_)));
+
+        let mut budget = MemoryBudget::new(pool, 1024).overrun(Overrun::Kill);
+        assert!(matches!(budget.acquire(0u8), Grant::Buffer(_)));
+        assert!(matches!(budget.acquire(0u8), Grant::Kill));
+    }
+}
@@ -3,9 +3,20 @@
 //! This module provides a thread-safe buffer pool that minimizes allocations
 //! during high-frequency network operations. Buffers are reused to reduce
 //! garbage collection pressure and improve cache locality.
+//!
+//! With the `bytes` feature, pooled storage can also be handed out as
+//! `BytesMut` and frozen into `Bytes` that return their buffer to the pool
+//! when the last clone drops, so codecs and libraries built on `bytes`
+//! (h2, tonic) receive pooled memory without a copy.
+//!
+//! Pools configured with [`headroom`](BufferPool::headroom) and
+//! [`tailroom`](BufferPool::tailroom) also hand out [`PacketBuf`]s, which
+//! reserve space on both sides of the payload the way `sk_buff` and `mbuf`
+//! do. Protocol layers prepend headers and append authentication tags in
+//! place, so encrypting or framing a packet never moves the payload.
 
 use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use crate::sync::{Arc, Mutex};
 
 /// A thread-safe buffer pool for network I/O operations
 ///
@@ -35,6 +46,10 @@ pub struct BufferPool {
     default_capacity: usize,
     /// Maximum number of buffers to keep in pool
     max_buffers: usize,
+    /// Bytes reserved before the payload of each `PacketBuf`
+    headroom: usize,
+    /// Bytes reserved after the payload of each `PacketBuf`
+    tailroom: usize,
 }
 
 impl BufferPool {
@@ -52,13 +67,15 @@ impl BufferPool {
     /// # Examples
     ///
     /// ```rust
+    /// use horizon_sockets::buffer_pool::BufferPool;
+    ///
     /// // Create pool with 32 buffers of 1KB each
     /// let pool = BufferPool::new(32, 1024);
     /// ```
     pub fn new(initial_count: usize, buffer_capacity: usize) -> Self {
         let mut buffers = VecDeque::with_capacity(initial_count * 2);
 
-        // Pre-allocate initial buffers
+        // Pre-allocate initial buffers; headroom and tailroom are added when configured
         for _ in 0..initial_count {
             buffers.push_back(Vec::with_capacity(buffer_capacity));
         }
@@ -67,9 +84,44 @@ impl BufferPool {
             buffers: Arc::new(Mutex::new(buffers)),
             default_capacity: buffer_capacity,
             max_buffers: initial_count * 2, // Allow pool to grow up to 2x initial size
+            headroom: 0,
+            tailroom: 0,
         }
     }
 
+    /// Reserves `bytes` in front of the payload of every [`PacketBuf`]
+    ///
+    /// Size it for the largest header stack a packet can get, e.g. 13
+    /// bytes for a DTLS record header. Pooled buffers grow to fit.
+    pub fn headroom(mut self, bytes: usize) -> Self {
+        self.headroom = bytes;
+        self.reserve_room();
+        self
+    }
+
+    /// Reserves `bytes` after the payload of every [`PacketBuf`]
+    ///
+    /// Size it for trailers such as a 16-byte AEAD tag. Pooled buffers grow
+    /// to fit.
+    pub fn tailroom(mut self, bytes: usize) -> Self {
+        self.tailroom = bytes;
+        self.reserve_room();
+        self
+    }
+
+    /// Grows the buffers already in the pool to hold headroom, payload, and tailroom
+    fn reserve_room(&self) {
+        let total = self.packet_size();
+        for buffer in self.buffers.lock().unwrap().iter_mut() {
+            buffer.reserve_exact(total.saturating_sub(buffer.len()));
+        }
+    }
+
+    /// Bytes in a packet buffer: headroom, default capacity, and tailroom
+    fn packet_size(&self) -> usize {
+        self.headroom + self.default_capacity + self.tailroom
+    }
+
     /// Acquires a buffer from the pool
     ///
     /// If no buffers are available in the pool, a new buffer is allocated
@@ -89,7 +141,7 @@ impl BufferPool {
 
         buffers.pop_front().unwrap_or_else(|| {
             // Pool is empty, allocate new buffer
-            Vec::with_capacity(self.default_capacity)
+            Vec::with_capacity(self.packet_size())
         })
     }
 
@@ -155,6 +207,8 @@ impl BufferPool {
     /// # Examples
     ///
     /// ```rust
+    /// use horizon_sockets::buffer_pool::BufferPool;
+    ///
     /// let pool = BufferPool::new(64, 2048);
     /// let buffers = pool.acquire_batch(16);
     ///
@@ -177,7 +231,7 @@ impl BufferPool {
 
         // Allocate remaining buffers if needed
         for _ in available..count {
-            result.push(Vec::with_capacity(self.default_capacity));
+            result.push(Vec::with_capacity(self.packet_size()));
         }
 
         result
@@ -202,6 +256,283 @@ impl BufferPool {
             // Excess buffers are dropped
         }
     }
+
+    /// Acquires a buffer with the pool's headroom and tailroom around an empty payload
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use horizon_sockets::buffer_pool::BufferPool;
+    ///
+    /// let pool = BufferPool::new(16, 1200).headroom(13).tailroom(16);
+    /// let mut packet = pool.acquire_packet();
+    /// packet.append(b"player input");
+    ///
+    /// // Encrypt in place, then add the tag and record header around it
+    /// let tag = [0u8; 16];
+    /// packet.append(&tag);
+    /// packet.prepend(&[23, 0xfe, 0xfd, 0, 1, 0, 0, 0, 0, 0, 7, 0, 28]);
+    /// assert_eq!(packet.len(), 13 + 12 + 16);
+    ///
+    /// pool.release_packet(packet);
+    /// ```
+    pub fn acquire_packet(&self) -> PacketBuf {
+        let mut buffer = self.acquire();
+        buffer.clear();
+        buffer.reserve_exact(self.packet_size());
+        // Only the headroom is zeroed; the rest is initialized as the payload grows
+        buffer.resize(self.headroom, 0);
+        PacketBuf { buffer, start: self.headroom, end: self.headroom }
+    }
+
+    /// Returns a packet buffer's storage to the pool
+    pub fn release_packet(&self, packet: PacketBuf) {
+        self.release(packet.buffer);
+    }
+}
+
+/// Packet payload with reserved space before and after it
+///
+/// The payload is a window into one pooled allocation. Headers are added
+/// with [`push`](Self::push)/[`prepend`](Self::prepend) into the headroom
+/// and removed with [`pull`](Self::pull); trailers go into the tailroom
+/// with [`put`](Self::put)/[`append`](Self::append) and come off with
+/// [`trim`](Self::trim). None of these move the payload.
+#[derive(Debug)]
+pub struct PacketBuf {
+    /// Storage, initialized up to its length; the capacity bounds the tailroom
+    buffer: Vec<u8>,
+    /// Payload is `buffer[start..end]`
+    start: usize,
+    end: usize,
+}
+
+impl PacketBuf {
+    /// Wraps `buffer`, placing an empty payload after `headroom` bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if `headroom` exceeds the buffer's capacity.
+    pub fn with_headroom(mut buffer: Vec<u8>, headroom: usize) -> Self {
+        assert!(headroom <= buffer.capacity(), "headroom exceeds buffer capacity");
+        if buffer.len() < headroom {
+            buffer.resize(headroom, 0);
+        }
+        Self { buffer, start: headroom, end: headroom }
+    }
+
+    /// Returns the payload
+    pub fn data(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+
+    /// Returns the payload for in-place modification, e.g. encryption
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[self.start..self.end]
+    }
+
+    /// Returns the payload length
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if the payload is empty
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the bytes free before the payload
+    pub fn headroom(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the bytes free after the payload
+    pub fn tailroom(&self) -> usize {
+        self.buffer.capacity() - self.end
+    }
+
+    /// Extends the payload `n` bytes to the front and returns the new bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` bytes of headroom are left.
+    pub fn push(&mut self, n: usize) -> &mut [u8] {
+        assert!(n <= self.start, "not enough headroom: need {}, have {}", n, self.start);
+        self.start -= n;
+        &mut self.buffer[self.start..self.start + n]
+    }
+
+    /// Copies `header` in front of the payload
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header does not fit in the headroom.
+    pub fn prepend(&mut self, header: &[u8]) {
+        self.push(header.len()).copy_from_slice(header);
+    }
+
+    /// Removes `n` bytes from the front of the payload and returns them
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload is shorter than `n`.
+    pub fn pull(&mut self, n: usize) -> &[u8] {
+        assert!(n <= self.len(), "payload shorter than {} bytes", n);
+        self.start += n;
+        &self.buffer[self.start - n..self.start]
+    }
+
+    /// Extends the payload `n` bytes at the end and returns the new bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` bytes of tailroom are left.
+    pub fn put(&mut self, n: usize) -> &mut [u8] {
+        assert!(n <= self.tailroom(), "not enough tailroom: need {}, have {}", n, self.tailroom());
+        if self.end + n > self.buffer.len() {
+            // Within capacity, so this never reallocates and the payload stays put
+            self.buffer.resize(self.end + n, 0);
+        }
+        self.end += n;
+        &mut self.buffer[self.end - n..self.end]
+    }
+
+    /// Copies `data` after the payload
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data does not fit in the tailroom.
+    pub fn append(&mut self, data: &[u8]) {
+        self.put(data.len()).copy_from_slice(data);
+    }
+
+    /// Removes `n` bytes from the end of the payload
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload is shorter than `n`.
+    pub fn trim(&mut self, n: usize) {
+        assert!(n <= self.len(), "payload shorter than {} bytes", n);
+        self.end -= n;
+    }
+
+    /// Returns everything after the headroom, for receiving a payload in place
+    ///
+    /// The first call zero-fills the rest of the allocation. Follow with
+    /// [`set_len`](Self::set_len) once the size is known.
+    pub fn spare_mut(&mut self) -> &mut [u8] {
+        self.buffer.resize(self.buffer.capacity(), 0);
+        &mut self.buffer[self.start..]
+    }
+
+    /// Sets the payload length after receiving into [`spare_mut`](Self::spare_mut)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` exceeds the space [`spare_mut`](Self::spare_mut) returned.
+    pub fn set_len(&mut self, len: usize) {
+        assert!(self.start + len <= self.buffer.len(), "length exceeds buffer");
+        self.end = self.start + len;
+    }
+
+    /// Returns the underlying allocation
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Clone for PacketBuf {
+    fn clone(&self) -> Self {
+        // Keep the capacity, which `Vec::clone` would shrink to the length
+        let mut buffer = Vec::with_capacity(self.buffer.capacity());
+        buffer.extend_from_slice(&self.buffer);
+        Self { buffer, start: self.start, end: self.end }
+    }
+}
+
+impl std::ops::Deref for PacketBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data()
+    }
+}
+
+impl std::ops::DerefMut for PacketBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.data_mut()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl BufferPool {
+    /// Acquires a pooled buffer as an empty `BytesMut`
+    ///
+    /// The `BytesMut` takes over the pooled allocation without copying;
+    /// give it back with [`release_bytes_mut`](Self::release_bytes_mut).
+    pub fn acquire_bytes_mut(&self) -> bytes::BytesMut {
+        let mut buffer = self.acquire();
+        buffer.clear();
+        // A fresh `Bytes` owns its allocation, so it converts back without copying
+        bytes::BytesMut::from(bytes::Bytes::from(buffer))
+    }
+
+    /// Returns a `BytesMut`'s allocation to the pool
+    ///
+    /// A `BytesMut` split from another shares its allocation; converting it
+    /// would copy, so it is dropped instead and the allocation is freed with
+    /// its last handle.
+    pub fn release_bytes_mut(&self, buffer: bytes::BytesMut) {
+        if let Ok(buffer) = buffer.freeze().try_into_mut() {
+            self.release(Vec::from(buffer));
+        }
+    }
+
+    /// Freezes `buffer` into `Bytes` that return it to this pool on drop
+    ///
+    /// Clones and slices of the returned `Bytes` share the buffer; it goes
+    /// back to the pool when the last of them is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use horizon_sockets::buffer_pool::BufferPool;
+    ///
+    /// let pool = BufferPool::new(1, 2048);
+    /// let mut buffer = pool.acquire();
+    /// buffer.extend_from_slice(b"response body");
+    ///
+    /// let body = pool.freeze(buffer);
+    /// let header = body.slice(..8);
+    /// drop(body);
+    /// assert_eq!(pool.available_count(), 0);
+    /// drop(header);
+    /// assert_eq!(pool.available_count(), 1);
+    /// ```
+    pub fn freeze(&self, buffer: Vec<u8>) -> bytes::Bytes {
+        bytes::Bytes::from_owner(Pooled { buffer, pool: self.clone() })
+    }
+}
+
+/// Owner of a frozen pooled buffer, releasing it when dropped
+#[cfg(feature = "bytes")]
+struct Pooled {
+    buffer: Vec<u8>,
+    pool: BufferPool,
+}
+
+#[cfg(feature = "bytes")]
+impl AsRef<[u8]> for Pooled {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buffer));
+    }
 }
 
 impl Default for BufferPool {
@@ -265,4 +596,89 @@ mod tests {
             assert_eq!(buffer.capacity(), 256);
         }
     }
+
+    #[test]
+    fn test_packet_headroom_and_tailroom_in_place() {
+        let pool = BufferPool::new(1, 32).headroom(8).tailroom(4);
+        let mut packet = pool.acquire_packet();
+        assert_eq!((packet.headroom(), packet.tailroom()), (8, 36));
+
+        packet.spare_mut()[..5].copy_from_slice(b"hello");
+        packet.set_len(5);
+        let payload = packet.data().as_ptr();
+        packet.prepend(b"HDR:");
+        packet.append(b"!tag");
+        assert_eq!(&packet[..], b"HDR:hello!tag");
+        // The payload did not move
+        assert_eq!(packet[4..].as_ptr(), payload);
+
+        assert_eq!(packet.pull(4), b"HDR:");
+        packet.trim(4);
+        assert_eq!(packet.data(), b"hello");
+        pool.release_packet(packet);
+        assert_eq!(pool.available_count(), 1);
+    }
+
+    #[test]
+    fn test_packet_initializes_only_headroom_and_payload() {
+        let pool = BufferPool::new(1, 1200).headroom(13).tailroom(16);
+        let mut packet = pool.acquire_packet();
+        packet.append(b"input");
+        assert_eq!(packet.tailroom(), 1200 + 16 - 5);
+        let copy = packet.clone();
+        assert_eq!((copy.data(), copy.tailroom()), (&b"input"[..], packet.tailroom()));
+        // Nothing past the payload was written
+        assert_eq!(packet.into_inner().len(), 13 + 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough headroom")]
+    fn test_packet_push_past_headroom_panics() {
+        let pool = BufferPool::new(1, 32).headroom(2);
+        pool.acquire_packet().push(3);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_bytes_mut_uses_pooled_storage() {
+        let pool = BufferPool::new(1, 4096);
+        let mut buf = pool.acquire_bytes_mut();
+        assert_eq!(pool.available_count(), 0);
+        assert!(buf.capacity() >= 4096);
+        buf.extend_from_slice(b"frame");
+        pool.release_bytes_mut(buf);
+        assert_eq!(pool.available_count(), 1);
+        assert!(pool.acquire().capacity() >= 4096);
+
+        // A split buffer shares its allocation until both halves are gone
+        let mut buf = pool.acquire_bytes_mut();
+        buf.extend_from_slice(b"header:body");
+        let header = buf.split_to(7);
+        pool.release_bytes_mut(buf);
+        assert_eq!(pool.available_count(), 0);
+        drop(header);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+
+    #[test]
+    fn loom_concurrent_acquire_release() {
+        loom::model(|| {
+            let pool = BufferPool::new(1, 16);
+            let other = pool.clone();
+            let worker = loom::thread::spawn(move || {
+                let mut buffer = other.acquire();
+                buffer.push(1);
+                other.release(buffer);
+            });
+            let buffer = pool.acquire();
+            pool.release(buffer);
+            worker.join().unwrap();
+            // Either both threads shared the one buffer in turn, or one allocated a second
+            assert!((1..=2).contains(&pool.available_count()));
+        });
+    }
 }
@@ -0,0 +1,400 @@
+//! Pooled read buffering for many mostly-idle streams
+//!
+//! A `BufReader` per connection pins its buffer for the connection's whole
+//! lifetime: 100k connections with 64KB buffers hold 6.4GB even when almost
+//! all of them are idle. [`BufferedStream`] borrows a buffer from a shared
+//! [`BufferPool`] only while it holds unconsumed bytes, and hands it back as
+//! soon as everything read has been consumed, the peer closes, or a
+//! nonblocking read finds nothing to read.
+//!
+//! Reads land in the buffer's spare capacity without zero-filling it
+//! first, so borrowing a buffer costs no memset. Streams provide this
+//! through [`ReadUninit`], which the crate's TCP streams implement.
+//!
+//! Codecs parse straight out of the pooled buffer: [`fill_buf`](BufferedStream::fill_buf)
+//! returns the buffered bytes, [`consume`](BufferedStream::consume) marks a
+//! decoded frame as used, and [`read_more`](BufferedStream::read_more)
+//! appends to an incomplete frame without dropping what is already buffered.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, buffer_pool::BufferPool, tcp::TcpStream};
+//! use horizon_sockets::buffered::BufferedStream;
+//!
+//! let pool = BufferPool::new(1024, 64 * 1024);
+//! let stream = TcpStream::connect("127.0.0.1:9000".parse()?, &NetConfig::default())?;
+//! let mut conn = BufferedStream::new(stream, pool.clone());
+//!
+//! loop {
+//!     let data = conn.fill_buf()?;
+//!     if data.is_empty() {
+//!         break; // peer closed
+//!     }
+//!     // Length-prefixed frames: decode every complete frame in place
+//!     let mut used = 0;
+//!     while data.len() - used >= 2 {
+//!         let len = u16::from_be_bytes([data[used], data[used + 1]]) as usize;
+//!         let Some(frame) = data.get(used + 2..used + 2 + len) else { break };
+//!         println!("frame of {} bytes", frame.len());
+//!         used += 2 + len;
+//!     }
+//!     let incomplete = used < data.len();
+//!     conn.consume(used);
+//!     if incomplete && conn.read_more()? == 0 {
+//!         break;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::buffer_pool::BufferPool;
+use crate::raw as r;
+use crate::tcp::TcpStream;
+use std::io::{self, BufRead, Read, Write};
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ptr;
+
+/// Streams that can read into uninitialized memory
+///
+/// [`BufferedStream`] reads into the spare capacity of pooled buffers
+/// through this trait. The provided method zero-fills `buf` and calls
+/// [`Read::read`]; sockets override it to receive without the memset.
+///
+/// # Safety
+///
+/// `read_uninit` must return a count no larger than `buf.len()` and must
+/// have initialized that many leading bytes of `buf`.
+pub unsafe trait ReadUninit: Read {
+    /// Reads into `buf`, returning the number of leading bytes written
+    fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        for byte in buf.iter_mut() {
+            byte.write(0);
+        }
+        // SAFETY: every byte was just initialized
+        let buf = unsafe { &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]) };
+        let len = buf.len();
+        self.read(buf).map(|n| n.min(len))
+    }
+}
+
+// SAFETY: recv writes at most buf.len() bytes and reports how many
+unsafe impl ReadUninit for TcpStream {
+    fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        r::recv_uninit(r::os_handle(self.as_std()), buf)
+    }
+}
+
+// SAFETY: as for TcpStream
+unsafe impl ReadUninit for &TcpStream {
+    fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        r::recv_uninit(r::os_handle(self.as_std()), buf)
+    }
+}
+
+// SAFETY: as for TcpStream
+unsafe impl ReadUninit for std::net::TcpStream {
+    fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        r::recv_uninit(r::os_handle(self), buf)
+    }
+}
+
+// SAFETY: the provided method initializes buf before reading
+unsafe impl ReadUninit for crate::memnet::MemStream {}
+// SAFETY: the provided method initializes buf before reading
+unsafe impl ReadUninit for &crate::memnet::MemStream {}
+// SAFETY: the provided method initializes buf before reading
+unsafe impl<S: crate::transport::StreamSocket> ReadUninit for crate::simnet::SimStream<S> {}
+
+/// Stream wrapper that reads into buffers borrowed from a [`BufferPool`]
+///
+/// The buffer is returned to the pool when dropped, so a closed
+/// connection gives its buffer back even with unconsumed data.
+#[derive(Debug)]
+pub struct BufferedStream<S> {
+    inner: S,
+    pool: BufferPool,
+    /// Borrowed buffer; `len()` marks the end of the buffered bytes
+    buf: Option<Vec<u8>>,
+    /// Start of the unconsumed bytes in `buf`
+    pos: usize,
+}
+
+impl<S: ReadUninit> BufferedStream<S> {
+    /// Wraps `inner`, borrowing read buffers from `pool` as needed
+    pub fn new(inner: S, pool: BufferPool) -> Self {
+        Self { inner, pool, buf: None, pos: 0 }
+    }
+
+    /// Returns the buffered bytes, reading from the stream only if there are none
+    ///
+    /// An empty slice means the peer closed the stream. On a nonblocking
+    /// stream with nothing to read, `WouldBlock` is returned and no buffer
+    /// stays borrowed.
+    pub fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buffered() == 0 {
+            self.pos = 0;
+            self.read_into_buffer()?;
+        }
+        Ok(self.buffer())
+    }
+
+    /// Reads more bytes after the ones already buffered
+    ///
+    /// Use this when the buffered bytes hold an incomplete frame. Consumed
+    /// bytes are compacted away first so the whole buffer capacity is
+    /// available.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read; `0` means the peer closed the stream
+    ///
+    /// # Errors
+    /// - The buffer is full of unconsumed bytes (`InvalidData`): the frame
+    ///   is larger than the pool's buffer capacity
+    /// - `WouldBlock` on a nonblocking stream, keeping the buffered bytes
+    pub fn read_more(&mut self) -> io::Result<usize> {
+        if let Some(buf) = self.buf.as_mut() {
+            if self.pos > 0 {
+                buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            if buf.len() == buf.capacity() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Buffered data exceeds the {} byte buffer capacity", buf.capacity()),
+                ));
+            }
+        }
+        self.read_into_buffer()
+    }
+
+    /// Appends one read to the buffer, borrowing one from the pool if needed
+    fn read_into_buffer(&mut self) -> io::Result<usize> {
+        let mut buf = match self.buf.take() {
+            Some(buf) => buf,
+            None => {
+                let mut buf = self.pool.acquire();
+                buf.clear();
+                if buf.capacity() == 0 {
+                    buf.reserve_exact(self.pool.default_capacity().max(1));
+                }
+                buf
+            }
+        };
+        // Read into the spare capacity without zero-filling it first
+        let result = self.inner.read_uninit(buf.spare_capacity_mut());
+        if let Ok(n) = result {
+            // SAFETY: ReadUninit guarantees the first n spare bytes were written
+            unsafe { buf.set_len(buf.len() + n) };
+        }
+        self.buf = Some(buf);
+        if self.buffered() == 0 {
+            self.release_buffer();
+        }
+        result
+    }
+}
+
+impl<S> BufferedStream<S> {
+    /// Marks `amt` buffered bytes as used
+    ///
+    /// Once every buffered byte is consumed the buffer goes back to the pool.
+    pub fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.as_ref().map_or(0, Vec::len));
+        if self.buffered() == 0 {
+            self.release_buffer();
+        }
+    }
+
+    /// Returns the buffered, unconsumed bytes without reading
+    pub fn buffer(&self) -> &[u8] {
+        self.buf.as_deref().map_or(&[], |b| &b[self.pos..])
+    }
+
+    /// Returns the number of buffered, unconsumed bytes
+    pub fn buffered(&self) -> usize {
+        self.buffer().len()
+    }
+
+    /// Returns `true` if a pool buffer is currently borrowed
+    pub fn holds_buffer(&self) -> bool {
+        self.buf.is_some()
+    }
+
+    /// Returns a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying stream
+    ///
+    /// Reading from it directly skips over the buffered bytes.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the underlying stream, releasing the buffer
+    ///
+    /// Buffered bytes that were not consumed are lost.
+    pub fn into_inner(self) -> S {
+        let mut this = ManuallyDrop::new(self);
+        this.release_buffer();
+        // SAFETY: `this` is never dropped, so `pool` and `inner` are each moved out exactly once
+        unsafe {
+            drop(ptr::read(&this.pool));
+            ptr::read(&this.inner)
+        }
+    }
+
+    fn release_buffer(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.pool.release(buf);
+        }
+        self.pos = 0;
+    }
+}
+
+impl<S> Drop for BufferedStream<S> {
+    fn drop(&mut self) {
+        self.release_buffer();
+    }
+}
+
+impl<S: ReadUninit> Read for BufferedStream<S> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        // Large reads with nothing buffered bypass the pool entirely
+        if self.buffered() == 0 && out.len() >= self.pool.default_capacity() {
+            return self.inner.read(out);
+        }
+        let data = self.fill_buf()?;
+        let n = data.len().min(out.len());
+        out[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<S: ReadUninit> BufRead for BufferedStream<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        BufferedStream::fill_buf(self)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        BufferedStream::consume(self, amt)
+    }
+}
+
+impl<S: Write> Write for BufferedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Reader replaying scripted reads; an empty script reads as EOF
+    struct Script(VecDeque<io::Result<Vec<u8>>>);
+
+    impl Read for Script {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(Ok(chunk)) => {
+                    out[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(chunk.len())
+                }
+                Some(Err(e)) => Err(e),
+                None => Ok(0),
+            }
+        }
+    }
+
+    // SAFETY: the provided method initializes buf before reading
+    unsafe impl ReadUninit for Script {}
+
+    #[test]
+    fn test_buffer_only_held_while_data_pending() {
+        let pool = BufferPool::new(1, 8);
+        let script = Script(VecDeque::from(vec![
+            Ok(b"abcd".to_vec()),
+            Err(io::ErrorKind::WouldBlock.into()),
+            Ok(b"ef".to_vec()),
+        ]));
+        let mut stream = BufferedStream::new(script, pool.clone());
+        assert!(!stream.holds_buffer());
+
+        assert_eq!(stream.fill_buf().unwrap(), b"abcd");
+        assert_eq!(pool.available_count(), 0);
+        stream.consume(4);
+        // Fully consumed: the buffer is back in the pool
+        assert!(!stream.holds_buffer());
+        assert_eq!(pool.available_count(), 1);
+
+        let err = stream.fill_buf().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(pool.available_count(), 1);
+
+        assert_eq!(stream.fill_buf().unwrap(), b"ef");
+        drop(stream);
+        assert_eq!(pool.available_count(), 1);
+    }
+
+    #[test]
+    fn test_read_more_keeps_partial_frame() {
+        let pool = BufferPool::new(1, 6);
+        let script = Script(VecDeque::from(vec![
+            Ok(b"xxab".to_vec()),
+            Ok(b"cd".to_vec()),
+            Ok(b"efghij".to_vec()),
+        ]));
+        let mut stream = BufferedStream::new(script, pool.clone());
+
+        assert_eq!(stream.fill_buf().unwrap(), b"xxab");
+        stream.consume(2);
+        assert_eq!(stream.read_more().unwrap(), 2);
+        assert_eq!(stream.buffer(), b"abcd");
+        stream.consume(4);
+
+        let mut line = String::new();
+        assert_eq!(stream.fill_buf().unwrap(), b"efghij");
+        assert!(stream.read_more().is_err());
+        stream.read_line(&mut line).unwrap();
+        assert_eq!(line, "efghij");
+        assert_eq!(stream.read_more().unwrap(), 0);
+        assert!(!stream.holds_buffer());
+        stream.into_inner();
+        assert_eq!(pool.available_count(), 1);
+    }
+
+    #[test]
+    fn test_tcp_reads_into_spare_capacity() {
+        use crate::{tcp::TcpListener, NetConfig};
+
+        let cfg = NetConfig::default();
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap(), &cfg).unwrap();
+        let (server, _) = listener.as_std().accept().unwrap();
+        server.set_nonblocking(false).unwrap();
+
+        // A recycled buffer with stale bytes past its length must not leak into reads
+        let pool = BufferPool::new(1, 16);
+        let mut stale = pool.acquire();
+        stale.extend_from_slice(&[0xee; 16]);
+        pool.release(stale);
+
+        client.write_all(b"hello").unwrap();
+        let mut stream = BufferedStream::new(server, pool.clone());
+        assert_eq!(stream.fill_buf().unwrap(), b"hello");
+        stream.consume(5);
+        drop(client);
+        assert_eq!(stream.fill_buf().unwrap(), b"");
+        assert_eq!(pool.available_count(), 1);
+    }
+}
@@ -31,6 +31,11 @@
 //!     .low_latency()?
 //!     .tcp_stream()?;
 //!
+//! // Create and connect a new TCP stream, configured before the handshake
+//! let connected = SocketBuilder::new()
+//!     .low_latency()?
+//!     .connect("127.0.0.1:8080")?;
+//!
 //! // Create dual-stack UDP socket
 //! let dual_stack = SocketBuilder::new()
 //!     .bind_dual_stack(8080)?
@@ -38,12 +43,44 @@
 //!     .udp()?;
 //! # Ok::<(), std::io::Error>(())
 //! ```
+//!
+//! # Typestates
+//!
+//! The builder tracks what the socket will be created from in its type
+//! parameter, so terminal methods only exist where they can succeed:
+//!
+//! | State | Entered with | Terminals |
+//! |-------|--------------|-----------|
+//! | `Unbound` | `SocketBuilder::new()` | `connect()`, `connect_timeout()` |
+//! | `Bound` | `bind()` | `udp()`, `tcp_listener()`, `connect()`, `connect_timeout()` |
+//! | `DualStack` | `bind_dual_stack()` | `udp()`, `tcp_listener()` |
+//! | `FromStd` | `from_std_tcp()` | `tcp_stream()` |
+//!
+//! Configuration methods are available in every state. Calling a terminal
+//! that does not apply is a compile error rather than a runtime `InvalidInput`:
+//!
+//! ```rust,compile_fail
+//! use horizon_sockets::builder::SocketBuilder;
+//!
+//! // No address to bind: `udp()` does not exist on `SocketBuilder<Unbound>`
+//! let socket = SocketBuilder::new().low_latency()?.udp()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! ```rust,compile_fail
+//! use horizon_sockets::builder::SocketBuilder;
+//!
+//! // No stream to wrap: `tcp_stream()` does not exist on `SocketBuilder<Bound>`
+//! let stream = SocketBuilder::new().bind("0.0.0.0:8080")?.tcp_stream()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
 
-use crate::config::NetConfig;
+use crate::config::{AddrPreferences, NetConfig};
 use crate::tcp::{TcpListener, TcpStream};
 use crate::udp::Udp;
 use std::io;
-use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::net::{Ipv6Addr, SocketAddr, TcpStream as StdTcpStream, ToSocketAddrs};
+use std::time::Duration;
 
 /// Universal socket builder for creating TCP and UDP sockets with method chaining
 ///
@@ -62,20 +99,35 @@ use std::net::{SocketAddr, TcpStream as StdTcpStream};
 /// - **Unified Configuration**: Same methods work for both TCP and UDP
 /// - **Preset Configurations**: Built-in low-latency, high-throughput, and power-efficient presets
 /// - **Platform Optimizations**: Automatically applies platform-specific optimizations
-/// - **Type Safety**: Prevents invalid configurations at compile time
+/// - **Type Safety**: Terminal methods are only available in states where they
+///   can succeed (see the module docs)
 ///
 /// # Memory Management
 ///
 /// The builder is lightweight and designed to be short-lived. It stores configuration
 /// parameters and builds the final socket only when a terminal method is called.
 #[derive(Debug)]
-pub struct SocketBuilder {
+pub struct SocketBuilder<S = Unbound> {
     config: NetConfig,
-    addr: Option<SocketAddr>,
-    dual_stack_port: Option<u16>,
-    std_tcp_stream: Option<StdTcpStream>,
+    state: S,
 }
 
+/// Builder state before an address or stream is chosen
+#[derive(Debug, Default)]
+pub struct Unbound;
+
+/// Builder state after `bind()`, holding the local address
+#[derive(Debug)]
+pub struct Bound(SocketAddr);
+
+/// Builder state after `bind_dual_stack()`, holding the port
+#[derive(Debug)]
+pub struct DualStack(u16);
+
+/// Builder state after `from_std_tcp()`, holding the stream to wrap
+#[derive(Debug)]
+pub struct FromStd(StdTcpStream);
+
 impl SocketBuilder {
     /// Creates a new socket builder with default configuration
     ///
@@ -85,9 +137,7 @@ impl SocketBuilder {
     pub fn new() -> Self {
         Self {
             config: NetConfig::default(),
-            addr: None,
-            dual_stack_port: None,
-            std_tcp_stream: None,
+            state: Unbound,
         }
     }
 
@@ -108,14 +158,14 @@ impl SocketBuilder {
     ///     .udp()?;
     /// # Ok::<(), std::io::Error>(())
     /// ```
-    pub fn bind<A>(mut self, addr: A) -> io::Result<Self>
+    pub fn bind<A>(self, addr: A) -> io::Result<SocketBuilder<Bound>>
     where
         A: AsRef<str>,
     {
-        self.addr = Some(addr.as_ref().parse().map_err(|e| {
+        let addr = addr.as_ref().parse().map_err(|e| {
             io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid address: {}", e))
-        })?);
-        Ok(self)
+        })?;
+        Ok(SocketBuilder { config: self.config, state: Bound(addr) })
     }
 
     /// Binds to a dual-stack IPv6 socket that accepts both IPv4 and IPv6 connections
@@ -125,10 +175,9 @@ impl SocketBuilder {
     ///
     /// # Arguments
     /// * `port` - Port number to bind to (0 for automatic assignment)
-    pub fn bind_dual_stack(mut self, port: u16) -> io::Result<Self> {
-        self.dual_stack_port = Some(port);
+    pub fn bind_dual_stack(mut self, port: u16) -> io::Result<SocketBuilder<DualStack>> {
         self.config.ipv6_only = Some(false);
-        Ok(self)
+        Ok(SocketBuilder { config: self.config, state: DualStack(port) })
     }
 
     /// Configures the builder with an existing standard library TCP stream
@@ -138,11 +187,52 @@ impl SocketBuilder {
     ///
     /// # Arguments
     /// * `stream` - Existing standard library TCP stream
-    pub fn from_std_tcp(mut self, stream: StdTcpStream) -> io::Result<Self> {
-        self.std_tcp_stream = Some(stream);
-        Ok(self)
+    pub fn from_std_tcp(self, stream: StdTcpStream) -> io::Result<SocketBuilder<FromStd>> {
+        Ok(SocketBuilder { config: self.config, state: FromStd(stream) })
     }
 
+    /// Creates a new TCP stream and connects it with the configured settings
+    ///
+    /// Unlike `from_std_tcp()`, every option is applied before the handshake,
+    /// so buffer sizes, TOS, and priority already affect the SYN. To connect
+    /// from a specific local address, call `bind()` first.
+    ///
+    /// Each resolved address is tried in turn until one connects.
+    ///
+    /// # Arguments
+    /// * `addr` - Remote address (e.g., "127.0.0.1:8080", "example.com:443")
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use horizon_sockets::builder::SocketBuilder;
+    ///
+    /// let stream = SocketBuilder::new()
+    ///     .low_latency()?
+    ///     .buffer_size(4 * 1024 * 1024)?
+    ///     .connect("127.0.0.1:8080")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    /// - Address resolution fails or yields no addresses
+    /// - Every resolved address refuses or fails the connection
+    pub fn connect<A: ToSocketAddrs>(self, addr: A) -> io::Result<TcpStream> {
+        connect_each(&self.config, None, addr, None)
+    }
+
+    /// Like `connect()`, but fails with `TimedOut` if a handshake takes longer than `timeout`
+    ///
+    /// The timeout applies to each resolved address separately.
+    ///
+    /// # Errors
+    /// - `timeout` is zero
+    /// - Same as `connect()`
+    pub fn connect_timeout<A: ToSocketAddrs>(self, addr: A, timeout: Duration) -> io::Result<TcpStream> {
+        connect_each(&self.config, None, addr, Some(timeout))
+    }
+}
+
+impl<S> SocketBuilder<S> {
     /// Enables or disables TCP_NODELAY (Nagle's algorithm)
     ///
     /// When enabled (true), TCP packets are sent immediately rather than being
@@ -219,6 +309,23 @@ impl SocketBuilder {
         Ok(self)
     }
 
+    /// Sets the receive low watermark (SO_RCVLOWAT)
+    ///
+    /// Readiness is not signalled until at least `bytes` bytes are queued,
+    /// reducing wakeups for bulk transfers. Not supported on Windows.
+    pub fn recv_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.recv_lowat = Some(bytes);
+        Ok(self)
+    }
+
+    /// Sets the send low watermark (SO_SNDLOWAT)
+    ///
+    /// **Note**: Ignored on Linux, where the value is read-only
+    pub fn send_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.send_lowat = Some(bytes);
+        Ok(self)
+    }
+
     /// Enables busy polling for the specified duration in microseconds (Linux only)
     ///
     /// Busy polling reduces latency by polling the network device for the specified
@@ -234,6 +341,29 @@ impl SocketBuilder {
         Ok(self)
     }
 
+    /// Grows the UDP receive buffer on packet drops, up to `cap` bytes
+    ///
+    /// Starts from the configured receive buffer (or 256KB) and doubles it
+    /// each time `recv_batch` sees the kernel drop counter increase.
+    ///
+    /// **Note**: Linux only; ignored for TCP
+    pub fn auto_tune_buffers(mut self, cap: usize) -> io::Result<Self> {
+        self.config.auto_tune_buffers = Some(cap);
+        Ok(self)
+    }
+
+    /// Prefers busy polling over interrupt-driven NAPI processing
+    ///
+    /// Sets SO_PREFER_BUSY_POLL and, if given, SO_BUSY_POLL_BUDGET (packets
+    /// per poll iteration). Use together with [`busy_poll`](Self::busy_poll).
+    ///
+    /// **Note**: Linux 5.11+ only; budgets above 8 require `CAP_NET_ADMIN`
+    pub fn prefer_busy_poll(mut self, budget: Option<u32>) -> io::Result<Self> {
+        self.config.prefer_busy_poll = true;
+        self.config.busy_poll_budget = budget;
+        Ok(self)
+    }
+
     /// Sets Type of Service / DSCP marking for traffic prioritization
     ///
     /// This sets the TOS byte in IP headers for QoS and traffic classification.
@@ -248,6 +378,43 @@ impl SocketBuilder {
         Ok(self)
     }
 
+    /// Sets the socket priority (SO_PRIORITY) used for qdisc band selection
+    ///
+    /// # Common Values
+    /// - `6`: Interactive, highest `pfifo_fast` band
+    /// - `0`: Best effort
+    /// - `2`: Bulk, lowest band
+    ///
+    /// **Note**: Linux only; values above 6 require `CAP_NET_ADMIN`
+    pub fn so_priority(mut self, prio: u32) -> io::Result<Self> {
+        self.config.so_priority = Some(prio);
+        Ok(self)
+    }
+
+    /// Enables ICMP error reporting for UDP sockets (IP_RECVERR, Linux only)
+    ///
+    /// Queued errors are read with `Udp::drain_errors()`.
+    pub fn recv_err(mut self, enable: bool) -> io::Result<Self> {
+        self.config.recv_err = enable;
+        Ok(self)
+    }
+
+    /// Sets the IPv6 flow label for outgoing packets (Linux only)
+    ///
+    /// Keeps the flow on one ECMP path. See [`NetConfig::flow_label`].
+    pub fn flow_label(mut self, label: u32) -> io::Result<Self> {
+        self.config.flow_label = Some(label);
+        Ok(self)
+    }
+
+    /// Sets IPv6 source address preferences (Linux only)
+    ///
+    /// See [`NetConfig::addr_preferences`].
+    pub fn addr_preferences(mut self, prefs: AddrPreferences) -> io::Result<Self> {
+        self.config.addr_preferences = Some(prefs);
+        Ok(self)
+    }
+
     /// Configures IPv6-only mode or dual-stack mode
     ///
     /// # Arguments
@@ -296,9 +463,12 @@ impl SocketBuilder {
         self.config.tcp_nodelay = preset.tcp_nodelay;
         self.config.tcp_quickack = preset.tcp_quickack;
         self.config.busy_poll = preset.busy_poll;
+        self.config.prefer_busy_poll = preset.prefer_busy_poll;
+        self.config.busy_poll_budget = preset.busy_poll_budget;
         self.config.recv_buf = preset.recv_buf;
         self.config.send_buf = preset.send_buf;
         self.config.tos = preset.tos;
+        self.config.so_priority = preset.so_priority;
         self.config.tcp_backlog = preset.tcp_backlog;
         self.config.poll_timeout_ms = preset.poll_timeout_ms;
         Ok(self)
@@ -317,9 +487,12 @@ impl SocketBuilder {
         self.config.tcp_nodelay = preset.tcp_nodelay;
         self.config.tcp_quickack = preset.tcp_quickack;
         self.config.busy_poll = preset.busy_poll;
+        self.config.prefer_busy_poll = preset.prefer_busy_poll;
+        self.config.busy_poll_budget = preset.busy_poll_budget;
         self.config.recv_buf = preset.recv_buf;
         self.config.send_buf = preset.send_buf;
         self.config.tos = preset.tos;
+        self.config.so_priority = preset.so_priority;
         self.config.tcp_backlog = preset.tcp_backlog;
         self.config.poll_timeout_ms = preset.poll_timeout_ms;
         Ok(self)
@@ -337,6 +510,8 @@ impl SocketBuilder {
         self.config.tcp_nodelay = preset.tcp_nodelay;
         self.config.tcp_quickack = preset.tcp_quickack;
         self.config.busy_poll = preset.busy_poll;
+        self.config.prefer_busy_poll = preset.prefer_busy_poll;
+        self.config.busy_poll_budget = preset.busy_poll_budget;
         self.config.recv_buf = preset.recv_buf;
         self.config.send_buf = preset.send_buf;
         self.config.reuse_port = preset.reuse_port;
@@ -344,69 +519,100 @@ impl SocketBuilder {
         self.config.poll_timeout_ms = preset.poll_timeout_ms;
         Ok(self)
     }
+}
 
-    /// Builds a UDP socket with the configured settings
+impl SocketBuilder<Bound> {
+    /// Builds a UDP socket bound to the `bind()` address
     ///
     /// # Returns
     /// A configured `Udp` socket ready for datagram operations
     ///
     /// # Errors
-    /// - No address specified with `bind()` or `bind_dual_stack()`
-    /// - Address is invalid or unavailable
+    /// - Address is unavailable
     /// - Socket creation fails
     pub fn udp(self) -> io::Result<Udp> {
-        if let Some(port) = self.dual_stack_port {
-            Udp::bind_dual_stack(port, &self.config)
-        } else if let Some(addr) = self.addr {
-            Udp::bind(addr, &self.config)
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Must specify address with bind() or bind_dual_stack()",
-            ))
-        }
+        Udp::bind(self.state.0, &self.config)
     }
 
-    /// Builds a TCP listener with the configured settings
+    /// Builds a TCP listener bound to the `bind()` address
     ///
     /// # Returns
     /// A configured `TcpListener` ready to accept connections
     ///
     /// # Errors
-    /// - No address specified with `bind()`
-    /// - Address is invalid or unavailable
+    /// - Address is unavailable
     /// - Listener creation fails
     pub fn tcp_listener(self) -> io::Result<TcpListener> {
-        if let Some(addr) = self.addr {
-            TcpListener::bind(addr, &self.config)
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Must specify address with bind() for TCP listener",
-            ))
-        }
+        TcpListener::bind(self.state.0, &self.config)
     }
 
-    /// Builds a TCP stream with the configured settings
+    /// Creates a new TCP stream from the `bind()` address and connects it
     ///
-    /// This requires a standard library TCP stream provided via `from_std_tcp()`.
+    /// Behaves like `connect()` on an unbound builder. Binding to port 0
+    /// lets the OS choose the source port.
+    pub fn connect<A: ToSocketAddrs>(self, addr: A) -> io::Result<TcpStream> {
+        connect_each(&self.config, Some(self.state.0), addr, None)
+    }
+
+    /// Like `connect()`, but fails with `TimedOut` if a handshake takes longer than `timeout`
+    pub fn connect_timeout<A: ToSocketAddrs>(self, addr: A, timeout: Duration) -> io::Result<TcpStream> {
+        connect_each(&self.config, Some(self.state.0), addr, Some(timeout))
+    }
+}
+
+impl SocketBuilder<DualStack> {
+    /// Builds a dual-stack UDP socket on the `bind_dual_stack()` port
+    ///
+    /// # Errors
+    /// - Port is unavailable
+    /// - IPv6 is not supported on this host
+    pub fn udp(self) -> io::Result<Udp> {
+        Udp::bind_dual_stack(self.state.0, &self.config)
+    }
+
+    /// Builds a dual-stack TCP listener on the `bind_dual_stack()` port
+    ///
+    /// # Errors
+    /// - Port is unavailable
+    /// - IPv6 is not supported on this host
+    pub fn tcp_listener(self) -> io::Result<TcpListener> {
+        TcpListener::bind(SocketAddr::from((Ipv6Addr::UNSPECIFIED, self.state.0)), &self.config)
+    }
+}
+
+impl SocketBuilder<FromStd> {
+    /// Builds a TCP stream by applying the configured settings to the `from_std_tcp()` stream
     ///
     /// # Returns
     /// A configured `TcpStream` ready for I/O operations
     ///
     /// # Errors
-    /// - No standard stream provided with `from_std_tcp()`
     /// - Stream configuration fails
     pub fn tcp_stream(self) -> io::Result<TcpStream> {
-        if let Some(std_stream) = self.std_tcp_stream {
-            TcpStream::from_std(std_stream, &self.config)
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Must provide standard stream with from_std_tcp() for TCP stream",
-            ))
+        TcpStream::from_std(self.state.0, &self.config)
+    }
+}
+
+/// Connects to each resolved address in turn, returning the first success
+fn connect_each<A: ToSocketAddrs>(
+    config: &NetConfig,
+    local: Option<SocketAddr>,
+    addr: A,
+    timeout: Option<Duration>,
+) -> io::Result<TcpStream> {
+    if timeout.is_some_and(|t| t.is_zero()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot connect with a zero timeout"));
+    }
+    let mut last_err = None;
+    for remote in addr.to_socket_addrs()? {
+        match TcpStream::connect_from(local, remote, config, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
         }
     }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Could not resolve to any addresses")
+    }))
 }
 
 impl Default for SocketBuilder {
@@ -422,8 +628,7 @@ mod tests {
     #[test]
     fn test_builder_creation() {
         let builder = SocketBuilder::new();
-        assert!(builder.addr.is_none());
-        assert!(builder.dual_stack_port.is_none());
+        assert_eq!(builder.config.ipv6_only, NetConfig::default().ipv6_only);
     }
 
     #[test]
@@ -431,8 +636,7 @@ mod tests {
         let builder = SocketBuilder::new()
             .bind("127.0.0.1:8080")
             .unwrap();
-        assert!(builder.addr.is_some());
-        assert_eq!(builder.addr.unwrap().port(), 8080);
+        assert_eq!(builder.state.0.port(), 8080);
     }
 
     #[test]
@@ -440,7 +644,7 @@ mod tests {
         let builder = SocketBuilder::new()
             .bind_dual_stack(8080)
             .unwrap();
-        assert_eq!(builder.dual_stack_port, Some(8080));
+        assert_eq!(builder.state.0, 8080);
         assert_eq!(builder.config.ipv6_only, Some(false));
     }
 
@@ -451,7 +655,7 @@ mod tests {
             .buffer_size(1024 * 1024).unwrap()
             .backlog(2048).unwrap();
         
-        assert_eq!(builder.config.tcp_nodelay, false);
+        assert!(!builder.config.tcp_nodelay);
         assert_eq!(builder.config.recv_buf, Some(1024 * 1024));
         assert_eq!(builder.config.tcp_backlog, Some(2048));
     }
@@ -462,12 +666,12 @@ mod tests {
             .low_latency()
             .unwrap();
         assert!(low_lat.config.busy_poll.is_some());
-        assert_eq!(low_lat.config.tcp_nodelay, true);
+        assert!(low_lat.config.tcp_nodelay);
 
         let high_tp = SocketBuilder::new()
             .high_throughput()
             .unwrap();
-        assert_eq!(high_tp.config.tcp_nodelay, false); // Nagle enabled for efficiency
+        assert!(!high_tp.config.tcp_nodelay); // Nagle enabled for efficiency
 
         let power = SocketBuilder::new()
             .power_efficient()
@@ -477,9 +681,7 @@ mod tests {
 
     #[test]
     fn test_udp_build_requires_address() {
-        let result = SocketBuilder::new().udp();
-        assert!(result.is_err());
-        
+        // Building without an address is a compile error, see the module docs
         let result = SocketBuilder::new()
             .bind("127.0.0.1:0")
             .unwrap()
@@ -489,13 +691,36 @@ mod tests {
 
     #[test]
     fn test_tcp_listener_build_requires_address() {
-        let result = SocketBuilder::new().tcp_listener();
-        assert!(result.is_err());
-        
         let result = SocketBuilder::new()
             .bind("127.0.0.1:0")
             .unwrap()
             .tcp_listener();
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_connect_applies_config() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stream = SocketBuilder::new()
+            .nodelay(true)
+            .unwrap()
+            .connect_timeout(addr, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        assert!(stream.as_std().nodelay().unwrap());
+
+        // The first resolved address refuses, the second connects
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let stream = SocketBuilder::new().connect(&[closed, addr][..]).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        let stream = SocketBuilder::new().bind("127.0.0.1:0").unwrap().connect(addr).unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), addr.ip());
+
+        let err = SocketBuilder::new().connect(closed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
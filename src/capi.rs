@@ -0,0 +1,764 @@
+//! C ABI over the UDP and TCP sockets, readiness polling, and the buffer pool
+//!
+//! Game engines written in C or C++ can embed the crate's tuned socket
+//! layer through these `extern "C"` functions instead of re-implementing
+//! it. Build a shared or static library with
+//! `cargo rustc --release --features capi --crate-type cdylib` (or
+//! `staticlib`) and include `include/horizon_sockets.h`.
+//!
+//! Conventions, matching the header:
+//!
+//! - Every call returns an [`HsError`]: `HS_OK` (0) or a flat error code.
+//!   The raw OS error behind the last failure on the calling thread is
+//!   available from [`hs_last_os_error`].
+//! - Sockets are opaque handles created by `hs_*_bind`/`hs_*_connect` and
+//!   destroyed with the matching `hs_*_free`. They are non-blocking: an
+//!   operation that would wait returns `HS_WOULD_BLOCK`, and
+//!   [`hs_poll`] waits for readiness.
+//! - Addresses cross the boundary as [`HsAddr`] with a host-order port.
+//! - A panic never unwinds into C; the call returns `HS_PANIC` instead.
+//!
+//! Requires the `capi` feature.
+
+use crate::addr::{fmt_addr_into, parse_addr};
+use crate::buffer_pool::BufferPool;
+use crate::poll::{self, Interest, Pollable};
+use crate::tcp::{TcpListener, TcpStream};
+use crate::udp::Udp;
+use crate::NetConfig;
+use std::cell::Cell;
+use std::ffi::{c_char, c_void, CStr};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+/// Result of every C API call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HsError {
+    /// Success
+    Ok = 0,
+    /// The operation would block; wait with [`hs_poll`] and retry
+    WouldBlock = 1,
+    /// A null pointer, malformed address, or unknown profile was passed
+    InvalidArgument = 2,
+    /// The address is already bound
+    AddrInUse = 3,
+    /// The address is not local to this host
+    AddrNotAvailable = 4,
+    /// The peer refused the connection
+    ConnectionRefused = 5,
+    /// The peer reset or aborted the connection
+    ConnectionReset = 6,
+    /// The socket is not connected
+    NotConnected = 7,
+    /// The operation timed out
+    TimedOut = 8,
+    /// The OS denied the operation
+    PermissionDenied = 9,
+    /// The option or operation is not supported on this platform
+    Unsupported = 10,
+    /// Any other I/O error; see [`hs_last_os_error`]
+    Io = 11,
+    /// The call panicked; the handle it was given should be freed
+    Panic = 12,
+}
+
+impl From<&io::Error> for HsError {
+    fn from(e: &io::Error) -> Self {
+        use io::ErrorKind::*;
+        match e.kind() {
+            WouldBlock => HsError::WouldBlock,
+            InvalidInput | InvalidData => HsError::InvalidArgument,
+            AddrInUse => HsError::AddrInUse,
+            AddrNotAvailable => HsError::AddrNotAvailable,
+            ConnectionRefused => HsError::ConnectionRefused,
+            ConnectionReset | ConnectionAborted | BrokenPipe => HsError::ConnectionReset,
+            NotConnected => HsError::NotConnected,
+            TimedOut => HsError::TimedOut,
+            PermissionDenied => HsError::PermissionDenied,
+            Unsupported => HsError::Unsupported,
+            _ => HsError::Io,
+        }
+    }
+}
+
+/// Socket address passed across the C boundary
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HsAddr {
+    /// 4 or 6
+    pub family: u8,
+    /// Port in host byte order
+    pub port: u16,
+    /// IPv4 address in the first 4 bytes, or the 16-byte IPv6 address
+    pub ip: [u8; 16],
+    /// IPv6 scope ID; 0 for IPv4
+    pub scope_id: u32,
+}
+
+impl From<SocketAddr> for HsAddr {
+    fn from(addr: SocketAddr) -> Self {
+        let mut out = HsAddr { port: addr.port(), ..Default::default() };
+        match addr {
+            SocketAddr::V4(v4) => {
+                out.family = 4;
+                out.ip[..4].copy_from_slice(&v4.ip().octets());
+            }
+            SocketAddr::V6(v6) => {
+                out.family = 6;
+                out.ip = v6.ip().octets();
+                out.scope_id = v6.scope_id();
+            }
+        }
+        out
+    }
+}
+
+impl HsAddr {
+    fn to_socket_addr(self) -> Option<SocketAddr> {
+        match self.family {
+            4 => Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(self.ip[0], self.ip[1], self.ip[2], self.ip[3])), self.port)),
+            6 => Some(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(self.ip), self.port, 0, self.scope_id))),
+            _ => None,
+        }
+    }
+}
+
+/// Pooled buffer handed to C: `capacity` writable bytes at `data`, `len` of them in use
+#[repr(C)]
+#[derive(Debug)]
+pub struct HsBuffer {
+    /// Start of the allocation
+    pub data: *mut u8,
+    /// Bytes in use, maintained by the caller
+    pub len: usize,
+    /// Bytes allocated
+    pub capacity: usize,
+}
+
+/// Which socket type an [`HsPollItem`] refers to
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HsSocketKind {
+    /// An `HsUdp` handle
+    Udp = 0,
+    /// An `HsTcpStream` handle
+    TcpStream = 1,
+    /// An `HsTcpListener` handle
+    TcpListener = 2,
+}
+
+impl TryFrom<u32> for HsSocketKind {
+    type Error = io::Error;
+
+    fn try_from(kind: u32) -> io::Result<Self> {
+        match kind {
+            0 => Ok(HsSocketKind::Udp),
+            1 => Ok(HsSocketKind::TcpStream),
+            2 => Ok(HsSocketKind::TcpListener),
+            _ => Err(invalid("unknown socket kind")),
+        }
+    }
+}
+
+/// One socket for [`hs_poll`], with the readiness it reported
+#[repr(C)]
+#[derive(Debug)]
+pub struct HsPollItem {
+    /// Type of `socket`, an [`HsSocketKind`] value; anything else is rejected
+    pub kind: u32,
+    /// The socket handle
+    pub socket: *const c_void,
+    /// Set by [`hs_poll`]: `HS_READABLE | HS_WRITABLE | HS_ERROR | HS_HANGUP`
+    pub revents: u8,
+}
+
+/// Readable, or a connection is waiting to be accepted
+pub const HS_READABLE: u8 = 1;
+/// Writable without blocking
+pub const HS_WRITABLE: u8 = 2;
+/// An error is pending on the socket
+pub const HS_ERROR: u8 = 4;
+/// The peer closed the connection
+pub const HS_HANGUP: u8 = 8;
+
+/// Opaque UDP socket handle
+pub type HsUdp = Udp;
+/// Opaque connected TCP stream handle
+pub type HsTcpStream = TcpStream;
+/// Opaque TCP listener handle
+pub type HsTcpListener = TcpListener;
+/// Opaque buffer pool handle
+pub type HsBufferPool = BufferPool;
+
+thread_local! {
+    static LAST_OS_ERROR: Cell<i32> = const { Cell::new(0) };
+}
+
+/// Runs `f`, turning errors and panics into codes and recording the OS error
+fn guard(f: impl FnOnce() -> io::Result<()>) -> HsError {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => HsError::Ok,
+        Ok(Err(e)) => {
+            LAST_OS_ERROR.with(|c| c.set(e.raw_os_error().unwrap_or(0)));
+            HsError::from(&e)
+        }
+        Err(_) => HsError::Panic,
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Borrows `*ptr`, failing on null
+unsafe fn arg<'a, T>(ptr: *const T) -> io::Result<&'a T> {
+    // SAFETY: the caller passes null or a valid pointer, per the function contract
+    unsafe { ptr.as_ref() }.ok_or_else(|| invalid("null pointer"))
+}
+
+/// Writes `value` to `*ptr` if it is not null
+unsafe fn put<T>(ptr: *mut T, value: T) {
+    if !ptr.is_null() {
+        // SAFETY: the caller passes null or a valid pointer, per the function contract
+        unsafe { ptr.write(value) };
+    }
+}
+
+/// Resolves an optional profile name to a config; null means the default
+unsafe fn config(profile: *const c_char) -> io::Result<NetConfig> {
+    if profile.is_null() {
+        return Ok(NetConfig::default());
+    }
+    // SAFETY: a non-null profile is a NUL-terminated string, per the function contract
+    let name = unsafe { CStr::from_ptr(profile) }.to_str().map_err(|_| invalid("profile name is not UTF-8"))?;
+    NetConfig::profile(name).ok_or_else(|| invalid("unknown profile"))
+}
+
+fn socket_addr(addr: &HsAddr) -> io::Result<SocketAddr> {
+    addr.to_socket_addr().ok_or_else(|| invalid("address family must be 4 or 6"))
+}
+
+/// Returns the OS error code behind the last failed call on this thread, or 0
+#[no_mangle]
+pub extern "C" fn hs_last_os_error() -> i32 {
+    LAST_OS_ERROR.with(|c| c.get())
+}
+
+/// Initializes the networking stack (WSAStartup on Windows); optional elsewhere
+#[no_mangle]
+pub extern "C" fn hs_init() -> HsError {
+    guard(crate::raw::init)
+}
+
+/// Parses `ip:port` or `[ipv6]:port` into `*out`
+///
+/// # Safety
+///
+/// `text` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_addr_parse(text: *const c_char, out: *mut HsAddr) -> HsError {
+    guard(|| {
+        if text.is_null() || out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        // SAFETY: text is NUL-terminated, per the contract
+        let bytes = unsafe { CStr::from_ptr(text) }.to_bytes();
+        let addr = parse_addr(bytes).ok_or_else(|| invalid("malformed address"))?;
+        unsafe { put(out, addr.into()) };
+        Ok(())
+    })
+}
+
+/// Formats `*addr` into `buf` as a NUL-terminated string, storing its length in `*len`
+///
+/// # Safety
+///
+/// `addr` must be valid for reads, `buf` valid for `cap` bytes of writes,
+/// and `len` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_addr_format(addr: *const HsAddr, buf: *mut c_char, cap: usize, len: *mut usize) -> HsError {
+    guard(|| {
+        let addr = socket_addr(unsafe { arg(addr) }?)?;
+        if buf.is_null() || cap == 0 {
+            return Err(invalid("null pointer"));
+        }
+        // SAFETY: buf is valid for cap bytes, per the contract
+        let out = unsafe { std::slice::from_raw_parts_mut(buf.cast::<u8>(), cap) };
+        let n = fmt_addr_into(&addr, &mut out[..cap - 1]).ok_or_else(|| invalid("buffer too small"))?;
+        out[n] = 0;
+        unsafe { put(len, n) };
+        Ok(())
+    })
+}
+
+/// Binds a UDP socket to `*addr`, tuned by the named profile (null for the default)
+///
+/// # Safety
+///
+/// `addr` must be valid for reads, `profile` null or NUL-terminated, and
+/// `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_udp_bind(addr: *const HsAddr, profile: *const c_char, out: *mut *mut HsUdp) -> HsError {
+    guard(|| {
+        let addr = socket_addr(unsafe { arg(addr) }?)?;
+        let cfg = unsafe { config(profile) }?;
+        if out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        let udp = Udp::bind(addr, &cfg)?;
+        unsafe { put(out, Box::into_raw(Box::new(udp))) };
+        Ok(())
+    })
+}
+
+/// Closes a UDP socket; null is ignored
+///
+/// # Safety
+///
+/// `udp` must come from [`hs_udp_bind`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_udp_free(udp: *mut HsUdp) {
+    if !udp.is_null() {
+        // SAFETY: udp was created by Box::into_raw in hs_udp_bind
+        drop(unsafe { Box::from_raw(udp) });
+    }
+}
+
+/// Stores the socket's bound address in `*out`
+///
+/// # Safety
+///
+/// `udp` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_udp_local_addr(udp: *const HsUdp, out: *mut HsAddr) -> HsError {
+    guard(|| {
+        let addr = unsafe { arg(udp) }?.local_addr()?;
+        unsafe { put(out, addr.into()) };
+        Ok(())
+    })
+}
+
+/// Sends `len` bytes at `buf` as one datagram to `*dest`
+///
+/// # Safety
+///
+/// `udp` must be a live handle, `buf` valid for `len` bytes of reads,
+/// `dest` valid for reads, and `sent` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_udp_send_to(udp: *const HsUdp, buf: *const u8, len: usize, dest: *const HsAddr, sent: *mut usize) -> HsError {
+    guard(|| {
+        let udp = unsafe { arg(udp) }?;
+        let dest = socket_addr(unsafe { arg(dest) }?)?;
+        let data = unsafe { bytes(buf, len) }?;
+        let n = udp.send_to(data, dest)?;
+        unsafe { put(sent, n) };
+        Ok(())
+    })
+}
+
+/// Receives one datagram into `buf`, storing its length in `*len` and sender in `*from`
+///
+/// Bytes beyond `cap` are discarded.
+///
+/// # Safety
+///
+/// `udp` must be a live handle, `buf` valid for `cap` bytes of writes, and
+/// `len` and `from` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_udp_recv_from(udp: *const HsUdp, buf: *mut u8, cap: usize, len: *mut usize, from: *mut HsAddr) -> HsError {
+    guard(|| {
+        let udp = unsafe { arg(udp) }?;
+        let data = unsafe { bytes_mut(buf, cap) }?;
+        let (n, addr) = udp.socket().recv_from(data)?;
+        unsafe {
+            put(len, n);
+            put(from, addr.into());
+        }
+        Ok(())
+    })
+}
+
+/// Connects a TCP stream to `*addr`, tuned by the named profile (null for the default)
+///
+/// Waits at most `timeout_ms` for the handshake (negative: the OS SYN
+/// timeout; 0 is rejected), returning `HS_TIMED_OUT` if it does not
+/// complete. The connected stream is non-blocking like every other handle.
+///
+/// # Safety
+///
+/// `addr` must be valid for reads, `profile` null or NUL-terminated, and
+/// `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_connect(
+    addr: *const HsAddr,
+    profile: *const c_char,
+    timeout_ms: i32,
+    out: *mut *mut HsTcpStream,
+) -> HsError {
+    guard(|| {
+        let addr = socket_addr(unsafe { arg(addr) }?)?;
+        let cfg = unsafe { config(profile) }?;
+        if out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        let stream = match u64::try_from(timeout_ms) {
+            Ok(ms) => TcpStream::connect_timeout(addr, &cfg, Duration::from_millis(ms))?,
+            Err(_) => TcpStream::connect(addr, &cfg)?,
+        };
+        stream.as_std().set_nonblocking(true)?;
+        unsafe { put(out, Box::into_raw(Box::new(stream))) };
+        Ok(())
+    })
+}
+
+/// Closes a TCP stream, applying its drop policy; null is ignored
+///
+/// # Safety
+///
+/// `stream` must come from [`hs_tcp_connect`] or [`hs_tcp_accept`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_free(stream: *mut HsTcpStream) {
+    if !stream.is_null() {
+        // SAFETY: stream was created by Box::into_raw in hs_tcp_connect or hs_tcp_accept
+        drop(unsafe { Box::from_raw(stream) });
+    }
+}
+
+/// Writes up to `len` bytes, storing how many were taken in `*sent`
+///
+/// # Safety
+///
+/// `stream` must be a live handle, `buf` valid for `len` bytes of reads,
+/// and `sent` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_send(stream: *const HsTcpStream, buf: *const u8, len: usize, sent: *mut usize) -> HsError {
+    guard(|| {
+        let mut stream = unsafe { arg(stream) }?;
+        let n = stream.write(unsafe { bytes(buf, len) }?)?;
+        unsafe { put(sent, n) };
+        Ok(())
+    })
+}
+
+/// Reads up to `cap` bytes, storing how many arrived in `*received`; 0 means the peer closed
+///
+/// # Safety
+///
+/// `stream` must be a live handle, `buf` valid for `cap` bytes of writes,
+/// and `received` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_recv(stream: *const HsTcpStream, buf: *mut u8, cap: usize, received: *mut usize) -> HsError {
+    guard(|| {
+        let mut stream = unsafe { arg(stream) }?;
+        let n = stream.read(unsafe { bytes_mut(buf, cap) }?)?;
+        unsafe { put(received, n) };
+        Ok(())
+    })
+}
+
+/// Binds a TCP listener to `*addr`, tuned by the named profile (null for the default)
+///
+/// # Safety
+///
+/// `addr` must be valid for reads, `profile` null or NUL-terminated, and
+/// `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_listen(addr: *const HsAddr, profile: *const c_char, out: *mut *mut HsTcpListener) -> HsError {
+    guard(|| {
+        let addr = socket_addr(unsafe { arg(addr) }?)?;
+        let cfg = unsafe { config(profile) }?;
+        if out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        let listener = TcpListener::bind(addr, &cfg)?;
+        unsafe { put(out, Box::into_raw(Box::new(listener))) };
+        Ok(())
+    })
+}
+
+/// Closes a TCP listener; null is ignored
+///
+/// # Safety
+///
+/// `listener` must come from [`hs_tcp_listen`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_listener_free(listener: *mut HsTcpListener) {
+    if !listener.is_null() {
+        // SAFETY: listener was created by Box::into_raw in hs_tcp_listen
+        drop(unsafe { Box::from_raw(listener) });
+    }
+}
+
+/// Stores the listener's bound address in `*out`
+///
+/// # Safety
+///
+/// `listener` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_listener_local_addr(listener: *const HsTcpListener, out: *mut HsAddr) -> HsError {
+    guard(|| {
+        let addr = unsafe { arg(listener) }?.local_addr()?;
+        unsafe { put(out, addr.into()) };
+        Ok(())
+    })
+}
+
+/// Accepts one pending connection into `*out`, storing the peer in `*peer`
+///
+/// # Safety
+///
+/// `listener` must be a live handle, `out` valid for writes, and `peer`
+/// null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_tcp_accept(listener: *const HsTcpListener, out: *mut *mut HsTcpStream, peer: *mut HsAddr) -> HsError {
+    guard(|| {
+        let listener = unsafe { arg(listener) }?;
+        if out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        let (stream, addr) = listener.accept_nonblocking()?;
+        unsafe {
+            put(out, Box::into_raw(Box::new(stream)));
+            put(peer, addr.into());
+        }
+        Ok(())
+    })
+}
+
+/// Waits up to `timeout_ms` (negative: forever) until a socket in `items` is ready for `interest`
+///
+/// `interest` is `HS_READABLE`, `HS_WRITABLE`, or both. Each item's
+/// `revents` is set, and the number of ready items stored in `*ready`.
+///
+/// # Safety
+///
+/// `items` must be valid for `n` items of reads and writes, each naming a
+/// live handle of its `kind`, and `ready` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_poll(items: *mut HsPollItem, n: usize, interest: u8, timeout_ms: i32, ready: *mut usize) -> HsError {
+    guard(|| {
+        if items.is_null() && n > 0 {
+            return Err(invalid("null pointer"));
+        }
+        let items: &mut [HsPollItem] = if n == 0 { &mut [] } else { unsafe { std::slice::from_raw_parts_mut(items, n) } };
+        let mut sockets: Vec<&dyn Pollable> = Vec::with_capacity(n);
+        for item in items.iter() {
+            if item.socket.is_null() {
+                return Err(invalid("null pointer"));
+            }
+            let kind = HsSocketKind::try_from(item.kind)?;
+            // SAFETY: each item names a live handle of its kind, per the contract
+            sockets.push(unsafe {
+                match kind {
+                    HsSocketKind::Udp => &*item.socket.cast::<Udp>() as &dyn Pollable,
+                    HsSocketKind::TcpStream => &*item.socket.cast::<TcpStream>(),
+                    HsSocketKind::TcpListener => &*item.socket.cast::<TcpListener>(),
+                }
+            });
+        }
+        let interest = match (interest & HS_READABLE != 0, interest & HS_WRITABLE != 0) {
+            (true, true) => Interest::READABLE | Interest::WRITABLE,
+            (true, false) => Interest::READABLE,
+            (false, true) => Interest::WRITABLE,
+            (false, false) => return Err(invalid("empty interest")),
+        };
+        let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+        let readiness = poll::wait(&sockets, interest, timeout)?;
+        let mut count = 0;
+        for (item, r) in items.iter_mut().zip(&readiness) {
+            item.revents = (r.readable as u8 * HS_READABLE)
+                | (r.writable as u8 * HS_WRITABLE)
+                | (r.error as u8 * HS_ERROR)
+                | (r.hangup as u8 * HS_HANGUP);
+            count += r.is_ready() as usize;
+        }
+        unsafe { put(ready, count) };
+        Ok(())
+    })
+}
+
+/// Creates a pool of `count` buffers of `capacity` bytes; returns null on failure
+#[no_mangle]
+pub extern "C" fn hs_pool_new(count: usize, capacity: usize) -> *mut HsBufferPool {
+    panic::catch_unwind(|| Box::into_raw(Box::new(BufferPool::new(count, capacity)))).unwrap_or(std::ptr::null_mut())
+}
+
+/// Frees a pool; buffers still acquired from it stay valid until released; null is ignored
+///
+/// # Safety
+///
+/// `pool` must come from [`hs_pool_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_pool_free(pool: *mut HsBufferPool) {
+    if !pool.is_null() {
+        // SAFETY: pool was created by Box::into_raw in hs_pool_new
+        drop(unsafe { Box::from_raw(pool) });
+    }
+}
+
+/// Takes a buffer from the pool into `*out`, with `len` 0
+///
+/// # Safety
+///
+/// `pool` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_pool_acquire(pool: *const HsBufferPool, out: *mut HsBuffer) -> HsError {
+    guard(|| {
+        let pool = unsafe { arg(pool) }?;
+        if out.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        let mut buffer = std::mem::ManuallyDrop::new(pool.acquire());
+        let raw = HsBuffer { data: buffer.as_mut_ptr(), len: 0, capacity: buffer.capacity() };
+        unsafe { put(out, raw) };
+        Ok(())
+    })
+}
+
+/// Returns a buffer to the pool
+///
+/// # Safety
+///
+/// `buffer` must come from [`hs_pool_acquire`] on any pool, unchanged
+/// except for `len`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_pool_release(pool: *const HsBufferPool, buffer: HsBuffer) -> HsError {
+    guard(|| {
+        let pool = unsafe { arg(pool) }?;
+        if buffer.data.is_null() {
+            return Err(invalid("null pointer"));
+        }
+        // SAFETY: data and capacity describe a Vec<u8> leaked by hs_pool_acquire; length 0 claims nothing initialized
+        pool.release(unsafe { Vec::from_raw_parts(buffer.data, 0, buffer.capacity) });
+        Ok(())
+    })
+}
+
+/// Views `len` bytes at `ptr`, allowing null for an empty slice
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> io::Result<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(invalid("null pointer")),
+        // SAFETY: ptr is valid for len bytes, per the function contract
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(ptr, len) }),
+    }
+}
+
+/// Views `len` writable bytes at `ptr`, allowing null for an empty slice
+unsafe fn bytes_mut<'a>(ptr: *mut u8, len: usize) -> io::Result<&'a mut [u8]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Ok(&mut []),
+        (true, _) => Err(invalid("null pointer")),
+        // SAFETY: ptr is valid for len bytes of writes, per the function contract
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts_mut(ptr, len) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_udp_round_trip_and_pool() {
+        unsafe {
+            let mut addr = HsAddr::default();
+            assert_eq!(hs_addr_parse(c"127.0.0.1:0".as_ptr(), &mut addr), HsError::Ok);
+            assert_eq!(hs_addr_parse(c"nope".as_ptr(), &mut addr), HsError::InvalidArgument);
+
+            let (mut rx, mut tx) = (ptr::null_mut(), ptr::null_mut());
+            assert_eq!(hs_udp_bind(&addr, ptr::null(), &mut rx), HsError::Ok);
+            assert_eq!(hs_udp_bind(&addr, c"low-latency".as_ptr(), &mut tx), HsError::Ok);
+            assert_eq!(hs_udp_bind(&addr, c"no-such-profile".as_ptr(), &mut tx), HsError::InvalidArgument);
+            let mut dest = HsAddr::default();
+            assert_eq!(hs_udp_local_addr(rx, &mut dest), HsError::Ok);
+
+            let mut text = [0 as c_char; 64];
+            let mut len = 0;
+            assert_eq!(hs_addr_format(&dest, text.as_mut_ptr(), text.len(), &mut len), HsError::Ok);
+            assert_eq!(CStr::from_ptr(text.as_ptr()).to_str().unwrap(), dest.to_socket_addr().unwrap().to_string());
+
+            let pool = hs_pool_new(2, 256);
+            let mut buf = HsBuffer { data: ptr::null_mut(), len: 0, capacity: 0 };
+            assert_eq!(hs_pool_acquire(pool, &mut buf), HsError::Ok);
+            assert!(buf.capacity >= 256);
+
+            assert_eq!(hs_udp_recv_from(rx, buf.data, buf.capacity, &mut len, ptr::null_mut()), HsError::WouldBlock);
+            let mut sent = 0;
+            assert_eq!(hs_udp_send_to(tx, b"ping".as_ptr(), 4, &dest, &mut sent), HsError::Ok);
+            assert_eq!(sent, 4);
+
+            let mut item = HsPollItem { kind: HsSocketKind::Udp as u32, socket: rx.cast(), revents: 0 };
+            let mut ready = 0;
+            assert_eq!(hs_poll(&mut item, 1, HS_READABLE, 5000, &mut ready), HsError::Ok);
+            assert_eq!((ready, item.revents & HS_READABLE), (1, HS_READABLE));
+
+            let mut from = HsAddr::default();
+            assert_eq!(hs_udp_recv_from(rx, buf.data, buf.capacity, &mut buf.len, &mut from), HsError::Ok);
+            assert_eq!(std::slice::from_raw_parts(buf.data, buf.len), b"ping");
+            let mut tx_addr = HsAddr::default();
+            hs_udp_local_addr(tx, &mut tx_addr);
+            assert_eq!(from, tx_addr);
+
+            assert_eq!(hs_pool_release(pool, buf), HsError::Ok);
+            assert_eq!((*pool).available_count(), 2);
+            hs_pool_free(pool);
+            hs_udp_free(rx);
+            hs_udp_free(tx);
+            assert_eq!(hs_udp_local_addr(ptr::null(), &mut dest), HsError::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_tcp_round_trip_is_non_blocking() {
+        unsafe {
+            let mut addr = HsAddr::default();
+            assert_eq!(hs_addr_parse(c"127.0.0.1:0".as_ptr(), &mut addr), HsError::Ok);
+            let mut listener = ptr::null_mut();
+            assert_eq!(hs_tcp_listen(&addr, ptr::null(), &mut listener), HsError::Ok);
+            let mut dest = HsAddr::default();
+            assert_eq!(hs_tcp_listener_local_addr(listener, &mut dest), HsError::Ok);
+
+            let mut client = ptr::null_mut();
+            assert_eq!(hs_tcp_connect(&dest, ptr::null(), 0, &mut client), HsError::InvalidArgument);
+            assert_eq!(hs_tcp_connect(&dest, ptr::null(), 5000, &mut client), HsError::Ok);
+
+            let mut item = HsPollItem { kind: HsSocketKind::TcpListener as u32, socket: listener.cast(), revents: 0 };
+            let mut ready = 0;
+            assert_eq!(hs_poll(&mut item, 1, HS_READABLE, 5000, &mut ready), HsError::Ok);
+            let mut server = ptr::null_mut();
+            assert_eq!(hs_tcp_accept(listener, &mut server, ptr::null_mut()), HsError::Ok);
+
+            // Neither end may block the caller when nothing has arrived
+            let mut buf = [0u8; 16];
+            let mut len = 0;
+            assert_eq!(hs_tcp_recv(client, buf.as_mut_ptr(), buf.len(), &mut len), HsError::WouldBlock);
+            assert_eq!(hs_tcp_recv(server, buf.as_mut_ptr(), buf.len(), &mut len), HsError::WouldBlock);
+
+            let mut sent = 0;
+            assert_eq!(hs_tcp_send(client, b"ping".as_ptr(), 4, &mut sent), HsError::Ok);
+            assert_eq!(sent, 4);
+            let mut item = HsPollItem { kind: HsSocketKind::TcpStream as u32, socket: server.cast(), revents: 0 };
+            assert_eq!(hs_poll(&mut item, 1, HS_READABLE, 5000, &mut ready), HsError::Ok);
+            assert_eq!(hs_tcp_recv(server, buf.as_mut_ptr(), buf.len(), &mut len), HsError::Ok);
+            assert_eq!(&buf[..len], b"ping");
+
+            assert_eq!(hs_tcp_send(server, b"pong".as_ptr(), 4, &mut sent), HsError::Ok);
+            let mut item = HsPollItem { kind: HsSocketKind::TcpStream as u32, socket: client.cast(), revents: 0 };
+            assert_eq!(hs_poll(&mut item, 1, HS_READABLE, 5000, &mut ready), HsError::Ok);
+            assert_eq!(hs_tcp_recv(client, buf.as_mut_ptr(), buf.len(), &mut len), HsError::Ok);
+            assert_eq!(&buf[..len], b"pong");
+
+            // Kinds outside the enum are rejected rather than reinterpreted
+            let mut item = HsPollItem { kind: 7, socket: client.cast(), revents: 0 };
+            assert_eq!(hs_poll(&mut item, 1, HS_READABLE, 0, &mut ready), HsError::InvalidArgument);
+
+            hs_tcp_free(client);
+            hs_tcp_free(server);
+            hs_tcp_listener_free(listener);
+        }
+    }
+}
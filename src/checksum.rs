@@ -0,0 +1,7 @@
+//! Internet checksum and CRC32C for packet processing
+//!
+//! Re-exported from `horizon_sockets_core`, where it builds without the
+//! standard library, so firmware computing the same checksums shares this
+//! code. Here the hardware CRC32C path is chosen by runtime CPU detection.
+
+pub use horizon_sockets_core::checksum::*;
@@ -0,0 +1,295 @@
+//! Connection-ID routing for UDP
+//!
+//! Routing datagrams by source address breaks as soon as a client's NAT
+//! rebinds or the client moves between networks. Protocols like QUIC put a
+//! connection ID in every packet instead. [`CidRouter`] routes datagrams
+//! received with `recv_batch` to per-connection handlers by that ID, tracks
+//! each connection's current peer address, and tells the handler when the
+//! path changes.
+//!
+//! The router does not know the wire format: a caller-supplied extractor
+//! pulls the ID out of the datagram. [`fixed_len`] covers the common case of
+//! a fixed-size ID at a fixed offset.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::cid::{fixed_len, CidRouter};
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:4433".parse()?, &NetConfig::default())?;
+//! // 8-byte connection ID after a 1-byte header
+//! let mut router = CidRouter::new(fixed_len::<8>(1));
+//! let mut bufs = vec![vec![0u8; 1500]; 32];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 32];
+//!
+//! loop {
+//!     let count = match socket.recv_batch(&mut bufs, &mut addrs) {
+//!         Ok(n) => n,
+//!         Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+//!         Err(e) => return Err(e.into()),
+//!     };
+//!     let mut accepted = Vec::new();
+//!     router.dispatch(&bufs[..count], &addrs[..count], |cid, _data, from| {
+//!         // Unknown ID: a new connection, or garbage
+//!         if let Some(cid) = cid {
+//!             accepted.push((cid, from));
+//!         }
+//!     });
+//!     for (cid, from) in accepted {
+//!         router.insert(cid, from, move |cid: &[u8; 8], data: &[u8], from: SocketAddr| {
+//!             println!("{:02x?}: {} bytes from {}", cid, data.len(), from);
+//!         });
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::net::SocketAddr;
+
+/// Receives the datagrams routed to one connection
+///
+/// Implemented for closures taking `(&cid, data, from)`.
+pub trait CidHandler<C> {
+    /// Handles a datagram addressed to `cid` received from `from`
+    fn on_datagram(&mut self, cid: &C, data: &[u8], from: SocketAddr);
+
+    /// Called before `on_datagram` when the connection's peer address changes
+    ///
+    /// The router has already switched the connection to `new`. Protocols
+    /// that validate new paths (QUIC path validation) can start that here.
+    fn on_path_change(&mut self, _cid: &C, _old: SocketAddr, _new: SocketAddr) {}
+}
+
+impl<C, F: FnMut(&C, &[u8], SocketAddr)> CidHandler<C> for F {
+    fn on_datagram(&mut self, cid: &C, data: &[u8], from: SocketAddr) {
+        self(cid, data, from)
+    }
+}
+
+/// Outcome of routing one datagram
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Routed<C> {
+    /// Delivered to the connection's handler from its known peer address
+    Delivered,
+    /// Delivered after the connection's peer address changed from `old`
+    PathChanged {
+        /// Previous peer address
+        old: SocketAddr,
+    },
+    /// No connection matched; `None` if the extractor found no ID
+    Unknown(Option<C>),
+}
+
+/// Routes datagrams to connection handlers by connection ID
+///
+/// A connection can be reachable under several IDs (see [`alias`](Self::alias)),
+/// as QUIC connections are after issuing new connection IDs.
+pub struct CidRouter<C, H> {
+    extract: Extractor<C>,
+    /// Connection slot per ID
+    routes: HashMap<C, usize>,
+    conns: Vec<Option<Conn<C, H>>>,
+    free: Vec<usize>,
+}
+
+type Extractor<C> = Box<dyn Fn(&[u8]) -> Option<C> + Send>;
+
+struct Conn<C, H> {
+    handler: H,
+    peer: SocketAddr,
+    cids: Vec<C>,
+}
+
+impl<C: Hash + Eq + Clone, H: CidHandler<C>> CidRouter<C, H> {
+    /// Creates a router using `extract` to read the connection ID of each datagram
+    pub fn new(extract: impl Fn(&[u8]) -> Option<C> + Send + 'static) -> Self {
+        Self { extract: Box::new(extract), routes: HashMap::new(), conns: Vec::new(), free: Vec::new() }
+    }
+
+    /// Registers a connection under `cid`, last seen at `peer`
+    ///
+    /// # Returns
+    ///
+    /// The handler of the connection previously registered under `cid`, if
+    /// any. That connection is removed along with all its IDs.
+    pub fn insert(&mut self, cid: C, peer: SocketAddr, handler: H) -> Option<H> {
+        let old = self.remove(&cid);
+        let conn = Conn { handler, peer, cids: vec![cid.clone()] };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.conns[slot] = Some(conn);
+                slot
+            }
+            None => {
+                self.conns.push(Some(conn));
+                self.conns.len() - 1
+            }
+        };
+        self.routes.insert(cid, slot);
+        old
+    }
+
+    /// Makes the connection registered under `existing` reachable under `cid` too
+    ///
+    /// Returns `false` if `existing` is unknown or `cid` already routes elsewhere.
+    pub fn alias(&mut self, existing: &C, cid: C) -> bool {
+        let Some(&slot) = self.routes.get(existing) else { return false };
+        match self.routes.get(&cid) {
+            Some(&other) => other == slot,
+            None => {
+                if let Some(conn) = self.conns[slot].as_mut() {
+                    conn.cids.push(cid.clone());
+                }
+                self.routes.insert(cid, slot);
+                true
+            }
+        }
+    }
+
+    /// Stops routing `cid`, keeping the connection if it has other IDs
+    ///
+    /// Returns the handler if this was the connection's last ID.
+    pub fn retire(&mut self, cid: &C) -> Option<H> {
+        let slot = self.routes.remove(cid)?;
+        let conn = self.conns[slot].as_mut()?;
+        conn.cids.retain(|c| c != cid);
+        if conn.cids.is_empty() {
+            self.free.push(slot);
+            return self.conns[slot].take().map(|c| c.handler);
+        }
+        None
+    }
+
+    /// Removes the connection registered under `cid` and all its other IDs
+    pub fn remove(&mut self, cid: &C) -> Option<H> {
+        let slot = *self.routes.get(cid)?;
+        let conn = self.conns[slot].take()?;
+        for c in &conn.cids {
+            self.routes.remove(c);
+        }
+        self.free.push(slot);
+        Some(conn.handler)
+    }
+
+    /// Returns the handler for `cid`
+    pub fn get_mut(&mut self, cid: &C) -> Option<&mut H> {
+        let slot = *self.routes.get(cid)?;
+        self.conns[slot].as_mut().map(|c| &mut c.handler)
+    }
+
+    /// Returns the last peer address seen for the connection under `cid`
+    pub fn peer(&self, cid: &C) -> Option<SocketAddr> {
+        let slot = *self.routes.get(cid)?;
+        self.conns[slot].as_ref().map(|c| c.peer)
+    }
+
+    /// Returns the number of connections
+    pub fn len(&self) -> usize {
+        self.conns.len() - self.free.len()
+    }
+
+    /// Returns `true` if no connections are registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Routes one datagram received from `from`
+    pub fn route(&mut self, data: &[u8], from: SocketAddr) -> Routed<C> {
+        let Some(cid) = (self.extract)(data) else { return Routed::Unknown(None) };
+        let Some(conn) = self.routes.get(&cid).and_then(|&slot| self.conns[slot].as_mut()) else {
+            return Routed::Unknown(Some(cid));
+        };
+        let routed = if conn.peer != from {
+            let old = std::mem::replace(&mut conn.peer, from);
+            conn.handler.on_path_change(&cid, old, from);
+            Routed::PathChanged { old }
+        } else {
+            Routed::Delivered
+        };
+        conn.handler.on_datagram(&cid, data, from);
+        routed
+    }
+
+    /// Routes a batch from `recv_batch`, passing unmatched datagrams to `on_unknown`
+    ///
+    /// `on_unknown` receives the extracted ID (if any), the datagram, and
+    /// the sender, typically to accept a new connection.
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams delivered to a handler
+    pub fn dispatch(
+        &mut self,
+        bufs: &[Vec<u8>],
+        addrs: &[SocketAddr],
+        mut on_unknown: impl FnMut(Option<C>, &[u8], SocketAddr),
+    ) -> usize {
+        let mut delivered = 0;
+        for (buf, &from) in bufs.iter().zip(addrs) {
+            match self.route(buf, from) {
+                Routed::Unknown(cid) => on_unknown(cid, buf, from),
+                _ => delivered += 1,
+            }
+        }
+        delivered
+    }
+}
+
+impl<C, H> fmt::Debug for CidRouter<C, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CidRouter")
+            .field("routes", &self.routes.len())
+            .field("connections", &(self.conns.len() - self.free.len()))
+            .finish()
+    }
+}
+
+/// Extractor for an `N`-byte connection ID starting at `offset`
+///
+/// Datagrams too short to hold the ID yield `None`.
+pub fn fixed_len<const N: usize>(offset: usize) -> impl Fn(&[u8]) -> Option<[u8; N]> + Send + 'static {
+    move |data| data.get(offset..offset + N)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_routes_by_cid_across_path_changes() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut router = CidRouter::new(fixed_len::<2>(1));
+        let a: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let b: SocketAddr = "198.51.100.7:6000".parse().unwrap();
+
+        let sink = log.clone();
+        router.insert([1, 2], a, move |cid: &[u8; 2], data: &[u8], from| {
+            sink.lock().unwrap().push((*cid, data[3], from))
+        });
+        assert!(router.alias(&[1, 2], [3, 4]));
+
+        assert_eq!(router.route(&[0, 1, 2, 10], a), Routed::Delivered);
+        // NAT rebinding: same connection ID, new source address
+        assert_eq!(router.route(&[0, 3, 4, 11], b), Routed::PathChanged { old: a });
+        assert_eq!(router.peer(&[1, 2]), Some(b));
+        assert_eq!(router.route(&[0, 9, 9, 12], b), Routed::Unknown(Some([9, 9])));
+        assert_eq!(router.route(&[0], b), Routed::Unknown(None));
+
+        let mut unknown = 0;
+        let bufs = vec![vec![0, 1, 2, 13], vec![0, 7, 7, 14]];
+        assert_eq!(router.dispatch(&bufs, &[b, b], |_, _, _| unknown += 1), 1);
+        assert_eq!(unknown, 1);
+        assert_eq!(*log.lock().unwrap(), vec![([1, 2], 10, a), ([3, 4], 11, b), ([1, 2], 13, b)]);
+
+        assert!(router.retire(&[1, 2]).is_none());
+        assert_eq!(router.len(), 1);
+        assert!(router.retire(&[3, 4]).is_some());
+        assert!(router.is_empty());
+    }
+}
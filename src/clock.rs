@@ -0,0 +1,261 @@
+//! Selectable monotonic clocks: TSC, `CLOCK_MONOTONIC_RAW`, and coarse
+//!
+//! `Instant::now()` is a vDSO call on Linux and `QueryPerformanceCounter`
+//! on Windows, around 20-40 ns each. That is nothing for one call but adds
+//! up in loops that stamp every packet, refill token buckets per send, or
+//! check timer deadlines per event. A [`Clock`] picks where readings come
+//! from, trading precision for cost:
+//!
+//! | [`ClockSource`] | Cost | Resolution | Availability |
+//! |---|---|---|---|
+//! | `Std` | vDSO / QPC | ns | everywhere |
+//! | `Tsc` | one `rdtsc` | sub-ns | x86_64 with an invariant TSC |
+//! | `MonotonicRaw` | vDSO | ns, not slewed by NTP | Linux, Android |
+//! | `Coarse` | a few ns | one scheduler tick (1-4 ms) | Linux, Android |
+//!
+//! Sources that are not available fall back to `Std`;
+//! [`source`](Clock::source) reports the one actually used. Every source
+//! returns an `Instant`, so readings mix freely with `Instant::now()` and
+//! with the deadlines the rest of the crate takes: each is offset from an
+//! anchor taken from both clocks once.
+//!
+//! The TSC is calibrated against the standard clock the first time a
+//! `Tsc` clock is created, which sleeps for about 10 ms. The calibration is
+//! shared by the process. Because it is measured rather than read from the
+//! CPU, TSC readings can drift from `Instant::now()` by a few parts per
+//! million, i.e. microseconds per second; use it for intervals and
+//! deadlines rather than to compare against other clocks over hours.
+//!
+//! `MonotonicRaw` drifts the same way for a different reason: it runs at
+//! the hardware rate while `Instant::now()` follows NTP's frequency
+//! corrections, which reach 500 parts per million. The anchor is never
+//! re-taken, since that would step readings, so the gap grows for as long
+//! as NTP is correcting the clock. `Coarse` shares `Instant::now()`'s
+//! corrections and does not drift.
+//!
+//! [`RecvArena`](crate::arena::RecvArena),
+//! [`TickScheduler`](crate::tick::TickScheduler),
+//! [`PacedSender`](crate::pacer::PacedSender), and
+//! [`Metrics`](crate::metrics::Metrics) take a clock through their
+//! builders.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::clock::{Clock, ClockSource};
+//!
+//! let clock = Clock::new(ClockSource::Tsc);
+//! let start = clock.now();
+//! // ... work ...
+//! println!("took {:?} using {:?}", clock.elapsed(start), clock.source());
+//! ```
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Where a [`Clock`] reads time from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ClockSource {
+    /// `Instant::now()`
+    #[default]
+    Std,
+    /// The CPU timestamp counter, calibrated against `Std` (x86_64 with an invariant TSC)
+    Tsc,
+    /// `CLOCK_MONOTONIC_RAW`, free of NTP frequency adjustments (Linux, Android)
+    MonotonicRaw,
+    /// `CLOCK_MONOTONIC_COARSE`, cheapest but only as fine as the scheduler tick (Linux, Android)
+    Coarse,
+}
+
+/// A monotonic clock reading from a chosen [`ClockSource`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Clock {
+    source: ClockSource,
+}
+
+impl Clock {
+    /// Creates a clock reading from `source`, or from `Std` if it is unavailable here
+    ///
+    /// The first `Tsc` clock in the process calibrates the TSC, which takes
+    /// about 10 ms.
+    pub fn new(source: ClockSource) -> Self {
+        let available = match source {
+            ClockSource::Std => true,
+            ClockSource::Tsc => tsc::calibration().is_some(),
+            ClockSource::MonotonicRaw | ClockSource::Coarse => cfg!(any(target_os = "linux", target_os = "android")),
+        };
+        Self { source: if available { source } else { ClockSource::Std } }
+    }
+
+    /// Returns the source readings actually come from
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// Reads the clock
+    #[inline]
+    pub fn now(&self) -> Instant {
+        match self.source {
+            ClockSource::Std => Instant::now(),
+            ClockSource::Tsc => tsc::now(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockSource::MonotonicRaw => posix::now(libc::CLOCK_MONOTONIC_RAW),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockSource::Coarse => posix::now(libc::CLOCK_MONOTONIC_COARSE),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            ClockSource::MonotonicRaw | ClockSource::Coarse => Instant::now(),
+        }
+    }
+
+    /// Returns the time since `earlier`, zero if `earlier` is in the future
+    #[inline]
+    pub fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    /// Returns the smallest step between distinct readings, if known
+    ///
+    /// Known for the Linux clocks (from `clock_getres`) and the TSC.
+    pub fn resolution(&self) -> Option<Duration> {
+        match self.source {
+            ClockSource::Tsc => tsc::calibration().map(|c| Duration::from_nanos(((c.mult >> 32) as u64).max(1))),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockSource::Std => posix::resolution(libc::CLOCK_MONOTONIC),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockSource::MonotonicRaw => posix::resolution(libc::CLOCK_MONOTONIC_RAW),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ClockSource::Coarse => posix::resolution(libc::CLOCK_MONOTONIC_COARSE),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            _ => None,
+        }
+    }
+}
+
+/// Returns the calibrated TSC frequency in Hz, or `None` without an invariant TSC
+///
+/// Calibrates on first use (about 10 ms).
+pub fn tsc_hz() -> Option<u64> {
+    tsc::calibration().map(|c| ((1u128 << 32) * 1_000_000_000 / c.mult) as u64)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod posix {
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant};
+
+    fn read(id: libc::clockid_t) -> Duration {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(id, &mut ts) };
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    /// Reads `id` as an `Instant` offset from an anchor taken from both clocks once
+    ///
+    /// The anchor is off by about one reading's resolution when taken.
+    /// `CLOCK_MONOTONIC_RAW` is not frequency-corrected, so it drifts from
+    /// `Instant` afterwards; see the module docs.
+    pub(super) fn now(id: libc::clockid_t) -> Instant {
+        static RAW: OnceLock<(Instant, Duration)> = OnceLock::new();
+        static COARSE: OnceLock<(Instant, Duration)> = OnceLock::new();
+        let anchor = if id == libc::CLOCK_MONOTONIC_COARSE { &COARSE } else { &RAW };
+        let (instant, base) = anchor.get_or_init(|| (Instant::now(), read(id)));
+        *instant + read(id).saturating_sub(*base)
+    }
+
+    pub(super) fn resolution(id: libc::clockid_t) -> Option<Duration> {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        if unsafe { libc::clock_getres(id, &mut ts) } != 0 {
+            return None;
+        }
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+mod tsc {
+    use super::*;
+
+    /// Maps TSC ticks onto `Instant`s: `ns = (ticks * mult) >> 32`
+    pub(super) struct Calibration {
+        anchor: Instant,
+        base: u64,
+        pub(super) mult: u128,
+    }
+
+    const WINDOW: Duration = Duration::from_millis(10);
+
+    pub(super) fn calibration() -> Option<&'static Calibration> {
+        static CALIBRATION: OnceLock<Option<Calibration>> = OnceLock::new();
+        CALIBRATION.get_or_init(calibrate).as_ref()
+    }
+
+    #[inline]
+    pub(super) fn now() -> Instant {
+        match calibration() {
+            Some(c) => {
+                let ticks = read().saturating_sub(c.base);
+                c.anchor + Duration::from_nanos(((u128::from(ticks) * c.mult) >> 32) as u64)
+            }
+            None => Instant::now(),
+        }
+    }
+
+    fn calibrate() -> Option<Calibration> {
+        if !invariant() {
+            return None;
+        }
+        let (t0, c0) = (Instant::now(), read());
+        std::thread::sleep(WINDOW);
+        let (t1, c1) = (Instant::now(), read());
+        let ticks = c1.checked_sub(c0).filter(|&t| t > 0)?;
+        let mult = ((t1 - t0).as_nanos() << 32) / u128::from(ticks);
+        Some(Calibration { anchor: t1, base: c1, mult })
+    }
+
+    /// CPUID 0x80000007 EDX bit 8: the TSC runs at a constant rate in all power states
+    #[cfg(target_arch = "x86_64")]
+    fn invariant() -> bool {
+        use std::arch::x86_64::__cpuid;
+        let max_extended = __cpuid(0x8000_0000).eax;
+        max_extended >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn invariant() -> bool {
+        false
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    fn read() -> u64 {
+        unsafe { std::arch::x86_64::_rdtsc() }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn read() -> u64 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_source_tracks_the_std_clock() {
+        for source in [ClockSource::Std, ClockSource::Tsc, ClockSource::MonotonicRaw, ClockSource::Coarse] {
+            let clock = Clock::new(source);
+            if source == ClockSource::Tsc {
+                assert_eq!(clock.source() == ClockSource::Tsc, tsc_hz().is_some());
+            }
+            let mut last = clock.now();
+            for _ in 0..100 {
+                let (ours, std) = (clock.now(), Instant::now());
+                assert!(ours >= last, "{:?} went backwards", source);
+                let skew = if ours > std { ours - std } else { std - ours };
+                // The coarse clock lags by up to a tick
+                assert!(skew < Duration::from_millis(50), "{:?} off by {:?}", source, skew);
+                last = ours;
+            }
+        }
+    }
+}
@@ -15,10 +15,16 @@
 //! ## Throughput Optimization  
 //! - `recv_buf`/`send_buf`: Larger socket buffers for high-bandwidth applications
 //! - `reuse_port`: Enables SO_REUSEPORT for load balancing across threads
+//! - `auto_tune_buffers`: Grows the UDP receive buffer when packets are dropped
 //!
 //! ## Quality of Service
 //! - `tos`: DSCP/TOS marking for traffic prioritization
 //! - `hop_limit`: IPv6 hop limit control
+//! - `flow_label`: IPv6 flow label for consistent ECMP path selection
+//! - `addr_preferences`: IPv6 source address preferences (temporary/public, home/care-of)
+//!
+//! ## Connection Liveness
+//! - `tcp_keepalive`: Keepalive probe timing for idle TCP connections behind NATs
 //!
 //! # Examples
 //!
@@ -44,11 +50,34 @@
 //!     ..Default::default()
 //! };
 //! ```
+//!
+//! # Profiles
+//!
+//! Tuned configurations can be registered under a name and looked up
+//! anywhere in the process, so services built from shared crates agree on
+//! what "market-data" or "voip" means:
+//!
+//! ```rust
+//! use horizon_sockets::NetConfig;
+//!
+//! let market_data = NetConfig { busy_poll: Some(100), ..NetConfig::low_latency() };
+//! NetConfig::register_profile("market-data", market_data.clone())?;
+//!
+//! assert_eq!(NetConfig::profile("market-data"), Some(market_data));
+//! assert_eq!(NetConfig::profile("voip"), Some(NetConfig::voip()));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
 
+use crate::error::Error;
 use crate::raw;
+use crate::trace;
+use std::collections::BTreeMap;
 use std::io;
-#[cfg(target_os = "linux")] use std::time::Duration;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
 
+pub use horizon_sockets_core::config::{AddrPreferences, DropPolicy, TcpKeepalive};
 
 /// Tunables to push latency down. Defaults are conservative.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -75,6 +104,9 @@ pub struct NetConfig {
     ///
     /// Allows multiple sockets to bind to the same port for load
     /// distribution across threads/processes. Requires kernel support.
+    /// iOS and macOS accept the option but deliver unicast datagrams to
+    /// the most recently bound socket instead of balancing them, so
+    /// [`ShardGroup`](crate::shard::ShardGroup) refuses to shard there.
     ///
     /// **Default**: `true`
     pub reuse_port: bool,
@@ -91,6 +123,25 @@ pub struct NetConfig {
     /// **Default**: `None`
     pub busy_poll: Option<u32>,
 
+    /// SO_PREFER_BUSY_POLL: keep NAPI processing in busy poll loops (Linux 5.11+)
+    ///
+    /// Tells the kernel to defer softirq processing while the application is
+    /// busy polling, so packets are handled on the polling thread. Only
+    /// effective together with `busy_poll` and the device's
+    /// `napi_defer_hard_irqs` / `gro_flush_timeout` settings.
+    ///
+    /// **Default**: `false`
+    pub prefer_busy_poll: bool,
+
+    /// SO_BUSY_POLL_BUDGET: packets processed per busy poll iteration (Linux 5.11+)
+    ///
+    /// - `None`: Kernel default (8)
+    /// - `Some(n)`: Up to `n` packets per poll; values above the default
+    ///   require `CAP_NET_ADMIN`
+    ///
+    /// **Default**: `None`
+    pub busy_poll_budget: Option<u32>,
+
     /// Socket receive buffer size in bytes
     ///
     /// Larger buffers can improve throughput but may increase latency.
@@ -111,6 +162,37 @@ pub struct NetConfig {
     /// **Default**: `Some(4MB)`
     pub send_buf: Option<usize>,
 
+    /// Grow SO_RCVBUF automatically when the kernel drops packets (UDP, Linux only)
+    ///
+    /// The socket starts with `recv_buf` (or 256KB if unset) and enables
+    /// SO_RXQ_OVFL. Whenever `recv_batch` observes the kernel's drop counter
+    /// increase, the receive buffer is doubled, up to this cap in bytes.
+    /// The cap is still subject to `net.core.rmem_max`.
+    ///
+    /// - `None`: Fixed buffer size (default)
+    /// - `Some(cap)`: Auto-tune up to `cap` bytes
+    ///
+    /// **Default**: `None`
+    pub auto_tune_buffers: Option<usize>,
+
+    /// Receive low watermark (SO_RCVLOWAT) in bytes
+    ///
+    /// The socket is not reported readable until at least this many bytes
+    /// are queued. With level-triggered polling this lets bulk transfers
+    /// wake up once per useful chunk instead of once per segment.
+    ///
+    /// **Default**: `None` (system default of 1 byte)
+    pub recv_lowat: Option<usize>,
+
+    /// Send low watermark (SO_SNDLOWAT) in bytes
+    ///
+    /// The socket is not reported writable until this much send buffer space
+    /// is free. Linux does not allow changing this value, so it is ignored
+    /// there; BSD and macOS honor it.
+    ///
+    /// **Default**: `None` (system default)
+    pub send_lowat: Option<usize>,
+
     /// IP Type of Service / DSCP marking
     ///
     /// Sets the TOS byte in IP headers for traffic classification
@@ -121,9 +203,40 @@ pub struct NetConfig {
     /// - `0x04`: High reliability
     /// - `0x02`: Low cost
     ///
+    /// Windows ignores the TOS byte from applications; TCP streams from
+    /// `connect` and `from_std` are marked through qWAVE instead, and other
+    /// sockets need `qos::mark_flow`.
+    ///
     /// **Default**: `None` (no marking)
     pub tos: Option<u32>,
 
+    /// Socket priority (SO_PRIORITY) for queueing discipline band selection
+    ///
+    /// Sets the Linux `skb->priority` of outgoing packets, which selects the
+    /// band in `pfifo_fast`/`prio` qdiscs and the traffic class in `mqprio`.
+    /// With the default priomap:
+    ///
+    /// - `6` (interactive): Band 0, dequeued first
+    /// - `0` (best effort): Band 1
+    /// - `2` (bulk): Band 2, dequeued last
+    ///
+    /// Values above 6 require `CAP_NET_ADMIN`. Ignored on non-Linux platforms.
+    ///
+    /// **Default**: `None` (derived from TOS by the kernel)
+    pub so_priority: Option<u32>,
+
+    /// Report ICMP errors for UDP sockets (IP_RECVERR/IPV6_RECVERR)
+    ///
+    /// When enabled, port unreachable, fragmentation needed, TTL exceeded,
+    /// and similar ICMP errors are queued on the socket and can be read with
+    /// `Udp::drain_errors()`. Required for userspace path MTU discovery.
+    ///
+    /// **Note**: Linux only. While enabled, Linux may also report a pending
+    /// error from the next send or receive call on the socket.
+    ///
+    /// **Default**: `false`
+    pub recv_err: bool,
+
     /// IPv6-only socket configuration
     ///
     /// Controls whether IPv6 sockets accept IPv4 connections:
@@ -143,6 +256,33 @@ pub struct NetConfig {
     /// **Default**: `None` (system default)
     pub hop_limit: Option<i32>,
 
+    /// IPv6 flow label for outgoing packets (IPV6_FLOWLABEL_MGR, Linux only)
+    ///
+    /// Routers hash the flow label into ECMP path selection, so packets
+    /// carrying the same label stay on one path. IPV6_FLOWINFO_SEND is
+    /// enabled on the socket, and TCP connections made with this config
+    /// lease the label and use it automatically. UDP sockets lease it with
+    /// `Udp::lease_flow_label` and send to the address it returns.
+    ///
+    /// Labels are 20 bits; with the default `net.ipv6.flowlabel_state_ranges`
+    /// they must be below `0x80000`. Ignored for IPv4 sockets and on
+    /// non-Linux platforms.
+    ///
+    /// **Default**: `None` (kernel-generated per-flow labels)
+    pub flow_label: Option<u32>,
+
+    /// IPv6 source address selection preferences (IPV6_ADDR_PREFERENCES, Linux only)
+    ///
+    /// Overrides the system policy (RFC 6724 rule 7, `use_tempaddr`) for
+    /// this socket. Privacy-conscious clients prefer
+    /// [`TEMPORARY`](AddrPreferences::TEMPORARY) addresses; servers and
+    /// clients whose peers allow-list addresses prefer
+    /// [`PUBLIC`](AddrPreferences::PUBLIC). Ignored for IPv4 sockets and
+    /// on non-Linux platforms.
+    ///
+    /// **Default**: `None` (system policy)
+    pub addr_preferences: Option<AddrPreferences>,
+
     /// TCP listen backlog size
     ///
     /// Maximum number of pending connections in the accept queue.
@@ -151,6 +291,15 @@ pub struct NetConfig {
     /// **Default**: `Some(1024)`
     pub tcp_backlog: Option<i32>,
 
+    /// TCP keepalive probing for idle connections (SO_KEEPALIVE plus timers)
+    ///
+    /// Keeps NAT and firewall bindings alive and detects dead peers on
+    /// connections that can sit idle. Applied to TCP sockets only; OpenBSD
+    /// only enables probing and keeps its system-wide timers.
+    ///
+    /// **Default**: `None` (keepalive off)
+    pub tcp_keepalive: Option<TcpKeepalive>,
+
     /// Event loop polling timeout in milliseconds
     ///
     /// Maximum time to wait for events before returning from poll.
@@ -189,12 +338,22 @@ impl Default for NetConfig {
             tcp_quickack: true,
             reuse_port: true,
             busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
             recv_buf: Some(default_buf_size),
             send_buf: Some(default_buf_size),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
             tos: None,
+            so_priority: None,
+            recv_err: false,
             ipv6_only: Some(false), // Dual-stack by default
             hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
             tcp_backlog: Some(1024),
+            tcp_keepalive: None,
             poll_timeout_ms: Some(10),
         }
     }
@@ -222,12 +381,22 @@ impl NetConfig {
             tcp_quickack: true,
             reuse_port: true,
             busy_poll: Some(50),        // 50μs busy polling
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
             recv_buf: Some(256 * 1024), // 256KB buffers
             send_buf: Some(256 * 1024),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
             tos: Some(0x10), // Low delay DSCP marking
+            so_priority: None,
+            recv_err: false,
             ipv6_only: Some(false),
             hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
             tcp_backlog: Some(512),   // Smaller backlog for faster processing
+            tcp_keepalive: None,
             poll_timeout_ms: Some(1), // 1ms timeout for responsiveness
         }
     }
@@ -253,12 +422,22 @@ impl NetConfig {
             tcp_quickack: false, // Delayed ACKs for efficiency
             reuse_port: true,
             busy_poll: None,          // No busy polling
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
             recv_buf: Some(16 << 20), // 16MB buffers
             send_buf: Some(16 << 20),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
             tos: Some(0x08), // High throughput DSCP marking
+            so_priority: None,
+            recv_err: false,
             ipv6_only: Some(false),
             hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
             tcp_backlog: Some(2048),   // Large backlog for connection bursts
+            tcp_keepalive: None,
             poll_timeout_ms: Some(50), // Longer timeout for efficiency
         }
     }
@@ -279,15 +458,315 @@ impl NetConfig {
             tcp_quickack: false, // Reduce CPU overhead
             reuse_port: false,   // Simpler socket management
             busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
             recv_buf: Some(512 * 1024), // 512KB buffers
             send_buf: Some(512 * 1024),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
             tos: None,
+            so_priority: None,
+            recv_err: false,
             ipv6_only: Some(false),
             hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
             tcp_backlog: Some(256),
+            tcp_keepalive: None,
             poll_timeout_ms: Some(100), // Long timeout to reduce wakeups
         }
     }
+
+    /// Creates a configuration for voice and video calls
+    ///
+    /// Media streams send small packets at a steady rate and care about
+    /// jitter more than throughput.
+    ///
+    /// # Features
+    /// - Expedited Forwarding DSCP marking (EF, TOS 0xB8)
+    /// - Interactive socket priority (SO_PRIORITY 6) on Linux
+    /// - Modest buffers (256KB) so queued audio does not add delay
+    /// - ICMP error reporting to detect unreachable peers quickly
+    /// - No busy polling, since media servers handle many quiet streams
+    pub fn voip() -> Self {
+        Self {
+            tcp_nodelay: true,
+            tcp_quickack: true,
+            reuse_port: true,
+            busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
+            recv_buf: Some(256 * 1024),
+            send_buf: Some(256 * 1024),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
+            tos: Some(0xB8), // DSCP EF
+            so_priority: Some(6),
+            recv_err: true,
+            ipv6_only: Some(false),
+            hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
+            tcp_backlog: Some(1024),
+            tcp_keepalive: None,
+            poll_timeout_ms: Some(5),
+        }
+    }
+
+    /// Creates a configuration for authoritative game servers
+    ///
+    /// Game servers receive bursts of small UDP packets from many clients
+    /// every tick and must not drop input under load.
+    ///
+    /// # Features
+    /// - 1MB receive buffer that auto-tunes up to 8MB on drops (Linux)
+    /// - Real-time interactive DSCP marking (CS4, TOS 0x80)
+    /// - SO_REUSEPORT for one socket per simulation thread
+    /// - ICMP error reporting to drop disconnected clients early
+    /// - 1ms polling timeout to keep tick scheduling tight
+    pub fn game_server() -> Self {
+        Self {
+            tcp_nodelay: true,
+            tcp_quickack: true,
+            reuse_port: true,
+            busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
+            recv_buf: Some(1 << 20),
+            send_buf: Some(1 << 20),
+            auto_tune_buffers: Some(8 << 20),
+            recv_lowat: None,
+            send_lowat: None,
+            tos: Some(0x80), // DSCP CS4
+            so_priority: Some(5),
+            recv_err: true,
+            ipv6_only: Some(false),
+            hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
+            tcp_backlog: Some(1024),
+            tcp_keepalive: None,
+            poll_timeout_ms: Some(1),
+        }
+    }
+
+    /// Creates a configuration for background bulk replication
+    ///
+    /// Replication and backup traffic should move as much data as possible
+    /// without competing with interactive traffic on the same host.
+    ///
+    /// # Features
+    /// - Large socket buffers (32MB) for long fat networks
+    /// - Lower-effort DSCP marking (CS1, TOS 0x20) and bulk SO_PRIORITY (2)
+    /// - Nagle and delayed ACKs enabled
+    /// - 64KB receive low watermark to wake up once per useful chunk
+    /// - Long polling timeout (100ms)
+    pub fn bulk_replication() -> Self {
+        Self {
+            tcp_nodelay: false,
+            tcp_quickack: false,
+            reuse_port: true,
+            busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
+            recv_buf: Some(32 << 20),
+            send_buf: Some(32 << 20),
+            auto_tune_buffers: None,
+            recv_lowat: Some(64 * 1024),
+            send_lowat: None,
+            tos: Some(0x20), // DSCP CS1
+            so_priority: Some(2),
+            recv_err: false,
+            ipv6_only: Some(false),
+            hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
+            tcp_backlog: Some(256),
+            tcp_keepalive: None,
+            poll_timeout_ms: Some(100),
+        }
+    }
+
+    /// Creates a configuration for mobile clients on Android and iOS
+    ///
+    /// On cellular and Wi-Fi radios every packet can wake the modem from
+    /// its low-power state, which costs far more battery than the CPU time
+    /// involved, and carrier NATs drop idle bindings after a few minutes.
+    ///
+    /// # Features
+    /// - No busy polling and a long polling timeout (250ms)
+    /// - Conservative 256KB buffers
+    /// - TCP keepalive after 4 minutes idle (then every 30s, 4 probes), just
+    ///   under common carrier NAT timeouts, so the radio wakes rarely
+    /// - No SO_REUSEPORT, which does not balance load on iOS
+    /// - No delayed-ACK override, letting ACKs coalesce
+    pub fn mobile() -> Self {
+        Self {
+            tcp_nodelay: true,
+            tcp_quickack: false,
+            reuse_port: false,
+            busy_poll: None,
+            prefer_busy_poll: false,
+            busy_poll_budget: None,
+            recv_buf: Some(256 * 1024),
+            send_buf: Some(256 * 1024),
+            auto_tune_buffers: None,
+            recv_lowat: None,
+            send_lowat: None,
+            tos: None,
+            so_priority: None,
+            recv_err: false,
+            ipv6_only: Some(false),
+            hop_limit: None,
+            flow_label: None,
+            addr_preferences: None,
+            tcp_backlog: Some(64),
+            tcp_keepalive: Some(TcpKeepalive {
+                idle: Duration::from_secs(240),
+                interval: Duration::from_secs(30),
+                retries: 4,
+            }),
+            poll_timeout_ms: Some(250),
+        }
+    }
+
+    /// Registers a named profile that can later be retrieved with [`profile`](Self::profile)
+    ///
+    /// Registering a name again replaces the previous profile, which is
+    /// returned. Built-in profile names cannot be replaced.
+    ///
+    /// # Errors
+    /// - `name` is empty (`InvalidInput`)
+    /// - `name` is a built-in profile (`AlreadyExists`)
+    pub fn register_profile(name: impl Into<String>, cfg: NetConfig) -> io::Result<Option<NetConfig>> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Profile name must not be empty"));
+        }
+        if builtin_profile(&name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Cannot replace built-in profile {:?}", name),
+            ));
+        }
+        Ok(PROFILES.lock().unwrap_or_else(|e| e.into_inner()).insert(name, cfg))
+    }
+
+    /// Looks up a registered or built-in profile by name
+    ///
+    /// # Built-in Profiles
+    ///
+    /// | Name | Constructor |
+    /// |------|-------------|
+    /// | `default` | [`NetConfig::default`] |
+    /// | `low-latency` | [`NetConfig::low_latency`] |
+    /// | `high-throughput` | [`NetConfig::high_throughput`] |
+    /// | `power-efficient` | [`NetConfig::power_efficient`] |
+    /// | `voip` | [`NetConfig::voip`] |
+    /// | `game-server` | [`NetConfig::game_server`] |
+    /// | `bulk-replication` | [`NetConfig::bulk_replication`] |
+    /// | `mobile` | [`NetConfig::mobile`] |
+    pub fn profile(name: &str) -> Option<NetConfig> {
+        builtin_profile(name).or_else(|| PROFILES.lock().unwrap_or_else(|e| e.into_inner()).get(name).cloned())
+    }
+
+    /// Removes a registered profile, returning it
+    pub fn unregister_profile(name: &str) -> Option<NetConfig> {
+        PROFILES.lock().unwrap_or_else(|e| e.into_inner()).remove(name)
+    }
+
+    /// Returns the names of all built-in and registered profiles
+    pub fn profile_names() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_PROFILES.iter().map(|s| s.to_string()).collect();
+        names.extend(PROFILES.lock().unwrap_or_else(|e| e.into_inner()).keys().cloned());
+        names
+    }
+
+    /// Returns `addr` carrying this config's [`flow_label`](Self::flow_label)
+    ///
+    /// Sockets only stamp a label on packets whose destination address
+    /// carries it, and only once the socket holds a lease on the label.
+    /// IPv4 addresses, and configs without a label, are returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use horizon_sockets::NetConfig;
+    /// use std::net::SocketAddr;
+    ///
+    /// let cfg = NetConfig { flow_label: Some(0x1234), ..Default::default() };
+    /// let dest: SocketAddr = "[2001:db8::1]:9000".parse().unwrap();
+    /// let SocketAddr::V6(labeled) = cfg.flow_dest(dest) else { unreachable!() };
+    /// assert_eq!(u32::from_be(labeled.flowinfo()), 0x1234);
+    /// ```
+    pub fn flow_dest(&self, addr: SocketAddr) -> SocketAddr {
+        match (addr, self.flow_label) {
+            // sin6_flowinfo is in network byte order
+            (SocketAddr::V6(mut a), Some(label)) => {
+                a.set_flowinfo((label & 0x000f_ffff).to_be());
+                SocketAddr::V6(a)
+            }
+            _ => addr,
+        }
+    }
+}
+
+/// Names accepted by `builtin_profile`
+const BUILTIN_PROFILES: [&str; 8] = [
+    "default",
+    "low-latency",
+    "high-throughput",
+    "power-efficient",
+    "voip",
+    "game-server",
+    "bulk-replication",
+    "mobile",
+];
+
+static PROFILES: Mutex<BTreeMap<String, NetConfig>> = Mutex::new(BTreeMap::new());
+
+fn builtin_profile(name: &str) -> Option<NetConfig> {
+    Some(match name {
+        "default" => NetConfig::default(),
+        "low-latency" => NetConfig::low_latency(),
+        "high-throughput" => NetConfig::high_throughput(),
+        "power-efficient" => NetConfig::power_efficient(),
+        "voip" => NetConfig::voip(),
+        "game-server" => NetConfig::game_server(),
+        "bulk-replication" => NetConfig::bulk_replication(),
+        "mobile" => NetConfig::mobile(),
+        _ => return None,
+    })
+}
+
+/// Initial SO_RCVBUF for auto-tuned sockets without an explicit `recv_buf`
+pub(crate) const AUTO_TUNE_START: usize = 256 * 1024;
+
+static DROP_POLICY: Mutex<DropPolicy> = Mutex::new(DropPolicy::Graceful);
+
+/// Sets the drop policy applied to TCP streams created from now on
+///
+/// Existing streams keep the policy they were created with.
+///
+/// # Examples
+///
+/// ```rust
+/// use horizon_sockets::config::{drop_policy, set_drop_policy, DropPolicy};
+///
+/// // Game servers often prefer RST over TIME_WAIT buildup on mass disconnects
+/// set_drop_policy(DropPolicy::Abort);
+/// assert_eq!(drop_policy(), DropPolicy::Abort);
+/// # set_drop_policy(DropPolicy::Graceful);
+/// ```
+pub fn set_drop_policy(policy: DropPolicy) {
+    *DROP_POLICY.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Returns the drop policy applied to newly created TCP streams
+pub fn drop_policy() -> DropPolicy {
+    *DROP_POLICY.lock().unwrap_or_else(|e| e.into_inner())
 }
 
 /// Applies network optimizations to a raw socket
@@ -315,6 +794,8 @@ impl NetConfig {
 /// - **Other Unix**: Basic socket options only
 ///
 /// Unsupported options are silently ignored rather than causing errors.
+/// A required option that fails stops the apply and leaves earlier options
+/// in place; [`apply_with`] can roll them back or collect failures instead.
 ///
 /// # Safety
 ///
@@ -327,23 +808,133 @@ pub fn apply_low_latency(
     ty: raw::Type,
     cfg: &NetConfig,
 ) -> io::Result<()> {
+    apply_with(os, domain, ty, cfg, ApplyStrategy::FailFast).map(drop)
+}
+
+/// How [`apply_with`] reacts when the platform rejects a required option
+///
+/// Options that are tuning hints on every platform (busy polling, quick ACK,
+/// send low watermark) never fail the apply; they are listed in
+/// [`ApplyReport::failed`] instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApplyStrategy {
+    /// Stop at the first failure, leaving earlier options in place
+    #[default]
+    FailFast,
+    /// Stop at the first failure and restore the options already changed
+    ///
+    /// Prior values are read before each option is set. Options whose value
+    /// cannot be read on this platform are left as applied.
+    Rollback,
+    /// Apply every option that can be applied and report the rest
+    Report,
+}
+
+/// Options set and refused while applying a [`NetConfig`]
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    /// Options that were set, in the order they were applied
+    pub applied: Vec<&'static str>,
+    /// Options the platform refused, with the reason
+    pub failed: Vec<(&'static str, io::Error)>,
+}
+
+impl ApplyReport {
+    /// Returns `true` if every option was applied
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Applies `cfg` to a raw socket, handling failures according to `strategy`
+///
+/// [`apply_low_latency`] is this function with [`ApplyStrategy::FailFast`].
+/// Use [`ApplyStrategy::Rollback`] when configuring a socket that is
+/// already in use, so a failed change does not leave it half-tuned.
+///
+/// # Returns
+///
+/// The report of applied and refused options, or the first error for a
+/// required option under `FailFast` and `Rollback`. The error wraps an
+/// [`Error::OptionFailed`] or [`Error::OptionNotSupported`] naming the option.
+///
+/// # Examples
+///
+/// ```rust
+/// use horizon_sockets::config::{apply_with, ApplyStrategy, NetConfig};
+/// use horizon_sockets::raw::{self, Domain, Type};
+/// use std::net::UdpSocket;
+///
+/// let socket = UdpSocket::bind("127.0.0.1:0")?;
+/// let os = raw::os_handle(&socket);
+/// let cfg = NetConfig { recv_buf: Some(1 << 20), ..NetConfig::low_latency() };
+///
+/// let report = apply_with(os, Domain::Ipv4, Type::Dgram, &cfg, ApplyStrategy::Report)?;
+/// for (option, err) in &report.failed {
+///     println!("{option} not applied: {err}");
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn apply_with(
+    os: raw::OsSocket,
+    domain: raw::Domain,
+    ty: raw::Type,
+    cfg: &NetConfig,
+    strategy: ApplyStrategy,
+) -> io::Result<ApplyReport> {
+    let _span = trace::span!(trace_span, "apply_options", ?domain, ?ty, ?strategy);
+    let mut applier = Applier { os, strategy, report: ApplyReport::default(), undo: Vec::new() };
+    let res = apply_options(&mut applier, domain, ty, cfg);
+    if res.is_err() {
+        trace::event!(warn, result = ?res, "socket options failed");
+    }
+    res.map(|()| applier.report)
+}
+
+fn apply_options(a: &mut Applier, domain: raw::Domain, ty: raw::Type, cfg: &NetConfig) -> io::Result<()> {
     use crate::raw as r;
 
-    if let Some(sz) = cfg.recv_buf { r::set_recv_buffer(os, sz as i32)?; }
-    if let Some(sz) = cfg.send_buf { r::set_send_buffer(os, sz as i32)?; }
+    if let Some(sz) = cfg.recv_buf { a.set("SO_RCVBUF", sz as i32, get_recv_buffer, r::set_recv_buffer)?; }
+    if let Some(sz) = cfg.send_buf { a.set("SO_SNDBUF", sz as i32, get_send_buffer, r::set_send_buffer)?; }
+
+    // Receive buffer auto-tuning: start modest and report drops with each packet
+    if let (Some(cap), r::Type::Dgram) = (cfg.auto_tune_buffers, ty) {
+        if cfg.recv_buf.is_none() {
+            a.set("SO_RCVBUF", AUTO_TUNE_START.min(cap) as i32, get_recv_buffer, r::set_recv_buffer)?;
+        }
+        a.set("SO_RXQ_OVFL", true, r::get_rxq_ovfl, r::set_rxq_ovfl)?;
+    }
+
+    // Low watermarks: defer readiness until enough data or space is available
+    if let Some(n) = cfg.recv_lowat { a.set("SO_RCVLOWAT", n as i32, r::get_recv_lowat, r::set_recv_lowat)?; }
+    if let Some(n) = cfg.send_lowat {
+        // Linux reports SO_SNDLOWAT as read-only, so failures are not fatal
+        a.best_effort("SO_SNDLOWAT", n as i32, r::get_send_lowat, r::set_send_lowat);
+    }
 
     // Apply Quality of Service / DSCP marking
     if let Some(tos) = cfg.tos {
-        match domain { r::Domain::Ipv4 => r::set_tos_v4(os, tos as i32)?, r::Domain::Ipv6 => r::set_tos_v6(os, tos as i32)?, }
+        match domain {
+            r::Domain::Ipv4 => a.set("IP_TOS", tos as i32, r::get_tos_v4, r::set_tos_v4)?,
+            r::Domain::Ipv6 => a.set("IPV6_TCLASS", tos as i32, r::get_tos_v6, r::set_tos_v6)?,
+        }
+    }
+
+    // Queueing discipline band selection (Linux only)
+    if let Some(prio) = cfg.so_priority { a.set("SO_PRIORITY", prio, r::get_priority, r::set_priority)?; }
+
+    // ICMP error reporting on the socket error queue (UDP, Linux only)
+    if cfg.recv_err && ty == r::Type::Dgram {
+        a.set("IP_RECVERR", true, move |os| r::get_recv_err(os, domain), move |os, on| r::set_recv_err(os, domain, on))?;
     }
 
     // Configure IPv6-specific options
     if let r::Domain::Ipv6 = domain {
         if let Some(only) = cfg.ipv6_only {
-            r::set_ipv6_only(os, only)?;
+            a.set("IPV6_V6ONLY", only, r::get_ipv6_only, r::set_ipv6_only)?;
         }
         if let Some(hops) = cfg.hop_limit {
-            r::set_ipv6_hop_limit(os, hops)?;
+            a.set("IPV6_UNICAST_HOPS", hops, r::get_ipv6_hop_limit, r::set_ipv6_hop_limit)?;
         }
     }
 
@@ -351,27 +942,137 @@ pub fn apply_low_latency(
     #[cfg(any(target_os = "linux", target_os = "android"))]
     {
         if cfg.reuse_port {
-            r::set_reuse_port(os, true)?;
+            a.set("SO_REUSEPORT", true, r::get_reuse_port, r::set_reuse_port)?;
         }
         if let Some(us) = cfg.busy_poll {
             // Busy polling: poll network device for specified microseconds
-            let _ = r::set_busy_poll(os, us);
+            a.best_effort("SO_BUSY_POLL", us, r::get_busy_poll, r::set_busy_poll);
+        }
+        if cfg.prefer_busy_poll {
+            a.best_effort("SO_PREFER_BUSY_POLL", true, r::get_prefer_busy_poll, r::set_prefer_busy_poll);
+        }
+        if let Some(budget) = cfg.busy_poll_budget {
+            a.best_effort("SO_BUSY_POLL_BUDGET", budget, r::get_busy_poll_budget, r::set_busy_poll_budget);
         }
         if cfg.tcp_quickack && ty == r::Type::Stream {
             // TCP Quick ACK: send ACKs immediately rather than delaying
-            let _ = r::set_tcp_quickack(os, true);
+            a.best_effort("TCP_QUICKACK", true, r::get_tcp_quickack, r::set_tcp_quickack);
+        }
+        if let (r::Domain::Ipv6, Some(prefs)) = (domain, cfg.addr_preferences) {
+            a.set("IPV6_ADDR_PREFERENCES", prefs.bits(), r::get_ipv6_addr_preferences, r::set_ipv6_addr_preferences)?;
         }
+        if let (r::Domain::Ipv6, Some(_)) = (domain, cfg.flow_label) {
+            // The label itself is leased per destination when connecting
+            a.set("IPV6_FLOWINFO_SEND", true, r::get_flowinfo_send, r::set_flowinfo_send)?;
+        }
+    }
+
+    // Keepalive probing; the timers are missing on some platforms, so only enabling it is required
+    if let (Some(ka), r::Type::Stream) = (cfg.tcp_keepalive, ty) {
+        a.set("SO_KEEPALIVE", true, r::get_keepalive, r::set_keepalive)?;
+        a.best_effort("TCP_KEEPIDLE", ka.idle.as_secs().min(u32::MAX as u64) as u32, r::get_tcp_keepidle, r::set_tcp_keepidle);
+        a.best_effort("TCP_KEEPINTVL", ka.interval.as_secs().min(u32::MAX as u64) as u32, r::get_tcp_keepintvl, r::set_tcp_keepintvl);
+        a.best_effort("TCP_KEEPCNT", ka.retries, r::get_tcp_keepcnt, r::set_tcp_keepcnt);
     }
 
     // Apply TCP-specific optimizations
     if ty == r::Type::Stream && cfg.tcp_nodelay {
         // TCP_NODELAY: disable Nagle's algorithm for immediate sending
-        r::set_tcp_nodelay(os, true)?;
+        a.set("TCP_NODELAY", true, r::get_tcp_nodelay, r::set_tcp_nodelay)?;
     }
 
     Ok(())
 }
 
+/// Reads SO_RCVBUF as a value that can be passed back to `set_recv_buffer`
+pub(crate) fn get_recv_buffer(os: raw::OsSocket) -> io::Result<i32> {
+    raw::get_recv_buffer(os).map(requested_size)
+}
+
+/// Reads SO_SNDBUF as a value that can be passed back to `set_send_buffer`
+pub(crate) fn get_send_buffer(os: raw::OsSocket) -> io::Result<i32> {
+    raw::get_send_buffer(os).map(requested_size)
+}
+
+/// Undoes the kernel's doubling of buffer sizes on Linux
+fn requested_size(reported: i32) -> i32 {
+    if cfg!(any(target_os = "linux", target_os = "android")) { reported / 2 } else { reported }
+}
+
+type Restore = Box<dyn FnOnce(raw::OsSocket) -> io::Result<()>>;
+
+/// Applies options one at a time, recording prior values for rollback
+struct Applier {
+    os: raw::OsSocket,
+    strategy: ApplyStrategy,
+    report: ApplyReport,
+    undo: Vec<(&'static str, Restore)>,
+}
+
+impl Applier {
+    /// Sets a required option; failure stops the apply unless reporting
+    fn set<T, G, S>(&mut self, option: &'static str, value: T, get: G, set: S) -> io::Result<()>
+    where
+        T: Copy + 'static,
+        G: FnOnce(raw::OsSocket) -> io::Result<T>,
+        S: Fn(raw::OsSocket, T) -> io::Result<()> + 'static,
+    {
+        self.apply(option, true, value, get, set)
+    }
+
+    /// Sets an optional option; failure is only recorded in the report
+    fn best_effort<T, G, S>(&mut self, option: &'static str, value: T, get: G, set: S)
+    where
+        T: Copy + 'static,
+        G: FnOnce(raw::OsSocket) -> io::Result<T>,
+        S: Fn(raw::OsSocket, T) -> io::Result<()> + 'static,
+    {
+        let _ = self.apply(option, false, value, get, set);
+    }
+
+    fn apply<T, G, S>(&mut self, option: &'static str, required: bool, value: T, get: G, set: S) -> io::Result<()>
+    where
+        T: Copy + 'static,
+        G: FnOnce(raw::OsSocket) -> io::Result<T>,
+        S: Fn(raw::OsSocket, T) -> io::Result<()> + 'static,
+    {
+        let prior = match self.strategy {
+            ApplyStrategy::Rollback => get(self.os).ok(),
+            _ => None,
+        };
+        match set(self.os, value) {
+            Ok(()) => {
+                self.report.applied.push(option);
+                if let Some(prior) = prior {
+                    self.undo.push((option, Box::new(move |os| set(os, prior))));
+                }
+                Ok(())
+            }
+            Err(source) => {
+                let err = Error::option_failed(option, source);
+                if !required || self.strategy == ApplyStrategy::Report {
+                    trace::event!(debug, option, error = %err, "socket option not applied");
+                    self.report.failed.push((option, err));
+                    return Ok(());
+                }
+                if self.strategy == ApplyStrategy::Rollback {
+                    self.rollback();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Restores prior values, most recent first
+    fn rollback(&mut self) {
+        while let Some((option, restore)) = self.undo.pop() {
+            if let Err(err) = restore(self.os) {
+                trace::event!(warn, option, error = %err, "socket option rollback failed");
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -379,7 +1080,7 @@ mod tests {
     #[test]
     fn test_default_config() {
         let config = NetConfig::default();
-        assert_eq!(config.tcp_nodelay, true);
+        assert!(config.tcp_nodelay);
         assert_eq!(config.recv_buf, Some(4 << 20));
         assert_eq!(config.send_buf, Some(4 << 20));
         assert_eq!(config.ipv6_only, Some(false));
@@ -397,7 +1098,7 @@ mod tests {
     fn test_high_throughput_config() {
         let config = NetConfig::high_throughput();
         assert_eq!(config.recv_buf, Some(16 << 20));
-        assert_eq!(config.tcp_nodelay, false); // Nagle enabled for efficiency
+        assert!(!config.tcp_nodelay); // Nagle enabled for efficiency
         assert_eq!(config.tcp_backlog, Some(2048));
     }
 
@@ -406,7 +1107,46 @@ mod tests {
         let config = NetConfig::power_efficient();
         assert_eq!(config.busy_poll, None);
         assert_eq!(config.poll_timeout_ms, Some(100));
-        assert_eq!(config.reuse_port, false);
+        assert!(!config.reuse_port);
+    }
+
+    #[test]
+    fn test_mobile_config_enables_keepalive() {
+        let config = NetConfig::mobile();
+        assert_eq!(config.busy_poll, None);
+        assert!(!config.reuse_port);
+        let ka = config.tcp_keepalive.unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let os = raw::os_handle(&listener);
+        let report = apply_with(os, raw::Domain::Ipv4, raw::Type::Stream, &config, ApplyStrategy::Report).unwrap();
+        assert!(report.applied.contains(&"SO_KEEPALIVE"));
+        assert!(raw::get_keepalive(os).unwrap());
+        if cfg!(target_os = "linux") {
+            assert_eq!(raw::get_tcp_keepidle(os).unwrap() as u64, ka.idle.as_secs());
+            assert_eq!(raw::get_tcp_keepcnt(os).unwrap(), ka.retries);
+        }
+    }
+
+    #[test]
+    fn test_profiles() {
+        for name in BUILTIN_PROFILES {
+            assert!(NetConfig::profile(name).is_some(), "{}", name);
+        }
+        assert_eq!(NetConfig::profile("game-server"), Some(NetConfig::game_server()));
+        assert_eq!(NetConfig::profile("missing"), None);
+
+        let custom = NetConfig { tos: Some(0x28), ..NetConfig::default() };
+        assert_eq!(NetConfig::register_profile("test-custom", custom.clone()).unwrap(), None);
+        assert_eq!(NetConfig::profile("test-custom"), Some(custom.clone()));
+        assert!(NetConfig::profile_names().contains(&"test-custom".to_string()));
+        assert_eq!(NetConfig::register_profile("test-custom", NetConfig::default()).unwrap(), Some(custom));
+        assert!(NetConfig::unregister_profile("test-custom").is_some());
+        assert_eq!(NetConfig::profile("test-custom"), None);
+
+        let err = NetConfig::register_profile("voip", NetConfig::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(NetConfig::register_profile("", NetConfig::default()).is_err());
     }
 
     #[test]
@@ -415,4 +1155,40 @@ mod tests {
         let config2 = config1.clone();
         assert_eq!(config1, config2);
     }
+
+    #[test]
+    fn test_rollback_restores_applied_options() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let os = raw::os_handle(&socket);
+        let before = raw::get_recv_buffer(os).unwrap();
+        // TCP_NODELAY on a UDP socket fails after SO_RCVBUF has been changed
+        let cfg = NetConfig { recv_buf: Some(96 * 1024), send_buf: None, tcp_nodelay: true, ..NetConfig::default() };
+
+        let err = apply_with(os, raw::Domain::Ipv4, raw::Type::Stream, &cfg, ApplyStrategy::Rollback).unwrap_err();
+        assert!(Error::from_io(&err).unwrap().is_tuning());
+        assert_eq!(raw::get_recv_buffer(os).unwrap(), before);
+
+        let report = apply_with(os, raw::Domain::Ipv4, raw::Type::Stream, &cfg, ApplyStrategy::Report).unwrap();
+        assert!(!report.is_complete());
+        assert!(report.applied.contains(&"SO_RCVBUF"));
+        assert!(report.failed.iter().any(|(option, _)| *option == "TCP_NODELAY"));
+        assert_ne!(raw::get_recv_buffer(os).unwrap(), before);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_addr_preferences_applied_to_ipv6() {
+        let Ok(socket) = std::net::UdpSocket::bind("[::1]:0") else {
+            return; // No IPv6 loopback in this environment
+        };
+        let os = raw::os_handle(&socket);
+        let prefs = AddrPreferences::PUBLIC | AddrPreferences::HOME;
+        let cfg = NetConfig { addr_preferences: Some(prefs), ipv6_only: None, ..NetConfig::default() };
+        apply_with(os, raw::Domain::Ipv6, raw::Type::Dgram, &cfg, ApplyStrategy::FailFast).unwrap();
+        assert!(AddrPreferences::from_bits(raw::get_ipv6_addr_preferences(os).unwrap()).contains(prefs));
+
+        let conflicting = NetConfig { addr_preferences: Some(AddrPreferences::TEMPORARY | AddrPreferences::PUBLIC), ..cfg };
+        let err = apply_with(os, raw::Domain::Ipv6, raw::Type::Dgram, &conflicting, ApplyStrategy::FailFast).unwrap_err();
+        assert!(Error::from_io(&err).unwrap().is_tuning());
+    }
 }
@@ -0,0 +1,225 @@
+//! Stateless address-validation cookies for UDP servers
+//!
+//! A UDP server cannot tell from a single datagram whether the source
+//! address is real. Allocating session state (or sending a large response)
+//! for every first packet lets an attacker exhaust memory with spoofed
+//! sources, or bounce amplified traffic at a victim. The standard defence,
+//! used by DTLS `HelloVerifyRequest` and QUIC Retry tokens, is a cookie:
+//! answer the first packet with a small token bound to the source address,
+//! and only create state when the client echoes it back. A spoofer never
+//! sees the token, so it cannot complete the exchange.
+//!
+//! [`Cookies`] issues and checks such tokens without storing anything.
+//! A cookie is the issue time plus an HMAC-SHA256 tag over the time, the
+//! peer's IP address and port, and optional protocol context (for example
+//! the client's connection ID). Verification recomputes the tag, so any
+//! server process holding the same secret can validate cookies issued by
+//! another. Cookies expire after a configurable lifetime, and
+//! [`rotate`](Cookies::rotate) replaces the secret while cookies issued
+//! under the previous one stay valid until they expire.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::cookie::{Cookies, Verdict};
+//! use std::time::SystemTime;
+//!
+//! let socket = Udp::bind("0.0.0.0:4433".parse()?, &NetConfig::default())?;
+//! // Load the secret from a CSPRNG or a secret store; share it across workers
+//! let secret = [0x42u8; 32];
+//! let cookies = Cookies::new(secret);
+//!
+//! let mut buf = [0u8; 1500];
+//! let (len, peer) = socket.socket().recv_from(&mut buf)?;
+//! match cookies.verify(&buf[..len], peer, b"", SystemTime::now()) {
+//!     Verdict::Valid => { /* address confirmed: create the session */ }
+//!     Verdict::Expired | Verdict::Invalid => {
+//!         let cookie = cookies.issue(peer, b"", SystemTime::now());
+//!         socket.send_to(&cookie, peer)?;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use hmac_sha256::HMAC;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of an encoded cookie in bytes
+///
+/// One key-generation byte, a 4-byte issue time, and a 16-byte tag.
+pub const COOKIE_LEN: usize = 21;
+
+/// Tag bytes kept from the 32-byte HMAC output
+const TAG_LEN: usize = 16;
+
+/// How far in the future an issue time may be, allowing for clock skew between servers
+const MAX_SKEW: Duration = Duration::from_secs(5);
+
+/// Outcome of checking a cookie
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Issued by this server for this address and context, and not expired
+    Valid,
+    /// Authentic but older than the lifetime; issue a fresh one
+    Expired,
+    /// Malformed, forged, issued for another address, or under a retired secret
+    Invalid,
+}
+
+impl Verdict {
+    /// Returns `true` for [`Verdict::Valid`]
+    pub fn is_valid(self) -> bool {
+        self == Verdict::Valid
+    }
+}
+
+/// Stateless cookie issuer and verifier
+#[derive(Clone)]
+pub struct Cookies {
+    current: [u8; 32],
+    previous: Option<[u8; 32]>,
+    /// Low bit identifies which secret issued a cookie
+    generation: u8,
+    lifetime: Duration,
+}
+
+impl std::fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the secrets
+        f.debug_struct("Cookies")
+            .field("generation", &self.generation)
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cookies {
+    /// Creates a verifier keyed with `secret`; cookies live for 30 seconds
+    ///
+    /// The secret must be unpredictable: take it from the operating
+    /// system's CSPRNG, not from a fixed value or a non-cryptographic RNG.
+    pub fn new(secret: [u8; 32]) -> Self {
+        Self { current: secret, previous: None, generation: 0, lifetime: Duration::from_secs(30) }
+    }
+
+    /// Sets how long issued cookies stay valid
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Replaces the secret, keeping the old one for cookies already issued
+    ///
+    /// Only one previous secret is kept, so rotate less often than the
+    /// lifetime to avoid rejecting fresh cookies.
+    pub fn rotate(&mut self, secret: [u8; 32]) {
+        self.previous = Some(std::mem::replace(&mut self.current, secret));
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Creates a cookie for `peer` and `context` at time `now`
+    pub fn issue(&self, peer: SocketAddr, context: &[u8], now: SystemTime) -> [u8; COOKIE_LEN] {
+        let mut cookie = [0u8; COOKIE_LEN];
+        cookie[0] = self.generation & 1;
+        cookie[1..5].copy_from_slice(&unix_secs(now).to_be_bytes());
+        let tag = tag(&self.current, &cookie[..5], peer, context);
+        cookie[5..].copy_from_slice(&tag[..TAG_LEN]);
+        cookie
+    }
+
+    /// Checks that `cookie` was issued for `peer` and `context` and has not expired at `now`
+    ///
+    /// Extra bytes after the first [`COOKIE_LEN`] are ignored, so a
+    /// datagram that starts with an echoed cookie can be passed as is.
+    pub fn verify(&self, cookie: &[u8], peer: SocketAddr, context: &[u8], now: SystemTime) -> Verdict {
+        let Some(cookie) = cookie.get(..COOKIE_LEN) else { return Verdict::Invalid };
+        let key = match cookie[0] {
+            g if g == self.generation & 1 => &self.current,
+            g if g == self.generation.wrapping_sub(1) & 1 => match &self.previous {
+                Some(key) => key,
+                None => return Verdict::Invalid,
+            },
+            _ => return Verdict::Invalid,
+        };
+        let expected = tag(key, &cookie[..5], peer, context);
+        if !constant_time_eq(&expected[..TAG_LEN], &cookie[5..]) {
+            return Verdict::Invalid;
+        }
+        let issued = u32::from_be_bytes(cookie[1..5].try_into().unwrap()) as u64;
+        let now = unix_secs(now) as u64;
+        if issued > now + MAX_SKEW.as_secs() {
+            return Verdict::Invalid;
+        }
+        if now.saturating_sub(issued) > self.lifetime.as_secs() {
+            return Verdict::Expired;
+        }
+        Verdict::Valid
+    }
+}
+
+/// HMAC over the cookie header, the peer address, and the context
+fn tag(key: &[u8; 32], header: &[u8], peer: SocketAddr, context: &[u8]) -> [u8; 32] {
+    let mut mac = HMAC::new(key);
+    mac.update(header);
+    match peer.ip() {
+        IpAddr::V4(ip) => mac.update(ip.octets()),
+        IpAddr::V6(ip) => mac.update(ip.octets()),
+    }
+    mac.update(peer.port().to_be_bytes());
+    mac.update(context);
+    mac.finalize()
+}
+
+/// Seconds since the Unix epoch, truncated to 32 bits (wraps in 2106)
+fn unix_secs(t: SystemTime) -> u32 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32
+}
+
+/// Compares tags without exiting early, so timing does not reveal the matching prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_bound_to_address_and_context() {
+        let cookies = Cookies::new([7; 32]).lifetime(Duration::from_secs(10));
+        let peer: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let now = SystemTime::now();
+        let cookie = cookies.issue(peer, b"cid", now);
+
+        assert_eq!(cookies.verify(&cookie, peer, b"cid", now + Duration::from_secs(3)), Verdict::Valid);
+        assert_eq!(cookies.verify(&cookie, "192.0.2.1:5001".parse().unwrap(), b"cid", now), Verdict::Invalid);
+        assert_eq!(cookies.verify(&cookie, peer, b"other", now), Verdict::Invalid);
+        assert_eq!(cookies.verify(&cookie[..20], peer, b"cid", now), Verdict::Invalid);
+        assert_eq!(cookies.verify(&cookie, peer, b"cid", now + Duration::from_secs(11)), Verdict::Expired);
+
+        let mut forged = cookie;
+        forged[1..5].copy_from_slice(&(unix_secs(now) + 60).to_be_bytes());
+        assert_eq!(cookies.verify(&forged, peer, b"cid", now + Duration::from_secs(60)), Verdict::Invalid);
+    }
+
+    #[test]
+    fn test_rotation_keeps_previous_secret() {
+        let mut cookies = Cookies::new([1; 32]);
+        let peer: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        let now = SystemTime::now();
+        let old = cookies.issue(peer, b"", now);
+
+        cookies.rotate([2; 32]);
+        let new = cookies.issue(peer, b"", now);
+        assert_ne!(old, new);
+        assert!(cookies.verify(&old, peer, b"", now).is_valid());
+        assert!(cookies.verify(&new, peer, b"", now).is_valid());
+
+        // A second rotation retires the first secret
+        cookies.rotate([3; 32]);
+        assert_eq!(cookies.verify(&old, peer, b"", now), Verdict::Invalid);
+        assert!(cookies.verify(&new, peer, b"", now).is_valid());
+    }
+}
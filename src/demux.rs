@@ -0,0 +1,350 @@
+//! Demultiplexing one datagram stream into per-service handler queues
+//!
+//! An AF_PACKET or XDP socket, or a transparent-proxy socket bound with
+//! `IP_TRANSPARENT`, receives traffic for many services at once. The
+//! receive thread should only classify and hand off; a [`Demux`] does that.
+//! Each [`Datagram`] goes to a queue chosen by its destination port
+//! ([`route_port`](Demux::route_port)), by a custom classifier closure
+//! consulted for ports without a route, or to a default queue.
+//!
+//! Queues are [`spsc`](crate::spsc) rings, one consumer thread each, whose
+//! consumers can block on a [`Notifier`](crate::signal::Notifier) or be
+//! registered with an event loop. A slow handler only affects its own
+//! queue, as chosen by its [`Overflow`] policy:
+//!
+//! - [`Overflow::Drop`]: the datagram is counted and discarded, so other
+//!   services keep flowing at full speed
+//! - [`Overflow::Backpressure`]: the datagram is handed back to the caller
+//!   in [`Full`], which should stop reading so the kernel buffer absorbs
+//!   the burst, and retry later
+//!
+//! [`Datagram::parse`] builds datagrams from raw IPv4/IPv6 UDP packets as
+//! delivered by AF_PACKET (after the link header) or XDP.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::demux::{Datagram, Demux, Dispatch, Overflow};
+//!
+//! let mut demux = Demux::new();
+//! let (dns, mut dns_rx) = demux.add_queue(1024, Overflow::Drop)?;
+//! let (other, mut other_rx) = demux.add_queue(256, Overflow::Backpressure)?;
+//! demux.route_port(53, dns);
+//! demux.set_default(other);
+//!
+//! let query = Datagram { data: b"query".to_vec(), src: "10.0.0.9:40000".parse()?, dst: "10.0.0.1:53".parse()? };
+//! assert_eq!(demux.dispatch(query).ok(), Some(Dispatch::Queued(dns)));
+//! assert_eq!(dns_rx.pop().unwrap().data, b"query");
+//! # let _ = &mut other_rx;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::spsc::{self, Consumer, Producer};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// A UDP payload with its source and destination
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram {
+    /// UDP payload
+    pub data: Vec<u8>,
+    /// Sender address
+    pub src: SocketAddr,
+    /// Address the datagram was sent to
+    pub dst: SocketAddr,
+}
+
+impl Datagram {
+    /// Parses an IPv4 or IPv6 packet carrying UDP
+    ///
+    /// Returns `None` for other protocols, fragments, IPv6 packets with
+    /// extension headers, and truncated packets.
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let (src, dst, udp) = match packet.first()? >> 4 {
+            4 => {
+                let ihl = usize::from(packet[0] & 0x0f) * 4;
+                let more_or_offset = u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]) & 0x3fff;
+                if ihl < 20 || *packet.get(9)? != 17 || more_or_offset != 0 {
+                    return None;
+                }
+                let total = usize::from(u16::from_be_bytes([packet[2], packet[3]])).min(packet.len());
+                let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+                let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+                (IpAddr::from(Ipv4Addr::from(src)), IpAddr::from(Ipv4Addr::from(dst)), packet.get(ihl..total)?)
+            }
+            6 => {
+                if *packet.get(6)? != 17 {
+                    return None;
+                }
+                let len = usize::from(u16::from_be_bytes([packet[4], packet[5]]));
+                let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+                let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+                let end = (40 + len).min(packet.len());
+                (IpAddr::from(Ipv6Addr::from(src)), IpAddr::from(Ipv6Addr::from(dst)), packet.get(40..end)?)
+            }
+            _ => return None,
+        };
+        let header = udp.get(..8)?;
+        let sport = u16::from_be_bytes([header[0], header[1]]);
+        let dport = u16::from_be_bytes([header[2], header[3]]);
+        let len = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        let data = udp.get(8..len.max(8))?.to_vec();
+        Some(Self { data, src: SocketAddr::new(src, sport), dst: SocketAddr::new(dst, dport) })
+    }
+}
+
+/// What a queue does with a datagram when it is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Discard the datagram and count it
+    Drop,
+    /// Hand the datagram back to the caller in [`Full`]
+    Backpressure,
+}
+
+/// Where [`Demux::dispatch`] put a datagram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Queued on the given queue
+    Queued(usize),
+    /// Discarded because the given [`Overflow::Drop`] queue was full
+    Dropped(usize),
+    /// No route, classifier, or default queue claimed it; discarded
+    Unmatched,
+}
+
+/// A datagram refused by a full [`Overflow::Backpressure`] queue
+#[derive(Debug)]
+pub struct Full {
+    /// The full queue
+    pub queue: usize,
+    /// The datagram, to retry once the queue drains
+    pub datagram: Datagram,
+}
+
+/// Counters for one queue
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Datagrams queued
+    pub delivered: u64,
+    /// Datagrams discarded or refused because the queue was full
+    pub overflowed: u64,
+    /// Datagrams currently waiting in the queue
+    pub queued: usize,
+}
+
+struct Queue {
+    tx: Producer<Datagram>,
+    overflow: Overflow,
+    delivered: u64,
+    overflowed: u64,
+}
+
+type Classifier = Box<dyn FnMut(&Datagram) -> Option<usize> + Send>;
+
+/// Classifier routing datagrams to bounded per-handler queues
+pub struct Demux {
+    queues: Vec<Queue>,
+    ports: HashMap<u16, usize>,
+    classifier: Option<Classifier>,
+    default: Option<usize>,
+    unmatched: u64,
+}
+
+impl fmt::Debug for Demux {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Demux")
+            .field("queues", &self.queues.len())
+            .field("ports", &self.ports)
+            .field("classifier", &self.classifier.is_some())
+            .field("default", &self.default)
+            .field("unmatched", &self.unmatched)
+            .finish()
+    }
+}
+
+impl Default for Demux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Demux {
+    /// Creates a demux with no queues; everything is unmatched until routes are added
+    pub fn new() -> Self {
+        Self { queues: Vec::new(), ports: HashMap::new(), classifier: None, default: None, unmatched: 0 }
+    }
+
+    /// Adds a queue holding at least `capacity` datagrams
+    ///
+    /// Returns the queue's index, used in routes, and the consumer for its
+    /// handler thread. The consumer has a notifier, so
+    /// [`wait`](Consumer::wait) blocks instead of spinning.
+    pub fn add_queue(&mut self, capacity: usize, overflow: Overflow) -> io::Result<(usize, Consumer<Datagram>)> {
+        let (tx, rx) = spsc::channel_with_notifier(capacity)?;
+        self.queues.push(Queue { tx, overflow, delivered: 0, overflowed: 0 });
+        Ok((self.queues.len() - 1, rx))
+    }
+
+    /// Sends datagrams addressed to `port` to `queue`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `queue` was not returned by [`add_queue`](Self::add_queue).
+    pub fn route_port(&mut self, port: u16, queue: usize) {
+        assert!(queue < self.queues.len(), "no such queue");
+        self.ports.insert(port, queue);
+    }
+
+    /// Consults `classify` for datagrams whose port has no route
+    ///
+    /// Returning `None`, or an index with no queue, falls through to the
+    /// default queue.
+    pub fn set_classifier<F: FnMut(&Datagram) -> Option<usize> + Send + 'static>(&mut self, classify: F) {
+        self.classifier = Some(Box::new(classify));
+    }
+
+    /// Sends datagrams nothing else claims to `queue`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `queue` was not returned by [`add_queue`](Self::add_queue).
+    pub fn set_default(&mut self, queue: usize) {
+        assert!(queue < self.queues.len(), "no such queue");
+        self.default = Some(queue);
+    }
+
+    /// Classifies `datagram` and queues it for its handler
+    ///
+    /// # Errors
+    ///
+    /// [`Full`] with the datagram if its queue is full and uses
+    /// [`Overflow::Backpressure`]
+    pub fn dispatch(&mut self, datagram: Datagram) -> Result<Dispatch, Full> {
+        let Some(queue) = self.classify(&datagram) else {
+            self.unmatched += 1;
+            return Ok(Dispatch::Unmatched);
+        };
+        let q = &mut self.queues[queue];
+        match q.tx.push(datagram) {
+            Ok(()) => {
+                q.delivered += 1;
+                Ok(Dispatch::Queued(queue))
+            }
+            Err(datagram) => {
+                q.overflowed += 1;
+                match q.overflow {
+                    Overflow::Drop => Ok(Dispatch::Dropped(queue)),
+                    Overflow::Backpressure => Err(Full { queue, datagram }),
+                }
+            }
+        }
+    }
+
+    /// Dispatches datagrams from the front of `batch` until it is empty or a queue pushes back
+    ///
+    /// Dispatched datagrams are removed from `batch`; after backpressure,
+    /// the refused datagram is back at the front, followed by the rest.
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams removed from `batch`
+    pub fn dispatch_batch(&mut self, batch: &mut Vec<Datagram>) -> usize {
+        let mut done = 0;
+        let mut rest = std::mem::take(batch).into_iter();
+        for datagram in rest.by_ref() {
+            if let Err(full) = self.dispatch(datagram) {
+                batch.push(full.datagram);
+                break;
+            }
+            done += 1;
+        }
+        batch.extend(rest);
+        done
+    }
+
+    /// Returns the counters of `queue`
+    pub fn stats(&mut self, queue: usize) -> QueueStats {
+        let q = &mut self.queues[queue];
+        QueueStats { delivered: q.delivered, overflowed: q.overflowed, queued: q.tx.capacity() - q.tx.free() }
+    }
+
+    /// Returns the number of datagrams no queue claimed
+    pub fn unmatched(&self) -> u64 {
+        self.unmatched
+    }
+
+    fn classify(&mut self, datagram: &Datagram) -> Option<usize> {
+        if let Some(&queue) = self.ports.get(&datagram.dst.port()) {
+            return Some(queue);
+        }
+        if let Some(classify) = self.classifier.as_mut() {
+            if let Some(queue) = classify(datagram).filter(|&q| q < self.queues.len()) {
+                return Some(queue);
+            }
+        }
+        self.default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(port: u16, payload: &[u8]) -> Datagram {
+        Datagram { data: payload.to_vec(), src: "10.0.0.2:5000".parse().unwrap(), dst: SocketAddr::new([10, 0, 0, 1].into(), port) }
+    }
+
+    #[test]
+    fn test_routes_and_per_queue_overflow() {
+        let mut demux = Demux::new();
+        let (dns, mut dns_rx) = demux.add_queue(2, Overflow::Drop).unwrap();
+        let (game, mut game_rx) = demux.add_queue(2, Overflow::Backpressure).unwrap();
+        demux.route_port(53, dns);
+        demux.set_classifier(move |d| (d.data.first() == Some(&0xAA)).then_some(game));
+
+        assert_eq!(demux.dispatch(datagram(9999, b"x")).unwrap(), Dispatch::Unmatched);
+        for _ in 0..3 {
+            demux.dispatch(datagram(53, b"q")).unwrap();
+        }
+        assert_eq!(demux.dispatch(datagram(53, b"q")).unwrap(), Dispatch::Dropped(dns));
+        assert_eq!(demux.stats(dns), QueueStats { delivered: 2, overflowed: 2, queued: 2 });
+
+        // Backpressure leaves the refused datagram and the rest in the batch
+        let mut batch: Vec<_> = (0..4u8).map(|i| datagram(7000, &[0xAA, i])).collect();
+        assert_eq!(demux.dispatch_batch(&mut batch), 2);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].data, [0xAA, 2]);
+        assert_eq!(game_rx.pop().unwrap().data, [0xAA, 0]);
+        assert_eq!(game_rx.pop().unwrap().data, [0xAA, 1]);
+        assert_eq!(demux.dispatch_batch(&mut batch), 2);
+        assert!(batch.is_empty());
+
+        assert_eq!(dns_rx.pop().unwrap().data, b"q");
+        assert_eq!(demux.unmatched(), 1);
+    }
+
+    #[test]
+    fn test_parse_ipv4_and_ipv6_udp() {
+        let mut v4 = vec![0x45, 0, 0, 33, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 2, 10, 0, 0, 1];
+        v4.extend_from_slice(&[0x13, 0x88, 0x00, 0x35, 0, 13, 0, 0]);
+        v4.extend_from_slice(b"hello");
+        let d = Datagram::parse(&v4).unwrap();
+        assert_eq!((d.src, d.dst), ("10.0.0.2:5000".parse().unwrap(), "10.0.0.1:53".parse().unwrap()));
+        assert_eq!(d.data, b"hello");
+
+        let mut v6 = vec![0x60, 0, 0, 0, 0, 10, 17, 64];
+        v6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        v6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        v6.extend_from_slice(&[0x04, 0xd2, 0x01, 0xbb, 0, 10, 0, 0, b'h', b'i']);
+        let d = Datagram::parse(&v6).unwrap();
+        assert_eq!(d.dst.port(), 443);
+        assert_eq!(d.data, b"hi");
+
+        // TCP is not parsed
+        v4[9] = 6;
+        assert!(Datagram::parse(&v4).is_none());
+    }
+}
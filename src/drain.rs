@@ -0,0 +1,221 @@
+//! Connection draining for zero-downtime deploys
+//!
+//! During a deploy, the old process must stop taking new connections while
+//! its existing connections finish. A [`ConnTracker`] counts live
+//! connections through [`ConnGuard`]s held next to each connection.
+//! [`TcpListener::drain`](crate::tcp::TcpListener::drain) stops accepting
+//! and returns a [`Drain`] that reports when the last tracked connection is
+//! gone.
+//!
+//! [`DrainMode::Close`] closes the listening socket right away. Connections
+//! still in its accept queue are reset, and the kernel routes new SYNs to
+//! other `SO_REUSEPORT` listeners on the port. [`DrainMode::Keep`] keeps the
+//! socket open but unused, so it can be handed to the new process. Either
+//! way, deregister the listener from the event loop first. Closing the
+//! socket removes it from epoll/kqueue; keeping it does not.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use horizon_sockets::drain::{ConnTracker, DrainMode};
+//! use std::time::Duration;
+//!
+//! let listener = TcpListener::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+//! let tracker = ConnTracker::new();
+//!
+//! for conn in listener.try_incoming() {
+//!     let (stream, _) = conn?;
+//!     let guard = tracker.track().expect("not draining yet");
+//!     std::thread::spawn(move || {
+//!         let _guard = guard; // released when the connection is done
+//!         drop(stream);
+//!     });
+//! }
+//!
+//! // Deploy signal received
+//! let drain = listener.drain(&tracker, DrainMode::Close)?;
+//! if !drain.wait(Some(Duration::from_secs(30))) {
+//!     eprintln!("{} connections still open, exiting anyway", drain.active());
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::tcp::TcpListener;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// What [`TcpListener::drain`] does with the listening socket
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DrainMode {
+    /// Close the listening socket immediately
+    #[default]
+    Close,
+    /// Keep the socket open without accepting, e.g. for handover to a new process
+    Keep,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    active: usize,
+    draining: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<TrackerState>,
+    idle: Condvar,
+}
+
+/// Counts live connections and refuses new ones once draining starts
+///
+/// Clones share the same count.
+#[derive(Clone, Debug, Default)]
+pub struct ConnTracker {
+    shared: Arc<Shared>,
+}
+
+impl ConnTracker {
+    /// Creates a tracker with no connections
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new connection
+    ///
+    /// Returns `None` once draining has started; the caller should close the
+    /// connection instead of serving it.
+    pub fn track(&self) -> Option<ConnGuard> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.draining {
+            return None;
+        }
+        state.active += 1;
+        Some(ConnGuard { shared: self.shared.clone() })
+    }
+
+    /// Returns the number of live connections
+    pub fn active(&self) -> usize {
+        self.shared.state.lock().unwrap().active
+    }
+
+    /// Returns `true` once draining has started
+    pub fn is_draining(&self) -> bool {
+        self.shared.state.lock().unwrap().draining
+    }
+
+    /// Stops admitting connections without touching any listener
+    pub fn start_draining(&self) {
+        self.shared.state.lock().unwrap().draining = true;
+    }
+}
+
+/// Keeps one connection counted by its [`ConnTracker`] until dropped
+#[derive(Debug)]
+pub struct ConnGuard {
+    shared: Arc<Shared>,
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.active -= 1;
+        if state.active == 0 {
+            self.shared.idle.notify_all();
+        }
+    }
+}
+
+/// A listener that has stopped accepting, waiting for its connections to finish
+#[derive(Debug)]
+pub struct Drain {
+    listener: Option<TcpListener>,
+    tracker: ConnTracker,
+}
+
+impl Drain {
+    pub(crate) fn new(listener: Option<TcpListener>, tracker: ConnTracker) -> Self {
+        Self { listener, tracker }
+    }
+
+    /// Returns the number of connections still open
+    pub fn active(&self) -> usize {
+        self.tracker.active()
+    }
+
+    /// Returns `true` once every tracked connection has finished
+    pub fn is_complete(&self) -> bool {
+        self.active() == 0
+    }
+
+    /// Blocks until every tracked connection has finished or `timeout` passes
+    ///
+    /// Returns `true` if draining completed.
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.tracker.shared.state.lock().unwrap();
+        while state.active > 0 {
+            state = match deadline {
+                None => self.tracker.shared.idle.wait(state).unwrap(),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return false;
+                    }
+                    self.tracker.shared.idle.wait_timeout(state, left).unwrap().0
+                }
+            };
+        }
+        true
+    }
+
+    /// Returns the listening socket kept by [`DrainMode::Keep`]
+    pub fn listener(&self) -> Option<&TcpListener> {
+        self.listener.as_ref()
+    }
+
+    /// Takes the listening socket kept by [`DrainMode::Keep`], e.g. to pass it on
+    pub fn into_listener(self) -> Option<TcpListener> {
+        self.listener
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+
+    #[test]
+    fn test_drain_waits_for_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tracker = ConnTracker::new();
+        let a = tracker.track().unwrap();
+        let b = tracker.track().unwrap();
+
+        let drain = listener.drain(&tracker, DrainMode::Close).unwrap();
+        assert!(drain.listener().is_none());
+        assert!(tracker.track().is_none());
+        // The port no longer accepts
+        assert!(std::net::TcpStream::connect(addr).is_err());
+
+        drop(a);
+        assert!(!drain.wait(Some(Duration::from_millis(10))));
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(b);
+        });
+        assert!(drain.wait(Some(Duration::from_secs(5))));
+        assert!(drain.is_complete());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_keep_mode_returns_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let drain = listener.drain(&ConnTracker::new(), DrainMode::Keep).unwrap();
+        assert!(drain.wait(None));
+        assert_eq!(drain.into_listener().unwrap().local_addr().unwrap(), addr);
+    }
+}
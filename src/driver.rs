@@ -0,0 +1,814 @@
+//! Protocol state machines driven by a mio event loop
+//!
+//! Every connection-oriented server built on the crate ends up with the
+//! same loop: map tokens to connection state, read into a buffer until
+//! `WouldBlock`, hand the bytes to a parser, queue the replies, flush
+//! them on WRITABLE, stop reading from peers that do not read their
+//! replies, and fire per-connection timers. [`Driver`] is that loop, so an
+//! application only writes the state machine, as a [`Protocol`]:
+//!
+//! - [`on_open`](Protocol::on_open) runs when the connection is added.
+//! - [`on_readable`](Protocol::on_readable) sees all received bytes not
+//!   yet consumed, through [`Context::input`], and queues replies with
+//!   [`Context::write`].
+//! - [`on_writable`](Protocol::on_writable) runs when queued output has
+//!   been fully flushed, e.g. to stream the next chunk of a large reply.
+//! - [`on_timer`](Protocol::on_timer) runs at the deadline set with
+//!   [`Context::set_timer`].
+//! - [`on_close`](Protocol::on_close) runs once when the connection ends,
+//!   with the reason.
+//!
+//! Callbacks are plain methods: no futures, no executor. The driver owns
+//! the buffers and applies backpressure: while a connection has more than
+//! the high watermark of output queued, it is not read from, until the
+//! peer has taken enough to get below the low watermark.
+//!
+//! A panic in a callback closes only that connection, with
+//! [`CloseReason::Panicked`], and is counted in
+//! [`panic_counter`](Driver::panic_counter); the loop and the other
+//! connections keep running. The panic hook still runs, so the message
+//! is logged as usual. Deployments that would rather crash, or that
+//! build with `panic = "abort"`, turn this off with
+//! [`catch_panics`](Driver::catch_panics).
+//!
+//! Connections get tokens from [`FIRST_TOKEN`] up. Lower tokens are left to
+//! the caller for listeners and other sources registered through
+//! [`registry`](Driver::registry); their events are handed back from
+//! [`poll`](Driver::poll).
+//!
+//! Connection buffers come from a [`BufferPool`] when one is set with
+//! [`buffer_pool`](Driver::buffer_pool) and go back to it when the
+//! connection closes, so churn does not reallocate them.
+//!
+//! The driver owns its mio `Poll` rather than sharing [`rt::Runtime`](crate::rt::Runtime):
+//! with the default features on Linux and Windows that runtime is the
+//! completion-based monoio back-end, which has no readiness loop to bind
+//! tokens in. Other sources share the driver's loop through
+//! [`registry`](Driver::registry) instead.
+//!
+//! Requires the `mio-runtime` feature.
+//!
+//! # Examples
+//!
+//! A line echo server:
+//!
+//! ```rust,no_run
+//! use horizon_sockets::driver::{Context, Driver, Protocol};
+//! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use mio::{Interest, Token};
+//! use std::io;
+//!
+//! struct Echo;
+//!
+//! impl Protocol for Echo {
+//!     fn on_readable(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+//!         while let Some(end) = cx.input().iter().position(|&b| b == b'\n') {
+//!             let line = cx.input()[..=end].to_vec();
+//!             cx.write(&line);
+//!             cx.consume(end + 1);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! const LISTENER: Token = Token(0);
+//! let mut listener = TcpListener::bind("0.0.0.0:7000".parse()?, &NetConfig::default())?;
+//! let mut driver = Driver::new()?;
+//! driver.registry().register(&mut listener, LISTENER, Interest::READABLE)?;
+//!
+//! let mut external = Vec::new();
+//! loop {
+//!     driver.poll(None, &mut external)?;
+//!     if external.contains(&LISTENER) {
+//!         for conn in listener.try_incoming() {
+//!             driver.add(conn?.0, Echo)?;
+//!         }
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::buffer_pool::BufferPool;
+use crate::trace;
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Registry, Token};
+use slab::Slab;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Lowest token given to a connection; tokens below it are free for the caller
+pub const FIRST_TOKEN: usize = 1024;
+
+/// A connection's state machine, called by a [`Driver`]
+///
+/// An error returned from any callback closes the connection with
+/// [`CloseReason::Error`].
+pub trait Protocol {
+    /// The connection was added to the driver, e.g. to start a handshake timer
+    fn on_open(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let _ = cx;
+        Ok(())
+    }
+
+    /// New bytes arrived; they are at the end of [`Context::input`]
+    fn on_readable(&mut self, cx: &mut Context<'_>) -> io::Result<()>;
+
+    /// All queued output has been handed to the socket
+    fn on_writable(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let _ = cx;
+        Ok(())
+    }
+
+    /// The deadline set with [`Context::set_timer`] passed
+    fn on_timer(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let _ = cx;
+        Ok(())
+    }
+
+    /// The connection ended; the socket is closed after this returns
+    fn on_close(&mut self, reason: &CloseReason) {
+        let _ = reason;
+    }
+}
+
+/// Why a connection ended
+#[derive(Debug)]
+pub enum CloseReason {
+    /// The protocol called [`Context::close`] and its output was flushed
+    Closed,
+    /// The peer closed its side of the connection
+    PeerClosed,
+    /// A socket or protocol error
+    Error(io::Error),
+    /// [`Driver::close`] was called
+    Dropped,
+    /// A callback panicked; the panic message, if it was a string
+    Panicked(String),
+}
+
+/// A connection's buffers and controls, passed to [`Protocol`] callbacks
+#[derive(Debug)]
+pub struct Context<'a> {
+    token: Token,
+    now: Instant,
+    input: &'a mut Vec<u8>,
+    output: &'a mut Vec<u8>,
+    timer: &'a mut Option<Instant>,
+    closing: &'a mut bool,
+}
+
+impl Context<'_> {
+    /// Returns the connection's token
+    pub fn token(&self) -> Token {
+        self.token
+    }
+
+    /// Returns the time the current batch of events is being handled at
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// Returns received bytes not yet consumed
+    pub fn input(&self) -> &[u8] {
+        self.input
+    }
+
+    /// Discards the first `n` bytes of [`input`](Self::input)
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` bytes are buffered.
+    pub fn consume(&mut self, n: usize) {
+        self.input.drain(..n);
+    }
+
+    /// Queues `data` to be sent
+    pub fn write(&mut self, data: &[u8]) {
+        self.output.extend_from_slice(data);
+    }
+
+    /// Returns how many bytes are queued and not yet sent
+    pub fn pending_output(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Calls [`on_timer`](Protocol::on_timer) at `at`, replacing any earlier timer
+    pub fn set_timer(&mut self, at: Instant) {
+        *self.timer = Some(at);
+    }
+
+    /// Cancels the timer
+    pub fn cancel_timer(&mut self) {
+        *self.timer = None;
+    }
+
+    /// Closes the connection once queued output has been sent
+    ///
+    /// No further input is delivered.
+    pub fn close(&mut self) {
+        *self.closing = true;
+    }
+}
+
+struct Conn<C, P> {
+    socket: C,
+    protocol: P,
+    /// Distinguishes this connection from earlier ones in the same slot
+    id: u64,
+    input: Vec<u8>,
+    /// Bytes of `input`'s allocation written at least once, so reads need not zero them again
+    input_init: usize,
+    output: Vec<u8>,
+    timer: Option<Instant>,
+    closing: bool,
+    /// An edge-triggered READABLE has not been read to `WouldBlock` yet
+    readable: bool,
+    writable: bool,
+    peer_closed: bool,
+    /// Reading paused until output drains to the low watermark
+    throttled: bool,
+}
+
+/// Runs [`Protocol`] instances on their connections from one mio `Poll`
+pub struct Driver<C, P> {
+    poll: Poll,
+    events: Events,
+    conns: Slab<Conn<C, P>>,
+    timers: BinaryHeap<Reverse<(Instant, usize, u64)>>,
+    next_id: u64,
+    read_chunk: usize,
+    input_limit: usize,
+    high_watermark: usize,
+    low_watermark: usize,
+    catch_panics: bool,
+    panics: Arc<AtomicU64>,
+    pool: Option<BufferPool>,
+}
+
+impl<C, P> fmt::Debug for Driver<C, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Driver")
+            .field("connections", &self.conns.len())
+            .field("timers", &self.timers.len())
+            .field("input_limit", &self.input_limit)
+            .field("high_watermark", &self.high_watermark)
+            .field("low_watermark", &self.low_watermark)
+            .field("catch_panics", &self.catch_panics)
+            .field("panics", &self.panics.load(Ordering::Relaxed))
+            .field("pool", &self.pool.as_ref().map(BufferPool::available_count))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Source + Read + Write, P: Protocol> Driver<C, P> {
+    /// Creates a driver with its own `Poll`
+    ///
+    /// Defaults: 16 KiB reads, 1 MiB input limit, output watermarks of
+    /// 1 MiB and 256 KiB.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(1024),
+            conns: Slab::new(),
+            timers: BinaryHeap::new(),
+            next_id: 0,
+            read_chunk: 16 * 1024,
+            input_limit: 1024 * 1024,
+            high_watermark: 1024 * 1024,
+            low_watermark: 256 * 1024,
+            catch_panics: true,
+            panics: Arc::new(AtomicU64::new(0)),
+            pool: None,
+        })
+    }
+
+    /// Takes connection input and output buffers from `pool`, returning them on close
+    ///
+    /// Without a pool each connection allocates its own buffers.
+    pub fn buffer_pool(mut self, pool: BufferPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Sets the most unconsumed input a connection may hold
+    ///
+    /// A protocol that leaves this much unconsumed is closed with
+    /// `InvalidData`, which bounds the memory a peer can pin by sending a
+    /// message that never completes.
+    pub fn input_limit(mut self, bytes: usize) -> Self {
+        self.input_limit = bytes.max(1);
+        self
+    }
+
+    /// Stops reading a connection above `high` bytes of queued output, until it falls to `low`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is not below `high`.
+    pub fn output_watermarks(mut self, high: usize, low: usize) -> Self {
+        assert!(low < high, "low watermark must be below the high watermark");
+        self.high_watermark = high;
+        self.low_watermark = low;
+        self
+    }
+
+    /// Catches panics in callbacks, closing only the connection that panicked (default `true`)
+    ///
+    /// With `false`, a panic unwinds out of [`poll`](Self::poll) as it
+    /// would from any other code.
+    pub fn catch_panics(mut self, enable: bool) -> Self {
+        self.catch_panics = enable;
+        self
+    }
+
+    /// Returns the shared count of caught panics, e.g. for a [`Metrics`](crate::metrics::Metrics) counter
+    pub fn panic_counter(&self) -> Arc<AtomicU64> {
+        self.panics.clone()
+    }
+
+    /// Returns the registry, for listeners and other sources below [`FIRST_TOKEN`]
+    pub fn registry(&self) -> &Registry {
+        self.poll.registry()
+    }
+
+    /// Registers `socket` and starts running `protocol` on it
+    ///
+    /// Bytes already readable are delivered on the next [`poll`](Self::poll).
+    /// Output queued by [`on_open`](Protocol::on_open) is sent then too. If
+    /// `on_open` fails or panics, the connection is closed and an error returned.
+    pub fn add(&mut self, mut socket: C, protocol: P) -> io::Result<Token> {
+        let entry = self.conns.vacant_entry();
+        let token = Token(FIRST_TOKEN + entry.key());
+        self.poll.registry().register(&mut socket, token, Interest::READABLE | Interest::WRITABLE)?;
+        self.next_id += 1;
+        let buffer = || self.pool.as_ref().map_or_else(Vec::new, BufferPool::acquire);
+        let (input, output) = (buffer(), buffer());
+        entry.insert(Conn {
+            socket,
+            protocol,
+            id: self.next_id,
+            input,
+            input_init: 0,
+            output,
+            timer: None,
+            closing: false,
+            readable: true,
+            writable: true,
+            peer_closed: false,
+            throttled: false,
+        });
+        let key = token.0 - FIRST_TOKEN;
+        if let Err(reason) = self.callback(key, Instant::now(), |p, cx| p.on_open(cx)) {
+            let err = match &reason {
+                CloseReason::Error(e) => io::Error::new(e.kind(), e.to_string()),
+                other => io::Error::other(format!("protocol open failed: {:?}", other)),
+            };
+            self.finish(key, reason);
+            return Err(err);
+        }
+        Ok(token)
+    }
+
+    /// Returns the protocol running on `token`
+    pub fn protocol(&self, token: Token) -> Option<&P> {
+        self.conns.get(token.0.checked_sub(FIRST_TOKEN)?).map(|c| &c.protocol)
+    }
+
+    /// Returns the protocol running on `token`, mutably
+    pub fn protocol_mut(&mut self, token: Token) -> Option<&mut P> {
+        self.conns.get_mut(token.0.checked_sub(FIRST_TOKEN)?).map(|c| &mut c.protocol)
+    }
+
+    /// Queues `data` on a connection from outside its callbacks, e.g. for broadcasts
+    ///
+    /// Returns `false` if no connection has `token`.
+    pub fn write(&mut self, token: Token, data: &[u8]) -> bool {
+        let Some(key) = token.0.checked_sub(FIRST_TOKEN).filter(|&k| self.conns.contains(k)) else {
+            return false;
+        };
+        self.conns[key].output.extend_from_slice(data);
+        self.drive(key, Instant::now());
+        true
+    }
+
+    /// Closes a connection immediately, dropping queued output
+    ///
+    /// Returns `false` if no connection has `token`.
+    pub fn close(&mut self, token: Token) -> bool {
+        match token.0.checked_sub(FIRST_TOKEN).filter(|&k| self.conns.contains(k)) {
+            Some(key) => {
+                self.finish(key, CloseReason::Dropped);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of open connections
+    pub fn len(&self) -> usize {
+        self.conns.len()
+    }
+
+    /// Returns `true` if no connections are open
+    pub fn is_empty(&self) -> bool {
+        self.conns.is_empty()
+    }
+
+    /// Returns the earliest connection timer, for bounding an outer wait
+    pub fn next_deadline(&mut self) -> Option<Instant> {
+        // Skip entries left behind by cancelled or replaced timers
+        while let Some(&Reverse((at, key, id))) = self.timers.peek() {
+            if self.conns.get(key).is_some_and(|c| c.id == id && c.timer == Some(at)) {
+                return Some(at);
+            }
+            self.timers.pop();
+        }
+        None
+    }
+
+    /// Waits up to `timeout` (or the next timer) for events and runs the callbacks they trigger
+    ///
+    /// Tokens below [`FIRST_TOKEN`] that became ready are appended to
+    /// `external` for the caller to handle.
+    ///
+    /// # Returns
+    ///
+    /// The number of events received
+    pub fn poll(&mut self, timeout: Option<Duration>, external: &mut Vec<Token>) -> io::Result<usize> {
+        let now = Instant::now();
+        let timer_wait = self.next_deadline().map(|at| at.saturating_duration_since(now));
+        let wait = match (timeout, timer_wait) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        match self.poll.poll(&mut self.events, wait) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        for event in self.events.iter() {
+            let Some(key) = event.token().0.checked_sub(FIRST_TOKEN) else {
+                external.push(event.token());
+                continue;
+            };
+            let Some(conn) = self.conns.get_mut(key) else { continue };
+            conn.readable |= event.is_readable() || event.is_read_closed() || event.is_error();
+            conn.writable |= event.is_writable() || event.is_write_closed() || event.is_error();
+            ready.push(key);
+        }
+        let count = self.events.iter().count();
+        for key in ready {
+            self.drive(key, now);
+        }
+        self.fire_timers(now);
+        Ok(count)
+    }
+
+    fn fire_timers(&mut self, now: Instant) {
+        while let Some(&Reverse((at, key, id))) = self.timers.peek() {
+            if at > now {
+                break;
+            }
+            self.timers.pop();
+            let Some(conn) = self.conns.get_mut(key) else { continue };
+            if conn.id != id || conn.timer != Some(at) {
+                continue;
+            }
+            conn.timer = None;
+            if let Err(reason) = self.callback(key, now, |p, cx| p.on_timer(cx)) {
+                self.finish(key, reason);
+                continue;
+            }
+            self.drive(key, now);
+        }
+    }
+
+    /// Runs one callback with a context over connection `key`, queueing any new timer
+    ///
+    /// A returned error or caught panic becomes the reason to close with.
+    fn callback<F>(&mut self, key: usize, now: Instant, f: F) -> Result<(), CloseReason>
+    where
+        F: FnOnce(&mut P, &mut Context<'_>) -> io::Result<()>,
+    {
+        let conn = &mut self.conns[key];
+        let before = conn.timer;
+        let mut cx = Context {
+            token: Token(FIRST_TOKEN + key),
+            now,
+            input: &mut conn.input,
+            output: &mut conn.output,
+            timer: &mut conn.timer,
+            closing: &mut conn.closing,
+        };
+        let result = if self.catch_panics {
+            match panic::catch_unwind(AssertUnwindSafe(|| f(&mut conn.protocol, &mut cx))) {
+                Ok(result) => result.map_err(CloseReason::Error),
+                Err(payload) => {
+                    self.panics.fetch_add(1, Ordering::Relaxed);
+                    trace::event!(warn, token = FIRST_TOKEN + key, "protocol callback panicked");
+                    Err(CloseReason::Panicked(panic_message(&*payload)))
+                }
+            }
+        } else {
+            f(&mut conn.protocol, &mut cx).map_err(CloseReason::Error)
+        };
+        if let Some(at) = conn.timer.filter(|_| conn.timer != before) {
+            self.timers.push(Reverse((at, key, conn.id)));
+        }
+        result
+    }
+
+    /// Flushes output and reads input until neither makes progress
+    fn drive(&mut self, key: usize, now: Instant) {
+        if !self.conns.contains(key) {
+            return;
+        }
+        loop {
+            let conn = &mut self.conns[key];
+            let mut progress = false;
+
+            // Flush queued output
+            let had_output = !conn.output.is_empty();
+            while conn.writable && !conn.output.is_empty() {
+                match conn.socket.write(&conn.output) {
+                    Ok(0) => {
+                        return self.finish(key, CloseReason::Error(io::ErrorKind::WriteZero.into()));
+                    }
+                    Ok(n) => {
+                        conn.output.drain(..n);
+                        progress = true;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => conn.writable = false,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return self.finish(key, CloseReason::Error(e)),
+                }
+            }
+            if conn.output.is_empty() {
+                if conn.closing {
+                    return self.finish(key, CloseReason::Closed);
+                }
+                if conn.peer_closed {
+                    return self.finish(key, CloseReason::PeerClosed);
+                }
+                if had_output {
+                    if let Err(reason) = self.callback(key, now, |p, cx| p.on_writable(cx)) {
+                        return self.finish(key, reason);
+                    }
+                    progress |= !self.conns[key].output.is_empty();
+                }
+            }
+
+            // Read while output is below the watermark; resume only once it drains to the low one
+            let conn = &mut self.conns[key];
+            if conn.output.len() > self.high_watermark {
+                conn.throttled = true;
+            } else if conn.output.len() <= self.low_watermark {
+                conn.throttled = false;
+            }
+            let mut received = false;
+            while conn.readable && !conn.throttled && !conn.closing && !conn.peer_closed && conn.input.len() < self.input_limit {
+                let len = conn.input.len();
+                let chunk = self.read_chunk.min(self.input_limit - len);
+                grow_for_read(&mut conn.input, &mut conn.input_init, chunk);
+                match conn.socket.read(&mut conn.input[len..]) {
+                    Ok(0) => {
+                        conn.input.truncate(len);
+                        conn.peer_closed = true;
+                    }
+                    Ok(n) => {
+                        conn.input.truncate(len + n);
+                        received = true;
+                    }
+                    Err(e) => {
+                        conn.input.truncate(len);
+                        match e.kind() {
+                            io::ErrorKind::WouldBlock => conn.readable = false,
+                            io::ErrorKind::Interrupted => {}
+                            _ => return self.finish(key, CloseReason::Error(e)),
+                        }
+                    }
+                }
+            }
+            if received {
+                if let Err(reason) = self.callback(key, now, |p, cx| p.on_readable(cx)) {
+                    return self.finish(key, reason);
+                }
+                let conn = &self.conns[key];
+                if conn.input.len() >= self.input_limit {
+                    trace::event!(debug, token = FIRST_TOKEN + key, "protocol input limit reached");
+                    return self.finish(key, CloseReason::Error(io::Error::new(io::ErrorKind::InvalidData, "protocol input limit reached")));
+                }
+                progress = true;
+            }
+            let conn = &self.conns[key];
+            if conn.peer_closed && conn.output.is_empty() {
+                return self.finish(key, CloseReason::PeerClosed);
+            }
+            if !progress {
+                return;
+            }
+        }
+    }
+
+    fn finish(&mut self, key: usize, reason: CloseReason) {
+        let mut conn = self.conns.remove(key);
+        trace::event!(debug, token = FIRST_TOKEN + key, reason = ?reason, "protocol connection closed");
+        let _ = self.poll.registry().deregister(&mut conn.socket);
+        if let Some(pool) = &self.pool {
+            pool.release(std::mem::take(&mut conn.input));
+            pool.release(std::mem::take(&mut conn.output));
+        }
+        if !self.catch_panics {
+            conn.protocol.on_close(&reason);
+        } else if panic::catch_unwind(AssertUnwindSafe(|| conn.protocol.on_close(&reason))).is_err() {
+            self.panics.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Lengthens `buf` by `n` bytes to read into, zeroing only memory not written before
+///
+/// `init` tracks how much of the allocation has been written; bytes past
+/// the length keep their old contents after `truncate` or `drain`, so
+/// they can be handed to `read` again without another memset.
+fn grow_for_read(buf: &mut Vec<u8>, init: &mut usize, n: usize) {
+    let (len, capacity) = (buf.len(), buf.capacity());
+    buf.reserve(n);
+    if buf.capacity() != capacity {
+        // Reallocation copied only the first `len` bytes
+        *init = len;
+    }
+    let start = (*init).max(len);
+    let end = len + n;
+    if start < end {
+        for byte in &mut buf.spare_capacity_mut()[start - len..n] {
+            byte.write(0);
+        }
+        *init = end;
+    }
+    // SAFETY: bytes up to `end` were written now or by an earlier read into this allocation
+    unsafe { buf.set_len(end) };
+}
+
+/// Extracts the message from a panic payload
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(msg) => msg.to_string(),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "non-string panic payload".into()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{tcp::TcpListener, tcp::TcpStream, NetConfig};
+    use std::net::TcpStream as StdTcpStream;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Upper-cases each line; closes on "quit" and after 50ms of silence, panics on "boom"
+    struct Shout {
+        closed: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Protocol for Shout {
+        fn on_readable(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+            cx.set_timer(cx.now() + Duration::from_millis(50));
+            while let Some(end) = cx.input().iter().position(|&b| b == b'\n') {
+                let line = cx.input()[..end].to_ascii_uppercase();
+                cx.consume(end + 1);
+                if line == b"QUIT" {
+                    cx.write(b"BYE\n");
+                    cx.close();
+                    return Ok(());
+                }
+                if line == b"BOOM" {
+                    panic!("boom");
+                }
+                cx.write(&line);
+                cx.write(b"\n");
+            }
+            Ok(())
+        }
+
+        fn on_timer(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+            cx.write(b"IDLE\n");
+            cx.close();
+            Ok(())
+        }
+
+        fn on_close(&mut self, reason: &CloseReason) {
+            self.closed.borrow_mut().push(format!("{:?}", reason));
+        }
+    }
+
+    fn read_until(client: &mut StdTcpStream, driver: &mut Driver<TcpStream, Shout>, want: &[u8]) -> Vec<u8> {
+        let mut got = Vec::new();
+        let mut buf = [0u8; 256];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !got.ends_with(want) && Instant::now() < deadline {
+            driver.poll(Some(Duration::from_millis(10)), &mut Vec::new()).unwrap();
+            match client.read(&mut buf) {
+                Ok(n) => got.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        got
+    }
+
+    #[test]
+    fn test_protocol_replies_times_out_and_closes() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let closed = Rc::new(RefCell::new(Vec::new()));
+        let pool = BufferPool::new(4, 1024);
+        let mut driver = Driver::new().unwrap().buffer_pool(pool.clone());
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.set_nonblocking(true).unwrap();
+            let (stream, _) = loop {
+                match listener.accept_nonblocking() {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+                    other => break other.unwrap(),
+                }
+            };
+            driver.add(stream, Shout { closed: closed.clone() }).unwrap();
+            clients.push(client);
+        }
+
+        clients[0].write_all(b"hello\nwor").unwrap();
+        assert_eq!(read_until(&mut clients[0], &mut driver, b"HELLO\n"), b"HELLO\n");
+        clients[0].write_all(b"ld\nquit\n").unwrap();
+        assert_eq!(read_until(&mut clients[0], &mut driver, b"BYE\n"), b"WORLD\nBYE\n");
+
+        // The second client sends one line, then goes quiet until the idle timer closes it
+        clients[1].write_all(b"x\n").unwrap();
+        assert_eq!(read_until(&mut clients[1], &mut driver, b"IDLE\n"), b"X\nIDLE\n");
+        assert!(driver.is_empty());
+        assert_eq!(*closed.borrow(), ["Closed", "Closed"]);
+        // Both connections' input and output buffers went back to the pool
+        assert_eq!(pool.available_count(), 4);
+    }
+
+    #[test]
+    fn test_grow_for_read_zeroes_each_byte_once() {
+        let mut buf = Vec::with_capacity(16);
+        let mut init = 0;
+        grow_for_read(&mut buf, &mut init, 8);
+        assert_eq!((buf.as_slice(), init), (&[0u8; 8][..], 8));
+        buf[..8].copy_from_slice(b"abcdefgh");
+        buf.truncate(2);
+        // The old bytes are reused as they are rather than zeroed again
+        grow_for_read(&mut buf, &mut init, 4);
+        assert_eq!((buf.as_slice(), init), (&b"abcdef"[..], 8));
+        // Reallocating keeps only the live bytes, so everything after them is zeroed
+        grow_for_read(&mut buf, &mut init, 64);
+        assert_eq!((&buf[..6], &buf[6..], init), (&b"abcdef"[..], &[0u8; 64][..], 70));
+    }
+
+    #[test]
+    fn test_panic_closes_only_that_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let closed = Rc::new(RefCell::new(Vec::new()));
+        let mut driver = Driver::new().unwrap();
+        let panics = driver.panic_counter();
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.set_nonblocking(true).unwrap();
+            let (stream, _) = loop {
+                match listener.accept_nonblocking() {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+                    other => break other.unwrap(),
+                }
+            };
+            driver.add(stream, Shout { closed: closed.clone() }).unwrap();
+            clients.push(client);
+        }
+
+        clients[0].write_all(b"boom\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while driver.len() == 2 && Instant::now() < deadline {
+            driver.poll(Some(Duration::from_millis(10)), &mut Vec::new()).unwrap();
+        }
+        assert_eq!(*closed.borrow(), ["Panicked(\"boom\")"]);
+        assert_eq!(panics.load(Ordering::Relaxed), 1);
+
+        // The other connection is still served by the same loop
+        clients[1].write_all(b"still\n").unwrap();
+        assert_eq!(read_until(&mut clients[1], &mut driver, b"STILL\n"), b"STILL\n");
+        assert_eq!(driver.len(), 1);
+    }
+}
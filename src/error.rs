@@ -0,0 +1,211 @@
+//! Structured errors carried inside `io::Error`
+//!
+//! Public functions keep returning `io::Result` so they compose with `std`
+//! and the runtimes, but where the crate knows more than an OS error code
+//! it wraps an [`Error`] in the `io::Error`. [`Error::from_io`] gets it
+//! back, letting callers tell a rejected tuning option apart from a failed
+//! bind or a batch that was cut short. The `io::ErrorKind` is preserved, so
+//! code that only matches on kinds keeps working.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::{Error, NetConfig, udp::Udp};
+//!
+//! let cfg = NetConfig { reuse_port: false, ..Default::default() };
+//! let first = Udp::bind("127.0.0.1:0".parse()?, &cfg)?;
+//! let addr = first.local_addr()?;
+//!
+//! let err = Udp::bind(addr, &cfg).unwrap_err();
+//! match Error::from_io(&err) {
+//!     Some(Error::BindFailed { addr: failed, .. }) => assert_eq!(*failed, addr),
+//!     Some(e) if e.is_tuning() => println!("option rejected: {e}"),
+//!     _ => println!("other failure: {err}"),
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+/// Failure with enough context to decide how to react
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The option does not exist on this platform
+    OptionNotSupported {
+        /// Option name, e.g. `"SO_INCOMING_CPU"`
+        option: &'static str,
+        /// Target OS, as in `std::env::consts::OS`
+        platform: &'static str,
+    },
+    /// The kernel rejected a socket option
+    OptionFailed {
+        /// Option name, e.g. `"SO_RCVBUF"`
+        option: &'static str,
+        /// Error reported by `setsockopt`
+        source: io::Error,
+    },
+    /// Binding a socket to a local address failed
+    BindFailed {
+        /// Address that could not be bound
+        addr: SocketAddr,
+        /// Error reported by `bind`
+        source: io::Error,
+    },
+    /// A batch send failed after some packets were already sent
+    PartialBatch {
+        /// Packets sent before the failure
+        sent: usize,
+        /// Error that stopped the batch
+        source: io::Error,
+    },
+    /// The platform socket library could not be initialized (`WSAStartup` on Windows)
+    InitFailed {
+        /// Error reported by the startup call
+        source: io::Error,
+    },
+    /// Any other I/O failure
+    Io(io::Error),
+}
+
+impl Error {
+    /// Returns the structured error wrapped in `err`, if there is one
+    pub fn from_io(err: &io::Error) -> Option<&Error> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+    }
+
+    /// Returns the `io::ErrorKind` this error converts to
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::OptionNotSupported { .. } => io::ErrorKind::Unsupported,
+            Error::OptionFailed { source, .. }
+            | Error::BindFailed { source, .. }
+            | Error::PartialBatch { source, .. }
+            | Error::InitFailed { source }
+            | Error::Io(source) => source.kind(),
+        }
+    }
+
+    /// Returns `true` for failures to apply a tuning option
+    ///
+    /// The socket itself is usable after these; only the tuning is missing.
+    pub fn is_tuning(&self) -> bool {
+        matches!(self, Error::OptionNotSupported { .. } | Error::OptionFailed { .. })
+    }
+
+    /// Wraps `source` as a batch cut short after `sent` packets
+    ///
+    /// A count `source` already carries is added to `sent`; with nothing
+    /// sent at all, `source` is returned unchanged.
+    pub(crate) fn partial(sent: usize, source: io::Error) -> io::Error {
+        match Error::from(source) {
+            Error::PartialBatch { sent: more, source } => Error::PartialBatch { sent: sent + more, source }.into(),
+            other if sent == 0 => other.into(),
+            other => Error::PartialBatch { sent, source: other.into() }.into(),
+        }
+    }
+
+    pub(crate) fn unsupported(option: &'static str) -> io::Error {
+        Error::OptionNotSupported { option, platform: std::env::consts::OS }.into()
+    }
+
+    /// Wraps a `setsockopt` failure, treating "no such option" as unsupported
+    pub(crate) fn option_failed(option: &'static str, source: io::Error) -> io::Error {
+        cfg_if::cfg_if! {
+            if #[cfg(windows)] {
+                let unknown = windows_sys::Win32::Networking::WinSock::WSAENOPROTOOPT;
+            } else {
+                let unknown = libc::ENOPROTOOPT;
+            }
+        }
+        if source.raw_os_error() == Some(unknown) {
+            return Self::unsupported(option);
+        }
+        Error::OptionFailed { option, source }.into()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OptionNotSupported { option, platform } => write!(f, "{option} is not supported on {platform}"),
+            Error::OptionFailed { option, source } => write!(f, "failed to set {option}: {source}"),
+            Error::BindFailed { addr, source } => write!(f, "failed to bind {addr}: {source}"),
+            Error::PartialBatch { sent, source } => write!(f, "batch stopped after {sent} packets: {source}"),
+            Error::InitFailed { source } => write!(f, "failed to initialize the socket library: {source}"),
+            Error::Io(source) => source.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OptionNotSupported { .. } => None,
+            Error::OptionFailed { source, .. }
+            | Error::BindFailed { source, .. }
+            | Error::PartialBatch { source, .. }
+            | Error::InitFailed { source }
+            | Error::Io(source) => Some(source),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => e,
+            other => io::Error::new(other.kind(), other),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if Error::from_io(&err).is_none() {
+            return Error::Io(err);
+        }
+        *err.into_inner().and_then(|inner| inner.downcast().ok()).expect("checked for a wrapped Error above")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+    use crate::udp::Udp;
+
+    #[test]
+    fn test_round_trip_preserves_kind_and_context() {
+        let err: io::Error = Error::PartialBatch { sent: 3, source: io::ErrorKind::ConnectionRefused.into() }.into();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(matches!(Error::from_io(&err), Some(Error::PartialBatch { sent: 3, .. })));
+        assert!(matches!(Error::from(err), Error::PartialBatch { sent: 3, .. }));
+
+        let err = Error::unsupported("SO_INCOMING_CPU");
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(Error::from_io(&err).unwrap().is_tuning());
+
+        let rejected = Error::option_failed("SO_RCVBUF", io::ErrorKind::PermissionDenied.into());
+        assert!(matches!(Error::from_io(&rejected), Some(Error::OptionFailed { option: "SO_RCVBUF", .. })));
+
+        let plain = io::Error::from(io::ErrorKind::WouldBlock);
+        assert!(Error::from_io(&plain).is_none());
+        assert!(matches!(Error::from(plain), Error::Io(_)));
+    }
+
+    #[test]
+    fn test_bind_conflict_reports_address() {
+        let cfg = NetConfig { reuse_port: false, ..Default::default() };
+        let first = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let addr = first.local_addr().unwrap();
+        let err = Udp::bind(addr, &cfg).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        match Error::from_io(&err) {
+            Some(Error::BindFailed { addr: failed, .. }) => assert_eq!(*failed, addr),
+            other => panic!("expected BindFailed, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! Handing sockets to another process with a manifest of their options
+//!
+//! A sidecar that takes over a socket (a privileged helper, a packet
+//! capture agent, the next binary during an upgrade) receives a bare
+//! descriptor over `SCM_RIGHTS` and cannot tell how it was tuned.
+//! [`Udp::export`], [`TcpStream::export`], and [`TcpListener::export`]
+//! give up ownership of the socket and return an [`Export`]: the owned
+//! handle plus a [`Manifest`] of its kind, addresses, and options.
+//!
+//! The manifest is line-based text (`to_string` and `parse`), so it can
+//! travel as the message body next to the descriptor. The receiver can
+//! [`verify`](Manifest::verify) that the socket it holds still matches,
+//! or [`apply`](Manifest::apply) the values to a replacement socket. On
+//! Unix, [`Export::send`] and [`Export::recv`] move both over a
+//! `UnixStream` in one message.
+//!
+//! Options are read back from the socket at export time, defaults
+//! included, so the manifest records what the socket is rather than what a
+//! [`NetConfig`](crate::NetConfig) asked for. Options the platform cannot
+//! read are left out. Buffer sizes are recorded as requested, without
+//! Linux's doubling. `TCP_QUICKACK` is never recorded because the kernel
+//! clears it by itself.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::export::Export;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use std::os::unix::net::UnixStream;
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::low_latency())?;
+//! let channel = UnixStream::connect("/run/sidecar.sock")?;
+//! socket.export()?.send(&channel)?;
+//!
+//! // In the sidecar
+//! let received = Export::recv(&channel)?;
+//! let os = horizon_sockets::raw::os_handle(&received.socket);
+//! for m in received.manifest.verify(os) {
+//!     eprintln!("{} is {:?}, expected {}", m.option, m.actual, m.expected);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Udp::export`]: crate::udp::Udp::export
+//! [`TcpStream::export`]: crate::tcp::TcpStream::export
+//! [`TcpListener::export`]: crate::tcp::TcpListener::export
+
+use crate::config::{self, ApplyReport};
+use crate::error::Error;
+use crate::raw::{self as r, Domain, OsSocket};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// Owned OS socket handle: `OwnedFd` on Unix, `OwnedSocket` on Windows
+#[cfg(unix)]
+pub type OwnedOsSocket = std::os::fd::OwnedFd;
+/// Owned OS socket handle: `OwnedFd` on Unix, `OwnedSocket` on Windows
+#[cfg(windows)]
+pub type OwnedOsSocket = std::os::windows::io::OwnedSocket;
+
+/// First line of every manifest; bumped if the format changes incompatibly
+const HEADER: &str = "horizon-sockets-manifest 1";
+
+/// Upper bound on a manifest's text, far above what the option table produces
+#[cfg(unix)]
+const MAX_MANIFEST_LEN: usize = 4096;
+
+/// Which crate type a socket was exported from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocketKind {
+    /// A [`Udp`](crate::udp::Udp) socket
+    Udp,
+    /// A connected [`TcpStream`](crate::tcp::TcpStream)
+    TcpStream,
+    /// A [`TcpListener`](crate::tcp::TcpListener)
+    TcpListener,
+}
+
+impl SocketKind {
+    /// Returns the socket type
+    pub fn ty(self) -> r::Type {
+        match self {
+            SocketKind::Udp => r::Type::Dgram,
+            SocketKind::TcpStream | SocketKind::TcpListener => r::Type::Stream,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SocketKind::Udp => "udp",
+            SocketKind::TcpStream => "tcp-stream",
+            SocketKind::TcpListener => "tcp-listener",
+        }
+    }
+}
+
+/// A socket handle given up by its crate type, with the manifest to go with it
+#[derive(Debug)]
+pub struct Export {
+    /// The socket; dropping it closes this process's copy
+    pub socket: OwnedOsSocket,
+    /// Kind, addresses, and options as read at export
+    pub manifest: Manifest,
+}
+
+/// Kind, addresses, and option values of an exported socket
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    kind: SocketKind,
+    local_addr: SocketAddr,
+    peer_addr: Option<SocketAddr>,
+    options: Vec<(&'static str, i64)>,
+}
+
+/// An option whose value on the socket differs from the manifest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Option name, e.g. `SO_RCVBUF`
+    pub option: &'static str,
+    /// Value in the manifest
+    pub expected: i64,
+    /// Value on the socket, or `None` if it could not be read
+    pub actual: Option<i64>,
+}
+
+impl Manifest {
+    /// Reads every option that applies to a socket of `kind` bound to `local_addr`
+    pub fn capture(os: OsSocket, kind: SocketKind, local_addr: SocketAddr, peer_addr: Option<SocketAddr>) -> Self {
+        let domain = domain_of(local_addr);
+        let options = OPTIONS
+            .iter()
+            .filter(|o| (o.applies)(kind, domain))
+            .filter_map(|o| Some((o.name, (o.get)(os, domain).ok()?)))
+            .collect();
+        Self { kind, local_addr, peer_addr, options }
+    }
+
+    /// Returns the kind of socket
+    pub fn kind(&self) -> SocketKind {
+        self.kind
+    }
+
+    /// Returns the address family
+    pub fn domain(&self) -> Domain {
+        domain_of(self.local_addr)
+    }
+
+    /// Returns the address the socket was bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the connected peer, if any
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns the recorded options in table order, booleans as 0 or 1
+    pub fn options(&self) -> &[(&'static str, i64)] {
+        &self.options
+    }
+
+    /// Returns the recorded value of `option`
+    pub fn option(&self, option: &str) -> Option<i64> {
+        self.options.iter().find(|(name, _)| *name == option).map(|&(_, v)| v)
+    }
+
+    /// Reads each recorded option from `os` and returns those that differ
+    pub fn verify(&self, os: OsSocket) -> Vec<Mismatch> {
+        let domain = self.domain();
+        self.recorded()
+            .filter_map(|(opt, expected)| {
+                let actual = (opt.get)(os, domain).ok();
+                (actual != Some(expected)).then_some(Mismatch { option: opt.name, expected, actual })
+            })
+            .collect()
+    }
+
+    /// Sets each recorded option on `os` whose value differs
+    ///
+    /// Options already at the recorded value are not touched, so read-only
+    /// ones such as Linux's `SO_SNDLOWAT` only fail if they really differ.
+    /// Every option is attempted, as with
+    /// [`ApplyStrategy::Report`](crate::config::ApplyStrategy::Report).
+    pub fn apply(&self, os: OsSocket) -> ApplyReport {
+        let domain = self.domain();
+        let mut report = ApplyReport::default();
+        for (opt, expected) in self.recorded() {
+            if (opt.get)(os, domain).ok() == Some(expected) {
+                continue;
+            }
+            match (opt.set)(os, domain, expected) {
+                Ok(()) => report.applied.push(opt.name),
+                Err(source) => report.failed.push((opt.name, Error::option_failed(opt.name, source))),
+            }
+        }
+        report
+    }
+
+    fn recorded(&self) -> impl Iterator<Item = (&'static Opt, i64)> + '_ {
+        self.options.iter().filter_map(|&(name, v)| Some((lookup(name)?, v)))
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "kind {}", self.kind.name())?;
+        writeln!(f, "local {}", self.local_addr)?;
+        if let Some(peer) = self.peer_addr {
+            writeln!(f, "peer {}", peer)?;
+        }
+        for (name, value) in &self.options {
+            writeln!(f, "option {} {}", name, value)?;
+        }
+        // Marks the end, so a truncated manifest is not mistaken for a short one
+        writeln!(f, "end")
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = io::Error;
+
+    /// Parses the text written by `Display`, rejecting options this build does not know
+    fn from_str(text: &str) -> io::Result<Self> {
+        let invalid = |what: &str, line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("manifest: {what}: {line:?}"));
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("unsupported header", text.lines().next().unwrap_or_default()));
+        }
+        let (mut kind, mut local_addr, mut peer_addr, mut options, mut ended) = (None, None, None, Vec::new(), false);
+        for line in lines {
+            if ended {
+                return Err(invalid("data after end", line));
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "kind" => {
+                    let k = [SocketKind::Udp, SocketKind::TcpStream, SocketKind::TcpListener].into_iter().find(|k| k.name() == rest);
+                    kind = Some(k.ok_or_else(|| invalid("unknown kind", line))?);
+                }
+                "local" => local_addr = Some(rest.parse().map_err(|_| invalid("bad address", line))?),
+                "peer" => peer_addr = Some(rest.parse().map_err(|_| invalid("bad address", line))?),
+                "option" => {
+                    let (name, value) = rest.split_once(' ').ok_or_else(|| invalid("missing value", line))?;
+                    let opt = lookup(name).ok_or_else(|| invalid("unknown option", line))?;
+                    options.push((opt.name, value.parse().map_err(|_| invalid("bad value", line))?));
+                }
+                "end" => ended = true,
+                _ => return Err(invalid("unknown line", line)),
+            }
+        }
+        match (kind, local_addr, ended) {
+            (Some(kind), Some(local_addr), true) => Ok(Self { kind, local_addr, peer_addr, options }),
+            _ => Err(invalid("incomplete", text)),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Export {
+    /// Sends the socket and its manifest as one message with `SCM_RIGHTS`
+    ///
+    /// The receiver gets its own descriptor for the same socket; drop
+    /// `self` afterwards unless this process keeps using it too.
+    pub fn send(&self, channel: &std::os::unix::net::UnixStream) -> io::Result<()> {
+        use std::io::Write;
+        use std::os::fd::AsRawFd;
+
+        let text = self.manifest.to_string();
+        let mut iov = libc::iovec { iov_base: text.as_ptr() as *mut libc::c_void, iov_len: text.len() };
+        // u64 words keep the control buffer aligned for cmsghdr
+        let mut control = [0u64; 8];
+        let fd_len = std::mem::size_of::<std::os::fd::RawFd>() as u32;
+        // SAFETY: all-zero is a valid msghdr; the pointers set below outlive the sendmsg call
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(fd_len) } as _;
+        // SAFETY: the control buffer holds CMSG_SPACE(fd_len) bytes, so the first header and its payload fit
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fd_len) as _;
+            libc::CMSG_DATA(cmsg).cast::<std::os::fd::RawFd>().write_unaligned(self.socket.as_raw_fd());
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = libc::MSG_NOSIGNAL;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = 0;
+        let rc = unsafe { libc::sendmsg(channel.as_raw_fd(), &msg, flags) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The descriptor went with the first byte; a stream may take the rest in pieces
+        (&*channel).write_all(&text.as_bytes()[rc as usize..])
+    }
+
+    /// Receives a socket and manifest sent with [`send`](Self::send)
+    ///
+    /// Fails with `InvalidData` if the message carries no descriptor or
+    /// the manifest does not parse; any descriptors received are closed.
+    pub fn recv(channel: &std::os::unix::net::UnixStream) -> io::Result<Self> {
+        use std::io::Read;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        let mut buf = vec![0u8; MAX_MANIFEST_LEN];
+        let mut control = [0u64; 8];
+        let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+        // SAFETY: all-zero is a valid msghdr; the pointers set below outlive the recvmsg call
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = libc::MSG_CMSG_CLOEXEC;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = 0;
+        let rc = unsafe { libc::recvmsg(channel.as_raw_fd(), &mut msg, flags) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the kernel wrote msg_controllen bytes of control messages into the buffer
+        let control = unsafe { std::slice::from_raw_parts(control.as_ptr().cast::<u8>(), msg.msg_controllen as usize) };
+        let mut fds = crate::parse::cmsgs(control)
+            .filter(|c| c.level == libc::SOL_SOCKET && c.kind == libc::SCM_RIGHTS)
+            .flat_map(|c| c.data.chunks_exact(std::mem::size_of::<std::os::fd::RawFd>()))
+            // SAFETY: SCM_RIGHTS payloads are descriptors the kernel just installed for this process
+            .map(|b| unsafe { OwnedFd::from_raw_fd(std::os::fd::RawFd::from_ne_bytes(b.try_into().unwrap_or_default())) })
+            .collect::<Vec<_>>()
+            .into_iter();
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(invalid("control message truncated"));
+        }
+        let socket = fds.next().ok_or_else(|| invalid("no descriptor received"))?;
+
+        // A stream may split the manifest; read on until its end line
+        let mut len = rc as usize;
+        while !buf[..len].ends_with(b"\nend\n") {
+            if len == buf.len() {
+                return Err(invalid("manifest too long"));
+            }
+            match (&*channel).read(&mut buf[len..])? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => len += n,
+            }
+        }
+        let text = std::str::from_utf8(&buf[..len]).map_err(|_| invalid("manifest is not UTF-8"))?;
+        Ok(Self { socket, manifest: text.parse()? })
+    }
+}
+
+fn domain_of(addr: SocketAddr) -> Domain {
+    if addr.is_ipv4() { Domain::Ipv4 } else { Domain::Ipv6 }
+}
+
+/// Conversion from the manifest's integer to a setter's argument type
+trait OptValue: Sized {
+    fn from_i64(v: i64) -> Option<Self>;
+}
+
+impl OptValue for bool {
+    fn from_i64(v: i64) -> Option<Self> {
+        match v {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl OptValue for i32 {
+    fn from_i64(v: i64) -> Option<Self> {
+        v.try_into().ok()
+    }
+}
+
+impl OptValue for u32 {
+    fn from_i64(v: i64) -> Option<Self> {
+        v.try_into().ok()
+    }
+}
+
+fn value<T: OptValue>(v: i64) -> io::Result<T> {
+    T::from_i64(v).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("option value {v} out of range")))
+}
+
+/// One recordable option: where it applies and how to read and write it
+struct Opt {
+    name: &'static str,
+    applies: fn(SocketKind, Domain) -> bool,
+    get: fn(OsSocket, Domain) -> io::Result<i64>,
+    set: fn(OsSocket, Domain, i64) -> io::Result<()>,
+}
+
+fn lookup(name: &str) -> Option<&'static Opt> {
+    OPTIONS.iter().find(|o| o.name == name)
+}
+
+fn any(_: SocketKind, _: Domain) -> bool {
+    true
+}
+
+fn dgram(kind: SocketKind, _: Domain) -> bool {
+    kind.ty() == r::Type::Dgram
+}
+
+fn stream(kind: SocketKind, _: Domain) -> bool {
+    kind.ty() == r::Type::Stream
+}
+
+fn v4(_: SocketKind, domain: Domain) -> bool {
+    domain == Domain::Ipv4
+}
+
+fn v6(_: SocketKind, domain: Domain) -> bool {
+    domain == Domain::Ipv6
+}
+
+/// An option whose getter and setter ignore the domain
+macro_rules! opt {
+    ($name:literal, $applies:expr, $get:path, $set:path) => {
+        Opt { name: $name, applies: $applies, get: |os, _| $get(os).map(i64::from), set: |os, _, v| $set(os, value(v)?) }
+    };
+}
+
+/// Options a manifest can record, covering everything [`NetConfig`](crate::NetConfig) sets
+static OPTIONS: &[Opt] = &[
+    opt!("SO_RCVBUF", any, config::get_recv_buffer, r::set_recv_buffer),
+    opt!("SO_SNDBUF", any, config::get_send_buffer, r::set_send_buffer),
+    opt!("SO_RCVLOWAT", any, r::get_recv_lowat, r::set_recv_lowat),
+    opt!("SO_SNDLOWAT", any, r::get_send_lowat, r::set_send_lowat),
+    opt!("SO_RXQ_OVFL", dgram, r::get_rxq_ovfl, r::set_rxq_ovfl),
+    opt!("IP_TOS", v4, r::get_tos_v4, r::set_tos_v4),
+    opt!("IPV6_TCLASS", v6, r::get_tos_v6, r::set_tos_v6),
+    opt!("SO_PRIORITY", any, r::get_priority, r::set_priority),
+    Opt {
+        name: "IP_RECVERR",
+        applies: dgram,
+        get: |os, domain| r::get_recv_err(os, domain).map(i64::from),
+        set: |os, domain, v| r::set_recv_err(os, domain, value(v)?),
+    },
+    opt!("IPV6_V6ONLY", v6, r::get_ipv6_only, r::set_ipv6_only),
+    opt!("IPV6_UNICAST_HOPS", v6, r::get_ipv6_hop_limit, r::set_ipv6_hop_limit),
+    opt!("SO_REUSEPORT", any, r::get_reuse_port, r::set_reuse_port),
+    opt!("SO_BUSY_POLL", any, r::get_busy_poll, r::set_busy_poll),
+    opt!("SO_PREFER_BUSY_POLL", any, r::get_prefer_busy_poll, r::set_prefer_busy_poll),
+    opt!("SO_BUSY_POLL_BUDGET", any, r::get_busy_poll_budget, r::set_busy_poll_budget),
+    opt!("IPV6_ADDR_PREFERENCES", v6, r::get_ipv6_addr_preferences, r::set_ipv6_addr_preferences),
+    opt!("IPV6_FLOWINFO_SEND", v6, r::get_flowinfo_send, r::set_flowinfo_send),
+    opt!("SO_KEEPALIVE", stream, r::get_keepalive, r::set_keepalive),
+    opt!("TCP_KEEPIDLE", stream, r::get_tcp_keepidle, r::set_tcp_keepidle),
+    opt!("TCP_KEEPINTVL", stream, r::get_tcp_keepintvl, r::set_tcp_keepintvl),
+    opt!("TCP_KEEPCNT", stream, r::get_tcp_keepcnt, r::set_tcp_keepcnt),
+    opt!("TCP_NODELAY", stream, r::get_tcp_nodelay, r::set_tcp_nodelay),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+
+    #[test]
+    fn test_manifest_round_trip_verify_and_apply() {
+        let cfg = NetConfig { tos: Some(0x28), recv_buf: Some(256 * 1024), ..NetConfig::default() };
+        let udp = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let local = udp.local_addr().unwrap();
+        let export = udp.export().unwrap();
+        let manifest = &export.manifest;
+        assert_eq!(manifest.kind(), SocketKind::Udp);
+        assert_eq!(manifest.local_addr(), local);
+        assert_eq!(manifest.option("IP_TOS"), Some(0x28));
+        assert_eq!(manifest.option("TCP_NODELAY"), None);
+
+        let text = manifest.to_string();
+        assert_eq!(&text.parse::<Manifest>().unwrap(), manifest);
+        assert!(text.replace("IP_TOS", "IP_BOGUS").parse::<Manifest>().is_err());
+        assert!(text.trim_end_matches("end\n").parse::<Manifest>().is_err());
+
+        let os = r::os_handle(&export.socket);
+        assert!(manifest.verify(os).is_empty());
+
+        // A fresh socket differs until the manifest is applied to it
+        let other = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let other_os = r::os_handle(&other);
+        assert!(manifest.verify(other_os).iter().any(|m| m.option == "IP_TOS" && m.actual == Some(0)));
+        let report = manifest.apply(other_os);
+        assert!(report.applied.contains(&"IP_TOS"));
+        assert!(manifest.verify(other_os).iter().all(|m| report.failed.iter().any(|(name, _)| *name == m.option)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listener_handed_over_unix_stream() {
+        let listener = crate::tcp::TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = std::os::unix::net::UnixStream::pair().unwrap();
+        let sent = listener.export().unwrap();
+        sent.send(&tx).unwrap();
+        drop(sent);
+
+        let received = Export::recv(&rx).unwrap();
+        assert_eq!(received.manifest.kind(), SocketKind::TcpListener);
+        assert_eq!(received.manifest.local_addr(), addr);
+        assert!(received.manifest.verify(r::os_handle(&received.socket)).is_empty());
+
+        // The received descriptor is the same listening socket
+        let listener = std::net::TcpListener::from(received.socket);
+        listener.set_nonblocking(false).unwrap();
+        let client = std::net::TcpStream::connect(addr).unwrap();
+        assert_eq!(listener.accept().unwrap().0.peer_addr().unwrap(), client.local_addr().unwrap());
+    }
+}
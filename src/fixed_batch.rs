@@ -0,0 +1,318 @@
+//! Fixed-capacity batch storage for receive and send loops without heap allocation
+//!
+//! [`Udp::recv_batch`] and [`Udp::send_batch`] build their `mmsghdr`
+//! arrays and address slots in `Vec`s on every call, which is fine for
+//! servers but not for embedded or latency-critical loops that must not
+//! touch the allocator once running. The types here hold the same
+//! descriptors in arrays sized by a const generic, so a batch lives on
+//! the stack or inside another struct and is reused call after call:
+//!
+//! - [`FixedRecvBatch<N>`] has `N` message headers, `iovec`s, and inline
+//!   `sockaddr_storage` slots for [`Udp::recv_batch_fixed`], which
+//!   receives into any caller buffers (`[[u8; 1500]; N]`, pooled `Vec`s).
+//! - [`FixedSendBatch<N>`] queues up to `N` `(payload, destination)`
+//!   pairs, converting each address when it is pushed, for
+//!   [`Udp::send_batch_fixed`].
+//!
+//! Linux and Android submit a batch with one `recvmmsg`/`sendmmsg` call;
+//! elsewhere the messages go one by one, still without allocating.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::fixed_batch::{FixedRecvBatch, FixedSendBatch};
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut bufs = [[0u8; 1500]; 32];
+//! let mut batch = FixedRecvBatch::<32>::new();
+//!
+//! loop {
+//!     let n = socket.recv_batch_fixed(&mut batch, &mut bufs)?;
+//!     let mut replies = FixedSendBatch::<32>::new();
+//!     for i in 0..n {
+//!         replies.push(&bufs[i][..batch.len(i)], batch.addr(i));
+//!     }
+//!     socket.send_batch_fixed(&mut replies)?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Udp::recv_batch`]: crate::udp::Udp::recv_batch
+//! [`Udp::send_batch`]: crate::udp::Udp::send_batch
+//! [`Udp::recv_batch_fixed`]: crate::udp::Udp::recv_batch_fixed
+//! [`Udp::send_batch_fixed`]: crate::udp::Udp::send_batch_fixed
+
+use std::fmt;
+use std::net::SocketAddr;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::mem::{size_of, MaybeUninit};
+
+/// Placeholder address for slots no message has filled
+const UNSPECIFIED: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(std::net::Ipv4Addr::UNSPECIFIED, 0));
+
+/// Header, buffer descriptor, and address storage for receiving up to `N` datagrams
+pub struct FixedRecvBatch<const N: usize> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    hdrs: [libc::mmsghdr; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    iovs: [libc::iovec; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    names: [MaybeUninit<libc::sockaddr_storage>; N],
+    pub(crate) lens: [usize; N],
+    pub(crate) addrs: [SocketAddr; N],
+    pub(crate) received: usize,
+}
+
+impl<const N: usize> fmt::Debug for FixedRecvBatch<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FixedRecvBatch").field("capacity", &N).field("received", &self.received).finish()
+    }
+}
+
+impl<const N: usize> Default for FixedRecvBatch<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FixedRecvBatch<N> {
+    /// Creates empty storage for `N` messages
+    pub fn new() -> Self {
+        Self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            hdrs: [crate::mmsg::empty_header(); N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            iovs: [libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 }; N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            names: [MaybeUninit::uninit(); N],
+            lens: [0; N],
+            addrs: [UNSPECIFIED; N],
+            received: 0,
+        }
+    }
+
+    /// Returns `N`, the most messages one receive can return
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of messages the last receive returned
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Returns the payload length of message `i`, clamped to its buffer
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not below [`received`](Self::received), as does [`addr`](Self::addr).
+    pub fn len(&self, i: usize) -> usize {
+        assert!(i < self.received, "message {} not received (got {})", i, self.received);
+        self.lens[i]
+    }
+
+    /// Returns the sender of message `i`
+    pub fn addr(&self, i: usize) -> SocketAddr {
+        assert!(i < self.received, "message {} not received (got {})", i, self.received);
+        self.addrs[i]
+    }
+
+    /// Iterates over the received messages in `bufs`, the buffers passed to the receive
+    pub fn iter<'b, B: AsRef<[u8]>>(&'b self, bufs: &'b [B]) -> impl Iterator<Item = (&'b [u8], SocketAddr)> + 'b {
+        bufs.iter().zip(&self.lens).zip(&self.addrs).take(self.received).map(|((buf, &len), &addr)| (&buf.as_ref()[..len], addr))
+    }
+
+    /// Receives with one `recvmmsg` call into the first `N` of `bufs`
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn recv_mmsg<B: AsMut<[u8]>>(&mut self, fd: std::os::fd::RawFd, bufs: &mut [B]) -> std::io::Result<usize> {
+        self.received = 0;
+        let max = bufs.len().min(N);
+        for (i, buf) in bufs[..max].iter_mut().enumerate() {
+            let buf = buf.as_mut();
+            self.iovs[i] = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+            let mut hdr = crate::mmsg::empty_header();
+            hdr.msg_hdr.msg_name = self.names[i].as_mut_ptr().cast();
+            hdr.msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as _;
+            hdr.msg_hdr.msg_iov = &mut self.iovs[i];
+            hdr.msg_hdr.msg_iovlen = 1;
+            self.hdrs[i] = hdr;
+        }
+
+        // SAFETY: every header in 0..max points at storage owned by self or borrowed for this call
+        let rc = unsafe { libc::recvmmsg(fd, self.hdrs.as_mut_ptr(), max as _, libc::MSG_DONTWAIT, std::ptr::null_mut()) };
+        // Drop the pointers into the caller's buffers before the borrow ends
+        for iov in &mut self.iovs[..max] {
+            iov.iov_base = std::ptr::null_mut();
+        }
+        if rc < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let n = rc as usize;
+        for i in 0..n {
+            self.lens[i] = (self.hdrs[i].msg_len as usize).min(self.iovs[i].iov_len);
+            let namelen = (self.hdrs[i].msg_hdr.msg_namelen as usize).min(size_of::<libc::sockaddr_storage>());
+            // SAFETY: the kernel initialized the first msg_namelen bytes of this slot
+            let name = unsafe { std::slice::from_raw_parts(self.names[i].as_ptr().cast::<u8>(), namelen) };
+            self.addrs[i] = crate::parse::sockaddr(name).unwrap_or(UNSPECIFIED);
+        }
+        self.received = n;
+        Ok(n)
+    }
+}
+
+/// Up to `N` queued datagrams with their destinations converted for the kernel
+pub struct FixedSendBatch<'a, const N: usize> {
+    bufs: [&'a [u8]; N],
+    dests: [SocketAddr; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    names: [MaybeUninit<libc::sockaddr_storage>; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    namelens: [libc::socklen_t; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    iovs: [libc::iovec; N],
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    hdrs: [libc::mmsghdr; N],
+    len: usize,
+}
+
+impl<const N: usize> fmt::Debug for FixedSendBatch<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FixedSendBatch").field("capacity", &N).field("len", &self.len).finish()
+    }
+}
+
+impl<const N: usize> Default for FixedSendBatch<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N: usize> FixedSendBatch<'a, N> {
+    /// Creates an empty batch with room for `N` datagrams
+    pub fn new() -> Self {
+        Self {
+            bufs: [&[]; N],
+            dests: [UNSPECIFIED; N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            names: [MaybeUninit::uninit(); N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            namelens: [0; N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            iovs: [libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 }; N],
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            hdrs: [crate::mmsg::empty_header(); N],
+            len: 0,
+        }
+    }
+
+    /// Queues one datagram; returns `false` if the batch is full
+    pub fn push(&mut self, buf: &'a [u8], dest: SocketAddr) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.bufs[self.len] = buf;
+        self.dests[self.len] = dest;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let (_, name, len) = crate::raw::to_sockaddr(dest);
+            let slot = self.names[self.len].as_mut_ptr();
+            // SAFETY: sockaddr_storage is large and aligned enough for either family
+            unsafe {
+                match name {
+                    crate::raw::SockAddr::V4(s) => slot.cast::<libc::sockaddr_in>().write(s),
+                    crate::raw::SockAddr::V6(s) => slot.cast::<libc::sockaddr_in6>().write(s),
+                }
+            }
+            self.namelens[self.len] = len;
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Returns the number of queued datagrams
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if nothing is queued
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all queued datagrams
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the queued datagrams in order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a [u8], SocketAddr)> + '_ {
+        self.bufs[..self.len].iter().copied().zip(self.dests[..self.len].iter().copied())
+    }
+
+    /// Drops the first `n` queued datagrams, keeping the rest in order
+    pub(crate) fn consume(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.bufs.copy_within(n..self.len, 0);
+        self.dests.copy_within(n..self.len, 0);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            self.names.copy_within(n..self.len, 0);
+            self.namelens.copy_within(n..self.len, 0);
+        }
+        self.len -= n;
+    }
+
+    /// Sends the queued datagrams with `sendmmsg` until done or the socket would block
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn send_mmsg(&mut self, fd: std::os::fd::BorrowedFd<'_>) -> std::io::Result<usize> {
+        for i in 0..self.len {
+            self.iovs[i] = libc::iovec { iov_base: self.bufs[i].as_ptr() as *mut libc::c_void, iov_len: self.bufs[i].len() };
+            let mut hdr = crate::mmsg::empty_header();
+            hdr.msg_hdr.msg_name = self.names[i].as_mut_ptr().cast();
+            hdr.msg_hdr.msg_namelen = self.namelens[i];
+            hdr.msg_hdr.msg_iov = &mut self.iovs[i];
+            hdr.msg_hdr.msg_iovlen = 1;
+            self.hdrs[i] = hdr;
+        }
+        crate::mmsg::sendmmsg_all(fd, &mut self.hdrs[..self.len], libc::MSG_DONTWAIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{udp::Udp, NetConfig};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_fixed_batches_over_loopback() {
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let dest = rx.local_addr().unwrap();
+
+        let mut out = FixedSendBatch::<3>::new();
+        assert!(out.push(b"one", dest));
+        assert!(out.push(b"two!", dest));
+        assert!(out.push(b"three-and-more", dest));
+        assert!(!out.push(b"full", dest));
+        assert_eq!(tx.send_batch_fixed(&mut out).unwrap(), 3);
+        assert!(out.is_empty());
+
+        // Smaller buffers than the third payload: its length is clamped
+        let mut bufs = [[0u8; 8]; 4];
+        let mut batch = FixedRecvBatch::<4>::new();
+        let mut got = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while got.len() < 3 && Instant::now() < deadline {
+            match rx.recv_batch_fixed(&mut batch, &mut bufs) {
+                Ok(_) => got.extend(batch.iter(&bufs).map(|(p, from)| (p.to_vec(), from))),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        let from = tx.local_addr().unwrap();
+        assert_eq!(got, [(b"one".to_vec(), from), (b"two!".to_vec(), from), (b"three-an".to_vec(), from)]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Per-flow state table for connectionless servers
+//!
+//! Every UDP server has to map a peer `SocketAddr` to its session state, at
+//! packet rate. [`FlowTable`] is a fixed-capacity map built for that lookup:
+//!
+//! - **Open addressing**: a flat index of 8-byte slots (hash tag + entry
+//!   number) probed linearly, so a lookup touches one or two cache lines
+//!   before reaching the entry
+//! - **Stable entries**: keys and values live in a separate array and never
+//!   move, so [`prefetch`](FlowTable::prefetch) can warm the index for a
+//!   whole receive batch before the lookups
+//! - **LRU eviction**: inserting into a full table evicts the least recently
+//!   used flow instead of growing
+//! - **TTL expiry**: with [`ttl`](FlowTable::ttl), flows idle longer than the
+//!   TTL disappear from lookups and can be drained with
+//!   [`pop_expired`](FlowTable::pop_expired)
+//!
+//! Time is a coarse clock advanced by the application once per loop
+//! iteration, so lookups never read the system clock.
+//!
+//! The table itself lives in `horizon_sockets_core`, where it builds
+//! without the standard library on a caller-supplied tick clock; this
+//! wrapper drives it with `Instant` and `Duration`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::flow::FlowTable;
+//! use std::net::SocketAddr;
+//! use std::time::{Duration, Instant};
+//!
+//! #[derive(Default)]
+//! struct Session { packets: u64 }
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut sessions: FlowTable<SocketAddr, Session> =
+//!     FlowTable::new(100_000).ttl(Duration::from_secs(30));
+//! let mut bufs = vec![vec![0u8; 2048]; 32];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 32];
+//!
+//! loop {
+//!     sessions.advance(Instant::now());
+//!     while let Some((peer, _session)) = sessions.pop_expired() {
+//!         println!("{} timed out", peer);
+//!     }
+//!
+//!     let count = match socket.recv_batch(&mut bufs, &mut addrs) {
+//!         Ok(n) => n,
+//!         Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+//!         Err(e) => return Err(e.into()),
+//!     };
+//!     for addr in &addrs[..count] {
+//!         sessions.prefetch(addr);
+//!     }
+//!     for addr in &addrs[..count] {
+//!         sessions.get_or_insert_with(*addr, Session::default).packets += 1;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use horizon_sockets_core::flow as core_flow;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+pub use horizon_sockets_core::flow::{FlowBuildHasher, FlowHasher};
+
+/// Fixed-capacity hash map with LRU and TTL eviction
+///
+/// Capacity is set at construction; the table never reallocates.
+#[derive(Debug)]
+pub struct FlowTable<K, V, S = FlowBuildHasher> {
+    inner: core_flow::FlowTable<K, V, S>,
+    /// Instant that tick 0 of the inner table stands for
+    epoch: Instant,
+}
+
+impl<K: Hash + Eq, V> FlowTable<K, V> {
+    /// Creates a table holding up to `capacity` flows
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0 or above 2^30.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, FlowBuildHasher::default())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> FlowTable<K, V, S> {
+    /// Creates a table with a custom hasher
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0 or above 2^30.
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        Self { inner: core_flow::FlowTable::with_hasher(capacity, hasher), epoch: Instant::now() }
+    }
+
+    /// Expires flows that have not been looked up or inserted for `ttl`
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.inner = self.inner.ttl(ticks(ttl));
+        self
+    }
+
+    /// Advances the table's clock
+    ///
+    /// Call once per event loop iteration. Lookups and inserts stamp entries
+    /// with this time, and expiry is measured against it.
+    pub fn advance(&mut self, now: Instant) {
+        self.inner.advance(ticks(now.saturating_duration_since(self.epoch)));
+    }
+
+    /// Returns the number of flows
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` if the table holds no flows
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the maximum number of flows
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Hints the CPU to load the index slot for `key`
+    ///
+    /// Issuing prefetches for a whole batch of keys before looking them up
+    /// overlaps the cache misses instead of serializing them.
+    pub fn prefetch(&self, key: &K) {
+        self.inner.prefetch(key)
+    }
+
+    /// Looks up a flow and marks it most recently used
+    ///
+    /// Expired flows are removed and reported as missing.
+    pub fn get(&mut self, key: &K) -> Option<&mut V> {
+        self.inner.get(key)
+    }
+
+    /// Looks up a flow without changing its recency
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.inner.peek(key)
+    }
+
+    /// Returns `true` if the table holds an unexpired flow for `key`
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    /// Inserts or replaces a flow, marking it most recently used
+    ///
+    /// # Returns
+    ///
+    /// The entry that made room: the previous entry for `key`, or the least
+    /// recently used flow if the table was full.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.inner.insert(key, value)
+    }
+
+    /// Returns the flow for `key`, inserting `f()` if it is missing or expired
+    ///
+    /// If the table is full, the least recently used flow is dropped to make
+    /// room; use [`insert`](Self::insert) to observe evictions.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &mut V {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    /// Removes a flow, returning its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.inner.remove(key)
+    }
+
+    /// Removes and returns the least recently used flow if it has expired
+    ///
+    /// Always `None` without a TTL. Call in a loop after [`advance`](Self::advance)
+    /// to close idle sessions.
+    pub fn pop_expired(&mut self) -> Option<(K, V)> {
+        self.inner.pop_expired()
+    }
+
+    /// Removes and returns the least recently used flow
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        self.inner.pop_lru()
+    }
+
+    /// Removes all flows
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+
+    /// Iterates over the flows from most to least recently used
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.inner.iter()
+    }
+}
+
+/// Converts a span to the inner table's nanosecond ticks
+fn ticks(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_lru_eviction_and_ttl() {
+        let start = Instant::now();
+        let mut table = FlowTable::new(3).ttl(Duration::from_secs(10));
+        table.advance(start);
+        assert_eq!(table.insert(1, "a"), None);
+        assert_eq!(table.insert(2, "b"), None);
+        assert_eq!(table.insert(3, "c"), None);
+
+        // Touching 1 makes 2 the least recently used
+        assert_eq!(table.get(&1), Some(&mut "a"));
+        assert_eq!(table.insert(4, "d"), Some((2, "b")));
+        assert_eq!(table.insert(4, "e"), Some((4, "d")));
+        assert_eq!(table.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![4, 1, 3]);
+
+        table.advance(start + Duration::from_secs(5));
+        table.get(&1);
+        table.advance(start + Duration::from_secs(12));
+        assert!(!table.contains_key(&3));
+        assert_eq!(table.pop_expired(), Some((3, "c")));
+        assert_eq!(table.pop_expired(), Some((4, "e")));
+        assert_eq!(table.pop_expired(), None);
+        assert_eq!(table.len(), 1);
+
+        *table.get_or_insert_with(5, || "f") = "g";
+        assert_eq!(table.peek(&5), Some(&"g"));
+    }
+
+    #[test]
+    fn test_matches_hashmap_under_churn() {
+        let mut table: FlowTable<SocketAddr, u32> = FlowTable::new(64);
+        let mut model = HashMap::new();
+        let mut seed = 0x2545_f491_u32;
+        for i in 0..20_000u32 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let addr = SocketAddr::from(([10, 0, 0, (seed % 40) as u8], 4000 + (seed % 3) as u16));
+            if seed.is_multiple_of(3) {
+                assert_eq!(table.remove(&addr), model.remove(&addr));
+            } else {
+                // 120 distinct keys never all fit; keep the model in sync with evictions
+                if let Some((k, _)) = table.insert(addr, i) {
+                    if k != addr {
+                        model.remove(&k);
+                    }
+                }
+                model.insert(addr, i);
+            }
+            assert_eq!(table.len(), model.len());
+        }
+        for (k, v) in &model {
+            assert_eq!(table.peek(k), Some(v));
+        }
+    }
+}
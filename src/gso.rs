@@ -0,0 +1,251 @@
+//! UDP generic segmentation offload (GSO) sizing per destination
+//!
+//! With `UDP_SEGMENT` (Linux 4.18+), one `sendmsg` hands the kernel a
+//! buffer of up to 64 datagrams, and the kernel (or the NIC) cuts it into
+//! equal-sized segments. The win is large for bulk senders, but only if
+//! the segment size is right: too large and every segment is fragmented or
+//! dropped, too small and bandwidth goes to headers.
+//!
+//! The best size is the path MTU minus the IP and UDP headers, and it
+//! differs per destination. [`GsoSizer`] keeps that size per destination:
+//!
+//! - The first send to a destination looks up the MTU of its route
+//!   ([`netif::route_mtu`](crate::netif::route_mtu)), falling back to a
+//!   configurable MTU (1500 by default) when the lookup fails.
+//! - [`on_icmp`](GsoSizer::on_icmp) lowers the size when the socket's
+//!   error queue (see [`NetConfig::recv_err`](crate::NetConfig::recv_err))
+//!   reports that a packet exceeded a smaller path MTU.
+//! - [`send`](GsoSizer::send) (Linux) sends a buffer with the
+//!   destination's segment size attached as a `UDP_SEGMENT` control
+//!   message, so one socket can talk to destinations with different MTUs.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::gso::GsoSizer;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//!
+//! let cfg = NetConfig { recv_err: true, ..Default::default() };
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &cfg)?;
+//! let peer = "198.51.100.20:4433".parse()?;
+//! let mut sizer = GsoSizer::new();
+//!
+//! // Frames of exactly one segment each, packed into one buffer
+//! let segment = sizer.segment_size(peer) as usize;
+//! let buf = vec![0u8; segment * 16];
+//! # #[cfg(target_os = "linux")]
+//! sizer.send(&socket, &buf, peer)?;
+//!
+//! for err in socket.drain_errors()? {
+//!     if let Some(smaller) = sizer.on_icmp(&err) {
+//!         println!("path to {:?} shrank, segments are now {} bytes", err.destination, smaller);
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::icmp::{IcmpError, IcmpErrorKind};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// Most segments the kernel accepts in one GSO send (`UDP_MAX_SEGMENTS`)
+pub const MAX_SEGMENTS: usize = 64;
+
+/// Largest UDP payload that fits in one packet of `mtu` bytes to `dest`
+///
+/// Subtracts 28 bytes of headers for IPv4 and 48 for IPv6.
+pub fn max_payload(mtu: u32, dest: SocketAddr) -> u16 {
+    let headers = if dest.is_ipv4() { 28 } else { 48 };
+    mtu.saturating_sub(headers).min(u32::from(u16::MAX)) as u16
+}
+
+/// Per-destination GSO segment sizes derived from route and path MTU
+#[derive(Clone, Debug)]
+pub struct GsoSizer {
+    sizes: HashMap<SocketAddr, u16>,
+    fallback_mtu: u32,
+}
+
+impl GsoSizer {
+    /// Creates an empty table with a fallback MTU of 1500
+    pub fn new() -> Self {
+        Self { sizes: HashMap::new(), fallback_mtu: 1500 }
+    }
+
+    /// Sets the MTU assumed when the route lookup fails
+    pub fn fallback_mtu(mut self, mtu: u32) -> Self {
+        self.fallback_mtu = mtu;
+        self
+    }
+
+    /// Returns the segment size for `dest`, looking up its route MTU on first use
+    pub fn segment_size(&mut self, dest: SocketAddr) -> u16 {
+        let fallback = self.fallback_mtu;
+        *self.sizes.entry(dest).or_insert_with(|| {
+            let mtu = crate::netif::route_mtu(dest.ip()).unwrap_or(fallback);
+            max_payload(mtu, dest)
+        })
+    }
+
+    /// Applies an ICMP "packet too big" report to its destination
+    ///
+    /// # Returns
+    ///
+    /// The new segment size if the report lowered it; `None` for other
+    /// errors, errors without a destination, and MTUs no smaller than the
+    /// current one.
+    pub fn on_icmp(&mut self, err: &IcmpError) -> Option<u16> {
+        let (IcmpErrorKind::FragmentationNeeded { mtu }, Some(dest)) = (err.kind, err.destination) else {
+            return None;
+        };
+        let smaller = max_payload(mtu, dest);
+        if smaller == 0 || smaller >= self.segment_size(dest) {
+            return None;
+        }
+        self.sizes.insert(dest, smaller);
+        Some(smaller)
+    }
+
+    /// Sets the segment size for `dest` directly, e.g. from an application-level PMTU probe
+    pub fn set_segment_size(&mut self, dest: SocketAddr, size: u16) {
+        self.sizes.insert(dest, size);
+    }
+
+    /// Forgets `dest`, so its next use looks the route up again
+    pub fn forget(&mut self, dest: SocketAddr) {
+        self.sizes.remove(&dest);
+    }
+
+    /// Sends `buf` to `dest` as segments of the destination's size (Linux)
+    ///
+    /// The last segment may be shorter. `buf` must hold at most
+    /// [`MAX_SEGMENTS`] segments and 64 KiB in total.
+    #[cfg(target_os = "linux")]
+    pub fn send(&mut self, socket: &crate::udp::Udp, buf: &[u8], dest: SocketAddr) -> std::io::Result<usize> {
+        let segment = self.segment_size(dest);
+        send_segmented(socket, buf, dest, segment)
+    }
+}
+
+impl Default for GsoSizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `UDP_SEGMENT` from `<linux/udp.h>`
+#[cfg(target_os = "linux")]
+const UDP_SEGMENT: libc::c_int = 103;
+
+/// Sets a socket-wide default segment size; sends without a control message use it
+#[cfg(target_os = "linux")]
+pub fn set_segment_size(socket: &crate::udp::Udp, size: u16) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let value = libc::c_int::from(size);
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.socket().as_raw_fd(),
+            libc::SOL_UDP,
+            UDP_SEGMENT,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sends `buf` to `dest` in one `sendmsg`, cut into `segment`-byte datagrams by the kernel
+#[cfg(target_os = "linux")]
+pub fn send_segmented(socket: &crate::udp::Udp, buf: &[u8], dest: SocketAddr, segment: u16) -> std::io::Result<usize> {
+    use crate::raw as r;
+    use std::os::fd::AsRawFd;
+
+    let (_, name, name_len) = r::to_sockaddr(dest);
+    let mut iov = libc::iovec { iov_base: buf.as_ptr() as *mut _, iov_len: buf.len() };
+    // u64 storage keeps the cmsghdr aligned; 4 words fit CMSG_SPACE(2) on every ABI
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = match &name {
+        r::SockAddr::V4(s) => s as *const _ as *mut libc::c_void,
+        r::SockAddr::V6(s) => s as *const _ as *mut libc::c_void,
+    };
+    msg.msg_namelen = name_len;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut _;
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(std::mem::size_of::<u16>() as _) } as _;
+    // SAFETY: the control buffer is aligned and large enough for one u16 message
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_UDP;
+        (*cmsg).cmsg_type = UDP_SEGMENT;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u16>() as _) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment);
+    }
+
+    let rc = unsafe { libc::sendmsg(socket.socket().as_raw_fd(), &msg, 0) };
+    if rc < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(rc as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icmp_lowers_only_its_destination() {
+        let a: SocketAddr = "192.0.2.1:9000".parse().unwrap();
+        let b: SocketAddr = "[2001:db8::1]:9000".parse().unwrap();
+        let mut sizer = GsoSizer::new();
+        sizer.set_segment_size(a, 1472);
+        sizer.set_segment_size(b, 1452);
+
+        let too_big = |dest, mtu| IcmpError {
+            kind: IcmpErrorKind::FragmentationNeeded { mtu },
+            errno: 0,
+            destination: Some(dest),
+            offender: None,
+            payload: Vec::new(),
+        };
+        assert_eq!(sizer.on_icmp(&too_big(a, 1400)), Some(1372));
+        assert_eq!(sizer.on_icmp(&too_big(a, 9000)), None);
+        assert_eq!(sizer.on_icmp(&too_big(b, 1280)), Some(1232));
+        assert_eq!((sizer.segment_size(a), sizer.segment_size(b)), (1372, 1232));
+        assert_eq!(max_payload(20, a), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_kernel_splits_segmented_send() {
+        use crate::{udp::Udp, NetConfig};
+
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let buf: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+        match send_segmented(&tx, &buf, rx.local_addr().unwrap(), 1000) {
+            Ok(n) => assert_eq!(n, buf.len()),
+            // Kernels before 4.18 have no UDP GSO
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) || e.raw_os_error() == Some(libc::ENOPROTOOPT) => return,
+            Err(e) => panic!("{}", e),
+        }
+
+        let mut lens = Vec::new();
+        let mut pkt = [0u8; 2048];
+        for _ in 0..1000 {
+            if lens.len() == 3 {
+                break;
+            }
+            match rx.socket().recv_from(&mut pkt) {
+                Ok((n, _)) => lens.push(n),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(std::time::Duration::from_millis(1)),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert_eq!(lens, [1000, 1000, 500]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Half-closed TCP connections and lingering close
+//!
+//! TCP lets each side finish sending independently: after
+//! `shutdown(Write)` a connection can still receive until the peer sends
+//! its own FIN. Proxies depend on this. When one side finishes, the proxy
+//! forwards the FIN and keeps relaying the other direction. Servers depend
+//! on it too. Closing a socket with unread data makes the kernel send RST,
+//! which can destroy the response the peer has not read yet. A lingering
+//! close sends FIN, then reads and discards until the peer closes or a
+//! timeout expires.
+//!
+//! [`HalfClose`] tracks both directions of one connection. It works with any
+//! [`StreamSocket`]. Its [`deadline`](HalfClose::deadline) plugs into an
+//! event loop's poll timeout, so lingering connections need no dedicated
+//! timer thread.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, tcp::TcpStream};
+//! use horizon_sockets::half_close::HalfClose;
+//! use std::time::{Duration, Instant};
+//!
+//! let stream = TcpStream::connect("127.0.0.1:8080".parse()?, &NetConfig::default())?;
+//! stream.as_std().set_nonblocking(true)?;
+//! // ... response written ...
+//!
+//! let mut close = HalfClose::new(Duration::from_secs(2));
+//! close.shutdown_write(&stream, Instant::now())?;
+//! loop {
+//!     // Wait for readability up to close.timeout(Instant::now()), e.g. with poll::wait
+//!     if close.drain(&stream)?.is_finished() || close.on_timer(Instant::now()).is_finished() {
+//!         break;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::transport::StreamSocket;
+use std::io;
+use std::net::Shutdown;
+use std::time::{Duration, Instant};
+
+/// Where a connection is in its shutdown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseState {
+    /// Both directions are open
+    Open,
+    /// The peer sent FIN; this side may still write
+    ReadClosed,
+    /// This side sent FIN and is waiting for the peer's
+    WriteClosed,
+    /// Both directions finished cleanly
+    Closed,
+    /// The peer did not finish within the linger timeout
+    TimedOut,
+}
+
+impl CloseState {
+    /// Returns `true` once the connection can be dropped
+    pub fn is_finished(self) -> bool {
+        matches!(self, CloseState::Closed | CloseState::TimedOut)
+    }
+}
+
+/// Shutdown state tracker for one connection
+#[derive(Clone, Debug)]
+pub struct HalfClose {
+    state: CloseState,
+    linger: Duration,
+    /// When waiting for the peer's FIN gives up
+    deadline: Option<Instant>,
+    /// Bytes read and discarded while lingering
+    discarded: u64,
+}
+
+impl HalfClose {
+    /// Creates a tracker that waits up to `linger` for the peer's FIN
+    pub fn new(linger: Duration) -> Self {
+        Self { state: CloseState::Open, linger, deadline: None, discarded: 0 }
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> CloseState {
+        self.state
+    }
+
+    /// Sends FIN on `stream` and starts the linger timer
+    ///
+    /// Calling it again after the write side is closed does nothing.
+    pub fn shutdown_write<S: StreamSocket + ?Sized>(&mut self, stream: &S, now: Instant) -> io::Result<()> {
+        let next = match self.state {
+            CloseState::Open => CloseState::WriteClosed,
+            CloseState::ReadClosed => CloseState::Closed,
+            _ => return Ok(()),
+        };
+        stream.shutdown(Shutdown::Write)?;
+        self.state = next;
+        if next == CloseState::WriteClosed {
+            self.deadline = Some(now + self.linger);
+        }
+        Ok(())
+    }
+
+    /// Records that a read returned `Ok(0)`: the peer sent FIN
+    pub fn on_eof(&mut self) -> CloseState {
+        self.state = match self.state {
+            CloseState::Open => CloseState::ReadClosed,
+            CloseState::WriteClosed => CloseState::Closed,
+            other => other,
+        };
+        self.deadline = None;
+        self.state
+    }
+
+    /// Reads and discards from a nonblocking `stream` until it would block or ends
+    ///
+    /// Use after [`shutdown_write`](Self::shutdown_write) when the
+    /// application has no interest in further input. Call it whenever the
+    /// stream is readable.
+    ///
+    /// # Errors
+    ///
+    /// Read errors other than `WouldBlock` and `Interrupted`. A reset from
+    /// the peer also ends the linger, so callers usually just drop the
+    /// stream on error.
+    pub fn drain<S: StreamSocket + ?Sized>(&mut self, stream: &S) -> io::Result<CloseState> {
+        let mut scratch = [0u8; 4096];
+        while !self.state.is_finished() && self.state != CloseState::ReadClosed {
+            match stream.recv(&mut scratch) {
+                Ok(0) => return Ok(self.on_eof()),
+                Ok(n) => self.discarded += n as u64,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.state)
+    }
+
+    /// Checks the linger timer, moving to [`CloseState::TimedOut`] once it expires
+    pub fn on_timer(&mut self, now: Instant) -> CloseState {
+        if self.deadline.is_some_and(|d| now >= d) {
+            self.state = CloseState::TimedOut;
+            self.deadline = None;
+        }
+        self.state
+    }
+
+    /// Returns when the linger timer expires, if it is running
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns how long an event loop may sleep before calling [`on_timer`](Self::on_timer)
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(now))
+    }
+
+    /// Returns the number of bytes discarded by [`drain`](Self::drain)
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memnet::MemStream;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_lingering_close_drains_until_eof() {
+        let (server, client) = MemStream::pair();
+        let now = Instant::now();
+        let mut close = HalfClose::new(Duration::from_secs(1));
+
+        close.shutdown_write(&server, now).unwrap();
+        assert_eq!(close.state(), CloseState::WriteClosed);
+        assert_eq!(close.timeout(now), Some(Duration::from_secs(1)));
+
+        // The client sees EOF but can still send
+        let mut buf = [0u8; 8];
+        assert_eq!((&client).read(&mut buf).unwrap(), 0);
+        (&client).write_all(b"late request").unwrap();
+        assert_eq!(close.drain(&server).unwrap(), CloseState::WriteClosed);
+        assert_eq!(close.discarded(), 12);
+
+        client.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(close.drain(&server).unwrap(), CloseState::Closed);
+        assert_eq!(close.deadline(), None);
+        assert_eq!(close.on_timer(now + Duration::from_secs(5)), CloseState::Closed);
+    }
+
+    #[test]
+    fn test_peer_first_and_timeout() {
+        let (a, b) = MemStream::pair();
+        let now = Instant::now();
+
+        // Proxy side: peer finished first, forward our FIN afterwards
+        let mut proxy = HalfClose::new(Duration::from_secs(1));
+        assert_eq!(proxy.on_eof(), CloseState::ReadClosed);
+        proxy.shutdown_write(&a, now).unwrap();
+        assert_eq!(proxy.state(), CloseState::Closed);
+
+        let mut close = HalfClose::new(Duration::from_millis(10));
+        close.shutdown_write(&b, now).unwrap();
+        assert_eq!(close.on_timer(now + Duration::from_millis(9)), CloseState::WriteClosed);
+        assert_eq!(close.on_timer(now + Duration::from_millis(10)), CloseState::TimedOut);
+        assert!(close.state().is_finished());
+    }
+}
@@ -0,0 +1,211 @@
+//! Slow-loris protection for accepted connections
+//!
+//! A slow-loris client opens many connections and sends its request one
+//! byte at a time, or not at all. Each connection ties up a descriptor and
+//! a slot in the application's pending-handshake state. [`HandshakeGuard`]
+//! limits both:
+//!
+//! - [`apply`](HandshakeGuard::apply) sets `TCP_DEFER_ACCEPT` on Linux, so
+//!   connections that have sent nothing wait in the kernel instead of
+//!   reaching `accept`. The kernel accepts them anyway once the timeout
+//!   passes, so the timers below are still needed.
+//! - Every admitted connection must finish its application handshake
+//!   (request headers, TLS hello, login message) within the timeout.
+//!   [`pop_expired`](HandshakeGuard::pop_expired) yields the ones that did
+//!   not, and [`next_deadline`](HandshakeGuard::next_deadline) drives the
+//!   event loop's timer.
+//! - At most `max_pending` connections may be mid-handshake. Admitting one
+//!   more evicts the oldest, which is the likeliest to be a slow client.
+//!
+//! Connections are identified by a caller-chosen key such as a slab index
+//! or poll token.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use horizon_sockets::handshake_guard::HandshakeGuard;
+//! use std::collections::HashMap;
+//! use std::time::{Duration, Instant};
+//!
+//! let listener = TcpListener::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+//! let mut guard = HandshakeGuard::new(Duration::from_millis(500), 1024);
+//! guard.apply(&listener)?;
+//!
+//! let mut conns = HashMap::new();
+//! let mut next_id = 0u64;
+//! loop {
+//!     let now = Instant::now();
+//!     for conn in listener.try_incoming() {
+//!         let (stream, _) = conn?;
+//!         next_id += 1;
+//!         conns.insert(next_id, stream);
+//!         if let Some(evicted) = guard.admit(next_id, now) {
+//!             conns.remove(&evicted);
+//!         }
+//!     }
+//!     // ... read; call guard.complete(id) once a connection's headers are parsed ...
+//!     while let Some(slow) = guard.pop_expired(now) {
+//!         conns.remove(&slow);
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::tcp::TcpListener;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Handshake deadline and concurrency limit for accepted connections
+#[derive(Debug)]
+pub struct HandshakeGuard<K> {
+    timeout: Duration,
+    max_pending: usize,
+    /// Admitted connections in admission order; entries whose key was
+    /// completed or readmitted are skipped lazily
+    queue: VecDeque<(Instant, K)>,
+    /// Deadline per connection still mid-handshake
+    pending: HashMap<K, Instant>,
+}
+
+impl<K: Hash + Eq + Clone> HandshakeGuard<K> {
+    /// Creates a guard allowing `timeout` per handshake and `max_pending` at once
+    pub fn new(timeout: Duration, max_pending: usize) -> Self {
+        Self { timeout, max_pending: max_pending.max(1), queue: VecDeque::new(), pending: HashMap::new() }
+    }
+
+    /// Enables kernel-side first-byte filtering on `listener` where available
+    ///
+    /// Sets `TCP_DEFER_ACCEPT` to the handshake timeout on Linux; a no-op elsewhere.
+    pub fn apply(&self, listener: &TcpListener) -> io::Result<()> {
+        listener.set_defer_accept(self.timeout)
+    }
+
+    /// Starts the handshake clock for a newly accepted connection
+    ///
+    /// # Returns
+    ///
+    /// The oldest pending connection if the limit was exceeded; the caller
+    /// should close it
+    pub fn admit(&mut self, key: K, now: Instant) -> Option<K> {
+        let deadline = now + self.timeout;
+        self.pending.insert(key.clone(), deadline);
+        self.queue.push_back((deadline, key));
+        if self.pending.len() <= self.max_pending {
+            return None;
+        }
+        while let Some((deadline, key)) = self.queue.pop_front() {
+            if self.pending.get(&key) == Some(&deadline) {
+                self.pending.remove(&key);
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Marks the connection's handshake as finished, stopping its clock
+    ///
+    /// Returns `false` if the connection was not pending (already completed,
+    /// expired, or evicted).
+    pub fn complete(&mut self, key: &K) -> bool {
+        let found = self.pending.remove(key).is_some();
+        // Keep stale queue entries bounded when handshakes finish quickly
+        if self.queue.len() > 2 * self.pending.len() + 64 {
+            let pending = &self.pending;
+            self.queue.retain(|(deadline, key)| pending.get(key) == Some(deadline));
+        }
+        found
+    }
+
+    /// Removes and returns one connection whose handshake deadline has passed
+    pub fn pop_expired(&mut self, now: Instant) -> Option<K> {
+        while let Some(&(deadline, _)) = self.queue.front() {
+            if deadline > now {
+                return None;
+            }
+            let (deadline, key) = self.queue.pop_front()?;
+            if self.pending.get(&key) == Some(&deadline) {
+                self.pending.remove(&key);
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Returns the earliest handshake deadline, for arming the event loop timer
+    pub fn next_deadline(&mut self) -> Option<Instant> {
+        while let Some((deadline, key)) = self.queue.front() {
+            if self.pending.get(key) == Some(deadline) {
+                return Some(*deadline);
+            }
+            self.queue.pop_front();
+        }
+        None
+    }
+
+    /// Returns the number of connections mid-handshake
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns `true` if `key` is mid-handshake
+    pub fn is_pending(&self, key: &K) -> bool {
+        self.pending.contains_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_and_eviction() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut guard = HandshakeGuard::new(ms(100), 2);
+
+        assert_eq!(guard.admit(1, start), None);
+        assert_eq!(guard.admit(2, start + ms(10)), None);
+        assert!(guard.complete(&1));
+        assert_eq!(guard.next_deadline(), Some(start + ms(110)));
+
+        assert_eq!(guard.admit(3, start + ms(20)), None);
+        // Over the limit: the oldest pending handshake is evicted
+        assert_eq!(guard.admit(4, start + ms(30)), Some(2));
+        assert_eq!(guard.pending(), 2);
+
+        assert_eq!(guard.pop_expired(start + ms(119)), None);
+        assert_eq!(guard.pop_expired(start + ms(125)), Some(3));
+        assert_eq!(guard.pop_expired(start + ms(125)), None);
+        assert!(!guard.complete(&3));
+        assert!(guard.is_pending(&4));
+    }
+
+    #[test]
+    fn test_defer_accept_on_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &crate::NetConfig::default()).unwrap();
+        let guard: HandshakeGuard<u64> = HandshakeGuard::new(Duration::from_millis(1500), 16);
+        guard.apply(&listener).unwrap();
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            let mut secs: libc::c_int = 0;
+            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+            let rc = unsafe {
+                libc::getsockopt(
+                    listener.as_raw_fd(),
+                    libc::IPPROTO_TCP,
+                    libc::TCP_DEFER_ACCEPT,
+                    &mut secs as *mut _ as *mut libc::c_void,
+                    &mut len,
+                )
+            };
+            assert_eq!(rc, 0);
+            // 1.5s rounds up to 2s; the kernel may round further to a SYN-ACK retransmit boundary
+            assert!(secs >= 2);
+        }
+    }
+}
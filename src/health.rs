@@ -0,0 +1,215 @@
+//! Built-in HTTP liveness, readiness, and stats endpoint
+//!
+//! Orchestrators (Kubernetes, Nomad, load balancer health checks) decide
+//! whether to route traffic to a process by probing an HTTP endpoint.
+//! Services built on this crate often have no HTTP stack of their own, and
+//! pulling in a web framework for three URLs is out of proportion.
+//! [`Health`] answers those probes as an [`http::Handler`](crate::http::Handler):
+//!
+//! - `GET /livez`: always `200 OK` while the process runs
+//! - `GET /readyz`: `200 OK` once [`set_ready(true)`](Health::set_ready)
+//!   has been called, `503 Service Unavailable` otherwise
+//! - `GET /stats`: JSON with readiness, uptime, and every sample in the
+//!   attached [`Metrics`] registry
+//!
+//! [`Health::serve`] binds a listener and returns the
+//! [`HttpServer`](crate::http::HttpServer) that serves it; poll it from the
+//! application's own loop, so no thread is spawned. An application that
+//! already runs an `HttpServer` can route requests to
+//! [`Health::handle`](crate::http::Handler::handle) instead.
+//!
+//! Each request must arrive complete within one second of the connection
+//! opening or of the previous response, however slowly its bytes trickle
+//! in, and a probe waiting on a slow client does not hold up others on
+//! the same loop. Bind the endpoint to a management address, not the
+//! public interface.
+//!
+//! Requires the `health` feature; `serve` is available on Unix.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::health::Health;
+//! use horizon_sockets::metrics::Metrics;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let metrics = Metrics::new();
+//! let health = Health::new(metrics.clone());
+//! # #[cfg(unix)]
+//! let mut probes = health.serve("127.0.0.1:9090".parse()?, &NetConfig::default())?;
+//!
+//! let socket = Arc::new(Udp::bind("0.0.0.0:7000".parse()?, &NetConfig::default())?);
+//! metrics.socket("game", &socket);
+//! health.set_ready(true);
+//! loop {
+//!     // ... handle game traffic, then answer any pending probes:
+//!     # #[cfg(unix)]
+//!     probes.poll(Some(Duration::from_millis(1)))?;
+//!     // curl http://127.0.0.1:9090/stats
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::http::{Handler, Request, Response};
+use crate::metrics::{MetricKind, Metrics};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+    ready: AtomicBool,
+    metrics: Metrics,
+}
+
+/// Health probe state and request handler
+///
+/// Clones share readiness and metrics, so the application keeps one to
+/// call [`set_ready`](Self::set_ready) while the server holds others.
+#[derive(Clone)]
+pub struct Health {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Health").field("ready", &self.is_ready()).finish_non_exhaustive()
+    }
+}
+
+impl Health {
+    /// Creates the probe state, reporting `metrics` at `/stats`; the service starts not ready
+    pub fn new(metrics: Metrics) -> Self {
+        Self { shared: Arc::new(Shared { ready: AtomicBool::new(false), metrics }) }
+    }
+
+    /// Binds `addr` and returns a server answering probes, to be polled by the caller (Unix)
+    #[cfg(unix)]
+    pub fn serve(
+        &self,
+        addr: std::net::SocketAddr,
+        cfg: &crate::NetConfig,
+    ) -> std::io::Result<crate::http::HttpServer<impl FnMut() -> Health, Health>> {
+        let health = self.clone();
+        let limits = crate::http::HttpLimits { max_head: 4096, max_body: 0, idle_timeout: std::time::Duration::from_secs(1) };
+        Ok(crate::http::HttpServer::bind(addr, cfg, move || health.clone())?.limits(limits))
+    }
+
+    /// Sets what `/readyz` reports
+    pub fn set_ready(&self, ready: bool) {
+        self.shared.ready.store(ready, Ordering::Relaxed);
+    }
+
+    /// Returns what `/readyz` currently reports
+    pub fn is_ready(&self) -> bool {
+        self.shared.ready.load(Ordering::Relaxed)
+    }
+}
+
+impl Handler for Health {
+    fn handle(&mut self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::new(405).header("Allow", "GET, HEAD");
+        }
+        let shared = &self.shared;
+        let (status, content_type, body) = match request.path() {
+            "/livez" => (200, "text/plain", "ok\n".to_string()),
+            "/readyz" if shared.ready.load(Ordering::Relaxed) => (200, "text/plain", "ready\n".to_string()),
+            "/readyz" => (503, "text/plain", "not ready\n".to_string()),
+            "/stats" => (200, "application/json", stats_json(shared)),
+            _ => (404, "text/plain", "not found\n".to_string()),
+        };
+        Response::new(status).header("Content-Type", content_type).body(body)
+    }
+}
+
+/// Renders readiness, uptime, and metric samples as a JSON object
+fn stats_json(shared: &Shared) -> String {
+    let ready = shared.ready.load(Ordering::Relaxed);
+    let mut out = format!(
+        "{{\"status\":\"ok\",\"ready\":{},\"uptime_secs\":{:.3},\"metrics\":[",
+        ready,
+        shared.metrics.uptime().as_secs_f64()
+    );
+    for (i, sample) in shared.metrics.samples().iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        json_string(&mut out, &sample.name);
+        let kind = match sample.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        let _ = write!(out, ",\"kind\":\"{}\",\"labels\":{{", kind);
+        for (j, (key, value)) in sample.labels.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            json_string(&mut out, key);
+            out.push(':');
+            json_string(&mut out, value);
+        }
+        // JSON has no NaN or infinity
+        if sample.value.is_finite() {
+            let _ = write!(out, "}},\"value\":{}}}", sample.value);
+        } else {
+            out.push_str("},\"value\":null}");
+        }
+    }
+    out.push_str("]}\n");
+    out
+}
+
+/// Appends `s` as a quoted, escaped JSON string
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probes_and_stats() {
+        use crate::http::exchange;
+
+        let metrics = Metrics::new();
+        metrics.counter("drops_total", "Dropped datagrams", || 4.0);
+        let health = Health::new(metrics);
+        let mut server = health.serve("127.0.0.1:0".parse().unwrap(), &crate::NetConfig::default()).unwrap();
+        let mut get = |path: &str| exchange(&mut server, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).as_bytes());
+
+        assert!(get("/livez").starts_with("HTTP/1.1 200 OK"));
+        assert!(get("/readyz").starts_with("HTTP/1.1 503"));
+        health.set_ready(true);
+        assert!(get("/readyz?verbose").starts_with("HTTP/1.1 200 OK"));
+        assert!(get("/nope").starts_with("HTTP/1.1 404"));
+
+        let stats = get("/stats");
+        assert!(stats.contains("Content-Type: application/json"));
+        assert!(stats.contains("\"ready\":true"));
+        assert!(stats.contains("{\"name\":\"drops_total\",\"kind\":\"counter\",\"labels\":{},\"value\":4}"));
+        assert!(exchange(&mut server, b"POST /livez HTTP/1.1\r\nConnection: close\r\n\r\n").starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn test_json_escaping() {
+        let mut out = String::new();
+        json_string(&mut out, "a\"b\\c\n");
+        assert_eq!(out, r#""a\"b\\c\u000a""#);
+    }
+}
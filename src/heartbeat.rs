@@ -0,0 +1,328 @@
+//! Keepalive heartbeats and idle timeouts for UDP sessions
+//!
+//! UDP has no kernel keepalive. A session-oriented protocol has to notice
+//! on its own that a peer went away, and has to send something when it
+//! has nothing else to say so NAT bindings and the peer's own timeout stay
+//! fresh. [`Heartbeats`] tracks when each peer was last heard from and
+//! last sent to, and reports two kinds of [`HeartbeatEvent`]:
+//!
+//! - [`Send`](HeartbeatEvent::Send) when nothing has been sent to a peer
+//!   for the heartbeat interval
+//! - [`Timeout`](HeartbeatEvent::Timeout) when nothing has been received
+//!   from a peer for the idle timeout; the peer is forgotten
+//!
+//! Any traffic counts: call [`seen`](Heartbeats::seen) for every datagram
+//! received and [`sent`](Heartbeats::sent) for every datagram sent, and
+//! heartbeats only go out on quiet sessions.
+//!
+//! Timers live in a hashed timing wheel, so tracking a hundred thousand
+//! peers costs one slot visit per [`granularity`](Heartbeats::granularity)
+//! rather than a scan of every peer. Refreshing a peer is a map update;
+//! its timer is rescheduled lazily when it fires. Events fire up to one
+//! granularity late, never early. [`next_deadline`](Heartbeats::next_deadline)
+//! bounds the event loop's poll timeout.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::heartbeat::{HeartbeatEvent, Heartbeats};
+//! use std::net::SocketAddr;
+//! use std::time::{Duration, Instant};
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut beats = Heartbeats::new(Duration::from_secs(1), Duration::from_secs(10));
+//! let mut bufs = vec![vec![0u8; 1500]; 32];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 32];
+//!
+//! loop {
+//!     // Wait for readability up to beats.next_deadline(), e.g. with poll::wait
+//!     let now = Instant::now();
+//!     if let Ok(count) = socket.recv_batch(&mut bufs, &mut addrs) {
+//!         for addr in &addrs[..count] {
+//!             beats.seen(*addr, now);
+//!         }
+//!     }
+//!     beats.poll(now, |event| match event {
+//!         HeartbeatEvent::Send(peer) => { let _ = socket.send_to(b"\0ping", peer); }
+//!         HeartbeatEvent::Timeout(peer) => println!("{} went quiet", peer),
+//!     });
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::transport::DatagramSocket;
+use crate::trace;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Timer outcome for one peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeartbeatEvent {
+    /// Nothing was sent to the peer for the heartbeat interval; send a heartbeat
+    Send(SocketAddr),
+    /// Nothing was received from the peer for the idle timeout; it is no longer tracked
+    Timeout(SocketAddr),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Peer {
+    last_seen: Instant,
+    last_sent: Instant,
+    /// Distinguishes this tracking from earlier ones of the same address
+    generation: u64,
+}
+
+/// Wheel entry: the tick it fires at and which tracking of the peer it belongs to
+#[derive(Clone, Copy, Debug)]
+struct Timer {
+    tick: u64,
+    peer: SocketAddr,
+    generation: u64,
+}
+
+/// Per-peer heartbeat and idle-timeout scheduler
+#[derive(Debug)]
+pub struct Heartbeats {
+    interval: Duration,
+    timeout: Duration,
+    granularity: Duration,
+    peers: HashMap<SocketAddr, Peer>,
+    slots: Vec<Vec<Timer>>,
+    /// Origin of the wheel's ticks
+    start: Instant,
+    /// Next tick to process
+    cursor: u64,
+    next_generation: u64,
+}
+
+impl Heartbeats {
+    /// Creates a scheduler sending heartbeats every `interval` and timing peers out after `timeout`
+    ///
+    /// The wheel has 512 slots with a granularity of a sixteenth of the
+    /// interval, at least one millisecond.
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            granularity: (interval / 16).max(Duration::from_millis(1)),
+            peers: HashMap::new(),
+            slots: vec![Vec::new(); 512],
+            start: Instant::now(),
+            cursor: 0,
+            next_generation: 0,
+        }
+    }
+
+    /// Sets how finely timers are resolved
+    ///
+    /// Coarser granularity means fewer wakeups and later events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero.
+    pub fn granularity(mut self, granularity: Duration) -> Self {
+        assert!(!granularity.is_zero(), "granularity must be non-zero");
+        self.granularity = granularity;
+        self
+    }
+
+    /// Starts tracking `peer` as if it had just been heard from and sent to
+    ///
+    /// Tracking a peer again restarts both of its timers.
+    pub fn add(&mut self, peer: SocketAddr, now: Instant) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.peers.insert(peer, Peer { last_seen: now, last_sent: now, generation });
+        self.schedule(peer, generation, now + self.interval.min(self.timeout));
+    }
+
+    /// Stops tracking `peer`; returns `true` if it was tracked
+    pub fn remove(&mut self, peer: &SocketAddr) -> bool {
+        // Its timer is discarded when it fires and finds no matching peer
+        self.peers.remove(peer).is_some()
+    }
+
+    /// Records a datagram received from `peer`, tracking it if it is new
+    pub fn seen(&mut self, peer: SocketAddr, now: Instant) {
+        match self.peers.get_mut(&peer) {
+            Some(p) => p.last_seen = now,
+            None => self.add(peer, now),
+        }
+    }
+
+    /// Records a datagram sent to `peer`, postponing its next heartbeat
+    ///
+    /// Untracked peers are ignored.
+    pub fn sent(&mut self, peer: SocketAddr, now: Instant) {
+        if let Some(p) = self.peers.get_mut(&peer) {
+            p.last_sent = now;
+        }
+    }
+
+    /// Returns `true` if `peer` is tracked
+    pub fn contains(&self, peer: &SocketAddr) -> bool {
+        self.peers.contains_key(peer)
+    }
+
+    /// Returns the number of tracked peers
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Returns `true` if no peers are tracked
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Returns when [`poll`](Self::poll) next needs to run, if any peer is tracked
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.peers.is_empty() {
+            return None;
+        }
+        Some(self.tick_time(self.cursor))
+    }
+
+    /// Fires every timer due at `now`, reporting heartbeats to send and peers timed out
+    ///
+    /// A [`Send`](HeartbeatEvent::Send) event counts as sending; there is
+    /// no need to call [`sent`](Self::sent) for the heartbeat itself.
+    pub fn poll<F: FnMut(HeartbeatEvent)>(&mut self, now: Instant, mut on_event: F) {
+        let Some(target) = self.tick_at(now) else { return };
+        if target < self.cursor {
+            return;
+        }
+        let n = self.slots.len() as u64;
+        let steps = (target - self.cursor + 1).min(n);
+        let mut fired = Vec::new();
+        for i in 0..steps {
+            let slot = &mut self.slots[((self.cursor + i) % n) as usize];
+            // Timers for later laps of the wheel stay in place
+            let mut j = 0;
+            while j < slot.len() {
+                if slot[j].tick <= target {
+                    fired.push(slot.swap_remove(j));
+                } else {
+                    j += 1;
+                }
+            }
+        }
+        self.cursor = target + 1;
+
+        for timer in fired {
+            let Some(peer) = self.peers.get_mut(&timer.peer) else { continue };
+            if peer.generation != timer.generation {
+                continue;
+            }
+            if now >= peer.last_seen + self.timeout {
+                self.peers.remove(&timer.peer);
+                trace::event!(debug, peer = %timer.peer, "heartbeat timeout");
+                on_event(HeartbeatEvent::Timeout(timer.peer));
+                continue;
+            }
+            if now >= peer.last_sent + self.interval {
+                peer.last_sent = now;
+                on_event(HeartbeatEvent::Send(timer.peer));
+            }
+            let due = (peer.last_sent + self.interval).min(peer.last_seen + self.timeout);
+            let generation = peer.generation;
+            self.schedule(timer.peer, generation, due);
+        }
+    }
+
+    /// Runs [`poll`](Self::poll), sending `payload` to every peer due a heartbeat with one `send_batch` call
+    ///
+    /// Timed-out peers are passed to `on_timeout`. Heartbeats the socket
+    /// does not take are skipped until the next interval.
+    ///
+    /// # Returns
+    ///
+    /// The number of heartbeats sent
+    pub fn send_due<S, F>(&mut self, sock: &S, payload: &[u8], now: Instant, mut on_timeout: F) -> io::Result<usize>
+    where
+        S: DatagramSocket + ?Sized,
+        F: FnMut(SocketAddr),
+    {
+        let mut packets = Vec::new();
+        self.poll(now, |event| match event {
+            HeartbeatEvent::Send(peer) => packets.push((payload, peer)),
+            HeartbeatEvent::Timeout(peer) => on_timeout(peer),
+        });
+        if packets.is_empty() {
+            return Ok(0);
+        }
+        sock.send_batch(&packets)
+    }
+
+    /// Tick containing `t`, or `None` before the wheel started
+    fn tick_at(&self, t: Instant) -> Option<u64> {
+        let since = t.checked_duration_since(self.start)?;
+        Some((since.as_nanos() / self.granularity.as_nanos()) as u64)
+    }
+
+    fn tick_time(&self, tick: u64) -> Instant {
+        self.start + Duration::from_nanos((tick as u128 * self.granularity.as_nanos()) as u64)
+    }
+
+    /// Queues a timer for the first tick at or after `due`
+    fn schedule(&mut self, peer: SocketAddr, generation: u64, due: Instant) {
+        let since = due.saturating_duration_since(self.start).as_nanos();
+        let tick = (since.div_ceil(self.granularity.as_nanos()) as u64).max(self.cursor);
+        let n = self.slots.len() as u64;
+        self.slots[(tick % n) as usize].push(Timer { tick, peer, generation });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn events(beats: &mut Heartbeats, now: Instant) -> Vec<HeartbeatEvent> {
+        let mut out = Vec::new();
+        beats.poll(now, |e| out.push(e));
+        out
+    }
+
+    #[test]
+    fn test_heartbeat_only_on_quiet_sessions() {
+        let mut beats = Heartbeats::new(100 * MS, 1000 * MS).granularity(10 * MS);
+        let t0 = beats.start;
+        let (quiet, busy): (SocketAddr, SocketAddr) = ("10.0.0.1:1".parse().unwrap(), "10.0.0.2:1".parse().unwrap());
+        beats.add(quiet, t0);
+        beats.add(busy, t0);
+
+        assert!(events(&mut beats, t0 + 99 * MS).is_empty());
+        beats.sent(busy, t0 + 90 * MS);
+        assert_eq!(events(&mut beats, t0 + 100 * MS), vec![HeartbeatEvent::Send(quiet)]);
+        // The busy peer's timer was rescheduled for 100ms after its last send
+        assert_eq!(events(&mut beats, t0 + 190 * MS), vec![HeartbeatEvent::Send(busy)]);
+        assert_eq!(events(&mut beats, t0 + 200 * MS), vec![HeartbeatEvent::Send(quiet)]);
+        assert_eq!(beats.next_deadline(), Some(t0 + 210 * MS));
+    }
+
+    #[test]
+    fn test_timeout_forgets_peer_unless_seen() {
+        let mut beats = Heartbeats::new(100 * MS, 250 * MS).granularity(10 * MS);
+        let t0 = beats.start;
+        let (alive, dead): (SocketAddr, SocketAddr) = ("10.0.0.1:1".parse().unwrap(), "10.0.0.2:1".parse().unwrap());
+        beats.seen(alive, t0);
+        beats.seen(dead, t0);
+        beats.seen(alive, t0 + 200 * MS);
+
+        // Skipping ahead past several heartbeats still fires each timer once
+        let out = events(&mut beats, t0 + 300 * MS);
+        assert!(out.contains(&HeartbeatEvent::Timeout(dead)));
+        assert!(out.contains(&HeartbeatEvent::Send(alive)));
+        assert!(!beats.contains(&dead) && beats.contains(&alive));
+
+        // A removed and re-added peer ignores its stale timer
+        beats.remove(&alive);
+        beats.add(alive, t0 + 400 * MS);
+        assert!(events(&mut beats, t0 + 450 * MS).is_empty());
+        assert_eq!(beats.len(), 1);
+    }
+}
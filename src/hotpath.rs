@@ -0,0 +1,153 @@
+//! Cache and branch helpers for per-packet loops
+//!
+//! After a `recv_batch` the packet payloads are usually cold: the NIC wrote
+//! them by DMA, possibly into a different cache than the one of the core
+//! now reading them. Touching them one at a time stalls on each miss.
+//! [`for_each_batch`] processes packets in chunks and prefetches the next
+//! chunk while the current one is handled, so the misses overlap with
+//! useful work.
+//!
+//! [`prefetch`] and [`prefetch_bytes`] wrap the architecture's prefetch
+//! instruction and compile to nothing where there is none. [`likely`] and
+//! [`unlikely`] mark the expected side of a branch on stable Rust by
+//! routing the other side through a `#[cold]` function.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::hotpath::{for_each_batch, unlikely};
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut bufs = vec![vec![0u8; 2048]; 64];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 64];
+//!
+//! let count = socket.recv_batch(&mut bufs, &mut addrs)?;
+//! for_each_batch(&bufs[..count], 8, |chunk| {
+//!     for packet in chunk {
+//!         if unlikely(packet.is_empty()) {
+//!             continue;
+//!         }
+//!         // parse packet[0] ...
+//!     }
+//! });
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Cache line size assumed for prefetch strides
+pub const CACHE_LINE: usize = 64;
+
+/// Hints the CPU to load the cache line holding `value` for reading
+///
+/// Never faults, whatever the address; a no-op on architectures without a
+/// stable prefetch instruction.
+#[inline(always)]
+pub fn prefetch<T: ?Sized>(value: &T) {
+    prefetch_ptr(value as *const T as *const u8);
+}
+
+/// Hints the CPU to load the first `lines` cache lines of `bytes`
+///
+/// One or two lines usually cover the headers a packet loop inspects first.
+#[inline(always)]
+pub fn prefetch_bytes(bytes: &[u8], lines: usize) {
+    let mut offset = 0;
+    for _ in 0..lines {
+        if offset >= bytes.len() {
+            break;
+        }
+        prefetch_ptr(bytes[offset..].as_ptr());
+        offset += CACHE_LINE;
+    }
+}
+
+#[inline(always)]
+fn prefetch_ptr(ptr: *const u8) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            // SAFETY: prefetching is a hint and never faults; SSE is part of the x86_64 baseline
+            unsafe {
+                use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+                _mm_prefetch::<_MM_HINT_T0>(ptr as *const i8);
+            }
+        } else if #[cfg(target_arch = "aarch64")] {
+            // SAFETY: PRFM is a hint and never faults
+            unsafe {
+                std::arch::asm!("prfm pldl1keep, [{0}]", in(reg) ptr, options(nostack, readonly, preserves_flags));
+            }
+        } else {
+            let _ = ptr;
+        }
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn cold() {}
+
+/// Returns `b`, telling the optimizer it is usually `true`
+#[inline(always)]
+pub fn likely(b: bool) -> bool {
+    if !b {
+        cold();
+    }
+    b
+}
+
+/// Returns `b`, telling the optimizer it is usually `false`
+#[inline(always)]
+pub fn unlikely(b: bool) -> bool {
+    if b {
+        cold();
+    }
+    b
+}
+
+/// Calls `f` on consecutive chunks of `n` buffers, prefetching each next chunk
+///
+/// Before `f` runs on a chunk, the first cache line of every buffer in the
+/// following chunk is prefetched. Chunks of 4-16 packets keep the
+/// prefetched lines in L1 until they are used. The last chunk may be shorter.
+pub fn for_each_batch<B: AsRef<[u8]>>(bufs: &[B], n: usize, mut f: impl FnMut(&[B])) {
+    let n = n.max(1);
+    let mut chunks = bufs.chunks(n).peekable();
+    if let Some(first) = chunks.peek() {
+        for buf in first.iter() {
+            prefetch_bytes(buf.as_ref(), 1);
+        }
+    }
+    while let Some(chunk) = chunks.next() {
+        if let Some(next) = chunks.peek() {
+            for buf in next.iter() {
+                prefetch_bytes(buf.as_ref(), 1);
+            }
+        }
+        f(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_batch_visits_all_in_chunks() {
+        let bufs: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 100]).collect();
+        let mut sizes = Vec::new();
+        let mut seen = Vec::new();
+        for_each_batch(&bufs, 4, |chunk| {
+            sizes.push(chunk.len());
+            seen.extend(chunk.iter().map(|b| b[0]));
+        });
+        assert_eq!(sizes, vec![4, 4, 2]);
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+
+        for_each_batch(&[] as &[Vec<u8>], 4, |_| panic!("no chunks expected"));
+        // Hints never fault, even on empty or short slices
+        prefetch_bytes(&[], 4);
+        prefetch_bytes(&bufs[0], 8);
+        prefetch(&bufs);
+        assert!(likely(true) && !unlikely(false));
+    }
+}
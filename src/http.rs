@@ -0,0 +1,741 @@
+//! Minimal HTTP/1.1 server engine on the protocol driver
+//!
+//! Control planes, admin endpoints, and webhooks need a little HTTP, and
+//! pulling in an async web stack for them is a heavy dependency for a
+//! socket crate user. This engine is small enough to read in one sitting
+//! and covers what such endpoints use:
+//!
+//! - request line and header parsing with size limits ([`parse_request`]),
+//!   rejecting ambiguous framing such as both `Content-Length` and
+//!   `Transfer-Encoding`, which request smuggling relies on
+//! - request bodies by `Content-Length` or `chunked` transfer coding
+//! - persistent connections (HTTP/1.1 by default, HTTP/1.0 with
+//!   `Connection: keep-alive`) and pipelined requests
+//! - chunked responses through [`Response::chunk`]
+//! - idle and slow-request timeouts
+//!
+//! [`HttpConnection`] is a [`Protocol`](crate::driver::Protocol), so it runs
+//! on a [`Driver`](crate::driver::Driver) next to other protocols.
+//! [`HttpServer`] bundles a listener and a driver for the common case.
+//! Handlers run on the event loop thread and should not block.
+//!
+//! There is no TLS, no HTTP/2, and no `Expect: 100-continue`; put a proxy
+//! in front for public traffic.
+//!
+//! Requires the `http` feature.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::http::{HttpServer, Request, Response};
+//! use horizon_sockets::NetConfig;
+//!
+//! let mut hits = 0u64;
+//! let mut server = HttpServer::bind("127.0.0.1:8081".parse()?, &NetConfig::default(), move || {
+//!     move |req: &Request| match (req.method.as_str(), req.path()) {
+//!         ("GET", "/status") => Response::ok("up\n"),
+//!         ("POST", "/echo") => Response::ok(req.body.clone()).header("Content-Type", "application/octet-stream"),
+//!         _ => Response::new(404),
+//!     }
+//! })?;
+//! println!("listening on {}", server.local_addr());
+//! server.run()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::driver::{Context, Protocol};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Size and time limits for one connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HttpLimits {
+    /// Largest request line plus headers (default 8 KiB)
+    pub max_head: usize,
+    /// Largest decoded request body (default 1 MiB)
+    pub max_body: usize,
+    /// Time a connection may sit without a complete request (default 30 s)
+    pub idle_timeout: Duration,
+}
+
+impl Default for HttpLimits {
+    fn default() -> Self {
+        Self { max_head: 8 * 1024, max_body: 1024 * 1024, idle_timeout: Duration::from_secs(30) }
+    }
+}
+
+/// A request that cannot be served, with the status to answer it with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HttpError {
+    /// Response status, e.g. 400
+    pub status: u16,
+    /// Short explanation, sent as the response body
+    pub message: &'static str,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+fn reject(status: u16, message: &'static str) -> HttpError {
+    HttpError { status, message }
+}
+
+/// A parsed request
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Request {
+    /// Method, e.g. `GET`
+    pub method: String,
+    /// Request target as sent, including any query string
+    pub target: String,
+    /// Minor version: 0 for HTTP/1.0, 1 for HTTP/1.1
+    pub minor_version: u8,
+    /// Headers in order received, names as sent
+    pub headers: Vec<(String, String)>,
+    /// Decoded body
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the first header named `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the target without its query string
+    pub fn path(&self) -> &str {
+        self.target.split_once('?').map_or(&self.target, |(path, _)| path)
+    }
+
+    /// Returns the query string, without the `?`
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Returns `true` if the connection stays open after this request
+    pub fn keep_alive(&self) -> bool {
+        let connection = |token: &str| {
+            self.headers
+                .iter()
+                .filter(|(n, _)| n.eq_ignore_ascii_case("connection"))
+                .any(|(_, v)| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+        };
+        if self.minor_version == 0 { connection("keep-alive") } else { !connection("close") }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Parses one request from the start of `buf`
+///
+/// # Returns
+///
+/// The request and the number of bytes it occupied, or `None` if `buf`
+/// does not hold a complete request yet.
+///
+/// # Errors
+///
+/// An [`HttpError`] with the status to answer: 400 for malformed requests,
+/// 413 and 431 for requests over `limits`, 501 for unsupported transfer
+/// codings, and 505 for versions other than HTTP/1.x.
+pub fn parse_request(buf: &[u8], limits: &HttpLimits) -> Result<Option<(Request, usize)>, HttpError> {
+    let Some(head_end) = find(&buf[..buf.len().min(limits.max_head)], b"\r\n\r\n") else {
+        if buf.len() >= limits.max_head {
+            return Err(reject(431, "request head too large"));
+        }
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&buf[..head_end]).map_err(|_| reject(400, "request head is not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+
+    let line = lines.next().unwrap_or_default();
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(reject(400, "malformed request line"));
+    };
+    if !is_token(method) || target.is_empty() {
+        return Err(reject(400, "malformed request line"));
+    }
+    let minor_version = match version {
+        "HTTP/1.1" => 1,
+        "HTTP/1.0" => 0,
+        _ => return Err(reject(505, "only HTTP/1.x is supported")),
+    };
+
+    let mut headers = Vec::new();
+    for line in lines {
+        if line.starts_with([' ', '\t']) {
+            return Err(reject(400, "folded headers are not allowed"));
+        }
+        let (name, value) = line.split_once(':').ok_or(reject(400, "malformed header"))?;
+        if !is_token(name) {
+            return Err(reject(400, "malformed header name"));
+        }
+        headers.push((name.to_string(), value.trim_matches([' ', '\t']).to_string()));
+    }
+    let mut request = Request { method: method.to_string(), target: target.to_string(), minor_version, headers, body: Vec::new() };
+
+    let body_start = head_end + 4;
+    let chunked = match request.header("transfer-encoding") {
+        None => false,
+        Some(_) if request.header("content-length").is_some() => {
+            return Err(reject(400, "both Content-Length and Transfer-Encoding"));
+        }
+        Some(te) if te.rsplit(',').next().is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked")) && !te.contains(',') => true,
+        Some(_) => return Err(reject(501, "unsupported transfer coding")),
+    };
+    if chunked {
+        let Some((body, used)) = decode_chunked(&buf[body_start..], limits.max_body)? else {
+            return Ok(None);
+        };
+        request.body = body;
+        return Ok(Some((request, body_start + used)));
+    }
+
+    let mut length = None;
+    for (_, value) in request.headers.iter().filter(|(n, _)| n.eq_ignore_ascii_case("content-length")) {
+        let parsed: usize = value
+            .parse()
+            .ok()
+            .filter(|_| value.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or(reject(400, "invalid Content-Length"))?;
+        if length.is_some_and(|l| l != parsed) {
+            return Err(reject(400, "conflicting Content-Length"));
+        }
+        length = Some(parsed);
+    }
+    let length = length.unwrap_or(0);
+    if length > limits.max_body {
+        return Err(reject(413, "request body too large"));
+    }
+    let Some(body) = buf.get(body_start..body_start + length) else {
+        return Ok(None);
+    };
+    request.body = body.to_vec();
+    Ok(Some((request, body_start + length)))
+}
+
+/// Decodes a chunked body from the start of `buf`, returning it and the bytes used
+fn decode_chunked(buf: &[u8], max_body: usize) -> Result<Option<(Vec<u8>, usize)>, HttpError> {
+    let mut body = Vec::new();
+    let mut at = 0;
+    loop {
+        let Some(line_len) = find(&buf[at..], b"\r\n") else {
+            if buf.len() - at > 1024 {
+                return Err(reject(400, "chunk size line too long"));
+            }
+            return Ok(None);
+        };
+        let line = std::str::from_utf8(&buf[at..at + line_len]).map_err(|_| reject(400, "malformed chunk size"))?;
+        let hex = line.split(';').next().unwrap_or_default().trim();
+        if hex.is_empty() || hex.len() > 8 {
+            return Err(reject(400, "malformed chunk size"));
+        }
+        let size = usize::from_str_radix(hex, 16).map_err(|_| reject(400, "malformed chunk size"))?;
+        at += line_len + 2;
+        if size == 0 {
+            break;
+        }
+        if body.len() + size > max_body {
+            return Err(reject(413, "request body too large"));
+        }
+        let Some(data) = buf.get(at..at + size) else {
+            return Ok(None);
+        };
+        match buf.get(at + size..at + size + 2) {
+            None => return Ok(None),
+            Some(b"\r\n") => {}
+            Some(_) => return Err(reject(400, "chunk not followed by CRLF")),
+        }
+        body.extend_from_slice(data);
+        at += size + 2;
+    }
+    // Trailer fields, ignored, up to an empty line
+    loop {
+        let Some(line_len) = find(&buf[at..], b"\r\n") else {
+            return Ok(None);
+        };
+        at += line_len + 2;
+        if line_len == 0 {
+            return Ok(Some((body, at)));
+        }
+    }
+}
+
+/// Returns the standard reason phrase for `status`
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Body {
+    Full(Vec<u8>),
+    Chunked(Vec<Vec<u8>>),
+}
+
+/// A response built by a handler
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl Response {
+    /// Creates an empty response with `status`
+    pub fn new(status: u16) -> Self {
+        Self { status, headers: Vec::new(), body: Body::Full(Vec::new()) }
+    }
+
+    /// Creates a `200 OK` response with `body`
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200).body(body)
+    }
+
+    /// Adds a header; `Content-Length`, `Transfer-Encoding`, and `Connection` are set by the engine
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Replaces the body, sent with `Content-Length`
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Full(body.into());
+        self
+    }
+
+    /// Appends a chunk, switching the response to `chunked` transfer coding
+    ///
+    /// HTTP/1.0 clients get the chunks joined, with `Content-Length`.
+    pub fn chunk(mut self, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        if data.is_empty() {
+            return self;
+        }
+        match &mut self.body {
+            Body::Chunked(chunks) => chunks.push(data),
+            Body::Full(full) if full.is_empty() => self.body = Body::Chunked(vec![data]),
+            Body::Full(full) => self.body = Body::Chunked(vec![std::mem::take(full), data]),
+        }
+        self
+    }
+
+    /// Returns the status code
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Serializes the response for a client speaking HTTP/1.`minor_version`
+    ///
+    /// `head_only` omits the body, as for `HEAD` requests.
+    pub fn write_to(&self, out: &mut Vec<u8>, minor_version: u8, keep_alive: bool, head_only: bool) {
+        use std::io::Write as _;
+        let _ = write!(out, "HTTP/1.{} {} {}\r\n", minor_version.min(1), self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            let _ = write!(out, "{}: {}\r\n", name, value);
+        }
+        let no_body = (100..200).contains(&self.status) || self.status == 204 || self.status == 304;
+        let joined;
+        let body: &[u8] = match &self.body {
+            Body::Chunked(chunks) if minor_version >= 1 && !no_body => {
+                out.extend_from_slice(b"Transfer-Encoding: chunked\r\n");
+                out.extend_from_slice(if keep_alive { b"Connection: keep-alive\r\n\r\n" } else { b"Connection: close\r\n\r\n" });
+                if !head_only {
+                    for chunk in chunks {
+                        let _ = write!(out, "{:x}\r\n", chunk.len());
+                        out.extend_from_slice(chunk);
+                        out.extend_from_slice(b"\r\n");
+                    }
+                    out.extend_from_slice(b"0\r\n\r\n");
+                }
+                return;
+            }
+            Body::Chunked(chunks) => {
+                joined = chunks.concat();
+                &joined
+            }
+            Body::Full(body) => body,
+        };
+        if !no_body {
+            let _ = write!(out, "Content-Length: {}\r\n", body.len());
+        }
+        out.extend_from_slice(if keep_alive { b"Connection: keep-alive\r\n\r\n" } else { b"Connection: close\r\n\r\n" });
+        if !head_only && !no_body {
+            out.extend_from_slice(body);
+        }
+    }
+}
+
+/// Produces a response for each request
+pub trait Handler {
+    /// Answers `request`
+    fn handle(&mut self, request: &Request) -> Response;
+}
+
+impl<F: FnMut(&Request) -> Response> Handler for F {
+    fn handle(&mut self, request: &Request) -> Response {
+        self(request)
+    }
+}
+
+/// HTTP/1.1 server side of one connection, run by a [`Driver`](crate::driver::Driver)
+pub struct HttpConnection<H> {
+    handler: H,
+    limits: HttpLimits,
+    served: u64,
+}
+
+impl<H> fmt::Debug for HttpConnection<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpConnection").field("limits", &self.limits).field("served", &self.served).finish_non_exhaustive()
+    }
+}
+
+impl<H: Handler> HttpConnection<H> {
+    /// Serves requests on one connection with `handler`
+    pub fn new(handler: H, limits: HttpLimits) -> Self {
+        Self { handler, limits, served: 0 }
+    }
+
+    /// Returns the number of requests answered
+    pub fn served(&self) -> u64 {
+        self.served
+    }
+}
+
+impl<H: Handler> Protocol for HttpConnection<H> {
+    fn on_open(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        cx.set_timer(cx.now() + self.limits.idle_timeout);
+        Ok(())
+    }
+
+    fn on_readable(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        loop {
+            match parse_request(cx.input(), &self.limits) {
+                Ok(Some((request, used))) => {
+                    cx.consume(used);
+                    let response = self.handler.handle(&request);
+                    let keep_alive = request.keep_alive();
+                    let mut out = Vec::new();
+                    response.write_to(&mut out, request.minor_version, keep_alive, request.method == "HEAD");
+                    cx.write(&out);
+                    self.served += 1;
+                    if !keep_alive {
+                        cx.close();
+                        return Ok(());
+                    }
+                    cx.set_timer(cx.now() + self.limits.idle_timeout);
+                }
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    let mut out = Vec::new();
+                    Response::new(e.status).body(format!("{}\n", e.message)).write_to(&mut out, 1, false, false);
+                    cx.write(&out);
+                    cx.close();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn on_timer(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        // A partial request gets an answer; an idle keep-alive connection just closes
+        if !cx.input().is_empty() {
+            let mut out = Vec::new();
+            Response::new(408).write_to(&mut out, 1, false, false);
+            cx.write(&out);
+        }
+        cx.close();
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub use server::HttpServer;
+#[cfg(all(test, unix))]
+pub(crate) use server::exchange;
+
+#[cfg(unix)]
+mod server {
+    use super::{Handler, HttpConnection, HttpLimits};
+    use crate::driver::Driver;
+    use crate::tcp::{TcpListener, TcpStream};
+    use crate::{trace, NetConfig};
+    use mio::{Interest, Token};
+    use std::fmt;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    const LISTENER: Token = Token(0);
+
+    /// How soon accepting is retried after an error such as `EMFILE` left connections queued
+    const ACCEPT_RETRY: Duration = Duration::from_millis(50);
+
+    /// A listener and a [`Driver`] serving [`HttpConnection`]s (Unix)
+    pub struct HttpServer<F, H> {
+        listener: TcpListener,
+        driver: Driver<TcpStream, HttpConnection<H>>,
+        make: F,
+        limits: HttpLimits,
+        accept_errors: u64,
+        /// Accepting stopped on an error with connections possibly still queued
+        accept_pending: bool,
+    }
+
+    impl<F, H> fmt::Debug for HttpServer<F, H> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("HttpServer").field("listener", &self.listener).field("driver", &self.driver).finish_non_exhaustive()
+        }
+    }
+
+    impl<F: FnMut() -> H, H: Handler> HttpServer<F, H> {
+        /// Binds `addr`; `make` creates the handler for each accepted connection
+        pub fn bind(addr: SocketAddr, cfg: &NetConfig, make: F) -> io::Result<Self> {
+            let mut listener = TcpListener::bind(addr, cfg)?;
+            let limits = HttpLimits::default();
+            let driver = Driver::new()?.input_limit(limits.max_head + limits.max_body + 64 * 1024);
+            driver.registry().register(&mut listener, LISTENER, Interest::READABLE)?;
+            Ok(Self { listener, driver, make, limits, accept_errors: 0, accept_pending: false })
+        }
+
+        /// Applies `limits` to connections accepted from now on
+        pub fn limits(mut self, limits: HttpLimits) -> Self {
+            self.limits = limits;
+            self.driver = self.driver.input_limit(limits.max_head + limits.max_body + 64 * 1024);
+            self
+        }
+
+        /// Returns the bound address
+        pub fn local_addr(&self) -> SocketAddr {
+            self.listener.local_addr().expect("bound listener has an address")
+        }
+
+        /// Returns the number of open connections
+        pub fn connections(&self) -> usize {
+            self.driver.len()
+        }
+
+        /// Returns how many `accept` calls have failed, e.g. with `EMFILE`
+        pub fn accept_errors(&self) -> u64 {
+            self.accept_errors
+        }
+
+        /// Accepts connections and serves requests for up to `timeout`
+        ///
+        /// Accept failures do not end serving: they are counted in
+        /// [`accept_errors`](Self::accept_errors) and accepting is retried
+        /// shortly, since no new readiness event arrives for connections
+        /// already queued.
+        pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+            let timeout = match (self.accept_pending, timeout) {
+                (true, Some(t)) => Some(t.min(ACCEPT_RETRY)),
+                (true, None) => Some(ACCEPT_RETRY),
+                (false, t) => t,
+            };
+            let mut external = Vec::new();
+            self.driver.poll(timeout, &mut external)?;
+            if self.accept_pending || external.contains(&LISTENER) {
+                self.accept();
+            }
+            Ok(())
+        }
+
+        /// Accepts until the queue is empty or an error that would repeat, such as `EMFILE`
+        fn accept(&mut self) {
+            self.accept_pending = false;
+            for conn in self.listener.try_incoming() {
+                let stream = match conn {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        self.accept_errors += 1;
+                        trace::event!(warn, error = %e, "http accept failed");
+                        // The aborted connection is gone; the ones behind it can still be accepted
+                        if matches!(e.kind(), io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset | io::ErrorKind::Interrupted) {
+                            continue;
+                        }
+                        self.accept_pending = true;
+                        return;
+                    }
+                };
+                let handler = (self.make)();
+                // A failed registration affects only that connection
+                let _ = self.driver.add(stream, HttpConnection::new(handler, self.limits));
+            }
+        }
+
+        /// Serves forever
+        pub fn run(&mut self) -> io::Result<()> {
+            loop {
+                self.poll(None)?;
+            }
+        }
+    }
+
+    /// Sends `request` from another thread while polling `server`, returning the whole response
+    #[cfg(test)]
+    pub(crate) fn exchange<F: FnMut() -> H, H: Handler>(server: &mut HttpServer<F, H>, request: &[u8]) -> String {
+        use std::io::{Read, Write};
+
+        let addr = server.local_addr();
+        let request = request.to_vec();
+        let client = std::thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(&request).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        while !client.is_finished() {
+            server.poll(Some(Duration::from_millis(5))).unwrap();
+        }
+        client.join().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pipelined_and_chunked_requests() {
+        let limits = HttpLimits::default();
+        let raw = b"POST /upload?x=1 HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: t\r\n\r\nGET / HTTP/1.0\r\n\r\n";
+        let (first, used) = parse_request(raw, &limits).unwrap().unwrap();
+        assert_eq!((first.method.as_str(), first.path(), first.query()), ("POST", "/upload", Some("x=1")));
+        assert_eq!(first.body, b"hello world");
+        assert!(first.keep_alive());
+        let (second, rest) = parse_request(&raw[used..], &limits).unwrap().unwrap();
+        assert_eq!((second.minor_version, second.keep_alive(), used + rest), (0, false, raw.len()));
+
+        // Every prefix is incomplete, never an error
+        for len in 0..used {
+            assert_eq!(parse_request(&raw[..len], &limits), Ok(None), "prefix {}", len);
+        }
+        let status = |raw: &[u8]| parse_request(raw, &limits).err().map(|e| e.status);
+        assert_eq!(status(b"GET / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n"), Some(400));
+        assert_eq!(status(b"GET / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n"), Some(400));
+        assert_eq!(status(b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"), Some(501));
+        assert_eq!(status(b"GET / HTTP/2.0\r\n\r\n"), Some(505));
+        assert_eq!(status(&[b'a'; 9000]), Some(431));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_keeps_connection_alive_and_chunks() {
+        use crate::NetConfig;
+        use std::io::{Read, Write};
+        use std::time::Instant;
+
+        let mut server = HttpServer::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default(), || {
+            |req: &Request| match req.path() {
+                "/stream" => Response::new(200).chunk("ab").chunk("cde"),
+                _ => Response::ok(format!("{} {}", req.method, req.body.len())),
+            }
+        })
+        .unwrap();
+        let mut client = std::net::TcpStream::connect(server.local_addr()).unwrap();
+        client.set_nonblocking(true).unwrap();
+        client.write_all(b"POST /a HTTP/1.1\r\nContent-Length: 4\r\n\r\nbodyGET /stream HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+
+        let mut got = Vec::new();
+        let mut buf = [0u8; 1024];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            server.poll(Some(Duration::from_millis(10))).unwrap();
+            match client.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => got.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        let text = String::from_utf8(got).unwrap();
+        assert_eq!(
+            text,
+            "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: keep-alive\r\n\r\nPOST 4\
+             HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n2\r\nab\r\n3\r\ncde\r\n0\r\n\r\n"
+        );
+        server.poll(Some(Duration::from_millis(10))).unwrap();
+        assert_eq!(server.connections(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_rejects_malformed_request_and_closes() {
+        use crate::NetConfig;
+
+        let mut server = HttpServer::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default(), || |_: &Request| Response::ok("never"))
+            .unwrap();
+        let response = exchange(&mut server, b"GET / HTTP/2.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 505"), "{}", response);
+        assert!(!response.contains("never"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_idle_timeout_bounds_a_trickled_request() {
+        use crate::NetConfig;
+        use std::io::{Read, Write};
+        use std::time::Instant;
+
+        let limits = HttpLimits { idle_timeout: Duration::from_millis(200), ..HttpLimits::default() };
+        let mut server = HttpServer::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default(), || |_: &Request| Response::ok("late"))
+            .unwrap()
+            .limits(limits);
+        let mut client = std::net::TcpStream::connect(server.local_addr()).unwrap();
+        client.set_nonblocking(true).unwrap();
+
+        // Every byte arrives well within the timeout, the request as a whole does not
+        let start = Instant::now();
+        let mut got = Vec::new();
+        let mut buf = [0u8; 256];
+        for byte in b"GET / HTTP/1.1\r\nX-Slow: ".iter().cycle() {
+            let _ = client.write(&[*byte]);
+            server.poll(Some(Duration::from_millis(20))).unwrap();
+            match client.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => got.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => break,
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "trickled request was never cut off");
+        }
+        assert!(String::from_utf8(got).unwrap().starts_with("HTTP/1.1 408"));
+    }
+}
@@ -0,0 +1,173 @@
+//! ICMP error reporting for UDP sockets
+//!
+//! UDP sends never fail because of what happens on the network, so by default
+//! an application learns nothing when its packets hit a closed port, a router
+//! whose TTL budget ran out, or a link with a smaller MTU. On Linux, enabling
+//! [`NetConfig::recv_err`](crate::NetConfig::recv_err) asks the kernel to queue
+//! these ICMP errors on the socket's error queue, and
+//! [`Udp::drain_errors`](crate::udp::Udp::drain_errors) returns them as
+//! [`IcmpError`] values.
+//!
+//! Typical uses are path MTU discovery (react to [`IcmpErrorKind::FragmentationNeeded`])
+//! and fast failure detection (drop a peer on [`IcmpErrorKind::PortUnreachable`]
+//! instead of waiting for a timeout).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::icmp::IcmpErrorKind;
+//!
+//! let cfg = NetConfig { recv_err: true, ..Default::default() };
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &cfg)?;
+//! socket.send_to(b"probe", "192.0.2.10:9000".parse()?)?;
+//!
+//! for err in socket.drain_errors()? {
+//!     match err.kind {
+//!         IcmpErrorKind::FragmentationNeeded { mtu } => println!("path MTU is {}", mtu),
+//!         IcmpErrorKind::PortUnreachable => println!("{:?} is not listening", err.destination),
+//!         other => println!("ICMP error {:?} from {:?}", other, err.offender),
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::net::SocketAddr;
+
+/// Classification of an error reported through the socket error queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IcmpErrorKind {
+    /// Destination port is closed (ICMP port unreachable)
+    PortUnreachable,
+    /// Destination host is unreachable
+    HostUnreachable,
+    /// Destination network is unreachable
+    NetUnreachable,
+    /// Packet exceeded the path MTU; `mtu` is the next-hop or local MTU
+    FragmentationNeeded {
+        /// Maximum packet size the path accepts, in bytes
+        mtu: u32,
+    },
+    /// TTL / hop limit reached zero in transit
+    TtlExceeded,
+    /// Any other ICMP or local error, with the raw type and code
+    Other {
+        /// ICMP type (or 0 for locally generated errors)
+        icmp_type: u8,
+        /// ICMP code (or 0 for locally generated errors)
+        icmp_code: u8,
+    },
+}
+
+/// A structured error drained from a UDP socket's error queue
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IcmpError {
+    /// What went wrong
+    pub kind: IcmpErrorKind,
+    /// The errno the kernel associates with the error (e.g. `ECONNREFUSED`)
+    pub errno: i32,
+    /// Destination of the packet that triggered the error
+    pub destination: Option<SocketAddr>,
+    /// Address of the node that generated the ICMP message, if known
+    pub offender: Option<SocketAddr>,
+    /// Leading bytes of the offending packet's payload
+    pub payload: Vec<u8>,
+}
+
+/// Maps raw `sock_extended_err` fields to an [`IcmpErrorKind`]
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn classify(origin: u8, icmp_type: u8, icmp_code: u8, info: u32, errno: i32) -> IcmpErrorKind {
+    // SO_EE_ORIGIN_* values from <linux/errqueue.h>
+    const ORIGIN_LOCAL: u8 = 1;
+    const ORIGIN_ICMP: u8 = 2;
+    const ORIGIN_ICMP6: u8 = 3;
+
+    match (origin, icmp_type, icmp_code) {
+        (ORIGIN_ICMP, 3, 3) | (ORIGIN_ICMP6, 1, 4) => IcmpErrorKind::PortUnreachable,
+        (ORIGIN_ICMP, 3, 1) | (ORIGIN_ICMP6, 1, 3) => IcmpErrorKind::HostUnreachable,
+        (ORIGIN_ICMP, 3, 0) | (ORIGIN_ICMP6, 1, 0) => IcmpErrorKind::NetUnreachable,
+        (ORIGIN_ICMP, 3, 4) | (ORIGIN_ICMP6, 2, _) => IcmpErrorKind::FragmentationNeeded { mtu: info },
+        (ORIGIN_ICMP, 11, _) | (ORIGIN_ICMP6, 3, _) => IcmpErrorKind::TtlExceeded,
+        (ORIGIN_LOCAL, _, _) if errno == libc::EMSGSIZE => IcmpErrorKind::FragmentationNeeded { mtu: info },
+        _ => IcmpErrorKind::Other { icmp_type, icmp_code },
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        use crate::raw as r;
+        use std::io;
+        use std::os::unix::io::RawFd;
+
+        /// Bytes of the offending payload kept per error
+        const PAYLOAD_LEN: usize = 256;
+
+        /// Reads every pending entry from the socket error queue
+        pub(crate) fn drain(fd: RawFd) -> io::Result<Vec<IcmpError>> {
+            let mut out = Vec::new();
+            loop {
+                let mut payload = [0u8; PAYLOAD_LEN];
+                let mut name: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+                // u64 storage keeps the control buffer aligned for cmsghdr
+                let mut control = [0u64; 64];
+                let mut iov = libc::iovec { iov_base: payload.as_mut_ptr() as *mut _, iov_len: payload.len() };
+                let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+                msg.msg_name = &mut name as *mut _ as *mut _;
+                msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as _;
+                msg.msg_iov = &mut iov;
+                msg.msg_iovlen = 1;
+                msg.msg_control = control.as_mut_ptr() as *mut _;
+                msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+                let rc = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        return Ok(out);
+                    }
+                    return Err(err);
+                }
+
+                let destination = r::from_sockaddr(&name).map(r::unmap_v4);
+                // SAFETY: the kernel wrote `msg_controllen` bytes of `control`
+                let control = unsafe { std::slice::from_raw_parts(control.as_ptr() as *const u8, msg.msg_controllen as _) };
+                for cmsg in crate::parse::cmsgs(control) {
+                    let is_err = (cmsg.level == libc::IPPROTO_IP && cmsg.kind == libc::IP_RECVERR)
+                        || (cmsg.level == libc::IPPROTO_IPV6 && cmsg.kind == libc::IPV6_RECVERR);
+                    let ee_len = std::mem::size_of::<libc::sock_extended_err>();
+                    if !is_err || cmsg.data.len() < ee_len {
+                        continue;
+                    }
+                    // SAFETY: length checked above; read_unaligned needs no alignment
+                    let ee = unsafe { std::ptr::read_unaligned(cmsg.data.as_ptr() as *const libc::sock_extended_err) };
+                    // The offender address follows the extended error (SO_EE_OFFENDER)
+                    let offender = crate::parse::sockaddr(&cmsg.data[ee_len..]).map(r::unmap_v4);
+                    let errno = ee.ee_errno as i32;
+                    out.push(IcmpError {
+                        kind: classify(ee.ee_origin, ee.ee_type, ee.ee_code, ee.ee_info, errno),
+                        errno,
+                        destination,
+                        offender,
+                        payload: payload[..(rc as usize).min(PAYLOAD_LEN)].to_vec(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_common_errors() {
+        assert_eq!(classify(2, 3, 3, 0, libc::ECONNREFUSED), IcmpErrorKind::PortUnreachable);
+        assert_eq!(classify(3, 1, 4, 0, libc::ECONNREFUSED), IcmpErrorKind::PortUnreachable);
+        assert_eq!(classify(2, 3, 4, 1400, libc::EMSGSIZE), IcmpErrorKind::FragmentationNeeded { mtu: 1400 });
+        assert_eq!(classify(3, 2, 0, 1280, libc::EMSGSIZE), IcmpErrorKind::FragmentationNeeded { mtu: 1280 });
+        assert_eq!(classify(2, 11, 0, 0, libc::EHOSTUNREACH), IcmpErrorKind::TtlExceeded);
+        assert_eq!(classify(1, 0, 0, 1500, libc::EMSGSIZE), IcmpErrorKind::FragmentationNeeded { mtu: 1500 });
+        assert_eq!(classify(2, 5, 1, 0, 0), IcmpErrorKind::Other { icmp_type: 5, icmp_code: 1 });
+    }
+}
@@ -0,0 +1,398 @@
+//! Native IOCP completion port for high-rate UDP servers on Windows
+//!
+//! mio drives Windows sockets through readiness emulated on top of AFD
+//! polling, so every readable event still costs one `recvfrom` per
+//! datagram. A [`UdpCompletionPort`] uses completion-based I/O instead:
+//! each registered socket keeps a fixed number of overlapped `WSARecvFrom`
+//! calls posted into buffers it owns, so the kernel copies datagrams in as
+//! they arrive. [`poll`](UdpCompletionPort::poll) harvests a batch of
+//! completions with one `GetQueuedCompletionStatusEx` call, hands each
+//! packet to a callback, and posts the buffer again. Sends are overlapped
+//! `WSASendTo`s from a pool of send buffers, reaped by the same poll.
+//!
+//! ICMP port-unreachable reports (`WSAECONNRESET` on UDP) are turned off on
+//! registered sockets, since they would otherwise fail posted receives.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::iocp::UdpCompletionPort;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use std::time::Duration;
+//!
+//! let mut port = UdpCompletionPort::new(64, 2048)?;
+//! port.register(Udp::bind("0.0.0.0:7777".parse()?, &NetConfig::default())?)?;
+//!
+//! let mut echoes = Vec::new();
+//! loop {
+//!     port.poll(Some(Duration::from_millis(10)), |token, packet, from| {
+//!         echoes.push((token, packet.to_vec(), from));
+//!     })?;
+//!     for (token, packet, to) in echoes.drain(..) {
+//!         port.send_to(token, &packet, to)?;
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::raw as r;
+use crate::udp::Udp;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::windows::io::AsRawSocket;
+use std::time::Duration;
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE, WAIT_TIMEOUT};
+use windows_sys::Win32::Networking::WinSock::{
+    AF_INET, AF_INET6, SIO_UDP_CONNRESET, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, SOCKADDR_STORAGE, SOCKET,
+    WSABUF, WSAGetLastError, WSAIoctl, WSARecvFrom, WSASendTo, WSA_IO_PENDING,
+};
+use windows_sys::Win32::System::IO::{
+    CancelIoEx, CreateIoCompletionPort, GetQueuedCompletionStatusEx, OVERLAPPED, OVERLAPPED_ENTRY,
+};
+
+/// Most completions harvested by one `GetQueuedCompletionStatusEx` call
+const HARVEST: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Recv,
+    Send,
+}
+
+/// One overlapped operation; the kernel owns it while it is in flight
+#[repr(C)]
+struct Op {
+    /// First field, so the `OVERLAPPED` pointer in a completion is the `Op`
+    overlapped: OVERLAPPED,
+    kind: Kind,
+    token: usize,
+    buf: Vec<u8>,
+    addr: SOCKADDR_STORAGE,
+    addr_len: i32,
+    flags: u32,
+}
+
+impl Op {
+    fn new(kind: Kind, token: usize, size: usize) -> Box<Self> {
+        Box::new(Self {
+            // SAFETY: both are plain C structs for which all zeroes is valid
+            overlapped: unsafe { std::mem::zeroed() },
+            kind,
+            token,
+            buf: vec![0; size],
+            addr: unsafe { std::mem::zeroed() },
+            addr_len: 0,
+            flags: 0,
+        })
+    }
+}
+
+/// Completion port with pre-posted overlapped receives on UDP sockets
+///
+/// Sockets are identified by the token returned from
+/// [`register`](Self::register) and stay registered until the port is
+/// dropped.
+pub struct UdpCompletionPort {
+    port: HANDLE,
+    sockets: Vec<Udp>,
+    recvs_per_socket: usize,
+    buffer_size: usize,
+    /// Boxed because ops are handed to the kernel by address
+    #[allow(clippy::vec_box)]
+    idle_sends: Vec<Box<Op>>,
+    /// Operations submitted to the kernel whose completions are not yet dequeued
+    in_flight: usize,
+    entries: Vec<OVERLAPPED_ENTRY>,
+}
+
+// SAFETY: the port handle and every in-flight buffer are owned by this value
+// and only touched through `&mut self`
+unsafe impl Send for UdpCompletionPort {}
+
+impl std::fmt::Debug for UdpCompletionPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UdpCompletionPort")
+            .field("sockets", &self.sockets.len())
+            .field("recvs_per_socket", &self.recvs_per_socket)
+            .field("buffer_size", &self.buffer_size)
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UdpCompletionPort {
+    /// Creates a port that keeps `recvs_per_socket` receives of `buffer_size` bytes posted per socket
+    ///
+    /// Datagrams longer than `buffer_size` are truncated. The posted
+    /// receives bound how many datagrams can be absorbed between polls
+    /// without falling back to the socket receive buffer.
+    pub fn new(recvs_per_socket: usize, buffer_size: usize) -> io::Result<Self> {
+        let port = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, std::ptr::null_mut(), 0, 1) };
+        if port.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            port,
+            sockets: Vec::new(),
+            recvs_per_socket: recvs_per_socket.max(1),
+            buffer_size: buffer_size.max(1),
+            idle_sends: Vec::new(),
+            in_flight: 0,
+            // SAFETY: all zeroes is a valid OVERLAPPED_ENTRY
+            entries: vec![unsafe { std::mem::zeroed() }; HARVEST],
+        })
+    }
+
+    /// Associates `socket` with the port and posts its receives, returning its token
+    ///
+    /// If posting fails the socket stays registered with the receives that
+    /// were posted; a socket cannot be detached from a completion port.
+    pub fn register(&mut self, socket: Udp) -> io::Result<usize> {
+        let raw = socket.as_raw_socket();
+        let token = self.sockets.len();
+        let off: u32 = 0;
+        let mut returned = 0u32;
+        // Best effort: without it an ICMP port unreachable fails a posted receive
+        unsafe {
+            WSAIoctl(
+                raw as SOCKET,
+                SIO_UDP_CONNRESET,
+                &off as *const u32 as *const _,
+                std::mem::size_of::<u32>() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+                None,
+            )
+        };
+        if unsafe { CreateIoCompletionPort(raw as HANDLE, self.port, token, 0) }.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        self.sockets.push(socket);
+        for _ in 0..self.recvs_per_socket {
+            self.post_recv(Op::new(Kind::Recv, token, self.buffer_size))?;
+        }
+        Ok(token)
+    }
+
+    /// Returns the socket registered under `token`
+    pub fn socket(&self, token: usize) -> Option<&Udp> {
+        self.sockets.get(token)
+    }
+
+    /// Returns the number of receives and sends the kernel has not completed
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Queues an overlapped send of `buf` to `addr` from socket `token`
+    ///
+    /// `buf` is copied into a pooled send buffer, which is recycled when the
+    /// send's completion is reaped by [`poll`](Self::poll).
+    pub fn send_to(&mut self, token: usize, buf: &[u8], addr: SocketAddr) -> io::Result<()> {
+        let raw = self
+            .sockets
+            .get(token)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown completion port token"))?
+            .as_raw_socket() as SOCKET;
+        let mut op = self.idle_sends.pop().unwrap_or_else(|| Op::new(Kind::Send, token, 0));
+        op.token = token;
+        op.buf.clear();
+        op.buf.extend_from_slice(buf);
+        let (_, sa, len) = r::to_sockaddr(addr);
+        let dst = &mut op.addr as *mut SOCKADDR_STORAGE;
+        // SAFETY: SOCKADDR_STORAGE is large and aligned enough for either address
+        unsafe {
+            match sa {
+                r::SockAddr::V4(s) => dst.cast::<SOCKADDR_IN>().write(s),
+                r::SockAddr::V6(s) => dst.cast::<SOCKADDR_IN6>().write(s),
+            }
+        }
+        op.addr_len = len;
+        // SAFETY: all zeroes is a valid OVERLAPPED
+        op.overlapped = unsafe { std::mem::zeroed() };
+        let op = Box::into_raw(op);
+        // SAFETY: `op` stays allocated until its completion is dequeued by poll or drop
+        let rc = unsafe {
+            let o = &mut *op;
+            let wsabuf = WSABUF { len: o.buf.len() as u32, buf: o.buf.as_mut_ptr() };
+            WSASendTo(
+                raw,
+                &wsabuf,
+                1,
+                std::ptr::null_mut(),
+                0,
+                &o.addr as *const SOCKADDR_STORAGE as *const SOCKADDR,
+                o.addr_len,
+                &mut o.overlapped,
+                None,
+            )
+        };
+        self.submitted(rc, op)
+    }
+
+    /// Waits up to `timeout` for completions and passes each received packet to `on_packet`
+    ///
+    /// `on_packet` gets the socket token, the payload, and the sender.
+    /// Completed sends are recycled and completed receives posted again
+    /// before returning. `None` waits indefinitely. Returns the number of
+    /// packets delivered, which is 0 on timeout.
+    pub fn poll(&mut self, timeout: Option<Duration>, mut on_packet: impl FnMut(usize, &[u8], SocketAddr)) -> io::Result<usize> {
+        let removed = self.dequeue(timeout)?;
+        let mut packets = 0;
+        let mut result = Ok(());
+        for i in 0..removed {
+            let entry = self.entries[i];
+            self.in_flight -= 1;
+            // SAFETY: every OVERLAPPED queued on this port is the first field of an Op leaked on submit
+            let op = unsafe { Box::from_raw(entry.lpOverlapped as *mut Op) };
+            match op.kind {
+                Kind::Send => self.idle_sends.push(op),
+                Kind::Recv => {
+                    // `Internal` holds the NTSTATUS. Errors have the top two bits set;
+                    // warnings such as STATUS_BUFFER_OVERFLOW still carry a truncated datagram.
+                    if (entry.Internal as u32) < 0xC000_0000 {
+                        if let Some(from) = from_storage(&op.addr) {
+                            on_packet(op.token, &op.buf[..entry.dwNumberOfBytesTransferred as usize], from);
+                            packets += 1;
+                        }
+                    }
+                    if let Err(e) = self.post_recv(op) {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result.map(|()| packets)
+    }
+
+    fn post_recv(&mut self, op: Box<Op>) -> io::Result<()> {
+        let raw = self.sockets[op.token].as_raw_socket() as SOCKET;
+        let op = Box::into_raw(op);
+        // SAFETY: `op` stays allocated until its completion is dequeued by poll or drop
+        let rc = unsafe {
+            let o = &mut *op;
+            o.overlapped = std::mem::zeroed();
+            o.flags = 0;
+            o.addr_len = std::mem::size_of::<SOCKADDR_STORAGE>() as i32;
+            let wsabuf = WSABUF { len: o.buf.len() as u32, buf: o.buf.as_mut_ptr() };
+            WSARecvFrom(
+                raw,
+                &wsabuf,
+                1,
+                std::ptr::null_mut(),
+                &mut o.flags,
+                &mut o.addr as *mut SOCKADDR_STORAGE as *mut SOCKADDR,
+                &mut o.addr_len,
+                &mut o.overlapped,
+                None,
+            )
+        };
+        self.submitted(rc, op)
+    }
+
+    /// Accounts for a submitted operation, reclaiming it if the submission failed
+    fn submitted(&mut self, rc: i32, op: *mut Op) -> io::Result<()> {
+        // Without FILE_SKIP_COMPLETION_PORT_ON_SUCCESS even immediate success is queued
+        let code = if rc == 0 { 0 } else { unsafe { WSAGetLastError() } };
+        if code == 0 || code == WSA_IO_PENDING {
+            self.in_flight += 1;
+            return Ok(());
+        }
+        // SAFETY: the kernel rejected the operation, so nothing else refers to it
+        let op = unsafe { Box::from_raw(op) };
+        if op.kind == Kind::Send {
+            self.idle_sends.push(op);
+        }
+        Err(io::Error::from_raw_os_error(code))
+    }
+
+    /// Dequeues up to `HARVEST` completions into `entries`, returning how many
+    fn dequeue(&mut self, timeout: Option<Duration>) -> io::Result<usize> {
+        let ms = timeout.map_or(u32::MAX, |t| t.as_millis().min(u32::MAX as u128 - 1) as u32);
+        let mut removed = 0u32;
+        let ok = unsafe {
+            GetQueuedCompletionStatusEx(self.port, self.entries.as_mut_ptr(), self.entries.len() as u32, &mut removed, ms, 0)
+        };
+        if ok == 0 {
+            let err = io::Error::last_os_error();
+            return if err.raw_os_error() == Some(WAIT_TIMEOUT as i32) { Ok(0) } else { Err(err) };
+        }
+        Ok(removed as usize)
+    }
+}
+
+impl Drop for UdpCompletionPort {
+    fn drop(&mut self) {
+        for socket in &self.sockets {
+            unsafe { CancelIoEx(socket.as_raw_socket() as HANDLE, std::ptr::null()) };
+        }
+        // Buffers may only be freed once the kernel has finished with them
+        while self.in_flight > 0 {
+            let Ok(removed) = self.dequeue(Some(Duration::from_secs(1))) else { break };
+            if removed == 0 {
+                break;
+            }
+            for i in 0..removed {
+                self.in_flight -= 1;
+                drop(unsafe { Box::from_raw(self.entries[i].lpOverlapped as *mut Op) });
+            }
+        }
+        // Anything still in flight is leaked rather than freed under the kernel
+        unsafe { CloseHandle(self.port) };
+    }
+}
+
+fn from_storage(ss: &SOCKADDR_STORAGE) -> Option<SocketAddr> {
+    match ss.ss_family {
+        AF_INET => {
+            let sin = unsafe { &*(ss as *const SOCKADDR_STORAGE as *const SOCKADDR_IN) };
+            let ip = Ipv4Addr::from(unsafe { sin.sin_addr.S_un.S_addr }.to_ne_bytes());
+            Some(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(sin.sin_port))))
+        }
+        AF_INET6 => {
+            let sin6 = unsafe { &*(ss as *const SOCKADDR_STORAGE as *const SOCKADDR_IN6) };
+            let ip = Ipv6Addr::from(unsafe { sin6.sin6_addr.u.Byte });
+            let scope = unsafe { sin6.Anonymous.sin6_scope_id };
+            Some(SocketAddr::V6(SocketAddrV6::new(ip, u16::from_be(sin6.sin6_port), u32::from_be(sin6.sin6_flowinfo), scope)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+
+    #[test]
+    fn test_posted_receives_deliver_and_sends_are_reaped() {
+        let cfg = NetConfig::default();
+        let mut port = UdpCompletionPort::new(4, 1500).unwrap();
+        let server = port.register(Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap()).unwrap();
+        let server_addr = port.socket(server).unwrap().local_addr().unwrap();
+        assert_eq!(port.in_flight(), 4);
+
+        let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        for i in 0..6u8 {
+            client.send_to(&[i; 10], server_addr).unwrap();
+        }
+        let mut got = Vec::new();
+        while got.len() < 6 {
+            port.poll(Some(Duration::from_secs(1)), |token, packet, from| got.push((token, packet.to_vec(), from))).unwrap();
+        }
+        assert!(got.iter().all(|(token, _, from)| *token == server && *from == client.local_addr().unwrap()));
+        assert_eq!(got[0].1, vec![0; 10]);
+        // Every receive is posted again after delivery
+        assert_eq!(port.in_flight(), 4);
+
+        port.send_to(server, b"reply", client.local_addr().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        let (n, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"reply");
+        while port.in_flight() > 4 {
+            port.poll(Some(Duration::from_millis(100)), |_, _, _| {}).unwrap();
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Receive-side jitter buffer for media playout
+//!
+//! Voice and video packets leave the sender evenly spaced and arrive
+//! bunched, late, and out of order. Playing them as they arrive produces
+//! gaps and stutter. A jitter buffer holds each packet for a short delay
+//! and releases it at its sender timestamp plus that delay, back in
+//! sequence order and with the original spacing.
+//!
+//! [`JitterBuffer`] schedules every packet at
+//! `media time + fastest observed transit + target delay`. Packets that
+//! arrive after a later one has been played are counted as late and
+//! dropped; missing sequence numbers are reported on the next [`Frame`]
+//! as [`lost_before`](Frame::lost_before) so a decoder can conceal them.
+//!
+//! The delay is fixed by default. In [`adaptive`](JitterBuffer::adaptive)
+//! mode it follows four times the interarrival jitter estimate of
+//! RFC 3550 (section 6.4.1), within bounds. A quiet network then gets low
+//! latency and a noisy one gets fewer late drops.
+//!
+//! The API follows [`Heartbeats`](crate::heartbeat::Heartbeats): time is
+//! passed in and [`next_deadline`](JitterBuffer::next_deadline) bounds the
+//! event loop's wait, alongside the heartbeat wheel's own deadline.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::jitter::JitterBuffer;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use std::time::{Duration, Instant};
+//!
+//! let socket = Udp::bind("0.0.0.0:5004".parse()?, &NetConfig::default())?;
+//! let mut jitter = JitterBuffer::new(Duration::from_millis(40))
+//!     .adaptive(Duration::from_millis(20), Duration::from_millis(200));
+//! let mut pkt = [0u8; 1500];
+//!
+//! loop {
+//!     // Wait for readability up to jitter.next_deadline(), e.g. with poll::wait
+//!     let now = Instant::now();
+//!     while let Ok((n, _)) = socket.socket().recv_from(&mut pkt) {
+//!         // 8-byte sequence number, 8-byte sender timestamp in µs, then audio
+//!         let seq = u64::from_be_bytes(pkt[..8].try_into()?);
+//!         let media = Duration::from_micros(u64::from_be_bytes(pkt[8..16].try_into()?));
+//!         jitter.push(seq, media, pkt[16..n].to_vec(), now);
+//!     }
+//!     jitter.poll(now, |frame| {
+//!         // ... conceal frame.lost_before missing frames, then decode frame.payload ...
+//!     });
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::trace;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// A packet released for playout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Sequence number given to [`push`](JitterBuffer::push)
+    pub seq: u64,
+    /// Sender timestamp given to [`push`](JitterBuffer::push)
+    pub media_time: Duration,
+    /// Packet contents
+    pub payload: Vec<u8>,
+    /// Sequence numbers skipped between the previous frame and this one, including overflow drops
+    pub lost_before: u64,
+}
+
+/// Counters for a [`JitterBuffer`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JitterStats {
+    /// Packets accepted into the buffer
+    pub received: u64,
+    /// Frames released for playout
+    pub played: u64,
+    /// Packets dropped because a later one had already been played
+    pub late: u64,
+    /// Packets dropped because their sequence number was already buffered
+    pub duplicate: u64,
+    /// Sequence numbers never played, including overflow drops; the sum of every frame's `lost_before`
+    pub lost: u64,
+    /// Packets dropped because the buffer was full, also counted in `lost`
+    pub overflow: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    media_time: Duration,
+    payload: Vec<u8>,
+}
+
+/// Reorders datagrams and releases them at their sender spacing plus a target delay
+#[derive(Debug)]
+pub struct JitterBuffer {
+    delay: Duration,
+    /// `(min, max)` delay bounds in adaptive mode
+    adaptive: Option<(Duration, Duration)>,
+    capacity: usize,
+    packets: BTreeMap<u64, Entry>,
+    /// Origin for transit times, set by the first packet
+    start: Option<Instant>,
+    /// Smallest `arrival - media_time` seen, in nanoseconds since `start`
+    min_transit: i128,
+    last_transit: Option<i128>,
+    /// RFC 3550 interarrival jitter, in nanoseconds
+    jitter: f64,
+    last_played: Option<u64>,
+    /// Overflow drops not yet reported in a frame's `lost_before`
+    unreported: u64,
+    stats: JitterStats,
+}
+
+impl JitterBuffer {
+    /// Creates a buffer holding packets for a fixed `delay` beyond the fastest transit
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            adaptive: None,
+            capacity: 256,
+            packets: BTreeMap::new(),
+            start: None,
+            min_transit: i128::MAX,
+            last_transit: None,
+            jitter: 0.0,
+            last_played: None,
+            unreported: 0,
+            stats: JitterStats::default(),
+        }
+    }
+
+    /// Lets the delay follow four times the measured jitter, between `min` and `max`
+    ///
+    /// The delay given to [`new`](Self::new) is used until jitter has been
+    /// measured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn adaptive(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "minimum delay must not exceed the maximum");
+        self.adaptive = Some((min, max));
+        self.delay = self.delay.clamp(min, max);
+        self
+    }
+
+    /// Sets how many packets may wait at once (default 256); the oldest is dropped beyond it
+    pub fn capacity(mut self, packets: usize) -> Self {
+        self.capacity = packets.max(1);
+        self
+    }
+
+    /// Buffers a packet received at `now`
+    ///
+    /// `media_time` is the sender's timestamp for the packet, relative to
+    /// any fixed origin, e.g. an RTP timestamp divided by the clock rate.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the packet was dropped as late or duplicate
+    pub fn push(&mut self, seq: u64, media_time: Duration, payload: Vec<u8>, now: Instant) -> bool {
+        if self.last_played.is_some_and(|last| seq <= last) {
+            self.stats.late += 1;
+            trace::event!(trace, seq, "jitter buffer late packet");
+            return false;
+        }
+        if self.packets.contains_key(&seq) {
+            self.stats.duplicate += 1;
+            return false;
+        }
+
+        let start = *self.start.get_or_insert(now);
+        let transit = now.saturating_duration_since(start).as_nanos() as i128 - media_time.as_nanos() as i128;
+        self.min_transit = self.min_transit.min(transit);
+        if let Some(last) = self.last_transit {
+            let d = (transit - last).unsigned_abs() as f64;
+            self.jitter += (d - self.jitter) / 16.0;
+            if let Some((min, max)) = self.adaptive {
+                self.delay = Duration::from_nanos((self.jitter * 4.0) as u64).clamp(min, max);
+            }
+        }
+        self.last_transit = Some(transit);
+
+        self.packets.insert(seq, Entry { media_time, payload });
+        self.stats.received += 1;
+        if self.packets.len() > self.capacity {
+            if let Some((oldest, _)) = self.packets.pop_first() {
+                self.unreported += self.skip_to(oldest) + 1;
+                self.stats.lost += 1;
+                self.stats.overflow += 1;
+            }
+        }
+        true
+    }
+
+    /// Returns when the next buffered packet is due for playout
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.packets.values().next().map(|e| self.due(e.media_time))
+    }
+
+    /// Releases every packet due at `now`, in sequence order
+    pub fn poll<F: FnMut(Frame)>(&mut self, now: Instant, mut on_frame: F) {
+        while let Some(frame) = self.pop(now) {
+            on_frame(frame);
+        }
+    }
+
+    /// Releases the next packet if it is due at `now`
+    pub fn pop(&mut self, now: Instant) -> Option<Frame> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        let (seq, Entry { media_time, payload }) = self.packets.pop_first()?;
+        let lost_before = self.skip_to(seq) + std::mem::take(&mut self.unreported);
+        self.stats.played += 1;
+        Some(Frame { seq, media_time, payload, lost_before })
+    }
+
+    /// Returns the current target delay
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns the interarrival jitter estimate
+    pub fn jitter(&self) -> Duration {
+        Duration::from_nanos(self.jitter as u64)
+    }
+
+    /// Returns the number of buffered packets
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Returns `true` if no packets are buffered
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Returns the counters
+    pub fn stats(&self) -> JitterStats {
+        self.stats
+    }
+
+    /// Forgets all packets and timing, e.g. when the sender restarts its stream
+    pub fn reset(&mut self) {
+        let (delay, adaptive, capacity, stats) = (self.delay, self.adaptive, self.capacity, self.stats);
+        *self = Self { delay, adaptive, capacity, stats, ..Self::new(delay) };
+    }
+
+    /// Playout time for a packet with sender timestamp `media_time`
+    fn due(&self, media_time: Duration) -> Instant {
+        let start = self.start.expect("packets imply a start time");
+        let offset = media_time.as_nanos() as i128 + self.min_transit + self.delay.as_nanos() as i128;
+        start + Duration::from_nanos(offset.max(0) as u64)
+    }
+
+    /// Marks `seq` as played, returning and counting the sequence numbers skipped to reach it
+    fn skip_to(&mut self, seq: u64) -> u64 {
+        let skipped = self.last_played.map_or(0, |last| seq - last - 1);
+        self.stats.lost += skipped;
+        self.last_played = Some(seq);
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_reorders_and_restores_sender_spacing() {
+        let t0 = Instant::now();
+        let mut jb = JitterBuffer::new(30 * MS);
+        // 20ms frames; frame 1 is delayed past frame 2, frame 3 never arrives
+        assert!(jb.push(0, Duration::ZERO, b"f0".to_vec(), t0 + 5 * MS));
+        assert!(jb.push(2, 40 * MS, b"f2".to_vec(), t0 + 46 * MS));
+        assert!(jb.push(1, 20 * MS, b"f1".to_vec(), t0 + 50 * MS));
+        assert!(!jb.push(1, 20 * MS, b"f1".to_vec(), t0 + 51 * MS));
+        assert!(jb.push(4, 80 * MS, b"f4".to_vec(), t0 + 86 * MS));
+
+        // Fastest transit is 5ms, so frame n plays at 5 + 20n + 30 ms
+        assert_eq!(jb.next_deadline(), Some(t0 + 35 * MS));
+        assert_eq!(jb.pop(t0 + 34 * MS), None);
+        let mut out = Vec::new();
+        jb.poll(t0 + 75 * MS, |f| out.push((f.seq, f.lost_before)));
+        assert_eq!(out, [(0, 0), (1, 0), (2, 0)]);
+        let f4 = jb.pop(t0 + 115 * MS).unwrap();
+        assert_eq!((f4.seq, f4.lost_before, f4.payload.as_slice()), (4, 1, &b"f4"[..]));
+
+        // Frame 3 arriving now is too late to play
+        assert!(!jb.push(3, 60 * MS, b"f3".to_vec(), t0 + 120 * MS));
+        let stats = jb.stats();
+        assert_eq!((stats.played, stats.late, stats.duplicate, stats.lost), (4, 1, 1, 1));
+
+        // Overflow drops are reported as losses on the next frame
+        let mut small = JitterBuffer::new(30 * MS).capacity(2);
+        for seq in 0..3u64 {
+            small.push(seq, seq as u32 * 20 * MS, Vec::new(), t0);
+        }
+        assert_eq!(small.pop(t0 + Duration::from_secs(1)).map(|f| (f.seq, f.lost_before)), Some((1, 1)));
+        assert_eq!((small.stats().lost, small.stats().overflow), (1, 1));
+    }
+
+    #[test]
+    fn test_adaptive_delay_follows_jitter() {
+        let t0 = Instant::now();
+        let mut jb = JitterBuffer::new(50 * MS).adaptive(10 * MS, 100 * MS);
+        for seq in 0..50u64 {
+            jb.push(seq, seq as u32 * 20 * MS, Vec::new(), t0 + seq as u32 * 20 * MS);
+        }
+        assert_eq!(jb.delay(), 10 * MS);
+
+        // Alternating 0 and 30ms of extra transit
+        for seq in 50..150u64 {
+            let extra = if seq % 2 == 0 { Duration::ZERO } else { 30 * MS };
+            jb.push(seq, seq as u32 * 20 * MS, Vec::new(), t0 + seq as u32 * 20 * MS + extra);
+            jb.poll(t0 + seq as u32 * 20 * MS, |_| {});
+        }
+        assert!(jb.jitter() > 20 * MS, "{:?}", jb.jitter());
+        assert_eq!(jb.delay(), 100 * MS);
+    }
+}
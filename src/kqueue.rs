@@ -0,0 +1,342 @@
+//! kqueue send-buffer and low-watermark notifications for BSD and macOS
+//!
+//! mio registers sockets with plain `EVFILT_READ`/`EVFILT_WRITE`, so a
+//! socket is readable after one byte arrives and writable after one byte
+//! of send buffer frees up. kqueue can do better, and a [`Watcher`]
+//! exposes it:
+//!
+//! - [`watch_send_empty`](Watcher::watch_send_empty): fires once everything
+//!   written has left the send buffer (`EVFILT_EMPTY` on FreeBSD and
+//!   NetBSD), for flushing before a close or measuring drain time without
+//!   polling `SO_NWRITE`-style counters
+//! - [`watch_send_lowat`](Watcher::watch_send_lowat): writable only once at
+//!   least `bytes` of buffer space is free (`EVFILT_WRITE` with `NOTE_LOWAT`),
+//!   so a whole frame can be written in one call
+//! - [`watch_recv_lowat`](Watcher::watch_recv_lowat): readable only once at
+//!   least `bytes` are queued (`EVFILT_READ` with `NOTE_LOWAT`), so a
+//!   fixed-size header is never read in pieces
+//!
+//! The watcher owns its own kqueue. Its descriptor becomes readable when
+//! any watch fires, so it registers with the [`Runtime`](crate::Runtime)
+//! like a socket; on that token, call [`read`](Watcher::read) to collect
+//! the [`KqueueEvent`]s. Watches are edge-triggered (`EV_CLEAR`), matching
+//! mio: each fires again only after its condition has been false.
+//!
+//! On macOS, iOS, DragonFly, and OpenBSD, which lack `EVFILT_EMPTY`,
+//! [`watch_send_empty`](Watcher::watch_send_empty) falls back to a write
+//! low watermark equal to the socket's `SO_SNDBUF`, which the kernel caps
+//! at the buffer's high watermark: it fires when the buffer has fully
+//! drained into the stack, though TCP may still hold unacknowledged data.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::kqueue::{Watch, Watcher};
+//! use std::net::TcpStream;
+//!
+//! let stream = TcpStream::connect("127.0.0.1:9000")?;
+//! let mut watcher = Watcher::new()?;
+//! // Wake only when a full 16-byte header is available
+//! watcher.watch_recv_lowat(&stream, 16, 7)?;
+//!
+//! let mut events = Vec::new();
+//! watcher.wait(&mut events, None)?;
+//! assert_eq!(events[0].token, 7);
+//! assert_eq!(events[0].watch, Watch::RecvLowat);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::poll::{self, Interest, Pollable};
+use crate::raw::OsSocket;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+/// Kind of condition a [`KqueueEvent`] reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// The send buffer has drained
+    SendEmpty,
+    /// At least the requested send buffer space is free
+    SendLowat,
+    /// At least the requested number of bytes can be read
+    RecvLowat,
+}
+
+/// A fired watch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KqueueEvent {
+    /// Token the socket was watched with
+    pub token: usize,
+    /// Condition that fired
+    pub watch: Watch,
+    /// Bytes readable for [`Watch::RecvLowat`], free send space for
+    /// [`Watch::SendLowat`] and the fallback [`Watch::SendEmpty`]
+    pub bytes: usize,
+    /// The peer shut down its side (`EV_EOF`)
+    pub eof: bool,
+}
+
+/// Private kqueue delivering send-empty and low-watermark events
+#[derive(Debug)]
+pub struct Watcher {
+    kq: OwnedFd,
+    /// The send watch registered on each socket descriptor, to delete the
+    /// right filter and, where both use `EVFILT_WRITE`, to tell
+    /// [`Watch::SendEmpty`] apart from [`Watch::SendLowat`]
+    send: Vec<(OsSocket, Watch)>,
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "freebsd", target_os = "netbsd"))] {
+        const HAS_EVFILT_EMPTY: bool = true;
+        const EVFILT_EMPTY: i64 = libc::EVFILT_EMPTY as i64;
+    } else {
+        const HAS_EVFILT_EMPTY: bool = false;
+        const EVFILT_EMPTY: i64 = i64::MIN;
+    }
+}
+
+impl Watcher {
+    /// Creates a watcher with its own close-on-exec kqueue
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: kqueue returned a new descriptor that nothing else owns
+        let kq = unsafe { OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { kq, send: Vec::new() })
+    }
+
+    /// Fires `token` once the socket's send buffer has drained
+    ///
+    /// Replaces any [`watch_send_lowat`](Self::watch_send_lowat) on the
+    /// same socket.
+    pub fn watch_send_empty(&mut self, socket: &impl AsRawFd, token: usize) -> io::Result<()> {
+        let fd = socket.as_raw_fd();
+        if HAS_EVFILT_EMPTY {
+            self.change(fd, EVFILT_EMPTY, libc::EV_ADD as u32 | libc::EV_CLEAR as u32, 0, 0, token)?;
+            if self.send_watch(fd) == Some(Watch::SendLowat) {
+                self.delete(fd, libc::EVFILT_WRITE as i64)?;
+            }
+        } else {
+            let size = crate::raw::get_send_buffer(fd)?.max(1) as i64;
+            self.change(fd, libc::EVFILT_WRITE as i64, libc::EV_ADD as u32 | libc::EV_CLEAR as u32, libc::NOTE_LOWAT, size, token)?;
+        }
+        self.set_send_watch(fd, Some(Watch::SendEmpty));
+        Ok(())
+    }
+
+    /// Fires `token` once at least `bytes` of send buffer space is free
+    ///
+    /// Replaces any [`watch_send_empty`](Self::watch_send_empty) on the
+    /// same socket.
+    pub fn watch_send_lowat(&mut self, socket: &impl AsRawFd, bytes: usize, token: usize) -> io::Result<()> {
+        let fd = socket.as_raw_fd();
+        self.change(fd, libc::EVFILT_WRITE as i64, libc::EV_ADD as u32 | libc::EV_CLEAR as u32, libc::NOTE_LOWAT, bytes.max(1) as i64, token)?;
+        if HAS_EVFILT_EMPTY && self.send_watch(fd) == Some(Watch::SendEmpty) {
+            self.delete(fd, EVFILT_EMPTY)?;
+        }
+        self.set_send_watch(fd, Some(Watch::SendLowat));
+        Ok(())
+    }
+
+    /// Fires `token` once at least `bytes` can be read from the socket
+    ///
+    /// End of stream and socket errors fire the watch regardless of `bytes`.
+    pub fn watch_recv_lowat(&mut self, socket: &impl AsRawFd, bytes: usize, token: usize) -> io::Result<()> {
+        let fd = socket.as_raw_fd();
+        self.change(fd, libc::EVFILT_READ as i64, libc::EV_ADD as u32 | libc::EV_CLEAR as u32, libc::NOTE_LOWAT, bytes.max(1) as i64, token)
+    }
+
+    /// Removes the socket's send watches
+    pub fn unwatch_send(&mut self, socket: &impl AsRawFd) -> io::Result<()> {
+        let fd = socket.as_raw_fd();
+        self.set_send_watch(fd, None);
+        // Delete every send filter, so nothing is left behind whatever was registered
+        let mut found = self.delete(fd, libc::EVFILT_WRITE as i64)?;
+        if HAS_EVFILT_EMPTY {
+            found |= self.delete(fd, EVFILT_EMPTY)?;
+        }
+        if !found {
+            return Err(io::Error::from_raw_os_error(libc::ENOENT));
+        }
+        Ok(())
+    }
+
+    /// Removes the socket's receive watch
+    pub fn unwatch_recv(&mut self, socket: &impl AsRawFd) -> io::Result<()> {
+        self.change(socket.as_raw_fd(), libc::EVFILT_READ as i64, libc::EV_DELETE as u32, 0, 0, 0)
+    }
+
+    /// Appends fired watches to `events` without blocking
+    ///
+    /// # Returns
+    ///
+    /// The number of events appended; `0` if none were pending
+    pub fn read(&mut self, events: &mut Vec<KqueueEvent>) -> io::Result<usize> {
+        self.collect(events, Some(Duration::ZERO))
+    }
+
+    /// Blocks until a watch fires or `timeout` passes, then reads events
+    ///
+    /// For threads without an event loop; event loops should register the
+    /// watcher instead.
+    pub fn wait(&mut self, events: &mut Vec<KqueueEvent>, timeout: Option<Duration>) -> io::Result<usize> {
+        let ready = poll::wait(&[&*self], Interest::READABLE, timeout)?;
+        if !ready[0].is_ready() {
+            return Ok(0);
+        }
+        self.read(events)
+    }
+
+    fn send_watch(&self, fd: OsSocket) -> Option<Watch> {
+        self.send.iter().find(|(s, _)| *s == fd).map(|(_, w)| *w)
+    }
+
+    fn set_send_watch(&mut self, fd: OsSocket, watch: Option<Watch>) {
+        self.send.retain(|(s, _)| *s != fd);
+        if let Some(watch) = watch {
+            self.send.push((fd, watch));
+        }
+    }
+
+    /// Deletes one filter, returning `false` if it was not registered
+    fn delete(&self, fd: OsSocket, filter: i64) -> io::Result<bool> {
+        match self.change(fd, filter, libc::EV_DELETE as u32, 0, 0, 0) {
+            Ok(()) => Ok(true),
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn change(&self, fd: OsSocket, filter: i64, flags: u32, fflags: u32, data: i64, token: usize) -> io::Result<()> {
+        // SAFETY: kevent is plain old data; zero is a valid value for every field
+        let mut ev: libc::kevent = unsafe { std::mem::zeroed() };
+        ev.ident = fd as _;
+        ev.filter = filter as _;
+        ev.flags = flags as _;
+        ev.fflags = fflags as _;
+        ev.data = data as _;
+        ev.udata = token as _;
+        let rc = unsafe { libc::kevent(self.kq.as_raw_fd(), &ev, 1, std::ptr::null_mut(), 0, std::ptr::null()) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn collect(&self, events: &mut Vec<KqueueEvent>, timeout: Option<Duration>) -> io::Result<usize> {
+        let ts = timeout.map(|t| libc::timespec { tv_sec: t.as_secs() as _, tv_nsec: t.subsec_nanos() as _ });
+        let ts_ptr = ts.as_ref().map_or(std::ptr::null(), |t| t as *const libc::timespec);
+        // SAFETY: as above
+        let mut raw: [libc::kevent; 64] = unsafe { std::mem::zeroed() };
+        let n = unsafe { libc::kevent(self.kq.as_raw_fd(), std::ptr::null(), 0, raw.as_mut_ptr(), raw.len() as _, ts_ptr) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for ev in &raw[..n as usize] {
+            let filter = ev.filter as i64;
+            let watch = if filter == libc::EVFILT_READ as i64 {
+                Watch::RecvLowat
+            } else if filter == EVFILT_EMPTY {
+                Watch::SendEmpty
+            } else if !HAS_EVFILT_EMPTY && self.send_watch(ev.ident as OsSocket) == Some(Watch::SendEmpty) {
+                // The fallback registers send-empty as EVFILT_WRITE too
+                Watch::SendEmpty
+            } else {
+                Watch::SendLowat
+            };
+            events.push(KqueueEvent {
+                token: ev.udata as usize,
+                watch,
+                bytes: ev.data.max(0) as usize,
+                eof: ev.flags as u32 & libc::EV_EOF as u32 != 0,
+            });
+        }
+        Ok(n as usize)
+    }
+}
+
+impl Pollable for Watcher {
+    fn poll_handle(&self) -> OsSocket {
+        self.kq.as_raw_fd()
+    }
+}
+
+impl AsRawFd for Watcher {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.kq.as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio-runtime")]
+impl mio::event::Source for Watcher {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_recv_lowat_waits_for_enough_bytes_and_send_empty_fires() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut tx = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (rx, _) = listener.accept().unwrap();
+
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch_recv_lowat(&rx, 100, 1).unwrap();
+        let mut events = Vec::new();
+
+        tx.write_all(&[0u8; 50]).unwrap();
+        assert_eq!(watcher.wait(&mut events, Some(Duration::from_millis(50))).unwrap(), 0);
+
+        tx.write_all(&[0u8; 60]).unwrap();
+        assert_eq!(watcher.wait(&mut events, Some(Duration::from_secs(5))).unwrap(), 1);
+        assert_eq!(events[0].token, 1);
+        assert_eq!(events[0].watch, Watch::RecvLowat);
+        assert!(events[0].bytes >= 100);
+
+        watcher.unwatch_recv(&rx).unwrap();
+        watcher.watch_send_empty(&tx, 2).unwrap();
+        events.clear();
+        assert_eq!(watcher.wait(&mut events, Some(Duration::from_secs(5))).unwrap(), 1);
+        assert_eq!((events[0].token, events[0].watch), (2, Watch::SendEmpty));
+    }
+
+    #[test]
+    fn test_send_lowat_replaces_send_empty_and_unwatch_removes_it() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tx = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _rx = listener.accept().unwrap();
+
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch_send_empty(&tx, 1).unwrap();
+        watcher.watch_send_lowat(&tx, 1, 2).unwrap();
+        let mut events = Vec::new();
+        assert_eq!(watcher.wait(&mut events, Some(Duration::from_secs(5))).unwrap(), 1);
+        assert_eq!((events[0].token, events[0].watch), (2, Watch::SendLowat));
+
+        watcher.unwatch_send(&tx).unwrap();
+        events.clear();
+        assert_eq!(watcher.wait(&mut events, Some(Duration::from_millis(50))).unwrap(), 0);
+        assert!(watcher.unwatch_send(&tx).is_err());
+    }
+}
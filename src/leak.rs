@@ -0,0 +1,250 @@
+//! Socket leak detection for debug builds
+//!
+//! A socket that is never dropped keeps its port and file descriptor for
+//! the life of the process. When sockets pass through connection pools,
+//! dispatchers, and maps keyed by peer, the code path that forgot one is
+//! hard to find after the fact. While [`enable`] is active, every
+//! [`Udp`](crate::udp::Udp), [`TcpStream`](crate::tcp::TcpStream), and
+//! [`TcpListener`](crate::tcp::TcpListener) the crate creates is recorded
+//! with the backtrace of its creation. The entry is removed when the socket
+//! is dropped or converted into its `std` type.
+//!
+//! [`live`] lists the sockets still open, and [`report`] formats them. The
+//! guard returned by [`enable`] prints the report to stderr when dropped, so
+//! holding it for the whole of `main` reports whatever is still open at
+//! exit. Only sockets created while tracking was on are recorded.
+//!
+//! Capturing a backtrace costs microseconds per socket, so tracking is off
+//! until enabled. In release builds (without `debug_assertions`) it is
+//! compiled out entirely: [`enable`] does nothing and [`live`] is always
+//! empty.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{leak, udp::Udp, NetConfig};
+//!
+//! let _leaks = leak::enable();
+//! let socket = Udp::bind("127.0.0.1:0".parse()?, &NetConfig::default())?;
+//! std::mem::forget(socket);
+//! // At the end of main the guard prints the forgotten socket and where it was bound
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::raw::OsSocket;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// A tracked socket that was still open when [`live`] was called
+#[derive(Clone, Debug)]
+pub struct LiveSocket {
+    /// Creation sequence number, unique for the process
+    pub id: u64,
+    /// `"udp"`, `"tcp stream"`, or `"tcp listener"`
+    pub kind: &'static str,
+    /// Raw descriptor or `SOCKET` handle
+    pub handle: OsSocket,
+    /// Current local address, if the socket is bound
+    pub local_addr: Option<SocketAddr>,
+    /// Time since the socket was created
+    pub age: Duration,
+    /// Backtrace captured at creation
+    pub backtrace: String,
+}
+
+/// Keeps tracking enabled; prints a [`report`] to stderr when dropped
+///
+/// Guards nest: tracking stays on until the last one is dropped.
+#[must_use = "dropping the guard stops tracking and prints the report immediately"]
+#[derive(Debug)]
+pub struct LeakCheck {
+    _private: (),
+}
+
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        imp::ENABLED.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+        let report = report();
+        if !report.is_empty() {
+            eprint!("{}", report);
+        }
+    }
+}
+
+/// Starts recording sockets created by the crate, until the guard is dropped
+///
+/// Does nothing in release builds.
+pub fn enable() -> LeakCheck {
+    #[cfg(debug_assertions)]
+    imp::ENABLED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    LeakCheck { _private: () }
+}
+
+/// Returns `true` while new sockets are being recorded
+pub fn is_enabled() -> bool {
+    #[cfg(debug_assertions)]
+    return imp::ENABLED.load(std::sync::atomic::Ordering::Relaxed) > 0;
+    #[cfg(not(debug_assertions))]
+    false
+}
+
+/// Lists recorded sockets that are still open, oldest first
+pub fn live() -> Vec<LiveSocket> {
+    #[cfg(debug_assertions)]
+    return imp::live();
+    #[cfg(not(debug_assertions))]
+    Vec::new()
+}
+
+/// Formats [`live`] for humans; empty when nothing is open
+pub fn report() -> String {
+    let sockets = live();
+    let mut out = String::new();
+    if sockets.is_empty() {
+        return out;
+    }
+    let _ = writeln!(out, "horizon_sockets: {} socket(s) still open", sockets.len());
+    for s in sockets {
+        let addr = s.local_addr.map_or_else(|| "unbound".to_string(), |a| a.to_string());
+        let _ = writeln!(out, "#{} {} {} (handle {}, open {:?}) created at:", s.id, s.kind, addr, s.handle, s.age);
+        for line in s.backtrace.lines() {
+            let _ = writeln!(out, "    {}", line);
+        }
+    }
+    out
+}
+
+/// Registry entry owned by a socket; removes itself on drop
+///
+/// Declare it before the socket handle so the entry is gone before the
+/// handle closes, and a report never queries a closed (or reused) handle.
+/// Zero-sized in release builds.
+pub(crate) struct Tracked {
+    #[cfg(debug_assertions)]
+    id: Option<u64>,
+}
+
+impl Tracked {
+    #[inline]
+    pub(crate) fn new(kind: &'static str, handle: OsSocket) -> Self {
+        #[cfg(debug_assertions)]
+        return Self { id: imp::register(kind, handle) };
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = (kind, handle);
+            Self {}
+        }
+    }
+}
+
+impl Drop for Tracked {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if let Some(id) = self.id {
+            imp::unregister(id);
+        }
+    }
+}
+
+impl std::fmt::Debug for Tracked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        #[cfg(debug_assertions)]
+        return f.debug_tuple("Tracked").field(&self.id).finish();
+        #[cfg(not(debug_assertions))]
+        f.write_str("Tracked")
+    }
+}
+
+#[cfg(debug_assertions)]
+mod imp {
+    use super::LiveSocket;
+    use crate::raw::OsSocket;
+    use std::backtrace::Backtrace;
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    pub(super) static ENABLED: AtomicUsize = AtomicUsize::new(0);
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    static REGISTRY: Mutex<BTreeMap<u64, Entry>> = Mutex::new(BTreeMap::new());
+
+    struct Entry {
+        kind: &'static str,
+        handle: OsSocket,
+        created: Instant,
+        backtrace: Backtrace,
+    }
+
+    pub(super) fn register(kind: &'static str, handle: OsSocket) -> Option<u64> {
+        if ENABLED.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let entry = Entry { kind, handle, created: Instant::now(), backtrace: Backtrace::force_capture() };
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).insert(id, entry);
+        Some(id)
+    }
+
+    pub(super) fn unregister(id: u64) {
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    pub(super) fn live() -> Vec<LiveSocket> {
+        // Holding the lock keeps every listed handle open (see Tracked)
+        let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry
+            .iter()
+            .map(|(&id, e)| LiveSocket {
+                id,
+                kind: e.kind,
+                handle: e.handle,
+                local_addr: local_addr(e.handle),
+                age: e.created.elapsed(),
+                backtrace: e.backtrace.to_string(),
+            })
+            .collect()
+    }
+
+    /// `getsockname` on a handle owned by someone else
+    fn local_addr(handle: OsSocket) -> Option<SocketAddr> {
+        #[cfg(unix)]
+        let borrowed = unsafe { <std::net::UdpSocket as std::os::fd::FromRawFd>::from_raw_fd(handle) };
+        #[cfg(windows)]
+        let borrowed = unsafe { <std::net::UdpSocket as std::os::windows::io::FromRawSocket>::from_raw_socket(handle) };
+        // Never close it: the owning socket does that
+        let borrowed = std::mem::ManuallyDrop::new(borrowed);
+        borrowed.local_addr().ok()
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use crate::{tcp::TcpListener, udp::Udp, NetConfig};
+
+    #[test]
+    fn test_reports_open_sockets_until_dropped() {
+        let guard = enable();
+        let udp = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let (udp_addr, tcp_addr) = (udp.local_addr().unwrap(), listener.local_addr().unwrap());
+        let find = |addr| live().into_iter().find(|s| s.local_addr == Some(addr));
+
+        let entry = find(udp_addr).expect("udp socket tracked");
+        assert_eq!(entry.kind, "udp");
+        assert!(entry.backtrace.contains("test_reports_open_sockets_until_dropped"), "{}", entry.backtrace);
+        assert_eq!(find(tcp_addr).unwrap().kind, "tcp listener");
+        assert!(report().contains(&format!("udp {}", udp_addr)));
+
+        // Handing the socket to std ends tracking too
+        let std_udp: std::net::UdpSocket = udp.into();
+        drop(listener);
+        assert!(find(udp_addr).is_none() && find(tcp_addr).is_none());
+        drop((guard, std_udp));
+    }
+}
@@ -95,7 +95,7 @@
 //! ```rust,no_run
 //! use horizon_sockets::{NetConfig, udp::Udp};
 //!
-//! fn main() -> std::io::Result<()> {
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     // Configure for low latency using NetConfig
 //!     let config = NetConfig {
 //!         busy_poll: Some(50), // 50 microseconds busy polling
@@ -118,10 +118,79 @@
 //! - [`raw`]: Low-level socket operations and platform-specific implementations
 //! - [`udp`]: High-level UDP socket interface with batch operations
 //! - [`tcp`]: High-level TCP socket interface with connection management
+//! - [`addr`]: Allocation-free socket address formatting into slices and parsing from bytes
+//! - `accept_gate` (`mio-runtime` feature): Pausing and resuming listener READABLE interest at worker queue watermarks
+//! - [`arena`]: Single contiguous allocation sliced into batch receive segments with per-packet receive times
 //! - [`buffer_pool`]: Memory-efficient buffer pool for network operations
-//! - [`affinity`]: CPU affinity and thread pinning utilities
+//! - [`budget`]: Per-connection caps on pooled buffer bytes with drop, park, or kill on overrun
+//! - [`batch`]: Adaptive batch sizing that follows observed traffic
+//! - [`blocking`]: Runtime-free `Send` handles with timeout-bounded batch receive and `send_all`, for pyo3 wrappers
+//! - [`hotpath`]: Prefetch, branch hints, and chunked processing for packet loops
+//! - `capi` (`capi` feature): `extern "C"` sockets, polling, and buffer pool with flat error codes for C/C++ engines
+//! - [`buffered`]: Pool-backed stream read buffering with `fill_buf`/`consume` for codecs
+//! - [`affinity`]: CPU affinity, thread pinning, and XPS/`SO_INCOMING_CPU` alignment
+//! - [`checksum`]: Internet checksum with pseudo-headers and hardware-accelerated CRC32C
+//! - [`cid`]: Connection-ID routing of UDP datagrams, tolerant of NAT rebinding
+//! - [`clock`]: Selectable monotonic clocks (calibrated TSC, `CLOCK_MONOTONIC_RAW`, coarse) trading precision for cost
+//! - [`error`]: Structured `Error` (unsupported option, bind failure, partial batch) inside `io::Error`
+//! - [`codec`]: Per-socket encode/decode hooks (LZ4 with the `lz4` feature) on batch send and receive
+//! - `cookie` (`cookie` feature): Stateless HMAC address-validation cookies with expiry and secret rotation
+//! - [`demux`]: Classifying datagrams by destination port or closure into per-handler queues with backpressure
+//! - `driver` (`mio-runtime` feature): `Protocol` state machines run on connections by a mio loop with buffering, timers, and backpressure
+//! - [`drain`]: Listener draining and live-connection tracking for zero-downtime deploys
+//! - [`export`]: Owned socket handles with a text manifest of option values, sent over `SCM_RIGHTS` to sidecars
+//! - [`fixed_batch`]: Const-generic receive and send batch storage for loops that never allocate
+//! - [`flow`]: Fixed-capacity per-peer state table with LRU and TTL eviction
+//! - [`gso`]: Per-destination UDP GSO segment sizes from route MTU, lowered by ICMP reports, sent with `UDP_SEGMENT`
+//! - [`half_close`]: Half-closed TCP connection tracking and lingering close with timeouts
+//! - [`handshake_guard`]: Slow-loris protection with handshake deadlines and pending limits
+//! - `health` (`health` feature): HTTP `/livez`, `/readyz`, and JSON `/stats` endpoint for orchestrator probes
+//! - [`heartbeat`]: Per-peer keepalive heartbeats and idle timeouts on a hashed timing wheel
+//! - `http` (`http` feature): Minimal HTTP/1.1 server engine with keep-alive, pipelining, and chunked bodies on the protocol driver
+//! - [`icmp`]: Structured ICMP errors (unreachable, MTU, TTL) drained from UDP sockets
+//! - `iocp` (Windows): Completion port with pre-posted overlapped UDP receives harvested in batches
+//! - [`jitter`]: Receive-side jitter buffer with fixed or adaptive playout delay for media streams
+//! - `kqueue` (BSD/macOS): Send-buffer-empty and receive/send low-watermark watches beyond mio's filters
+//! - [`leak`]: Debug-build registry of open sockets with creation backtraces, reported on demand or at exit
+//! - [`loop_stats`]: Event loop poll-versus-callback time accounting and utilization percentages
+//! - [`metrics`]: Pull-based counter, gauge, and socket buffer registry shared by exporters
+//! - `mmsg` (Linux/Android): Reusable `recvmmsg`/`sendmmsg` batches over `MaybeUninit` storage with safe per-message accessors
+//! - [`mux`]: Single-port listener dispatching connections to handlers by first bytes or ALPN
+//! - [`netif`]: Interface enumeration with indexes, MAC, MTU, flags, and subnet addresses
+//! - [`netmon`]: Link up/down, address, and route change events for rebinding long-lived sockets
+//! - [`napi`]: Grouping sockets by NIC receive queue for busy-polling event loops
+//! - [`rss`]: NIC receive-side scaling queue, hash, and indirection table inspection
+//! - [`write_batch`]: Resumable vectored writes of header/body pieces without copying
+//! - [`sample`]: One-in-N packet sampling into a diagnostic SO_REUSEPORT tap socket
+//! - [`send_queue`]: Per-destination coalescing of small messages flushed with `sendmmsg`
+//! - [`shard`]: SO_REUSEPORT shard groups with 4-tuple hash or CPU steering programs
+//! - [`memnet`]: In-memory sockets mirroring the UDP/TCP API for tests without real ports
+//! - [`signal`]: Cross-thread event loop wakeups (eventfd or loopback socket pair)
+//! - [`spsc`]: Cache-line padded SPSC ring for handing packets between pinned threads
+//! - [`simnet`]: Deterministic loss, latency, and bandwidth simulation for testing
+//! - [`test_runtime`]: Deterministic single-threaded test runtime with virtual-time timers over in-memory sockets
+//! - [`tick`]: Fixed-rate frame scheduler bounding I/O waits by tick boundaries and reporting overruns
+//! - [`transport`]: `DatagramSocket`/`StreamSocket` traits for transport-agnostic code
+//! - [`tx_scheduler`]: Strict-priority or weighted round robin sending across traffic classes with rate caps
+//! - [`pacer`]: Packets released at absolute departure times by hybrid sleep/spin or `SO_TXTIME`
+//! - [`parse`]: Panic-free decoders for sockaddr bytes, cmsg buffers, and CPU lists, exposed for fuzzing
+//! - `packet` (Linux): `AF_PACKET` link-layer sockets with 802.1Q PCP tagging and VLAN tags via `PACKET_AUXDATA`
+//! - [`poll`]: `poll`/`WSAPoll` readiness helper for simple clients without a runtime
+//! - `prometheus` (`prometheus` feature): HTTP `/metrics` endpoint rendering the metrics registry in Prometheus text format
+//! - `qos` (Windows): qWAVE flow marking so `tos` takes effect, plus `SIO_SET_PRIORITY_HINT` bandwidth hints
+//! - [`ports`]: Pre-bound port reservation for sockets that must use whitelisted source ports
+//! - [`relay`]: Receive-once, send-to-many overlay fan-out with bounded per-peer backlogs
+//! - [`replay`]: RFC 6479 sliding-window anti-replay bitmap for sequence-numbered datagrams
+//! - `repair` (Linux, `unsafe_advanced`): TCP_REPAIR checkpoint and restore for migrating established connections
+//! - [`retry`]: Spin/yield/park backoff for `WouldBlock` retry loops
+//! - `rtp` (`rtp` feature): Zero-copy RTP/RTCP header parsing and building over pooled buffers
 //! - [`rt`]: Runtime backends (mio/monoio) for async I/O operations
 //!
+//! [`checksum`], [`replay`], and the option value types in [`config`]
+//! ([`AddrPreferences`](config::AddrPreferences), [`TcpKeepalive`](config::TcpKeepalive),
+//! [`DropPolicy`]) live in the `no_std` `horizon_sockets_core` crate in
+//! `core/` and are re-exported here, so embedded targets can share them.
+//!
 //! ## Performance Tips
 //!
 //! 1. **Use Buffer Pools**: Always use `BufferPool` for high-frequency operations
@@ -134,20 +203,164 @@
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
 
+/// Accept backpressure by pausing listener interest
+#[cfg(feature = "mio-runtime")]
+pub mod accept_gate;
+/// Allocation-free socket address formatting and parsing
+pub mod addr;
 /// CPU affinity and thread pinning utilities
 pub mod affinity;
+/// Contiguous receive arena for batch UDP receives
+pub mod arena;
+/// Adaptive batch sizing for batch send/receive loops
+pub mod batch;
+/// Blocking send and receive with timeouts for language bindings
+pub mod blocking;
 /// Universal socket builder for creating both TCP and UDP sockets
 pub mod builder;
 /// Memory-efficient buffer pool for network operations
 pub mod buffer_pool;
+/// Per-connection pooled buffer accounting and caps
+pub mod budget;
+/// Stream reads into pooled buffers held only while data is pending
+pub mod buffered;
+/// C ABI for embedding in C and C++ hosts
+#[cfg(feature = "capi")]
+pub mod capi;
+/// Internet checksum and CRC32C utilities
+pub mod checksum;
+/// Payload transforms such as compression on datagram batch paths
+pub mod codec;
+/// Connection-ID routing for UDP
+pub mod cid;
+/// Monotonic clocks with selectable precision and cost
+pub mod clock;
 /// Network configuration and performance tuning
 pub mod config;
+/// Stateless anti-spoofing cookies for UDP handshakes
+#[cfg(feature = "cookie")]
+pub mod cookie;
+/// Datagram demultiplexing into per-service handler queues
+pub mod demux;
+/// Connection tracking and listener draining for graceful restarts
+pub mod drain;
+/// Protocol state-machine driver for mio event loops
+#[cfg(feature = "mio-runtime")]
+pub mod driver;
+/// Structured errors that distinguish tuning from transport failures
+pub mod error;
+/// Socket handoff to other processes with a manifest of applied options
+pub mod export;
+/// Fixed-capacity batch storage without heap allocation
+pub mod fixed_batch;
+/// Per-flow state table for connectionless servers
+pub mod flow;
+/// UDP GSO segment sizing from route and path MTU
+pub mod gso;
+/// Half-close and lingering close state tracking for streams
+pub mod half_close;
+/// First-data deadlines and pending-handshake limits for accepted connections
+pub mod handshake_guard;
+/// HTTP health and readiness probe endpoint
+#[cfg(feature = "health")]
+pub mod health;
+/// Heartbeat scheduling and idle timeouts for UDP sessions
+pub mod heartbeat;
+/// Minimal HTTP/1.1 server engine on the protocol driver
+#[cfg(feature = "http")]
+pub mod http;
+/// Prefetch and branch-hint helpers for packet processing loops
+pub mod hotpath;
+/// ICMP error reporting for UDP sockets
+pub mod icmp;
+/// Native IOCP completion port for batched UDP on Windows
+#[cfg(windows)]
+pub mod iocp;
+/// Playout jitter buffering for media datagrams
+pub mod jitter;
+/// kqueue EVFILT_EMPTY and NOTE_LOWAT watches for BSD and macOS
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd", target_os = "macos", target_os = "ios"))]
+pub mod kqueue;
+/// Debug-build socket leak detection
+pub mod leak;
+/// Event loop CPU time accounting
+pub mod loop_stats;
+/// In-memory loopback transport for tests
+pub mod memnet;
+/// Metrics registry for counters, gauges, and socket statistics
+pub mod metrics;
+/// Safe recvmmsg/sendmmsg batch wrappers
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod mmsg;
+/// Protocol-detecting multiplexer for serving several protocols on one port
+pub mod mux;
+/// NAPI-aware grouping of sockets across event loops
+pub mod napi;
+/// Network interface enumeration
+pub mod netif;
+/// Link, address, and route change notifications
+pub mod netmon;
+/// Deadline-paced datagram sending
+pub mod pacer;
+/// Link-layer packet sockets with VLAN priority tagging
+#[cfg(target_os = "linux")]
+pub mod packet;
+/// Fuzzable decoders for kernel- and user-supplied bytes
+pub mod parse;
+/// Readiness waiting for a few sockets without a runtime
+pub mod poll;
+/// Local port reservation and leasing
+pub mod ports;
+/// Prometheus exposition endpoint for the metrics registry
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+/// Windows qWAVE traffic marking and socket priority hints
+#[cfg(windows)]
+pub mod qos;
 /// Low-level socket operations and platform abstractions  
 pub mod raw;
+/// Overlay fan-out relaying datagrams to downstream peers
+pub mod relay;
+/// Sliding-window anti-replay bitmap for sequence-numbered datagrams
+pub mod replay;
+/// TCP repair mode for connection checkpoint and migration
+#[cfg(all(feature = "unsafe_advanced", target_os = "linux"))]
+pub mod repair;
+/// Backoff strategy for retrying non-blocking operations
+pub mod retry;
+/// Receive-side scaling inspection via ethtool
+pub mod rss;
+/// RTP and RTCP header parsing and building
+#[cfg(feature = "rtp")]
+pub mod rtp;
+/// Reuseport-based packet sampling for observability
+pub mod sample;
+/// Per-destination send coalescing for chatty datagram protocols
+pub mod send_queue;
+/// SO_REUSEPORT shard groups with BPF socket selection
+pub mod shard;
+/// Cross-thread wakeup notifier for event loops
+pub mod signal;
+/// Fault injection and network condition simulation
+pub mod simnet;
+/// Lock-free single-producer single-consumer ring
+pub mod spsc;
+mod sync;
 /// High-performance TCP socket implementation
 pub mod tcp;
+/// Deterministic virtual-time runtime for protocol tests
+pub mod test_runtime;
+/// Fixed-rate tick scheduling for game-server loops
+pub mod tick;
+mod trace;
+/// Transport traits abstracting over real, in-memory, and simulated sockets
+pub mod transport;
+/// Traffic-class queues with priority and weighted outbound scheduling
+pub mod tx_scheduler;
 /// High-performance UDP socket implementation
 pub mod udp;
+/// Resumable vectored writes of multi-piece responses
+pub mod write_batch;
 
 cfg_if::cfg_if! {
     if #[cfg(all(
@@ -166,17 +379,21 @@ cfg_if::cfg_if! {
     }
 }
 
+pub use batch::AdaptiveBatcher;
 pub use buffer_pool::BufferPool;
 /// Convenience re-exports for common types and functions
 ///
 /// These re-exports provide easy access to the most commonly used
 /// types and functions without requiring full module paths.
-pub use config::{NetConfig, apply_low_latency};
+pub use config::{DropPolicy, NetConfig, apply_low_latency};
+pub use error::Error;
+pub use raw::{init, shutdown};
 pub use rt::{NetHandle, Runtime};
 
 // Re-export main socket types and builders for easier access
 pub use builder::SocketBuilder;
 pub use tcp::{TcpListener, TcpListenerBuilder, TcpStream, TcpStreamBuilder};
+pub use transport::{DatagramSocket, StreamSocket};
 pub use udp::{Udp, UdpBuilder};
 
 // Re-export affinity utilities for performance tuning
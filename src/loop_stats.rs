@@ -0,0 +1,236 @@
+//! Event loop time accounting: poll versus callbacks
+//!
+//! A pinned event-loop core at 100% CPU says nothing about where the time
+//! goes. If most of it is spent inside `epoll_wait` (busy polling, or a
+//! flood of wakeups), adding handler threads will not help; if most of it
+//! is in callbacks, the handlers are the bottleneck. [`LoopStats`] splits
+//! each loop iteration into the time spent polling and the time spent
+//! dispatching events, and [`Utilization`] turns the totals into
+//! percentages of wall-clock time for capacity planning.
+//!
+//! Wall time inside poll includes sleeping while idle. With
+//! [`thread_cpu`](LoopStats::thread_cpu) enabled, the thread's CPU clock is
+//! also sampled around each poll, which separates time actually spent in
+//! the kernel (busy polling, processing wakeups) from time asleep. This
+//! costs one extra `clock_gettime` per poll, so it is off by default, and
+//! it is only available on Linux and Android.
+//!
+//! The mio runtime keeps a `LoopStats` for its own loops (see
+//! `Runtime::loop_stats`); hand-written loops can time themselves with
+//! [`poll`](LoopStats::poll) and [`callbacks`](LoopStats::callbacks).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::loop_stats::LoopStats;
+//! use std::time::Duration;
+//!
+//! let mut poll = mio::Poll::new()?;
+//! let mut events = mio::Events::with_capacity(1024);
+//! let mut stats = LoopStats::new().thread_cpu(true);
+//! loop {
+//!     stats.poll(|| poll.poll(&mut events, Some(Duration::from_millis(10))))?;
+//!     let count = events.iter().count();
+//!     stats.callbacks(count, || {
+//!         for event in events.iter() {
+//!             let _ = event; // dispatch
+//!         }
+//!     });
+//!     if stats.window() >= Duration::from_secs(10) {
+//!         let u = stats.take();
+//!         println!("poll {:.1}% callbacks {:.1}%", u.poll_percent(), u.callback_percent());
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::time::{Duration, Instant};
+
+/// Running poll and callback time totals for one event loop
+#[derive(Clone, Debug)]
+pub struct LoopStats {
+    since: Instant,
+    iterations: u64,
+    events: u64,
+    poll: Duration,
+    poll_cpu: Option<Duration>,
+    callbacks: Duration,
+    thread_cpu: bool,
+}
+
+/// Loop time totals over a window, with percentage helpers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Utilization {
+    /// Wall-clock length of the window
+    pub wall: Duration,
+    /// Poll calls made
+    pub iterations: u64,
+    /// Events dispatched to callbacks
+    pub events: u64,
+    /// Wall time inside poll, including sleeping
+    pub poll: Duration,
+    /// Thread CPU time inside poll, when [`thread_cpu`](LoopStats::thread_cpu) is enabled and supported
+    pub poll_cpu: Option<Duration>,
+    /// Wall time running callbacks
+    pub callbacks: Duration,
+}
+
+impl Utilization {
+    /// Percentage of the window spent inside poll, including sleeping
+    pub fn poll_percent(&self) -> f64 {
+        percent(self.poll, self.wall)
+    }
+
+    /// Percentage of the window spent on CPU inside poll, if measured
+    pub fn poll_cpu_percent(&self) -> Option<f64> {
+        self.poll_cpu.map(|cpu| percent(cpu, self.wall))
+    }
+
+    /// Percentage of the window spent running callbacks
+    pub fn callback_percent(&self) -> f64 {
+        percent(self.callbacks, self.wall)
+    }
+
+    /// Percentage of the window spent outside both, e.g. timers and bookkeeping between polls
+    pub fn other_percent(&self) -> f64 {
+        percent(self.wall.saturating_sub(self.poll + self.callbacks), self.wall)
+    }
+
+    /// Percentage of the window the thread was busy: callbacks, other work, and CPU inside poll
+    ///
+    /// Without CPU sampling, all of poll counts as idle.
+    pub fn busy_percent(&self) -> f64 {
+        let idle = self.poll.saturating_sub(self.poll_cpu.unwrap_or_default());
+        100.0 - percent(idle, self.wall)
+    }
+}
+
+impl LoopStats {
+    /// Starts an empty window now, with CPU sampling off
+    pub fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            iterations: 0,
+            events: 0,
+            poll: Duration::ZERO,
+            poll_cpu: None,
+            callbacks: Duration::ZERO,
+            thread_cpu: false,
+        }
+    }
+
+    /// Also samples the thread CPU clock around each poll (Linux and Android)
+    pub fn thread_cpu(mut self, enable: bool) -> Self {
+        self.thread_cpu = enable;
+        self
+    }
+
+    /// Runs `f` as one poll call, adding its duration to the poll total
+    pub fn poll<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let cpu_start = self.cpu_now();
+        let start = Instant::now();
+        let out = f();
+        self.poll += start.elapsed();
+        if let (Some(before), Some(after)) = (cpu_start, self.cpu_now()) {
+            *self.poll_cpu.get_or_insert(Duration::ZERO) += after.saturating_sub(before);
+        }
+        self.iterations += 1;
+        out
+    }
+
+    /// Runs `f` as the dispatch of `events` events, adding its duration to the callback total
+    pub fn callbacks<T>(&mut self, events: usize, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.callbacks += start.elapsed();
+        self.events += events as u64;
+        out
+    }
+
+    /// Returns how long the current window has been open
+    pub fn window(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// Returns the totals for the current window
+    pub fn utilization(&self) -> Utilization {
+        Utilization {
+            wall: self.since.elapsed(),
+            iterations: self.iterations,
+            events: self.events,
+            poll: self.poll,
+            poll_cpu: self.poll_cpu,
+            callbacks: self.callbacks,
+        }
+    }
+
+    /// Returns the totals for the current window and starts a new one
+    pub fn take(&mut self) -> Utilization {
+        let out = self.utilization();
+        self.reset();
+        out
+    }
+
+    /// Clears the totals and starts a new window now
+    pub fn reset(&mut self) {
+        *self = Self::new().thread_cpu(self.thread_cpu);
+    }
+
+    fn cpu_now(&self) -> Option<Duration> {
+        if self.thread_cpu { thread_cpu_time() } else { None }
+    }
+}
+
+impl Default for LoopStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn percent(part: Duration, whole: Duration) -> f64 {
+    if whole.is_zero() {
+        return 0.0;
+    }
+    (part.as_secs_f64() / whole.as_secs_f64() * 100.0).min(100.0)
+}
+
+/// CPU time consumed by the calling thread
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    let rc = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    (rc == 0).then(|| Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_poll_and_callback_time() {
+        let mut stats = LoopStats::new().thread_cpu(true);
+        stats.poll(|| std::thread::sleep(Duration::from_millis(20)));
+        stats.callbacks(3, || {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(10) {}
+        });
+
+        let u = stats.take();
+        assert_eq!((u.iterations, u.events), (1, 3));
+        assert!(u.poll >= Duration::from_millis(20) && u.callbacks >= Duration::from_millis(10));
+        assert!(u.poll_percent() > u.callback_percent());
+        assert!(u.poll_percent() + u.callback_percent() + u.other_percent() <= 100.0 + 1e-9);
+        #[cfg(target_os = "linux")]
+        {
+            // Sleeping in poll burns almost no CPU, so the loop is mostly idle
+            assert!(u.poll_cpu.unwrap() < Duration::from_millis(10));
+            assert!(u.busy_percent() < 60.0);
+        }
+        assert_eq!(stats.utilization().iterations, 0);
+    }
+}
@@ -0,0 +1,507 @@
+//! In-memory loopback transport for tests
+//!
+//! This module provides socket types that mirror the public API of [`Udp`]
+//! and [`TcpStream`] but move bytes through process memory instead of the
+//! kernel. Application code can be unit tested without binding real ports,
+//! without firewall prompts, and without interference between parallel tests.
+//!
+//! - [`MemNetwork`]: An isolated address space that routes datagrams between sockets
+//! - [`MemUdp`]: Datagram socket bound to an address on a `MemNetwork`
+//! - [`MemStream`]: One end of a connected, bidirectional byte stream
+//!
+//! Like the real sockets, all operations are non-blocking and report
+//! `WouldBlock` when no data is available.
+//!
+//! [`Udp`]: crate::udp::Udp
+//! [`TcpStream`]: crate::tcp::TcpStream
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::memnet::MemNetwork;
+//! use std::net::SocketAddr;
+//!
+//! let net = MemNetwork::new();
+//! let server = net.bind("127.0.0.1:9000".parse()?)?;
+//! let client = net.bind("127.0.0.1:0".parse()?)?;
+//!
+//! client.send_to(b"ping", server.local_addr())?;
+//!
+//! let mut bufs = vec![vec![0u8; 64]; 4];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 4];
+//! let n = server.recv_batch(&mut bufs, &mut addrs)?;
+//! assert_eq!(n, 1);
+//! assert_eq!(&bufs[0], b"ping");
+//! assert_eq!(addrs[0], client.local_addr());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::transport::{DatagramSocket, StreamSocket};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+/// First port handed out when binding to port 0
+const EPHEMERAL_START: u16 = 49152;
+
+/// Default number of datagrams queued per socket before new ones are dropped
+const DEFAULT_QUEUE_LIMIT: usize = 4096;
+
+type Inbox = Arc<Mutex<VecDeque<(Vec<u8>, SocketAddr)>>>;
+
+#[derive(Debug)]
+struct NetState {
+    sockets: HashMap<SocketAddr, Inbox>,
+    next_port: u16,
+    queue_limit: usize,
+}
+
+/// Isolated in-memory network that routes datagrams between [`MemUdp`] sockets
+///
+/// Each `MemNetwork` is its own address space: sockets on different networks
+/// never see each other, so tests can reuse fixed port numbers freely.
+/// Cloning a `MemNetwork` yields another handle to the same network.
+#[derive(Clone, Debug)]
+pub struct MemNetwork {
+    state: Arc<Mutex<NetState>>,
+}
+
+impl MemNetwork {
+    /// Creates a new, empty network
+    pub fn new() -> Self {
+        Self::with_queue_limit(DEFAULT_QUEUE_LIMIT)
+    }
+
+    /// Creates a network whose sockets queue at most `limit` datagrams
+    ///
+    /// Datagrams arriving at a full socket are dropped, emulating a kernel
+    /// receive buffer overflow.
+    pub fn with_queue_limit(limit: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetState {
+                sockets: HashMap::new(),
+                next_port: EPHEMERAL_START,
+                queue_limit: limit,
+            })),
+        }
+    }
+
+    /// Binds a new datagram socket on this network
+    ///
+    /// Port 0 selects an unused ephemeral port. Binding to an unspecified
+    /// address (`0.0.0.0` or `[::]`) receives datagrams sent to any address
+    /// of the same family on that port.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AddrInUse` if another socket is already bound to `addr`, or
+    /// `AddrNotAvailable` if the ephemeral port range is exhausted.
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<MemUdp> {
+        let mut st = self.state.lock().unwrap();
+        let mut addr = addr;
+        if addr.port() == 0 {
+            let mut found = None;
+            for _ in EPHEMERAL_START..=u16::MAX {
+                let port = st.next_port;
+                st.next_port = if port == u16::MAX { EPHEMERAL_START } else { port + 1 };
+                if !st.sockets.contains_key(&SocketAddr::new(addr.ip(), port)) {
+                    found = Some(port);
+                    break;
+                }
+            }
+            match found {
+                Some(port) => addr.set_port(port),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrNotAvailable,
+                        "No free ephemeral ports on in-memory network",
+                    ))
+                }
+            }
+        }
+        if st.sockets.contains_key(&addr) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "Address already bound"));
+        }
+        let inbox: Inbox = Arc::new(Mutex::new(VecDeque::new()));
+        st.sockets.insert(addr, inbox.clone());
+        Ok(MemUdp { net: self.clone(), addr, inbox })
+    }
+
+    /// Finds the socket that should receive traffic for `dest`
+    fn route(&self, dest: SocketAddr) -> Option<(Inbox, usize)> {
+        let st = self.state.lock().unwrap();
+        let wildcard = match dest.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        st.sockets
+            .get(&dest)
+            .or_else(|| st.sockets.get(&SocketAddr::new(wildcard, dest.port())))
+            .map(|inbox| (inbox.clone(), st.queue_limit))
+    }
+}
+
+impl Default for MemNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// In-memory datagram socket with the same API shape as [`Udp`](crate::udp::Udp)
+///
+/// Datagrams are delivered instantly and in order. Sending to an address with
+/// no bound socket silently discards the datagram, just as UDP would.
+#[derive(Debug)]
+pub struct MemUdp {
+    net: MemNetwork,
+    addr: SocketAddr,
+    inbox: Inbox,
+}
+
+impl MemUdp {
+    /// Returns the address this socket is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the source address stamped on outgoing datagrams
+    fn source_addr(&self) -> SocketAddr {
+        match self.addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.addr.port()),
+            IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), self.addr.port()),
+            _ => self.addr,
+        }
+    }
+
+    /// Sends a datagram to the socket bound at `addr`
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes sent, which is always `buf.len()`
+    pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if let Some((inbox, limit)) = self.net.route(addr) {
+            let mut queue = inbox.lock().unwrap();
+            if queue.len() < limit {
+                queue.push_back((buf.to_vec(), self.source_addr()));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Sends multiple datagrams, mirroring [`Udp::send_batch`](crate::udp::Udp::send_batch)
+    pub fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        for (buf, addr) in packets {
+            self.send_to(buf, *addr)?;
+        }
+        Ok(packets.len())
+    }
+
+    /// Receives queued datagrams, mirroring [`Udp::recv_batch`](crate::udp::Udp::recv_batch)
+    ///
+    /// Each buffer is truncated to the received length. Datagrams longer than
+    /// a non-empty buffer are truncated to fit; an empty buffer is grown to
+    /// its capacity (or 2048 bytes when it has none).
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(count)` - Number of datagrams received
+    /// - `Err(WouldBlock)` - No datagrams queued
+    pub fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        let max = bufs.len().min(addrs.len());
+        if max == 0 {
+            return Ok(0);
+        }
+        let mut queue = self.inbox.lock().unwrap();
+        if queue.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let mut n = 0;
+        while n < max {
+            let Some((data, from)) = queue.pop_front() else { break };
+            let buf = &mut bufs[n];
+            let limit = if buf.is_empty() { buf.capacity().max(2048) } else { buf.len() };
+            buf.clear();
+            buf.extend_from_slice(&data[..data.len().min(limit)]);
+            addrs[n] = from;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Returns the number of datagrams waiting to be received
+    pub fn pending(&self) -> usize {
+        self.inbox.lock().unwrap().len()
+    }
+}
+
+impl Drop for MemUdp {
+    fn drop(&mut self) {
+        if let Ok(mut st) = self.net.state.lock() {
+            st.sockets.remove(&self.addr);
+        }
+    }
+}
+
+/// One direction of a stream connection
+#[derive(Debug, Default)]
+struct Pipe {
+    data: VecDeque<u8>,
+    /// Writer has shut down; readers see EOF once `data` drains
+    write_closed: bool,
+    /// Reader has gone away; writers get `BrokenPipe`
+    read_closed: bool,
+}
+
+/// In-memory stream endpoint with the same I/O shape as [`TcpStream`](crate::tcp::TcpStream)
+///
+/// Streams are created in connected pairs with [`MemStream::pair`]. Bytes
+/// written to one end can be read from the other; reads return `WouldBlock`
+/// when no data is buffered and `Ok(0)` once the peer has shut down writing.
+#[derive(Debug)]
+pub struct MemStream {
+    local: SocketAddr,
+    peer: SocketAddr,
+    rx: Arc<Mutex<Pipe>>,
+    tx: Arc<Mutex<Pipe>>,
+}
+
+impl MemStream {
+    /// Creates a connected pair of streams with synthetic loopback addresses
+    pub fn pair() -> (MemStream, MemStream) {
+        Self::pair_with_addrs(
+            SocketAddr::from(([127, 0, 0, 1], EPHEMERAL_START)),
+            SocketAddr::from(([127, 0, 0, 1], EPHEMERAL_START + 1)),
+        )
+    }
+
+    /// Creates a connected pair of streams reporting the given addresses
+    ///
+    /// The first stream reports `a` as its local address and `b` as its peer,
+    /// and vice versa.
+    pub fn pair_with_addrs(a: SocketAddr, b: SocketAddr) -> (MemStream, MemStream) {
+        let ab = Arc::new(Mutex::new(Pipe::default()));
+        let ba = Arc::new(Mutex::new(Pipe::default()));
+        (
+            MemStream { local: a, peer: b, rx: ba.clone(), tx: ab.clone() },
+            MemStream { local: b, peer: a, rx: ab, tx: ba },
+        )
+    }
+
+    /// Returns the local address of this end
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    /// Returns the address of the other end
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Shuts down the read, write, or both halves of this end
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if matches!(how, Shutdown::Write | Shutdown::Both) {
+            self.tx.lock().unwrap().write_closed = true;
+        }
+        if matches!(how, Shutdown::Read | Shutdown::Both) {
+            let mut rx = self.rx.lock().unwrap();
+            rx.read_closed = true;
+            rx.data.clear();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes buffered for reading on this end
+    pub fn available(&self) -> usize {
+        self.rx.lock().unwrap().data.len()
+    }
+}
+
+impl Read for &MemStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut rx = self.rx.lock().unwrap();
+        if rx.data.is_empty() {
+            return if rx.write_closed || rx.read_closed {
+                Ok(0)
+            } else {
+                Err(io::ErrorKind::WouldBlock.into())
+            };
+        }
+        let n = buf.len().min(rx.data.len());
+        for (dst, src) in buf.iter_mut().zip(rx.data.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for &MemStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut tx = self.tx.lock().unwrap();
+        if tx.read_closed || tx.write_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        tx.data.extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for MemStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for MemStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemStream {
+    fn drop(&mut self) {
+        if let Ok(mut tx) = self.tx.lock() {
+            tx.write_closed = true;
+        }
+        if let Ok(mut rx) = self.rx.lock() {
+            rx.read_closed = true;
+        }
+    }
+}
+
+impl DatagramSocket for MemUdp {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        MemUdp::send_to(self, buf, addr)
+    }
+
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        MemUdp::recv_batch(self, bufs, addrs)
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        MemUdp::send_batch(self, packets)
+    }
+}
+
+impl StreamSocket for MemStream {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(MemStream::local_addr(self))
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(MemStream::peer_addr(self))
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        MemStream::shutdown(self, how)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recv_one(sock: &MemUdp) -> io::Result<(Vec<u8>, SocketAddr)> {
+        let mut bufs = vec![Vec::new()];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0))];
+        sock.recv_batch(&mut bufs, &mut addrs)?;
+        Ok((bufs.pop().unwrap(), addrs[0]))
+    }
+
+    #[test]
+    fn test_udp_roundtrip() {
+        let net = MemNetwork::new();
+        let a = net.bind("127.0.0.1:1000".parse().unwrap()).unwrap();
+        let b = net.bind("127.0.0.1:2000".parse().unwrap()).unwrap();
+
+        a.send_to(b"hello", b.local_addr()).unwrap();
+        let (data, from) = recv_one(&b).unwrap();
+        assert_eq!(data, b"hello");
+        assert_eq!(from, a.local_addr());
+
+        let err = recv_one(&b).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_ephemeral_and_in_use() {
+        let net = MemNetwork::new();
+        let a = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(a.local_addr().port() >= EPHEMERAL_START);
+
+        let err = net.bind(a.local_addr()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        let addr = a.local_addr();
+        drop(a);
+        assert!(net.bind(addr).is_ok());
+    }
+
+    #[test]
+    fn test_wildcard_bind_and_unknown_destination() {
+        let net = MemNetwork::new();
+        let server = net.bind("0.0.0.0:53".parse().unwrap()).unwrap();
+        let client = net.bind("10.0.0.2:0".parse().unwrap()).unwrap();
+
+        client.send_to(b"query", "10.0.0.1:53".parse().unwrap()).unwrap();
+        assert_eq!(server.pending(), 1);
+
+        // No socket on this port: silently dropped
+        assert_eq!(client.send_to(b"lost", "10.0.0.1:54".parse().unwrap()).unwrap(), 4);
+        assert_eq!(server.pending(), 1);
+    }
+
+    #[test]
+    fn test_queue_limit_drops_excess() {
+        let net = MemNetwork::with_queue_limit(2);
+        let a = net.bind("127.0.0.1:1".parse().unwrap()).unwrap();
+        let b = net.bind("127.0.0.1:2".parse().unwrap()).unwrap();
+        for _ in 0..5 {
+            a.send_to(b"x", b.local_addr()).unwrap();
+        }
+        assert_eq!(b.pending(), 2);
+    }
+
+    #[test]
+    fn test_stream_pair() {
+        let (mut a, mut b) = MemStream::pair();
+        let (c, mut d) = MemStream::pair();
+        c.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(d.read(&mut [0u8; 4]).unwrap(), 0);
+        assert_eq!(a.peer_addr(), b.local_addr());
+
+        let mut buf = [0u8; 16];
+        assert_eq!(b.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        a.write_all(b"hello").unwrap();
+        assert_eq!(b.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+
+        drop(a);
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+        assert_eq!(b.write(b"x").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
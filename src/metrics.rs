@@ -0,0 +1,253 @@
+//! Registry of counters, gauges, and socket statistics for export
+//!
+//! Services built on the crate usually want a handful of numbers visible
+//! outside the process: queue depths, drop counters, buffer sizes. The
+//! crate's components already keep these (for example
+//! [`Demux::stats`](crate::demux::Demux::stats) or
+//! [`Relay::stats`](crate::relay::Relay::stats)); a [`Metrics`] registry
+//! collects them in one place so an exporter can read them all.
+//!
+//! Values are pulled, not pushed: each metric is a closure evaluated when
+//! [`samples`](Metrics::samples) is called, so the hot path pays nothing
+//! until someone scrapes. Sockets registered with
+//! [`socket`](Metrics::socket) report their kernel buffer sizes and are
+//! held weakly, so a closed socket simply disappears from the output
+//! instead of pinning its descriptor open.
+//!
+//! The registry is cheap to clone; clones share the same metrics.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::metrics::Metrics;
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::sync::Arc;
+//!
+//! let metrics = Metrics::new();
+//! let dropped = Arc::new(AtomicU64::new(0));
+//! let counter = dropped.clone();
+//! metrics.counter("packets_dropped_total", "Datagrams dropped by the handler", move || counter.load(Ordering::Relaxed) as f64);
+//!
+//! dropped.fetch_add(3, Ordering::Relaxed);
+//! let sample = metrics.samples().into_iter().find(|s| s.name == "packets_dropped_total").unwrap();
+//! assert_eq!(sample.value, 3.0);
+//! ```
+
+use crate::clock::Clock;
+use crate::poll::Pollable;
+use crate::raw as r;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+/// How a metric's value behaves over time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Only ever increases, except on restart
+    Counter,
+    /// Goes up and down
+    Gauge,
+}
+
+/// One metric value read from the registry
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Metric name, e.g. `packets_dropped_total`
+    pub name: String,
+    /// One-line description
+    pub help: &'static str,
+    /// Counter or gauge
+    pub kind: MetricKind,
+    /// Label pairs distinguishing series of the same name
+    pub labels: Vec<(&'static str, String)>,
+    /// Current value
+    pub value: f64,
+}
+
+type ReadFn = Box<dyn Fn() -> f64 + Send + Sync>;
+
+enum Source {
+    Value { name: String, help: &'static str, kind: MetricKind, read: ReadFn },
+    Socket { name: String, socket: Weak<dyn Pollable + Send + Sync> },
+}
+
+/// Shared registry of pull-based metrics
+#[derive(Clone)]
+pub struct Metrics {
+    sources: Arc<Mutex<Vec<Source>>>,
+    start: Instant,
+    clock: Clock,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").field("sources", &self.sources.lock().unwrap().len()).finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    /// Creates an empty registry; its creation time is the reported start time
+    pub fn new() -> Self {
+        Self { sources: Arc::new(Mutex::new(Vec::new())), start: Instant::now(), clock: Clock::default() }
+    }
+
+    /// Measures uptime with `clock`, restarting it now
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self.start = clock.now();
+        self
+    }
+
+    /// Registers a counter read by calling `read`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid metric name (`[a-zA-Z_:][a-zA-Z0-9_:]*`).
+    pub fn counter<F>(&self, name: impl Into<String>, help: &'static str, read: F)
+    where
+        F: Fn() -> f64 + Send + Sync + 'static,
+    {
+        self.add_value(name.into(), help, MetricKind::Counter, Box::new(read));
+    }
+
+    /// Registers a gauge read by calling `read`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid metric name (`[a-zA-Z_:][a-zA-Z0-9_:]*`).
+    pub fn gauge<F>(&self, name: impl Into<String>, help: &'static str, read: F)
+    where
+        F: Fn() -> f64 + Send + Sync + 'static,
+    {
+        self.add_value(name.into(), help, MetricKind::Gauge, Box::new(read));
+    }
+
+    /// Reports the kernel buffer sizes of `socket`, labelled `socket="name"`
+    ///
+    /// Only a weak reference is kept; once the last `Arc` is dropped the
+    /// socket stops appearing. On Linux the series also carry the socket's
+    /// `SO_COOKIE`, which identifies it unambiguously across restarts of
+    /// the same name.
+    pub fn socket<S: Pollable + Send + Sync + 'static>(&self, name: impl Into<String>, socket: &Arc<S>) {
+        let socket: Arc<dyn Pollable + Send + Sync> = socket.clone();
+        self.sources.lock().unwrap().push(Source::Socket { name: name.into(), socket: Arc::downgrade(&socket) });
+    }
+
+    /// Removes every metric and socket registered under `name`; returns `true` if any were
+    pub fn remove(&self, name: &str) -> bool {
+        let mut sources = self.sources.lock().unwrap();
+        let before = sources.len();
+        sources.retain(|s| match s {
+            Source::Value { name: n, .. } | Source::Socket { name: n, .. } => n != name,
+        });
+        sources.len() != before
+    }
+
+    /// Returns how long ago the registry was created
+    pub fn uptime(&self) -> std::time::Duration {
+        self.clock.elapsed(self.start)
+    }
+
+    /// Reads every metric, including `uptime_seconds`
+    ///
+    /// Sockets that have been dropped are pruned from the registry.
+    pub fn samples(&self) -> Vec<Sample> {
+        let mut out = vec![Sample {
+            name: "uptime_seconds".into(),
+            help: "Seconds since the metrics registry was created",
+            kind: MetricKind::Gauge,
+            labels: Vec::new(),
+            value: self.uptime().as_secs_f64(),
+        }];
+        let mut sources = self.sources.lock().unwrap();
+        sources.retain(|s| match s {
+            Source::Value { name, help, kind, read } => {
+                out.push(Sample { name: name.clone(), help, kind: *kind, labels: Vec::new(), value: read() });
+                true
+            }
+            Source::Socket { name, socket } => match socket.upgrade() {
+                Some(socket) => {
+                    socket_samples(name, socket.poll_handle(), &mut out);
+                    true
+                }
+                None => false,
+            },
+        });
+        out
+    }
+
+    fn add_value(&self, name: String, help: &'static str, kind: MetricKind, read: ReadFn) {
+        assert!(is_valid_name(&name), "invalid metric name {:?}", name);
+        self.sources.lock().unwrap().push(Source::Value { name, help, kind, read });
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends the buffer-size series for one socket
+fn socket_samples(name: &str, os: r::OsSocket, out: &mut Vec<Sample>) {
+    let mut labels = vec![("socket", name.to_string())];
+    if let Ok(cookie) = r::get_cookie(os) {
+        labels.push(("cookie", cookie.to_string()));
+    }
+    type ReadOpt = fn(r::OsSocket) -> std::io::Result<i32>;
+    let series: [(&str, &'static str, ReadOpt); 2] = [
+        ("socket_recv_buffer_bytes", "Kernel receive buffer size (SO_RCVBUF)", r::get_recv_buffer),
+        ("socket_send_buffer_bytes", "Kernel send buffer size (SO_SNDBUF)", r::get_send_buffer),
+    ];
+    for (metric, help, read) in series {
+        if let Ok(value) = read(os) {
+            out.push(Sample { name: metric.into(), help, kind: MetricKind::Gauge, labels: labels.clone(), value: value as f64 });
+        }
+    }
+}
+
+/// Checks a name against the Prometheus metric name grammar
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{udp::Udp, NetConfig};
+
+    #[test]
+    fn test_values_read_at_sample_time_and_removable() {
+        let metrics = Metrics::new();
+        let level = Arc::new(std::sync::atomic::AtomicU64::new(1));
+        let read = level.clone();
+        metrics.gauge("queue_depth", "Queued items", move || read.load(std::sync::atomic::Ordering::Relaxed) as f64);
+        level.store(7, std::sync::atomic::Ordering::Relaxed);
+
+        let samples = metrics.samples();
+        assert_eq!(samples[0].name, "uptime_seconds");
+        assert_eq!(samples[1].value, 7.0);
+        assert!(metrics.remove("queue_depth"));
+        assert_eq!(metrics.samples().len(), 1);
+        assert!(std::panic::catch_unwind(|| metrics.counter("bad name", "", || 0.0)).is_err());
+    }
+
+    #[test]
+    fn test_socket_series_vanish_when_dropped() {
+        let metrics = Metrics::new();
+        let udp = Arc::new(Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap());
+        metrics.socket("game", &udp);
+
+        let samples = metrics.samples();
+        let rcvbuf = samples.iter().find(|s| s.name == "socket_recv_buffer_bytes").unwrap();
+        assert!(rcvbuf.value > 0.0);
+        assert_eq!(rcvbuf.labels[0], ("socket", "game".to_string()));
+        #[cfg(target_os = "linux")]
+        assert_eq!(rcvbuf.labels[1], ("cookie", udp.cookie().unwrap().to_string()));
+
+        drop(udp);
+        assert_eq!(metrics.samples().len(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! Safe wrappers for batched `recvmmsg` and `sendmmsg` (Linux and Android)
+//!
+//! `recvmmsg` and `sendmmsg` take an array of `mmsghdr`, and each header
+//! points at its message's buffers, address, and control data. Building
+//! these arrays inline is where raw pointer casts and uninitialized
+//! buffers crept into the UDP code. This module keeps that unsafe code in
+//! one place:
+//!
+//! - [`RecvBatch`] owns the headers, address slots, and control buffers
+//!   for up to `capacity` messages. Address and control slots are
+//!   `MaybeUninit` storage that the kernel fills. The accessors only read
+//!   the bytes the kernel reported writing (`msg_namelen`,
+//!   `msg_controllen`), so nothing uninitialized is ever read.
+//! - [`SendBatch`] collects `(payload, destination)` pairs and submits
+//!   them in chunks of [`MAX_BATCH`], retrying on `EINTR` and stopping at
+//!   the first partial send.
+//!
+//! Payloads are passed as `IoSliceMut`/`IoSlice`, which have the same
+//! layout as `iovec` on Unix. The borrow checker therefore ties each
+//! buffer to the call that fills or sends it, and no pointer into caller
+//! memory outlives that call. The batches are reusable, so an event loop
+//! can keep one per socket and avoid allocating on every receive.
+//!
+//! The address slots hold a full `sockaddr_storage`, so Unix domain
+//! datagram sockets can use [`RecvBatch`] too: [`RecvBatch::name`] returns
+//! the raw address bytes for families [`RecvBatch::addr`] does not decode.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::mmsg::RecvBatch;
+//! use std::io::IoSliceMut;
+//! use std::os::fd::AsFd;
+//!
+//! let socket = std::net::UdpSocket::bind("0.0.0.0:9000")?;
+//! let mut batch = RecvBatch::new(32, 0);
+//! let mut storage = vec![[0u8; 1500]; 32];
+//! let mut bufs: Vec<IoSliceMut> = storage.iter_mut().map(|b| IoSliceMut::new(b)).collect();
+//! let n = batch.recv(socket.as_fd(), &mut bufs, 0)?;
+//! for i in 0..n {
+//!     println!("{} bytes from {:?}", batch.len(i), batch.addr(i));
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::error::Error;
+use crate::raw as r;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::mem::{size_of, MaybeUninit};
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+/// Kernel limit on messages per `sendmmsg`/`recvmmsg` call (`UIO_MAXIOV`)
+pub const MAX_BATCH: usize = 1024;
+
+/// An all-zero header: null pointers and zero lengths
+pub(crate) fn empty_header() -> libc::mmsghdr {
+    // SAFETY: mmsghdr is plain data; all-zero is a valid value (null pointers, zero lengths)
+    unsafe { MaybeUninit::zeroed().assume_init() }
+}
+
+/// Reusable header, address, and control storage for `recvmmsg`
+pub struct RecvBatch {
+    hdrs: Box<[libc::mmsghdr]>,
+    names: Box<[MaybeUninit<libc::sockaddr_storage>]>,
+    /// `control_words` u64s per message; u64 keeps each `cmsghdr` aligned
+    control: Box<[MaybeUninit<u64>]>,
+    control_words: usize,
+    received: usize,
+}
+
+// SAFETY: between calls the headers point only into the batch's own boxed
+// storage, which moves with it; caller buffers are unlinked before returning
+unsafe impl Send for RecvBatch {}
+
+impl std::fmt::Debug for RecvBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecvBatch")
+            .field("capacity", &self.capacity())
+            .field("control_len", &(self.control_words * 8))
+            .field("received", &self.received)
+            .finish()
+    }
+}
+
+impl RecvBatch {
+    /// Creates storage for up to `capacity` messages with `control_len` bytes of ancillary data each
+    ///
+    /// `capacity` is capped at [`MAX_BATCH`]; `control_len` is rounded up
+    /// to a multiple of 8. Pass 0 when no control messages are enabled.
+    pub fn new(capacity: usize, control_len: usize) -> Self {
+        let capacity = capacity.min(MAX_BATCH);
+        let control_words = control_len.div_ceil(8);
+        Self {
+            hdrs: (0..capacity).map(|_| empty_header()).collect(),
+            names: (0..capacity).map(|_| MaybeUninit::uninit()).collect(),
+            control: (0..capacity * control_words).map(|_| MaybeUninit::uninit()).collect(),
+            control_words,
+            received: 0,
+        }
+    }
+
+    /// Returns the most messages one [`recv`](Self::recv) can return
+    pub fn capacity(&self) -> usize {
+        self.hdrs.len()
+    }
+
+    /// Returns the number of messages the last [`recv`](Self::recv) returned
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Receives up to one message per buffer with a single `recvmmsg` call
+    ///
+    /// At most [`capacity`](Self::capacity) buffers are used. `flags` are
+    /// passed through, e.g. `MSG_DONTWAIT`. On error no messages are
+    /// reported.
+    ///
+    /// # Returns
+    ///
+    /// The number of messages received; read them with [`len`](Self::len),
+    /// [`addr`](Self::addr), and [`control`](Self::control).
+    pub fn recv(&mut self, fd: BorrowedFd<'_>, bufs: &mut [IoSliceMut<'_>], flags: libc::c_int) -> io::Result<usize> {
+        // SAFETY: IoSliceMut is guaranteed to be ABI-compatible with iovec on Unix, and
+        // each slice is valid for writes for the duration of the call
+        unsafe { self.recv_iovecs(fd, std::slice::from_raw_parts_mut(bufs.as_mut_ptr().cast(), bufs.len()), flags) }
+    }
+
+    /// Like [`recv`](Self::recv), over raw `iovec`s that may point at uninitialized memory
+    ///
+    /// # Safety
+    ///
+    /// Every iovec must describe memory valid for writes of `iov_len` bytes.
+    pub(crate) unsafe fn recv_iovecs(&mut self, fd: BorrowedFd<'_>, iovecs: &mut [libc::iovec], flags: libc::c_int) -> io::Result<usize> {
+        self.received = 0;
+        let max = iovecs.len().min(self.capacity());
+        let control_bytes = self.control_words * 8;
+        for (i, iov) in iovecs[..max].iter_mut().enumerate() {
+            let mut hdr = empty_header();
+            hdr.msg_hdr.msg_name = self.names[i].as_mut_ptr().cast();
+            hdr.msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as _;
+            hdr.msg_hdr.msg_iov = iov;
+            hdr.msg_hdr.msg_iovlen = 1;
+            if control_bytes > 0 {
+                hdr.msg_hdr.msg_control = self.control[i * self.control_words..].as_mut_ptr().cast();
+                hdr.msg_hdr.msg_controllen = control_bytes as _;
+            }
+            self.hdrs[i] = hdr;
+        }
+
+        // SAFETY: every header in 0..max points at live storage owned by self or valid per the contract
+        let rc = unsafe { libc::recvmmsg(fd.as_raw_fd(), self.hdrs.as_mut_ptr(), max as _, flags, std::ptr::null_mut()) };
+        // Drop the pointers into the caller's buffers before the borrow ends
+        for hdr in &mut self.hdrs[..max] {
+            hdr.msg_hdr.msg_iov = std::ptr::null_mut();
+        }
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.received = rc as usize;
+        Ok(self.received)
+    }
+
+    fn header(&self, i: usize) -> &libc::msghdr {
+        assert!(i < self.received, "message {} not received (got {})", i, self.received);
+        &self.hdrs[i].msg_hdr
+    }
+
+    /// Returns the payload length of message `i`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not below [`received`](Self::received), as do the other accessors.
+    pub fn len(&self, i: usize) -> usize {
+        self.header(i);
+        self.hdrs[i].msg_len as usize
+    }
+
+    /// Returns `true` if message `i` did not fit its buffer (`MSG_TRUNC`)
+    pub fn truncated(&self, i: usize) -> bool {
+        self.header(i).msg_flags & libc::MSG_TRUNC != 0
+    }
+
+    /// Returns the raw sender address bytes of message `i`, as long as the kernel reported
+    ///
+    /// Empty when the kernel reports no address, e.g. for unnamed Unix domain peers.
+    pub fn name(&self, i: usize) -> &[u8] {
+        let len = (self.header(i).msg_namelen as usize).min(size_of::<libc::sockaddr_storage>());
+        // SAFETY: the kernel initialized the first msg_namelen bytes of this slot
+        unsafe { std::slice::from_raw_parts(self.names[i].as_ptr().cast(), len) }
+    }
+
+    /// Decodes the IPv4 or IPv6 sender of message `i`
+    pub fn addr(&self, i: usize) -> Option<SocketAddr> {
+        crate::parse::sockaddr(self.name(i))
+    }
+
+    /// Returns the control data of message `i`, ready for [`parse::cmsgs`](crate::parse::cmsgs)
+    pub fn control(&self, i: usize) -> &[u8] {
+        let hdr = self.header(i);
+        let len: usize = hdr.msg_controllen as _;
+        if self.control_words == 0 || len == 0 {
+            return &[];
+        }
+        let len = len.min(self.control_words * 8);
+        // SAFETY: the kernel initialized the first msg_controllen bytes of this slot
+        unsafe { std::slice::from_raw_parts(self.control[i * self.control_words..].as_ptr().cast(), len) }
+    }
+}
+
+/// Destinations and payloads queued for `sendmmsg`
+///
+/// Borrows each payload until the batch is cleared or dropped.
+#[derive(Default)]
+pub struct SendBatch<'a> {
+    names: Vec<(r::SockAddr, libc::socklen_t)>,
+    bufs: Vec<IoSlice<'a>>,
+    hdrs: Vec<libc::mmsghdr>,
+}
+
+impl std::fmt::Debug for SendBatch<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendBatch").field("len", &self.len()).finish()
+    }
+}
+
+impl<'a> SendBatch<'a> {
+    /// Creates an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty batch with room for `capacity` messages
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            names: Vec::with_capacity(capacity),
+            bufs: Vec::with_capacity(capacity),
+            hdrs: Vec::with_capacity(capacity.min(MAX_BATCH)),
+        }
+    }
+
+    /// Queues one datagram
+    pub fn push(&mut self, buf: &'a [u8], dest: SocketAddr) {
+        let (_, name, len) = r::to_sockaddr(dest);
+        self.names.push((name, len));
+        self.bufs.push(IoSlice::new(buf));
+    }
+
+    /// Returns the number of queued datagrams
+    pub fn len(&self) -> usize {
+        self.bufs.len()
+    }
+
+    /// Returns `true` if nothing is queued
+    pub fn is_empty(&self) -> bool {
+        self.bufs.is_empty()
+    }
+
+    /// Removes all queued datagrams, keeping the allocations
+    pub fn clear(&mut self) {
+        self.names.clear();
+        self.bufs.clear();
+    }
+
+    /// Sends the queued datagrams in order, up to [`MAX_BATCH`] per `sendmmsg` call
+    ///
+    /// Stops early when the socket would block. A hard error after some
+    /// datagrams went out is returned as
+    /// [`Error::PartialBatch`](crate::Error::PartialBatch) carrying the sent
+    /// count. The batch is left intact, so the caller can resend the unsent
+    /// tail.
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams sent
+    pub fn send(&mut self, fd: BorrowedFd<'_>, flags: libc::c_int) -> io::Result<usize> {
+        let mut sent = 0;
+        while sent < self.len() {
+            let end = self.len().min(sent + MAX_BATCH);
+            self.hdrs.clear();
+            for ((name, len), buf) in self.names[sent..end].iter().zip(&self.bufs[sent..end]) {
+                let mut hdr = empty_header();
+                hdr.msg_hdr.msg_name = match name {
+                    r::SockAddr::V4(s) => s as *const _ as *mut libc::c_void,
+                    r::SockAddr::V6(s) => s as *const _ as *mut libc::c_void,
+                };
+                hdr.msg_hdr.msg_namelen = *len;
+                // IoSlice is ABI-compatible with iovec; the kernel only reads through it
+                hdr.msg_hdr.msg_iov = buf as *const IoSlice<'_> as *mut libc::iovec;
+                hdr.msg_hdr.msg_iovlen = 1;
+                self.hdrs.push(hdr);
+            }
+            let done = match sendmmsg_all(fd, &mut self.hdrs, flags) {
+                Ok(done) => done,
+                Err(e) => {
+                    self.hdrs.clear();
+                    return Err(Error::partial(sent, e));
+                }
+            };
+            sent += done;
+            if done < self.hdrs.len() {
+                break;
+            }
+        }
+        self.hdrs.clear();
+        Ok(sent)
+    }
+}
+
+/// Submits `hdrs` with `sendmmsg` until all are sent or the socket would block
+///
+/// Returns the number of messages sent. A hard error after some messages
+/// went out is returned as [`Error::PartialBatch`] with the sent count.
+pub(crate) fn sendmmsg_all(fd: BorrowedFd<'_>, hdrs: &mut [libc::mmsghdr], flags: libc::c_int) -> io::Result<usize> {
+    let mut done = 0;
+    while done < hdrs.len() {
+        // SAFETY: the headers point into the SendBatch, which outlives this call
+        let rc = unsafe { libc::sendmmsg(fd.as_raw_fd(), hdrs[done..].as_mut_ptr(), (hdrs.len() - done) as _, flags) };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => break,
+                _ => return Err(Error::partial(done, err)),
+            }
+        }
+        done += rc as usize;
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn test_send_and_recv_batches_over_loopback() {
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = rx.local_addr().unwrap();
+
+        let payloads: [&[u8]; 3] = [b"one", b"two!", b"three-and-more"];
+        let mut out = SendBatch::with_capacity(3);
+        for p in payloads {
+            out.push(p, dest);
+        }
+        assert_eq!(out.send(tx.as_fd(), 0).unwrap(), 3);
+
+        // Four buffers but capacity two: the third datagram stays queued
+        let mut batch = RecvBatch::new(2, 0);
+        let mut storage = [[0u8; 8]; 4];
+        let mut bufs: Vec<IoSliceMut> = storage.iter_mut().map(|b| IoSliceMut::new(b)).collect();
+        assert_eq!(batch.recv(rx.as_fd(), &mut bufs, 0).unwrap(), 2);
+        drop(bufs);
+        for i in 0..2 {
+            assert_eq!(&storage[i][..batch.len(i)], payloads[i]);
+            assert_eq!(batch.addr(i), Some(tx.local_addr().unwrap()));
+            assert!(!batch.truncated(i) && batch.control(i).is_empty());
+        }
+
+        let mut small = [0u8; 4];
+        assert_eq!(batch.recv(rx.as_fd(), &mut [IoSliceMut::new(&mut small)], 0).unwrap(), 1);
+        assert!(batch.truncated(0));
+        assert_eq!(&small, b"thre");
+        assert!(batch.recv(rx.as_fd(), &mut [IoSliceMut::new(&mut small)], libc::MSG_DONTWAIT).is_err());
+        assert_eq!(batch.received(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not received")]
+    fn test_accessors_reject_unreceived_slots() {
+        // Slots the kernel never wrote must not be readable
+        let batch = RecvBatch::new(4, 64);
+        assert_eq!((batch.capacity(), batch.received()), (4, 0));
+        batch.name(0);
+    }
+}
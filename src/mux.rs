@@ -0,0 +1,570 @@
+//! Single-port listener that routes connections by their first bytes
+//!
+//! Serving TLS, plaintext HTTP, and a custom protocol on one port (443
+//! behind a restrictive firewall, or one port per container) means looking
+//! at what each client sends before deciding who handles it. A [`PortMux`]
+//! accepts connections, [`peek`](crate::tcp::TcpStream::peek)s at their
+//! first bytes without consuming them, and hands each stream to the first
+//! registered route whose detector matches. The handler sees the stream
+//! from its very first byte, exactly as if it had accepted it itself.
+//!
+//! Detectors are plain functions of the bytes seen so far returning a
+//! [`Detect`]. Built-in ones cover [`tls`], [`tls_alpn`] (routing TLS by
+//! the ALPN protocol in the ClientHello, say `h2` to one handler and
+//! `http/1.1` to another), [`http1`], [`http2_prior_knowledge`], and
+//! [`proxy_protocol`]. Routes are tried in registration order; a route
+//! that needs more bytes holds the decision until they arrive.
+//!
+//! Connections that match nothing go to the [`fallback`](PortMux::fallback)
+//! handler, or are closed without one. Connections that send too little
+//! within the [`timeout`](PortMux::timeout) are closed, and at most
+//! [`max_pending`](PortMux::max_pending) may be waiting at once; admitting
+//! another closes the oldest.
+//!
+//! # Runtime Integration
+//!
+//! On Unix, `PortMux` is a mio event source. Register it once; it registers
+//! waiting connections under the same token, so every event on that token
+//! is a call to [`ready`](PortMux::ready). Without a runtime, call `ready`
+//! in a loop. Either way, [`next_deadline`](PortMux::next_deadline) bounds
+//! the wait so timeouts fire.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use horizon_sockets::mux::{self, PortMux};
+//! use mio::{Events, Interest, Poll, Token};
+//! use std::time::Instant;
+//!
+//! let listener = TcpListener::bind("0.0.0.0:8443".parse()?, &NetConfig::default())?;
+//! let mut mux = PortMux::new(listener)
+//!     .route("h2", mux::tls_alpn(b"h2"), |_stream, peer| println!("HTTP/2 over TLS from {}", peer))
+//!     .route("tls", mux::tls, |_stream, peer| println!("TLS from {}", peer))
+//!     .route("http", mux::http1, |_stream, peer| println!("plaintext HTTP from {}", peer));
+//!
+//! let mut poll = Poll::new()?;
+//! let mut events = Events::with_capacity(256);
+//! # #[cfg(unix)]
+//! poll.registry().register(&mut mux, Token(0), Interest::READABLE)?;
+//! loop {
+//!     let timeout = mux.next_deadline().map(|d| d.saturating_duration_since(Instant::now()));
+//!     // Only the mux is registered, so every event is for it
+//!     poll.poll(&mut events, timeout)?;
+//!     mux.ready()?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::tcp::{TcpListener, TcpStream};
+use crate::trace;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Verdict of a detector on the bytes received so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detect {
+    /// The connection speaks this route's protocol
+    Match,
+    /// The connection does not speak this route's protocol
+    NoMatch,
+    /// Too few bytes to decide
+    NeedMore,
+}
+
+type Detector = Box<dyn Fn(&[u8]) -> Detect + Send>;
+type Handler = Box<dyn FnMut(TcpStream, SocketAddr) + Send>;
+
+struct Route {
+    name: String,
+    detect: Detector,
+    handler: Handler,
+}
+
+#[derive(Debug)]
+struct Pending {
+    stream: TcpStream,
+    peer: SocketAddr,
+    deadline: Instant,
+}
+
+/// What became of a waiting connection after a peek
+enum Outcome {
+    Wait,
+    Route(usize),
+    Fallback,
+    Close,
+}
+
+/// Listener dispatching accepted connections to handlers by protocol
+pub struct PortMux {
+    listener: TcpListener,
+    routes: Vec<Route>,
+    fallback: Option<Handler>,
+    /// Connections waiting for enough bytes to classify, oldest first
+    pending: Vec<Pending>,
+    timeout: Duration,
+    max_pending: usize,
+    peek_buf: Vec<u8>,
+    #[cfg(all(feature = "mio-runtime", unix))]
+    registration: Option<(mio::Registry, mio::Token)>,
+}
+
+impl std::fmt::Debug for PortMux {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortMux")
+            .field("listener", &self.listener)
+            .field("routes", &self.routes.iter().map(|r| r.name.as_str()).collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .field("pending", &self.pending.len())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl PortMux {
+    /// Wraps `listener` with no routes, a 5 second timeout, and 1024 pending connections
+    pub fn new(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            routes: Vec::new(),
+            fallback: None,
+            pending: Vec::new(),
+            timeout: Duration::from_secs(5),
+            max_pending: 1024,
+            peek_buf: vec![0; 1024],
+            #[cfg(all(feature = "mio-runtime", unix))]
+            registration: None,
+        }
+    }
+
+    /// Adds a route tried after those added before it
+    ///
+    /// `name` identifies the route in traces. The handler receives the
+    /// stream with nothing consumed and, on Unix, deregistered from the
+    /// runtime.
+    pub fn route<D, H>(mut self, name: &str, detect: D, handler: H) -> Self
+    where
+        D: Fn(&[u8]) -> Detect + Send + 'static,
+        H: FnMut(TcpStream, SocketAddr) + Send + 'static,
+    {
+        self.routes.push(Route { name: name.to_string(), detect: Box::new(detect), handler: Box::new(handler) });
+        self
+    }
+
+    /// Sets the handler for connections no route matches
+    pub fn fallback<H: FnMut(TcpStream, SocketAddr) + Send + 'static>(mut self, handler: H) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets how long a connection may take to send enough bytes to classify
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many connections may wait for classification at once
+    pub fn max_pending(mut self, max: usize) -> Self {
+        self.max_pending = max.max(1);
+        self
+    }
+
+    /// Sets the most bytes detectors are shown (default 1024)
+    ///
+    /// A detector still answering [`Detect::NeedMore`] once this many bytes
+    /// are buffered is treated as [`Detect::NoMatch`]. Raise it for
+    /// [`tls_alpn`] if clients send large ClientHellos (post-quantum key
+    /// shares push them past 1KB).
+    pub fn peek_len(mut self, len: usize) -> Self {
+        self.peek_buf = vec![0; len.max(1)];
+        self
+    }
+
+    /// Returns the underlying listener
+    pub fn listener(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    /// Returns the number of connections waiting for classification
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the earliest timeout among waiting connections
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.first().map(|p| p.deadline)
+    }
+
+    /// Accepts new connections, classifies waiting ones, and dispatches them
+    ///
+    /// Never blocks. Closes connections that timed out, hit end of stream,
+    /// or failed, and those no route matches when there is no fallback.
+    ///
+    /// # Returns
+    ///
+    /// The number of connections handed to a route or the fallback
+    pub fn ready(&mut self) -> io::Result<usize> {
+        let now = Instant::now();
+        loop {
+            match self.listener.accept_nonblocking() {
+                Ok((stream, peer)) => self.admit(stream, peer, now),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // The peer gave up before we accepted; keep draining the queue
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut dispatched = 0;
+        let mut i = 0;
+        while i < self.pending.len() {
+            let outcome = self.classify(i, now);
+            if let Outcome::Wait = outcome {
+                i += 1;
+                continue;
+            }
+            // Keep the oldest-first order that next_deadline relies on
+            let Pending { mut stream, peer, .. } = self.pending.remove(i);
+            self.unwatch(&mut stream);
+            match outcome {
+                Outcome::Route(r) => {
+                    trace::event!(debug, route = %self.routes[r].name, %peer, "mux dispatch");
+                    (self.routes[r].handler)(stream, peer);
+                    dispatched += 1;
+                }
+                Outcome::Fallback => {
+                    if let Some(handler) = self.fallback.as_mut() {
+                        handler(stream, peer);
+                        dispatched += 1;
+                    } else {
+                        trace::event!(debug, %peer, "mux closed unmatched connection");
+                    }
+                }
+                Outcome::Wait | Outcome::Close => {}
+            }
+        }
+        Ok(dispatched)
+    }
+
+    fn admit(&mut self, mut stream: TcpStream, peer: SocketAddr, now: Instant) {
+        if self.pending.len() >= self.max_pending {
+            let mut oldest = self.pending.remove(0);
+            self.unwatch(&mut oldest.stream);
+        }
+        self.watch(&mut stream);
+        self.pending.push(Pending { stream, peer, deadline: now + self.timeout });
+    }
+
+    fn classify(&mut self, i: usize, now: Instant) -> Outcome {
+        let pending = &self.pending[i];
+        let n = match pending.stream.peek(&mut self.peek_buf) {
+            Ok(0) => return Outcome::Close,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+            Err(_) => return Outcome::Close,
+        };
+        let full = n == self.peek_buf.len();
+        let seen = &self.peek_buf[..n];
+        for (r, route) in self.routes.iter().enumerate() {
+            match (route.detect)(seen) {
+                Detect::Match => return Outcome::Route(r),
+                Detect::NeedMore if !full => {
+                    return if now >= pending.deadline { Outcome::Close } else { Outcome::Wait };
+                }
+                Detect::NoMatch | Detect::NeedMore => {}
+            }
+        }
+        if n == 0 {
+            return if now >= pending.deadline { Outcome::Close } else { Outcome::Wait };
+        }
+        Outcome::Fallback
+    }
+
+    #[cfg(all(feature = "mio-runtime", unix))]
+    fn watch(&self, stream: &mut TcpStream) {
+        if let Some((registry, token)) = &self.registration {
+            let _ = registry.register(stream, *token, mio::Interest::READABLE);
+        }
+    }
+
+    #[cfg(all(feature = "mio-runtime", unix))]
+    fn unwatch(&self, stream: &mut TcpStream) {
+        if let Some((registry, _)) = &self.registration {
+            let _ = registry.deregister(stream);
+        }
+    }
+
+    #[cfg(not(all(feature = "mio-runtime", unix)))]
+    fn watch(&self, _stream: &mut TcpStream) {}
+
+    #[cfg(not(all(feature = "mio-runtime", unix)))]
+    fn unwatch(&self, _stream: &mut TcpStream) {}
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for PortMux {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        registry.register(&mut self.listener, token, interests)?;
+        self.registration = Some((registry.try_clone()?, token));
+        for p in &mut self.pending {
+            registry.register(&mut p.stream, token, mio::Interest::READABLE)?;
+        }
+        Ok(())
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        registry.reregister(&mut self.listener, token, interests)?;
+        self.registration = Some((registry.try_clone()?, token));
+        for p in &mut self.pending {
+            registry.reregister(&mut p.stream, token, mio::Interest::READABLE)?;
+        }
+        Ok(())
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        registry.deregister(&mut self.listener)?;
+        for p in &mut self.pending {
+            registry.deregister(&mut p.stream)?;
+        }
+        self.registration = None;
+        Ok(())
+    }
+}
+
+/// Compares `seen` against the start of `prefix`
+fn starts_with(seen: &[u8], prefix: &[u8]) -> Detect {
+    let n = seen.len().min(prefix.len());
+    if seen[..n] != prefix[..n] {
+        Detect::NoMatch
+    } else if n < prefix.len() {
+        Detect::NeedMore
+    } else {
+        Detect::Match
+    }
+}
+
+/// Matches a TLS handshake record (any TLS or SSL 3.0 version)
+pub fn tls(seen: &[u8]) -> Detect {
+    match seen {
+        [] => Detect::NeedMore,
+        [0x16] => Detect::NeedMore,
+        [0x16, 0x03, ..] => Detect::Match,
+        _ => Detect::NoMatch,
+    }
+}
+
+/// Matches a TLS ClientHello offering `protocol` through ALPN
+///
+/// Waits for the whole first record. ClientHellos without ALPN, or
+/// spread over several records, do not match.
+pub fn tls_alpn(protocol: &'static [u8]) -> impl Fn(&[u8]) -> Detect + Send + 'static {
+    move |seen: &[u8]| {
+        match tls(seen) {
+            Detect::Match => {}
+            other => return other,
+        }
+        if seen.len() < 5 {
+            return Detect::NeedMore;
+        }
+        let record_len = usize::from(u16::from_be_bytes([seen[3], seen[4]]));
+        let Some(record) = seen.get(5..5 + record_len) else { return Detect::NeedMore };
+        match alpn_protocols(record) {
+            Some(protocols) => {
+                let mut protocols = protocols;
+                if protocols.any(|p| p == protocol) { Detect::Match } else { Detect::NoMatch }
+            }
+            None => Detect::NoMatch,
+        }
+    }
+}
+
+/// Iterates over the ALPN protocol names in a ClientHello handshake message
+fn alpn_protocols(hello: &[u8]) -> Option<impl Iterator<Item = &[u8]>> {
+    let mut r = Reader(hello);
+    if r.u8()? != 1 {
+        return None;
+    }
+    let len = r.u24()?;
+    let mut body = Reader(r.take(len)?);
+    body.take(2 + 32)?; // version, random
+    let n = body.u8()?;
+    body.take(usize::from(n))?; // session id
+    let n = body.u16()?;
+    body.take(usize::from(n))?; // cipher suites
+    let n = body.u8()?;
+    body.take(usize::from(n))?; // compression methods
+    let n = body.u16()?;
+    let mut exts = Reader(body.take(usize::from(n))?);
+    while !exts.0.is_empty() {
+        let kind = exts.u16()?;
+        let n = exts.u16()?;
+        let data = exts.take(usize::from(n))?;
+        if kind == 16 {
+            let mut list = Reader(data);
+            let n = list.u16()?;
+            let mut names = Reader(list.take(usize::from(n))?);
+            return Some(std::iter::from_fn(move || {
+                let n = names.u8()?;
+                names.take(usize::from(n))
+            }));
+        }
+    }
+    None
+}
+
+/// Bounds-checked big-endian reader over a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.take(3).map(|b| usize::from(b[0]) << 16 | usize::from(b[1]) << 8 | usize::from(b[2]))
+    }
+}
+
+/// Matches an HTTP/1.x request line by its method
+pub fn http1(seen: &[u8]) -> Detect {
+    const METHODS: [&[u8]; 9] =
+        [b"GET ", b"POST ", b"PUT ", b"HEAD ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT ", b"TRACE "];
+    let mut verdict = Detect::NoMatch;
+    for method in METHODS {
+        match starts_with(seen, method) {
+            Detect::Match => return Detect::Match,
+            Detect::NeedMore => verdict = Detect::NeedMore,
+            Detect::NoMatch => {}
+        }
+    }
+    verdict
+}
+
+/// Matches the HTTP/2 connection preface sent by prior-knowledge (h2c) clients
+pub fn http2_prior_knowledge(seen: &[u8]) -> Detect {
+    starts_with(seen, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+}
+
+/// Matches a PROXY protocol header, version 1 (text) or 2 (binary)
+///
+/// The handler must parse and consume the header before the proxied
+/// protocol's bytes.
+pub fn proxy_protocol(seen: &[u8]) -> Detect {
+    match starts_with(seen, b"PROXY ") {
+        Detect::NoMatch => starts_with(seen, b"\r\n\r\n\0\r\nQUIT\n"),
+        verdict => verdict,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+    use std::io::Write;
+    use std::net::TcpStream as StdTcpStream;
+    use std::sync::{Arc, Mutex};
+
+    /// A TLS 1.3 ClientHello record offering the given ALPN protocols
+    fn client_hello(alpn: &[&[u8]]) -> Vec<u8> {
+        let mut list = Vec::new();
+        for p in alpn {
+            list.push(p.len() as u8);
+            list.extend_from_slice(p);
+        }
+        let mut ext = vec![0x00, 0x00, 0x00, 0x00]; // empty server_name
+        ext.extend_from_slice(&16u16.to_be_bytes());
+        ext.extend_from_slice(&(list.len() as u16 + 2).to_be_bytes());
+        ext.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&list);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[7; 32]);
+        body.extend_from_slice(&[0, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        body.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+        body.extend_from_slice(&ext);
+
+        let mut hs = vec![0x01, 0, (body.len() >> 8) as u8, body.len() as u8];
+        hs.extend_from_slice(&body);
+        let mut record = vec![0x16, 0x03, 0x01, (hs.len() >> 8) as u8, hs.len() as u8];
+        record.extend_from_slice(&hs);
+        record
+    }
+
+    #[test]
+    fn test_detectors() {
+        assert_eq!(http1(b"GE"), Detect::NeedMore);
+        assert_eq!(http1(b"GET / HTTP/1.1"), Detect::Match);
+        assert_eq!(http1(b"\x16\x03"), Detect::NoMatch);
+        assert_eq!(tls(b"\x16\x03\x01"), Detect::Match);
+        assert_eq!(proxy_protocol(b"\r\n\r\n\0\r\nQUIT\n\x21"), Detect::Match);
+        assert_eq!(http2_prior_knowledge(b"PRI * HTTP/2.0\r\n"), Detect::NeedMore);
+
+        let hello = client_hello(&[b"h2", b"http/1.1"]);
+        assert_eq!(tls_alpn(b"h2")(&hello), Detect::Match);
+        assert_eq!(tls_alpn(b"http/1.1")(&hello), Detect::Match);
+        assert_eq!(tls_alpn(b"h3")(&hello), Detect::NoMatch);
+        assert_eq!(tls_alpn(b"h2")(&hello[..40]), Detect::NeedMore);
+        assert_eq!(tls_alpn(b"h2")(&client_hello(&[])), Detect::NoMatch);
+    }
+
+    #[test]
+    fn test_connections_routed_by_first_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (a, b, c) = (seen.clone(), seen.clone(), seen.clone());
+        let mut mux = PortMux::new(listener)
+            .timeout(Duration::from_millis(100))
+            .route("h2", tls_alpn(b"h2"), move |_, _| a.lock().unwrap().push("h2"))
+            .route("http", http1, move |stream, _| {
+                let mut buf = [0u8; 4];
+                stream.peek(&mut buf).unwrap();
+                assert_eq!(&buf, b"GET ");
+                b.lock().unwrap().push("http");
+            })
+            .fallback(move |_, _| c.lock().unwrap().push("other"));
+
+        let mut http = StdTcpStream::connect(addr).unwrap();
+        let mut tls = StdTcpStream::connect(addr).unwrap();
+        let mut other = StdTcpStream::connect(addr).unwrap();
+        let _silent = StdTcpStream::connect(addr).unwrap();
+        http.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        let hello = client_hello(&[b"h2"]);
+        // Split the ClientHello so the first peek sees a partial record
+        tls.write_all(&hello[..20]).unwrap();
+        other.write_all(b"SSH-2.0-OpenSSH\r\n").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut dispatched = 0;
+        while dispatched < 2 && Instant::now() < deadline {
+            dispatched += mux.ready().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        tls.write_all(&hello[20..]).unwrap();
+        while (dispatched < 3 || mux.pending() > 0) && Instant::now() < deadline {
+            dispatched += mux.ready().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, ["h2", "http", "other"]);
+        // The silent connection timed out
+        assert_eq!(mux.pending(), 0);
+    }
+}
@@ -0,0 +1,170 @@
+//! NAPI-aware grouping of sockets across event loops
+//!
+//! Epoll busy polling (see `Runtime::set_busy_poll` in the mio runtime and
+//! [`NetConfig::busy_poll`](crate::NetConfig::busy_poll)) polls the NAPI
+//! context of the sockets in the ready list. It only pays off when every
+//! socket an event loop handles is fed by the same NIC receive queue;
+//! otherwise the loop busy polls one queue while packets wait on another.
+//!
+//! [`NapiGroups`] reads `SO_INCOMING_NAPI_ID` for each socket, groups them by
+//! receive queue, and [`assign`](NapiGroups::assign)s whole groups to event
+//! loops so no queue is split across loops.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, TcpListener};
+//! use horizon_sockets::napi::NapiGroups;
+//!
+//! let listener = TcpListener::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut groups = NapiGroups::new();
+//!
+//! // Accepted streams carry the NAPI ID of the queue that received the SYN
+//! for (id, conn) in listener.try_incoming().enumerate() {
+//!     let (stream, _) = conn?;
+//!     groups.add(id, &stream)?;
+//! }
+//!
+//! // One list of connection ids per event loop thread
+//! for (worker, conns) in groups.assign(4).into_iter().enumerate() {
+//!     println!("loop {} handles {:?}", worker, conns);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Platform Support
+//!
+//! Linux 4.12+. Elsewhere [`napi_id`] returns `Unsupported`; use
+//! [`NapiGroups::insert`] with `None` to fall back to plain balancing.
+
+use crate::poll::Pollable;
+use crate::raw as r;
+use std::collections::BTreeMap;
+use std::io;
+
+/// Returns the NAPI ID of the receive queue that last delivered to `socket`
+///
+/// `Ok(None)` means the socket has not received traffic through a NAPI
+/// device yet (for example, loopback traffic or a freshly bound socket).
+pub fn napi_id(socket: &dyn Pollable) -> io::Result<Option<u32>> {
+    let id = r::get_incoming_napi_id(socket.poll_handle())?;
+    Ok(if id == 0 { None } else { Some(id) })
+}
+
+/// Sockets grouped by the NIC receive queue (NAPI context) that feeds them
+///
+/// `K` is whatever the application uses to identify a socket: a token,
+/// a connection id, or the socket itself.
+#[derive(Clone, Debug)]
+pub struct NapiGroups<K> {
+    /// Sockets per NAPI ID
+    groups: BTreeMap<u32, Vec<K>>,
+    /// Sockets without a known NAPI ID
+    ungrouped: Vec<K>,
+}
+
+impl<K> NapiGroups<K> {
+    /// Creates an empty grouping
+    pub fn new() -> Self {
+        Self { groups: BTreeMap::new(), ungrouped: Vec::new() }
+    }
+
+    /// Reads the socket's NAPI ID and records `key` in the matching group
+    ///
+    /// # Returns
+    ///
+    /// The NAPI ID the socket was grouped under, or `None` if it is not known yet
+    pub fn add(&mut self, key: K, socket: &dyn Pollable) -> io::Result<Option<u32>> {
+        let id = napi_id(socket)?;
+        self.insert(key, id);
+        Ok(id)
+    }
+
+    /// Records `key` under a NAPI ID obtained elsewhere
+    pub fn insert(&mut self, key: K, napi_id: Option<u32>) {
+        match napi_id {
+            Some(id) => self.groups.entry(id).or_default().push(key),
+            None => self.ungrouped.push(key),
+        }
+    }
+
+    /// Returns the sockets per NAPI ID
+    pub fn groups(&self) -> &BTreeMap<u32, Vec<K>> {
+        &self.groups
+    }
+
+    /// Returns the sockets whose NAPI ID is unknown
+    pub fn ungrouped(&self) -> &[K] {
+        &self.ungrouped
+    }
+
+    /// Splits the sockets across `loops` event loops
+    ///
+    /// Every NAPI group goes to a single loop; largest groups are placed
+    /// first on the least loaded loop. Sockets without a NAPI ID then fill
+    /// the least loaded loops one by one. With more groups than loops some
+    /// loops serve several queues, which still never splits a queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `loops` is 0.
+    pub fn assign(self, loops: usize) -> Vec<Vec<K>> {
+        assert!(loops > 0, "at least one event loop is required");
+        let mut out: Vec<Vec<K>> = (0..loops).map(|_| Vec::new()).collect();
+
+        let mut groups: Vec<Vec<K>> = self.groups.into_values().collect();
+        groups.sort_by_key(|g| std::cmp::Reverse(g.len()));
+        for group in groups {
+            least_loaded(&mut out).extend(group);
+        }
+        for key in self.ungrouped {
+            least_loaded(&mut out).push(key);
+        }
+        out
+    }
+}
+
+impl<K> Default for NapiGroups<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn least_loaded<K>(loops: &mut [Vec<K>]) -> &mut Vec<K> {
+    loops.iter_mut().min_by_key(|l| l.len()).expect("loops is not empty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_keeps_groups_together() {
+        let mut groups = NapiGroups::new();
+        for key in 0..4 {
+            groups.insert(key, Some(100));
+        }
+        for key in 4..6 {
+            groups.insert(key, Some(200));
+        }
+        groups.insert(6, Some(300));
+        groups.insert(7, None);
+        groups.insert(8, None);
+
+        let loops = groups.assign(2);
+        assert_eq!(loops[0], vec![0, 1, 2, 3, 8]);
+        assert_eq!(loops[1], vec![4, 5, 6, 7]);
+        // Groups are never split, ungrouped sockets balance the rest
+        assert_eq!(loops.iter().map(Vec::len).sum::<usize>(), 9);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loopback_socket_has_no_napi_id() {
+        let cfg = crate::NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let mut groups = NapiGroups::new();
+        assert_eq!(groups.add("udp", &socket).unwrap(), None);
+        assert_eq!(groups.ungrouped(), &["udp"]);
+    }
+}
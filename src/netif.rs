@@ -0,0 +1,607 @@
+//! Network interface enumeration
+//!
+//! Binding to a device, picking the interface for multicast, or computing a
+//! subnet's broadcast address all start from the list of local interfaces.
+//! [`interfaces`] returns that list with names, indexes, hardware
+//! addresses, MTUs, state flags, and assigned addresses, from
+//! `getifaddrs` on Unix and `GetAdaptersAddresses` on Windows.
+//!
+//! [`mtu`] and [`route_mtu`] size datagrams before path MTU discovery has
+//! had a chance to run: the first reports a link's MTU, the second asks the
+//! routing table which link (and route MTU override) a destination uses.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::netif;
+//!
+//! for iface in netif::interfaces()? {
+//!     if iface.flags.up && !iface.flags.loopback {
+//!         for addr in &iface.addrs {
+//!             println!("{} (#{}) {}/{}", iface.name, iface.index, addr.ip, addr.prefix_len);
+//!         }
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Platform Support
+//!
+//! - **Linux**: Everything; MTUs come from sysfs, routes from netlink
+//! - **macOS/BSD**: MAC addresses from `AF_LINK` entries; MTU is not reported
+//!   and [`route_mtu`] returns `Unsupported`
+//! - **Windows**: Interfaces are named by their friendly name; broadcast
+//!   addresses are computed from the prefix; routes from `GetBestRoute2`
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// State flags of an interface
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterfaceFlags {
+    /// Administratively up
+    pub up: bool,
+    /// Operationally up (carrier present)
+    pub running: bool,
+    /// Loopback interface
+    pub loopback: bool,
+    /// Supports broadcast
+    pub broadcast: bool,
+    /// Supports multicast
+    pub multicast: bool,
+    /// Point-to-point link (tunnels, PPP)
+    pub point_to_point: bool,
+}
+
+/// Address assigned to an interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IfAddr {
+    /// The address itself
+    pub ip: IpAddr,
+    /// Length of the network prefix in bits
+    pub prefix_len: u8,
+    /// Broadcast address of the subnet (IPv4 on broadcast-capable links)
+    pub broadcast: Option<Ipv4Addr>,
+}
+
+impl IfAddr {
+    /// Returns the netmask corresponding to `prefix_len`
+    pub fn netmask(&self) -> IpAddr {
+        match self.ip {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(prefix_mask(self.prefix_len, 32) as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(prefix_mask(self.prefix_len, 128))),
+        }
+    }
+
+    /// Returns `true` if `ip` is in this address's subnet
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.ip, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 32) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = prefix_mask(self.prefix_len, 128);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Local network interface
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interface {
+    /// Interface name (`eth0`, `en0`, or the Windows friendly name)
+    pub name: String,
+    /// Interface index, as used for IPv6 scope IDs and multicast membership
+    pub index: u32,
+    /// Hardware address, if the link has a 6-byte one
+    pub mac: Option<[u8; 6]>,
+    /// Link MTU, where the platform reports it
+    pub mtu: Option<u32>,
+    /// State flags
+    pub flags: InterfaceFlags,
+    /// Assigned addresses
+    pub addrs: Vec<IfAddr>,
+}
+
+impl Interface {
+    /// Returns the IPv4 addresses of the interface
+    pub fn ipv4(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.addrs.iter().filter_map(|a| match a.ip {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        })
+    }
+
+    /// Returns the IPv6 addresses of the interface
+    pub fn ipv6(&self) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        self.addrs.iter().filter_map(|a| match a.ip {
+            IpAddr::V6(ip) => Some(ip),
+            IpAddr::V4(_) => None,
+        })
+    }
+}
+
+/// Lists the local network interfaces in system order
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+    sys::interfaces()
+}
+
+/// Returns the interface named `name`, if there is one
+pub fn by_name(name: &str) -> io::Result<Option<Interface>> {
+    Ok(interfaces()?.into_iter().find(|i| i.name == name))
+}
+
+/// Returns the interface with index `index`, if there is one
+pub fn by_index(index: u32) -> io::Result<Option<Interface>> {
+    Ok(interfaces()?.into_iter().find(|i| i.index == index))
+}
+
+/// Returns the MTU of the interface named `interface`
+///
+/// `NotFound` if there is no such interface; `Unsupported` where the
+/// platform does not report MTUs.
+pub fn mtu(interface: &str) -> io::Result<u32> {
+    let iface = by_name(interface)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface named {interface}")))?;
+    iface.mtu.ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "interface MTU is not reported on this platform"))
+}
+
+/// Returns the MTU of the route the kernel would use to reach `dest`
+///
+/// This is the route's MTU override if one is configured, otherwise the
+/// MTU of the outgoing interface. It does not include path MTU discovery
+/// results learned after the fact. Subtract IP and UDP headers (28 bytes
+/// for IPv4, 48 for IPv6) to get the largest unfragmented payload.
+pub fn route_mtu(dest: IpAddr) -> io::Result<u32> {
+    sys::route_mtu(dest)
+}
+
+// Shared with the netmon module, which parses the same netlink attributes
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use sys::{attrs as nl_attrs, u32_at};
+#[cfg(windows)]
+pub(crate) use sys::sockaddr_ip;
+
+/// Returns a mask with the top `prefix` of `bits` bits set, right-aligned in a u128
+fn prefix_mask(prefix: u8, bits: u32) -> u128 {
+    let prefix = u32::from(prefix).min(bits);
+    if prefix == 0 {
+        return 0;
+    }
+    (u128::MAX << (128 - prefix)) >> (128 - bits)
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::*;
+    use std::ffi::CStr;
+
+    pub(super) fn interfaces() -> io::Result<Vec<Interface>> {
+        let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut head) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut list = Vec::new();
+        let mut cur = head;
+        while !cur.is_null() {
+            // SAFETY: getifaddrs returned a valid list that lives until freeifaddrs
+            let ifa = unsafe { &*cur };
+            cur = ifa.ifa_next;
+            let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy();
+            let iface = entry(&mut list, &name);
+            let flags = ifa.ifa_flags as libc::c_int;
+            iface.flags = InterfaceFlags {
+                up: flags & libc::IFF_UP != 0,
+                running: flags & libc::IFF_RUNNING != 0,
+                loopback: flags & libc::IFF_LOOPBACK != 0,
+                broadcast: flags & libc::IFF_BROADCAST != 0,
+                multicast: flags & libc::IFF_MULTICAST != 0,
+                point_to_point: flags & libc::IFF_POINTOPOINT != 0,
+            };
+            if ifa.ifa_addr.is_null() {
+                continue;
+            }
+            if let Some(mac) = unsafe { link_addr(ifa.ifa_addr) } {
+                iface.mac = Some(mac);
+                continue;
+            }
+            let Some(ip) = (unsafe { sockaddr_ip(ifa.ifa_addr) }) else { continue };
+            let prefix_len = unsafe { sockaddr_ip(ifa.ifa_netmask) }.map_or(0, |mask| match mask {
+                IpAddr::V4(m) => u32::from(m).count_ones() as u8,
+                IpAddr::V6(m) => u128::from(m).count_ones() as u8,
+            });
+            let broadcast = match (ip, iface.flags.broadcast) {
+                (IpAddr::V4(_), true) => match unsafe { sockaddr_ip(broadcast_addr(ifa)) } {
+                    Some(IpAddr::V4(b)) => Some(b),
+                    _ => None,
+                },
+                _ => None,
+            };
+            iface.addrs.push(IfAddr { ip, prefix_len, broadcast });
+        }
+        unsafe { libc::freeifaddrs(head) };
+
+        for iface in &mut list {
+            if let Ok(cname) = std::ffi::CString::new(iface.name.as_str()) {
+                iface.index = unsafe { libc::if_nametoindex(cname.as_ptr()) };
+            }
+            iface.mtu = mtu(&iface.name);
+        }
+        Ok(list)
+    }
+
+    /// Reads an IPv4 or IPv6 address; `None` for null or other families
+    unsafe fn sockaddr_ip(sa: *const libc::sockaddr) -> Option<IpAddr> {
+        if sa.is_null() {
+            return None;
+        }
+        match unsafe { (*sa).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { &*(sa as *const libc::sockaddr_in) };
+                Some(IpAddr::V4(Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes())))
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(sa as *const libc::sockaddr_in6) };
+                Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the entry for `name`, appending a new one if needed
+    fn entry<'a>(list: &'a mut Vec<Interface>, name: &str) -> &'a mut Interface {
+        match list.iter().position(|i| i.name == name) {
+            Some(pos) => &mut list[pos],
+            None => {
+                list.push(Interface {
+                    name: name.to_string(),
+                    index: 0,
+                    mac: None,
+                    mtu: None,
+                    flags: InterfaceFlags::default(),
+                    addrs: Vec::new(),
+                });
+                list.last_mut().unwrap()
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn broadcast_addr(ifa: &libc::ifaddrs) -> *const libc::sockaddr {
+        ifa.ifa_ifu
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn broadcast_addr(ifa: &libc::ifaddrs) -> *const libc::sockaddr {
+        ifa.ifa_dstaddr
+    }
+
+    /// Reads a 6-byte hardware address from an `AF_PACKET` entry
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn link_addr(sa: *const libc::sockaddr) -> Option<[u8; 6]> {
+        if unsafe { (*sa).sa_family } as libc::c_int != libc::AF_PACKET {
+            return None;
+        }
+        let sll = unsafe { &*(sa as *const libc::sockaddr_ll) };
+        (sll.sll_halen == 6).then(|| sll.sll_addr[..6].try_into().unwrap())
+    }
+
+    /// Reads a 6-byte hardware address from an `AF_LINK` entry
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+    unsafe fn link_addr(sa: *const libc::sockaddr) -> Option<[u8; 6]> {
+        if unsafe { (*sa).sa_family } as libc::c_int != libc::AF_LINK {
+            return None;
+        }
+        let sdl = unsafe { &*(sa as *const libc::sockaddr_dl) };
+        if sdl.sdl_alen != 6 {
+            return None;
+        }
+        // LLADDR: the address follows the name in sdl_data
+        let data = std::ptr::addr_of!(sdl.sdl_data) as *const u8;
+        let mut mac = [0u8; 6];
+        unsafe { std::ptr::copy_nonoverlapping(data.add(sdl.sdl_nlen as usize), mac.as_mut_ptr(), 6) };
+        Some(mac)
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    )))]
+    unsafe fn link_addr(_sa: *const libc::sockaddr) -> Option<[u8; 6]> {
+        None
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn mtu(name: &str) -> Option<u32> {
+        std::fs::read_to_string(format!("/sys/class/net/{name}/mtu")).ok()?.trim().parse().ok()
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn mtu(_name: &str) -> Option<u32> {
+        None
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(super) fn route_mtu(dest: IpAddr) -> io::Result<u32> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        const NLMSG_HDRLEN: usize = 16;
+        const RTMSG_LEN: usize = 12;
+        const RTAX_MTU: u16 = 2;
+
+        let (family, addr): (u8, Vec<u8>) = match dest {
+            IpAddr::V4(ip) => (libc::AF_INET as u8, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (libc::AF_INET6 as u8, ip.octets().to_vec()),
+        };
+        // nlmsghdr + rtmsg + one RTA_DST attribute
+        let len = NLMSG_HDRLEN + RTMSG_LEN + 4 + addr.len();
+        let mut req = Vec::with_capacity(len);
+        req.extend_from_slice(&(len as u32).to_ne_bytes());
+        req.extend_from_slice(&libc::RTM_GETROUTE.to_ne_bytes());
+        req.extend_from_slice(&(libc::NLM_F_REQUEST as u16).to_ne_bytes());
+        req.extend_from_slice(&1u32.to_ne_bytes()); // seq
+        req.extend_from_slice(&0u32.to_ne_bytes()); // pid
+        req.extend_from_slice(&[family, (addr.len() * 8) as u8, 0, 0, 0, 0, 0, 0]);
+        req.extend_from_slice(&0u32.to_ne_bytes()); // rtm_flags
+        req.extend_from_slice(&((4 + addr.len()) as u16).to_ne_bytes());
+        req.extend_from_slice(&libc::RTA_DST.to_ne_bytes());
+        req.extend_from_slice(&addr);
+
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd was just created and is owned here
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::send(fd.as_raw_fd(), req.as_ptr() as *const _, req.len(), 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = [0u8; 4096];
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let msg = &buf[..(n as usize).min(u32_at(&buf, 0) as usize)];
+        if msg.len() < NLMSG_HDRLEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "short netlink reply"));
+        }
+        let ty = u16::from_ne_bytes([msg[4], msg[5]]);
+        if ty == libc::NLMSG_ERROR as u16 {
+            let errno = u32_at(msg, NLMSG_HDRLEN) as i32;
+            return Err(io::Error::from_raw_os_error(-errno));
+        }
+        if ty != libc::RTM_NEWROUTE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected netlink reply"));
+        }
+
+        let mut oif = None;
+        let mut route_mtu = None;
+        for (kind, payload) in attrs(msg.get(NLMSG_HDRLEN + RTMSG_LEN..).unwrap_or_default()) {
+            match kind {
+                libc::RTA_OIF if payload.len() >= 4 => oif = Some(u32_at(payload, 0)),
+                libc::RTA_METRICS => {
+                    route_mtu = attrs(payload).find(|(k, p)| *k == RTAX_MTU && p.len() >= 4).map(|(_, p)| u32_at(p, 0))
+                }
+                _ => {}
+            }
+        }
+        if let Some(mtu) = route_mtu.filter(|&m| m > 0) {
+            return Ok(mtu);
+        }
+        let oif = oif.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "route has no outgoing interface"))?;
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        if unsafe { libc::if_indextoname(oif, name.as_mut_ptr()) }.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy();
+        mtu(&name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no MTU for {name}")))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn u32_at(buf: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Iterates `(type, payload)` over a run of netlink route attributes
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+        std::iter::from_fn(move || {
+            if buf.len() < 4 {
+                return None;
+            }
+            let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+            let kind = u16::from_ne_bytes([buf[2], buf[3]]);
+            if len < 4 || len > buf.len() {
+                return None;
+            }
+            let payload = &buf[4..len];
+            buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+            Some((kind, payload))
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(super) fn route_mtu(_dest: IpAddr) -> io::Result<u32> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "route MTU lookup is not implemented on this platform"))
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::*;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetAdaptersAddresses, GetBestRoute2, GetIpInterfaceEntry, InitializeIpInterfaceEntry, MIB_IPFORWARD_ROW2,
+        MIB_IPINTERFACE_ROW, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER, GAA_FLAG_SKIP_MULTICAST,
+        IF_TYPE_PPP, IF_TYPE_SOFTWARE_LOOPBACK, IP_ADAPTER_ADDRESSES_LH, IP_ADAPTER_NO_MULTICAST,
+    };
+    use windows_sys::Win32::NetworkManagement::Ndis::IfOperStatusUp;
+    use windows_sys::Win32::Networking::WinSock::{
+        AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, SOCKADDR_INET,
+    };
+
+    const ERROR_BUFFER_OVERFLOW: u32 = 111;
+
+    pub(super) fn interfaces() -> io::Result<Vec<Interface>> {
+        let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+        // u64 elements keep the buffer aligned for the adapter structs
+        let mut buf: Vec<u64> = vec![0; 2048];
+        loop {
+            let mut size = (buf.len() * 8) as u32;
+            let head = buf.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH;
+            let rc = unsafe { GetAdaptersAddresses(AF_UNSPEC as u32, flags, std::ptr::null(), head, &mut size) };
+            match rc {
+                0 => return Ok(unsafe { collect(head) }),
+                ERROR_BUFFER_OVERFLOW => buf = vec![0; (size as usize).div_ceil(8)],
+                // No adapters at all
+                232 | 1168 => return Ok(Vec::new()),
+                err => return Err(io::Error::from_raw_os_error(err as i32)),
+            }
+        }
+    }
+
+    pub(super) fn route_mtu(dest: IpAddr) -> io::Result<u32> {
+        let mut dst: SOCKADDR_INET = unsafe { std::mem::zeroed() };
+        let family = match dest {
+            IpAddr::V4(ip) => {
+                dst.Ipv4.sin_family = AF_INET;
+                dst.Ipv4.sin_addr.S_un.S_addr = u32::from_ne_bytes(ip.octets());
+                AF_INET
+            }
+            IpAddr::V6(ip) => {
+                dst.Ipv6.sin6_family = AF_INET6;
+                dst.Ipv6.sin6_addr.u.Byte = ip.octets();
+                AF_INET6
+            }
+        };
+        let mut route: MIB_IPFORWARD_ROW2 = unsafe { std::mem::zeroed() };
+        let mut src: SOCKADDR_INET = unsafe { std::mem::zeroed() };
+        let rc = unsafe { GetBestRoute2(std::ptr::null(), 0, std::ptr::null(), &dst, 0, &mut route, &mut src) };
+        if rc != 0 {
+            return Err(io::Error::from_raw_os_error(rc as i32));
+        }
+        let mut row: MIB_IPINTERFACE_ROW = unsafe { std::mem::zeroed() };
+        unsafe { InitializeIpInterfaceEntry(&mut row) };
+        row.Family = family;
+        row.InterfaceIndex = route.InterfaceIndex;
+        let rc = unsafe { GetIpInterfaceEntry(&mut row) };
+        if rc != 0 {
+            return Err(io::Error::from_raw_os_error(rc as i32));
+        }
+        Ok(row.NlMtu)
+    }
+
+    unsafe fn collect(mut cur: *const IP_ADAPTER_ADDRESSES_LH) -> Vec<Interface> {
+        let mut list = Vec::new();
+        while !cur.is_null() {
+            // SAFETY: GetAdaptersAddresses filled a valid list inside the buffer
+            let a = unsafe { &*cur };
+            cur = a.Next;
+            let loopback = a.IfType == IF_TYPE_SOFTWARE_LOOPBACK;
+            let point_to_point = a.IfType == IF_TYPE_PPP;
+            let up = a.OperStatus == IfOperStatusUp;
+            let flags = InterfaceFlags {
+                up,
+                running: up,
+                loopback,
+                broadcast: !loopback && !point_to_point,
+                multicast: unsafe { a.Anonymous2.Flags } & IP_ADAPTER_NO_MULTICAST == 0,
+                point_to_point,
+            };
+            let index = match unsafe { a.Anonymous1.Anonymous.IfIndex } {
+                0 => a.Ipv6IfIndex,
+                i => i,
+            };
+            let mut addrs = Vec::new();
+            let mut ua = a.FirstUnicastAddress;
+            while !ua.is_null() {
+                let u = unsafe { &*ua };
+                ua = u.Next;
+                let Some(ip) = (unsafe { sockaddr_ip(u.Address.lpSockaddr) }) else { continue };
+                let mut addr = IfAddr { ip, prefix_len: u.OnLinkPrefixLength, broadcast: None };
+                if let (IpAddr::V4(v4), true) = (ip, flags.broadcast) {
+                    let mask = prefix_mask(addr.prefix_len, 32) as u32;
+                    addr.broadcast = Some(Ipv4Addr::from(u32::from(v4) | !mask));
+                }
+                addrs.push(addr);
+            }
+            list.push(Interface {
+                name: unsafe { wide_string(a.FriendlyName) },
+                index,
+                mac: (a.PhysicalAddressLength == 6).then(|| a.PhysicalAddress[..6].try_into().unwrap()),
+                mtu: (a.Mtu != u32::MAX).then_some(a.Mtu),
+                flags,
+                addrs,
+            });
+        }
+        list
+    }
+
+    pub(crate) unsafe fn sockaddr_ip(sa: *const SOCKADDR) -> Option<IpAddr> {
+        if sa.is_null() {
+            return None;
+        }
+        match unsafe { (*sa).sa_family } {
+            AF_INET => {
+                let sin = unsafe { &*(sa as *const SOCKADDR_IN) };
+                Some(IpAddr::V4(Ipv4Addr::from(unsafe { sin.sin_addr.S_un.S_addr }.to_ne_bytes())))
+            }
+            AF_INET6 => {
+                let sin6 = unsafe { &*(sa as *const SOCKADDR_IN6) };
+                Some(IpAddr::V6(Ipv6Addr::from(unsafe { sin6.sin6_addr.u.Byte })))
+            }
+            _ => None,
+        }
+    }
+
+    unsafe fn wide_string(p: *const u16) -> String {
+        if p.is_null() {
+            return String::new();
+        }
+        let mut len = 0;
+        while unsafe { *p.add(len) } != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(p, len) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loopback_is_listed() {
+        let list = interfaces().unwrap();
+        let lo = list
+            .iter()
+            .find(|i| i.ipv4().any(|ip| ip.is_loopback()))
+            .expect("no interface carries 127.0.0.1");
+        assert!(lo.flags.loopback);
+        assert!(lo.index > 0);
+        let addr = lo.addrs.iter().find(|a| a.ip == IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        assert_eq!(addr.prefix_len, 8);
+        assert_eq!(addr.netmask(), IpAddr::V4(Ipv4Addr::new(255, 0, 0, 0)));
+        assert!(addr.contains(IpAddr::V4(Ipv4Addr::new(127, 1, 2, 3))));
+        assert!(!addr.contains(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+
+        assert_eq!(by_index(lo.index).unwrap().unwrap().name, lo.name);
+        assert_eq!(by_name(&lo.name).unwrap().unwrap().index, lo.index);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", windows))]
+    #[test]
+    fn test_route_mtu_follows_outgoing_interface() {
+        let lo = interfaces().unwrap().into_iter().find(|i| i.flags.loopback).unwrap();
+        let lo_mtu = mtu(&lo.name).unwrap();
+        assert!(lo_mtu >= 1280);
+        assert_eq!(route_mtu(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap(), lo_mtu);
+        assert_eq!(mtu("no-such-interface").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,510 @@
+//! Link and address change notifications
+//!
+//! Long-lived sockets outlive the network they were set up on: a laptop
+//! moves between Wi-Fi networks, a VPN comes up, a container's interface is
+//! replaced. A [`Monitor`] subscribes to the kernel's change notifications
+//! (rtnetlink on Linux, `NotifyIpInterfaceChange` and
+//! `NotifyUnicastIpAddressChange` on Windows) and reports them as
+//! [`NetEvent`]s, so the application knows when to rebind or re-resolve.
+//!
+//! A monitor is readable whenever events are pending. Pass it to
+//! [`poll::wait`](crate::poll::wait), or on Unix register it with the
+//! [`Runtime`](crate::Runtime) next to the sockets it concerns, and call
+//! [`read`](Monitor::read) when it wakes. Link events are deduplicated:
+//! `LinkUp`/`LinkDown` fire only when an interface's state actually flips.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::netmon::{Monitor, NetEvent};
+//! use std::time::Duration;
+//!
+//! let mut monitor = Monitor::new()?;
+//! let mut events = Vec::new();
+//! loop {
+//!     monitor.wait(&mut events, Some(Duration::from_secs(1)))?;
+//!     for event in events.drain(..) {
+//!         match event {
+//!             NetEvent::AddrRemoved { addr, .. } => println!("lost {}, rebinding", addr.ip),
+//!             NetEvent::Overrun => println!("missed events, rescanning interfaces"),
+//!             other => println!("{other:?}"),
+//!         }
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Platform Support
+//!
+//! - **Linux**: Full support
+//! - **Windows**: Events arrive on a system thread and are queued behind a
+//!   [`Notifier`](crate::signal::Notifier); `RouteChanged` is not reported
+//! - **Other platforms**: [`Monitor::new`] returns `Unsupported`
+
+use crate::netif::IfAddr;
+use crate::poll::{self, Interest, Pollable};
+use crate::raw::OsSocket;
+use std::io;
+use std::time::Duration;
+
+/// Network change reported by a [`Monitor`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetEvent {
+    /// The interface came up and has carrier
+    LinkUp {
+        /// Interface index; see [`netif::by_index`](crate::netif::by_index)
+        index: u32,
+    },
+    /// The interface went down, lost carrier, or was removed
+    LinkDown {
+        /// Interface index
+        index: u32,
+    },
+    /// An address was assigned to an interface
+    AddrAdded {
+        /// Interface index
+        index: u32,
+        /// The new address
+        addr: IfAddr,
+    },
+    /// An address was removed from an interface
+    AddrRemoved {
+        /// Interface index
+        index: u32,
+        /// The removed address
+        addr: IfAddr,
+    },
+    /// Routes were added or removed; reported at most once per [`read`](Monitor::read)
+    RouteChanged,
+    /// The kernel dropped notifications; rescan with [`netif::interfaces`](crate::netif::interfaces)
+    Overrun,
+}
+
+/// Subscription to link, address, and route changes
+#[derive(Debug)]
+pub struct Monitor {
+    sys: sys::Monitor,
+}
+
+impl Monitor {
+    /// Subscribes to change notifications
+    ///
+    /// The socket is nonblocking; only changes after this call are reported.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self { sys: sys::Monitor::new()? })
+    }
+
+    /// Appends pending events to `events` without blocking
+    ///
+    /// # Returns
+    ///
+    /// The number of events appended; `0` if none were pending
+    pub fn read(&mut self, events: &mut Vec<NetEvent>) -> io::Result<usize> {
+        let before = events.len();
+        self.sys.read(events)?;
+        Ok(events.len() - before)
+    }
+
+    /// Blocks until events arrive or `timeout` passes, then reads them
+    ///
+    /// For threads without an event loop; event loops should register the
+    /// monitor instead.
+    pub fn wait(&mut self, events: &mut Vec<NetEvent>, timeout: Option<Duration>) -> io::Result<usize> {
+        let ready = poll::wait(&[&*self], Interest::READABLE, timeout)?;
+        if !ready[0].is_ready() {
+            return Ok(0);
+        }
+        self.read(events)
+    }
+}
+
+impl Pollable for Monitor {
+    fn poll_handle(&self) -> OsSocket {
+        self.sys.poll_handle()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for Monitor {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.poll_handle()
+    }
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for Monitor {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).deregister(registry)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use super::*;
+    use crate::netif::{nl_attrs, u32_at};
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const NLMSG_HDRLEN: usize = 16;
+    const IFINFOMSG_LEN: usize = 16;
+    const IFADDRMSG_LEN: usize = 8;
+    // Multicast groups from <linux/rtnetlink.h>, which libc omits on Android
+    const RTMGRP_LINK: u32 = 0x1;
+    const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+    const RTMGRP_IPV4_ROUTE: u32 = 0x40;
+    const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+    const RTMGRP_IPV6_ROUTE: u32 = 0x400;
+
+    #[derive(Debug)]
+    pub(super) struct Monitor {
+        fd: OwnedFd,
+        /// Last reported up/running state per interface index
+        links: HashMap<u32, bool>,
+    }
+
+    impl Monitor {
+        pub(super) fn new() -> io::Result<Self> {
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                    libc::NETLINK_ROUTE,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: fd was just created and is owned here
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR | RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_ROUTE;
+            let rc = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd, links: current_links() })
+        }
+
+        pub(super) fn read(&mut self, events: &mut Vec<NetEvent>) -> io::Result<()> {
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+                if n < 0 {
+                    let err = io::Error::last_os_error();
+                    match err.raw_os_error() {
+                        Some(libc::EAGAIN) => return Ok(()),
+                        Some(libc::EINTR) => continue,
+                        // The socket buffer overflowed and messages were lost
+                        Some(libc::ENOBUFS) => {
+                            self.links = current_links();
+                            events.push(NetEvent::Overrun);
+                            continue;
+                        }
+                        _ => return Err(err),
+                    }
+                }
+                parse(&mut self.links, &buf[..n as usize], events);
+            }
+        }
+
+        pub(super) fn poll_handle(&self) -> OsSocket {
+            self.fd.as_raw_fd()
+        }
+    }
+
+    fn current_links() -> HashMap<u32, bool> {
+        crate::netif::interfaces()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| (i.index, i.flags.up && i.flags.running))
+            .collect()
+    }
+
+    /// Translates one datagram of rtnetlink messages into events
+    pub(super) fn parse(links: &mut HashMap<u32, bool>, mut buf: &[u8], events: &mut Vec<NetEvent>) {
+        let mut route_changed = false;
+        while buf.len() >= NLMSG_HDRLEN {
+            let len = u32_at(buf, 0) as usize;
+            if len < NLMSG_HDRLEN || len > buf.len() {
+                break;
+            }
+            let ty = u16::from_ne_bytes([buf[4], buf[5]]);
+            let body = &buf[NLMSG_HDRLEN..len];
+            buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+
+            match ty {
+                libc::RTM_NEWLINK | libc::RTM_DELLINK if body.len() >= IFINFOMSG_LEN => {
+                    let index = u32_at(body, 4);
+                    let flags = u32_at(body, 8) as libc::c_int;
+                    let up = ty == libc::RTM_NEWLINK && flags & libc::IFF_UP != 0 && flags & libc::IFF_RUNNING != 0;
+                    if links.insert(index, up) != Some(up) {
+                        events.push(if up { NetEvent::LinkUp { index } } else { NetEvent::LinkDown { index } });
+                    }
+                    if ty == libc::RTM_DELLINK {
+                        links.remove(&index);
+                    }
+                }
+                libc::RTM_NEWADDR | libc::RTM_DELADDR if body.len() >= IFADDRMSG_LEN => {
+                    let family = body[0] as libc::c_int;
+                    let prefix_len = body[1];
+                    let index = u32_at(body, 4);
+                    let (mut address, mut local, mut broadcast) = (None, None, None);
+                    for (kind, payload) in nl_attrs(&body[IFADDRMSG_LEN..]) {
+                        let ip = match (family, payload.len()) {
+                            (libc::AF_INET, 4) => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(payload).unwrap())),
+                            (libc::AF_INET6, 16) => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(payload).unwrap())),
+                            _ => continue,
+                        };
+                        match kind {
+                            libc::IFA_ADDRESS => address = Some(ip),
+                            // Differs from IFA_ADDRESS on point-to-point links, where that is the peer
+                            libc::IFA_LOCAL => local = Some(ip),
+                            libc::IFA_BROADCAST => broadcast = Some(ip),
+                            _ => {}
+                        }
+                    }
+                    let Some(ip) = local.or(address) else { continue };
+                    let broadcast = match broadcast {
+                        Some(IpAddr::V4(b)) => Some(b),
+                        _ => None,
+                    };
+                    let addr = IfAddr { ip, prefix_len, broadcast };
+                    events.push(if ty == libc::RTM_NEWADDR {
+                        NetEvent::AddrAdded { index, addr }
+                    } else {
+                        NetEvent::AddrRemoved { index, addr }
+                    });
+                }
+                libc::RTM_NEWROUTE | libc::RTM_DELROUTE => route_changed = true,
+                _ => {}
+            }
+        }
+        if route_changed {
+            events.push(NetEvent::RouteChanged);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::*;
+    use crate::signal::Notifier;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        CancelMibChangeNotify2, NotifyIpInterfaceChange, NotifyUnicastIpAddressChange, MibAddInstance,
+        MibDeleteInstance, MIB_IPINTERFACE_ROW, MIB_NOTIFICATION_TYPE, MIB_UNICASTIPADDRESS_ROW,
+    };
+    use windows_sys::Win32::Networking::WinSock::AF_UNSPEC;
+
+    #[derive(Debug)]
+    struct Shared {
+        queue: Mutex<VecDeque<NetEvent>>,
+        /// Last reported connected state per interface index
+        links: Mutex<HashMap<u32, bool>>,
+        notifier: Notifier,
+    }
+
+    impl Shared {
+        fn push(&self, event: NetEvent) {
+            self.queue.lock().unwrap_or_else(|e| e.into_inner()).push_back(event);
+            let _ = self.notifier.notify();
+        }
+    }
+
+    #[derive(Debug)]
+    pub(super) struct Monitor {
+        shared: Arc<Shared>,
+        handles: [HANDLE; 2],
+    }
+
+    // SAFETY: the notification handles are only passed to CancelMibChangeNotify2, from any thread
+    unsafe impl Send for Monitor {}
+
+    impl Monitor {
+        pub(super) fn new() -> io::Result<Self> {
+            let shared = Arc::new(Shared {
+                queue: Mutex::new(VecDeque::new()),
+                links: Mutex::new(HashMap::new()),
+                notifier: Notifier::new()?,
+            });
+            let ctx = Arc::as_ptr(&shared) as *const core::ffi::c_void;
+            let mut monitor = Self { shared, handles: [std::ptr::null_mut(); 2] };
+            let rc = unsafe { NotifyIpInterfaceChange(AF_UNSPEC, Some(on_interface), ctx, 0, &mut monitor.handles[0]) };
+            if rc != 0 {
+                return Err(io::Error::from_raw_os_error(rc as i32));
+            }
+            let rc =
+                unsafe { NotifyUnicastIpAddressChange(AF_UNSPEC, Some(on_address), ctx, 0, &mut monitor.handles[1]) };
+            if rc != 0 {
+                return Err(io::Error::from_raw_os_error(rc as i32));
+            }
+            Ok(monitor)
+        }
+
+        pub(super) fn read(&mut self, events: &mut Vec<NetEvent>) -> io::Result<()> {
+            self.shared.notifier.drain()?;
+            events.extend(self.shared.queue.lock().unwrap_or_else(|e| e.into_inner()).drain(..));
+            Ok(())
+        }
+
+        pub(super) fn poll_handle(&self) -> OsSocket {
+            self.shared.notifier.poll_handle()
+        }
+    }
+
+    impl Drop for Monitor {
+        fn drop(&mut self) {
+            // Waits for running callbacks, so none can touch `shared` afterwards
+            for handle in self.handles {
+                if !handle.is_null() {
+                    unsafe { CancelMibChangeNotify2(handle) };
+                }
+            }
+        }
+    }
+
+    unsafe extern "system" fn on_interface(
+        ctx: *const core::ffi::c_void,
+        row: *const MIB_IPINTERFACE_ROW,
+        kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        // SAFETY: ctx is the Arc kept alive by Monitor until notifications are cancelled
+        let shared = unsafe { &*(ctx as *const Shared) };
+        let Some(row) = (unsafe { row.as_ref() }) else { return };
+        let index = row.InterfaceIndex;
+        let up = kind == MibAddInstance || (kind != MibDeleteInstance && row.Connected != 0);
+        // Parameter changes also arrive here; report only state flips
+        let mut links = shared.links.lock().unwrap_or_else(|e| e.into_inner());
+        if kind == MibDeleteInstance {
+            links.remove(&index);
+        } else if links.insert(index, up) == Some(up) {
+            return;
+        }
+        drop(links);
+        shared.push(if up { NetEvent::LinkUp { index } } else { NetEvent::LinkDown { index } });
+    }
+
+    unsafe extern "system" fn on_address(
+        ctx: *const core::ffi::c_void,
+        row: *const MIB_UNICASTIPADDRESS_ROW,
+        kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        // SAFETY: as in on_interface
+        let shared = unsafe { &*(ctx as *const Shared) };
+        let Some(row) = (unsafe { row.as_ref() }) else { return };
+        let Some(ip) = (unsafe { crate::netif::sockaddr_ip(&row.Address as *const _ as *const _) }) else { return };
+        let addr = IfAddr { ip, prefix_len: row.OnLinkPrefixLength, broadcast: None };
+        let index = row.InterfaceIndex;
+        if kind == MibAddInstance {
+            shared.push(NetEvent::AddrAdded { index, addr });
+        } else if kind == MibDeleteInstance {
+            shared.push(NetEvent::AddrRemoved { index, addr });
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+mod sys {
+    use super::*;
+
+    #[derive(Debug)]
+    pub(super) struct Monitor;
+
+    impl Monitor {
+        pub(super) fn new() -> io::Result<Self> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "network monitoring is not implemented on this platform"))
+        }
+
+        pub(super) fn read(&mut self, _events: &mut Vec<NetEvent>) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub(super) fn poll_handle(&self) -> OsSocket {
+            -1
+        }
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn nlmsg(ty: u16, body: &[u8]) -> Vec<u8> {
+        let mut msg = ((16 + body.len()) as u32).to_ne_bytes().to_vec();
+        msg.extend_from_slice(&ty.to_ne_bytes());
+        msg.extend_from_slice(&[0; 10]);
+        msg.extend_from_slice(body);
+        msg
+    }
+
+    #[test]
+    fn test_parse_link_and_address_messages() {
+        let mut links = HashMap::from([(2, false)]);
+        let mut link = vec![0u8; 16];
+        link[4..8].copy_from_slice(&2u32.to_ne_bytes());
+        link[8..12].copy_from_slice(&((libc::IFF_UP | libc::IFF_RUNNING) as u32).to_ne_bytes());
+
+        let mut addr = vec![libc::AF_INET as u8, 24, 0, 0];
+        addr.extend_from_slice(&2u32.to_ne_bytes());
+        for (kind, ip) in [(libc::IFA_ADDRESS, [10, 0, 0, 5]), (libc::IFA_BROADCAST, [10, 0, 0, 255])] {
+            addr.extend_from_slice(&8u16.to_ne_bytes());
+            addr.extend_from_slice(&kind.to_ne_bytes());
+            addr.extend_from_slice(&ip);
+        }
+
+        let mut buf = nlmsg(libc::RTM_NEWLINK, &link);
+        // A repeated state is not reported again
+        buf.extend(nlmsg(libc::RTM_NEWLINK, &link));
+        buf.extend(nlmsg(libc::RTM_NEWADDR, &addr));
+        buf.extend(nlmsg(libc::RTM_NEWROUTE, &[0; 12]));
+        buf.extend(nlmsg(libc::RTM_DELROUTE, &[0; 12]));
+        let mut events = Vec::new();
+        sys::parse(&mut links, &buf, &mut events);
+
+        let ifaddr = IfAddr {
+            ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)),
+            prefix_len: 24,
+            broadcast: Some(Ipv4Addr::new(10, 0, 0, 255)),
+        };
+        assert_eq!(
+            events,
+            vec![
+                NetEvent::LinkUp { index: 2 },
+                NetEvent::AddrAdded { index: 2, addr: ifaddr },
+                NetEvent::RouteChanged
+            ]
+        );
+
+        events.clear();
+        sys::parse(&mut links, &nlmsg(libc::RTM_DELLINK, &link), &mut events);
+        assert_eq!(events, vec![NetEvent::LinkDown { index: 2 }]);
+    }
+
+    #[test]
+    fn test_monitor_is_quiet_without_changes() {
+        let mut monitor = Monitor::new().unwrap();
+        let mut events = Vec::new();
+        let n = monitor.wait(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert_eq!(n, events.len());
+    }
+}
@@ -0,0 +1,406 @@
+//! Deadline pacing: sending each packet at an absolute departure time
+//!
+//! Media streams, trace replay, and load generators need packets to leave
+//! with precise gaps between them, not merely in order. Sleeping between
+//! sends is not enough: a sleep ends up to a scheduler tick late, and the
+//! error accumulates if each gap is measured from the previous send.
+//!
+//! A [`PacedSender`] holds packets ordered by their departure time, as an
+//! absolute `Instant` from its [`Clock`]. Because deadlines are absolute,
+//! a late send does not push back the packets after it. Packets leave in
+//! one of two ways:
+//!
+//! - **Hybrid wait** (any [`DatagramSocket`]): the event loop waits
+//!   [`timeout`](PacedSender::timeout), which ends a little before the next
+//!   departure, then calls [`poll_send`](PacedSender::poll_send). That
+//!   sends everything due and busy-waits for departures closer than the
+//!   [`spin`](PacedSender::spin) threshold, which covers the imprecision of
+//!   the OS wait. [`send_all`](PacedSender::send_all) does the same with
+//!   sleeps, for a thread dedicated to one stream.
+//! - **`SO_TXTIME`** (Linux, [`Udp`](crate::udp::Udp) only): after
+//!   [`enable_txtime`], [`poll_send_txtime`](PacedSender::poll_send_txtime)
+//!   hands packets to the kernel ahead of time with their departure
+//!   attached, and the `fq` or `etf` qdisc releases them on schedule with
+//!   no CPU spinning. Other qdiscs send them immediately.
+//!
+//! A [`Clock`] with the TSC source makes the busy-wait cheap; see
+//! [`clock`](crate::clock).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::pacer::PacedSender;
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use std::time::{Duration, Instant};
+//!
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+//! let peer = "198.51.100.7:5004".parse()?;
+//! let mut pacer = PacedSender::new();
+//!
+//! // One 1200-byte packet every 250 µs, starting 10 ms from now
+//! let start = Instant::now() + Duration::from_millis(10);
+//! for i in 0..400u32 {
+//!     pacer.enqueue(vec![0; 1200], peer, start + Duration::from_micros(250) * i);
+//! }
+//! pacer.send_all(&socket)?;
+//! println!("worst lateness {:?}", pacer.stats().max_late);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::clock::Clock;
+use crate::trace;
+use crate::transport::DatagramSocket;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Counters for a [`PacedSender`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PacerStats {
+    /// Packets handed to the socket
+    pub sent: u64,
+    /// Packets dropped after a hard send error
+    pub failed: u64,
+    /// Largest gap between a packet's departure time and its send
+    pub max_late: Duration,
+}
+
+#[derive(Debug)]
+struct Pending {
+    at: Instant,
+    /// Enqueue order, so packets with equal departure times keep it
+    seq: u64,
+    dest: SocketAddr,
+    buf: Vec<u8>,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+/// Packets queued for release at absolute departure times
+#[derive(Debug)]
+pub struct PacedSender {
+    queue: BinaryHeap<Reverse<Pending>>,
+    next_seq: u64,
+    clock: Clock,
+    spin: Duration,
+    stats: PacerStats,
+}
+
+impl PacedSender {
+    /// Creates an empty sender on the standard clock, spinning for the last 200 µs
+    pub fn new() -> Self {
+        Self { queue: BinaryHeap::new(), next_seq: 0, clock: Clock::default(), spin: Duration::from_micros(200), stats: PacerStats::default() }
+    }
+
+    /// Reads departure times against `clock`
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Busy-waits for departures closer than `spin` instead of returning to the event loop
+    ///
+    /// Set it a little above the wake-up error of the OS wait: around
+    /// 50-100 µs on a tuned Linux host, a millisecond or more on Windows.
+    /// Zero disables spinning.
+    pub fn spin(mut self, spin: Duration) -> Self {
+        self.spin = spin;
+        self
+    }
+
+    /// Queues `buf` to leave for `addr` at `departure`
+    ///
+    /// A departure in the past means "as soon as possible".
+    pub fn enqueue(&mut self, buf: Vec<u8>, addr: SocketAddr, departure: Instant) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse(Pending { at: departure, seq, dest: addr, buf }));
+    }
+
+    /// Returns the departure time of the next packet
+    pub fn next_departure(&self) -> Option<Instant> {
+        self.queue.peek().map(|p| p.0.at)
+    }
+
+    /// Returns how long the event loop may wait before calling [`poll_send`](Self::poll_send)
+    ///
+    /// The wait ends the spin threshold before the next departure. `None`
+    /// when nothing is queued.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.next_departure().map(|at| at.saturating_duration_since(now).saturating_sub(self.spin))
+    }
+
+    /// Returns the number of queued packets
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if nothing is queued
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the counters
+    pub fn stats(&self) -> PacerStats {
+        self.stats
+    }
+
+    /// Sends every due packet, busy-waiting for those due within the spin threshold
+    ///
+    /// A packet the socket does not accept (`WouldBlock`) stays queued. A
+    /// hard error drops the packet that caused it and is returned.
+    ///
+    /// # Returns
+    ///
+    /// The number of packets sent
+    pub fn poll_send<S: DatagramSocket + ?Sized>(&mut self, socket: &S) -> io::Result<usize> {
+        let mut sent = 0;
+        while let Some(Reverse(head)) = self.queue.peek() {
+            let at = head.at;
+            let mut now = self.clock.now();
+            if at > now {
+                if at - now > self.spin {
+                    break;
+                }
+                while now < at {
+                    std::hint::spin_loop();
+                    now = self.clock.now();
+                }
+            }
+            match socket.send_to(&head.buf, head.dest) {
+                Ok(_) => {
+                    self.stats.sent += 1;
+                    self.stats.max_late = self.stats.max_late.max(now - at);
+                    self.queue.pop();
+                    sent += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.stats.failed += 1;
+                    self.queue.pop();
+                    trace::event!(debug, error = %e, "paced send failed");
+                    return Err(e);
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends the whole queue on schedule, sleeping between departures
+    ///
+    /// Blocks the thread until the last packet has left. On a non-blocking
+    /// socket `WouldBlock` is retried after a short sleep.
+    pub fn send_all<S: DatagramSocket + ?Sized>(&mut self, socket: &S) -> io::Result<()> {
+        while let Some(wait) = self.timeout(self.clock.now()) {
+            if !wait.is_zero() {
+                std::thread::sleep(wait);
+            }
+            if self.poll_send(socket)? == 0 && self.timeout(self.clock.now()) == Some(Duration::ZERO) {
+                // Socket buffer full
+                std::thread::sleep(Duration::from_micros(50));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the kernel every packet departing within `lead`, tagged with its departure time (Linux)
+    ///
+    /// The socket needs [`enable_txtime`]. The `fq` qdisc holds packets
+    /// for up to its horizon (10 s by default) and drops those beyond it,
+    /// so keep `lead` well below that.
+    #[cfg(target_os = "linux")]
+    pub fn poll_send_txtime(&mut self, socket: &crate::udp::Udp, lead: Duration) -> io::Result<usize> {
+        let horizon = self.clock.now() + lead;
+        let mut sent = 0;
+        while let Some(Reverse(head)) = self.queue.peek() {
+            if head.at > horizon {
+                break;
+            }
+            match send_at(socket, &head.buf, head.dest, head.at) {
+                Ok(_) => {
+                    self.stats.sent += 1;
+                    self.queue.pop();
+                    sent += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.stats.failed += 1;
+                    self.queue.pop();
+                    trace::event!(debug, error = %e, "txtime send failed");
+                    return Err(e);
+                }
+            }
+        }
+        Ok(sent)
+    }
+}
+
+impl Default for PacedSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enables `SO_TXTIME` against `CLOCK_MONOTONIC` on `socket` (Linux 4.19+)
+///
+/// With `report_errors`, packets the qdisc drops for missing their
+/// deadline are reported on the socket's error queue.
+#[cfg(target_os = "linux")]
+pub fn enable_txtime(socket: &crate::udp::Udp, report_errors: bool) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let cfg = libc::sock_txtime {
+        clockid: libc::CLOCK_MONOTONIC,
+        flags: if report_errors { libc::SOF_TXTIME_REPORT_ERRORS } else { 0 },
+    };
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.socket().as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TXTIME,
+            &cfg as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::sock_txtime>() as libc::socklen_t,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sends `buf` to `dest` with an `SCM_TXTIME` departure time of `at` (Linux)
+#[cfg(target_os = "linux")]
+pub fn send_at(socket: &crate::udp::Udp, buf: &[u8], dest: SocketAddr, at: Instant) -> io::Result<usize> {
+    use crate::raw as r;
+    use std::os::fd::AsRawFd;
+
+    let txtime = monotonic_ns(at);
+    let (_, name, name_len) = r::to_sockaddr(dest);
+    let mut iov = libc::iovec { iov_base: buf.as_ptr() as *mut _, iov_len: buf.len() };
+    // u64 storage keeps the cmsghdr aligned; 4 words fit CMSG_SPACE(8) on every ABI
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = match &name {
+        r::SockAddr::V4(s) => s as *const _ as *mut libc::c_void,
+        r::SockAddr::V6(s) => s as *const _ as *mut libc::c_void,
+    };
+    msg.msg_namelen = name_len;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut _;
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(std::mem::size_of::<u64>() as _) } as _;
+    // SAFETY: the control buffer is aligned and large enough for one u64 message
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_TXTIME;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u64>() as _) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u64, txtime);
+    }
+
+    let rc = unsafe { libc::sendmsg(socket.socket().as_raw_fd(), &msg, 0) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(rc as usize)
+}
+
+/// Converts an `Instant` to `CLOCK_MONOTONIC` nanoseconds through an anchor taken once
+#[cfg(target_os = "linux")]
+fn monotonic_ns(at: Instant) -> u64 {
+    use std::sync::OnceLock;
+
+    static ANCHOR: OnceLock<(Instant, u64)> = OnceLock::new();
+    let (instant, ns) = *ANCHOR.get_or_init(|| {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        (Instant::now(), ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+    });
+    if at >= instant {
+        ns + (at - instant).as_nanos() as u64
+    } else {
+        ns.saturating_sub((instant - at).as_nanos() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memnet::MemNetwork;
+
+    #[test]
+    fn test_packets_leave_in_departure_order_not_before_their_time() {
+        let net = MemNetwork::new();
+        let tx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let rx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let dest = rx.local_addr();
+        let start = Instant::now() + Duration::from_millis(5);
+        let mut pacer = PacedSender::new().spin(Duration::from_millis(1));
+        pacer.enqueue(b"c".to_vec(), dest, start + Duration::from_millis(4));
+        pacer.enqueue(b"a".to_vec(), dest, start);
+        pacer.enqueue(b"b".to_vec(), dest, start + Duration::from_millis(2));
+        pacer.enqueue(b"b2".to_vec(), dest, start + Duration::from_millis(2));
+
+        assert_eq!(pacer.poll_send(&tx).unwrap(), 0);
+        assert!(pacer.timeout(Instant::now()).unwrap() <= Duration::from_millis(4));
+        pacer.send_all(&tx).unwrap();
+        assert!(Instant::now() >= start + Duration::from_millis(4));
+        assert!(pacer.is_empty());
+
+        let mut bufs = vec![Vec::new(); 8];
+        let mut addrs = vec![dest; 8];
+        let n = rx.recv_batch(&mut bufs, &mut addrs).unwrap();
+        let got: Vec<&[u8]> = bufs[..n].iter().map(|b| b.as_slice()).collect();
+        assert_eq!(got, [&b"a"[..], b"b", b"b2", b"c"]);
+        assert_eq!(pacer.stats().sent, 4);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_txtime_send_reaches_peer() {
+        use crate::{udp::Udp, NetConfig};
+
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        match enable_txtime(&tx, false) {
+            Ok(()) => {}
+            // Kernels before 4.19 have no SO_TXTIME
+            Err(e) if e.raw_os_error() == Some(libc::ENOPROTOOPT) || e.raw_os_error() == Some(libc::EINVAL) => return,
+            Err(e) => panic!("{}", e),
+        }
+        let mut pacer = PacedSender::new();
+        pacer.enqueue(b"timed".to_vec(), rx.local_addr().unwrap(), Instant::now() + Duration::from_millis(1));
+        pacer.enqueue(b"later".to_vec(), rx.local_addr().unwrap(), Instant::now() + Duration::from_secs(60));
+        assert_eq!(pacer.poll_send_txtime(&tx, Duration::from_millis(100)).unwrap(), 1);
+        assert_eq!(pacer.len(), 1);
+
+        let mut pkt = [0u8; 64];
+        for _ in 0..1000 {
+            match rx.socket().recv_from(&mut pkt) {
+                Ok((n, _)) => return assert_eq!(&pkt[..n], b"timed"),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        panic!("txtime packet never arrived");
+    }
+}
@@ -0,0 +1,218 @@
+//! Pure decoders for untrusted kernel and user input
+//!
+//! A few places in the crate decode bytes it did not produce: socket
+//! addresses and control messages filled in by the kernel, and CPU lists
+//! read from sysfs or configuration. Each decoder here takes a plain slice
+//! or string, never panics, and never reads outside its input, whatever the
+//! bytes. That makes them safe entry points for fuzzing (see the targets
+//! under `fuzz/`), and the socket code calls the same functions, so the
+//! fuzzed code is the code that runs.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::parse;
+//!
+//! assert_eq!(parse::cpu_list("0-2,8").unwrap(), [0, 1, 2, 8]);
+//! assert!(parse::cpu_list("0-99999999").is_err());
+//! ```
+
+use std::io;
+#[cfg(unix)]
+use std::net::SocketAddr;
+
+/// Highest CPU number accepted by [`cpu_list`] and [`cpu_mask`], exclusive
+///
+/// Linux supports at most 8192 CPUs; the margin keeps odd platforms
+/// working while stopping a hostile range like `0-4294967295` from
+/// allocating gigabytes.
+pub const MAX_CPUS: usize = 65536;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses a Linux CPU list such as `0-3,8-11` into sorted CPU numbers
+///
+/// Empty entries are skipped. Reversed ranges, non-numeric entries, and
+/// CPU numbers of [`MAX_CPUS`] or more are rejected with `InvalidData`.
+pub fn cpu_list(list: &str) -> io::Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        let range = range.trim();
+        if range.is_empty() {
+            continue;
+        }
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (cpu_number(start)?, cpu_number(end)?),
+            None => {
+                let cpu = cpu_number(range)?;
+                (cpu, cpu)
+            }
+        };
+        if start > end {
+            return Err(invalid("Invalid CPU range"));
+        }
+        cpus.extend(start..=end);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+fn cpu_number(s: &str) -> io::Result<usize> {
+    match s.parse::<usize>() {
+        Ok(cpu) if cpu < MAX_CPUS => Ok(cpu),
+        _ => Err(invalid("Invalid CPU number")),
+    }
+}
+
+/// Parses a sysfs CPU mask such as `00000001,000000ff` into sorted CPU numbers
+///
+/// Words are comma-separated 32-bit hex values, most significant first.
+pub fn cpu_mask(mask: &str) -> io::Result<Vec<usize>> {
+    let words: Vec<&str> = mask.split(',').collect();
+    if words.len() > MAX_CPUS / 32 {
+        return Err(invalid("Invalid CPU mask"));
+    }
+    let mut cpus = Vec::new();
+    for (word_idx, word) in words.iter().rev().enumerate() {
+        if word.is_empty() || !word.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid("Invalid CPU mask"));
+        }
+        let bits = u32::from_str_radix(word, 16).map_err(|_| invalid("Invalid CPU mask"))?;
+        cpus.extend((0..32).filter(|bit| bits & (1 << bit) != 0).map(|bit| word_idx * 32 + bit));
+    }
+    cpus.sort_unstable();
+    Ok(cpus)
+}
+
+/// Decodes a native `sockaddr` (as filled in by `recvfrom`, `getsockname`, ...) into a `SocketAddr`
+///
+/// `bytes` is the address as returned by the kernel, truncated to the
+/// reported length. Returns `None` for families other than IPv4 and IPv6
+/// and for input shorter than the family's address structure.
+#[cfg(unix)]
+pub fn sockaddr(bytes: &[u8]) -> Option<SocketAddr> {
+    use std::mem::size_of;
+
+    let mut ss: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = bytes.len().min(size_of::<libc::sockaddr_storage>());
+    // SAFETY: `sockaddr_storage` is plain data and `len` fits in both buffers
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut ss as *mut _ as *mut u8, len) };
+    let needed = match ss.ss_family as i32 {
+        libc::AF_INET => size_of::<libc::sockaddr_in>(),
+        libc::AF_INET6 => size_of::<libc::sockaddr_in6>(),
+        _ => return None,
+    };
+    if len < needed {
+        return None;
+    }
+    crate::raw::from_sockaddr(&ss)
+}
+
+/// One control message from a `recvmsg` ancillary data buffer
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cmsg<'a> {
+    /// Originating protocol, e.g. `SOL_SOCKET` or `IPPROTO_IP`
+    pub level: i32,
+    /// Protocol-specific type, e.g. `SO_RXQ_OVFL` or `IP_RECVERR`
+    pub kind: i32,
+    /// Payload following the header
+    pub data: &'a [u8],
+}
+
+/// Iterator over the control messages in an ancillary data buffer
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct Cmsgs<'a> {
+    buf: &'a [u8],
+}
+
+/// Walks the control messages in `control`, the first `msg_controllen` bytes of a `recvmsg` control buffer
+///
+/// Follows the platform's `CMSG_FIRSTHDR`/`CMSG_NXTHDR` layout but works on
+/// a slice, so the buffer needs no particular alignment. Iteration stops
+/// at the first truncated or malformed header.
+#[cfg(unix)]
+pub fn cmsgs(control: &[u8]) -> Cmsgs<'_> {
+    Cmsgs { buf: control }
+}
+
+#[cfg(unix)]
+impl<'a> Iterator for Cmsgs<'a> {
+    type Item = Cmsg<'a>;
+
+    fn next(&mut self) -> Option<Cmsg<'a>> {
+        if self.buf.len() < std::mem::size_of::<libc::cmsghdr>() {
+            return None;
+        }
+        // SAFETY: the slice holds a whole header; read_unaligned needs no alignment
+        let hdr = unsafe { std::ptr::read_unaligned(self.buf.as_ptr() as *const libc::cmsghdr) };
+        // Aligned header size, i.e. the offset of the payload
+        let head = unsafe { libc::CMSG_LEN(0) } as usize;
+        let len: usize = hdr.cmsg_len as _;
+        if len < head || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+        let item = Cmsg { level: hdr.cmsg_level, kind: hdr.cmsg_type, data: &self.buf[head..len] };
+        // The next header starts at the aligned end of this message, if the buffer reaches it
+        let step = unsafe { libc::CMSG_SPACE((len - head) as _) } as usize;
+        self.buf = self.buf.get(step..).unwrap_or_default();
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_parsers_reject_hostile_input() {
+        assert_eq!(cpu_list(" 3, 0-1 ,,1").unwrap(), [0, 1, 3]);
+        for bad in ["0-4294967295", "5-2", "x", "1-", "-1", "65536"] {
+            assert!(cpu_list(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(cpu_mask("1,80000000").unwrap(), [31, 32]);
+        for bad in ["", "1,,2", "+1", "123456789"] {
+            assert!(cpu_mask(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cmsgs_and_sockaddr_on_raw_bytes() {
+        // Two messages laid out as the kernel would, then a truncated header
+        let mut control = Vec::new();
+        for (kind, data) in [(1, &[7u8, 0, 0, 0][..]), (2, &[1u8, 2, 3][..])] {
+            let len = unsafe { libc::CMSG_LEN(data.len() as _) } as usize;
+            let space = unsafe { libc::CMSG_SPACE(data.len() as _) } as usize;
+            let mut hdr: libc::cmsghdr = unsafe { std::mem::zeroed() };
+            hdr.cmsg_len = len as _;
+            hdr.cmsg_level = libc::SOL_SOCKET;
+            hdr.cmsg_type = kind;
+            let start = control.len();
+            control.resize(start + space, 0);
+            unsafe { std::ptr::write_unaligned(control[start..].as_mut_ptr() as *mut libc::cmsghdr, hdr) };
+            let head = unsafe { libc::CMSG_LEN(0) } as usize;
+            control[start + head..start + len].copy_from_slice(data);
+        }
+        control.extend_from_slice(&[0xff; 5]);
+        let parsed: Vec<Cmsg> = cmsgs(&control).collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((parsed[0].kind, parsed[0].data), (1, &[7u8, 0, 0, 0][..]));
+        assert_eq!((parsed[1].kind, parsed[1].data), (2, &[1u8, 2, 3][..]));
+
+        let addr: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        let (_, sa, len) = crate::raw::to_sockaddr(addr);
+        let bytes = match &sa {
+            crate::raw::SockAddr::V4(s) => unsafe { std::slice::from_raw_parts(s as *const _ as *const u8, len as usize) },
+            crate::raw::SockAddr::V6(s) => unsafe { std::slice::from_raw_parts(s as *const _ as *const u8, len as usize) },
+        };
+        assert_eq!(sockaddr(bytes), Some(addr));
+        assert_eq!(sockaddr(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(sockaddr(&[]), None);
+    }
+}
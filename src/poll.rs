@@ -0,0 +1,227 @@
+//! Lightweight readiness waiting without a runtime
+//!
+//! [`wait`] blocks until one of a handful of the crate's sockets becomes
+//! readable or writable, using `poll(2)` on Unix and `WSAPoll` on Windows.
+//! It is meant for simple clients and tools that only juggle one or two
+//! sockets and do not want to construct a full [`Runtime`](crate::Runtime).
+//!
+//! For many sockets or long-lived event loops, prefer the runtime backends:
+//! `poll` scans every handle on each call.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::poll::{self, Interest};
+//! use std::time::Duration;
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let ready = poll::wait(&[&socket], Interest::READABLE, Some(Duration::from_secs(1)))?;
+//! if ready[0].readable {
+//!     // recv_batch will not return WouldBlock now
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::raw::{self as r, OsSocket};
+use crate::trace;
+use crate::tcp::{TcpListener, TcpStream};
+use crate::udp::Udp;
+use std::io;
+use std::ops::BitOr;
+use std::time::{Duration, Instant};
+
+/// Readiness conditions to wait for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interest(u8);
+
+impl Interest {
+    /// Wait until data (or a connection) can be read
+    pub const READABLE: Interest = Interest(0b01);
+    /// Wait until data can be written without blocking
+    pub const WRITABLE: Interest = Interest(0b10);
+
+    /// Returns `true` if readability is requested
+    pub fn is_readable(self) -> bool {
+        self.0 & Self::READABLE.0 != 0
+    }
+
+    /// Returns `true` if writability is requested
+    pub fn is_writable(self) -> bool {
+        self.0 & Self::WRITABLE.0 != 0
+    }
+}
+
+impl BitOr for Interest {
+    type Output = Interest;
+
+    fn bitor(self, rhs: Interest) -> Interest {
+        Interest(self.0 | rhs.0)
+    }
+}
+
+/// Readiness reported for one socket by [`wait`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Readiness {
+    /// The socket can be read (or accepted from) without blocking
+    pub readable: bool,
+    /// The socket can be written without blocking
+    pub writable: bool,
+    /// An error is pending on the socket
+    pub error: bool,
+    /// The peer closed the connection
+    pub hangup: bool,
+}
+
+impl Readiness {
+    /// Returns `true` if any condition was reported
+    pub fn is_ready(&self) -> bool {
+        self.readable || self.writable || self.error || self.hangup
+    }
+}
+
+/// Sockets that can be passed to [`wait`]
+pub trait Pollable {
+    /// Returns the raw handle to poll
+    fn poll_handle(&self) -> OsSocket;
+}
+
+impl Pollable for Udp {
+    fn poll_handle(&self) -> OsSocket {
+        r::os_handle(self.socket())
+    }
+}
+
+impl Pollable for TcpListener {
+    fn poll_handle(&self) -> OsSocket {
+        r::os_handle(self.as_std())
+    }
+}
+
+impl Pollable for TcpStream {
+    fn poll_handle(&self) -> OsSocket {
+        r::os_handle(self.as_std())
+    }
+}
+
+/// Waits until at least one socket is ready or the timeout elapses
+///
+/// # Arguments
+///
+/// * `sockets` - Sockets to watch; at most a handful is recommended
+/// * `interest` - Conditions to wait for, applied to every socket
+/// * `timeout` - Maximum time to wait; `None` waits indefinitely
+///
+/// # Returns
+///
+/// One [`Readiness`] per socket, in the same order as `sockets`. If the
+/// timeout elapsed, every entry reports not ready. Interrupted waits are
+/// retried with the remaining time.
+pub fn wait(sockets: &[&dyn Pollable], interest: Interest, timeout: Option<Duration>) -> io::Result<Vec<Readiness>> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        match poll_once(sockets, interest, remaining) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => {
+                trace::event!(trace, sockets = sockets.len(), ?timeout, ready = ?res, "poll wait");
+                return res;
+            }
+        }
+    }
+}
+
+/// Converts a timeout to milliseconds, rounding up so short waits never spin
+fn timeout_ms(timeout: Option<Duration>) -> i32 {
+    match timeout {
+        None => -1,
+        Some(t) => {
+            let ms = t.as_nanos().div_ceil(1_000_000);
+            ms.min(i32::MAX as u128) as i32
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        fn poll_once(sockets: &[&dyn Pollable], interest: Interest, timeout: Option<Duration>) -> io::Result<Vec<Readiness>> {
+            let mut events = 0;
+            if interest.is_readable() { events |= libc::POLLIN; }
+            if interest.is_writable() { events |= libc::POLLOUT; }
+            let mut fds: Vec<libc::pollfd> = sockets
+                .iter()
+                .map(|s| libc::pollfd { fd: s.poll_handle(), events, revents: 0 })
+                .collect();
+            let rc = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms(timeout)) };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(fds
+                .iter()
+                .map(|p| Readiness {
+                    readable: p.revents & libc::POLLIN != 0,
+                    writable: p.revents & libc::POLLOUT != 0,
+                    error: p.revents & (libc::POLLERR | libc::POLLNVAL) != 0,
+                    hangup: p.revents & libc::POLLHUP != 0,
+                })
+                .collect())
+        }
+    } else if #[cfg(windows)] {
+        use windows_sys::Win32::Networking::WinSock::{
+            WSAGetLastError, WSAPoll, WSAPOLLFD, POLLERR, POLLHUP, POLLNVAL, POLLRDNORM, POLLWRNORM,
+        };
+
+        fn poll_once(sockets: &[&dyn Pollable], interest: Interest, timeout: Option<Duration>) -> io::Result<Vec<Readiness>> {
+            let mut events = 0;
+            if interest.is_readable() { events |= POLLRDNORM; }
+            if interest.is_writable() { events |= POLLWRNORM; }
+            let mut fds: Vec<WSAPOLLFD> = sockets
+                .iter()
+                .map(|s| WSAPOLLFD { fd: s.poll_handle() as usize, events, revents: 0 })
+                .collect();
+            let rc = unsafe { WSAPoll(fds.as_mut_ptr(), fds.len() as u32, timeout_ms(timeout)) };
+            if rc < 0 {
+                return Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() }));
+            }
+            Ok(fds
+                .iter()
+                .map(|p| Readiness {
+                    readable: p.revents & POLLRDNORM != 0,
+                    writable: p.revents & POLLWRNORM != 0,
+                    error: p.revents & (POLLERR | POLLNVAL) != 0,
+                    hangup: p.revents & POLLHUP != 0,
+                })
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+
+    #[test]
+    fn test_wait_reports_udp_readiness() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+
+        let idle = wait(&[&socket], Interest::READABLE, Some(Duration::ZERO)).unwrap();
+        assert!(!idle[0].is_ready());
+
+        let writable = wait(&[&socket], Interest::WRITABLE, Some(Duration::from_secs(1))).unwrap();
+        assert!(writable[0].writable);
+
+        socket.send_to(b"ping", socket.local_addr().unwrap()).unwrap();
+        let ready = wait(&[&socket], Interest::READABLE | Interest::WRITABLE, Some(Duration::from_secs(1))).unwrap();
+        assert!(ready[0].readable);
+    }
+
+    #[test]
+    fn test_timeout_rounds_up() {
+        assert_eq!(timeout_ms(None), -1);
+        assert_eq!(timeout_ms(Some(Duration::ZERO)), 0);
+        assert_eq!(timeout_ms(Some(Duration::from_micros(10))), 1);
+        assert_eq!(timeout_ms(Some(Duration::from_secs(u64::MAX))), i32::MAX);
+    }
+}
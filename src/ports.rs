@@ -0,0 +1,270 @@
+//! Reservation of local ports for sockets that must use known source ports
+//!
+//! Firewalls and peering agreements sometimes only admit traffic from a
+//! whitelisted port range. Binding to such a port on demand races with
+//! every other process on the host, including the kernel's own ephemeral
+//! port selection. A [`PortAllocator`] avoids the race by binding and
+//! holding each port up front with placeholder UDP and TCP sockets, then
+//! handing ports out as [`PortLease`]s. A lease gives up a placeholder only
+//! at the moment the real socket binds, and returns the port to the pool
+//! when dropped.
+//!
+//! [`ephemeral_range`] reports the range the kernel picks source ports
+//! from (`ip_local_port_range` on Linux), and
+//! [`PortAllocator::ephemeral`] reserves ports from it.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::NetConfig;
+//! use horizon_sockets::ports::PortAllocator;
+//!
+//! let ports = PortAllocator::new("127.0.0.1".parse()?, 47000..=47099, 8)?;
+//! let mut lease = ports.acquire()?;
+//! let socket = lease.bind_udp(&NetConfig::default())?;
+//! assert_eq!(socket.local_addr()?.port(), lease.port());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Reclaiming
+//!
+//! A dropped lease's port is reserved again on the next
+//! [`acquire`](PortAllocator::acquire) or [`reclaim`](PortAllocator::reclaim)
+//! once it can be bound. A TCP port stays unavailable while a connection
+//! that used it sits in TIME_WAIT.
+
+use crate::raw as r;
+use crate::tcp::TcpStream;
+use crate::udp::Udp;
+use crate::NetConfig;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Returns the range the kernel assigns ephemeral source ports from
+///
+/// Reads `/proc/sys/net/ipv4/ip_local_port_range` on Linux; elsewhere
+/// returns the IANA dynamic range 49152-65535 that Windows and macOS use by
+/// default.
+pub fn ephemeral_range() -> io::Result<RangeInclusive<u16>> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let text = std::fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range")?;
+            let mut bounds = text.split_whitespace().map(str::parse::<u16>);
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(low)), Some(Ok(high))) if low <= high => Ok(low..=high),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, "malformed ip_local_port_range")),
+            }
+        } else {
+            Ok(49152..=65535)
+        }
+    }
+}
+
+/// Placeholder sockets holding one port
+#[derive(Debug)]
+struct Hold {
+    port: u16,
+    udp: Option<std::net::UdpSocket>,
+    /// Bound but never listening, so it holds the port without accepting
+    tcp: Option<std::net::TcpStream>,
+}
+
+impl Hold {
+    fn bind(ip: IpAddr, port: u16) -> io::Result<Self> {
+        let addr = SocketAddr::new(ip, port);
+        let (domain, sa, len) = r::to_sockaddr(addr);
+        let udp_os = r::socket(domain, r::Type::Dgram, r::Protocol::Udp)?;
+        let udp = unsafe { r::udp_from_os(udp_os) };
+        unsafe { r::bind_raw(udp_os, &sa, len) }?;
+        let tcp_os = r::socket(domain, r::Type::Stream, r::Protocol::Tcp)?;
+        let tcp = unsafe { r::tcp_stream_from_os(tcp_os) };
+        unsafe { r::bind_raw(tcp_os, &sa, len) }?;
+        Ok(Self { port, udp: Some(udp), tcp: Some(tcp) })
+    }
+}
+
+#[derive(Debug)]
+struct Pool {
+    free: VecDeque<Hold>,
+    /// Ports of dropped leases waiting to be bound again
+    returned: Vec<u16>,
+}
+
+/// Pool of pre-bound local ports handed out as [`PortLease`]s
+///
+/// Cheap to share: clones refer to the same pool.
+#[derive(Clone, Debug)]
+pub struct PortAllocator {
+    ip: IpAddr,
+    pool: Arc<Mutex<Pool>>,
+}
+
+impl PortAllocator {
+    /// Reserves up to `max` ports from `range` on `ip`
+    ///
+    /// Ports already in use (for UDP or TCP) are skipped. Each reserved port
+    /// costs two file descriptors while held.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput` if `range` is empty or includes port 0
+    /// - `AddrInUse` if no port in `range` could be reserved
+    pub fn new(ip: IpAddr, range: RangeInclusive<u16>, max: usize) -> io::Result<Self> {
+        if range.is_empty() || *range.start() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "port range must be non-empty and exclude 0"));
+        }
+        let free: VecDeque<Hold> = range.filter_map(|port| Hold::bind(ip, port).ok()).take(max).collect();
+        if free.is_empty() && max > 0 {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "no free port in range"));
+        }
+        Ok(Self { ip, pool: Arc::new(Mutex::new(Pool { free, returned: Vec::new() })) })
+    }
+
+    /// Reserves up to `count` ports from the kernel's ephemeral range
+    ///
+    /// Held ports are withheld from the kernel's own source port selection,
+    /// so this shrinks the ephemeral range available to other connections.
+    pub fn ephemeral(ip: IpAddr, count: usize) -> io::Result<Self> {
+        Self::new(ip, ephemeral_range()?, count)
+    }
+
+    /// Hands out a reserved port, reclaiming returned ones first if needed
+    ///
+    /// Fails with `AddrInUse` when every reserved port is leased.
+    pub fn acquire(&self) -> io::Result<PortLease> {
+        let mut pool = self.lock();
+        if pool.free.is_empty() {
+            self.reclaim_locked(&mut pool);
+        }
+        let hold = pool
+            .free
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::AddrInUse, "all reserved ports are leased"))?;
+        Ok(PortLease { ip: self.ip, hold: Some(hold), pool: self.pool.clone() })
+    }
+
+    /// Binds the ports of dropped leases again, returning how many were reclaimed
+    ///
+    /// Ports still bound elsewhere (for example in TIME_WAIT) stay pending.
+    pub fn reclaim(&self) -> usize {
+        let mut pool = self.lock();
+        self.reclaim_locked(&mut pool)
+    }
+
+    /// Returns the number of ports ready to be leased
+    pub fn available(&self) -> usize {
+        self.lock().free.len()
+    }
+
+    fn reclaim_locked(&self, pool: &mut Pool) -> usize {
+        let before = pool.free.len();
+        let mut pending = Vec::new();
+        for port in std::mem::take(&mut pool.returned) {
+            match Hold::bind(self.ip, port) {
+                Ok(hold) => pool.free.push_back(hold),
+                Err(_) => pending.push(port),
+            }
+        }
+        pool.returned = pending;
+        pool.free.len() - before
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pool> {
+        self.pool.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A port taken from a [`PortAllocator`], returned to it on drop
+#[derive(Debug)]
+pub struct PortLease {
+    ip: IpAddr,
+    hold: Option<Hold>,
+    pool: Arc<Mutex<Pool>>,
+}
+
+impl PortLease {
+    /// Returns the leased port
+    pub fn port(&self) -> u16 {
+        self.hold.as_ref().map_or(0, |h| h.port)
+    }
+
+    /// Returns the leased local address
+    pub fn local_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port())
+    }
+
+    /// Binds a UDP socket to the leased port
+    ///
+    /// The UDP placeholder is released immediately before the bind.
+    pub fn bind_udp(&mut self, cfg: &NetConfig) -> io::Result<Udp> {
+        if let Some(hold) = self.hold.as_mut() {
+            hold.udp = None;
+        }
+        Udp::bind(self.local_addr(), cfg)
+    }
+
+    /// Connects a TCP stream to `addr` from the leased port
+    ///
+    /// The TCP placeholder is released immediately before the bind.
+    pub fn connect_tcp(&mut self, addr: SocketAddr, cfg: &NetConfig) -> io::Result<TcpStream> {
+        if let Some(hold) = self.hold.as_mut() {
+            hold.tcp = None;
+        }
+        TcpStream::connect_from(Some(self.local_addr()), addr, cfg, None)
+    }
+}
+
+impl Drop for PortLease {
+    fn drop(&mut self) {
+        let Some(hold) = self.hold.take() else { return };
+        let mut pool = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        if hold.udp.is_some() && hold.tcp.is_some() {
+            // Never used, so the placeholders still hold the port
+            pool.free.push_back(hold);
+        } else {
+            pool.returned.push(hold.port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leases_hold_and_return_ports() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let ports = PortAllocator::new(ip, 46000..=46199, 2).unwrap();
+        assert_eq!(ports.available(), 2);
+
+        let mut lease = ports.acquire().unwrap();
+        let second = ports.acquire().unwrap();
+        assert_eq!(ports.acquire().unwrap_err().kind(), io::ErrorKind::AddrInUse);
+
+        // The placeholder keeps others off the port until the lease binds
+        let cfg = NetConfig { reuse_port: false, ..Default::default() };
+        assert!(Udp::bind(second.local_addr(), &cfg).is_err());
+        let socket = lease.bind_udp(&cfg).unwrap();
+        assert_eq!(socket.local_addr().unwrap(), lease.local_addr());
+
+        // An unused lease goes straight back; a used one once its socket closes
+        drop(second);
+        assert_eq!(ports.available(), 1);
+        drop(lease);
+        assert_eq!(ports.reclaim(), 0);
+        drop(socket);
+        assert_eq!(ports.reclaim(), 1);
+        assert_eq!(ports.available(), 2);
+    }
+
+    #[test]
+    fn test_range_validation_and_ephemeral_range() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(PortAllocator::new(ip, 0..=10, 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let range = ephemeral_range().unwrap();
+        assert!(range.start() <= range.end() && *range.start() > 0);
+    }
+}
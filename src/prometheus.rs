@@ -0,0 +1,184 @@
+//! Prometheus text exposition of the [`metrics`](crate::metrics) registry
+//!
+//! [`Exporter`] answers `GET /metrics` in the Prometheus text format
+//! (version 0.0.4) as an [`http::Handler`](crate::http::Handler).
+//! [`Exporter::serve`] binds a listener and returns the
+//! [`HttpServer`](crate::http::HttpServer) that serves it, polled from the
+//! application's own loop like the [`health`](crate::health) endpoint.
+//!
+//! Applications that already run an HTTP server can skip the listener and
+//! route to the exporter, or call [`render`] from their own handler.
+//!
+//! Samples sharing a name are grouped under one `# HELP`/`# TYPE` header,
+//! so per-socket series such as `socket_recv_buffer_bytes{socket="game"}`
+//! appear as one metric family. Label values are escaped as the format
+//! requires.
+//!
+//! Requires the `prometheus` feature; `serve` is available on Unix.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::metrics::Metrics;
+//! use horizon_sockets::prometheus::Exporter;
+//! use horizon_sockets::NetConfig;
+//!
+//! let metrics = Metrics::new();
+//! metrics.gauge("sessions", "Connected sessions", || 12.0);
+//! # #[cfg(unix)]
+//! # {
+//! let mut server = Exporter::new(metrics).serve("0.0.0.0:9464".parse()?, &NetConfig::default())?;
+//! println!("scrape http://{}/metrics", server.local_addr());
+//! server.run()?;
+//! # }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::http::{Handler, Request, Response};
+use crate::metrics::{MetricKind, Metrics, Sample};
+use std::fmt::Write as _;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Request handler serving `/metrics`
+#[derive(Clone)]
+pub struct Exporter {
+    metrics: Metrics,
+}
+
+impl std::fmt::Debug for Exporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Exporter").finish_non_exhaustive()
+    }
+}
+
+impl Exporter {
+    /// Creates a handler rendering `metrics`
+    pub fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+
+    /// Binds `addr` and returns a server answering scrapes, to be polled by the caller (Unix)
+    #[cfg(unix)]
+    pub fn serve(
+        &self,
+        addr: std::net::SocketAddr,
+        cfg: &crate::NetConfig,
+    ) -> std::io::Result<crate::http::HttpServer<impl FnMut() -> Exporter, Exporter>> {
+        let exporter = self.clone();
+        let limits = crate::http::HttpLimits { max_head: 4096, max_body: 0, idle_timeout: std::time::Duration::from_secs(5) };
+        Ok(crate::http::HttpServer::bind(addr, cfg, move || exporter.clone())?.limits(limits))
+    }
+}
+
+impl Handler for Exporter {
+    fn handle(&mut self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::new(405).header("Allow", "GET, HEAD");
+        }
+        match request.path() {
+            "/metrics" => Response::ok(render(&self.metrics)).header("Content-Type", CONTENT_TYPE),
+            _ => Response::new(404).header("Content-Type", "text/plain").body("not found\n"),
+        }
+    }
+}
+
+/// Renders every sample in `metrics` in the text exposition format
+pub fn render(metrics: &Metrics) -> String {
+    let samples = metrics.samples();
+    // Group by name, keeping families in order of first appearance
+    let mut families: Vec<(&str, Vec<&Sample>)> = Vec::new();
+    for sample in &samples {
+        match families.iter_mut().find(|(name, _)| *name == sample.name) {
+            Some((_, members)) => members.push(sample),
+            None => families.push((&sample.name, vec![sample])),
+        }
+    }
+
+    let mut out = String::new();
+    for (name, members) in families {
+        let kind = match members[0].kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        let _ = writeln!(out, "# HELP {} {}", name, members[0].help.replace('\\', "\\\\").replace('\n', "\\n"));
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for sample in members {
+            out.push_str(name);
+            if !sample.labels.is_empty() {
+                out.push('{');
+                for (i, (key, value)) in sample.labels.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "{}=\"{}\"", key, escape_label(value));
+                }
+                out.push('}');
+            }
+            out.push(' ');
+            push_value(&mut out, sample.value);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Escapes backslash, double quote, and newline in a label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes a sample value, spelling non-finite values the way Prometheus parses them
+fn push_value(out: &mut String, value: f64) {
+    if value.is_nan() {
+        out.push_str("NaN");
+    } else if value.is_infinite() {
+        out.push_str(if value > 0.0 { "+Inf" } else { "-Inf" });
+    } else {
+        let _ = write!(out, "{}", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{udp::Udp, NetConfig};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_render_groups_families_and_escapes() {
+        let metrics = Metrics::new();
+        metrics.counter("drops_total", "Dropped\ndatagrams", || 3.0);
+        metrics.gauge("load", "Loop load", || f64::NAN);
+        let a = Arc::new(Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap());
+        let b = Arc::new(Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap());
+        metrics.socket("game", &a);
+        metrics.socket("say \"hi\"", &b);
+
+        let text = render(&metrics);
+        assert!(text.starts_with("# HELP uptime_seconds "));
+        assert!(text.contains("# HELP drops_total Dropped\\ndatagrams\n# TYPE drops_total counter\ndrops_total 3\n"));
+        assert!(text.contains("load NaN\n"));
+        assert_eq!(text.matches("# TYPE socket_recv_buffer_bytes gauge").count(), 1);
+        assert!(text.contains("socket_recv_buffer_bytes{socket=\"game\""));
+        assert!(text.contains("socket=\"say \\\"hi\\\"\""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serves_metrics_path() {
+        use crate::http::exchange;
+
+        let metrics = Metrics::new();
+        metrics.gauge("sessions", "Connected sessions", || 12.0);
+        let mut server = Exporter::new(metrics).serve("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let mut get = |path: &str| exchange(&mut server, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).as_bytes());
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("# TYPE sessions gauge\nsessions 12\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
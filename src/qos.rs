@@ -0,0 +1,265 @@
+//! Windows traffic marking through qWAVE (QoS2) and socket priority hints
+//!
+//! Windows ignores `IP_TOS` set by ordinary applications unless a registry
+//! switch is flipped, so [`NetConfig::tos`](crate::NetConfig::tos) alone
+//! leaves packets unmarked. The supported route is the QoS2 (qWAVE) API:
+//! a socket is added to a flow with a traffic type, and Windows marks the
+//! flow's packets with the DSCP value policy assigns to that type. With
+//! administrator rights the exact DSCP value can be set as well.
+//!
+//! - [`QosHandle`] wraps `QOSCreateHandle` and adds sockets to flows.
+//! - [`mark_flow`] uses a process-wide handle to mark a socket towards one
+//!   destination with the traffic type closest to a TOS byte, and applies
+//!   the exact DSCP where permitted. TCP streams created by
+//!   [`TcpStream::connect`](crate::tcp::TcpStream::connect) or
+//!   [`TcpStream::from_std`](crate::tcp::TcpStream::from_std) with a `tos`
+//!   in their [`NetConfig`](crate::NetConfig) are marked this way
+//!   automatically. Accepted streams are not, since a listener keeps no
+//!   configuration; mark each one after accepting it. Connected UDP
+//!   sockets need one call; unconnected ones need one per destination.
+//! - [`set_priority_hint`] issues `SIO_SET_PRIORITY_HINT`, which lets
+//!   bulk transfers yield bandwidth to interactive traffic (Windows 10
+//!   2004 and later).
+//!
+//! Flows end when the socket closes. qWAVE is missing on some Server
+//! installations; the functions then return the underlying error and
+//! automatic marking is skipped.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::qos::{self, PriorityHint};
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//!
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+//! // DSCP EF: voice traffic type, and exactly EF when running elevated
+//! qos::mark_flow(socket.socket(), Some("203.0.113.7:7777".parse()?), 0xB8)?;
+//!
+//! let bulk = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+//! qos::set_priority_hint(bulk.socket(), PriorityHint::Low)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::raw as r;
+use crate::trace;
+use std::io;
+use std::net::SocketAddr;
+use std::os::windows::io::AsRawSocket;
+use std::sync::OnceLock;
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::NetworkManagement::QoS::{
+    QOSAddSocketToFlow, QOSCloseHandle, QOSCreateHandle, QOSRemoveSocketFromFlow, QOSSetFlow, QOSSetOutgoingDSCPValue,
+    QOSTrafficTypeAudioVideo, QOSTrafficTypeBackground, QOSTrafficTypeBestEffort, QOSTrafficTypeControl,
+    QOSTrafficTypeExcellentEffort, QOSTrafficTypeVoice, QOS_NON_ADAPTIVE_FLOW, QOS_TRAFFIC_TYPE, QOS_VERSION,
+};
+use windows_sys::Win32::Networking::WinSock::{
+    SocketPriorityHintLow, SocketPriorityHintNormal, SocketPriorityHintVeryLow, WSAIoctl, SIO_SET_PRIORITY_HINT, SOCKADDR,
+    SOCKET, SOCKET_ERROR, SOCKET_PRIORITY_HINT,
+};
+
+/// qWAVE traffic type, which Windows policy maps to a DSCP value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficType {
+    /// Ordinary traffic (DSCP 0)
+    BestEffort,
+    /// Bulk transfers that should yield to everything else (CS1)
+    Background,
+    /// More important than best effort, e.g. game state (CS5)
+    ExcellentEffort,
+    /// Latency-sensitive media streams (CS5)
+    AudioVideo,
+    /// Real-time voice (CS7)
+    Voice,
+    /// Network control traffic (CS7)
+    Control,
+}
+
+impl TrafficType {
+    /// Picks the traffic type closest to a TOS byte as used by [`NetConfig::tos`](crate::NetConfig::tos)
+    ///
+    /// EF becomes voice, CS4 through AF43 audio/video, CS1 through AF13
+    /// background, CS6 and CS7 control, and other non-zero values excellent
+    /// effort.
+    pub fn from_tos(tos: u32) -> Self {
+        match (tos >> 2) & 0x3f {
+            0 => Self::BestEffort,
+            46 => Self::Voice,
+            8..=15 => Self::Background,
+            32..=39 => Self::AudioVideo,
+            48.. => Self::Control,
+            _ => Self::ExcellentEffort,
+        }
+    }
+
+    fn raw(self) -> QOS_TRAFFIC_TYPE {
+        match self {
+            Self::BestEffort => QOSTrafficTypeBestEffort,
+            Self::Background => QOSTrafficTypeBackground,
+            Self::ExcellentEffort => QOSTrafficTypeExcellentEffort,
+            Self::AudioVideo => QOSTrafficTypeAudioVideo,
+            Self::Voice => QOSTrafficTypeVoice,
+            Self::Control => QOSTrafficTypeControl,
+        }
+    }
+}
+
+/// Identifies a flow created by [`QosHandle::add_socket`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FlowId(u32);
+
+/// An open qWAVE handle; flows added through it end when it is dropped
+#[derive(Debug)]
+pub struct QosHandle {
+    handle: HANDLE,
+}
+
+// SAFETY: qWAVE handles may be used from any thread
+unsafe impl Send for QosHandle {}
+unsafe impl Sync for QosHandle {}
+
+impl QosHandle {
+    /// Opens a handle with `QOSCreateHandle`
+    pub fn new() -> io::Result<Self> {
+        let version = QOS_VERSION { MajorVersion: 1, MinorVersion: 0 };
+        let mut handle: HANDLE = std::ptr::null_mut();
+        if unsafe { QOSCreateHandle(&version, &mut handle) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Adds `socket` to a new non-adaptive flow of traffic type `ty`
+    ///
+    /// `dest` is required for unconnected sockets and must be `None` for
+    /// connected ones.
+    pub fn add_socket(&self, socket: &impl AsRawSocket, dest: Option<SocketAddr>, ty: TrafficType) -> io::Result<FlowId> {
+        let dest = dest.map(r::to_sockaddr);
+        let dest_ptr = match &dest {
+            Some((_, r::SockAddr::V4(s), _)) => s as *const _ as *const SOCKADDR,
+            Some((_, r::SockAddr::V6(s), _)) => s as *const _ as *const SOCKADDR,
+            None => std::ptr::null(),
+        };
+        let mut flow = 0u32;
+        let ok = unsafe {
+            QOSAddSocketToFlow(self.handle, socket.as_raw_socket() as SOCKET, dest_ptr, ty.raw(), QOS_NON_ADAPTIVE_FLOW, &mut flow)
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(FlowId(flow))
+    }
+
+    /// Sets the exact outgoing DSCP value (0-63) of a flow
+    ///
+    /// Requires administrator rights; fails with `PermissionDenied` otherwise.
+    pub fn set_dscp(&self, flow: FlowId, dscp: u8) -> io::Result<()> {
+        let value = u32::from(dscp & 0x3f);
+        let ok = unsafe {
+            QOSSetFlow(
+                self.handle,
+                flow.0,
+                QOSSetOutgoingDSCPValue,
+                std::mem::size_of::<u32>() as u32,
+                &value as *const u32 as *const _,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Removes `socket` from a flow before it closes
+    pub fn remove_socket(&self, socket: &impl AsRawSocket, flow: FlowId) -> io::Result<()> {
+        if unsafe { QOSRemoveSocketFromFlow(self.handle, socket.as_raw_socket() as SOCKET, flow.0, 0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for QosHandle {
+    fn drop(&mut self) {
+        unsafe { QOSCloseHandle(self.handle) };
+    }
+}
+
+/// Process-wide handle for [`mark_flow`]; `None` if qWAVE is unavailable
+fn shared() -> io::Result<&'static QosHandle> {
+    static SHARED: OnceLock<Option<QosHandle>> = OnceLock::new();
+    SHARED
+        .get_or_init(|| QosHandle::new().ok())
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "qWAVE is not available"))
+}
+
+/// Marks traffic from `socket` to `dest` according to a TOS byte
+///
+/// Adds the socket to a flow of [`TrafficType::from_tos`] on a shared
+/// process-wide handle, then tries to set the exact DSCP (`tos >> 2`). The
+/// DSCP step needs administrator rights and is skipped without them; the
+/// traffic type still applies. Pass `None` for connected sockets.
+pub fn mark_flow(socket: &impl AsRawSocket, dest: Option<SocketAddr>, tos: u32) -> io::Result<FlowId> {
+    let qos = shared()?;
+    let flow = qos.add_socket(socket, dest, TrafficType::from_tos(tos))?;
+    let exact = qos.set_dscp(flow, (tos >> 2) as u8);
+    if exact.is_err() {
+        trace::event!(debug, result = ?exact, "qos exact dscp not applied");
+    }
+    Ok(flow)
+}
+
+/// Bandwidth priority of a socket relative to others on the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriorityHint {
+    /// Yields to all other traffic, for background transfers
+    VeryLow,
+    /// Yields to normal traffic
+    Low,
+    /// The default
+    Normal,
+}
+
+/// Sets a socket's bandwidth priority with `SIO_SET_PRIORITY_HINT`
+pub fn set_priority_hint(socket: &impl AsRawSocket, hint: PriorityHint) -> io::Result<()> {
+    let value: SOCKET_PRIORITY_HINT = match hint {
+        PriorityHint::VeryLow => SocketPriorityHintVeryLow,
+        PriorityHint::Low => SocketPriorityHintLow,
+        PriorityHint::Normal => SocketPriorityHintNormal,
+    };
+    let mut returned = 0u32;
+    let rc = unsafe {
+        WSAIoctl(
+            socket.as_raw_socket() as SOCKET,
+            SIO_SET_PRIORITY_HINT,
+            &value as *const SOCKET_PRIORITY_HINT as *const _,
+            std::mem::size_of::<SOCKET_PRIORITY_HINT>() as u32,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+            None,
+        )
+    };
+    if rc == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tos_maps_to_traffic_type() {
+        assert_eq!(TrafficType::from_tos(0), TrafficType::BestEffort);
+        assert_eq!(TrafficType::from_tos(0xB8), TrafficType::Voice);
+        assert_eq!(TrafficType::from_tos(0x80), TrafficType::AudioVideo);
+        assert_eq!(TrafficType::from_tos(0x20), TrafficType::Background);
+        assert_eq!(TrafficType::from_tos(0xC0), TrafficType::Control);
+        assert_eq!(TrafficType::from_tos(0x10), TrafficType::ExcellentEffort);
+    }
+}
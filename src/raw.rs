@@ -14,7 +14,7 @@
 //! - Native IPv6 dual-stack support
 //!
 //! ## Windows
-//! - Uses WinSock2 APIs with automatic WSA initialization
+//! - Uses WinSock2 APIs, initialized lazily or explicitly with [`init`]/[`shutdown`]
 //! - SOCKET handle-based operations
 //! - Enhanced IOCP preparation for async operations
 //! - Comprehensive socket option support
@@ -65,9 +65,89 @@ pub enum Protocol {
     Udp,
 }
 
+/// Classic BPF instruction, laid out like the kernel's `struct sock_filter`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BpfInsn {
+    /// Opcode (class, size/operation, and mode/source bits)
+    pub code: u16,
+    /// Jump offset if the condition is true
+    pub jt: u8,
+    /// Jump offset if the condition is false
+    pub jf: u8,
+    /// Constant operand
+    pub k: u32,
+}
+
+/// Convert an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) to plain IPv4
+///
+/// Dual-stack sockets report IPv4 peers in mapped form; normalizing keeps
+/// addresses comparable regardless of how the socket was bound. Other
+/// addresses are returned unchanged.
+pub fn unmap_v4(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(a) => match a.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), a.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+/// References to the platform socket library
+#[derive(Debug)]
+struct Startup {
+    /// Held by [`init`] calls not yet matched by [`shutdown`]
+    explicit: usize,
+    /// Taken by the first socket created with no explicit reference; never released
+    #[cfg_attr(not(windows), allow(dead_code))]
+    lazy: bool,
+}
+
+static STARTUP: std::sync::Mutex<Startup> = std::sync::Mutex::new(Startup { explicit: 0, lazy: false });
+
+fn lock_startup() -> std::sync::MutexGuard<'static, Startup> {
+    STARTUP.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Initializes the platform socket library and takes a reference to it
+///
+/// On Windows this calls `WSAStartup`; elsewhere there is nothing to set
+/// up and only the reference is counted. Sockets created without a prior
+/// `init` start Winsock lazily and keep it loaded for the life of the
+/// process, so embedders that must unload cleanly (plugins, DLLs) should
+/// call `init` before creating any socket and [`shutdown`] after closing
+/// the last one.
+///
+/// # Errors
+///
+/// A failed `WSAStartup` is reported as [`Error::InitFailed`](crate::Error::InitFailed).
+pub fn init() -> io::Result<()> {
+    let mut state = lock_startup();
+    startup()?;
+    state.explicit += 1;
+    Ok(())
+}
+
+/// Releases a reference taken by [`init`]
+///
+/// On Windows each call is matched by a `WSACleanup`; once the last
+/// reference is gone, sockets that are still open stop working. Fails
+/// with `InvalidInput` if no reference is held.
+pub fn shutdown() -> io::Result<()> {
+    let mut state = lock_startup();
+    if state.explicit == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "shutdown without a matching init"));
+    }
+    cleanup();
+    state.explicit -= 1;
+    Ok(())
+}
+
 cfg_if::cfg_if! {
     if #[cfg(unix)] {
         use std::os::unix::io::{RawFd, FromRawFd};
+        /// Unix socket handle type (file descriptor)
         pub type OsSocket = RawFd;
 
         /// Platform-specific socket address storage
@@ -87,7 +167,7 @@ cfg_if::cfg_if! {
                     let mut s: libc::sockaddr_in = unsafe { std::mem::zeroed() };
                     s.sin_family = libc::AF_INET as _;
                     s.sin_port = a.port().to_be();
-                    s.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(a.ip().octets()).to_be() };
+                    s.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(a.ip().octets()) };
                     (Domain::Ipv4, SockAddr::V4(s), std::mem::size_of::<libc::sockaddr_in>() as _)
                 }
                 SocketAddr::V6(a) => {
@@ -102,7 +182,52 @@ cfg_if::cfg_if! {
             }
         }
 
+        /// Convert a kernel-filled `sockaddr_storage` back into a `SocketAddr`
+        ///
+        /// Returns `None` for address families other than IPv4 and IPv6.
+        pub fn from_sockaddr(ss: &libc::sockaddr_storage) -> Option<SocketAddr> {
+            match ss.ss_family as i32 {
+                libc::AF_INET => {
+                    let sin = unsafe { &*(ss as *const _ as *const libc::sockaddr_in) };
+                    let ip = std::net::Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+                    Some(SocketAddr::new(ip.into(), u16::from_be(sin.sin_port)))
+                }
+                libc::AF_INET6 => {
+                    let sin6 = unsafe { &*(ss as *const _ as *const libc::sockaddr_in6) };
+                    let ip = std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+                    Some(SocketAddr::V6(std::net::SocketAddrV6::new(
+                        ip,
+                        u16::from_be(sin6.sin6_port),
+                        sin6.sin6_flowinfo,
+                        sin6.sin6_scope_id,
+                    )))
+                }
+                _ => None,
+            }
+        }
+
+        /// Receive stream bytes into uninitialized memory, returning how many were written
+        pub fn recv_uninit(os: OsSocket, buf: &mut [std::mem::MaybeUninit<u8>]) -> io::Result<usize> {
+            let rc = unsafe { libc::recv(os, buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if rc < 0 { Err(io::Error::last_os_error()) } else { Ok(rc as usize) }
+        }
+        /// Receive one datagram into uninitialized memory, returning its length and sender
+        ///
+        /// Datagrams longer than `buf` are truncated.
+        pub fn recv_from_uninit(os: OsSocket, buf: &mut [std::mem::MaybeUninit<u8>]) -> io::Result<(usize, SocketAddr)> {
+            let mut ss: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+            let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            let rc = unsafe { libc::recvfrom(os, buf.as_mut_ptr().cast(), buf.len(), 0, &mut ss as *mut _ as *mut libc::sockaddr, &mut len) };
+            if rc < 0 { return Err(io::Error::last_os_error()); }
+            let addr = from_sockaddr(&ss).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported sender address family"))?;
+            Ok((rc as usize, addr))
+        }
+
         /// Raw bind operation for socket to address
+        ///
+        /// # Safety
+        ///
+        /// `os` must be a valid, open socket and `len` must match the size of `sa`.
         pub unsafe fn bind_raw(os: OsSocket, sa: &SockAddr, len: libc::socklen_t) -> io::Result<()> {
             let (ptr, l) = match sa {
                 SockAddr::V4(s) => (s as *const _ as *const libc::sockaddr, len),
@@ -112,6 +237,26 @@ cfg_if::cfg_if! {
             Ok(())
         }
 
+        /// Start connecting the socket to a remote address
+        ///
+        /// On a non-blocking socket this returns an error for which
+        /// [`connect_in_progress`] is `true` while the handshake continues.
+        ///
+        /// # Safety
+        ///
+        /// `os` must be a valid, open socket and `len` must match the size of `sa`.
+        pub unsafe fn connect_raw(os: OsSocket, sa: &SockAddr, len: libc::socklen_t) -> io::Result<()> {
+            let ptr = match sa {
+                SockAddr::V4(s) => s as *const _ as *const libc::sockaddr,
+                SockAddr::V6(s) => s as *const _ as *const libc::sockaddr,
+            };
+            if unsafe { libc::connect(os, ptr, len) } != 0 { return Err(io::Error::last_os_error()); }
+            Ok(())
+        }
+
+        /// Returns `true` if a `connect_raw` error means the handshake is still in progress
+        pub fn connect_in_progress(err: &io::Error) -> bool { err.raw_os_error() == Some(libc::EINPROGRESS) }
+
         /// Create a new socket with specified domain and type
         pub fn socket(domain: Domain, ty: Type, proto: Protocol) -> io::Result<OsSocket> {
             let d = match domain { Domain::Ipv4 => libc::AF_INET, Domain::Ipv6 => libc::AF_INET6 };
@@ -120,18 +265,16 @@ cfg_if::cfg_if! {
             
             // Use SOCK_CLOEXEC where available, fallback to fcntl for macOS
             cfg_if::cfg_if! {
-                if #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))] {
-                    let fd = unsafe { libc::socket(d, t | libc::SOCK_CLOEXEC, p) };
+                if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd", target_os = "illumos", target_os = "solaris"))] {
+                    let mut fd = unsafe { libc::socket(d, t | libc::SOCK_CLOEXEC, p) };
+                    // Kernels that predate the flag (NetBSD 5, older illumos) reject it
+                    if fd < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+                        fd = unsafe { libc::socket(d, t, p) };
+                        set_cloexec(fd);
+                    }
                 } else {
                     let fd = unsafe { libc::socket(d, t, p) };
-                    if fd >= 0 {
-                        unsafe {
-                            let flags = libc::fcntl(fd, libc::F_GETFD);
-                            if flags >= 0 {
-                                let _ = libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
-                            }
-                        }
-                    }
+                    set_cloexec(fd);
                 }
             }
             
@@ -139,6 +282,51 @@ cfg_if::cfg_if! {
             Ok(fd)
         }
 
+        /// Accept a pending connection with close-on-exec set, optionally non-blocking
+        ///
+        /// Uses `accept4` where the platform has it, so both flags are set
+        /// atomically; elsewhere (macOS, iOS) falls back to `accept` plus
+        /// `fcntl`.
+        pub fn accept_raw(os: OsSocket, nonblocking: bool) -> io::Result<(OsSocket, SocketAddr)> {
+            let mut ss: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+            let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            let sa = &mut ss as *mut libc::sockaddr_storage as *mut libc::sockaddr;
+            cfg_if::cfg_if! {
+                if #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd", target_os = "illumos", target_os = "solaris"))] {
+                    let flags = if nonblocking { libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK } else { libc::SOCK_CLOEXEC };
+                    let fd = unsafe { libc::accept4(os, sa, &mut len, flags) };
+                    if fd < 0 { return Err(io::Error::last_os_error()); }
+                } else {
+                    let fd = unsafe { libc::accept(os, sa, &mut len) };
+                    if fd < 0 { return Err(io::Error::last_os_error()); }
+                    set_cloexec(fd);
+                    // BSD-derived stacks copy O_NONBLOCK from the listener, so set it either way
+                    if let Err(e) = set_nonblocking(fd, nonblocking) {
+                        unsafe { libc::close(fd) };
+                        return Err(e);
+                    }
+                }
+            }
+            match from_sockaddr(&ss) {
+                Some(addr) => Ok((fd, addr)),
+                None => {
+                    unsafe { libc::close(fd) };
+                    Err(io::Error::new(io::ErrorKind::InvalidData, "accepted connection has a non-IP address"))
+                }
+            }
+        }
+
+        /// Set FD_CLOEXEC on `fd`, ignoring invalid descriptors
+        fn set_cloexec(fd: RawFd) {
+            if fd < 0 { return; }
+            unsafe {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags >= 0 {
+                    let _ = libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
+                }
+            }
+        }
+
         /// Set socket non-blocking mode
         pub fn set_nonblocking(os: OsSocket, on: bool) -> io::Result<()> {
             unsafe {
@@ -158,7 +346,11 @@ cfg_if::cfg_if! {
         /// Set socket send buffer size
         pub fn set_send_buffer(os: OsSocket, sz: i32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_SNDBUF, sz) }
         /// Enable port reuse for multiple binds
+        #[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
         pub fn set_reuse_port(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_REUSEPORT, on as i32) }
+        /// Enable port reuse (no-op where SO_REUSEPORT does not exist)
+        #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+        pub fn set_reuse_port(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
         /// Set IPv4 Type of Service for low-latency routing
         pub fn set_tos_v4(os: OsSocket, tos: i32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_IP, libc::IP_TOS, tos) }
         /// Set IPv6 Traffic Class for low-latency routing
@@ -169,10 +361,381 @@ cfg_if::cfg_if! {
         pub fn set_ipv6_hop_limit(os: OsSocket, hops: i32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, hops) }
         /// Disable TCP Nagle algorithm for low latency
         pub fn set_tcp_nodelay(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_NODELAY, on as i32) }
+        /// Enable TCP keepalive probes (SO_KEEPALIVE)
+        pub fn set_keepalive(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_KEEPALIVE, on as i32) }
+        /// Seconds of idleness before the first keepalive probe (TCP_KEEPIDLE, TCP_KEEPALIVE on Apple)
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn set_tcp_keepidle(os: OsSocket, secs: u32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, TCP_KEEPIDLE, secs.min(i32::MAX as u32) as i32) }
+        /// Seconds between unanswered keepalive probes (TCP_KEEPINTVL)
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn set_tcp_keepintvl(os: OsSocket, secs: u32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, secs.min(i32::MAX as u32) as i32) }
+        /// Unanswered keepalive probes before the connection is dropped (TCP_KEEPCNT)
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn set_tcp_keepcnt(os: OsSocket, count: u32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, count.min(i32::MAX as u32) as i32) }
+        /// Set TCP_KEEPIDLE (no-op on OpenBSD, where keepalive timing is system-wide)
+        #[cfg(target_os = "openbsd")]
+        pub fn set_tcp_keepidle(_os: OsSocket, _secs: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set TCP_KEEPINTVL (no-op on OpenBSD)
+        #[cfg(target_os = "openbsd")]
+        pub fn set_tcp_keepintvl(_os: OsSocket, _secs: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set TCP_KEEPCNT (no-op on OpenBSD)
+        #[cfg(target_os = "openbsd")]
+        pub fn set_tcp_keepcnt(_os: OsSocket, _count: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Hold back partial segments until uncorked (TCP_CORK on Linux, TCP_NOPUSH on BSD/macOS)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_tcp_cork(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_CORK, on as i32) }
+        /// Hold back partial segments until uncorked (TCP_NOPUSH)
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+        pub fn set_tcp_cork(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_NOPUSH, on as i32) }
+        /// Set TCP_CORK (no-op where neither TCP_CORK nor TCP_NOPUSH exists)
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly")))]
+        pub fn set_tcp_cork(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
         /// Enable TCP quick ACK for low latency
         pub fn set_tcp_quickack(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, 12, on as i32) }
         /// Enable busy polling for minimal latency
         pub fn set_busy_poll(os: OsSocket, usec: u32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, 46, usec as i32) }
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        const TCP_KEEPIDLE: i32 = libc::TCP_KEEPALIVE;
+        #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd")))]
+        const TCP_KEEPIDLE: i32 = libc::TCP_KEEPIDLE;
+        // Spelled out because libc does not define them for Android
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const SO_PREFER_BUSY_POLL: i32 = 69;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const SO_BUSY_POLL_BUDGET: i32 = 70;
+        /// Prefer busy polling over interrupt-driven NAPI processing (SO_PREFER_BUSY_POLL, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_prefer_busy_poll(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, SO_PREFER_BUSY_POLL, on as i32) }
+        /// Set SO_PREFER_BUSY_POLL (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_prefer_busy_poll(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set the packets processed per busy poll iteration (SO_BUSY_POLL_BUDGET, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_busy_poll_budget(os: OsSocket, budget: u32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, SO_BUSY_POLL_BUDGET, budget as i32) }
+        /// Set SO_BUSY_POLL_BUDGET (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_busy_poll_budget(_os: OsSocket, _budget: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set SO_PRIORITY to select the qdisc band for outgoing packets (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_priority(os: OsSocket, prio: u32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_PRIORITY, prio as i32) }
+        /// Set SO_PRIORITY (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_priority(_os: OsSocket, _prio: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Queue ICMP errors on the socket error queue (IP_RECVERR/IPV6_RECVERR, Linux only)
+        ///
+        /// IPv6 sockets also enable IP_RECVERR so dual-stack traffic to IPv4-mapped
+        /// destinations reports errors too.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_recv_err(os: OsSocket, domain: Domain, on: bool) -> io::Result<()> {
+            match domain {
+                Domain::Ipv4 => setsockopt_int(os, libc::IPPROTO_IP, libc::IP_RECVERR, on as i32),
+                Domain::Ipv6 => {
+                    setsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_RECVERR, on as i32)?;
+                    let _ = setsockopt_int(os, libc::IPPROTO_IP, libc::IP_RECVERR, on as i32);
+                    Ok(())
+                }
+            }
+        }
+        /// Queue ICMP errors on the socket error queue (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_recv_err(_os: OsSocket, _domain: Domain, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set the minimum number of bytes before a read is reported ready
+        pub fn set_recv_lowat(os: OsSocket, bytes: i32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_RCVLOWAT, bytes) }
+        /// Set the minimum free send space before a write is reported ready (read-only on Linux)
+        pub fn set_send_lowat(os: OsSocket, bytes: i32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_SNDLOWAT, bytes) }
+        /// Start `MSG_PEEK` reads at byte `off`, advancing it with each peek; -1 disables (SO_PEEK_OFF, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_peek_off(os: OsSocket, off: i32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_PEEK_OFF, off) }
+        /// Set SO_PEEK_OFF (unsupported outside Linux, where ignoring it would return the wrong bytes)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_peek_off(_os: OsSocket, _off: i32) -> io::Result<()> { Err(crate::error::Error::unsupported("SO_PEEK_OFF")) }
+        /// Read the current peek offset, -1 when disabled (SO_PEEK_OFF, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_peek_off(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_PEEK_OFF) }
+        /// Read SO_PEEK_OFF (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_peek_off(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_PEEK_OFF")) }
+        /// Wake accept only once data arrives, waiting up to `secs` seconds (TCP_DEFER_ACCEPT, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_tcp_defer_accept(os: OsSocket, secs: u32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT, secs.min(i32::MAX as u32) as i32) }
+        /// Set TCP_DEFER_ACCEPT (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_tcp_defer_accept(_os: OsSocket, _secs: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Honor the flow label in `sin6_flowinfo` of destination addresses (IPV6_FLOWINFO_SEND, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_flowinfo_send(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_FLOWINFO_SEND, on as i32) }
+        /// Set IPV6_FLOWINFO_SEND (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_flowinfo_send(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read IPV6_FLOWINFO_SEND (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_flowinfo_send(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_FLOWINFO_SEND).map(|v| v != 0) }
+        /// Read IPV6_FLOWINFO_SEND (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_flowinfo_send(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("IPV6_FLOWINFO_SEND")) }
+        /// Lease an IPv6 flow label for this socket (IPV6_FLOWLABEL_MGR, Linux only)
+        ///
+        /// `dst` is recorded with the lease but the label may be used towards
+        /// any destination. A `label` of 0 asks the kernel to pick one.
+        /// Returns the leased label. With `net.ipv6.flowlabel_state_ranges`
+        /// set, explicit labels must be below `0x80000`.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn lease_flow_label(os: OsSocket, label: u32, dst: std::net::Ipv6Addr) -> io::Result<u32> {
+            // struct in6_flowlabel_req from <linux/in6.h>
+            #[repr(C)]
+            struct FlowLabelReq { dst: libc::in6_addr, label: u32, action: u8, share: u8, flags: u16, expires: u16, linger: u16, pad: u32 }
+            const IPV6_FL_A_GET: u8 = 0;
+            const IPV6_FL_S_EXCL: u8 = 1;
+            const IPV6_FL_F_CREATE: u16 = 1;
+
+            if label & !(libc::IPV6_FLOWINFO_FLOWLABEL as u32) != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Flow labels are 20 bits"));
+            }
+            let mut req = FlowLabelReq {
+                dst: libc::in6_addr { s6_addr: dst.octets() },
+                label: label.to_be(),
+                action: IPV6_FL_A_GET,
+                share: IPV6_FL_S_EXCL,
+                flags: IPV6_FL_F_CREATE,
+                expires: 0,
+                linger: 0,
+                pad: 0,
+            };
+            let rc = unsafe { libc::setsockopt(os, libc::IPPROTO_IPV6, libc::IPV6_FLOWLABEL_MGR, &mut req as *mut _ as _, std::mem::size_of::<FlowLabelReq>() as _) };
+            if rc != 0 { return Err(io::Error::last_os_error()); }
+            // The kernel writes back the label it picked when asked for 0
+            Ok(u32::from_be(req.label))
+        }
+        /// Lease an IPv6 flow label (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn lease_flow_label(_os: OsSocket, _label: u32, _dst: std::net::Ipv6Addr) -> io::Result<u32> { Err(crate::error::Error::unsupported("IPV6_FLOWLABEL_MGR")) }
+        /// Set source address selection preferences (IPV6_ADDR_PREFERENCES, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn set_ipv6_addr_preferences(os: OsSocket, flags: u32) -> io::Result<()> { setsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_ADDR_PREFERENCES, flags as i32) }
+        /// Set IPV6_ADDR_PREFERENCES (no-op outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn set_ipv6_addr_preferences(_os: OsSocket, _flags: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read source address selection preferences, with defaults filled in (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_ipv6_addr_preferences(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_ADDR_PREFERENCES).map(|v| v as u32) }
+        /// Read IPV6_ADDR_PREFERENCES (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_ipv6_addr_preferences(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("IPV6_ADDR_PREFERENCES")) }
+        /// Read the NAPI ID of the queue that delivered the last packet (SO_INCOMING_NAPI_ID, Linux only)
+        ///
+        /// Returns 0 until the socket has received traffic through a NAPI-capable device.
+        #[cfg(target_os = "linux")]
+        pub fn get_incoming_napi_id(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_INCOMING_NAPI_ID).map(|v| v as u32) }
+        /// Attach the socket's cumulative drop counter to received packets (SO_RXQ_OVFL, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn set_rxq_ovfl(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_RXQ_OVFL, on as i32) }
+        /// Set SO_RXQ_OVFL (no-op outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn set_rxq_ovfl(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read SO_INCOMING_NAPI_ID (unsupported outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn get_incoming_napi_id(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_INCOMING_NAPI_ID")) }
+        /// Associate the socket with a CPU for receive and SO_REUSEPORT steering (SO_INCOMING_CPU, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn set_incoming_cpu(os: OsSocket, cpu: i32) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_INCOMING_CPU, cpu) }
+        /// Set SO_INCOMING_CPU (no-op outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn set_incoming_cpu(_os: OsSocket, _cpu: i32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read the CPU that processed the last received packet (SO_INCOMING_CPU, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn get_incoming_cpu(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_INCOMING_CPU) }
+        /// Read SO_INCOMING_CPU (unsupported outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn get_incoming_cpu(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_INCOMING_CPU")) }
+        // Spelled out because libc does not define it for Android
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const SO_COOKIE: i32 = 57;
+        /// Read the socket's kernel-assigned cookie (SO_COOKIE, Linux 4.13+)
+        ///
+        /// Unique for the lifetime of the boot, unlike the descriptor number,
+        /// which is reused as soon as the socket closes.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_cookie(os: OsSocket) -> io::Result<u64> {
+            let mut v: u64 = 0;
+            let mut len = std::mem::size_of::<u64>() as libc::socklen_t;
+            let rc = unsafe { libc::getsockopt(os, libc::SOL_SOCKET, SO_COOKIE, &mut v as *mut _ as _, &mut len) };
+            if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(v) }
+        }
+        /// Read SO_COOKIE (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_cookie(_os: OsSocket) -> io::Result<u64> { Err(crate::error::Error::unsupported("SO_COOKIE")) }
+        /// Attach a classic BPF program that picks the SO_REUSEPORT group member (SO_ATTACH_REUSEPORT_CBPF, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn attach_reuseport_cbpf(os: OsSocket, prog: &[BpfInsn]) -> io::Result<()> {
+            let fprog = libc::sock_fprog {
+                len: u16::try_from(prog.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "BPF program too long"))?,
+                filter: prog.as_ptr() as *mut libc::sock_filter,
+            };
+            let rc = unsafe { libc::setsockopt(os, libc::SOL_SOCKET, libc::SO_ATTACH_REUSEPORT_CBPF, &fprog as *const _ as _, std::mem::size_of::<libc::sock_fprog>() as _) };
+            if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+        }
+        /// Attach a classic BPF reuseport program (unsupported outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn attach_reuseport_cbpf(_os: OsSocket, _prog: &[BpfInsn]) -> io::Result<()> { Err(crate::error::Error::unsupported("SO_ATTACH_REUSEPORT_CBPF")) }
+        /// Attach a loaded `BPF_PROG_TYPE_SOCKET_FILTER` program by fd (SO_ATTACH_REUSEPORT_EBPF, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn attach_reuseport_ebpf(os: OsSocket, prog_fd: RawFd) -> io::Result<()> { setsockopt_int(os, libc::SOL_SOCKET, libc::SO_ATTACH_REUSEPORT_EBPF, prog_fd) }
+        /// Attach an eBPF reuseport program (unsupported outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn attach_reuseport_ebpf(_os: OsSocket, _prog_fd: RawFd) -> io::Result<()> { Err(crate::error::Error::unsupported("SO_ATTACH_REUSEPORT_EBPF")) }
+        /// Create a non-blocking `AF_PACKET` raw socket for EtherType `protocol` (Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn packet_socket(protocol: u16) -> io::Result<OsSocket> {
+            let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, i32::from(protocol.to_be())) };
+            if fd < 0 { Err(io::Error::last_os_error()) } else { Ok(fd) }
+        }
+        /// Bind an `AF_PACKET` socket to interface `ifindex` for EtherType `protocol` (Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn bind_packet(os: OsSocket, ifindex: u32, protocol: u16) -> io::Result<()> {
+            let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as libc::c_ushort;
+            addr.sll_protocol = protocol.to_be();
+            addr.sll_ifindex = ifindex as libc::c_int;
+            let rc = unsafe { libc::bind(os, &addr as *const libc::sockaddr_ll as *const libc::sockaddr, std::mem::size_of::<libc::sockaddr_ll>() as _) };
+            if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+        }
+        /// Report the VLAN tag stripped from received frames in a control message (PACKET_AUXDATA, Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn set_packet_auxdata(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, libc::SOL_PACKET, libc::PACKET_AUXDATA, on as i32) }
+        /// Receive one frame from an `AF_PACKET` socket (Linux only)
+        ///
+        /// Returns the frame's full length, which exceeds `buf.len()` if it
+        /// was truncated, its link-layer source address, and its
+        /// `PACKET_AUXDATA` if that option is enabled.
+        #[cfg(target_os = "linux")]
+        pub fn recv_packet(os: OsSocket, buf: &mut [u8]) -> io::Result<(usize, libc::sockaddr_ll, Option<libc::tpacket_auxdata>)> {
+            let mut from: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            // u64 elements keep the control buffer aligned for cmsghdr
+            let mut control = [0u64; 8];
+            let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+            let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+            msg.msg_name = (&mut from as *mut libc::sockaddr_ll).cast();
+            msg.msg_namelen = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+            let n = unsafe { libc::recvmsg(os, &mut msg, libc::MSG_TRUNC) };
+            if n < 0 { return Err(io::Error::last_os_error()); }
+            let mut aux = None;
+            // SAFETY: msg was filled by recvmsg and control outlives the iteration
+            let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+            while !cmsg.is_null() {
+                let hdr = unsafe { &*cmsg };
+                if hdr.cmsg_level == libc::SOL_PACKET && hdr.cmsg_type == libc::PACKET_AUXDATA {
+                    aux = Some(unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::tpacket_auxdata) });
+                }
+                cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+            }
+            Ok((n as usize, from, aux))
+        }
+        /// Read the receive buffer size (Linux reports double the requested size)
+        pub fn get_recv_buffer(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_RCVBUF) }
+        /// Read the send buffer size (Linux reports double the requested size)
+        pub fn get_send_buffer(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_SNDBUF) }
+        /// Read whether port reuse is enabled
+        #[cfg(not(any(target_os = "illumos", target_os = "solaris")))]
+        pub fn get_reuse_port(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_REUSEPORT).map(|v| v != 0) }
+        /// Read SO_REUSEPORT (unsupported on illumos and Solaris)
+        #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+        pub fn get_reuse_port(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_REUSEPORT")) }
+        /// Read the IPv4 Type of Service
+        pub fn get_tos_v4(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::IPPROTO_IP, libc::IP_TOS) }
+        /// Read the IPv6 Traffic Class
+        pub fn get_tos_v6(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_TCLASS) }
+        /// Read whether the socket is IPv6-only
+        pub fn get_ipv6_only(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY).map(|v| v != 0) }
+        /// Read the IPv6 unicast hop limit
+        pub fn get_ipv6_hop_limit(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS) }
+        /// Read whether Nagle's algorithm is disabled
+        pub fn get_tcp_nodelay(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_NODELAY).map(|v| v != 0) }
+        /// Read whether keepalive probes are enabled
+        pub fn get_keepalive(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_KEEPALIVE).map(|v| v != 0) }
+        /// Read the keepalive idle time in seconds
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn get_tcp_keepidle(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::IPPROTO_TCP, TCP_KEEPIDLE).map(|v| v as u32) }
+        /// Read the keepalive probe interval in seconds
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn get_tcp_keepintvl(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL).map(|v| v as u32) }
+        /// Read the keepalive probe count
+        #[cfg(not(target_os = "openbsd"))]
+        pub fn get_tcp_keepcnt(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::IPPROTO_TCP, libc::TCP_KEEPCNT).map(|v| v as u32) }
+        /// Read TCP_KEEPIDLE (unsupported on OpenBSD)
+        #[cfg(target_os = "openbsd")]
+        pub fn get_tcp_keepidle(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("TCP_KEEPIDLE")) }
+        /// Read TCP_KEEPINTVL (unsupported on OpenBSD)
+        #[cfg(target_os = "openbsd")]
+        pub fn get_tcp_keepintvl(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("TCP_KEEPINTVL")) }
+        /// Read TCP_KEEPCNT (unsupported on OpenBSD)
+        #[cfg(target_os = "openbsd")]
+        pub fn get_tcp_keepcnt(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("TCP_KEEPCNT")) }
+        /// Read whether TCP quick ACK is enabled
+        pub fn get_tcp_quickack(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::IPPROTO_TCP, 12).map(|v| v != 0) }
+        /// Read the busy poll duration in microseconds
+        pub fn get_busy_poll(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::SOL_SOCKET, 46).map(|v| v as u32) }
+        /// Read SO_PREFER_BUSY_POLL (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_prefer_busy_poll(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::SOL_SOCKET, SO_PREFER_BUSY_POLL).map(|v| v != 0) }
+        /// Read SO_PREFER_BUSY_POLL (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_prefer_busy_poll(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_PREFER_BUSY_POLL")) }
+        /// Read SO_BUSY_POLL_BUDGET (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_busy_poll_budget(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::SOL_SOCKET, SO_BUSY_POLL_BUDGET).map(|v| v as u32) }
+        /// Read SO_BUSY_POLL_BUDGET (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_busy_poll_budget(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_BUSY_POLL_BUDGET")) }
+        /// Read SO_PRIORITY (Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_priority(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_PRIORITY).map(|v| v as u32) }
+        /// Read SO_PRIORITY (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_priority(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_PRIORITY")) }
+        /// Read whether ICMP errors are queued (IP_RECVERR/IPV6_RECVERR, Linux only)
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub fn get_recv_err(os: OsSocket, domain: Domain) -> io::Result<bool> {
+            match domain {
+                Domain::Ipv4 => getsockopt_int(os, libc::IPPROTO_IP, libc::IP_RECVERR).map(|v| v != 0),
+                Domain::Ipv6 => getsockopt_int(os, libc::IPPROTO_IPV6, libc::IPV6_RECVERR).map(|v| v != 0),
+            }
+        }
+        /// Read IP_RECVERR (unsupported outside Linux)
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        pub fn get_recv_err(_os: OsSocket, _domain: Domain) -> io::Result<bool> { Err(crate::error::Error::unsupported("IP_RECVERR")) }
+        /// Read the receive low watermark
+        pub fn get_recv_lowat(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_RCVLOWAT) }
+        /// Read the send low watermark
+        pub fn get_send_lowat(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_SNDLOWAT) }
+        /// Read SO_RXQ_OVFL (Linux only)
+        #[cfg(target_os = "linux")]
+        pub fn get_rxq_ovfl(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, libc::SOL_SOCKET, libc::SO_RXQ_OVFL).map(|v| v != 0) }
+        /// Read SO_RXQ_OVFL (unsupported outside Linux)
+        #[cfg(not(target_os = "linux"))]
+        pub fn get_rxq_ovfl(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_RXQ_OVFL")) }
+        /// Borrow the raw handle of a standard library socket
+        pub fn os_handle(s: &impl std::os::unix::io::AsRawFd) -> OsSocket { s.as_raw_fd() }
+        /// Configure SO_LINGER: `None` closes gracefully in the background,
+        /// `Some(d)` blocks close for up to `d`, and `Some(ZERO)` resets the connection
+        pub fn set_linger(os: OsSocket, linger: Option<std::time::Duration>) -> io::Result<()> {
+            let l = libc::linger {
+                l_onoff: linger.is_some() as libc::c_int,
+                l_linger: linger.map_or(0, |d| d.as_secs().min(i32::MAX as u64) as libc::c_int),
+            };
+            let rc = unsafe { libc::setsockopt(os, libc::SOL_SOCKET, libc::SO_LINGER, &l as *const _ as _, std::mem::size_of::<libc::linger>() as _) };
+            if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+        }
+        /// Close a socket, reporting the error `close(2)` returns
+        ///
+        /// The descriptor is released even when an error is reported.
+        pub fn close_owned(s: impl std::os::unix::io::IntoRawFd) -> io::Result<()> {
+            if unsafe { libc::close(s.into_raw_fd()) } != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+        }
 
         fn setsockopt_int(fd: RawFd, level: i32, opt: i32, val: i32) -> io::Result<()> {
             let v = val as libc::c_int;
@@ -180,24 +743,58 @@ cfg_if::cfg_if! {
             if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
         }
 
+        fn getsockopt_int(fd: RawFd, level: i32, opt: i32) -> io::Result<i32> {
+            let mut v: libc::c_int = 0;
+            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+            let rc = unsafe { libc::getsockopt(fd, level, opt, &mut v as *mut _ as _, &mut len) };
+            if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(v) }
+        }
+
+        /// Wraps a raw UDP socket in a standard library `UdpSocket`
+        ///
+        /// # Safety
+        ///
+        /// `fd` must be an open UDP socket that is not owned by anything else.
         pub unsafe fn udp_from_os(fd: RawFd) -> std::net::UdpSocket { unsafe { std::net::UdpSocket::from_raw_fd(fd) } }
+        /// Wraps a raw listening TCP socket in a standard library `TcpListener`
+        ///
+        /// # Safety
+        ///
+        /// `fd` must be an open, listening TCP socket that is not owned by anything else.
         pub unsafe fn tcp_listener_from_os(fd: RawFd) -> std::net::TcpListener { unsafe { std::net::TcpListener::from_raw_fd(fd) } }
+        /// Wraps a raw TCP socket in a standard library `TcpStream`
+        ///
+        /// # Safety
+        ///
+        /// `fd` must be an open TCP socket that is not owned by anything else.
+        pub unsafe fn tcp_stream_from_os(fd: RawFd) -> std::net::TcpStream { unsafe { std::net::TcpStream::from_raw_fd(fd) } }
+
+        fn startup() -> io::Result<()> { Ok(()) /* not applicable */ }
+        fn cleanup() { /* not applicable */ }
 
     } else if #[cfg(windows)] {
         // Windows
-        use std::sync::Once;
         use windows_sys::Win32::Networking::WinSock::*;
         use std::os::windows::io::{RawSocket, FromRawSocket};
         /// Windows socket handle type
         pub type OsSocket = RawSocket; // SOCKET
 
-        static START: Once = Once::new();
-        fn ensure_wsa() {
-            START.call_once(|| unsafe {
-                let mut data: WSADATA = std::mem::zeroed();
-                let rc = WSAStartup(0x202, &mut data); // MAKEWORD(2,2)
-                if rc != 0 { panic!("WSAStartup failed: {}", rc); }
-            });
+        fn startup() -> io::Result<()> {
+            let mut data: WSADATA = unsafe { std::mem::zeroed() };
+            let rc = unsafe { WSAStartup(0x202, &mut data) }; // MAKEWORD(2,2)
+            if rc != 0 { return Err(crate::error::Error::InitFailed { source: io::Error::from_raw_os_error(rc) }.into()); }
+            Ok(())
+        }
+        fn cleanup() { unsafe { WSACleanup() }; }
+
+        /// Starts Winsock for sockets created without an explicit [`init`]
+        fn ensure_wsa() -> io::Result<()> {
+            let mut state = lock_startup();
+            if state.explicit == 0 && !state.lazy {
+                startup()?;
+                state.lazy = true;
+            }
+            Ok(())
         }
 
         #[allow(non_camel_case_types)]
@@ -209,7 +806,7 @@ cfg_if::cfg_if! {
                     let mut s: SOCKADDR_IN = unsafe { std::mem::zeroed() };
                     s.sin_family = AF_INET as _;
                     s.sin_port = a.port().to_be();
-                    s.sin_addr = IN_ADDR { S_un: IN_ADDR_0 { S_addr: u32::from_ne_bytes(a.ip().octets()).to_be() } };
+                    s.sin_addr = IN_ADDR { S_un: IN_ADDR_0 { S_addr: u32::from_ne_bytes(a.ip().octets()) } };
                     (Domain::Ipv4, SockAddr::V4(s), std::mem::size_of::<SOCKADDR_IN>() as _)
                 }
                 SocketAddr::V6(a) => {
@@ -231,8 +828,12 @@ cfg_if::cfg_if! {
         }
 
         /// Raw bind operation for socket to address
+        ///
+        /// # Safety
+        ///
+        /// `os` must be a valid, open socket and `len` must match the size of `sa`.
         pub unsafe fn bind_raw(os: OsSocket, sa: &SockAddr, len: i32) -> io::Result<()> {
-            ensure_wsa();
+            ensure_wsa()?;
             let (ptr, l) = match sa {
                 SockAddr::V4(s) => (s as *const _ as *const SOCKADDR, len),
                 SockAddr::V6(s) => (s as *const _ as *const SOCKADDR, len),
@@ -242,9 +843,26 @@ cfg_if::cfg_if! {
             Ok(())
         }
 
+        /// Start connecting the socket to a remote address
+        ///
+        /// # Safety
+        ///
+        /// `os` must be a valid, open socket and `len` must match the size of `sa`.
+        pub unsafe fn connect_raw(os: OsSocket, sa: &SockAddr, len: i32) -> io::Result<()> {
+            let ptr = match sa {
+                SockAddr::V4(s) => s as *const _ as *const SOCKADDR,
+                SockAddr::V6(s) => s as *const _ as *const SOCKADDR,
+            };
+            if unsafe { connect(os as usize, ptr, len) } != 0 { return Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })); }
+            Ok(())
+        }
+
+        /// Returns `true` if a `connect_raw` error means the handshake is still in progress
+        pub fn connect_in_progress(err: &io::Error) -> bool { err.raw_os_error() == Some(WSAEWOULDBLOCK) }
+
         /// Create a new socket with specified domain and type
         pub fn socket(domain: Domain, ty: Type, _proto: Protocol) -> io::Result<OsSocket> {
-            ensure_wsa();
+            ensure_wsa()?;
             let d = match domain { Domain::Ipv4 => AF_INET, Domain::Ipv6 => AF_INET6 } as i32;
             let t = match ty { Type::Stream => SOCK_STREAM, Type::Dgram => SOCK_DGRAM } as i32;
             let s = unsafe { WSASocketW(d, t, 0, std::ptr::null_mut(), 0, WSA_FLAG_OVERLAPPED) };
@@ -254,7 +872,7 @@ cfg_if::cfg_if! {
 
         /// Set socket non-blocking mode
         pub fn set_nonblocking(os: OsSocket, on: bool) -> io::Result<()> {
-            ensure_wsa();
+            ensure_wsa()?;
             unsafe {
                 let mut nb: u32 = if on {1} else {0};
                 if ioctlsocket(os as usize, FIONBIO, &mut nb) != 0 { return Err(io::Error::from_raw_os_error(WSAGetLastError())); }
@@ -285,14 +903,207 @@ cfg_if::cfg_if! {
         pub fn set_ipv6_hop_limit(os: OsSocket, hops: i32) -> io::Result<()> { setsockopt_int(os, IPPROTO_IPV6 as _, IPV6_UNICAST_HOPS as _, hops) }
         /// Disable TCP Nagle algorithm for low latency
         pub fn set_tcp_nodelay(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, IPPROTO_TCP as _, TCP_NODELAY as _, if on {1} else {0}) }
+        /// Enable TCP keepalive probes (SO_KEEPALIVE)
+        pub fn set_keepalive(os: OsSocket, on: bool) -> io::Result<()> { setsockopt_int(os, SOL_SOCKET as _, SO_KEEPALIVE as _, if on {1} else {0}) }
+        /// Seconds of idleness before the first keepalive probe (TCP_KEEPIDLE, Windows 10 1709+)
+        pub fn set_tcp_keepidle(os: OsSocket, secs: u32) -> io::Result<()> { setsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPIDLE, secs.min(i32::MAX as u32) as i32) }
+        /// Seconds between unanswered keepalive probes (TCP_KEEPINTVL, Windows 10 1709+)
+        pub fn set_tcp_keepintvl(os: OsSocket, secs: u32) -> io::Result<()> { setsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPINTVL, secs.min(i32::MAX as u32) as i32) }
+        /// Unanswered keepalive probes before the connection is dropped (TCP_KEEPCNT, Windows 10 1703+)
+        pub fn set_tcp_keepcnt(os: OsSocket, count: u32) -> io::Result<()> { setsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPCNT, count.min(i32::MAX as u32) as i32) }
+        /// Set TCP_CORK (no-op on Windows)
+        pub fn set_tcp_cork(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not available on Windows */ }
         /// Enable TCP quick ACK (no-op on Windows)
         pub fn set_tcp_quickack(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not available on Windows */ }
         /// Enable port reuse (no-op on Windows)
         pub fn set_reuse_port(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
         /// Enable busy polling for minimal latency (no-op on Windows)
         pub fn set_busy_poll(_os: OsSocket, _usec: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Prefer busy polling (no-op on Windows)
+        pub fn set_prefer_busy_poll(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set busy poll budget (no-op on Windows)
+        pub fn set_busy_poll_budget(_os: OsSocket, _budget: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set SO_PRIORITY (no-op on Windows)
+        pub fn set_priority(_os: OsSocket, _prio: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Queue ICMP errors on the socket error queue (no-op on Windows)
+        pub fn set_recv_err(_os: OsSocket, _domain: Domain, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set receive low watermark (no-op on Windows)
+        pub fn set_recv_lowat(_os: OsSocket, _bytes: i32) -> io::Result<()> { Ok(()) /* not supported by WinSock */ }
+        /// Set send low watermark (no-op on Windows)
+        pub fn set_send_lowat(_os: OsSocket, _bytes: i32) -> io::Result<()> { Ok(()) /* not supported by WinSock */ }
+        /// Set SO_PEEK_OFF (unsupported on Windows)
+        pub fn set_peek_off(_os: OsSocket, _off: i32) -> io::Result<()> { Err(crate::error::Error::unsupported("SO_PEEK_OFF")) }
+        /// Read SO_PEEK_OFF (unsupported on Windows)
+        pub fn get_peek_off(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_PEEK_OFF")) }
+        /// Set TCP_DEFER_ACCEPT (no-op on Windows)
+        pub fn set_tcp_defer_accept(_os: OsSocket, _secs: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Set IPV6_FLOWINFO_SEND (no-op on Windows)
+        pub fn set_flowinfo_send(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read IPV6_FLOWINFO_SEND (unsupported on Windows)
+        pub fn get_flowinfo_send(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("IPV6_FLOWINFO_SEND")) }
+        /// Lease an IPv6 flow label (unsupported on Windows)
+        pub fn lease_flow_label(_os: OsSocket, _label: u32, _dst: std::net::Ipv6Addr) -> io::Result<u32> { Err(crate::error::Error::unsupported("IPV6_FLOWLABEL_MGR")) }
+        /// Set IPV6_ADDR_PREFERENCES (no-op on Windows)
+        pub fn set_ipv6_addr_preferences(_os: OsSocket, _flags: u32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read IPV6_ADDR_PREFERENCES (unsupported on Windows)
+        pub fn get_ipv6_addr_preferences(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("IPV6_ADDR_PREFERENCES")) }
+        /// Set SO_RXQ_OVFL (no-op on Windows)
+        pub fn set_rxq_ovfl(_os: OsSocket, _on: bool) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read SO_INCOMING_NAPI_ID (unsupported on Windows)
+        pub fn get_incoming_napi_id(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_INCOMING_NAPI_ID")) }
+        /// Set SO_INCOMING_CPU (no-op on Windows)
+        pub fn set_incoming_cpu(_os: OsSocket, _cpu: i32) -> io::Result<()> { Ok(()) /* not applicable */ }
+        /// Read SO_INCOMING_CPU (unsupported on Windows)
+        pub fn get_incoming_cpu(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_INCOMING_CPU")) }
+        /// Read SO_COOKIE (unsupported on Windows)
+        pub fn get_cookie(_os: OsSocket) -> io::Result<u64> { Err(crate::error::Error::unsupported("SO_COOKIE")) }
+        /// Attach a classic BPF reuseport program (unsupported on Windows)
+        pub fn attach_reuseport_cbpf(_os: OsSocket, _prog: &[BpfInsn]) -> io::Result<()> { Err(crate::error::Error::unsupported("SO_ATTACH_REUSEPORT_CBPF")) }
+        fn getsockopt_int(socket: OsSocket, level: i32, opt: i32) -> io::Result<i32> {
+            let mut val: i32 = 0;
+            let mut len = std::mem::size_of::<i32>() as i32;
+            let rc = unsafe { getsockopt(socket as usize, level, opt, &mut val as *mut _ as _, &mut len) };
+            if rc != 0 { Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })) } else { Ok(val) }
+        }
+        /// Read the receive buffer size
+        pub fn get_recv_buffer(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, SOL_SOCKET as _, SO_RCVBUF as _) }
+        /// Read the send buffer size
+        pub fn get_send_buffer(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, SOL_SOCKET as _, SO_SNDBUF as _) }
+        /// Read the IPv4 Type of Service
+        pub fn get_tos_v4(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, IPPROTO_IP as _, IP_TOS as _) }
+        /// Read the IPv6 Traffic Class
+        pub fn get_tos_v6(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, IPPROTO_IPV6 as _, IPV6_TCLASS as _) }
+        /// Read whether the socket is IPv6-only
+        pub fn get_ipv6_only(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, IPPROTO_IPV6 as _, IPV6_V6ONLY as _).map(|v| v != 0) }
+        /// Read the IPv6 unicast hop limit
+        pub fn get_ipv6_hop_limit(os: OsSocket) -> io::Result<i32> { getsockopt_int(os, IPPROTO_IPV6 as _, IPV6_UNICAST_HOPS as _) }
+        /// Read whether Nagle's algorithm is disabled
+        pub fn get_tcp_nodelay(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, IPPROTO_TCP as _, TCP_NODELAY as _).map(|v| v != 0) }
+        /// Read whether keepalive probes are enabled
+        pub fn get_keepalive(os: OsSocket) -> io::Result<bool> { getsockopt_int(os, SOL_SOCKET as _, SO_KEEPALIVE as _).map(|v| v != 0) }
+        /// Read the keepalive idle time in seconds
+        pub fn get_tcp_keepidle(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPIDLE).map(|v| v as u32) }
+        /// Read the keepalive probe interval in seconds
+        pub fn get_tcp_keepintvl(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPINTVL).map(|v| v as u32) }
+        /// Read the keepalive probe count
+        pub fn get_tcp_keepcnt(os: OsSocket) -> io::Result<u32> { getsockopt_int(os, IPPROTO_TCP as _, TCP_KEEPCNT).map(|v| v as u32) }
+        /// Read whether port reuse is enabled (unsupported on Windows)
+        pub fn get_reuse_port(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_REUSEPORT")) }
+        /// Read TCP quick ACK (unsupported on Windows)
+        pub fn get_tcp_quickack(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("TCP_QUICKACK")) }
+        /// Read the busy poll duration (unsupported on Windows)
+        pub fn get_busy_poll(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_BUSY_POLL")) }
+        /// Read SO_PREFER_BUSY_POLL (unsupported on Windows)
+        pub fn get_prefer_busy_poll(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_PREFER_BUSY_POLL")) }
+        /// Read SO_BUSY_POLL_BUDGET (unsupported on Windows)
+        pub fn get_busy_poll_budget(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_BUSY_POLL_BUDGET")) }
+        /// Read SO_PRIORITY (unsupported on Windows)
+        pub fn get_priority(_os: OsSocket) -> io::Result<u32> { Err(crate::error::Error::unsupported("SO_PRIORITY")) }
+        /// Read IP_RECVERR (unsupported on Windows)
+        pub fn get_recv_err(_os: OsSocket, _domain: Domain) -> io::Result<bool> { Err(crate::error::Error::unsupported("IP_RECVERR")) }
+        /// Read the receive low watermark (unsupported on Windows)
+        pub fn get_recv_lowat(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_RCVLOWAT")) }
+        /// Read the send low watermark (unsupported on Windows)
+        pub fn get_send_lowat(_os: OsSocket) -> io::Result<i32> { Err(crate::error::Error::unsupported("SO_SNDLOWAT")) }
+        /// Read SO_RXQ_OVFL (unsupported on Windows)
+        pub fn get_rxq_ovfl(_os: OsSocket) -> io::Result<bool> { Err(crate::error::Error::unsupported("SO_RXQ_OVFL")) }
+        /// Borrow the raw handle of a standard library socket
+        pub fn os_handle(s: &impl std::os::windows::io::AsRawSocket) -> OsSocket { s.as_raw_socket() }
+        /// Receive stream bytes into uninitialized memory, returning how many were written
+        pub fn recv_uninit(os: OsSocket, buf: &mut [std::mem::MaybeUninit<u8>]) -> io::Result<usize> {
+            let cap = buf.len().min(i32::MAX as usize) as i32;
+            let rc = unsafe { recv(os as usize, buf.as_mut_ptr().cast(), cap, 0) };
+            if rc == SOCKET_ERROR { Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })) } else { Ok(rc as usize) }
+        }
+        /// Receive one datagram into uninitialized memory, returning its length and sender
+        ///
+        /// Datagrams longer than `buf` fail with `WSAEMSGSIZE`, as with `UdpSocket::recv_from`.
+        pub fn recv_from_uninit(os: OsSocket, buf: &mut [std::mem::MaybeUninit<u8>]) -> io::Result<(usize, SocketAddr)> {
+            let mut ss: SOCKADDR_STORAGE = unsafe { std::mem::zeroed() };
+            let mut len = std::mem::size_of::<SOCKADDR_STORAGE>() as i32;
+            let cap = buf.len().min(i32::MAX as usize) as i32;
+            let rc = unsafe { recvfrom(os as usize, buf.as_mut_ptr().cast(), cap, 0, &mut ss as *mut _ as *mut SOCKADDR, &mut len) };
+            if rc == SOCKET_ERROR { return Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })); }
+            let addr = match ss.ss_family {
+                AF_INET => {
+                    let sin = unsafe { &*(&ss as *const _ as *const SOCKADDR_IN) };
+                    let ip = std::net::Ipv4Addr::from(unsafe { sin.sin_addr.S_un.S_addr }.to_ne_bytes());
+                    SocketAddr::new(ip.into(), u16::from_be(sin.sin_port))
+                }
+                AF_INET6 => {
+                    let sin6 = unsafe { &*(&ss as *const _ as *const SOCKADDR_IN6) };
+                    let ip = std::net::Ipv6Addr::from(unsafe { sin6.sin6_addr.u.Byte });
+                    SocketAddr::V6(std::net::SocketAddrV6::new(ip, u16::from_be(sin6.sin6_port), sin6.sin6_flowinfo, unsafe { sin6.Anonymous.sin6_scope_id }))
+                }
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported sender address family")),
+            };
+            Ok((rc as usize, addr))
+        }
+        /// Configure SO_LINGER: `None` closes gracefully in the background,
+        /// `Some(d)` blocks close for up to `d`, and `Some(ZERO)` resets the connection
+        pub fn set_linger(os: OsSocket, linger: Option<std::time::Duration>) -> io::Result<()> {
+            let l = LINGER {
+                l_onoff: linger.is_some() as u16,
+                l_linger: linger.map_or(0, |d| d.as_secs().min(u16::MAX as u64) as u16),
+            };
+            let rc = unsafe { setsockopt(os as usize, SOL_SOCKET as _, SO_LINGER as _, &l as *const _ as _, std::mem::size_of::<LINGER>() as _) };
+            if rc != 0 { Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })) } else { Ok(()) }
+        }
+        /// Close a socket, reporting the error `closesocket` returns
+        pub fn close_owned(s: impl std::os::windows::io::IntoRawSocket) -> io::Result<()> {
+            if unsafe { closesocket(s.into_raw_socket() as usize) } != 0 { Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() })) } else { Ok(()) }
+        }
 
+        /// Wraps a raw UDP socket in a standard library `UdpSocket`
+        ///
+        /// # Safety
+        ///
+        /// `s` must be an open UDP socket that is not owned by anything else.
         pub unsafe fn udp_from_os(s: OsSocket) -> std::net::UdpSocket { unsafe { std::net::UdpSocket::from_raw_socket(s) } }
+        /// Wraps a raw listening TCP socket in a standard library `TcpListener`
+        ///
+        /// # Safety
+        ///
+        /// `s` must be an open, listening TCP socket that is not owned by anything else.
         pub unsafe fn tcp_listener_from_os(s: OsSocket) -> std::net::TcpListener { unsafe { std::net::TcpListener::from_raw_socket(s) } }
+        /// Wraps a raw TCP socket in a standard library `TcpStream`
+        ///
+        /// # Safety
+        ///
+        /// `s` must be an open TCP socket that is not owned by anything else.
+        pub unsafe fn tcp_stream_from_os(s: OsSocket) -> std::net::TcpStream { unsafe { std::net::TcpStream::from_raw_socket(s) } }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_and_shutdown_are_reference_counted() {
+        init().unwrap();
+        init().unwrap();
+        shutdown().unwrap();
+        let socket = socket(Domain::Ipv4, Type::Dgram, Protocol::Udp).unwrap();
+        drop(unsafe { udp_from_os(socket) });
+        shutdown().unwrap();
+        assert_eq!(shutdown().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_accepted_socket_is_close_on_exec() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (fd, peer) = accept_raw(os_handle(&listener), false).unwrap();
+        let stream = unsafe { tcp_stream_from_os(fd) };
+        assert_eq!(peer, client.local_addr().unwrap());
+        assert_ne!(unsafe { libc::fcntl(os_handle(&stream), libc::F_GETFD) } & libc::FD_CLOEXEC, 0);
+        assert_eq!(unsafe { libc::fcntl(os_handle(&stream), libc::F_GETFL) } & libc::O_NONBLOCK, 0);
+
+        let _second = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (fd, _) = accept_raw(os_handle(&listener), true).unwrap();
+        let stream = unsafe { tcp_stream_from_os(fd) };
+        assert_ne!(unsafe { libc::fcntl(os_handle(&stream), libc::F_GETFL) } & libc::O_NONBLOCK, 0);
     }
 }
@@ -0,0 +1,323 @@
+//! Application-level multicast: receive once, fan out to many peers
+//!
+//! Cloud networks and most of the internet do not route IP multicast, so
+//! one-to-many distribution (game spectator feeds, market data, live media
+//! ingest) is built as an overlay: a relay receives each datagram once and
+//! re-sends it to its downstream peers, which may themselves be relays. A
+//! [`Relay`] does the re-sending with [`Udp::send_to_many`], so a datagram
+//! reaches every peer with a few `sendmmsg` calls and no per-peer copy.
+//!
+//! When the socket's send buffer fills mid-fan-out, the peers not yet
+//! reached keep the datagram in a per-peer backlog and get it on the next
+//! [`flush`](Relay::flush), ahead of anything newer, so each peer sees the
+//! stream in order. Backlogs are bounded by
+//! [`max_backlog`](Relay::max_backlog); a peer that stays behind loses its
+//! oldest datagrams rather than holding memory for everyone. Per-peer
+//! [`PeerStats`] show who is falling behind.
+//!
+//! Set [`upstream`](Relay::upstream) when relaying with [`pump`](Relay::pump):
+//! otherwise anyone who can reach the relay's port can make it amplify
+//! their traffic to every peer.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::relay::Relay;
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:6000".parse()?, &NetConfig::default())?;
+//! let mut relay = Relay::new().upstream("198.51.100.7:6000".parse()?);
+//! relay.add_peer("10.0.1.2:6000".parse()?);
+//! relay.add_peer("10.0.1.3:6000".parse()?);
+//!
+//! let mut bufs = vec![vec![0u8; 1500]; 32];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 32];
+//! loop {
+//!     // Wait for readability (and writability while relay.backlog() > 0), e.g. with poll::wait
+//!     relay.flush(&socket)?;
+//!     relay.pump(&socket, &socket, &mut bufs, &mut addrs)?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::Error;
+use crate::trace;
+use crate::udp::Udp;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Delivery counters for one downstream peer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    /// Datagrams sent to the peer
+    pub sent: u64,
+    /// Datagrams that had to wait in the peer's backlog
+    pub deferred: u64,
+    /// Datagrams discarded because the backlog was full
+    pub dropped: u64,
+    /// Datagrams the kernel rejected for this peer (e.g. no route)
+    pub failed: u64,
+    /// Datagrams waiting in the backlog now
+    pub queued: usize,
+}
+
+#[derive(Debug)]
+struct Peer {
+    addr: SocketAddr,
+    backlog: VecDeque<Arc<[u8]>>,
+    stats: PeerStats,
+}
+
+/// Fan-out of datagrams to a set of downstream peers
+#[derive(Debug)]
+pub struct Relay {
+    peers: Vec<Peer>,
+    upstream: Option<SocketAddr>,
+    max_backlog: usize,
+    /// Peers with an empty backlog, reused across calls
+    ready: Vec<SocketAddr>,
+}
+
+impl Relay {
+    /// Creates a relay with no peers, accepting datagrams from any source
+    ///
+    /// Backlogs hold up to 256 datagrams per peer.
+    pub fn new() -> Self {
+        Self { peers: Vec::new(), upstream: None, max_backlog: 256, ready: Vec::new() }
+    }
+
+    /// Only relays datagrams received from `addr` in [`pump`](Self::pump)
+    pub fn upstream(mut self, addr: SocketAddr) -> Self {
+        self.upstream = Some(addr);
+        self
+    }
+
+    /// Sets how many datagrams each peer may have waiting
+    pub fn max_backlog(mut self, datagrams: usize) -> Self {
+        self.max_backlog = datagrams;
+        self
+    }
+
+    /// Adds a downstream peer; returns `false` if it was already present
+    pub fn add_peer(&mut self, addr: SocketAddr) -> bool {
+        if self.peers.iter().any(|p| p.addr == addr) {
+            return false;
+        }
+        self.peers.push(Peer { addr, backlog: VecDeque::new(), stats: PeerStats::default() });
+        true
+    }
+
+    /// Removes a downstream peer and its backlog; returns `false` if it was not present
+    pub fn remove_peer(&mut self, addr: &SocketAddr) -> bool {
+        let before = self.peers.len();
+        self.peers.retain(|p| p.addr != *addr);
+        self.peers.len() != before
+    }
+
+    /// Returns the downstream peers in the order datagrams are sent to them
+    pub fn peers(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.peers.iter().map(|p| p.addr)
+    }
+
+    /// Returns the counters for `addr`, if it is a peer
+    pub fn stats(&self, addr: &SocketAddr) -> Option<PeerStats> {
+        let peer = self.peers.iter().find(|p| p.addr == *addr)?;
+        Some(PeerStats { queued: peer.backlog.len(), ..peer.stats })
+    }
+
+    /// Returns the number of datagrams waiting across all backlogs
+    pub fn backlog(&self) -> usize {
+        self.peers.iter().map(|p| p.backlog.len()).sum()
+    }
+
+    /// Sends `payload` to every peer through `sock`
+    ///
+    /// Earlier backlogs are flushed first. Peers that still have a backlog,
+    /// or that the socket could not reach before filling up, get `payload`
+    /// queued instead.
+    ///
+    /// # Returns
+    ///
+    /// The number of peers `payload` was sent to right away
+    pub fn relay(&mut self, sock: &Udp, payload: &[u8]) -> io::Result<usize> {
+        self.flush(sock)?;
+        self.ready.clear();
+        self.ready.extend(self.peers.iter().filter(|p| p.backlog.is_empty()).map(|p| p.addr));
+
+        let mut done = 0;
+        let mut sent = 0;
+        while done < self.ready.len() {
+            match sock.send_to_many(payload, &self.ready[done..]) {
+                Ok(n) => {
+                    for addr in &self.ready[done..done + n] {
+                        Self::find(&mut self.peers, addr).stats.sent += 1;
+                    }
+                    done += n;
+                    sent += n;
+                    if n == 0 || done < self.ready.len() {
+                        break;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // A hard error belongs to the first unsent peer; carry on with the rest
+                Err(_) => {
+                    Self::find(&mut self.peers, &self.ready[done]).stats.failed += 1;
+                    done += 1;
+                }
+            }
+        }
+
+        let unsent = self.ready.len() - done;
+        if unsent > 0 || self.ready.len() < self.peers.len() {
+            let shared: Arc<[u8]> = Arc::from(payload);
+            let max = self.max_backlog;
+            for peer in &mut self.peers {
+                let behind = !peer.backlog.is_empty();
+                if behind || self.ready[done..].contains(&peer.addr) {
+                    Self::enqueue(peer, shared.clone(), max);
+                }
+            }
+            trace::event!(debug, unsent, backlog = self.backlog(), "relay deferred");
+        }
+        Ok(sent)
+    }
+
+    /// Sends waiting datagrams, oldest first per peer, until the socket fills up
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams sent
+    pub fn flush(&mut self, sock: &Udp) -> io::Result<usize> {
+        let mut sent = 0;
+        for peer in &mut self.peers {
+            while let Some(payload) = peer.backlog.front() {
+                match sock.send_to(payload, peer.addr) {
+                    Ok(_) => {
+                        peer.stats.sent += 1;
+                        sent += 1;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(sent),
+                    Err(_) => peer.stats.failed += 1,
+                }
+                peer.backlog.pop_front();
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Receives a batch from `upstream` and relays each datagram through `downstream`
+    ///
+    /// Datagrams from sources other than the configured
+    /// [`upstream`](Self::upstream) address are discarded. The two sockets
+    /// may be the same.
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams relayed, or `WouldBlock` if none were waiting.
+    /// If relaying fails partway through the batch, the error is an
+    /// [`Error::PartialBatch`] counting the datagrams relayed before it; the
+    /// rest of the batch is dropped.
+    pub fn pump(&mut self, upstream: &Udp, downstream: &Udp, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        let n = upstream.recv_batch(bufs, addrs)?;
+        let mut relayed = 0;
+        for i in 0..n {
+            if self.upstream.is_some_and(|up| up != addrs[i]) {
+                continue;
+            }
+            self.relay(downstream, &bufs[i]).map_err(|e| Error::partial(relayed, e))?;
+            relayed += 1;
+        }
+        Ok(relayed)
+    }
+
+    fn find<'a>(peers: &'a mut [Peer], addr: &SocketAddr) -> &'a mut Peer {
+        peers.iter_mut().find(|p| p.addr == *addr).expect("ready peers come from the peer list")
+    }
+
+    /// Queues `payload` for `peer`, discarding its oldest datagram if the backlog is full
+    fn enqueue(peer: &mut Peer, payload: Arc<[u8]>, max: usize) {
+        if max == 0 {
+            peer.stats.dropped += 1;
+            return;
+        }
+        if peer.backlog.len() >= max {
+            peer.backlog.pop_front();
+            peer.stats.dropped += 1;
+        }
+        peer.backlog.push_back(payload);
+        peer.stats.deferred += 1;
+    }
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+    use std::time::Duration;
+
+    fn bind() -> Udp {
+        Udp::bind("127.0.0.1:0".parse().unwrap(), &NetConfig::default()).unwrap()
+    }
+
+    fn recv_all(sock: &Udp) -> Vec<Vec<u8>> {
+        std::thread::sleep(Duration::from_millis(20));
+        let mut bufs = vec![vec![0u8; 64]; 16];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 16];
+        let n = sock.recv_batch(&mut bufs, &mut addrs).unwrap_or(0);
+        bufs.truncate(n);
+        bufs
+    }
+
+    #[test]
+    fn test_pump_fans_out_from_upstream_only() {
+        let (source, stranger, relay_sock) = (bind(), bind(), bind());
+        let peers = [bind(), bind(), bind()];
+        let mut relay = Relay::new().upstream(source.local_addr().unwrap());
+        for p in &peers {
+            assert!(relay.add_peer(p.local_addr().unwrap()));
+        }
+        assert!(!relay.add_peer(peers[0].local_addr().unwrap()));
+
+        source.send_to(b"frame", relay_sock.local_addr().unwrap()).unwrap();
+        stranger.send_to(b"spoof", relay_sock.local_addr().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let mut bufs = vec![vec![0u8; 64]; 8];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 8];
+        assert_eq!(relay.pump(&relay_sock, &relay_sock, &mut bufs, &mut addrs).unwrap(), 1);
+
+        for p in &peers {
+            assert_eq!(recv_all(p), vec![b"frame".to_vec()]);
+            assert_eq!(relay.stats(&p.local_addr().unwrap()).unwrap().sent, 1);
+        }
+    }
+
+    #[test]
+    fn test_backlogged_peer_stays_in_order_and_bounded() {
+        let (relay_sock, slow, fast) = (bind(), bind(), bind());
+        let (slow_addr, fast_addr) = (slow.local_addr().unwrap(), fast.local_addr().unwrap());
+        let mut relay = Relay::new().max_backlog(2);
+        relay.add_peer(slow_addr);
+        relay.add_peer(fast_addr);
+
+        // As if earlier fan-outs hit a full send buffer before reaching `slow`
+        for old in [&b"a"[..], b"b", b"c"] {
+            Relay::enqueue(&mut relay.peers[0], Arc::from(old), 2);
+        }
+        assert_eq!(relay.stats(&slow_addr).unwrap().dropped, 1);
+
+        assert_eq!(relay.relay(&relay_sock, b"d").unwrap(), 2);
+        assert_eq!(recv_all(&slow), vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(recv_all(&fast), vec![b"d".to_vec()]);
+        let stats = relay.stats(&slow_addr).unwrap();
+        assert_eq!((stats.sent, stats.deferred, stats.queued), (3, 3, 0));
+    }
+}
@@ -0,0 +1,347 @@
+//! TCP repair mode for checkpointing and migrating established connections
+//!
+//! Linux's `TCP_REPAIR` lets a privileged process freeze a connection,
+//! read out its sequence numbers, queued data, negotiated options, and
+//! window state, and rebuild an identical socket elsewhere without the
+//! peer noticing. This is how CRIU live-migrates containers, and how a
+//! server can hand established connections to a new binary on the same
+//! host without draining them.
+//!
+//! - [`checkpoint`] freezes a stream and captures a [`TcpCheckpoint`]
+//! - [`restore`] rebuilds a connected stream from a checkpoint
+//! - [`set_repair`], [`select_queue`], [`queue_seq`], and
+//!   [`set_queue_seq`] expose the underlying socket options for tools
+//!   that need finer control
+//!
+//! Requires `CAP_NET_ADMIN`. Only available with the `unsafe_advanced`
+//! feature: nothing here is memory-unsafe, but a checkpoint restored twice,
+//! or a stream used after it was checkpointed, corrupts the connection for
+//! both ends.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, repair, tcp::TcpStream};
+//!
+//! # fn handoff(stream: TcpStream) -> std::io::Result<()> {
+//! // In the old process: freeze the connection and capture its state
+//! let snapshot = repair::checkpoint(&stream)?;
+//! // Closing a socket in repair mode sends neither FIN nor RST
+//! drop(stream);
+//!
+//! // In the new process, after shipping `snapshot` over
+//! let stream = repair::restore(&snapshot, &NetConfig::default())?;
+//! # let _ = stream;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Platform Support
+//!
+//! Linux 4.8+ (for `TCP_REPAIR_WINDOW`).
+
+use crate::config::NetConfig;
+use crate::raw as r;
+use crate::tcp::TcpStream;
+use std::io;
+use std::net::SocketAddr;
+
+/// Kernel queue selected with `TCP_REPAIR_QUEUE`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Queue {
+    /// No queue selected (`TCP_NO_QUEUE`)
+    None,
+    /// Received data not yet read by the application (`TCP_RECV_QUEUE`)
+    Recv,
+    /// Written data not yet acknowledged by the peer (`TCP_SEND_QUEUE`)
+    Send,
+}
+
+/// Window state read and restored with `TCP_REPAIR_WINDOW`
+///
+/// Field order and layout match `struct tcp_repair_window`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairWindow {
+    /// Sequence number of the segment that last updated the send window
+    pub snd_wl1: u32,
+    /// Peer's advertised receive window
+    pub snd_wnd: u32,
+    /// Largest window the peer has advertised
+    pub max_window: u32,
+    /// Receive window we last advertised
+    pub rcv_wnd: u32,
+    /// `rcv_nxt` when the receive window was last advertised
+    pub rcv_wup: u32,
+}
+
+/// Everything needed to rebuild an established TCP connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TcpCheckpoint {
+    /// Local address of the connection
+    pub local: SocketAddr,
+    /// Remote address of the connection
+    pub peer: SocketAddr,
+    /// Next sequence number to be written (`write_seq`)
+    pub send_seq: u32,
+    /// Next sequence number expected from the peer (`rcv_nxt`)
+    pub recv_seq: u32,
+    /// Written but unacknowledged bytes, oldest first
+    pub send_queue: Vec<u8>,
+    /// Received but unread bytes, oldest first
+    pub recv_queue: Vec<u8>,
+    /// Maximum segment size
+    pub mss: u32,
+    /// Negotiated window scale shifts as `(send, receive)`; `None` without window scaling
+    pub window_scale: Option<(u8, u8)>,
+    /// Selective acknowledgements were negotiated
+    pub sack: bool,
+    /// TCP timestamps were negotiated
+    pub timestamps: bool,
+    /// Current TCP timestamp clock value (`TCP_TIMESTAMP`)
+    pub timestamp: u32,
+    /// Send and receive window state
+    pub window: RepairWindow,
+}
+
+// TCP_REPAIR_QUEUE values (linux/tcp.h)
+const TCP_NO_QUEUE: i32 = 0;
+const TCP_RECV_QUEUE: i32 = 1;
+const TCP_SEND_QUEUE: i32 = 2;
+// TCP_REPAIR_OPTIONS codes (the TCP option kinds)
+const TCPOPT_MSS: u32 = 2;
+const TCPOPT_WINDOW: u32 = 3;
+const TCPOPT_SACK_PERM: u32 = 4;
+const TCPOPT_TIMESTAMP: u32 = 8;
+// tcp_info.tcpi_options bits
+const TCPI_OPT_TIMESTAMPS: u8 = 1;
+const TCPI_OPT_SACK: u8 = 2;
+const TCPI_OPT_WSCALE: u8 = 4;
+
+/// Enters or leaves repair mode
+///
+/// In repair mode the socket sends nothing: `connect` completes without a
+/// handshake, writes go straight into the selected queue, and `close`
+/// discards the connection without a FIN or RST. Leaving repair mode sends
+/// a window probe so the peer resynchronizes.
+pub fn set_repair(stream: &TcpStream, on: bool) -> io::Result<()> {
+    setsockopt(os(stream), libc::TCP_REPAIR, &(on as i32))
+}
+
+/// Selects the queue that [`queue_seq`], [`set_queue_seq`], reads, and writes apply to
+pub fn select_queue(stream: &TcpStream, queue: Queue) -> io::Result<()> {
+    select(os(stream), queue)
+}
+
+/// Reads the sequence number of the selected queue
+///
+/// `write_seq` for [`Queue::Send`], `rcv_nxt` for [`Queue::Recv`].
+pub fn queue_seq(stream: &TcpStream) -> io::Result<u32> {
+    getsockopt(os(stream), libc::TCP_QUEUE_SEQ)
+}
+
+/// Sets the sequence number of the selected queue
+///
+/// Only allowed in repair mode before the socket is connected.
+pub fn set_queue_seq(stream: &TcpStream, seq: u32) -> io::Result<()> {
+    setsockopt(os(stream), libc::TCP_QUEUE_SEQ, &seq)
+}
+
+/// Freezes `stream` in repair mode and captures its state
+///
+/// The stream is left in repair mode, so dropping it afterwards sends
+/// nothing to the peer. On error it is taken out of repair mode again.
+pub fn checkpoint(stream: &TcpStream) -> io::Result<TcpCheckpoint> {
+    let fd = os(stream);
+    let local = stream.local_addr()?;
+    let peer = stream.peer_addr()?;
+    set_repair(stream, true)?;
+    let captured = capture(fd, local, peer);
+    let _ = select(fd, Queue::None);
+    if captured.is_err() {
+        let _ = set_repair(stream, false);
+    }
+    captured
+}
+
+/// Rebuilds the connection captured in `snapshot`
+///
+/// The original socket must already be closed. `cfg` is applied as for
+/// any new stream, except that `reuse_port` is irrelevant: repair mode
+/// lets the socket bind the original local address regardless.
+pub fn restore(snapshot: &TcpCheckpoint, cfg: &NetConfig) -> io::Result<TcpStream> {
+    let (domain, sa, len) = r::to_sockaddr(snapshot.peer);
+    let fd = r::socket(domain, r::Type::Stream, r::Protocol::Tcp)?;
+    let std = unsafe { r::tcp_stream_from_os(fd) };
+    setsockopt(fd, libc::TCP_REPAIR, &1i32)?;
+
+    select(fd, Queue::Send)?;
+    setsockopt(fd, libc::TCP_QUEUE_SEQ, &snapshot.send_seq.wrapping_sub(snapshot.send_queue.len() as u32))?;
+    select(fd, Queue::Recv)?;
+    setsockopt(fd, libc::TCP_QUEUE_SEQ, &snapshot.recv_seq.wrapping_sub(snapshot.recv_queue.len() as u32))?;
+
+    let (_, lsa, llen) = r::to_sockaddr(snapshot.local);
+    unsafe { r::bind_raw(fd, &lsa, llen) }?;
+    unsafe { r::connect_raw(fd, &sa, len) }?;
+
+    let mut opts = vec![[TCPOPT_MSS, snapshot.mss]];
+    if let Some((snd, rcv)) = snapshot.window_scale {
+        opts.push([TCPOPT_WINDOW, u32::from(snd) | (u32::from(rcv) << 16)]);
+    }
+    if snapshot.sack {
+        opts.push([TCPOPT_SACK_PERM, 0]);
+    }
+    if snapshot.timestamps {
+        opts.push([TCPOPT_TIMESTAMP, 0]);
+    }
+    setsockopt_slice(fd, libc::TCP_REPAIR_OPTIONS, &opts)?;
+    if snapshot.timestamps {
+        setsockopt(fd, libc::TCP_TIMESTAMP, &snapshot.timestamp)?;
+    }
+
+    select(fd, Queue::Recv)?;
+    write_queue(fd, &snapshot.recv_queue)?;
+    select(fd, Queue::Send)?;
+    write_queue(fd, &snapshot.send_queue)?;
+    select(fd, Queue::None)?;
+
+    setsockopt(fd, libc::TCP_REPAIR_WINDOW, &snapshot.window)?;
+    setsockopt(fd, libc::TCP_REPAIR, &0i32)?;
+    TcpStream::from_std(std, cfg)
+}
+
+fn capture(fd: r::OsSocket, local: SocketAddr, peer: SocketAddr) -> io::Result<TcpCheckpoint> {
+    select(fd, Queue::Recv)?;
+    let recv_seq = getsockopt(fd, libc::TCP_QUEUE_SEQ)?;
+    let recv_queue = read_queue(fd, ioctl_len(fd, libc::FIONREAD)?)?;
+    select(fd, Queue::Send)?;
+    let send_seq = getsockopt(fd, libc::TCP_QUEUE_SEQ)?;
+    let send_queue = read_queue(fd, ioctl_len(fd, libc::TIOCOUTQ)?)?;
+
+    // The first bytes of struct tcp_info are stable across kernel versions
+    let info: [u8; 8] = getsockopt(fd, libc::TCP_INFO)?;
+    let options = info[5];
+    let (snd_wscale, rcv_wscale) = if cfg!(target_endian = "little") {
+        (info[6] & 0xf, info[6] >> 4)
+    } else {
+        (info[6] >> 4, info[6] & 0xf)
+    };
+
+    Ok(TcpCheckpoint {
+        local,
+        peer,
+        send_seq,
+        recv_seq,
+        send_queue,
+        recv_queue,
+        mss: getsockopt(fd, libc::TCP_MAXSEG)?,
+        window_scale: (options & TCPI_OPT_WSCALE != 0).then_some((snd_wscale, rcv_wscale)),
+        sack: options & TCPI_OPT_SACK != 0,
+        timestamps: options & TCPI_OPT_TIMESTAMPS != 0,
+        timestamp: getsockopt(fd, libc::TCP_TIMESTAMP)?,
+        window: getsockopt(fd, libc::TCP_REPAIR_WINDOW)?,
+    })
+}
+
+fn os(stream: &TcpStream) -> r::OsSocket {
+    r::os_handle(stream.as_std())
+}
+
+fn select(fd: r::OsSocket, queue: Queue) -> io::Result<()> {
+    let q = match queue {
+        Queue::None => TCP_NO_QUEUE,
+        Queue::Recv => TCP_RECV_QUEUE,
+        Queue::Send => TCP_SEND_QUEUE,
+    };
+    setsockopt(fd, libc::TCP_REPAIR_QUEUE, &q)
+}
+
+fn ioctl_len(fd: r::OsSocket, req: libc::Ioctl) -> io::Result<usize> {
+    let mut n: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, req, &mut n) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n.max(0) as usize)
+}
+
+/// Peeks `len` bytes from the selected queue, which repair mode allows for both queues
+fn read_queue(fd: r::OsSocket, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    if len == 0 {
+        return Ok(buf);
+    }
+    let n = unsafe { libc::recv(fd, buf.as_mut_ptr().cast(), len, libc::MSG_PEEK | libc::MSG_DONTWAIT) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(n as usize);
+    Ok(buf)
+}
+
+/// Writes `data` into the selected queue; the kernel accepts at most one MSS-sized skb per call
+fn write_queue(fd: r::OsSocket, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let n = unsafe { libc::send(fd, data.as_ptr().cast(), data.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        data = &data[n as usize..];
+    }
+    Ok(())
+}
+
+fn getsockopt<T: Copy>(fd: r::OsSocket, opt: i32) -> io::Result<T> {
+    // SAFETY: only instantiated with integer, byte array, and repr(C) integer struct types
+    let mut v: T = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<T>() as libc::socklen_t;
+    let rc = unsafe { libc::getsockopt(fd, libc::IPPROTO_TCP, opt, &mut v as *mut T as *mut _, &mut len) };
+    if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(v) }
+}
+
+fn setsockopt<T>(fd: r::OsSocket, opt: i32, v: &T) -> io::Result<()> {
+    setsockopt_slice(fd, opt, std::slice::from_ref(v))
+}
+
+fn setsockopt_slice<T>(fd: r::OsSocket, opt: i32, v: &[T]) -> io::Result<()> {
+    let len = std::mem::size_of_val(v) as libc::socklen_t;
+    let rc = unsafe { libc::setsockopt(fd, libc::IPPROTO_TCP, opt, v.as_ptr() as *const _, len) };
+    if rc != 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream as StdTcpStream};
+
+    #[test]
+    fn test_checkpoint_and_restore_preserve_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let server = TcpStream::from_std(server, &NetConfig::default()).unwrap();
+
+        client.write_all(b"unread").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let snapshot = match checkpoint(&server) {
+            Ok(snapshot) => snapshot,
+            // Needs CAP_NET_ADMIN
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(e) => panic!("checkpoint failed: {}", e),
+        };
+        assert_eq!(snapshot.recv_queue, b"unread");
+        assert_eq!(snapshot.peer, client.local_addr().unwrap());
+        drop(server);
+
+        let restored = restore(&snapshot, &NetConfig::default()).unwrap();
+        restored.as_std().set_nonblocking(false).unwrap();
+        let mut buf = [0u8; 6];
+        (&mut restored.as_std()).read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"unread");
+
+        (&mut restored.as_std()).write_all(b"migrated").unwrap();
+        let mut buf = [0u8; 8];
+        client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"migrated");
+    }
+}
@@ -0,0 +1,7 @@
+//! Sliding-window replay protection for datagram protocols
+//!
+//! Re-exported from `horizon_sockets_core`, which builds without the
+//! standard library, so a device and the server it talks to reject
+//! replays with the same window.
+
+pub use horizon_sockets_core::replay::*;
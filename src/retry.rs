@@ -0,0 +1,174 @@
+//! Backoff strategy for `WouldBlock`-heavy polling loops
+//!
+//! Every non-blocking socket loop has to decide what to do when an operation
+//! returns `WouldBlock`. Retrying immediately burns a core, sleeping for a
+//! fixed time adds latency. [`Backoff`] standardizes the usual compromise:
+//! spin briefly (new data often arrives within microseconds), then yield to
+//! other threads, then park with exponentially growing timeouts.
+//!
+//! Call [`Backoff::snooze`] after each `WouldBlock` and [`Backoff::reset`]
+//! after progress, or let [`retry`] do both.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::retry::{retry, Backoff};
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::default())?;
+//! let mut bufs = vec![vec![0u8; 2048]; 32];
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 32];
+//! let mut backoff = Backoff::new();
+//!
+//! loop {
+//!     let count = retry(&mut backoff, || socket.recv_batch(&mut bufs, &mut addrs))?;
+//!     println!("received {} packets", count);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+/// Spin, then yield, then park strategy for retrying non-blocking operations
+///
+/// - **Spin**: `spins` rounds of exponentially growing busy-wait (1, 2, 4, ... 64 hints)
+/// - **Yield**: `yields` calls to `std::thread::yield_now`
+/// - **Park**: `std::thread::park_timeout`, starting at `park` and doubling up to `max_park`
+///
+/// Parking can be cut short with `Thread::unpark`, so another thread that
+/// knows data is ready (for example an event loop) can wake the waiter early.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Spin rounds before yielding
+    spins: u32,
+    /// Yields before parking
+    yields: u32,
+    /// First park duration
+    park: Duration,
+    /// Upper bound for the park duration
+    max_park: Duration,
+    /// Snoozes since the last reset
+    step: u32,
+}
+
+impl Backoff {
+    /// Creates a backoff with 10 spin rounds, 10 yields, and parks from 50μs to 1ms
+    pub fn new() -> Self {
+        Self::spin_then_yield_then_park(10, 10, Duration::from_micros(50))
+    }
+
+    /// Creates a backoff with explicit spin, yield, and initial park settings
+    ///
+    /// The park duration doubles on every parked snooze, capped at 20x
+    /// `park` unless changed with [`max_park`](Self::max_park).
+    pub fn spin_then_yield_then_park(spins: u32, yields: u32, park: Duration) -> Self {
+        Self { spins, yields, park, max_park: park * 20, step: 0 }
+    }
+
+    /// Sets the longest single park
+    pub fn max_park(mut self, max: Duration) -> Self {
+        self.max_park = max.max(self.park);
+        self
+    }
+
+    /// Waits before the next retry, escalating with each call
+    pub fn snooze(&mut self) {
+        let step = self.step;
+        self.step = self.step.saturating_add(1);
+
+        if step < self.spins {
+            for _ in 0..1u32 << step.min(6) {
+                std::hint::spin_loop();
+            }
+        } else if step - self.spins < self.yields {
+            std::thread::yield_now();
+        } else {
+            std::thread::park_timeout(self.park_duration(step - self.spins - self.yields));
+        }
+    }
+
+    /// Returns to spinning after the operation made progress
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// Returns `true` once the spin and yield phases are exhausted
+    ///
+    /// A loop that can do other work might prefer to switch to an event
+    /// loop or [`poll::wait`](crate::poll::wait) at this point.
+    pub fn is_parking(&self) -> bool {
+        self.step >= self.spins.saturating_add(self.yields)
+    }
+
+    /// Park duration for the `n`th parked snooze
+    fn park_duration(&self, n: u32) -> Duration {
+        self.park.saturating_mul(1 << n.min(16)).min(self.max_park)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs `op` until it does not return `WouldBlock`, backing off in between
+///
+/// `Interrupted` errors are retried immediately. Any other result is
+/// returned; the backoff is reset on success so the next call starts by
+/// spinning again.
+pub fn retry<T>(backoff: &mut Backoff, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match op() {
+            Ok(v) => {
+                backoff.reset();
+                return Ok(v);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => backoff.snooze(),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_and_park_growth() {
+        let mut b = Backoff::spin_then_yield_then_park(2, 1, Duration::from_micros(10))
+            .max_park(Duration::from_micros(30));
+        b.snooze();
+        b.snooze();
+        assert!(!b.is_parking());
+        b.snooze();
+        assert!(b.is_parking());
+        assert_eq!(b.park_duration(0), Duration::from_micros(10));
+        assert_eq!(b.park_duration(1), Duration::from_micros(20));
+        assert_eq!(b.park_duration(5), Duration::from_micros(30));
+        b.reset();
+        assert!(!b.is_parking());
+    }
+
+    #[test]
+    fn test_retry_until_ready() {
+        let mut attempts = 0;
+        let mut backoff = Backoff::new();
+        let value = retry(&mut backoff, || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+                3 => Err(io::Error::from(io::ErrorKind::Interrupted)),
+                _ => Ok(attempts),
+            }
+        })
+        .unwrap();
+        assert_eq!(value, 4);
+
+        let err = retry(&mut backoff, || Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,359 @@
+//! Receive-side scaling (RSS) inspection through the ethtool ioctl
+//!
+//! NICs spread incoming flows across receive queues by hashing selected
+//! header fields with a secret key and looking the hash up in an indirection
+//! table. Sharding sockets with `SO_REUSEPORT`, pinning event loops with
+//! [`affinity`](crate::affinity), or grouping by [`napi`](crate::napi) works
+//! best when it mirrors that hardware steering. This module reads the
+//! queue count, hashed fields, hash key, and indirection table of an
+//! interface, and can compute the Toeplitz hash the NIC uses.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::rss::{self, FlowType};
+//!
+//! let info = rss::query("eth0")?;
+//! println!("{} rx queues, {:?} hash", info.rx_queues, info.hash_function);
+//!
+//! let fields = rss::hash_fields("eth0", FlowType::UdpV4)?;
+//! if !fields.src_port {
+//!     println!("UDP flows between two hosts all land on one queue");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Platform Support
+//!
+//! Linux only; other platforms return `Unsupported`. Reading the hash key
+//! and indirection table needs driver support (`ETHTOOL_GRSSH`), and some
+//! drivers restrict it to `CAP_NET_ADMIN`.
+
+use std::io;
+use std::net::SocketAddr;
+
+/// Traffic class whose RSS hash fields are queried
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowType {
+    /// TCP over IPv4
+    TcpV4,
+    /// UDP over IPv4
+    UdpV4,
+    /// TCP over IPv6
+    TcpV6,
+    /// UDP over IPv6
+    UdpV6,
+}
+
+impl FlowType {
+    /// Value of the `flow_type` field in `struct ethtool_rxnfc`
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn raw(self) -> u32 {
+        match self {
+            FlowType::TcpV4 => 0x01,
+            FlowType::UdpV4 => 0x02,
+            FlowType::TcpV6 => 0x05,
+            FlowType::UdpV6 => 0x06,
+        }
+    }
+}
+
+/// Header fields included in the RSS hash for a flow type
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RssHashFields {
+    /// Source IP address
+    pub src_ip: bool,
+    /// Destination IP address
+    pub dst_ip: bool,
+    /// Source port (first two bytes of the L4 header)
+    pub src_port: bool,
+    /// Destination port (bytes 2-3 of the L4 header)
+    pub dst_port: bool,
+    /// Raw `RXH_*` bit set reported by the driver
+    pub raw: u64,
+}
+
+impl RssHashFields {
+    /// Decodes an `RXH_*` bit set
+    pub fn from_raw(raw: u64) -> Self {
+        Self {
+            src_ip: raw & (1 << 4) != 0,
+            dst_ip: raw & (1 << 5) != 0,
+            src_port: raw & (1 << 6) != 0,
+            dst_port: raw & (1 << 7) != 0,
+            raw,
+        }
+    }
+}
+
+/// Hash function the NIC applies to the selected fields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RssHashFunction {
+    /// Toeplitz hash, the common default
+    Toeplitz,
+    /// XOR of the input fields
+    Xor,
+    /// CRC32 of the input fields
+    Crc32,
+    /// Driver did not report a function, or reported an unknown one
+    Unknown(u8),
+}
+
+impl RssHashFunction {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn from_raw(hfunc: u8) -> Self {
+        match hfunc {
+            0x01 => RssHashFunction::Toeplitz,
+            0x02 => RssHashFunction::Xor,
+            0x04 => RssHashFunction::Crc32,
+            other => RssHashFunction::Unknown(other),
+        }
+    }
+}
+
+/// RSS configuration of a network interface
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RssInfo {
+    /// Number of receive queues (rings) RSS spreads flows across
+    pub rx_queues: u32,
+    /// Hash function in use
+    pub hash_function: RssHashFunction,
+    /// Hash key, empty if the driver does not expose it
+    pub key: Vec<u8>,
+    /// Indirection table mapping hash buckets to receive queues
+    pub indirection: Vec<u32>,
+}
+
+impl RssInfo {
+    /// Returns the receive queue a packet with the given RSS hash lands on
+    pub fn queue_for_hash(&self, hash: u32) -> Option<u32> {
+        if self.indirection.is_empty() {
+            return None;
+        }
+        Some(self.indirection[hash as usize % self.indirection.len()])
+    }
+
+    /// Predicts the receive queue of a flow from its 4-tuple
+    ///
+    /// Assumes the NIC hashes addresses and ports (see [`hash_fields`]) with
+    /// Toeplitz; returns `None` for other functions or a missing key/table.
+    pub fn queue_for_flow(&self, src: SocketAddr, dst: SocketAddr) -> Option<u32> {
+        if self.hash_function != RssHashFunction::Toeplitz {
+            return None;
+        }
+        let input = flow_input(src, dst)?;
+        let hash = toeplitz_hash(&self.key, &input)?;
+        self.queue_for_hash(hash)
+    }
+
+    /// Returns how many indirection table entries point at each queue
+    ///
+    /// Uneven weights mean some queues (and the cores serving them) receive
+    /// a larger share of flows.
+    pub fn queue_weights(&self) -> Vec<usize> {
+        let mut weights = vec![0; self.rx_queues as usize];
+        for &q in &self.indirection {
+            if let Some(w) = weights.get_mut(q as usize) {
+                *w += 1;
+            }
+        }
+        weights
+    }
+}
+
+/// Computes the Toeplitz hash of `input` with `key`
+///
+/// Returns `None` if the key is shorter than `input.len() + 4` bytes.
+pub fn toeplitz_hash(key: &[u8], input: &[u8]) -> Option<u32> {
+    if key.len() < input.len() + 4 {
+        return None;
+    }
+    let mut window = u32::from_be_bytes([key[0], key[1], key[2], key[3]]);
+    let mut hash = 0u32;
+    for (i, &byte) in input.iter().enumerate() {
+        let next = key[i + 4];
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                hash ^= window;
+            }
+            window = (window << 1) | ((next >> (7 - bit)) & 1) as u32;
+        }
+    }
+    Some(hash)
+}
+
+/// Builds the Toeplitz input (src ip, dst ip, src port, dst port) for a flow
+fn flow_input(src: SocketAddr, dst: SocketAddr) -> Option<Vec<u8>> {
+    let mut input = Vec::with_capacity(36);
+    match (src, dst) {
+        (SocketAddr::V4(s), SocketAddr::V4(d)) => {
+            input.extend_from_slice(&s.ip().octets());
+            input.extend_from_slice(&d.ip().octets());
+        }
+        (SocketAddr::V6(s), SocketAddr::V6(d)) => {
+            input.extend_from_slice(&s.ip().octets());
+            input.extend_from_slice(&d.ip().octets());
+        }
+        _ => return None,
+    }
+    input.extend_from_slice(&src.port().to_be_bytes());
+    input.extend_from_slice(&dst.port().to_be_bytes());
+    Some(input)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        // ethtool commands from <linux/ethtool.h>
+        const ETHTOOL_GRXFH: u32 = 0x29;
+        const ETHTOOL_GRXRINGS: u32 = 0x2d;
+        const ETHTOOL_GRSSH: u32 = 0x46;
+
+        /// Header of `struct ethtool_rxfh`, followed by the table and key
+        #[repr(C)]
+        #[derive(Clone, Copy, Default)]
+        struct EthtoolRxfh {
+            cmd: u32,
+            rss_context: u32,
+            indir_size: u32,
+            key_size: u32,
+            hfunc: u8,
+            input_xfrm: u8,
+            rsvd8: [u8; 2],
+            rsvd32: u32,
+        }
+
+        /// Issues `SIOCETHTOOL` for `iface` with `data` as the command buffer
+        fn ethtool(iface: &str, data: *mut libc::c_void) -> io::Result<()> {
+            let name = iface.as_bytes();
+            if name.is_empty() || name.len() >= libc::IFNAMSIZ {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"));
+            }
+            let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+            for (dst, &src) in ifr.ifr_name.iter_mut().zip(name) {
+                *dst = src as libc::c_char;
+            }
+            ifr.ifr_ifru.ifru_data = data as *mut libc::c_char;
+
+            let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let rc = unsafe { libc::ioctl(fd, libc::SIOCETHTOOL as _, &mut ifr) };
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            if rc < 0 { Err(err) } else { Ok(()) }
+        }
+
+        /// Runs an `ethtool_rxnfc` command and returns its `data` field
+        fn rxnfc(iface: &str, cmd: u32, flow_type: u32) -> io::Result<u64> {
+            // struct ethtool_rxnfc is under 256 bytes; u64 storage keeps `data` aligned
+            let mut buf = [0u64; 32];
+            buf[0] = u64::from_ne_bytes(pack_u32s(cmd, flow_type));
+            ethtool(iface, buf.as_mut_ptr() as *mut _)?;
+            Ok(buf[1])
+        }
+
+        fn pack_u32s(a: u32, b: u32) -> [u8; 8] {
+            let mut out = [0u8; 8];
+            out[..4].copy_from_slice(&a.to_ne_bytes());
+            out[4..].copy_from_slice(&b.to_ne_bytes());
+            out
+        }
+    }
+}
+
+/// Returns the number of receive queues RSS distributes flows across
+pub fn rx_queue_count(iface: &str) -> io::Result<u32> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            rxnfc(iface, ETHTOOL_GRXRINGS, 0).map(|n| n as u32)
+        } else {
+            let _ = iface;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "ethtool is Linux only"))
+        }
+    }
+}
+
+/// Returns the header fields hashed for `flow` traffic
+pub fn hash_fields(iface: &str, flow: FlowType) -> io::Result<RssHashFields> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            rxnfc(iface, ETHTOOL_GRXFH, flow.raw()).map(RssHashFields::from_raw)
+        } else {
+            let _ = (iface, flow);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "ethtool is Linux only"))
+        }
+    }
+}
+
+/// Reads the queue count, hash function, key, and indirection table
+pub fn query(iface: &str) -> io::Result<RssInfo> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            let rx_queues = rx_queue_count(iface)?;
+
+            // First call reports the table and key sizes
+            let mut hdr = EthtoolRxfh { cmd: ETHTOOL_GRSSH, ..Default::default() };
+            ethtool(iface, &mut hdr as *mut _ as *mut _)?;
+
+            let hdr_len = std::mem::size_of::<EthtoolRxfh>();
+            let indir_len = hdr.indir_size as usize * 4;
+            let total = hdr_len + indir_len + hdr.key_size as usize;
+            let mut buf = vec![0u32; total.div_ceil(4)];
+            let req = EthtoolRxfh { cmd: ETHTOOL_GRSSH, indir_size: hdr.indir_size, key_size: hdr.key_size, ..Default::default() };
+            unsafe { std::ptr::write(buf.as_mut_ptr() as *mut EthtoolRxfh, req) };
+            ethtool(iface, buf.as_mut_ptr() as *mut _)?;
+
+            let resp = unsafe { std::ptr::read(buf.as_ptr() as *const EthtoolRxfh) };
+            let bytes: &[u8] = bytemuck::cast_slice(&buf);
+            let indirection = bytes[hdr_len..hdr_len + indir_len]
+                .chunks_exact(4)
+                .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            let key = bytes[hdr_len + indir_len..total].to_vec();
+
+            Ok(RssInfo { rx_queues, hash_function: RssHashFunction::from_raw(resp.hfunc), key, indirection })
+        } else {
+            let _ = iface;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "ethtool is Linux only"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Verification key and vectors from the Microsoft RSS specification
+    const KEY: [u8; 40] = [
+        0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+        0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+        0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+    ];
+
+    #[test]
+    fn test_toeplitz_matches_spec() {
+        let src: SocketAddr = "66.9.149.187:2794".parse().unwrap();
+        let dst: SocketAddr = "161.142.100.80:1766".parse().unwrap();
+        let input = flow_input(src, dst).unwrap();
+        assert_eq!(toeplitz_hash(&KEY, &input[..8]), Some(0x323e8fc2));
+        assert_eq!(toeplitz_hash(&KEY, &input), Some(0x51ccc178));
+        assert_eq!(toeplitz_hash(&KEY[..8], &input), None);
+
+        let info = RssInfo {
+            rx_queues: 4,
+            hash_function: RssHashFunction::Toeplitz,
+            key: KEY.to_vec(),
+            indirection: (0..128).map(|i| i % 4).collect(),
+        };
+        assert_eq!(info.queue_for_flow(src, dst), Some(0x51ccc178 % 128 % 4));
+        assert_eq!(info.queue_weights(), vec![32; 4]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loopback_has_no_rss() {
+        assert!(query("lo").is_err());
+        assert!(rx_queue_count("").is_err());
+    }
+}
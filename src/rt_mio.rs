@@ -12,11 +12,14 @@
 //! The runtime is designed for high-performance networking applications that
 //! require precise control over event handling and minimal overhead.
 
+use crate::loop_stats::{LoopStats, Utilization};
+use crate::trace;
 use mio::net::{
     TcpListener as MioTcpListener, TcpStream as MioTcpStream, UdpSocket as MioUdpSocket,
 };
 use mio::{Events, Interest, Poll, Token};
-use std::{io, time::Duration};
+use std::io;
+use std::time::{Duration, Instant};
 
 /// High-performance networking runtime using mio
 ///
@@ -31,6 +34,8 @@ pub struct Runtime {
     events: Events,
     /// Configurable timeout for poll operations
     poll_timeout: Duration,
+    /// Time spent in poll versus event callbacks
+    stats: LoopStats,
 }
 
 /// Handle for per-socket operations and metadata
@@ -40,6 +45,27 @@ pub struct Runtime {
 #[derive(Debug, Clone, Copy)]
 pub struct NetHandle;
 
+/// Busy poll parameters for the event loop itself
+///
+/// Applied to the epoll instance with [`Runtime::set_busy_poll`], these make
+/// `epoll_wait` busy poll the NAPI contexts of all registered sockets instead
+/// of relying on per-socket `SO_BUSY_POLL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyPoll {
+    /// How long each wait busy polls before sleeping, in microseconds
+    pub usecs: u32,
+    /// Packets processed per busy poll iteration (kernel default: 8)
+    pub budget: u16,
+    /// Defer interrupt-driven processing while busy polling (prefer_busy_poll)
+    pub prefer: bool,
+}
+
+impl Default for BusyPoll {
+    fn default() -> Self {
+        Self { usecs: 50, budget: 8, prefer: false }
+    }
+}
+
 impl Runtime {
     /// Creates a new runtime with default configuration
     pub fn new() -> io::Result<Self> {
@@ -47,6 +73,7 @@ impl Runtime {
             poll: Poll::new()?,
             events: Events::with_capacity(4096),
             poll_timeout: Duration::from_millis(10),
+            stats: LoopStats::new(),
         })
     }
 
@@ -56,6 +83,7 @@ impl Runtime {
             poll: Poll::new()?,
             events: Events::with_capacity(event_capacity),
             poll_timeout: Duration::from_millis(10),
+            stats: LoopStats::new(),
         })
     }
 
@@ -69,13 +97,49 @@ impl Runtime {
         self.poll_timeout
     }
 
+    /// Enables busy polling on the runtime's epoll instance (Linux 6.9+)
+    ///
+    /// Uses the `EPIOCSPARAMS` ioctl so every `poll` busy polls the NAPI
+    /// contexts of registered sockets. Passing `usecs: 0` disables it again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Unsupported` on older kernels and other platforms. There, set
+    /// [`NetConfig::busy_poll`](crate::NetConfig::busy_poll),
+    /// [`NetConfig::prefer_busy_poll`](crate::NetConfig::prefer_busy_poll), and
+    /// [`NetConfig::busy_poll_budget`](crate::NetConfig::busy_poll_budget) on
+    /// each socket instead. Budgets above 8 require `CAP_NET_ADMIN`.
+    pub fn set_busy_poll(&self, params: BusyPoll) -> io::Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                use std::os::fd::AsRawFd;
+
+                let mut raw = libc::epoll_params {
+                    busy_poll_usecs: params.usecs,
+                    busy_poll_budget: params.budget,
+                    prefer_busy_poll: params.prefer as u8,
+                    __pad: 0,
+                };
+                let rc = unsafe { libc::ioctl(self.poll.as_raw_fd(), libc::EPIOCSPARAMS, &mut raw) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.raw_os_error() == Some(libc::ENOTTY) {
+                        return Err(io::Error::new(io::ErrorKind::Unsupported, "epoll busy poll requires Linux 6.9+"));
+                    }
+                    return Err(err);
+                }
+                Ok(())
+            } else {
+                let _ = params;
+                Err(crate::error::Error::unsupported("EPIOCSPARAMS"))
+            }
+        }
+    }
+
     /// Runs the event loop indefinitely with configurable event handling
     pub fn run<F: FnMut(&mio::event::Event)>(&mut self, mut f: F) -> io::Result<()> {
         loop {
-            self.poll.poll(&mut self.events, Some(self.poll_timeout))?;
-            for ev in self.events.iter() {
-                f(ev);
-            }
+            self.poll_and_dispatch(self.poll_timeout, &mut f)?;
         }
     }
 
@@ -86,23 +150,87 @@ impl Runtime {
         mut f: F,
     ) -> io::Result<()> {
         loop {
-            self.poll.poll(&mut self.events, Some(timeout))?;
-            for ev in self.events.iter() {
-                f(ev);
-            }
+            self.poll_and_dispatch(timeout, &mut f)?;
         }
     }
 
     /// Processes events for a single poll cycle
     pub fn poll_once<F: FnMut(&mio::event::Event)>(&mut self, mut f: F) -> io::Result<usize> {
-        self.poll.poll(&mut self.events, Some(self.poll_timeout))?;
+        self.poll_and_dispatch(self.poll_timeout, &mut f)
+    }
+
+    /// Processes events for one poll cycle, waking no later than `deadline`
+    ///
+    /// Connection timers such as [`HalfClose::deadline`](crate::half_close::HalfClose::deadline)
+    /// bound the wait so they fire on time; with no deadline the configured
+    /// poll timeout applies.
+    pub fn poll_until<F: FnMut(&mio::event::Event)>(
+        &mut self,
+        deadline: Option<Instant>,
+        mut f: F,
+    ) -> io::Result<usize> {
+        let timeout = match deadline {
+            Some(d) => d.saturating_duration_since(Instant::now()).min(self.poll_timeout),
+            None => self.poll_timeout,
+        };
+        self.poll_and_dispatch(timeout, &mut f)
+    }
+
+    /// Returns poll and callback time totals since creation or the last [`take_loop_stats`](Self::take_loop_stats)
+    ///
+    /// See [`loop_stats`](crate::loop_stats) for how to read the split.
+    pub fn loop_stats(&self) -> Utilization {
+        self.stats.utilization()
+    }
+
+    /// Returns the loop time totals and starts a new accounting window
+    pub fn take_loop_stats(&mut self) -> Utilization {
+        self.stats.take()
+    }
+
+    /// Enables sampling the thread CPU clock around each poll (Linux and Android)
+    ///
+    /// Separates time busy inside the kernel from time asleep in
+    /// [`Utilization::poll_cpu`], at the cost of one `clock_gettime` per poll.
+    pub fn set_thread_cpu_accounting(&mut self, enable: bool) {
+        self.stats = std::mem::take(&mut self.stats).thread_cpu(enable);
+    }
+
+    /// Polls once and dispatches every event, timing both phases
+    fn poll_and_dispatch<F: FnMut(&mio::event::Event)>(&mut self, timeout: Duration, f: &mut F) -> io::Result<usize> {
+        let (poll, events) = (&mut self.poll, &mut self.events);
+        self.stats.poll(|| poll.poll(events, Some(timeout)))?;
         let count = self.events.iter().count();
-        for ev in self.events.iter() {
-            f(ev);
-        }
+        trace::event!(trace, events = count, "mio poll");
+        let events = &self.events;
+        self.stats.callbacks(count, || {
+            for ev in events.iter() {
+                f(ev);
+            }
+        });
         Ok(count)
     }
 
+    /// Registers any mio event source, including the crate's own socket wrappers
+    ///
+    /// On Unix, [`Udp`](crate::udp::Udp), [`TcpListener`](crate::tcp::TcpListener),
+    /// and [`TcpStream`](crate::tcp::TcpStream) implement `mio::event::Source`
+    /// directly, so they can be registered without converting to `mio::net` types.
+    pub fn register<S: mio::event::Source + ?Sized>(
+        &self,
+        source: &mut S,
+        token: Token,
+        interest: Interest,
+    ) -> io::Result<NetHandle> {
+        self.poll.registry().register(source, token, interest)?;
+        Ok(NetHandle)
+    }
+
+    /// Removes a previously registered event source
+    pub fn deregister<S: mio::event::Source + ?Sized>(&self, source: &mut S) -> io::Result<()> {
+        self.poll.registry().deregister(source)
+    }
+
     /// Registers a UDP socket for event notification
     pub fn register_udp(
         &self,
@@ -172,4 +300,39 @@ mod tests {
         let result = runtime.register_udp(&mut socket, Token(0), Interest::READABLE);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_set_busy_poll() {
+        let runtime = Runtime::new().unwrap();
+        match runtime.set_busy_poll(BusyPoll::default()) {
+            Ok(()) => runtime.set_busy_poll(BusyPoll { usecs: 0, ..Default::default() }).unwrap(),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+
+    #[test]
+    fn test_loop_stats_count_polls_and_events() {
+        let mut runtime = Runtime::new().unwrap();
+        let mut socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        runtime.register_udp(&mut socket, Token(0), Interest::WRITABLE).unwrap();
+
+        let mut seen = 0;
+        runtime.poll_once(|_| seen += 1).unwrap();
+        runtime.poll_until(Some(Instant::now()), |_| {}).unwrap();
+        let stats = runtime.take_loop_stats();
+        assert_eq!((stats.iterations, stats.events), (2, seen as u64));
+        assert!(stats.poll + stats.callbacks <= stats.wall);
+        assert_eq!(runtime.loop_stats().iterations, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_register_crate_udp() {
+        let runtime = Runtime::new().unwrap();
+        let cfg = crate::NetConfig { ipv6_only: None, ..Default::default() };
+        let mut socket = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+
+        assert!(runtime.register(&mut socket, Token(1), Interest::READABLE).is_ok());
+        assert!(runtime.deregister(&mut socket).is_ok());
+    }
 }
@@ -26,8 +26,14 @@
 //! This implementation is currently under development. Basic structures
 //! are provided for API compatibility, with full implementation coming
 //! in future releases.
+//!
+//! io_uring fixed-file registration (`IORING_REGISTER_FILES`) is not
+//! offered. monoio 0.2 keeps its ring private, and a file table registered
+//! on any other ring cannot be referenced by the operations monoio submits.
+//! It can be added once monoio exposes registration on its own ring.
 
 #[cfg(feature = "monoio-runtime")]
+#[allow(clippy::module_inception)]
 mod rt_monoio {
     use std::io;
     
@@ -0,0 +1,519 @@
+//! Zero-copy RTP and RTCP header parsing and building
+//!
+//! VoIP and video applications receiving with
+//! [`recv_batch`](crate::udp::Udp::recv_batch) otherwise pick RTP headers
+//! apart by hand. The views here read fields straight from the received
+//! bytes without copying, and check lengths up front, so a malformed
+//! packet is an `InvalidData` error rather than a panic:
+//!
+//! - [`RtpPacket`] reads an RTP header (RFC 3550): marker, payload type,
+//!   sequence number, timestamp, SSRC, CSRCs, and the header extension,
+//!   whose RFC 8285 elements [`Extension::elements`] iterates.
+//! - [`RtpHeader`] writes a header. [`prepend_to`](RtpHeader::prepend_to)
+//!   writes it into the headroom of a pooled
+//!   [`PacketBuf`](crate::buffer_pool::PacketBuf) in front of a payload
+//!   that is already in place, so the payload is never moved.
+//! - [`rtcp_packets`] walks a compound RTCP packet and [`RtcpPacket`]
+//!   reads sender info and report blocks from SR and RR packets;
+//!   [`write_receiver_report`] builds an RR.
+//! - [`is_rtcp`] tells the two apart on a multiplexed port (RFC 5761).
+//! - [`extend_seq`] widens 16-bit sequence numbers for
+//!   [`JitterBuffer`](crate::jitter::JitterBuffer).
+//!
+//! Requires the `rtp` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::buffer_pool::BufferPool;
+//! use horizon_sockets::rtp::{RtpHeader, RtpPacket};
+//!
+//! let pool = BufferPool::new(16, 1500).headroom(64);
+//! let mut packet = pool.acquire_packet();
+//! packet.append(b"opus frame");
+//!
+//! let header = RtpHeader { payload_type: 111, sequence: 7, timestamp: 960, ssrc: 0x1234, ..Default::default() };
+//! header.prepend_to(&mut packet);
+//!
+//! let parsed = RtpPacket::parse(packet.data())?;
+//! assert_eq!((parsed.sequence(), parsed.timestamp(), parsed.ssrc()), (7, 960, 0x1234));
+//! assert_eq!(parsed.payload(), b"opus frame");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::buffer_pool::PacketBuf;
+use std::io;
+
+/// RTP and RTCP protocol version
+pub const VERSION: u8 = 2;
+/// Length of the fixed RTP header, without CSRCs or extension
+pub const HEADER_LEN: usize = 12;
+
+/// RTCP sender report
+pub const RTCP_SR: u8 = 200;
+/// RTCP receiver report
+pub const RTCP_RR: u8 = 201;
+/// RTCP source description
+pub const RTCP_SDES: u8 = 202;
+/// RTCP goodbye
+pub const RTCP_BYE: u8 = 203;
+/// RTCP application-defined
+pub const RTCP_APP: u8 = 204;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn be16(b: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([b[at], b[at + 1]])
+}
+
+fn be32(b: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+/// A validated view of an RTP packet
+#[derive(Clone, Copy, Debug)]
+pub struct RtpPacket<'a> {
+    buf: &'a [u8],
+    /// Offset of the payload
+    header_len: usize,
+    /// End of the payload, before any padding
+    payload_end: usize,
+}
+
+impl<'a> RtpPacket<'a> {
+    /// Checks the version and lengths of an RTP packet
+    ///
+    /// # Errors
+    ///
+    /// `InvalidData` if the version is not 2 or the CSRC list, extension,
+    /// or padding runs past the end of `buf`.
+    pub fn parse(buf: &'a [u8]) -> io::Result<Self> {
+        if buf.len() < HEADER_LEN {
+            return Err(invalid("RTP packet shorter than its header"));
+        }
+        if buf[0] >> 6 != VERSION {
+            return Err(invalid("RTP version is not 2"));
+        }
+        let mut header_len = HEADER_LEN + 4 * usize::from(buf[0] & 0x0f);
+        if buf[0] & 0x10 != 0 {
+            if buf.len() < header_len + 4 {
+                return Err(invalid("RTP extension header truncated"));
+            }
+            header_len += 4 + 4 * usize::from(be16(buf, header_len + 2));
+        }
+        if buf.len() < header_len {
+            return Err(invalid("RTP header truncated"));
+        }
+        let mut payload_end = buf.len();
+        if buf[0] & 0x20 != 0 {
+            let pad = usize::from(buf[buf.len() - 1]);
+            if pad == 0 || pad > buf.len() - header_len {
+                return Err(invalid("RTP padding length out of range"));
+            }
+            payload_end -= pad;
+        }
+        Ok(Self { buf, header_len, payload_end })
+    }
+
+    /// Returns the marker bit, e.g. the last packet of a video frame
+    pub fn marker(&self) -> bool {
+        self.buf[1] & 0x80 != 0
+    }
+
+    /// Returns the 7-bit payload type
+    pub fn payload_type(&self) -> u8 {
+        self.buf[1] & 0x7f
+    }
+
+    /// Returns the 16-bit sequence number
+    pub fn sequence(&self) -> u16 {
+        be16(self.buf, 2)
+    }
+
+    /// Returns the media timestamp, in units of the payload's clock rate
+    pub fn timestamp(&self) -> u32 {
+        be32(self.buf, 4)
+    }
+
+    /// Returns the synchronization source
+    pub fn ssrc(&self) -> u32 {
+        be32(self.buf, 8)
+    }
+
+    /// Returns the contributing sources
+    pub fn csrcs(&self) -> impl Iterator<Item = u32> + 'a {
+        let buf = self.buf;
+        (0..usize::from(buf[0] & 0x0f)).map(move |i| be32(buf, HEADER_LEN + 4 * i))
+    }
+
+    /// Returns the header extension, if the X bit is set
+    pub fn extension(&self) -> Option<Extension<'a>> {
+        if self.buf[0] & 0x10 == 0 {
+            return None;
+        }
+        let at = HEADER_LEN + 4 * usize::from(self.buf[0] & 0x0f);
+        Some(Extension { profile: be16(self.buf, at), data: &self.buf[at + 4..self.header_len] })
+    }
+
+    /// Returns the length of the header including CSRCs and extension
+    pub fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    /// Returns the payload, without padding
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buf[self.header_len..self.payload_end]
+    }
+}
+
+/// An RTP header extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extension<'a> {
+    /// Profile-defined identifier; `0xBEDE` and `0x100x` mark RFC 8285 elements
+    pub profile: u16,
+    /// Extension contents, a multiple of four bytes
+    pub data: &'a [u8],
+}
+
+impl<'a> Extension<'a> {
+    /// Iterates RFC 8285 `(id, value)` elements of a one-byte or two-byte header extension
+    ///
+    /// Empty for other profiles. Iteration stops at the first element that
+    /// runs past the end of the data.
+    pub fn elements(&self) -> impl Iterator<Item = (u8, &'a [u8])> + 'a {
+        let two_byte = self.profile & 0xfff0 == 0x1000;
+        let data = if self.profile == 0xBEDE || two_byte { self.data } else { &[] };
+        let mut at = 0;
+        std::iter::from_fn(move || loop {
+            let &first = data.get(at)?;
+            if first == 0 {
+                // Padding between elements
+                at += 1;
+                continue;
+            }
+            let (id, len, start) = if two_byte {
+                (first, usize::from(*data.get(at + 1)?), at + 2)
+            } else if first >> 4 == 15 {
+                // Id 15 ends one-byte processing
+                return None;
+            } else {
+                (first >> 4, usize::from(first & 0x0f) + 1, at + 1)
+            };
+            let value = data.get(start..start + len)?;
+            at = start + len;
+            return Some((id, value));
+        })
+    }
+}
+
+/// Fields for writing an RTP header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RtpHeader<'a> {
+    /// Marker bit
+    pub marker: bool,
+    /// 7-bit payload type
+    pub payload_type: u8,
+    /// Sequence number
+    pub sequence: u16,
+    /// Media timestamp
+    pub timestamp: u32,
+    /// Synchronization source
+    pub ssrc: u32,
+    /// Contributing sources, at most 15
+    pub csrcs: &'a [u32],
+    /// Extension profile and data; the data is zero-padded to four bytes
+    pub extension: Option<(u16, &'a [u8])>,
+}
+
+impl RtpHeader<'_> {
+    /// Returns the encoded length of the header
+    pub fn len(&self) -> usize {
+        HEADER_LEN + 4 * self.csrcs.len().min(15) + self.extension.map_or(0, |(_, data)| 4 + data.len().next_multiple_of(4))
+    }
+
+    /// Returns `false`; a header is never empty
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Writes the header into the start of `out`, returning its length
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than [`len`](Self::len).
+    pub fn write(&self, out: &mut [u8]) -> usize {
+        let len = self.len();
+        let out = &mut out[..len];
+        let csrcs = &self.csrcs[..self.csrcs.len().min(15)];
+        out[0] = VERSION << 6 | u8::from(self.extension.is_some()) << 4 | csrcs.len() as u8;
+        out[1] = u8::from(self.marker) << 7 | self.payload_type & 0x7f;
+        out[2..4].copy_from_slice(&self.sequence.to_be_bytes());
+        out[4..8].copy_from_slice(&self.timestamp.to_be_bytes());
+        out[8..12].copy_from_slice(&self.ssrc.to_be_bytes());
+        let mut at = HEADER_LEN;
+        for csrc in csrcs {
+            out[at..at + 4].copy_from_slice(&csrc.to_be_bytes());
+            at += 4;
+        }
+        if let Some((profile, data)) = self.extension {
+            let words = data.len().div_ceil(4);
+            out[at..at + 2].copy_from_slice(&profile.to_be_bytes());
+            out[at + 2..at + 4].copy_from_slice(&(words as u16).to_be_bytes());
+            out[at + 4..at + 4 + data.len()].copy_from_slice(data);
+            out[at + 4 + data.len()..].fill(0);
+        }
+        len
+    }
+
+    /// Writes the header into the headroom of `packet`, in front of its payload
+    ///
+    /// # Panics
+    ///
+    /// Panics if the headroom is smaller than [`len`](Self::len).
+    pub fn prepend_to(&self, packet: &mut PacketBuf) {
+        self.write(packet.push(self.len()));
+    }
+
+    /// Appends the header to `out`
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.resize(start + self.len(), 0);
+        self.write(&mut out[start..]);
+    }
+}
+
+/// Widens a 16-bit sequence number to 64 bits, picking the value nearest `last`
+///
+/// Feed each result back as `last` to follow a stream across wraps.
+pub fn extend_seq(last: u64, seq: u16) -> u64 {
+    let candidate = (last & !0xffff) | u64::from(seq);
+    let delta = i64::from(seq.wrapping_sub(last as u16) as i16);
+    let extended = (last as i64).wrapping_add(delta);
+    if extended < 0 { candidate } else { extended as u64 }
+}
+
+/// Returns `true` if `buf` looks like RTCP rather than RTP on a shared port (RFC 5761)
+///
+/// RTCP packet types 192-223 fall where RTP payload types 64-95 would be,
+/// which RFC 5761 reserves for this purpose.
+pub fn is_rtcp(buf: &[u8]) -> bool {
+    buf.len() >= 2 && buf[0] >> 6 == VERSION && (192..=223).contains(&buf[1])
+}
+
+/// One packet from a compound RTCP packet
+#[derive(Clone, Copy, Debug)]
+pub struct RtcpPacket<'a> {
+    buf: &'a [u8],
+}
+
+/// Sender information from an RTCP sender report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SenderInfo {
+    /// NTP timestamp of the report, 32.32 fixed point
+    pub ntp_timestamp: u64,
+    /// RTP timestamp corresponding to `ntp_timestamp`
+    pub rtp_timestamp: u32,
+    /// Packets sent
+    pub packet_count: u32,
+    /// Payload bytes sent
+    pub octet_count: u32,
+}
+
+/// Reception statistics about one source, from an SR or RR
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReportBlock {
+    /// Source the block reports on
+    pub ssrc: u32,
+    /// Fraction of packets lost since the last report, out of 256
+    pub fraction_lost: u8,
+    /// Total packets lost, 24-bit signed
+    pub cumulative_lost: i32,
+    /// Highest sequence number received, extended with the wrap count
+    pub highest_seq: u32,
+    /// Interarrival jitter, in timestamp units
+    pub jitter: u32,
+    /// Middle 32 bits of the last SR's NTP timestamp
+    pub last_sr: u32,
+    /// Delay since the last SR, in units of 1/65536 s
+    pub delay_since_last_sr: u32,
+}
+
+impl ReportBlock {
+    const LEN: usize = 24;
+
+    fn read(b: &[u8]) -> Self {
+        let lost = be32(b, 4);
+        Self {
+            ssrc: be32(b, 0),
+            fraction_lost: (lost >> 24) as u8,
+            // Sign-extend the low 24 bits
+            cumulative_lost: ((lost << 8) as i32) >> 8,
+            highest_seq: be32(b, 8),
+            jitter: be32(b, 12),
+            last_sr: be32(b, 16),
+            delay_since_last_sr: be32(b, 20),
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let lost = u32::from(self.fraction_lost) << 24 | (self.cumulative_lost as u32 & 0x00ff_ffff);
+        for word in [self.ssrc, lost, self.highest_seq, self.jitter, self.last_sr, self.delay_since_last_sr] {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+    }
+}
+
+impl<'a> RtcpPacket<'a> {
+    /// Returns the packet type, e.g. [`RTCP_SR`]
+    pub fn packet_type(&self) -> u8 {
+        self.buf[1]
+    }
+
+    /// Returns the 5-bit count field: report blocks for SR and RR, sources for SDES and BYE
+    pub fn count(&self) -> u8 {
+        self.buf[0] & 0x1f
+    }
+
+    /// Returns the SSRC of the sender, for packet types that start with one
+    pub fn ssrc(&self) -> Option<u32> {
+        (self.buf.len() >= 8).then(|| be32(self.buf, 4))
+    }
+
+    /// Returns the whole packet, header included
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Returns the sender information of an SR
+    pub fn sender_info(&self) -> Option<SenderInfo> {
+        if self.packet_type() != RTCP_SR || self.buf.len() < 28 {
+            return None;
+        }
+        let b = self.buf;
+        Some(SenderInfo {
+            ntp_timestamp: u64::from(be32(b, 8)) << 32 | u64::from(be32(b, 12)),
+            rtp_timestamp: be32(b, 16),
+            packet_count: be32(b, 20),
+            octet_count: be32(b, 24),
+        })
+    }
+
+    /// Iterates the report blocks of an SR or RR; empty for other types
+    pub fn report_blocks(&self) -> impl Iterator<Item = ReportBlock> + 'a {
+        let start = match self.packet_type() {
+            RTCP_SR => 28,
+            RTCP_RR => 8,
+            _ => usize::MAX,
+        };
+        let blocks = self.buf.get(start..).unwrap_or(&[]);
+        blocks.chunks_exact(ReportBlock::LEN).take(usize::from(self.count())).map(ReportBlock::read)
+    }
+}
+
+/// Iterator over a compound RTCP packet, from [`rtcp_packets`]
+#[derive(Clone, Debug)]
+pub struct RtcpPackets<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for RtcpPackets<'a> {
+    type Item = io::Result<RtcpPacket<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let buf = self.rest;
+        if buf.len() < 4 || buf[0] >> 6 != VERSION {
+            self.rest = &[];
+            return Some(Err(invalid("RTCP header malformed")));
+        }
+        let len = 4 * (usize::from(be16(buf, 2)) + 1);
+        if len > buf.len() {
+            self.rest = &[];
+            return Some(Err(invalid("RTCP length runs past the datagram")));
+        }
+        self.rest = &buf[len..];
+        Some(Ok(RtcpPacket { buf: &buf[..len] }))
+    }
+}
+
+/// Iterates the packets of a compound RTCP datagram
+///
+/// A malformed packet yields one error and ends iteration.
+pub fn rtcp_packets(buf: &[u8]) -> RtcpPackets<'_> {
+    RtcpPackets { rest: buf }
+}
+
+/// Appends an RTCP receiver report from `ssrc` with up to 31 report blocks
+pub fn write_receiver_report(out: &mut Vec<u8>, ssrc: u32, blocks: &[ReportBlock]) {
+    let blocks = &blocks[..blocks.len().min(31)];
+    let words = 1 + blocks.len() * ReportBlock::LEN / 4;
+    out.push(VERSION << 6 | blocks.len() as u8);
+    out.push(RTCP_RR);
+    out.extend_from_slice(&(words as u16).to_be_bytes());
+    out.extend_from_slice(&ssrc.to_be_bytes());
+    for block in blocks {
+        block.write(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip_with_csrcs_and_extension() {
+        // One-byte elements: id 1 with 1 byte, id 3 with 2 bytes, then padding
+        let ext = [0x10, 0xaa, 0x31, 0xbb, 0xcc];
+        let header = RtpHeader {
+            marker: true,
+            payload_type: 96,
+            sequence: 65535,
+            timestamp: 0xdead_beef,
+            ssrc: 42,
+            csrcs: &[7, 8],
+            extension: Some((0xBEDE, &ext)),
+        };
+        let mut buf = Vec::new();
+        header.write_to(&mut buf);
+        assert_eq!(buf.len(), header.len());
+        buf.extend_from_slice(b"payload\0\0\x03");
+        buf[0] |= 0x20;
+
+        let pkt = RtpPacket::parse(&buf).unwrap();
+        assert!(pkt.marker());
+        assert_eq!((pkt.payload_type(), pkt.sequence(), pkt.timestamp(), pkt.ssrc()), (96, 65535, 0xdead_beef, 42));
+        assert_eq!(pkt.csrcs().collect::<Vec<_>>(), [7, 8]);
+        let elements: Vec<_> = pkt.extension().unwrap().elements().collect();
+        assert_eq!(elements, [(1, &[0xaa][..]), (3, &[0xbb, 0xcc][..])]);
+        assert_eq!(pkt.payload(), b"payload");
+
+        // Truncations and bad padding are errors, never panics
+        for len in 0..buf.len() - 3 {
+            assert!(RtpPacket::parse(&buf[..len]).is_err(), "length {}", len);
+        }
+        assert_eq!(extend_seq(65535, 0), 65536);
+        assert_eq!(extend_seq(65536, 65535), 65535);
+        assert_eq!(extend_seq(3, 65534), 65534);
+    }
+
+    #[test]
+    fn test_compound_rtcp_parse() {
+        let block = ReportBlock { ssrc: 9, fraction_lost: 25, cumulative_lost: -3, highest_seq: 70_000, jitter: 160, last_sr: 1, delay_since_last_sr: 2 };
+        let mut buf = Vec::new();
+        write_receiver_report(&mut buf, 0xabc, &[block]);
+        // An empty SDES follows the RR in a compound packet
+        buf.extend_from_slice(&[0x80, RTCP_SDES, 0, 0]);
+        assert!(is_rtcp(&buf));
+
+        let packets: Vec<RtcpPacket> = rtcp_packets(&buf).collect::<io::Result<_>>().unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!((packets[0].packet_type(), packets[0].ssrc()), (RTCP_RR, Some(0xabc)));
+        assert_eq!(packets[0].report_blocks().collect::<Vec<_>>(), [block]);
+        assert_eq!(packets[1].packet_type(), RTCP_SDES);
+        assert!(rtcp_packets(&buf[..buf.len() - 2]).any(|p| p.is_err()));
+    }
+}
@@ -0,0 +1,136 @@
+//! Packet sampling through a diagnostic SO_REUSEPORT socket
+//!
+//! Inspecting live traffic usually means a packet capture, which copies
+//! every packet, or instrumenting the receive loop, which slows every
+//! packet. A [`PacketSampler`] does neither: it binds a diagnostic "tap"
+//! socket into the primary socket's SO_REUSEPORT group and attaches
+//! [`ReuseportProgram::sample`], so the kernel delivers a random one in `N`
+//! datagrams to the tap and the rest to the primary socket as before. The
+//! primary receive path runs no extra code; a separate thread reads the
+//! tap at its own pace.
+//!
+//! Sampled datagrams are diverted, not copied: the primary socket never
+//! sees them. The tap reader records what it needs and then processes the
+//! datagram itself or hands it to the primary worker (for example over an
+//! [`spsc`](crate::spsc) ring), so sampling does not drop traffic.
+//!
+//! Dropping the sampler closes the tap. The program stays attached, but
+//! its index 1 no longer exists, so the kernel falls back to the primary
+//! socket for every datagram.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, sample::PacketSampler, udp::Udp};
+//!
+//! let cfg = NetConfig::default();
+//! let socket = Udp::bind("0.0.0.0:9000".parse()?, &cfg)?;
+//! let sampler = PacketSampler::attach(&socket, 1000, &cfg)?;
+//!
+//! std::thread::spawn(move || {
+//!     let mut buf = [0u8; 2048];
+//!     while let Ok((len, peer)) = sampler.tap().socket().recv_from(&mut buf) {
+//!         println!("sampled {} bytes from {}", len, peer);
+//!     }
+//! });
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Platform Support
+//!
+//! Linux 4.5+. The primary socket must be bound with
+//! [`NetConfig::reuse_port`] and be the only socket on its address, so
+//! that it holds index 0 of the group. Elsewhere [`PacketSampler::attach`]
+//! returns `Unsupported`.
+
+use crate::config::NetConfig;
+use crate::raw as r;
+use crate::shard::ReuseportProgram;
+use crate::udp::Udp;
+use std::io;
+
+/// Diagnostic socket receiving a random sample of another socket's datagrams
+#[derive(Debug)]
+pub struct PacketSampler {
+    tap: Udp,
+    one_in: u32,
+}
+
+impl PacketSampler {
+    /// Diverts a random one in `one_in` of `primary`'s datagrams to a new tap socket
+    ///
+    /// The tap is bound to `primary`'s address with `cfg`, with
+    /// `reuse_port` forced on. Replaces any reuseport program attached to
+    /// the group earlier.
+    ///
+    /// # Errors
+    /// - `one_in` is 0 (`InvalidInput`)
+    /// - `primary` was bound without SO_REUSEPORT (`AddrInUse`, from the tap's bind)
+    /// - The platform is not Linux (`Unsupported`)
+    pub fn attach(primary: &Udp, one_in: u32, cfg: &NetConfig) -> io::Result<Self> {
+        if one_in == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "sampling rate must be at least one in one"));
+        }
+        if !cfg!(target_os = "linux") {
+            return Err(crate::error::Error::unsupported("SO_ATTACH_REUSEPORT_CBPF"));
+        }
+        let cfg = NetConfig { reuse_port: true, ..cfg.clone() };
+        let tap = Udp::bind(primary.local_addr()?, &cfg)?;
+        r::attach_reuseport_cbpf(r::os_handle(primary.socket()), ReuseportProgram::sample(one_in).insns())?;
+        Ok(Self { tap, one_in })
+    }
+
+    /// Returns the socket receiving sampled datagrams
+    pub fn tap(&self) -> &Udp {
+        &self.tap
+    }
+
+    /// Returns `N`, where one in `N` datagrams is sampled
+    pub fn one_in(&self) -> u32 {
+        self.one_in
+    }
+
+    /// Takes the tap socket; sampling continues until it is closed
+    pub fn into_tap(self) -> Udp {
+        self.tap
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    fn drain(socket: &Udp) -> usize {
+        let mut bufs = vec![vec![0u8; 64]; 64];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 64];
+        socket.recv_batch(&mut bufs, &mut addrs).unwrap_or(0)
+    }
+
+    #[test]
+    fn test_sampled_datagrams_reach_tap_until_dropped() {
+        let cfg = NetConfig::default();
+        let primary = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let dest = primary.local_addr().unwrap();
+        assert_eq!(PacketSampler::attach(&primary, 0, &cfg).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // One in one: everything goes to the tap
+        let sampler = PacketSampler::attach(&primary, 1, &cfg).unwrap();
+        for _ in 0..5 {
+            tx.send_to(b"sample", dest).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(drain(sampler.tap()), 5);
+        assert_eq!(drain(&primary), 0);
+
+        // Without the tap, the primary socket gets everything again
+        drop(sampler);
+        for _ in 0..5 {
+            tx.send_to(b"direct", dest).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(drain(&primary), 5);
+    }
+}
@@ -0,0 +1,291 @@
+//! Per-destination send coalescing for chatty datagram protocols
+//!
+//! Protocols that emit many small messages (input acks, state deltas,
+//! heartbeats) pay one system call and one set of IP/UDP headers per
+//! message if each is sent on its own. [`SendQueue`] packs messages for the
+//! same destination into shared datagrams up to a size limit, and sends the
+//! queued datagrams for all destinations with one `send_batch` call
+//! (`sendmmsg` on Linux).
+//!
+//! A flush happens when:
+//!
+//! - the queued bytes reach the [`flush_bytes`](SendQueue::flush_bytes) threshold
+//!   during [`push`](SendQueue::push),
+//! - the oldest queued message is older than [`max_delay`](SendQueue::max_delay)
+//!   when [`flush_if_due`](SendQueue::flush_if_due) is called from a timer, or
+//! - the application calls [`flush`](SendQueue::flush), e.g. at the end of a tick.
+//!
+//! With [`Framing::LengthPrefix`], each message carries a 2-byte big-endian
+//! length so the receiver can split datagrams again with [`Framing::split`];
+//! `Framing` lives in `horizon_sockets_core`, so no_std peers split with the
+//! same code.
+//! With [`Framing::None`], messages are concatenated as-is for protocols
+//! that are self-delimiting.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::send_queue::{Framing, SendQueue};
+//! use std::net::SocketAddr;
+//!
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+//! let mut queue = SendQueue::new(1200, Framing::LengthPrefix);
+//! let players: Vec<SocketAddr> = vec!["10.0.0.2:7000".parse()?, "10.0.0.3:7000".parse()?];
+//!
+//! for player in &players {
+//!     queue.push(&socket, *player, b"ack 17")?;
+//!     queue.push(&socket, *player, b"delta ...")?;
+//! }
+//! // One sendmmsg call, one datagram per player
+//! queue.flush(&socket)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::Error;
+use crate::transport::DatagramSocket;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+pub use horizon_sockets_core::framing::Framing;
+
+/// Coalesces small messages into datagrams per destination
+#[derive(Debug)]
+pub struct SendQueue {
+    max_datagram: usize,
+    framing: Framing,
+    flush_bytes: usize,
+    max_delay: Option<Duration>,
+    /// Datagrams waiting to be sent, in the order they were started
+    pending: Vec<(SocketAddr, Vec<u8>)>,
+    /// Datagram in `pending` still accepting messages, per destination
+    open: HashMap<SocketAddr, usize>,
+    pending_bytes: usize,
+    /// When the oldest queued message was pushed
+    oldest: Option<Instant>,
+    /// Emptied datagram buffers kept for reuse
+    spare: Vec<Vec<u8>>,
+}
+
+impl SendQueue {
+    /// Creates a queue that builds datagrams of at most `max_datagram` bytes
+    ///
+    /// 1200 bytes is safe on virtually every path; 1472 fits a 1500-byte
+    /// Ethernet MTU over IPv4. The flush threshold defaults to 64 datagrams'
+    /// worth of bytes.
+    pub fn new(max_datagram: usize, framing: Framing) -> Self {
+        Self {
+            max_datagram,
+            framing,
+            flush_bytes: max_datagram.saturating_mul(64),
+            max_delay: None,
+            pending: Vec::new(),
+            open: HashMap::new(),
+            pending_bytes: 0,
+            oldest: None,
+            spare: Vec::new(),
+        }
+    }
+
+    /// Flushes automatically from `push` once `bytes` are queued
+    pub fn flush_bytes(mut self, bytes: usize) -> Self {
+        self.flush_bytes = bytes;
+        self
+    }
+
+    /// Sets the longest a message may wait before `flush_if_due` sends it
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = Some(delay);
+        self
+    }
+
+    /// Queues `msg` for `dest`, flushing through `sock` if the threshold is reached
+    ///
+    /// # Errors
+    /// - `msg` plus framing does not fit in one datagram (`InvalidInput`)
+    /// - The flush fails with a hard error
+    pub fn push<S: DatagramSocket + ?Sized>(&mut self, sock: &S, dest: SocketAddr, msg: &[u8]) -> io::Result<()> {
+        let framed = msg.len() + self.framing.overhead();
+        if framed > self.max_datagram || (self.framing == Framing::LengthPrefix && msg.len() > u16::MAX as usize) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Message of {} bytes does not fit in a {} byte datagram", msg.len(), self.max_datagram),
+            ));
+        }
+
+        let idx = match self.open.get(&dest) {
+            Some(&i) if self.pending[i].1.len() + framed <= self.max_datagram => i,
+            _ => {
+                let buf = self.spare.pop().unwrap_or_else(|| Vec::with_capacity(self.max_datagram));
+                self.pending.push((dest, buf));
+                self.open.insert(dest, self.pending.len() - 1);
+                self.pending.len() - 1
+            }
+        };
+        let buf = &mut self.pending[idx].1;
+        if self.framing == Framing::LengthPrefix {
+            buf.extend_from_slice(&(msg.len() as u16).to_be_bytes());
+        }
+        buf.extend_from_slice(msg);
+        self.pending_bytes += framed;
+        self.oldest.get_or_insert_with(Instant::now);
+
+        if self.pending_bytes >= self.flush_bytes {
+            self.flush(sock)?;
+        }
+        Ok(())
+    }
+
+    /// Sends all queued datagrams with one `send_batch` call
+    ///
+    /// Datagrams the socket could not take (`WouldBlock`) stay queued for
+    /// the next flush. A datagram rejected with a hard error (for example an
+    /// unreachable destination) is dropped and the error returned; datagrams
+    /// sent before it are counted in
+    /// [`Error::PartialBatch`](crate::Error::PartialBatch).
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams sent
+    pub fn flush<S: DatagramSocket + ?Sized>(&mut self, sock: &S) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        let result = {
+            let packets: Vec<(&[u8], SocketAddr)> = self.pending.iter().map(|(a, b)| (b.as_slice(), *a)).collect();
+            sock.send_batch(&packets)
+        };
+        // Remaining datagrams stop accepting messages so ordering per destination is kept
+        self.open.clear();
+        let sent = match result {
+            Ok(n) => n,
+            Err(e) => {
+                // Retire what went out, plus the datagram that failed hard so it cannot wedge the queue
+                let sent = match Error::from_io(&e) {
+                    Some(Error::PartialBatch { sent, .. }) => *sent,
+                    _ => 0,
+                };
+                self.retire(sent + 1);
+                return Err(e);
+            }
+        };
+        self.retire(sent);
+        Ok(sent)
+    }
+
+    /// Flushes if the oldest queued message has waited at least `max_delay`
+    ///
+    /// Call from a timer or once per event loop iteration; see
+    /// [`next_deadline`](Self::next_deadline) for when to wake up.
+    pub fn flush_if_due<S: DatagramSocket + ?Sized>(&mut self, sock: &S, now: Instant) -> io::Result<usize> {
+        match self.next_deadline() {
+            Some(deadline) if now >= deadline => self.flush(sock),
+            _ => Ok(0),
+        }
+    }
+
+    /// Returns when the queued messages must be flushed, if a `max_delay` is set
+    pub fn next_deadline(&self) -> Option<Instant> {
+        Some(self.oldest? + self.max_delay?)
+    }
+
+    /// Returns the number of queued datagrams
+    pub fn pending_datagrams(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of queued bytes, including framing
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
+    /// Removes the first `n` queued datagrams
+    fn retire(&mut self, n: usize) {
+        let n = n.min(self.pending.len());
+        for (_, mut buf) in self.pending.drain(..n) {
+            self.pending_bytes -= buf.len();
+            buf.clear();
+            self.spare.push(buf);
+        }
+        if self.pending.is_empty() {
+            self.pending_bytes = 0;
+            self.oldest = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memnet::MemNetwork;
+
+    #[test]
+    fn test_coalesces_per_destination() {
+        let net = MemNetwork::new();
+        let tx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let a = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let b = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut queue = SendQueue::new(16, Framing::LengthPrefix).flush_bytes(1000);
+        for msg in [&b"one"[..], b"two", b"three"] {
+            queue.push(&tx, a.local_addr(), msg).unwrap();
+        }
+        queue.push(&tx, b.local_addr(), b"hi").unwrap();
+        // "one" + "two" + "three" with prefixes is 17 bytes, so "three" starts a new datagram
+        assert_eq!(queue.pending_datagrams(), 3);
+        assert!(queue.push(&tx, a.local_addr(), &[0; 15]).is_err());
+
+        assert_eq!(queue.flush(&tx).unwrap(), 3);
+        assert_eq!(queue.pending_bytes(), 0);
+
+        let mut bufs = vec![vec![0u8; 64]; 4];
+        let mut addrs = vec![tx.local_addr(); 4];
+        assert_eq!(a.recv_batch(&mut bufs, &mut addrs).unwrap(), 2);
+        let msgs: Vec<&[u8]> = bufs[..2].iter().flat_map(|d| Framing::LengthPrefix.split(d)).collect();
+        assert_eq!(msgs, vec![&b"one"[..], b"two", b"three"]);
+        assert_eq!(b.recv_batch(&mut bufs, &mut addrs).unwrap(), 1);
+        assert_eq!(bufs[0], b"\0\x02hi");
+    }
+
+    #[test]
+    fn test_threshold_and_deadline() {
+        let net = MemNetwork::new();
+        let tx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let rx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut queue = SendQueue::new(100, Framing::None).flush_bytes(10).max_delay(Duration::from_millis(5));
+        assert_eq!(queue.next_deadline(), None);
+        queue.push(&tx, rx.local_addr(), b"abcd").unwrap();
+        let deadline = queue.next_deadline().unwrap();
+        assert_eq!(queue.flush_if_due(&tx, deadline - Duration::from_millis(1)).unwrap(), 0);
+        assert_eq!(queue.flush_if_due(&tx, deadline).unwrap(), 1);
+
+        queue.push(&tx, rx.local_addr(), b"abcdef").unwrap();
+        queue.push(&tx, rx.local_addr(), b"ghij").unwrap();
+        // Reaching 10 bytes flushed from push
+        assert_eq!(queue.pending_datagrams(), 0);
+        assert_eq!(Framing::None.split(b"xyz").collect::<Vec<_>>(), vec![&b"xyz"[..]]);
+    }
+
+    #[test]
+    fn test_flush_retires_sent_prefix_and_failed_datagram() {
+        let config = crate::NetConfig { ipv6_only: None, ..Default::default() };
+        let tx = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let rx = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let ok = rx.local_addr().unwrap();
+        let bad: SocketAddr = "[::1]:9".parse().unwrap(); // wrong family for an IPv4 socket
+
+        let mut queue = SendQueue::new(100, Framing::None);
+        queue.push(&tx, ok, b"one").unwrap();
+        queue.push(&tx, bad, b"two").unwrap();
+        queue.push(&tx, "127.0.0.1:9".parse().unwrap(), b"three").unwrap();
+        let err = queue.flush(&tx).unwrap_err();
+        assert!(matches!(Error::from_io(&err), Some(Error::PartialBatch { sent: 1, .. })), "{err:?}");
+        // "one" went out and "two" was dropped; only "three" is left
+        assert_eq!(queue.pending_datagrams(), 1);
+        assert_eq!(queue.pending_bytes(), 5);
+        assert_eq!(queue.flush(&tx).unwrap(), 1);
+    }
+}
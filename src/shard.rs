@@ -0,0 +1,421 @@
+//! SO_REUSEPORT shard groups with programmable socket selection
+//!
+//! A [`ShardGroup`] binds one socket per worker thread to the same address
+//! with SO_REUSEPORT. By default the kernel picks a group member with its
+//! own flow hash, which depends on how many sockets are in the group and in
+//! which order they joined, and is not visible to the application.
+//!
+//! Attaching a [`ReuseportProgram`] makes the choice explicit:
+//!
+//! - [`ReuseportProgram::hash_4tuple`]: hash of the address/port 4-tuple
+//!   modulo the shard count. The same flow always lands on the same shard,
+//!   and [`shard_for_flow`] computes the shard in userspace, so a worker can
+//!   hand off or rebuild per-flow state deterministically when resharding.
+//! - [`ReuseportProgram::incoming_cpu`]: the CPU that processed the packet
+//!   modulo the shard count. With one pinned worker per CPU (see
+//!   [`affinity`](crate::affinity)), packets never cross cores.
+//!
+//! Custom logic can be attached as a classic BPF program built from
+//! [`BpfInsn`]s, or as an already loaded eBPF program with
+//! [`ShardGroup::attach_reuseport_ebpf`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::NetConfig;
+//! use horizon_sockets::shard::{ReuseportProgram, ShardGroup};
+//!
+//! let group = ShardGroup::udp("0.0.0.0:9000".parse()?, 4, &NetConfig::default())?;
+//! group.attach_reuseport_cbpf(&ReuseportProgram::hash_4tuple(4))?;
+//!
+//! for (shard, socket) in group.into_sockets().into_iter().enumerate() {
+//!     std::thread::spawn(move || {
+//!         let _ = horizon_sockets::affinity::pin_to_cpu(shard);
+//!         let mut buf = [0u8; 2048];
+//!         while let Ok((len, peer)) = socket.socket().recv_from(&mut buf) {
+//!             println!("shard {} got {} bytes from {}", shard, len, peer);
+//!         }
+//!     });
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Caveats
+//!
+//! Programs return an index into the group in join order. When a member is
+//! closed the kernel moves the last member into its slot, so close and
+//! recreate the whole group when resharding. Indices outside the group fall
+//! back to the kernel's default selection.
+//!
+//! # Platform Support
+//!
+//! Linux 4.5+ (4.6+ for TCP). Elsewhere the attach methods return `Unsupported`.
+
+use crate::config::NetConfig;
+use crate::poll::Pollable;
+use crate::raw::{self as r, BpfInsn};
+use crate::tcp::TcpListener;
+use crate::udp::Udp;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+/// Multiplier used to spread the XOR-folded 4-tuple before the modulo
+const HASH_MUL: u32 = 0x9E37_79B1;
+
+/// A set of sockets bound to one address with SO_REUSEPORT
+///
+/// Shard `i` is the `i`th socket to join the kernel's reuseport group,
+/// which is the index a [`ReuseportProgram`] returns to select it.
+#[derive(Debug)]
+pub struct ShardGroup<S> {
+    sockets: Vec<S>,
+    local: SocketAddr,
+}
+
+impl ShardGroup<Udp> {
+    /// Binds `shards` UDP sockets to `addr`
+    ///
+    /// `cfg.reuse_port` is forced on. With port 0 the first socket picks a
+    /// port and the others bind to the same one.
+    ///
+    /// Fails with `Unsupported` for more than one shard on iOS and macOS.
+    pub fn udp(addr: SocketAddr, shards: usize, cfg: &NetConfig) -> io::Result<Self> {
+        Self::bind_with(addr, shards, cfg, Udp::bind, Udp::local_addr)
+    }
+}
+
+impl ShardGroup<TcpListener> {
+    /// Binds `shards` TCP listeners to `addr`
+    ///
+    /// `cfg.reuse_port` is forced on. With port 0 the first listener picks a
+    /// port and the others bind to the same one.
+    ///
+    /// Fails with `Unsupported` for more than one shard on iOS and macOS.
+    pub fn tcp(addr: SocketAddr, shards: usize, cfg: &NetConfig) -> io::Result<Self> {
+        Self::bind_with(addr, shards, cfg, TcpListener::bind, TcpListener::local_addr)
+    }
+}
+
+impl<S> ShardGroup<S> {
+    fn bind_with(
+        addr: SocketAddr,
+        shards: usize,
+        cfg: &NetConfig,
+        bind: fn(SocketAddr, &NetConfig) -> io::Result<S>,
+        local_addr: fn(&S) -> io::Result<SocketAddr>,
+    ) -> io::Result<Self> {
+        if shards == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Shard group needs at least one socket"));
+        }
+        if cfg!(any(target_os = "macos", target_os = "ios")) && shards > 1 {
+            // Apple's SO_REUSEPORT hands every datagram to the last socket bound
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "SO_REUSEPORT does not balance load on Apple platforms"));
+        }
+        let cfg = NetConfig { reuse_port: true, ..cfg.clone() };
+        let first = bind(addr, &cfg)?;
+        let local = local_addr(&first)?;
+        let mut sockets = Vec::with_capacity(shards);
+        sockets.push(first);
+        for _ in 1..shards {
+            sockets.push(bind(SocketAddr::new(addr.ip(), local.port()), &cfg)?);
+        }
+        Ok(Self { sockets, local })
+    }
+
+    /// Returns the number of shards
+    pub fn len(&self) -> usize {
+        self.sockets.len()
+    }
+
+    /// Returns `true` if the group has no sockets
+    pub fn is_empty(&self) -> bool {
+        self.sockets.is_empty()
+    }
+
+    /// Returns the address every shard is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    /// Returns the sockets in shard order
+    pub fn sockets(&self) -> &[S] {
+        &self.sockets
+    }
+
+    /// Takes the sockets, typically to move one into each worker thread
+    pub fn into_sockets(self) -> Vec<S> {
+        self.sockets
+    }
+}
+
+impl<S: Pollable> ShardGroup<S> {
+    /// Attaches a classic BPF program that selects the shard for each packet or connection
+    ///
+    /// Replaces any program attached earlier.
+    ///
+    /// # Errors
+    /// - The program was generated for a different shard count (`InvalidInput`)
+    /// - The kernel rejects the program, or the platform is not Linux (`Unsupported`)
+    pub fn attach_reuseport_cbpf(&self, prog: &ReuseportProgram) -> io::Result<()> {
+        if let Some(shards) = prog.shards {
+            if shards as usize != self.sockets.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Program targets {} shards but the group has {}", shards, self.sockets.len()),
+                ));
+            }
+        }
+        r::attach_reuseport_cbpf(self.sockets[0].poll_handle(), &prog.insns)
+    }
+
+    /// Attaches a loaded eBPF program that selects the shard
+    ///
+    /// `prog` is a `BPF_PROG_TYPE_SOCKET_FILTER` program loaded with
+    /// `bpf(BPF_PROG_LOAD)` (for example with libbpf or aya) whose return
+    /// value is the shard index. The group keeps its own reference, so the
+    /// program fd may be closed afterwards.
+    #[cfg(unix)]
+    pub fn attach_reuseport_ebpf(&self, prog: &impl std::os::unix::io::AsRawFd) -> io::Result<()> {
+        r::attach_reuseport_ebpf(self.sockets[0].poll_handle(), prog.as_raw_fd())
+    }
+}
+
+/// A classic BPF program for SO_ATTACH_REUSEPORT_CBPF
+///
+/// The program runs with the packet data positioned after the transport
+/// header; the IP header is reachable through `SKF_NET_OFF` and CPU/hash
+/// metadata through `SKF_AD_OFF` loads. It returns the shard index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReuseportProgram {
+    insns: Vec<BpfInsn>,
+    shards: Option<u32>,
+}
+
+impl ReuseportProgram {
+    /// Steers each flow by a hash of its 4-tuple modulo `shards`
+    ///
+    /// Handles IPv4 (including options) and IPv6 without extension headers.
+    /// [`shard_for_flow`] returns the same index in userspace.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn hash_4tuple(shards: u32) -> Self {
+        assert!(shards > 0, "at least one shard is required");
+        let net = |off: u32| (SKF_NET_OFF + off as i32) as u32;
+        let mut p = Vec::with_capacity(32);
+
+        // Branch on the IP version nibble
+        p.push(stmt(LD | B | ABS, net(0)));
+        p.push(stmt(ALU | RSH | K, 4));
+        p.push(jump(JMP | JEQ | K, 6, 0, 0)); // jt patched below
+        let branch = p.len() - 1;
+
+        // IPv4: ports after the variable-length header, then both addresses
+        p.push(stmt(LDX | B | MSH, net(0)));
+        p.push(stmt(LD | W | IND, net(0)));
+        p.push(stmt(MISC | TAX, 0));
+        for off in [12, 16] {
+            p.push(stmt(LD | W | ABS, net(off)));
+            p.push(stmt(ALU | XOR | X, 0));
+            p.push(stmt(MISC | TAX, 0));
+        }
+        p.push(stmt(JMP | JA, 0)); // k patched below
+        let skip_v6 = p.len() - 1;
+
+        // IPv6: ports after the fixed 40-byte header, then eight address words
+        let v6 = p.len();
+        p.push(stmt(LD | W | ABS, net(40)));
+        p.push(stmt(MISC | TAX, 0));
+        for off in (8..40).step_by(4) {
+            p.push(stmt(LD | W | ABS, net(off)));
+            p.push(stmt(ALU | XOR | X, 0));
+            p.push(stmt(MISC | TAX, 0));
+        }
+
+        // Mix, then reduce to a shard index (A == X == folded 4-tuple here)
+        let mix = p.len();
+        p.push(stmt(ALU | MUL | K, HASH_MUL));
+        p.push(stmt(MISC | TAX, 0));
+        p.push(stmt(ALU | RSH | K, 16));
+        p.push(stmt(ALU | XOR | X, 0));
+        p.push(stmt(ALU | MOD | K, shards));
+        p.push(stmt(RET | A, 0));
+
+        p[branch].jt = (v6 - branch - 1) as u8;
+        p[skip_v6].k = (mix - skip_v6 - 1) as u32;
+        Self { insns: p, shards: Some(shards) }
+    }
+
+    /// Steers each packet by the CPU that received it, modulo `shards`
+    ///
+    /// With `shards` equal to the number of RX CPUs and worker `i` pinned
+    /// to CPU `i`, each packet is handled on the core that received it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn incoming_cpu(shards: u32) -> Self {
+        assert!(shards > 0, "at least one shard is required");
+        let insns = vec![
+            stmt(LD | W | ABS, (SKF_AD_OFF + SKF_AD_CPU) as u32),
+            stmt(ALU | MOD | K, shards),
+            stmt(RET | A, 0),
+        ];
+        Self { insns, shards: Some(shards) }
+    }
+
+    /// Sends a random one in `one_in` packets to shard 1, the rest to shard 0
+    ///
+    /// Used by [`PacketSampler`](crate::sample::PacketSampler) to divert a
+    /// sample of traffic to a diagnostic socket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `one_in` is 0.
+    pub fn sample(one_in: u32) -> Self {
+        assert!(one_in > 0, "sampling rate must be at least one in one");
+        let insns = vec![
+            stmt(LD | W | ABS, (SKF_AD_OFF + SKF_AD_RANDOM) as u32),
+            stmt(ALU | MOD | K, one_in),
+            jump(JMP | JEQ | K, 0, 0, 1),
+            stmt(RET | K, 1),
+            stmt(RET | K, 0),
+        ];
+        Self { insns, shards: Some(2) }
+    }
+
+    /// Wraps a hand-written program whose return value is the shard index
+    pub fn from_insns(insns: Vec<BpfInsn>) -> Self {
+        Self { insns, shards: None }
+    }
+
+    /// Returns the program's instructions
+    pub fn insns(&self) -> &[BpfInsn] {
+        &self.insns
+    }
+}
+
+/// Returns the shard [`ReuseportProgram::hash_4tuple`] selects for a flow
+///
+/// `peer` is the remote address and `local` the address the packet was sent
+/// to (not the wildcard the group is bound to). IPv4-mapped IPv6 addresses
+/// are treated as IPv4, matching the headers of packets on dual-stack sockets.
+pub fn shard_for_flow(peer: SocketAddr, local: SocketAddr, shards: u32) -> u32 {
+    assert!(shards > 0, "at least one shard is required");
+    let (peer, local) = (r::unmap_v4(peer), r::unmap_v4(local));
+    let mut h = (u32::from(peer.port()) << 16) | u32::from(local.port());
+    for ip in [peer.ip(), local.ip()] {
+        match ip {
+            IpAddr::V4(v4) => h ^= u32::from(v4),
+            IpAddr::V6(v6) => {
+                for word in v6.octets().chunks_exact(4) {
+                    h ^= u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+                }
+            }
+        }
+    }
+    let h = h.wrapping_mul(HASH_MUL);
+    ((h >> 16) ^ h) % shards
+}
+
+// Classic BPF opcode fields (linux/bpf_common.h)
+const LD: u16 = 0x00;
+const LDX: u16 = 0x01;
+const ALU: u16 = 0x04;
+const JMP: u16 = 0x05;
+const RET: u16 = 0x06;
+const MISC: u16 = 0x07;
+const W: u16 = 0x00;
+const B: u16 = 0x10;
+const ABS: u16 = 0x20;
+const IND: u16 = 0x40;
+const MSH: u16 = 0xa0;
+const MUL: u16 = 0x20;
+const RSH: u16 = 0x70;
+const MOD: u16 = 0x90;
+const XOR: u16 = 0xa0;
+const JA: u16 = 0x00;
+const JEQ: u16 = 0x10;
+const K: u16 = 0x00;
+const X: u16 = 0x08;
+const A: u16 = 0x10;
+const TAX: u16 = 0x00;
+
+/// Base offset of ancillary data loads (`SKF_AD_OFF`)
+const SKF_AD_OFF: i32 = -0x1000;
+/// Ancillary load returning the current CPU (`SKF_AD_CPU`)
+const SKF_AD_CPU: i32 = 36;
+/// Ancillary load returning a pseudo-random `u32` (`SKF_AD_RANDOM`)
+const SKF_AD_RANDOM: i32 = 56;
+
+/// Base offset of loads relative to the network header (`SKF_NET_OFF`)
+const SKF_NET_OFF: i32 = -0x100000;
+
+fn stmt(code: u16, k: u32) -> BpfInsn {
+    BpfInsn { code, jt: 0, jf: 0, k }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> BpfInsn {
+    BpfInsn { code, jt, jf, k }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_programs() {
+        let prog = ReuseportProgram::hash_4tuple(4);
+        let insns = prog.insns();
+        assert_eq!(insns.last(), Some(&stmt(RET | A, 0)));
+        // The version check jumps to the IPv6 branch, the IPv4 branch skips it
+        assert_eq!(insns[2].code, JMP | JEQ | K);
+        assert_eq!(insns[3 + insns[2].jt as usize], stmt(LD | W | ABS, (SKF_NET_OFF + 40) as u32));
+
+        let cpu = ReuseportProgram::incoming_cpu(8);
+        assert_eq!(cpu.insns()[1], stmt(ALU | MOD | K, 8));
+
+        let sample = ReuseportProgram::sample(100);
+        assert_eq!(sample.insns()[3 + sample.insns()[2].jf as usize], stmt(RET | K, 0));
+
+        let a: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:9000".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:10.0.0.1]:5000".parse().unwrap();
+        assert_eq!(shard_for_flow(a, b, 16), shard_for_flow(mapped, b, 16));
+        assert!(shard_for_flow(a, b, 3) < 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_hash_4tuple_is_sticky_and_predictable() {
+        use std::time::Duration;
+
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let group = ShardGroup::udp("127.0.0.1:0".parse().unwrap(), 4, &cfg).unwrap();
+        group.attach_reuseport_cbpf(&ReuseportProgram::hash_4tuple(4)).unwrap();
+        assert!(group.attach_reuseport_cbpf(&ReuseportProgram::hash_4tuple(3)).is_err());
+        let local = group.local_addr();
+
+        let clients: Vec<_> = (0..8).map(|_| std::net::UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+        for client in &clients {
+            for _ in 0..3 {
+                client.send_to(b"ping", local).unwrap();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+
+        let mut received = 0;
+        let mut buf = [0u8; 16];
+        for (shard, socket) in group.sockets().iter().enumerate() {
+            while let Ok((_, peer)) = socket.socket().recv_from(&mut buf) {
+                assert_eq!(shard_for_flow(peer, local, 4) as usize, shard, "{}", peer);
+                received += 1;
+            }
+        }
+        assert_eq!(received, 24);
+
+        let tcp = ShardGroup::tcp("127.0.0.1:0".parse().unwrap(), 2, &cfg).unwrap();
+        tcp.attach_reuseport_cbpf(&ReuseportProgram::incoming_cpu(2)).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! Cross-thread wakeups for event loops
+//!
+//! A worker blocked in `epoll_wait` (or any poll) needs a file descriptor
+//! to become readable before it can notice work queued by another thread.
+//! [`Notifier`] is that descriptor: [`notify`](Notifier::notify) from any
+//! thread makes it readable, and the loop calls [`drain`](Notifier::drain)
+//! when it wakes.
+//!
+//! On Linux it is an `eventfd`. Elsewhere it is a connected loopback UDP
+//! [`socket_pair`](crate::udp::socket_pair), which works with kqueue and
+//! `WSAPoll` alike. Either way it can be passed to
+//! [`poll::wait`](crate::poll::wait), and on Unix it registers with the
+//! [`Runtime`](crate::Runtime) like a socket.
+//!
+//! Notifications coalesce: many `notify` calls before a `drain` produce
+//! one wakeup.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::signal::Notifier;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let notifier = Arc::new(Notifier::new()?);
+//! let remote = notifier.clone();
+//! std::thread::spawn(move || remote.notify());
+//!
+//! // Blocks until the other thread calls notify
+//! assert!(notifier.wait(Some(Duration::from_secs(5)))?);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::poll::{self, Interest, Pollable};
+use crate::raw::OsSocket;
+use std::io;
+use std::time::Duration;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Wakeup handle readable after [`notify`](Self::notify), shareable across threads
+#[derive(Debug)]
+pub struct Notifier {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fd: OwnedFd,
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    rx: crate::udp::Udp,
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    tx: crate::udp::Udp,
+}
+
+impl Notifier {
+    /// Creates a nonblocking notifier
+    pub fn new() -> io::Result<Self> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SAFETY: eventfd returned a new descriptor that nothing else owns
+                Ok(Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+            } else {
+                let cfg = crate::NetConfig { recv_buf: None, send_buf: None, ..Default::default() };
+                let (rx, tx) = crate::udp::socket_pair(&cfg)?;
+                Ok(Self { rx, tx })
+            }
+        }
+    }
+
+    /// Makes the notifier readable, waking a thread waiting on it
+    ///
+    /// Never blocks. A notification already pending absorbs this one.
+    pub fn notify(&self) -> io::Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let one = 1u64.to_ne_bytes();
+                let rc = unsafe { libc::write(self.fd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    // The counter is saturated, so a wakeup is pending anyway
+                    if err.kind() != io::ErrorKind::WouldBlock {
+                        return Err(err);
+                    }
+                }
+                Ok(())
+            } else {
+                match self.tx.socket().send(&[1]) {
+                    Ok(_) => Ok(()),
+                    // The socket buffer is full of pending wakeups
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Consumes pending notifications so the notifier is no longer readable
+    ///
+    /// # Returns
+    ///
+    /// The number of notifications consumed (a lower bound on non-Linux
+    /// platforms once the socket buffer has filled); `0` if none were pending
+    pub fn drain(&self) -> io::Result<u64> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let mut count = [0u8; 8];
+                let rc = unsafe { libc::read(self.fd.as_raw_fd(), count.as_mut_ptr().cast(), count.len()) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    return if err.kind() == io::ErrorKind::WouldBlock { Ok(0) } else { Err(err) };
+                }
+                Ok(u64::from_ne_bytes(count))
+            } else {
+                let mut count = 0;
+                let mut buf = [0u8; 16];
+                loop {
+                    match self.rx.socket().recv(&mut buf) {
+                        Ok(_) => count += 1,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(count),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blocks until notified or `timeout` passes, then drains
+    ///
+    /// Returns `true` if a notification was received. For threads without
+    /// an event loop; event loops should register the notifier instead.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let ready = poll::wait(&[self], Interest::READABLE, timeout)?;
+        if !ready[0].is_ready() {
+            return Ok(false);
+        }
+        Ok(self.drain()? > 0)
+    }
+}
+
+impl Pollable for Notifier {
+    fn poll_handle(&self) -> OsSocket {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                self.fd.as_raw_fd()
+            } else {
+                crate::raw::os_handle(self.rx.socket())
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for Notifier {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.poll_handle()
+    }
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for Notifier {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.poll_handle()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_notifications_coalesce() {
+        let notifier = Notifier::new().unwrap();
+        assert_eq!(notifier.drain().unwrap(), 0);
+        assert!(!notifier.wait(Some(Duration::from_millis(1))).unwrap());
+
+        notifier.notify().unwrap();
+        notifier.notify().unwrap();
+        let ready = poll::wait(&[&notifier], Interest::READABLE, Some(Duration::from_secs(1))).unwrap();
+        assert!(ready[0].readable);
+        assert!(notifier.drain().unwrap() >= 1);
+        assert_eq!(notifier.drain().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_cross_thread_wakeup_and_socket_pair() {
+        let notifier = Arc::new(Notifier::new().unwrap());
+        let remote = notifier.clone();
+        let handle = std::thread::spawn(move || remote.notify().unwrap());
+        assert!(notifier.wait(Some(Duration::from_secs(5))).unwrap());
+        handle.join().unwrap();
+
+        let (a, b) = crate::udp::socket_pair(&crate::NetConfig::default()).unwrap();
+        a.socket().send(b"hi").unwrap();
+        poll::wait(&[&b], Interest::READABLE, Some(Duration::from_secs(1))).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(b.socket().recv(&mut buf).unwrap(), 2);
+        assert_eq!(b.peer_addr().unwrap(), a.local_addr().unwrap());
+    }
+}
@@ -0,0 +1,705 @@
+//! Fault injection and network condition simulation
+//!
+//! This module wraps the crate's sockets with a deterministic impairment layer
+//! so protocol logic can be exercised against loss, duplication, reordering,
+//! latency, jitter, and bandwidth limits without external `tc`/`netem` setups.
+//!
+//! All random decisions are driven by a seeded [`SimRng`], so a given seed and
+//! send sequence always produces the same drop/duplicate/reorder pattern.
+//!
+//! # Impairments
+//!
+//! - **Loss**: Packets are silently discarded with probability `loss`
+//! - **Duplication**: Packets are sent twice with probability `duplicate`
+//! - **Reordering**: Packets are held back by `reorder_delay` with probability `reorder`
+//! - **Latency/Jitter**: Every packet is delayed by `latency` plus a uniform `0..jitter`
+//! - **Bandwidth**: Packets are serialized onto a link of `bandwidth` bytes per second
+//!
+//! Impairments are applied on the send path, mirroring how `netem` shapes
+//! egress traffic. Delayed packets are released by [`SimUdp::flush`], which is
+//! also called implicitly by every send and receive operation.
+//!
+//! The wrappers are generic over [`DatagramSocket`] and [`StreamSocket`], so
+//! they can impair real sockets as well as the in-memory
+//! [`memnet`](crate::memnet) transport.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::simnet::{SimConfig, SimUdp};
+//! use std::time::Duration;
+//!
+//! let udp = Udp::bind("127.0.0.1:0".parse()?, &NetConfig::default())?;
+//! let sim = SimUdp::new(udp, SimConfig {
+//!     loss: 0.05,                         // 5% packet loss
+//!     latency: Duration::from_millis(40), // 40ms one-way delay
+//!     jitter: Duration::from_millis(10),  // up to 10ms of jitter
+//!     seed: 42,
+//!     ..Default::default()
+//! });
+//!
+//! sim.send_to(b"hello", "127.0.0.1:9000".parse()?)?;
+//!
+//! // Drive delayed packets out from the application loop
+//! sim.flush()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::tcp::TcpStream;
+use crate::transport::{DatagramSocket, StreamSocket};
+use crate::udp::Udp;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Network impairment profile applied by simulated sockets
+///
+/// Probabilities are expressed in the range `0.0..=1.0`; values outside that
+/// range are clamped. The default profile applies no impairment at all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    /// Probability that a packet is dropped
+    ///
+    /// **Default**: `0.0`
+    pub loss: f64,
+
+    /// Probability that a packet is delivered twice
+    ///
+    /// **Default**: `0.0`
+    pub duplicate: f64,
+
+    /// Probability that a packet is held back and overtaken by later packets
+    ///
+    /// **Default**: `0.0`
+    pub reorder: f64,
+
+    /// Extra delay applied to reordered packets
+    ///
+    /// **Default**: `5ms`
+    pub reorder_delay: Duration,
+
+    /// Fixed one-way delay applied to every packet
+    ///
+    /// **Default**: `0`
+    pub latency: Duration,
+
+    /// Maximum additional random delay, uniformly distributed
+    ///
+    /// **Default**: `0`
+    pub jitter: Duration,
+
+    /// Link capacity in bytes per second
+    ///
+    /// - `None`: Unlimited bandwidth
+    /// - `Some(bps)`: Packets are serialized at the given rate
+    ///
+    /// **Default**: `None`
+    pub bandwidth: Option<u64>,
+
+    /// Seed for the deterministic random number generator
+    ///
+    /// **Default**: `0x5EED`
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(5),
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth: None,
+            seed: 0x5EED,
+        }
+    }
+}
+
+/// Counters describing the impairments applied so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimStats {
+    /// Packets (or stream writes) handed to the simulator
+    pub submitted: u64,
+    /// Packets actually written to the underlying socket
+    pub delivered: u64,
+    /// Packets discarded by the loss model
+    pub dropped: u64,
+    /// Extra copies created by the duplication model
+    pub duplicated: u64,
+    /// Packets delayed by the reordering model
+    pub reordered: u64,
+}
+
+/// Small deterministic pseudo-random generator (SplitMix64)
+///
+/// This is not cryptographically secure; it exists so that simulated network
+/// conditions are reproducible from a seed without pulling in `rand`.
+#[derive(Clone, Debug)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    /// Creates a generator from the given seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next 64-bit value
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value uniformly distributed in `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns `true` with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        if p <= 0.0 {
+            false
+        } else if p >= 1.0 {
+            true
+        } else {
+            self.next_f64() < p
+        }
+    }
+
+    /// Returns a duration uniformly distributed in `0..=max`
+    pub fn duration_up_to(&mut self, max: Duration) -> Duration {
+        if max.is_zero() {
+            return Duration::ZERO;
+        }
+        let nanos = max.as_nanos().min(u64::MAX as u128) as u64;
+        Duration::from_nanos(self.next_u64() % (nanos + 1))
+    }
+}
+
+/// Impairment engine shared by the simulated socket types
+///
+/// Decides the fate of each submitted packet and computes release times.
+#[derive(Debug)]
+struct Shaper {
+    cfg: SimConfig,
+    rng: SimRng,
+    link_free_at: Option<Instant>,
+    stats: SimStats,
+}
+
+impl Shaper {
+    fn new(cfg: SimConfig) -> Self {
+        let rng = SimRng::new(cfg.seed);
+        Self { cfg, rng, link_free_at: None, stats: SimStats::default() }
+    }
+
+    /// Returns the release times for a packet of `len` bytes submitted at `now`
+    ///
+    /// An empty result means the packet was dropped; two entries mean it was
+    /// duplicated.
+    fn schedule(&mut self, len: usize, now: Instant, datagram: bool) -> Vec<Instant> {
+        self.stats.submitted += 1;
+        if datagram && self.rng.chance(self.cfg.loss) {
+            self.stats.dropped += 1;
+            return Vec::new();
+        }
+
+        let copies = if datagram && self.rng.chance(self.cfg.duplicate) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        let mut times = Vec::with_capacity(copies);
+        for _ in 0..copies {
+            // Serialize onto the link first, then apply propagation delay
+            let mut depart = now;
+            if let Some(bps) = self.cfg.bandwidth.filter(|&b| b > 0) {
+                let start = self.link_free_at.map_or(now, |t| t.max(now));
+                let nanos = (len as u128 * 1_000_000_000) / bps as u128;
+                depart = start + Duration::from_nanos(nanos.min(u64::MAX as u128) as u64);
+                self.link_free_at = Some(depart);
+            }
+
+            let mut at = depart + self.cfg.latency + self.rng.duration_up_to(self.cfg.jitter);
+            if datagram && self.rng.chance(self.cfg.reorder) {
+                self.stats.reordered += 1;
+                at += self.cfg.reorder_delay;
+            }
+            times.push(at);
+        }
+        times
+    }
+}
+
+/// A datagram waiting for its simulated release time
+#[derive(Debug)]
+struct PendingDatagram {
+    at: Instant,
+    seq: u64,
+    data: Vec<u8>,
+    addr: SocketAddr,
+}
+
+impl PartialEq for PendingDatagram {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PendingDatagram {}
+
+impl PartialOrd for PendingDatagram {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingDatagram {
+    // Reversed so the BinaryHeap pops the earliest release time first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+#[derive(Debug)]
+struct UdpState {
+    shaper: Shaper,
+    queue: BinaryHeap<PendingDatagram>,
+    next_seq: u64,
+}
+
+/// Datagram socket wrapper that injects simulated network impairments
+///
+/// `SimUdp` mirrors the sending and receiving API of [`Udp`] and implements
+/// [`DatagramSocket`] itself, so code under test can switch between a real
+/// and an impaired socket with minimal changes. Sends always report the full
+/// payload as written, just like a real network that silently loses packets.
+#[derive(Debug)]
+pub struct SimUdp<S: DatagramSocket = Udp> {
+    inner: S,
+    state: Mutex<UdpState>,
+}
+
+impl<S: DatagramSocket> SimUdp<S> {
+    /// Wraps a datagram socket with the given impairment profile
+    pub fn new(inner: S, cfg: SimConfig) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(UdpState {
+                shaper: Shaper::new(cfg),
+                queue: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// Submits a datagram to the simulated link
+    ///
+    /// The packet is dropped, duplicated, or delayed according to the
+    /// profile. Packets whose release time has already passed are written
+    /// immediately.
+    ///
+    /// # Returns
+    ///
+    /// The payload length, regardless of whether the packet survives the link
+    pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        {
+            let mut st = self.state.lock().unwrap();
+            let now = Instant::now();
+            for at in st.shaper.schedule(buf.len(), now, true) {
+                let seq = st.next_seq;
+                st.next_seq += 1;
+                st.queue.push(PendingDatagram { at, seq, data: buf.to_vec(), addr });
+            }
+        }
+        self.flush()?;
+        Ok(buf.len())
+    }
+
+    /// Submits multiple datagrams to the simulated link
+    ///
+    /// # Returns
+    ///
+    /// The number of packets accepted, which is always `packets.len()`
+    /// unless the underlying socket reports a hard error
+    pub fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        for (buf, addr) in packets {
+            self.send_to(buf, *addr)?;
+        }
+        Ok(packets.len())
+    }
+
+    /// Releases due packets, then receives from the underlying socket
+    ///
+    /// See [`Udp::recv_batch`] for buffer and error semantics.
+    pub fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        self.flush()?;
+        self.inner.recv_batch(bufs, addrs)
+    }
+
+    /// Writes every queued packet whose release time has passed
+    ///
+    /// Call this periodically (for example once per event-loop iteration or
+    /// when [`next_release`](Self::next_release) elapses) to keep delayed
+    /// traffic moving.
+    ///
+    /// # Returns
+    ///
+    /// The number of packets written to the underlying socket. Packets that
+    /// hit `WouldBlock` stay queued for the next call.
+    pub fn flush(&self) -> io::Result<usize> {
+        let mut st = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut sent = 0;
+        while st.queue.peek().is_some_and(|p| p.at <= now) {
+            let pkt = st.queue.pop().unwrap();
+            match self.inner.send_to(&pkt.data, pkt.addr) {
+                Ok(_) => {
+                    sent += 1;
+                    st.shaper.stats.delivered += 1;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    st.queue.push(pkt);
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Returns the number of packets waiting for their release time
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    /// Returns the release time of the next queued packet, if any
+    ///
+    /// Useful for sizing the poll timeout of an event loop.
+    pub fn next_release(&self) -> Option<Instant> {
+        self.state.lock().unwrap().queue.peek().map(|p| p.at)
+    }
+
+    /// Returns a snapshot of the impairment counters
+    pub fn stats(&self) -> SimStats {
+        self.state.lock().unwrap().shaper.stats
+    }
+
+    /// Gets a reference to the wrapped socket
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the simulator, discarding any packets still in flight
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: DatagramSocket> DatagramSocket for SimUdp<S> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        SimUdp::send_to(self, buf, addr)
+    }
+
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        SimUdp::recv_batch(self, bufs, addrs)
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        SimUdp::send_batch(self, packets)
+    }
+}
+
+/// A chunk of stream data waiting for its simulated release time
+#[derive(Debug)]
+struct PendingChunk {
+    at: Instant,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+/// Stream wrapper that injects simulated latency and bandwidth limits
+///
+/// Since TCP is a reliable, ordered byte stream, only `latency`, `jitter`,
+/// and `bandwidth` apply; loss, duplication, and reordering settings are
+/// ignored. Jitter never reorders bytes: each chunk is released no earlier
+/// than the one written before it.
+///
+/// Writes are queued and released by [`SimStream::flush`], which delivers
+/// every chunk whose departure time has passed. Reads release due data first
+/// and then read from the underlying stream.
+#[derive(Debug)]
+pub struct SimStream<S: StreamSocket = TcpStream> {
+    inner: S,
+    state: Mutex<StreamState>,
+}
+
+#[derive(Debug)]
+struct StreamState {
+    shaper: Shaper,
+    queue: VecDeque<PendingChunk>,
+}
+
+impl<S: StreamSocket> SimStream<S> {
+    /// Wraps a stream with the given impairment profile
+    pub fn new(inner: S, cfg: SimConfig) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(StreamState { shaper: Shaper::new(cfg), queue: VecDeque::new() }),
+        }
+    }
+
+    /// Queues `buf` on the simulated link and releases any due chunks
+    ///
+    /// # Returns
+    ///
+    /// The payload length; the bytes are always accepted into the queue
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        {
+            let mut st = self.state.lock().unwrap();
+            let now = Instant::now();
+            if let Some(mut at) = st.shaper.schedule(buf.len(), now, false).pop() {
+                // Preserve byte order even when jitter would move this chunk earlier
+                if let Some(last) = st.queue.back() {
+                    at = at.max(last.at);
+                }
+                st.queue.push_back(PendingChunk { at, data: buf.to_vec(), offset: 0 });
+            }
+        }
+        self.flush()?;
+        Ok(buf.len())
+    }
+
+    /// Releases due chunks, then reads from the underlying stream
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.flush()?;
+        self.inner.recv(buf)
+    }
+
+    /// Writes every queued chunk whose release time has passed
+    ///
+    /// Chunks that hit `WouldBlock` stay queued for the next call, as do
+    /// chunks still inside their simulated delay, so `Ok` does not mean
+    /// everything written has reached the stream. Check
+    /// [`pending_bytes`](Self::pending_bytes) and call again at
+    /// [`next_release`](Self::next_release) to drain the rest.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut st = self.state.lock().unwrap();
+        let now = Instant::now();
+        while let Some(chunk) = st.queue.front_mut() {
+            if chunk.at > now {
+                break;
+            }
+            match self.inner.send(&chunk.data[chunk.offset..]) {
+                Ok(n) => {
+                    chunk.offset += n;
+                    if chunk.offset == chunk.data.len() {
+                        st.queue.pop_front();
+                        st.shaper.stats.delivered += 1;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes waiting for their release time
+    pub fn pending_bytes(&self) -> usize {
+        self.state.lock().unwrap().queue.iter().map(|c| c.data.len() - c.offset).sum()
+    }
+
+    /// Returns the release time of the next queued chunk, if any
+    pub fn next_release(&self) -> Option<Instant> {
+        self.state.lock().unwrap().queue.front().map(|c| c.at)
+    }
+
+    /// Returns a snapshot of the impairment counters
+    pub fn stats(&self) -> SimStats {
+        self.state.lock().unwrap().shaper.stats
+    }
+
+    /// Gets a reference to the wrapped stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the simulator, discarding any data still in flight
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: StreamSocket> StreamSocket for SimStream<S> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        SimStream::recv(self, buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        SimStream::send(self, buf)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+}
+
+impl<S: StreamSocket> Write for SimStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf)
+    }
+
+    /// Releases due chunks only; delayed chunks stay queued (see [`SimStream::flush`])
+    fn flush(&mut self) -> io::Result<()> {
+        SimStream::flush(self)
+    }
+}
+
+impl<S: StreamSocket> Read for SimStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NetConfig;
+
+    fn pair() -> (Udp, Udp) {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let a = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let b = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        (a, b)
+    }
+
+    fn drain(sock: &Udp) -> usize {
+        let mut bufs = vec![vec![0u8; 2048]; 16];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 16];
+        let mut total = 0;
+        for _ in 0..50 {
+            match sock.recv_batch(&mut bufs, &mut addrs) {
+                Ok(0) => {}
+                Ok(n) => total += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("recv failed: {}", e),
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        total
+    }
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let mut a = SimRng::new(7);
+        let mut b = SimRng::new(7);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(SimRng::new(7).next_u64(), SimRng::new(8).next_u64());
+    }
+
+    #[test]
+    fn test_total_loss_drops_everything() {
+        let (a, b) = pair();
+        let dest = b.socket().local_addr().unwrap();
+        let sim = SimUdp::new(a, SimConfig { loss: 1.0, ..Default::default() });
+
+        for _ in 0..10 {
+            assert_eq!(sim.send_to(b"x", dest).unwrap(), 1);
+        }
+        assert_eq!(sim.stats().dropped, 10);
+        assert_eq!(drain(&b), 0);
+    }
+
+    #[test]
+    fn test_duplication_delivers_twice() {
+        let (a, b) = pair();
+        let dest = b.socket().local_addr().unwrap();
+        let sim = SimUdp::new(a, SimConfig { duplicate: 1.0, ..Default::default() });
+
+        sim.send_to(b"dup", dest).unwrap();
+        assert_eq!(sim.stats().duplicated, 1);
+        assert_eq!(drain(&b), 2);
+    }
+
+    #[test]
+    fn test_latency_holds_packets() {
+        let (a, _b) = pair();
+        let dest = "127.0.0.1:9".parse().unwrap();
+        let sim = SimUdp::new(a, SimConfig { latency: Duration::from_secs(60), ..Default::default() });
+
+        sim.send_to(b"later", dest).unwrap();
+        assert_eq!(sim.pending(), 1);
+        assert!(sim.next_release().unwrap() > Instant::now());
+        assert_eq!(sim.flush().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_bandwidth_serializes_packets() {
+        let mut shaper = Shaper::new(SimConfig { bandwidth: Some(1000), ..Default::default() });
+        let now = Instant::now();
+        let first = shaper.schedule(100, now, true)[0];
+        let second = shaper.schedule(100, now, true)[0];
+        assert_eq!(first - now, Duration::from_millis(100));
+        assert_eq!(second - now, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_same_seed_same_pattern() {
+        let cfg = SimConfig { loss: 0.3, duplicate: 0.2, reorder: 0.1, seed: 99, ..Default::default() };
+        let now = Instant::now();
+        let mut a = Shaper::new(cfg.clone());
+        let mut b = Shaper::new(cfg);
+        for _ in 0..200 {
+            assert_eq!(a.schedule(64, now, true).len(), b.schedule(64, now, true).len());
+        }
+        assert_eq!(a.stats, b.stats);
+    }
+
+    #[test]
+    fn test_impairs_in_memory_transport() {
+        let net = crate::memnet::MemNetwork::new();
+        let a = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let b = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let sim = SimUdp::new(a, SimConfig { duplicate: 1.0, ..Default::default() });
+
+        sim.send_to(b"mem", b.local_addr()).unwrap();
+        assert_eq!(b.pending(), 2);
+    }
+
+    #[test]
+    fn test_stream_latency_holds_bytes() {
+        let (a, b) = crate::memnet::MemStream::pair();
+        let sim = SimStream::new(a, SimConfig { latency: Duration::from_secs(60), ..Default::default() });
+
+        assert_eq!(sim.send(b"later").unwrap(), 5);
+        assert_eq!(sim.pending_bytes(), 5);
+        assert_eq!(b.available(), 0);
+    }
+}
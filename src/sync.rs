@@ -0,0 +1,59 @@
+//! Synchronization primitives, swappable for loom's model-checked versions
+//!
+//! Shared-memory structures ([`BufferPool`](crate::buffer_pool::BufferPool),
+//! the [`spsc`](crate::spsc) ring, and any future lock-free code) take
+//! their `Arc`, `Mutex`, atomics, and `UnsafeCell` from here instead of
+//! `std`. Normally these are the `std` types. Building with `--cfg loom`
+//! swaps in [loom](https://docs.rs/loom)'s instrumented versions, so tests
+//! inside `loom::model` explore every interleaving the memory model allows:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+//!
+//! Event loops hand data between threads through these structures; their
+//! wakeups go through kernel objects ([`signal`](crate::signal)) that loom
+//! cannot model, so model tests use the busy-wait variants.
+//!
+//! Only the `loom` tests are meaningful in that build; loom types panic
+//! when used outside a model, so other tests fail.
+//!
+//! `UnsafeCell` follows loom's closure API (`with`/`with_mut`) because
+//! loom needs to see each access to check it against the happens-before
+//! order.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(loom)]
+pub(crate) use loom::sync::{Arc, Mutex};
+#[cfg(loom)]
+pub(crate) use loom::cell::UnsafeCell;
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Arc, Mutex};
+
+/// `std::cell::UnsafeCell` with loom's access API
+#[cfg(not(loom))]
+#[derive(Debug)]
+pub(crate) struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self(std::cell::UnsafeCell::new(value))
+    }
+
+    /// Runs `f` with a shared pointer to the contents
+    #[inline(always)]
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    /// Runs `f` with a mutable pointer to the contents
+    #[inline(always)]
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
@@ -28,15 +28,18 @@
 //!
 //! ```rust,no_run
 //! use horizon_sockets::{NetConfig, tcp::TcpListener};
+//! use horizon_sockets::retry::Backoff;
 //! use std::io::{Read, Write};
 //!
-//! fn main() -> std::io::Result<()> {
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let config = NetConfig::low_latency();
 //!     let listener = TcpListener::bind("0.0.0.0:8080".parse()?, &config)?;
+//!     let mut backoff = Backoff::new();
 //!
 //!     loop {
 //!         match listener.accept_nonblocking() {
 //!             Ok((mut stream, addr)) => {
+//!                 backoff.reset();
 //!                 println!("Connection from: {}", addr);
 //!                 
 //!                 let mut buffer = [0u8; 1024];
@@ -45,10 +48,10 @@
 //!                 }
 //!             }
 //!             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-//!                 std::thread::sleep(std::time::Duration::from_millis(1));
+//!                 backoff.snooze();
 //!                 continue;
 //!             }
-//!             Err(e) => return Err(e),
+//!             Err(e) => return Err(e.into()),
 //!         }
 //!     }
 //! }
@@ -76,10 +79,22 @@
 //! }
 //! ```
 
-use crate::config::{NetConfig, apply_low_latency};
+use crate::config::{DropPolicy, NetConfig, TcpKeepalive, apply_low_latency};
+use crate::drain::{ConnTracker, Drain, DrainMode};
+use crate::error::Error;
+use crate::export::{Export, Manifest, SocketKind};
+use crate::leak::Tracked;
 use crate::raw as r;
-use std::io;
-use std::net::{SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream};
+use crate::trace;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
+
+#[cfg(unix)]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 
 /// High-performance TCP listener with low-latency optimizations
 ///
@@ -112,13 +127,15 @@ use std::net::{SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStr
 ///         Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
 ///             // No pending connections, continue polling
 ///         }
-///         Err(e) => return Err(e),
+///         Err(e) => return Err(e.into()),
 ///     }
 /// }
-/// # Ok::<(), std::io::Error>(())
+/// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 #[derive(Debug)]
 pub struct TcpListener {
+    /// Leak detector entry, declared first so it is removed before the socket closes
+    _tracked: Tracked,
     /// Underlying standard library TCP listener with applied optimizations
     inner: StdTcpListener,
 }
@@ -138,8 +155,9 @@ pub struct TcpListener {
 ///
 /// # Usage
 ///
-/// The stream provides access to the underlying standard library stream
-/// for all I/O operations while maintaining the applied optimizations:
+/// The stream implements `Read` and `Write` (for both `TcpStream` and
+/// `&TcpStream`), and still exposes the underlying standard library stream
+/// through `as_std()` for everything else:
 ///
 /// ```rust,no_run
 /// use horizon_sockets::{NetConfig, tcp::TcpStream};
@@ -148,14 +166,16 @@ pub struct TcpListener {
 ///
 /// let config = NetConfig::low_latency();
 /// let std_stream = StdTcpStream::connect("127.0.0.1:8080")?;
-/// let stream = TcpStream::from_std(std_stream, &config)?;
+/// let mut stream = TcpStream::from_std(std_stream, &config)?;
 ///
-/// // Use standard library methods through as_std()
-/// stream.as_std().write_all(b"Hello")?;
+/// stream.write_all(b"Hello")?;
+/// stream.as_std().set_ttl(64)?;
 /// # Ok::<(), std::io::Error>(())
 /// ```
 #[derive(Debug)]
 pub struct TcpStream {
+    /// Leak detector entry, declared first so it is removed before the socket closes
+    _tracked: Tracked,
     /// Underlying standard library TCP stream with applied optimizations
     inner: StdTcpStream,
 }
@@ -206,8 +226,10 @@ impl TcpListenerBuilder {
     ///
     /// # Arguments
     /// * `addr` - Address to bind to (can be &str or SocketAddr)
-    pub fn bind(mut self, addr: impl Into<SocketAddr>) -> io::Result<Self> {
-        self.addr = Some(addr.into());
+    pub fn bind(mut self, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        self.addr = Some(addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Address resolved to nothing")
+        })?);
         Ok(self)
     }
 
@@ -254,12 +276,30 @@ impl TcpListenerBuilder {
         Ok(self)
     }
 
+    /// Sets the receive low watermark (SO_RCVLOWAT) in bytes
+    pub fn recv_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.recv_lowat = Some(bytes);
+        Ok(self)
+    }
+
+    /// Sets the send low watermark (SO_SNDLOWAT) in bytes; ignored on Linux
+    pub fn send_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.send_lowat = Some(bytes);
+        Ok(self)
+    }
+
     /// Sets Type of Service / DSCP marking for traffic prioritization
     pub fn tos(mut self, tos: u32) -> io::Result<Self> {
         self.config.tos = Some(tos);
         Ok(self)
     }
 
+    /// Sets the socket priority (SO_PRIORITY, Linux only)
+    pub fn so_priority(mut self, prio: u32) -> io::Result<Self> {
+        self.config.so_priority = Some(prio);
+        Ok(self)
+    }
+
     /// Configures IPv6-only mode (true) or dual-stack mode (false)
     pub fn ipv6_only(mut self, only: bool) -> io::Result<Self> {
         self.config.ipv6_only = Some(only);
@@ -293,6 +333,7 @@ impl TcpListenerBuilder {
         self.config.send_buf = low_latency_config.send_buf;
         self.config.tcp_backlog = low_latency_config.tcp_backlog;
         self.config.tos = low_latency_config.tos;
+        self.config.so_priority = low_latency_config.so_priority;
         self.config.poll_timeout_ms = low_latency_config.poll_timeout_ms;
         Ok(self)
     }
@@ -312,6 +353,7 @@ impl TcpListenerBuilder {
         self.config.send_buf = high_throughput_config.send_buf;
         self.config.tcp_backlog = high_throughput_config.tcp_backlog;
         self.config.tos = high_throughput_config.tos;
+        self.config.so_priority = high_throughput_config.so_priority;
         self.config.poll_timeout_ms = high_throughput_config.poll_timeout_ms;
         Ok(self)
     }
@@ -438,6 +480,18 @@ impl TcpStreamBuilder {
         Ok(self)
     }
 
+    /// Sets the receive low watermark (SO_RCVLOWAT) in bytes
+    pub fn recv_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.recv_lowat = Some(bytes);
+        Ok(self)
+    }
+
+    /// Sets the send low watermark (SO_SNDLOWAT) in bytes; ignored on Linux
+    pub fn send_lowat(mut self, bytes: usize) -> io::Result<Self> {
+        self.config.send_lowat = Some(bytes);
+        Ok(self)
+    }
+
     /// Applies low-latency preset configuration
     pub fn low_latency(mut self) -> io::Result<Self> {
         let low_latency_config = NetConfig::low_latency();
@@ -535,7 +589,7 @@ impl TcpListener {
     /// // Bind with low-latency configuration
     /// let low_latency = NetConfig::low_latency();
     /// let listener = TcpListener::bind("[::]:8080".parse()?, &low_latency)?;
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Performance Notes
@@ -545,6 +599,7 @@ impl TcpListener {
     /// - Listen backlog is configured from `cfg.tcp_backlog`
     /// - All TCP optimizations (NODELAY, QUICKACK) are applied
     pub fn bind(addr: SocketAddr, cfg: &NetConfig) -> io::Result<Self> {
+        let _span = trace::span!(debug_span, "tcp_listen", %addr);
         let (domain, sa, len) = r::to_sockaddr(addr);
         let os = r::socket(domain, r::Type::Stream, r::Protocol::Tcp)?;
         r::set_nonblocking(os, true)?;
@@ -554,13 +609,15 @@ impl TcpListener {
                 r::set_ipv6_only(os, only)?;
             }
         }
-        unsafe {
-            r::bind_raw(os, &sa, len)?;
+        let bound = unsafe { r::bind_raw(os, &sa, len) };
+        if bound.is_err() {
+            trace::event!(warn, result = ?bound, "bind failed");
         }
+        bound.map_err(|source| Error::BindFailed { addr, source })?;
         let backlog = cfg.tcp_backlog.unwrap_or(1024);
         r::listen_raw(os, backlog)?;
         let std = unsafe { r::tcp_listener_from_os(os) };
-        Ok(Self { inner: std })
+        Ok(Self { _tracked: Tracked::new("tcp listener", r::os_handle(&std)), inner: std })
     }
     /// Accepts an incoming connection in non-blocking mode
     ///
@@ -595,22 +652,34 @@ impl TcpListener {
     ///             std::thread::sleep(std::time::Duration::from_millis(1));
     ///             continue;
     ///         }
-    ///         Err(e) => return Err(e),
+    ///         Err(e) => return Err(e.into()),
     ///     }
     /// }
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Performance Notes
     ///
-    /// - The returned `TcpStream` has TCP_NODELAY automatically enabled
+    /// - The returned `TcpStream` has TCP_NODELAY automatically enabled and is
+    ///   non-blocking and close-on-exec, set atomically by `accept4` on Linux and the BSDs
+    /// - The listener is already non-blocking from construction, so no `fcntl` is
+    ///   issued per call; don't switch it back through [`as_std`](Self::as_std)
     /// - This method should be called in a loop for continuous operation
     /// - Consider using with event notification systems for efficiency
     pub fn accept_nonblocking(&self) -> io::Result<(TcpStream, SocketAddr)> {
-        self.inner.set_nonblocking(true)?;
-        let (s, a) = self.inner.accept()?;
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                let (os, a) = r::accept_raw(r::os_handle(&self.inner), true)?;
+                let s = unsafe { r::tcp_stream_from_os(os) };
+            } else {
+                let (s, a) = self.inner.accept()?;
+                s.set_nonblocking(true)?;
+            }
+        }
         s.set_nodelay(true)?;
-        Ok((TcpStream { inner: s }, a))
+        let stream = TcpStream { _tracked: Tracked::new("tcp stream", r::os_handle(&s)), inner: s };
+        stream.apply_default_drop_policy()?;
+        Ok((stream, a))
     }
     /// Gets a reference to the underlying standard library TCP listener
     ///
@@ -633,11 +702,171 @@ impl TcpListener {
     /// // Access standard library methods
     /// let local_addr = listener.as_std().local_addr()?;
     /// println!("Listening on: {}", local_addr);
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn as_std(&self) -> &StdTcpListener {
         &self.inner
     }
+    /// Returns the local address this listener is bound to
+    ///
+    /// IPv4-mapped IPv6 addresses are reported as plain IPv4.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr().map(r::unmap_v4)
+    }
+    /// Returns the kernel's identifier for this socket (SO_COOKIE, Linux only)
+    ///
+    /// See [`TcpStream::cookie`].
+    pub fn cookie(&self) -> io::Result<u64> {
+        r::get_cookie(r::os_handle(&self.inner))
+    }
+    /// Gives up the listener as an owned handle with a manifest of its options
+    ///
+    /// See [`Udp::export`](crate::udp::Udp::export).
+    pub fn export(self) -> io::Result<Export> {
+        let local = self.inner.local_addr()?;
+        let manifest = Manifest::capture(r::os_handle(&self.inner), SocketKind::TcpListener, local, None);
+        Ok(Export { socket: self.inner.into(), manifest })
+    }
+    /// Creates a new handle referring to the same listening socket
+    ///
+    /// Useful for accepting on several threads; the clone shares all socket
+    /// options and the non-blocking mode of the original.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let inner = self.inner.try_clone()?;
+        Ok(Self { _tracked: Tracked::new("tcp listener", r::os_handle(&inner)), inner })
+    }
+    /// Returns an iterator over accept attempts on this listener
+    ///
+    /// Each item is the result of [`accept_nonblocking`](Self::accept_nonblocking),
+    /// so accepted streams receive the same configuration. Because the listener
+    /// is non-blocking, the iterator never ends and yields `WouldBlock` errors
+    /// while no connections are pending, mirroring `std::net::TcpListener::incoming`
+    /// on a non-blocking socket.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, tcp::TcpListener};
+    /// use std::io::ErrorKind;
+    ///
+    /// let listener = TcpListener::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+    ///
+    /// for conn in listener.incoming() {
+    ///     match conn {
+    ///         Ok((stream, addr)) => println!("New connection from: {}", addr),
+    ///         Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+    ///         Err(e) => return Err(e.into()),
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+    /// Returns an iterator that drains the currently pending connections
+    ///
+    /// The iterator yields accepted connections (or hard errors) until the
+    /// accept queue is empty, then returns `None` instead of a `WouldBlock`
+    /// error. This fits readiness-driven loops: call it once per readable
+    /// event on the listener.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, tcp::TcpListener};
+    ///
+    /// let listener = TcpListener::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+    ///
+    /// // After the poller reports the listener as readable:
+    /// for conn in listener.try_incoming() {
+    ///     let (stream, addr) = conn?;
+    ///     println!("New connection from: {}", addr);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn try_incoming(&self) -> TryIncoming<'_> {
+        TryIncoming { listener: self }
+    }
+    /// Delays accepting connections until the client sends data (TCP_DEFER_ACCEPT)
+    ///
+    /// A connection is held back from `accept` until its first data arrives,
+    /// for up to `timeout` (rounded up to whole seconds, then to the kernel's
+    /// SYN-ACK retransmission schedule). Held connections cost no file
+    /// descriptors or application state. Once the timeout passes the kernel
+    /// completes the handshake on the client's next ACK and the connection
+    /// is accepted without data, so silent clients are delayed, not dropped.
+    ///
+    /// # Platform Support
+    ///
+    /// Linux only; a no-op elsewhere. Use
+    /// [`HandshakeGuard`](crate::handshake_guard::HandshakeGuard) timers on all platforms.
+    pub fn set_defer_accept(&self, timeout: Duration) -> io::Result<()> {
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        r::set_tcp_defer_accept(r::os_handle(&self.inner), secs.min(u32::MAX as u64) as u32)
+    }
+    /// Stops accepting and starts draining the connections counted by `tracker`
+    ///
+    /// `tracker` refuses new connections from now on. With
+    /// [`DrainMode::Close`](crate::drain::DrainMode::Close) the listening
+    /// socket is closed; with `Keep` it stays open, unused, in the returned
+    /// [`Drain`](crate::drain::Drain). Deregister the listener from the event
+    /// loop before calling this.
+    ///
+    /// # Errors
+    ///
+    /// Closing the socket fails; the tracker is already draining.
+    pub fn drain(self, tracker: &ConnTracker, mode: DrainMode) -> io::Result<Drain> {
+        tracker.start_draining();
+        let kept = match mode {
+            DrainMode::Close => {
+                self.close()?;
+                None
+            }
+            DrainMode::Keep => Some(self),
+        };
+        Ok(Drain::new(kept, tracker.clone()))
+    }
+    /// Closes the listener, reporting any error from the OS
+    ///
+    /// Dropping a listener also closes it but silently ignores errors.
+    pub fn close(self) -> io::Result<()> {
+        r::close_owned(self.inner)
+    }
+}
+
+/// Iterator over accept attempts, created by [`TcpListener::incoming`]
+///
+/// Never returns `None`; `WouldBlock` is reported as an error item.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a TcpListener,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = io::Result<(TcpStream, SocketAddr)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.listener.accept_nonblocking())
+    }
+}
+
+/// Iterator over pending connections, created by [`TcpListener::try_incoming`]
+///
+/// Returns `None` once the accept queue is empty.
+#[derive(Debug)]
+pub struct TryIncoming<'a> {
+    listener: &'a TcpListener,
+}
+
+impl Iterator for TryIncoming<'_> {
+    type Item = io::Result<(TcpStream, SocketAddr)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.listener.accept_nonblocking() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => None,
+            res => Some(res),
+        }
+    }
 }
 
 impl TcpStream {
@@ -697,10 +926,284 @@ impl TcpStream {
     /// # Applied Optimizations
     ///
     /// - TCP_NODELAY is set according to `cfg.tcp_nodelay`
+    /// - Receive/send low watermarks from `cfg.recv_lowat` and `cfg.send_lowat`
+    /// - Keepalive probing from `cfg.tcp_keepalive`
+    /// - On Windows, qWAVE marking from `cfg.tos`
+    /// - SO_LINGER according to the crate-wide [`DropPolicy`](crate::DropPolicy)
     /// - Additional optimizations may be applied in future versions
     pub fn from_std(s: StdTcpStream, cfg: &NetConfig) -> io::Result<Self> {
         s.set_nodelay(cfg.tcp_nodelay)?;
-        Ok(Self { inner: s })
+        let stream = Self { _tracked: Tracked::new("tcp stream", r::os_handle(&s)), inner: s };
+        if let Some(n) = cfg.recv_lowat {
+            stream.set_recv_lowat(n)?;
+        }
+        if let Some(n) = cfg.send_lowat {
+            // Read-only on Linux; honored on BSD/macOS
+            let _ = r::set_send_lowat(r::os_handle(&stream.inner), n as i32);
+        }
+        if cfg.tcp_keepalive.is_some() {
+            stream.set_keepalive(cfg.tcp_keepalive)?;
+        }
+        #[cfg(windows)]
+        stream.mark_qos(cfg.tos);
+        stream.apply_default_drop_policy()?;
+        Ok(stream)
+    }
+    /// Connects to `addr` with every `NetConfig` option applied before the handshake
+    ///
+    /// Unlike wrapping `std::net::TcpStream::connect`, options such as buffer
+    /// sizes, TOS, and SO_PRIORITY already apply to the SYN, and the window
+    /// scale negotiated during the handshake reflects `cfg.recv_buf`.
+    ///
+    /// The returned stream is in blocking mode, like `std::net::TcpStream::connect`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, tcp::TcpStream};
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:8080".parse()?, &NetConfig::low_latency())?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn connect(addr: SocketAddr, cfg: &NetConfig) -> io::Result<Self> {
+        Self::connect_from(None, addr, cfg, None)
+    }
+    /// Connects to `addr`, failing with `TimedOut` if the handshake takes longer than `timeout`
+    pub fn connect_timeout(addr: SocketAddr, cfg: &NetConfig, timeout: Duration) -> io::Result<Self> {
+        if timeout.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot connect with a zero timeout"));
+        }
+        Self::connect_from(None, addr, cfg, Some(timeout))
+    }
+    /// Creates, configures, optionally binds, and connects a new stream
+    pub(crate) fn connect_from(
+        local: Option<SocketAddr>,
+        addr: SocketAddr,
+        cfg: &NetConfig,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let _span = trace::span!(debug_span, "tcp_connect", %addr, local = ?local);
+        let (domain, sa, len) = r::to_sockaddr(cfg.flow_dest(addr));
+        let os = r::socket(domain, r::Type::Stream, r::Protocol::Tcp)?;
+        // Take ownership right away so the handle is closed if configuration fails
+        let stream = Self { _tracked: Tracked::new("tcp stream", os), inner: unsafe { r::tcp_stream_from_os(os) } };
+        apply_low_latency(os, domain, r::Type::Stream, cfg)?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let (SocketAddr::V6(dst), Some(label)) = (addr, cfg.flow_label) {
+            r::lease_flow_label(os, label, *dst.ip()).map_err(|source| Error::option_failed("IPV6_FLOWLABEL_MGR", source))?;
+        }
+        if let Some(local) = local {
+            let (_, lsa, llen) = r::to_sockaddr(local);
+            let bound = unsafe { r::bind_raw(os, &lsa, llen) };
+            if bound.is_err() {
+                trace::event!(warn, result = ?bound, "bind failed");
+            }
+            bound.map_err(|source| Error::BindFailed { addr: local, source })?;
+        }
+
+        r::set_nonblocking(os, true)?;
+        match unsafe { r::connect_raw(os, &sa, len) } {
+            Ok(()) => {}
+            Err(ref e) if r::connect_in_progress(e) => {
+                let ready = crate::poll::wait(&[&stream], crate::poll::Interest::WRITABLE, timeout)?;
+                if !ready[0].is_ready() {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "Connection timed out"));
+                }
+                if let Some(err) = stream.inner.take_error()? {
+                    return Err(err);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        r::set_nonblocking(os, false)?;
+        #[cfg(windows)]
+        stream.mark_qos(cfg.tos);
+
+        stream.apply_default_drop_policy()?;
+        Ok(stream)
+    }
+    /// Marks the connection through qWAVE, since Windows ignores IP_TOS from applications
+    #[cfg(windows)]
+    fn mark_qos(&self, tos: Option<u32>) {
+        if let Some(tos) = tos.filter(|&tos| tos != 0) {
+            let marked = crate::qos::mark_flow(&self.inner, None, tos);
+            if marked.is_err() {
+                trace::event!(debug, result = ?marked, "qos marking skipped");
+            }
+        }
+    }
+    /// Applies the crate-wide drop policy, leaving the OS default for `Graceful`
+    fn apply_default_drop_policy(&self) -> io::Result<()> {
+        match crate::config::drop_policy() {
+            DropPolicy::Graceful => Ok(()),
+            policy => self.set_drop_policy(policy),
+        }
+    }
+    /// Writes from `buf` with one vectored write, advancing it past the bytes written
+    ///
+    /// Accepts `Bytes`, `BytesMut`, and chained buffers without copying
+    /// them together first.
+    #[cfg(feature = "bytes")]
+    pub fn write_buf<B: bytes::Buf>(&self, buf: &mut B) -> io::Result<usize> {
+        let mut iov = [io::IoSlice::new(&[]); 64];
+        let count = buf.chunks_vectored(&mut iov);
+        let written = (&self.inner).write_vectored(&iov[..count])?;
+        buf.advance(written);
+        Ok(written)
+    }
+    /// Sets what happens to unsent data when this stream is closed or dropped
+    ///
+    /// Overrides the crate-wide default from
+    /// [`set_drop_policy`](crate::config::set_drop_policy) for this stream.
+    pub fn set_drop_policy(&self, policy: DropPolicy) -> io::Result<()> {
+        r::set_linger(r::os_handle(&self.inner), policy.linger())
+    }
+    /// Closes the stream, reporting any error from the OS
+    ///
+    /// Dropping ignores the result of `close`; this returns it. `Ok` does not
+    /// mean queued data was delivered. With [`DropPolicy::Linger`] the call
+    /// blocks for up to the linger time, but an expired linger is not an
+    /// error on Linux: the kernel keeps sending in the background. The BSDs
+    /// may report it as `WouldBlock`. To confirm delivery, call
+    /// [`shutdown_write`](Self::shutdown_write) and read until the peer closes.
+    pub fn close(self) -> io::Result<()> {
+        r::close_owned(self.inner)
+    }
+    /// Resets the connection, discarding unsent data
+    ///
+    /// Sends RST instead of FIN so the connection is torn down immediately
+    /// without entering TIME_WAIT. The peer sees `ConnectionReset`.
+    pub fn abort(self) -> io::Result<()> {
+        self.set_drop_policy(DropPolicy::Abort)?;
+        self.close()
+    }
+    /// Shuts down the sending half of the connection
+    ///
+    /// The peer reads end-of-stream once queued data is delivered, while
+    /// this side can keep reading until the peer closes its half. See
+    /// [`HalfClose`](crate::half_close::HalfClose) for tracking a lingering close.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.inner.shutdown(std::net::Shutdown::Write)
+    }
+    /// Holds back partially filled segments until [`uncork`](Self::uncork)
+    ///
+    /// Writes made while corked are coalesced into full-sized segments, so a
+    /// response assembled from several writes leaves in as few packets as
+    /// possible regardless of TCP_NODELAY. Linux sends anything still queued
+    /// after 200ms even if the stream stays corked.
+    ///
+    /// # Platform Support
+    ///
+    /// TCP_CORK on Linux, TCP_NOPUSH on macOS/FreeBSD/OpenBSD; a no-op elsewhere.
+    pub fn cork(&self) -> io::Result<()> {
+        r::set_tcp_cork(r::os_handle(&self.inner), true)
+    }
+    /// Releases corked data, sending any partial segment immediately
+    ///
+    /// With TCP_NOPUSH (BSD, macOS) the remaining partial segment may wait for
+    /// the next write or an ACK.
+    pub fn uncork(&self) -> io::Result<()> {
+        r::set_tcp_cork(r::os_handle(&self.inner), false)
+    }
+    /// Corks the stream until the returned guard is dropped
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, tcp::TcpStream};
+    /// use std::io::Write;
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:8080".parse()?, &NetConfig::default())?;
+    /// {
+    ///     let mut corked = stream.corked()?;
+    ///     corked.write_all(b"HTTP/1.1 200 OK\r\n")?;
+    ///     corked.write_all(b"Content-Length: 2\r\n\r\n")?;
+    ///     corked.write_all(b"ok")?;
+    /// } // uncorked here: one segment on the wire
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn corked(&self) -> io::Result<CorkGuard<'_>> {
+        self.cork()?;
+        Ok(CorkGuard { stream: self })
+    }
+    /// Sets the receive low watermark (SO_RCVLOWAT) on the connection
+    ///
+    /// The stream is not reported readable until `bytes` bytes are buffered
+    /// (or the connection is closed). This can be adjusted at any time, for
+    /// example raised to the size of a known upcoming message body.
+    ///
+    /// # Platform Support
+    ///
+    /// Unix only; a no-op on Windows.
+    pub fn set_recv_lowat(&self, bytes: usize) -> io::Result<()> {
+        r::set_recv_lowat(r::os_handle(&self.inner), bytes as i32)
+    }
+    /// Copies pending data into `buf` without consuming it
+    ///
+    /// Returns `WouldBlock` on a non-blocking stream with nothing buffered.
+    /// With a peek offset set through [`set_peek_offset`](Self::set_peek_offset),
+    /// starts at the offset and advances it.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.peek(buf)
+    }
+    /// Copies pending data starting `offset` bytes in, without consuming anything
+    ///
+    /// Lets a protocol detector look past a PROXY header or a TLS record
+    /// header without reading the stream, so the connection can still be
+    /// handed to a handler that expects to see the bytes from the start.
+    /// Returns fewer bytes than `buf.len()` when less is buffered. On Linux a
+    /// non-blocking stream with nothing past `offset` returns `WouldBlock`.
+    ///
+    /// Uses SO_PEEK_OFF on Linux, so only the requested bytes are copied;
+    /// elsewhere the first `offset + buf.len()` bytes are peeked into a
+    /// temporary buffer. Resets any offset set with
+    /// [`set_peek_offset`](Self::set_peek_offset).
+    pub fn peek_at(&self, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                self.set_peek_offset(Some(offset))?;
+                let peeked = self.inner.peek(buf);
+                self.set_peek_offset(None)?;
+                peeked
+            } else {
+                let mut tmp = vec![0u8; offset + buf.len()];
+                let n = self.inner.peek(&mut tmp)?;
+                let avail = n.saturating_sub(offset);
+                buf[..avail].copy_from_slice(&tmp[offset..n]);
+                Ok(avail)
+            }
+        }
+    }
+    /// Sets where the next [`peek`](Self::peek) starts, or disables the offset with `None`
+    ///
+    /// While an offset is set, each peek advances it past the bytes
+    /// returned and each read moves it back by the bytes consumed, so
+    /// successive peeks walk through buffered data without copying it twice.
+    ///
+    /// # Platform Support
+    ///
+    /// Linux only (SO_PEEK_OFF); `Unsupported` elsewhere.
+    pub fn set_peek_offset(&self, offset: Option<usize>) -> io::Result<()> {
+        let off = match offset {
+            Some(off) => i32::try_from(off).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "peek offset too large"))?,
+            None => -1,
+        };
+        r::set_peek_off(r::os_handle(&self.inner), off)
+    }
+    /// Enables keepalive probing with the given timing, or disables it with `None`
+    ///
+    /// Mobile apps can lengthen the idle time while in the background and
+    /// shorten it again in the foreground. Enabling fails only if
+    /// SO_KEEPALIVE itself is rejected; timers the platform does not
+    /// support per socket (OpenBSD) are left at the system defaults.
+    pub fn set_keepalive(&self, keepalive: Option<TcpKeepalive>) -> io::Result<()> {
+        let os = r::os_handle(&self.inner);
+        let Some(ka) = keepalive else { return r::set_keepalive(os, false) };
+        r::set_keepalive(os, true)?;
+        let _ = r::set_tcp_keepidle(os, ka.idle.as_secs().min(u32::MAX as u64) as u32);
+        let _ = r::set_tcp_keepintvl(os, ka.interval.as_secs().min(u32::MAX as u64) as u32);
+        let _ = r::set_tcp_keepcnt(os, ka.retries);
+        Ok(())
     }
     /// Gets a reference to the underlying standard library TCP stream
     ///
@@ -734,4 +1237,439 @@ impl TcpStream {
     pub fn as_std(&self) -> &StdTcpStream {
         &self.inner
     }
+    /// Returns the local address of this connection
+    ///
+    /// IPv4-mapped IPv6 addresses are reported as plain IPv4.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr().map(r::unmap_v4)
+    }
+    /// Returns the kernel's identifier for this socket (SO_COOKIE, Linux only)
+    ///
+    /// Stays unique until reboot even after the descriptor number is reused,
+    /// so logs and metrics can correlate a connection across fd reuse. The
+    /// same value appears as `sk_cookie` in `ss -e` output and BPF programs.
+    /// `Unsupported` on other platforms.
+    pub fn cookie(&self) -> io::Result<u64> {
+        r::get_cookie(r::os_handle(&self.inner))
+    }
+    /// Gives up the stream as an owned handle with a manifest of its options
+    ///
+    /// See [`Udp::export`](crate::udp::Udp::export).
+    pub fn export(self) -> io::Result<Export> {
+        let (local, peer) = (self.inner.local_addr()?, self.inner.peer_addr().ok());
+        let manifest = Manifest::capture(r::os_handle(&self.inner), SocketKind::TcpStream, local, peer);
+        Ok(Export { socket: self.inner.into(), manifest })
+    }
+    /// Returns the remote address of this connection
+    ///
+    /// When accepted on a dual-stack listener, IPv4 peers are reported as
+    /// plain IPv4 rather than `::ffff:a.b.c.d`.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr().map(r::unmap_v4)
+    }
+    /// Creates a new handle referring to the same connection
+    ///
+    /// The handle is duplicated with `dup` on Unix and `WSADuplicateSocket`
+    /// on Windows, so options such as TCP_NODELAY applied to the original are
+    /// shared by the clone. Typical use is splitting a stream into a reader
+    /// thread and a writer thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, tcp::TcpStream};
+    /// use std::net::TcpStream as StdTcpStream;
+    /// use std::io::Write;
+    ///
+    /// let std_stream = StdTcpStream::connect("127.0.0.1:8080")?;
+    /// let stream = TcpStream::from_std(std_stream, &NetConfig::low_latency())?;
+    /// let mut writer = stream.try_clone()?;
+    ///
+    /// std::thread::spawn(move || writer.write_all(b"hello"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let inner = self.inner.try_clone()?;
+        Ok(Self { _tracked: Tracked::new("tcp stream", r::os_handle(&inner)), inner })
+    }
+}
+
+/// Keeps a [`TcpStream`] corked while alive, created by [`TcpStream::corked`]
+///
+/// Writes go through the guard (or the stream directly); dropping the guard
+/// uncorks and ignores errors, [`finish`](Self::finish) reports them.
+#[derive(Debug)]
+pub struct CorkGuard<'a> {
+    stream: &'a TcpStream,
+}
+
+impl CorkGuard<'_> {
+    /// Uncorks the stream, reporting any error
+    pub fn finish(self) -> io::Result<()> {
+        let stream = self.stream;
+        std::mem::forget(self);
+        stream.uncork()
+    }
+}
+
+impl Write for CorkGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self.stream).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        (&*self.stream).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for CorkGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.stream.uncork();
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Read for &TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        (&self.inner).read_vectored(bufs)
+    }
+}
+
+impl Write for &TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.inner).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        (&self.inner).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.inner).flush()
+    }
+}
+
+impl From<TcpStream> for StdTcpStream {
+    fn from(stream: TcpStream) -> Self {
+        stream.inner
+    }
+}
+
+impl TryFrom<StdTcpStream> for TcpStream {
+    type Error = io::Error;
+
+    /// Wraps an existing stream as is
+    ///
+    /// Options already set on the socket, including its blocking mode, are
+    /// preserved; no `NetConfig` is applied. Use [`TcpStream::from_std`] to
+    /// apply one. The crate-wide drop policy still takes effect.
+    fn try_from(stream: StdTcpStream) -> io::Result<Self> {
+        let stream = Self { _tracked: Tracked::new("tcp stream", r::os_handle(&stream)), inner: stream };
+        stream.apply_default_drop_policy()?;
+        Ok(stream)
+    }
+}
+
+impl From<TcpListener> for StdTcpListener {
+    fn from(listener: TcpListener) -> Self {
+        listener.inner
+    }
+}
+
+impl TryFrom<StdTcpListener> for TcpListener {
+    type Error = io::Error;
+
+    /// Wraps an existing listener, switching it to non-blocking mode
+    ///
+    /// Options already set on the socket are preserved; no `NetConfig` is applied.
+    fn try_from(listener: StdTcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Self { _tracked: Tracked::new("tcp listener", r::os_handle(&listener)), inner: listener })
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for TcpStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for TcpListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for TcpStream {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+#[cfg(windows)]
+impl AsSocket for TcpStream {
+    fn as_socket(&self) -> BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for TcpListener {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+#[cfg(windows)]
+impl AsSocket for TcpListener {
+    fn as_socket(&self) -> BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for TcpStream {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for TcpListener {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_stream_read_write_and_conversions() {
+        let std_listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = std_listener.local_addr().unwrap();
+        let listener = TcpListener::try_from(std_listener).unwrap();
+
+        let std_client = StdTcpStream::connect(addr).unwrap();
+        std_client.set_nodelay(false).unwrap();
+        let mut client = TcpStream::try_from(std_client).unwrap();
+        // Like the listener and UDP conversions, no NetConfig is applied
+        assert!(!client.as_std().nodelay().unwrap());
+        let (server, _) = loop {
+            match listener.accept_nonblocking() {
+                Ok(pair) => break pair,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(e) => panic!("accept failed: {}", e),
+            }
+        };
+        server.as_std().set_nonblocking(false).unwrap();
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        (&server).read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        #[cfg(unix)]
+        assert_eq!(client.as_raw_fd(), client.as_std().as_raw_fd());
+
+        let std_stream: StdTcpStream = server.into();
+        assert_eq!(std_stream.local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn test_try_incoming_drains_pending() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let addr = listener.as_std().local_addr().unwrap();
+        assert_eq!(listener.try_incoming().count(), 0);
+
+        let _a = StdTcpStream::connect(addr).unwrap();
+        let _b = StdTcpStream::connect(addr).unwrap();
+        let mut accepted = 0;
+        for _ in 0..100 {
+            for conn in listener.try_incoming() {
+                conn.unwrap();
+                accepted += 1;
+            }
+            if accepted == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(accepted, 2);
+
+        let next = listener.incoming().next().unwrap();
+        assert_eq!(next.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_std_applies_recv_lowat() {
+        let std_listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = std_listener.local_addr().unwrap();
+        let cfg = NetConfig { recv_lowat: Some(4096), ..Default::default() };
+        let stream = TcpStream::from_std(StdTcpStream::connect(addr).unwrap(), &cfg).unwrap();
+
+        let mut val: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_RCVLOWAT, &mut val as *mut _ as *mut _, &mut len)
+        };
+        assert_eq!(rc, 0);
+        assert_eq!(val, 4096);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cork_guard_toggles_tcp_cork() {
+        let std_listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = TcpStream::connect(std_listener.local_addr().unwrap(), &NetConfig::default()).unwrap();
+        let (mut server, _) = std_listener.accept().unwrap();
+        let corked = |s: &TcpStream| {
+            let mut val: libc::c_int = 0;
+            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+            let rc = unsafe {
+                libc::getsockopt(s.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_CORK, &mut val as *mut _ as *mut _, &mut len)
+            };
+            assert_eq!(rc, 0);
+            val != 0
+        };
+
+        {
+            let mut guard = stream.corked().unwrap();
+            assert!(corked(&stream));
+            guard.write_all(b"head").unwrap();
+            guard.write_all(b"body").unwrap();
+        }
+        assert!(!corked(&stream));
+        let mut buf = [0u8; 8];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"headbody");
+
+        stream.cork().unwrap();
+        stream.corked().unwrap().finish().unwrap();
+        assert!(!corked(&stream));
+    }
+
+    #[test]
+    fn test_addr_getters_unmap_v4() {
+        let mapped: SocketAddr = "[::ffff:10.0.0.1]:443".parse().unwrap();
+        assert_eq!(r::unmap_v4(mapped), "10.0.0.1:443".parse().unwrap());
+        let v6: SocketAddr = "[::1]:443".parse().unwrap();
+        assert_eq!(r::unmap_v4(v6), v6);
+
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::try_from(StdTcpStream::connect(addr).unwrap()).unwrap();
+        assert_eq!(client.peer_addr().unwrap(), addr);
+        assert!(client.local_addr().unwrap().is_ipv4());
+    }
+
+    #[test]
+    fn test_abort_resets_and_close_reports() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let mut client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.incoming().find(|c| c.is_ok()).unwrap().unwrap();
+
+        server.abort().unwrap();
+        client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let mut buf = [0u8; 8];
+        let err = client.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        TcpStream::from_std(client, &cfg).unwrap().close().unwrap();
+        listener.close().unwrap();
+    }
+
+    #[test]
+    fn test_peek_at_offset_leaves_data_unread() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let mut client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.incoming().find(|c| c.is_ok()).unwrap().unwrap();
+        client.write_all(b"PROXY TCP4 1.2.3.4\r\n\x16\x03\x01").unwrap();
+        server.as_std().set_nonblocking(false).unwrap();
+        while server.peek(&mut [0u8; 32]).unwrap() < 23 {
+            std::thread::yield_now();
+        }
+
+        let mut word = [0u8; 4];
+        assert_eq!(server.peek_at(6, &mut word).unwrap(), 4);
+        assert_eq!(&word, b"TCP4");
+        let mut tls = [0u8; 8];
+        assert_eq!(server.peek_at(20, &mut tls).unwrap(), 3);
+        assert_eq!(&tls[..3], b"\x16\x03\x01");
+
+        let mut all = [0u8; 23];
+        (&server).read_exact(&mut all).unwrap();
+        assert_eq!(&all[..5], b"PROXY");
+    }
 }
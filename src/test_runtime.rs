@@ -0,0 +1,326 @@
+//! Deterministic single-threaded runtime with virtual time for tests
+//!
+//! Protocol state machines (handshakes, retransmission, keepalives) are
+//! hard to test against a real event loop: timeouts make tests slow, and
+//! the interleaving of timers and packets changes from run to run.
+//! [`TestRuntime`] replaces both sources of nondeterminism:
+//!
+//! - **Virtual time**: [`now`](TestRuntime::now) only moves when the test
+//!   advances it, or when [`run_until_idle`](TestRuntime::run_until_idle)
+//!   jumps straight to the next timer. A 30 second idle timeout runs in
+//!   microseconds.
+//! - **In-memory sockets**: sockets bound with [`bind`](TestRuntime::bind)
+//!   live on a [`MemNetwork`], so delivery is instant, lossless, and
+//!   independent of the host.
+//! - **Fixed dispatch order**: each [`turn`](TestRuntime::turn) reports
+//!   readable sockets in bind order, then expired timers by deadline (ties
+//!   in creation order). The same test always sees the same events.
+//!
+//! The crate's timing components take the current time as a parameter
+//! ([`Heartbeats::poll`](crate::heartbeat::Heartbeats::poll),
+//! [`TickScheduler::poll_tick`](crate::tick::TickScheduler::poll_tick),
+//! [`FlowTable`](crate::flow::FlowTable) eviction), so passing
+//! [`now`](TestRuntime::now) runs them on virtual time unchanged. Code
+//! written against [`DatagramSocket`](crate::transport::DatagramSocket)
+//! accepts the runtime's [`MemUdp`] sockets directly.
+//!
+//! Like mio, readiness is reported once per turn while data is queued;
+//! handlers should drain a readable socket, or the next turn reports it
+//! again.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::test_runtime::{TestEvent, TestRuntime};
+//! use std::time::Duration;
+//!
+//! let mut rt = TestRuntime::new();
+//! let server = rt.bind("10.0.0.1:9000".parse()?)?;
+//! let client = rt.bind("10.0.0.2:5000".parse()?)?;
+//!
+//! // The client sends a request and arms a 1s retransmission timer
+//! let server_addr = rt.socket(server).local_addr();
+//! rt.socket(client).send_to(b"hello", server_addr)?;
+//! let retransmit = rt.set_timeout(Duration::from_secs(1));
+//!
+//! let mut acked = false;
+//! rt.run_until_idle(|rt, event| match event {
+//!     TestEvent::Readable(id) if id == server => {
+//!         let mut bufs = vec![Vec::new(); 8];
+//!         let mut addrs = vec![server_addr; 8];
+//!         let n = rt.socket(server).recv_batch(&mut bufs, &mut addrs).unwrap();
+//!         for addr in &addrs[..n] {
+//!             rt.socket(server).send_to(b"ack", *addr).unwrap();
+//!         }
+//!     }
+//!     TestEvent::Readable(id) => {
+//!         while rt.socket(id).recv_batch(&mut [Vec::new()], &mut [server_addr]).is_ok() {}
+//!         acked = true;
+//!         rt.cancel(retransmit);
+//!     }
+//!     TestEvent::Timer(_) => panic!("retransmitted"),
+//! });
+//! assert!(acked);
+//! assert_eq!(rt.elapsed(), Duration::ZERO);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::memnet::{MemNetwork, MemUdp};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Identifies a socket bound on a [`TestRuntime`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SocketId(usize);
+
+/// Identifies a timer set on a [`TestRuntime`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(u64);
+
+/// Event dispatched by a [`TestRuntime`] turn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestEvent {
+    /// The socket has datagrams queued
+    Readable(SocketId),
+    /// The timer's deadline has been reached; it does not fire again
+    Timer(TimerId),
+}
+
+/// Single-threaded event loop over in-memory sockets and virtual-time timers
+#[derive(Debug)]
+pub struct TestRuntime {
+    net: MemNetwork,
+    sockets: Vec<MemUdp>,
+    /// Real instant the virtual clock starts from
+    base: Instant,
+    elapsed: Duration,
+    /// Pending timers ordered by deadline, then creation
+    timers: BTreeMap<(Instant, u64), ()>,
+    deadlines: HashMap<u64, Instant>,
+    next_timer: u64,
+}
+
+impl TestRuntime {
+    /// Creates a runtime with an empty network and the clock at zero
+    pub fn new() -> Self {
+        Self::with_network(MemNetwork::new())
+    }
+
+    /// Creates a runtime whose sockets bind on `net`
+    ///
+    /// Useful with [`MemNetwork::with_queue_limit`] to exercise receive
+    /// buffer overflow.
+    pub fn with_network(net: MemNetwork) -> Self {
+        Self {
+            net,
+            sockets: Vec::new(),
+            base: Instant::now(),
+            elapsed: Duration::ZERO,
+            timers: BTreeMap::new(),
+            deadlines: HashMap::new(),
+            next_timer: 0,
+        }
+    }
+
+    /// Returns the network, for binding sockets the test drives by hand
+    pub fn network(&self) -> &MemNetwork {
+        &self.net
+    }
+
+    /// Binds a socket whose readiness this runtime reports
+    pub fn bind(&mut self, addr: SocketAddr) -> io::Result<SocketId> {
+        let socket = self.net.bind(addr)?;
+        self.sockets.push(socket);
+        Ok(SocketId(self.sockets.len() - 1))
+    }
+
+    /// Gets a socket bound with [`bind`](Self::bind)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` came from another runtime.
+    pub fn socket(&self, id: SocketId) -> &MemUdp {
+        &self.sockets[id.0]
+    }
+
+    /// Returns the current virtual time
+    pub fn now(&self) -> Instant {
+        self.base + self.elapsed
+    }
+
+    /// Returns how far the virtual clock has advanced
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Moves the virtual clock forward without dispatching anything
+    pub fn advance(&mut self, by: Duration) {
+        self.elapsed += by;
+    }
+
+    /// Sets a one-shot timer firing once the clock reaches `deadline`
+    ///
+    /// A deadline in the past fires on the next turn.
+    pub fn set_timer(&mut self, deadline: Instant) -> TimerId {
+        let id = self.next_timer;
+        self.next_timer += 1;
+        self.timers.insert((deadline, id), ());
+        self.deadlines.insert(id, deadline);
+        TimerId(id)
+    }
+
+    /// Sets a one-shot timer firing `after` from now
+    pub fn set_timeout(&mut self, after: Duration) -> TimerId {
+        self.set_timer(self.now() + after)
+    }
+
+    /// Cancels a pending timer; returns `false` if it already fired or was cancelled
+    pub fn cancel(&mut self, timer: TimerId) -> bool {
+        match self.deadlines.remove(&timer.0) {
+            Some(deadline) => self.timers.remove(&(deadline, timer.0)).is_some(),
+            None => false,
+        }
+    }
+
+    /// Returns the earliest pending timer deadline
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.timers.keys().next().map(|&(deadline, _)| deadline)
+    }
+
+    /// Dispatches one round of events at the current time without advancing it
+    ///
+    /// Readable sockets come first, in bind order, then timers due by now.
+    /// Timers set by the handler during the turn fire on a later turn;
+    /// timers it cancels do not fire.
+    ///
+    /// # Returns
+    ///
+    /// The number of events dispatched
+    pub fn turn<F: FnMut(&mut Self, TestEvent)>(&mut self, mut f: F) -> usize {
+        let readable: Vec<SocketId> = (0..self.sockets.len()).filter(|&i| self.sockets[i].pending() > 0).map(SocketId).collect();
+        let now = self.now();
+        let due: Vec<u64> = self.timers.range(..=(now, u64::MAX)).map(|(&(_, id), _)| id).collect();
+        let mut count = readable.len();
+        for id in readable {
+            f(self, TestEvent::Readable(id));
+        }
+        for id in due {
+            // An earlier handler in this turn may have cancelled it
+            if self.cancel(TimerId(id)) {
+                f(self, TestEvent::Timer(TimerId(id)));
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Runs turns until no socket has data and no timer is pending
+    ///
+    /// When a turn dispatches nothing, the clock jumps to the next timer
+    /// deadline. A timer that always re-arms itself keeps this running
+    /// forever; use [`run_for`](Self::run_for) for periodic work.
+    pub fn run_until_idle<F: FnMut(&mut Self, TestEvent)>(&mut self, mut f: F) {
+        loop {
+            if self.turn(&mut f) > 0 {
+                continue;
+            }
+            match self.next_timer() {
+                Some(deadline) => self.jump_to(deadline),
+                None => return,
+            }
+        }
+    }
+
+    /// Runs turns for `duration` of virtual time, then leaves the clock at its end
+    pub fn run_for<F: FnMut(&mut Self, TestEvent)>(&mut self, duration: Duration, mut f: F) {
+        let end = self.now() + duration;
+        loop {
+            if self.turn(&mut f) > 0 {
+                continue;
+            }
+            match self.next_timer() {
+                Some(deadline) if deadline <= end => self.jump_to(deadline),
+                _ => break,
+            }
+        }
+        self.jump_to(end);
+    }
+
+    fn jump_to(&mut self, at: Instant) {
+        self.elapsed = self.elapsed.max(at.saturating_duration_since(self.base));
+    }
+}
+
+impl Default for TestRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::DatagramSocket;
+
+    #[test]
+    fn test_timers_fire_in_virtual_time_order() {
+        let mut rt = TestRuntime::new();
+        let start = rt.now();
+        let late = rt.set_timeout(Duration::from_secs(30));
+        let early = rt.set_timeout(Duration::from_secs(5));
+        let tie = rt.set_timeout(Duration::from_secs(5));
+        let cancelled = rt.set_timeout(Duration::from_secs(10));
+        assert!(rt.cancel(cancelled));
+        assert!(!rt.cancel(cancelled));
+
+        let mut fired = Vec::new();
+        rt.run_until_idle(|rt, event| fired.push((event, rt.now() - start)));
+        assert_eq!(
+            fired,
+            [
+                (TestEvent::Timer(early), Duration::from_secs(5)),
+                (TestEvent::Timer(tie), Duration::from_secs(5)),
+                (TestEvent::Timer(late), Duration::from_secs(30)),
+            ]
+        );
+
+        // run_for stops at the window end even with nothing to do
+        rt.set_timeout(Duration::from_secs(10));
+        rt.run_for(Duration::from_secs(3), |_, _| panic!("fired early"));
+        assert_eq!(rt.elapsed(), Duration::from_secs(33));
+    }
+
+    #[test]
+    fn test_retransmits_until_peer_appears() {
+        // A client resends every second until a server bound later answers
+        let mut rt = TestRuntime::new();
+        let server_addr: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+        let client = rt.bind("10.0.0.2:5000".parse().unwrap()).unwrap();
+        rt.socket(client).send_to(b"syn", server_addr).unwrap();
+        let mut retransmit = rt.set_timeout(Duration::from_secs(1));
+
+        let mut sends = 1;
+        let mut server = None;
+        rt.run_for(Duration::from_secs(10), |rt, event| match event {
+            TestEvent::Timer(_) => {
+                if sends == 3 {
+                    server = Some(rt.bind(server_addr).unwrap());
+                }
+                rt.socket(client).send_to(b"syn", server_addr).unwrap();
+                sends += 1;
+                retransmit = rt.set_timeout(Duration::from_secs(1));
+            }
+            TestEvent::Readable(id) if Some(id) == server => {
+                let mut bufs = vec![Vec::new(); 4];
+                let mut addrs = vec![server_addr; 4];
+                let n = DatagramSocket::recv_batch(rt.socket(id), &mut bufs, &mut addrs).unwrap();
+                assert_eq!((n, addrs[0], rt.elapsed()), (1, rt.socket(client).local_addr(), Duration::from_secs(3)));
+                assert!(rt.cancel(retransmit));
+            }
+            TestEvent::Readable(_) => unreachable!(),
+        });
+        assert_eq!(sends, 4);
+    }
+}
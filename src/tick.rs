@@ -0,0 +1,311 @@
+//! Fixed-rate tick scheduling for game-server loops
+//!
+//! Most game servers run the same loop: service sockets until the next
+//! frame boundary, advance the simulation by one tick, repeat. Getting it
+//! right takes more than a sleep. The wait has to end exactly at the
+//! boundary, boundaries must not drift when a tick runs long, and a
+//! server that falls behind has to choose between running missed ticks
+//! back to back and skipping them.
+//!
+//! A [`TickScheduler`] keeps the boundaries. Its
+//! [`next_deadline`](TickScheduler::next_deadline) bounds the I/O wait and
+//! [`poll_tick`](TickScheduler::poll_tick) reports a due [`Tick`], with how
+//! late it is and how many boundaries were skipped to get there. Boundaries
+//! are multiples of the period from the start, so lateness never
+//! accumulates. [`run`](TickScheduler::run) wraps the whole loop around an
+//! I/O step supplied by the caller, so it works with any event loop: with
+//! the mio backend that step is `Runtime::poll_until(Some(deadline), ..)`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::tick::TickScheduler;
+//! use mio::{Events, Interest, Poll, Token};
+//! use std::net::SocketAddr;
+//! use std::ops::ControlFlow;
+//! use std::time::Instant;
+//!
+//! let mut socket = Udp::bind("0.0.0.0:7777".parse()?, &NetConfig::default())?;
+//! let mut poll = Poll::new()?;
+//! let mut events = Events::with_capacity(64);
+//! poll.registry().register(&mut socket, Token(0), Interest::READABLE)?;
+//!
+//! let mut bufs: Vec<Vec<u8>> = (0..64).map(|_| Vec::with_capacity(1500)).collect();
+//! let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 64];
+//! let mut ticks = TickScheduler::from_hz(60).max_catch_up(2);
+//! ticks.run(
+//!     |deadline| {
+//!         poll.poll(&mut events, Some(deadline.saturating_duration_since(Instant::now())))?;
+//!         while let Ok(n @ 1..) = socket.recv_batch(&mut bufs, &mut addrs) {
+//!             // ... queue `n` player inputs ...
+//!             # let _ = n;
+//!         }
+//!         Ok(())
+//!     },
+//!     |tick| {
+//!         if tick.is_overrun() {
+//!             eprintln!("tick {} ran {:?} late, skipped {}", tick.index, tick.late, tick.skipped);
+//!         }
+//!         // ... simulate one step and broadcast state ...
+//!         ControlFlow::Continue(())
+//!     },
+//! )?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::clock::Clock;
+use crate::trace;
+use std::io;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// One due tick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tick {
+    /// Boundary number since the scheduler started, counting skipped ones
+    pub index: u64,
+    /// The boundary this tick belongs to
+    pub scheduled: Instant,
+    /// How long after `scheduled` the tick was delivered
+    pub late: Duration,
+    /// Boundaries dropped immediately before this tick
+    pub skipped: u64,
+    /// The scheduler's period, for convenience
+    pub period: Duration,
+}
+
+impl Tick {
+    /// Returns `true` if the next boundary had already passed when this tick was delivered
+    ///
+    /// An overrun means the previous tick's work plus I/O took longer than
+    /// one period.
+    pub fn is_overrun(&self) -> bool {
+        self.late >= self.period
+    }
+}
+
+/// Running totals kept by a [`TickScheduler`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// Ticks delivered
+    pub ticks: u64,
+    /// Delivered ticks that were overruns
+    pub overruns: u64,
+    /// Boundaries skipped without a tick
+    pub skipped: u64,
+    /// Largest lateness seen
+    pub max_late: Duration,
+}
+
+/// Fixed-rate tick boundaries with overrun detection
+#[derive(Clone, Debug)]
+pub struct TickScheduler {
+    period: Duration,
+    /// Next boundary to deliver
+    next: Instant,
+    /// Index of `next`
+    index: u64,
+    max_catch_up: u32,
+    /// Late ticks delivered back to back so far
+    caught_up: u32,
+    stats: TickStats,
+    clock: Clock,
+}
+
+impl TickScheduler {
+    /// Creates a scheduler whose first tick is one `period` from now
+    ///
+    /// Missed boundaries are skipped by default; see
+    /// [`max_catch_up`](Self::max_catch_up).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(period: Duration) -> Self {
+        assert!(!period.is_zero(), "tick period must be non-zero");
+        Self {
+            period,
+            next: Instant::now() + period,
+            index: 0,
+            max_catch_up: 0,
+            caught_up: 0,
+            stats: TickStats::default(),
+            clock: Clock::default(),
+        }
+    }
+
+    /// Creates a scheduler ticking `hz` times per second
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hz` is zero.
+    pub fn from_hz(hz: u32) -> Self {
+        assert!(hz > 0, "tick rate must be non-zero");
+        Self::new(Duration::from_nanos(1_000_000_000 / hz as u64))
+    }
+
+    /// Runs up to `ticks` missed ticks back to back before skipping (default 0)
+    ///
+    /// Catching up keeps the simulation in step with wall-clock time after a
+    /// brief stall. Past the limit, the remaining missed boundaries are
+    /// skipped so a long stall does not turn into a burst of ticks.
+    pub fn max_catch_up(mut self, ticks: u32) -> Self {
+        self.max_catch_up = ticks;
+        self
+    }
+
+    /// Reads the time from `clock` in [`run`](Self::run) and restarts the schedule on it
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self.reset(clock.now());
+        self
+    }
+
+    /// Restarts the schedule so the next tick is one period after `now`
+    ///
+    /// Statistics and the tick index carry on.
+    pub fn reset(&mut self, now: Instant) {
+        self.next = now + self.period;
+        self.caught_up = 0;
+    }
+
+    /// Returns the tick period
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the boundary of the next tick
+    ///
+    /// Use it as the I/O wait deadline. It may be in the past while the
+    /// scheduler is catching up.
+    pub fn next_deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Returns how long to wait for I/O before the next tick is due
+    pub fn timeout(&self, now: Instant) -> Duration {
+        self.next.saturating_duration_since(now)
+    }
+
+    /// Returns running totals
+    pub fn stats(&self) -> TickStats {
+        self.stats
+    }
+
+    /// Returns the due tick, if the next boundary has passed at `now`
+    pub fn poll_tick(&mut self, now: Instant) -> Option<Tick> {
+        if now < self.next {
+            return None;
+        }
+        let late = now - self.next;
+        let missed = (late.as_nanos() / self.period.as_nanos()) as u64;
+        let skipped = if missed == 0 {
+            self.caught_up = 0;
+            0
+        } else if self.caught_up < self.max_catch_up {
+            self.caught_up += 1;
+            0
+        } else {
+            self.caught_up = 0;
+            missed
+        };
+        // Deliver the latest boundary not skipped, keeping the grid aligned
+        let scheduled = self.next + Duration::from_nanos((self.period.as_nanos() * skipped as u128) as u64);
+        let tick = Tick {
+            index: self.index + skipped,
+            scheduled,
+            late: now - scheduled,
+            skipped,
+            period: self.period,
+        };
+        self.index = tick.index + 1;
+        self.next = scheduled + self.period;
+
+        self.stats.ticks += 1;
+        self.stats.skipped += skipped;
+        self.stats.overruns += tick.is_overrun() as u64;
+        self.stats.max_late = self.stats.max_late.max(tick.late);
+        if tick.is_overrun() || skipped > 0 {
+            trace::event!(debug, index = tick.index, late = ?tick.late, skipped, "tick overrun");
+        }
+        Some(tick)
+    }
+
+    /// Alternates I/O and ticks until `on_tick` breaks
+    ///
+    /// `io` is called with the next boundary and should service sockets
+    /// until then, returning early when events arrive is fine. Every due
+    /// tick is then passed to `on_tick`. While catching up, `io` is still
+    /// called between ticks with a deadline already passed, so sockets keep
+    /// draining.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from `io`.
+    pub fn run<I, T>(&mut self, mut io: I, mut on_tick: T) -> io::Result<()>
+    where
+        I: FnMut(Instant) -> io::Result<()>,
+        T: FnMut(&Tick) -> ControlFlow<()>,
+    {
+        loop {
+            io(self.next)?;
+            if let Some(tick) = self.poll_tick(self.clock.now()) {
+                if on_tick(&tick).is_break() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_ticks_stay_on_grid_and_skip_missed() {
+        let start = Instant::now();
+        let mut ticks = TickScheduler::new(10 * MS);
+        ticks.reset(start);
+
+        assert_eq!(ticks.poll_tick(start + 9 * MS), None);
+        assert_eq!(ticks.timeout(start + 9 * MS), MS);
+
+        // A late tick does not shift later boundaries
+        let first = ticks.poll_tick(start + 13 * MS).unwrap();
+        assert_eq!((first.index, first.late, first.skipped), (0, 3 * MS, 0));
+        assert!(!first.is_overrun());
+        assert_eq!(ticks.next_deadline(), start + 20 * MS);
+
+        // A stall past two boundaries skips to the latest one
+        let tick = ticks.poll_tick(start + 45 * MS).unwrap();
+        assert_eq!((tick.index, tick.scheduled, tick.skipped), (3, start + 40 * MS, 2));
+        assert_eq!(ticks.next_deadline(), start + 50 * MS);
+        assert_eq!(ticks.stats().skipped, 2);
+    }
+
+    #[test]
+    fn test_catch_up_runs_missed_ticks_back_to_back() {
+        let start = Instant::now();
+        let mut ticks = TickScheduler::new(10 * MS).max_catch_up(2);
+        ticks.reset(start);
+
+        // At 45ms, boundaries 10, 20, 30, and 40 are due: two catch up, the rest skip
+        let now = start + 45 * MS;
+        let a = ticks.poll_tick(now).unwrap();
+        let b = ticks.poll_tick(now).unwrap();
+        let c = ticks.poll_tick(now).unwrap();
+        assert_eq!((a.index, a.skipped, a.late), (0, 0, 35 * MS));
+        assert!(a.is_overrun());
+        assert_eq!((b.index, b.skipped), (1, 0));
+        assert_eq!((c.index, c.skipped, c.scheduled), (3, 1, start + 40 * MS));
+        assert_eq!(ticks.poll_tick(now), None);
+
+        let stats = ticks.stats();
+        assert_eq!((stats.ticks, stats.overruns, stats.skipped), (3, 2, 1));
+        assert_eq!(stats.max_late, 35 * MS);
+    }
+}
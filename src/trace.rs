@@ -0,0 +1,76 @@
+//! Internal instrumentation macros
+//!
+//! With the `tracing` feature these forward to the `tracing` crate; without
+//! it they expand to an unreachable use of their arguments, so values logged
+//! nowhere else still count as used, and are never evaluated.
+
+/// Emits a `tracing` event at the given level (`trace`, `debug`, `warn`, ...)
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::$level!($($arg)+);
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            if false {
+                $crate::trace::consume!($($arg)+);
+            }
+        }
+    };
+}
+
+/// Enters a `tracing` span (`debug_span`, `trace_span`, ...) until the returned guard drops
+macro_rules! span {
+    ($kind:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::$kind!($($arg)+).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Borrows each field value and format argument of an event
+///
+/// Accepts the `tracing` field syntax: `name = value`, `name = %value`,
+/// `name = ?value`, `%name`, `?name`, `name`, and the message literal.
+#[cfg(not(feature = "tracing"))]
+macro_rules! consume {
+    () => {};
+    ($name:ident = % $value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    ($name:ident = ? $value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    ($name:ident = $value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    (% $name:ident $(, $($rest:tt)*)?) => {
+        let _ = &$name;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    (? $name:ident $(, $($rest:tt)*)?) => {
+        let _ = &$name;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    ($message:literal $(, $($rest:tt)*)?) => {
+        $($crate::trace::consume!($($rest)*);)?
+    };
+    ($value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        $($crate::trace::consume!($($rest)*);)?
+    };
+}
+
+/// Stand-in span guard when the `tracing` feature is off
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use consume;
+pub(crate) use {event, span};
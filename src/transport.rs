@@ -0,0 +1,286 @@
+//! Transport traits for writing code generic over socket implementations
+//!
+//! This module defines [`DatagramSocket`] and [`StreamSocket`], which abstract
+//! over the crate's real sockets ([`Udp`], [`TcpStream`]), the in-memory
+//! transport in [`memnet`](crate::memnet), and the impairment wrappers in
+//! [`simnet`](crate::simnet). Libraries built on Horizon Sockets can accept any
+//! implementation and be tested without binding real ports.
+//!
+//! Both traits take `&self` for I/O, matching the underlying sockets, so a
+//! single handle can be shared between a sending and a receiving code path.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use horizon_sockets::transport::DatagramSocket;
+//! use horizon_sockets::memnet::MemNetwork;
+//! use std::net::SocketAddr;
+//!
+//! // Echo everything currently queued back to its sender
+//! fn echo<S: DatagramSocket>(sock: &S) -> std::io::Result<usize> {
+//!     let mut bufs = vec![vec![0u8; 1500]; 16];
+//!     let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 16];
+//!     let n = sock.recv_batch(&mut bufs, &mut addrs)?;
+//!     for i in 0..n {
+//!         sock.send_to(&bufs[i], addrs[i])?;
+//!     }
+//!     Ok(n)
+//! }
+//!
+//! let net = MemNetwork::new();
+//! let server = net.bind("127.0.0.1:7".parse()?)?;
+//! let client = net.bind("127.0.0.1:0".parse()?)?;
+//! client.send_to(b"hi", server.local_addr())?;
+//! assert_eq!(echo(&server)?, 1);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::tcp::TcpStream;
+use crate::udp::{send_each_status, SendResult, Udp};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::Arc;
+
+/// A connectionless, message-oriented socket
+///
+/// Implemented by [`Udp`], [`MemUdp`](crate::memnet::MemUdp), and
+/// [`SimUdp`](crate::simnet::SimUdp). All operations are non-blocking and
+/// report `WouldBlock` when they cannot make progress.
+pub trait DatagramSocket {
+    /// Returns the local address the socket is bound to
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Sends a single datagram to `addr`
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize>;
+
+    /// Receives up to `bufs.len()` datagrams
+    ///
+    /// Each received buffer is truncated to the datagram length and the
+    /// sender address is written to the matching slot in `addrs`.
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize>;
+
+    /// Sends multiple datagrams, stopping at the first `WouldBlock`
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams sent before the socket would block
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        let mut sent = 0;
+        for (buf, addr) in packets {
+            match self.send_to(buf, *addr) {
+                Ok(_) => sent += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends multiple datagrams and reports the outcome of each one
+    ///
+    /// Hard errors are recorded per packet; after the first `WouldBlock` the
+    /// remaining packets are reported as [`SendResult::WouldBlock`].
+    fn send_batch_status(&self, packets: &[(&[u8], SocketAddr)]) -> Vec<SendResult> {
+        send_each_status(packets, |buf, addr| self.send_to(buf, addr))
+    }
+}
+
+/// A connected, reliable byte stream
+///
+/// Implemented by [`TcpStream`], [`MemStream`](crate::memnet::MemStream), and
+/// [`SimStream`](crate::simnet::SimStream). Reads and writes take `&self` so
+/// one handle can be read and written from separate code paths.
+pub trait StreamSocket {
+    /// Returns the local address of the connection
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns the remote address of the connection
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Reads available bytes into `buf`, returning `Ok(0)` at end of stream
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Writes bytes from `buf`, returning how many were accepted
+    fn send(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Shuts down the read, write, or both halves of the connection
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+}
+
+impl DatagramSocket for Udp {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Udp::local_addr(self)
+    }
+
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        Udp::send_to(self, buf, addr)
+    }
+
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        Udp::recv_batch(self, bufs, addrs)
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        Udp::send_batch(self, packets)
+    }
+
+    fn send_batch_status(&self, packets: &[(&[u8], SocketAddr)]) -> Vec<SendResult> {
+        Udp::send_batch_status(self, packets)
+    }
+}
+
+impl StreamSocket for TcpStream {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_std().read(buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.as_std().write(buf)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.as_std().shutdown(how)
+    }
+}
+
+impl<T: DatagramSocket + ?Sized> DatagramSocket for &T {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        (**self).local_addr()
+    }
+
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        (**self).send_to(buf, addr)
+    }
+
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        (**self).recv_batch(bufs, addrs)
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        (**self).send_batch(packets)
+    }
+
+    fn send_batch_status(&self, packets: &[(&[u8], SocketAddr)]) -> Vec<SendResult> {
+        (**self).send_batch_status(packets)
+    }
+}
+
+impl<T: DatagramSocket + ?Sized> DatagramSocket for Arc<T> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        (**self).local_addr()
+    }
+
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        (**self).send_to(buf, addr)
+    }
+
+    fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        (**self).recv_batch(bufs, addrs)
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        (**self).send_batch(packets)
+    }
+
+    fn send_batch_status(&self, packets: &[(&[u8], SocketAddr)]) -> Vec<SendResult> {
+        (**self).send_batch_status(packets)
+    }
+}
+
+impl<T: StreamSocket + ?Sized> StreamSocket for &T {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        (**self).local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        (**self).peer_addr()
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).recv(buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        (**self).send(buf)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        (**self).shutdown(how)
+    }
+}
+
+impl<T: StreamSocket + ?Sized> StreamSocket for Arc<T> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        (**self).local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        (**self).peer_addr()
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).recv(buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        (**self).send(buf)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        (**self).shutdown(how)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memnet::{MemNetwork, MemStream};
+    use crate::NetConfig;
+
+    fn roundtrip<S: DatagramSocket>(a: &S, b: &S) -> Vec<u8> {
+        a.send_to(b"generic", b.local_addr().unwrap()).unwrap();
+        let mut bufs = vec![vec![0u8; 64]];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0))];
+        for _ in 0..100 {
+            match b.recv_batch(&mut bufs, &mut addrs) {
+                Ok(1) => return bufs.pop().unwrap(),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("recv failed: {}", e),
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("datagram never arrived");
+    }
+
+    #[test]
+    fn test_generic_over_udp_and_mem() {
+        let cfg = NetConfig { ipv6_only: None, ..Default::default() };
+        let a = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let b = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        assert_eq!(roundtrip(&a, &b), b"generic");
+
+        let net = MemNetwork::new();
+        let a = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let b = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_eq!(roundtrip(&a, &b), b"generic");
+    }
+
+    #[test]
+    fn test_stream_trait_object() {
+        let (a, b) = MemStream::pair();
+        let a: Box<dyn StreamSocket> = Box::new(a);
+        assert_eq!(a.send(b"abc").unwrap(), 3);
+        let mut buf = [0u8; 8];
+        assert_eq!(StreamSocket::recv(&b, &mut buf).unwrap(), 3);
+        assert_eq!(a.peer_addr().unwrap(), StreamSocket::local_addr(&b).unwrap());
+    }
+}
@@ -0,0 +1,438 @@
+//! Outbound traffic classes with priority scheduling and rate caps
+//!
+//! A server that sends voice, game state, and file transfers on one socket
+//! has to decide what goes first when the socket cannot take everything at
+//! once. Sending in arrival order leaves a voice packet stuck behind
+//! megabytes of bulk data. A [`TxScheduler`] keeps one queue per traffic
+//! class and decides the order in which queued packets are handed to
+//! `send_batch` (`sendmmsg` on Linux):
+//!
+//! - [`Policy::StrictPriority`] always sends from the highest-priority
+//!   non-empty class first. Classes are ranked by the order they were
+//!   added, first being highest.
+//! - [`Policy::WeightedRoundRobin`] visits classes in turn, sending up to
+//!   each class's [`weight`](ClassConfig::weight) packets per visit, so
+//!   bulk traffic still progresses under load.
+//!
+//! Either policy honours optional per-class [`rate_limit`](ClassConfig::rate_limit)
+//! token buckets. A capped class that is out of tokens is passed over, and
+//! [`next_deadline`](TxScheduler::next_deadline) says when it can send
+//! again. [`TxScheduler::standard`] sets up the usual control, realtime, and
+//! bulk classes.
+//!
+//! Packets the socket does not accept (`WouldBlock`) stay at the head of
+//! their class queue for the next [`flush`](TxScheduler::flush), so
+//! ordering within a class is preserved.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, udp::Udp};
+//! use horizon_sockets::tx_scheduler::{Policy, TxScheduler, BULK, REALTIME};
+//! use std::time::Instant;
+//!
+//! let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+//! let peer = "10.0.0.2:5000".parse()?;
+//! let mut tx = TxScheduler::standard(Policy::StrictPriority);
+//!
+//! for chunk in [0u8; 64 * 1024].chunks(1200) {
+//!     let _ = tx.enqueue(BULK, peer, chunk.to_vec());
+//! }
+//! let _ = tx.enqueue(REALTIME, peer, b"voice frame".to_vec());
+//!
+//! // The voice frame leaves in the first batch, ahead of the queued bulk data
+//! tx.flush(&socket, Instant::now(), 32)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::Error;
+use crate::transport::DatagramSocket;
+use crate::trace;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Class for connection management: handshakes, acks, keepalives
+pub const CONTROL: ClassId = ClassId(0);
+/// Class for latency-sensitive media and game state
+pub const REALTIME: ClassId = ClassId(1);
+/// Class for transfers that only need throughput
+pub const BULK: ClassId = ClassId(2);
+
+/// How the scheduler chooses between classes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Always send from the earliest-added non-empty class
+    #[default]
+    StrictPriority,
+    /// Take turns, sending up to each class's weight in packets per turn
+    WeightedRoundRobin,
+}
+
+/// Identifies a traffic class within one scheduler
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClassId(pub usize);
+
+/// Settings for one traffic class
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClassConfig {
+    weight: u32,
+    capacity: usize,
+    rate: Option<(u64, u64)>,
+}
+
+impl ClassConfig {
+    /// Creates a class of weight 1 holding up to 1024 packets, without a rate cap
+    pub fn new() -> Self {
+        Self { weight: 1, capacity: 1024, rate: None }
+    }
+
+    /// Packets sent per turn under [`Policy::WeightedRoundRobin`] (minimum 1)
+    pub fn weight(mut self, weight: u32) -> Self {
+        self.weight = weight.max(1);
+        self
+    }
+
+    /// Maximum packets queued before [`enqueue`](TxScheduler::enqueue) rejects more
+    pub fn capacity(mut self, packets: usize) -> Self {
+        self.capacity = packets;
+        self
+    }
+
+    /// Caps the class at `bytes_per_sec`, allowing bursts of up to `burst` bytes
+    ///
+    /// A packet larger than `burst` is sent once the bucket is full.
+    pub fn rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.rate = Some((bytes_per_sec, burst));
+        self
+    }
+}
+
+impl Default for ClassConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters for one traffic class
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClassStats {
+    /// Packets handed to the socket
+    pub sent: u64,
+    /// Bytes handed to the socket
+    pub sent_bytes: u64,
+    /// Packets rejected because the class queue was full
+    pub rejected: u64,
+    /// Packets dropped after a hard send error
+    pub failed: u64,
+    /// Packets currently queued
+    pub queued: usize,
+}
+
+#[derive(Debug)]
+struct Class {
+    config: ClassConfig,
+    queue: VecDeque<(SocketAddr, Vec<u8>)>,
+    /// Available bytes in the token bucket
+    tokens: u64,
+    refilled: Instant,
+    stats: ClassStats,
+}
+
+impl Class {
+    fn refill(&mut self, now: Instant) {
+        if let Some((rate, burst)) = self.config.rate {
+            let elapsed = now.saturating_duration_since(self.refilled);
+            let add = (elapsed.as_nanos() * rate as u128 / 1_000_000_000) as u64;
+            if add > 0 {
+                self.tokens = self.tokens.saturating_add(add).min(burst);
+                // Advance only by the time that produced whole tokens, keeping the remainder
+                self.refilled += Duration::from_nanos((add as u128 * 1_000_000_000 / rate as u128) as u64);
+            }
+            if self.tokens == burst {
+                self.refilled = now;
+            }
+        }
+    }
+
+    /// Tokens the head packet needs before it may be sent
+    fn needed(&self) -> Option<u64> {
+        let (_, burst) = self.config.rate?;
+        let len = self.queue.front()?.1.len() as u64;
+        Some(len.min(burst))
+    }
+
+    fn can_send(&self) -> bool {
+        !self.queue.is_empty() && self.needed().is_none_or(|n| self.tokens >= n)
+    }
+}
+
+/// Per-class outbound queues drained in priority or weighted order
+#[derive(Debug)]
+pub struct TxScheduler {
+    policy: Policy,
+    classes: Vec<Class>,
+    /// Class whose turn it is under weighted round robin
+    turn: usize,
+    /// Packets the current class may still send this turn, once it has started
+    credit: Option<u32>,
+}
+
+impl TxScheduler {
+    /// Creates a scheduler with no classes
+    pub fn new(policy: Policy) -> Self {
+        Self { policy, classes: Vec::new(), turn: 0, credit: None }
+    }
+
+    /// Creates a scheduler with the [`CONTROL`], [`REALTIME`], and [`BULK`] classes
+    ///
+    /// Weights are 4, 8, and 1, so under round robin control and realtime
+    /// traffic get most turns while bulk traffic keeps moving.
+    pub fn standard(policy: Policy) -> Self {
+        let mut sched = Self::new(policy);
+        sched.add_class(ClassConfig::new().weight(4).capacity(256));
+        sched.add_class(ClassConfig::new().weight(8).capacity(1024));
+        sched.add_class(ClassConfig::new().weight(1).capacity(8192));
+        sched
+    }
+
+    /// Adds a class, ranked below all existing ones
+    pub fn add_class(&mut self, config: ClassConfig) -> ClassId {
+        let tokens = config.rate.map_or(0, |(_, burst)| burst);
+        self.classes.push(Class {
+            config,
+            queue: VecDeque::new(),
+            tokens,
+            refilled: Instant::now(),
+            stats: ClassStats::default(),
+        });
+        ClassId(self.classes.len() - 1)
+    }
+
+    /// Queues `packet` for `dest` in `class`
+    ///
+    /// # Errors
+    ///
+    /// Returns the packet if the class queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `class` was not returned by this scheduler.
+    pub fn enqueue(&mut self, class: ClassId, dest: SocketAddr, packet: Vec<u8>) -> Result<(), Vec<u8>> {
+        let c = &mut self.classes[class.0];
+        if c.queue.len() >= c.config.capacity {
+            c.stats.rejected += 1;
+            return Err(packet);
+        }
+        c.queue.push_back((dest, packet));
+        Ok(())
+    }
+
+    /// Sends up to `max` queued packets through `sock` with one `send_batch` call
+    ///
+    /// Packets the socket did not take stay queued in order. After a hard
+    /// error the failing packet is dropped, so it cannot wedge its class,
+    /// and the error returned; packets sent before it are counted in
+    /// [`Error::PartialBatch`](crate::Error::PartialBatch) and not resent.
+    ///
+    /// # Returns
+    ///
+    /// The number of packets sent
+    pub fn flush<S: DatagramSocket + ?Sized>(&mut self, sock: &S, now: Instant, max: usize) -> io::Result<usize> {
+        for c in &mut self.classes {
+            c.refill(now);
+        }
+        let mut batch: Vec<(usize, SocketAddr, Vec<u8>)> = Vec::new();
+        while batch.len() < max {
+            let Some(i) = self.pick() else { break };
+            let c = &mut self.classes[i];
+            if let Some(n) = c.needed() {
+                c.tokens -= n;
+            }
+            let (dest, packet) = c.queue.pop_front().expect("picked class has a packet");
+            batch.push((i, dest, packet));
+        }
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let result = {
+            let packets: Vec<(&[u8], SocketAddr)> = batch.iter().map(|(_, a, p)| (p.as_slice(), *a)).collect();
+            sock.send_batch(&packets)
+        };
+        let (sent, failed) = match result {
+            Ok(n) => (n, None),
+            Err(e) => match Error::from_io(&e) {
+                Some(Error::PartialBatch { sent, .. }) => (*sent, Some(e)),
+                _ => (0, Some(e)),
+            },
+        };
+        let mut rest = batch.split_off(sent).into_iter();
+        for (i, _, packet) in &batch {
+            let stats = &mut self.classes[*i].stats;
+            stats.sent += 1;
+            stats.sent_bytes += packet.len() as u64;
+        }
+        // The packet at the failure point is dropped rather than requeued
+        if failed.is_some() {
+            if let Some((i, _, _)) = rest.next() {
+                self.classes[i].stats.failed += 1;
+            }
+        }
+        // Requeue the unsent tail at the front of each class, refunding its tokens
+        for (i, dest, packet) in rest.rev() {
+            let c = &mut self.classes[i];
+            c.queue.push_front((dest, packet));
+            if let Some(n) = c.needed() {
+                c.tokens += n;
+            }
+        }
+        trace::event!(trace, batch = sent, failed = failed.is_some(), "tx scheduler flush");
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(sent),
+        }
+    }
+
+    /// Chooses the class to send the next packet from
+    fn pick(&mut self) -> Option<usize> {
+        match self.policy {
+            Policy::StrictPriority => self.classes.iter().position(Class::can_send),
+            Policy::WeightedRoundRobin => {
+                let n = self.classes.len();
+                for _ in 0..=n {
+                    let turn = self.turn;
+                    let credit = self.credit.get_or_insert_with(|| self.classes.get(turn).map_or(0, |c| c.config.weight));
+                    if *credit > 0 && self.classes.get(turn).is_some_and(Class::can_send) {
+                        *credit -= 1;
+                        return Some(turn);
+                    }
+                    self.turn = (turn + 1) % n.max(1);
+                    self.credit = None;
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns when a rate-capped class will next have tokens for its head packet
+    ///
+    /// `None` if no queued packet is waiting on a rate cap. Packets in
+    /// uncapped classes are always ready; flush them right away.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.classes
+            .iter()
+            .filter_map(|c| {
+                let (rate, _) = c.config.rate?;
+                let short = c.needed()?.checked_sub(c.tokens).filter(|&s| s > 0)?;
+                let wait = (short as u128 * 1_000_000_000).div_ceil(rate.max(1) as u128);
+                Some(c.refilled + Duration::from_nanos(wait as u64))
+            })
+            .min()
+    }
+
+    /// Returns the number of queued packets across all classes
+    pub fn queued(&self) -> usize {
+        self.classes.iter().map(|c| c.queue.len()).sum()
+    }
+
+    /// Returns the counters for `class`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `class` was not returned by this scheduler.
+    pub fn stats(&self, class: ClassId) -> ClassStats {
+        let c = &self.classes[class.0];
+        ClassStats { queued: c.queue.len(), ..c.stats }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memnet::MemNetwork;
+
+    fn recv_all(sock: &crate::memnet::MemUdp) -> Vec<Vec<u8>> {
+        let mut bufs = vec![vec![0u8; 64]; 32];
+        let mut addrs = vec![sock.local_addr(); 32];
+        let n = sock.recv_batch(&mut bufs, &mut addrs).unwrap_or(0);
+        bufs.truncate(n);
+        bufs
+    }
+
+    #[test]
+    fn test_strict_priority_and_weighted_order() {
+        let net = MemNetwork::new();
+        let tx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let rx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let now = Instant::now();
+
+        let mut strict = TxScheduler::standard(Policy::StrictPriority);
+        strict.enqueue(BULK, rx.local_addr(), b"b1".to_vec()).unwrap();
+        strict.enqueue(REALTIME, rx.local_addr(), b"r1".to_vec()).unwrap();
+        strict.enqueue(CONTROL, rx.local_addr(), b"c1".to_vec()).unwrap();
+        assert_eq!(strict.flush(&tx, now, 2).unwrap(), 2);
+        assert_eq!(strict.flush(&tx, now, 2).unwrap(), 1);
+        assert_eq!(recv_all(&rx), vec![b"c1".to_vec(), b"r1".to_vec(), b"b1".to_vec()]);
+
+        let mut wrr = TxScheduler::new(Policy::WeightedRoundRobin);
+        let heavy = wrr.add_class(ClassConfig::new().weight(2));
+        let light = wrr.add_class(ClassConfig::new());
+        for i in 0..4 {
+            wrr.enqueue(heavy, rx.local_addr(), vec![b'h', b'0' + i]).unwrap();
+            wrr.enqueue(light, rx.local_addr(), vec![b'l', b'0' + i]).unwrap();
+        }
+        assert_eq!(wrr.flush(&tx, now, 6).unwrap(), 6);
+        let order: Vec<Vec<u8>> = recv_all(&rx);
+        assert_eq!(order, [&b"h0"[..], b"h1", b"l0", b"h2", b"h3", b"l1"].map(|p| p.to_vec()));
+        assert_eq!(wrr.stats(light).queued, 2);
+    }
+
+    #[test]
+    fn test_rate_limit_defers_class() {
+        let net = MemNetwork::new();
+        let tx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let rx = net.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut sched = TxScheduler::new(Policy::StrictPriority);
+        let capped = sched.add_class(ClassConfig::new().rate_limit(1000, 100).capacity(3));
+        let open = sched.add_class(ClassConfig::new());
+        for _ in 0..3 {
+            sched.enqueue(capped, rx.local_addr(), vec![0; 60]).unwrap();
+        }
+        assert!(sched.enqueue(capped, rx.local_addr(), vec![0; 60]).is_err());
+        sched.enqueue(open, rx.local_addr(), vec![1; 10]).unwrap();
+
+        // The 100-byte burst covers one 60-byte packet; the uncapped class still goes out
+        let now = Instant::now();
+        assert_eq!(sched.flush(&tx, now, 8).unwrap(), 2);
+        let deadline = sched.next_deadline().unwrap();
+        assert!(deadline > now && deadline <= now + Duration::from_millis(20));
+        assert_eq!(sched.flush(&tx, deadline, 8).unwrap(), 1);
+        let stats = sched.stats(capped);
+        assert_eq!((stats.sent, stats.sent_bytes, stats.rejected, stats.queued), (2, 120, 1, 1));
+    }
+
+    #[test]
+    fn test_partial_batch_is_not_resent() {
+        let config = crate::NetConfig { ipv6_only: None, ..Default::default() };
+        let tx = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let rx = crate::udp::Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let ok = rx.local_addr().unwrap();
+        let bad: SocketAddr = "[::1]:9".parse().unwrap(); // wrong family for an IPv4 socket
+
+        let mut sched = TxScheduler::new(Policy::StrictPriority);
+        let class = sched.add_class(ClassConfig::new());
+        for (dest, packet) in [(ok, b"a"), (bad, b"b"), (ok, b"c")] {
+            sched.enqueue(class, dest, packet.to_vec()).unwrap();
+        }
+        let err = sched.flush(&tx, Instant::now(), 8).unwrap_err();
+        assert!(matches!(Error::from_io(&err), Some(Error::PartialBatch { sent: 1, .. })), "{err:?}");
+        let stats = sched.stats(class);
+        assert_eq!((stats.sent, stats.failed, stats.queued), (1, 1, 1));
+
+        assert_eq!(sched.flush(&tx, Instant::now(), 8).unwrap(), 1);
+        assert_eq!(sched.queued(), 0);
+    }
+}
@@ -30,9 +30,10 @@
 //!
 //! ```rust,no_run
 //! use horizon_sockets::{NetConfig, udp::Udp, buffer_pool::BufferPool};
+//! use horizon_sockets::retry::Backoff;
 //! use std::net::SocketAddr;
 //!
-//! fn main() -> std::io::Result<()> {
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     // Configure for low latency with busy polling
 //!     let config = NetConfig {
 //!         busy_poll: Some(50), // 50 microseconds busy polling
@@ -46,10 +47,12 @@
 //!     let pool = BufferPool::new(64, 2048);
 //!     let mut buffers = pool.acquire_batch(32);
 //!     let mut addrs = vec![SocketAddr::from(([0,0,0,0], 0)); 32];
+//!     let mut backoff = Backoff::new();
 //!
 //!     loop {
 //!         match socket.recv_batch(&mut buffers, &mut addrs) {
 //!             Ok(count) => {
+//!                 backoff.reset();
 //!                 println!("Received {} packets", count);
 //!                 
 //!                 // Echo packets back
@@ -58,10 +61,10 @@
 //!                 }
 //!             }
 //!             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-//!                 std::thread::yield_now();
+//!                 backoff.snooze();
 //!                 continue;
 //!             }
-//!             Err(e) => return Err(e),
+//!             Err(e) => return Err(e.into()),
 //!         }
 //!     }
 //! }
@@ -73,7 +76,7 @@
 //! use horizon_sockets::{NetConfig, udp::Udp};
 //! use std::net::SocketAddr;
 //!
-//! fn batch_sender() -> std::io::Result<()> {
+//! fn batch_sender() -> Result<(), Box<dyn std::error::Error>> {
 //!     let config = NetConfig::high_throughput();
 //!     let socket = Udp::bind("0.0.0.0:0".parse()?, &config)?;
 //!     
@@ -90,10 +93,19 @@
 //! }
 //! ```
 
+use crate::arena::{RecvArena, Segment};
 use crate::config::{NetConfig, apply_low_latency};
+use crate::icmp::IcmpError;
+use crate::leak::Tracked;
+use crate::error::Error;
+use crate::export::{Export, Manifest, SocketKind};
+use crate::fixed_batch::{FixedRecvBatch, FixedSendBatch};
 use crate::raw as r;
+use crate::trace;
 use std::io;
-use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket as StdUdpSocket};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 #[cfg(windows)]
 use std::os::windows::io::AsRawSocket;
@@ -136,12 +148,66 @@ use std::os::fd::AsRawFd;
 /// let socket = Udp::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
 /// let pool = BufferPool::new(64, 2048); // 64 buffers, 2KB each
 /// let buffers = pool.acquire_batch(16);
-/// # Ok::<(), std::io::Error>(())
+/// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 #[derive(Debug)]
 pub struct Udp {
+    /// Leak detector entry, declared first so it is removed before the socket closes
+    _tracked: Tracked,
     /// Underlying standard library UDP socket with applied optimizations
     inner: StdUdpSocket,
+    /// Receive buffer auto-tuning state, shared with clones
+    tuner: Option<Arc<RecvBufTuner>>,
+    /// `recvmmsg` headers reused by batch receives; empty where there is no `recvmmsg`
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    recv_cache: RecvCache,
+}
+
+/// Per-socket `recvmmsg` storage, kept between batch receives
+#[derive(Debug, Default)]
+struct RecvCache {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    batch: std::sync::Mutex<Option<crate::mmsg::RecvBatch>>,
+}
+
+/// Receive buffer growth state for [`NetConfig::auto_tune_buffers`]
+#[derive(Debug)]
+struct RecvBufTuner {
+    /// Upper bound for the requested SO_RCVBUF
+    cap: usize,
+    /// Currently requested SO_RCVBUF
+    current: AtomicUsize,
+    /// Last observed value of the kernel's cumulative drop counter
+    drops: AtomicU32,
+}
+
+impl RecvBufTuner {
+    /// Creates tuning state if the config enables it on this platform
+    fn from_config(cfg: &NetConfig) -> Option<Arc<Self>> {
+        let cap = cfg.auto_tune_buffers?;
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let start = cfg.recv_buf.unwrap_or(crate::config::AUTO_TUNE_START).min(cap);
+        Some(Arc::new(Self { cap, current: AtomicUsize::new(start), drops: AtomicU32::new(0) }))
+    }
+
+    /// Records the drop counter and doubles SO_RCVBUF if it increased
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn observe(&self, os: r::OsSocket, drops: u32) {
+        let prev = self.drops.swap(drops, Ordering::Relaxed);
+        if drops == prev {
+            return;
+        }
+        let current = self.current.load(Ordering::Relaxed);
+        if current >= self.cap {
+            return;
+        }
+        let next = current.saturating_mul(2).min(self.cap);
+        if r::set_recv_buffer(os, next.min(i32::MAX as usize) as i32).is_ok() {
+            self.current.store(next, Ordering::Relaxed);
+        }
+    }
 }
 
 /// Builder for creating UDP sockets with convenient method chaining
@@ -197,8 +263,10 @@ impl UdpBuilder {
     ///
     /// # Arguments
     /// * `addr` - Address to bind to (can be string or SocketAddr)
-    pub fn bind(mut self, addr: impl Into<SocketAddr>) -> io::Result<Self> {
-        self.addr = Some(addr.into());
+    pub fn bind(mut self, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        self.addr = Some(addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Address resolved to nothing")
+        })?);
         Ok(self)
     }
 
@@ -240,12 +308,37 @@ impl UdpBuilder {
         Ok(self)
     }
 
+    /// Grows SO_RCVBUF on packet drops, up to `cap` bytes (Linux only)
+    pub fn auto_tune_buffers(mut self, cap: usize) -> io::Result<Self> {
+        self.config.auto_tune_buffers = Some(cap);
+        Ok(self)
+    }
+
+    /// Prefers busy polling with an optional per-poll packet budget (Linux 5.11+)
+    pub fn prefer_busy_poll(mut self, budget: Option<u32>) -> io::Result<Self> {
+        self.config.prefer_busy_poll = true;
+        self.config.busy_poll_budget = budget;
+        Ok(self)
+    }
+
     /// Sets Type of Service / DSCP marking for traffic prioritization
     pub fn tos(mut self, tos: u32) -> io::Result<Self> {
         self.config.tos = Some(tos);
         Ok(self)
     }
 
+    /// Enables ICMP error reporting drained by `Udp::drain_errors()` (Linux only)
+    pub fn recv_err(mut self, enable: bool) -> io::Result<Self> {
+        self.config.recv_err = enable;
+        Ok(self)
+    }
+
+    /// Sets the socket priority (SO_PRIORITY, Linux only)
+    pub fn so_priority(mut self, prio: u32) -> io::Result<Self> {
+        self.config.so_priority = Some(prio);
+        Ok(self)
+    }
+
     /// Configures IPv6-only mode (true) or dual-stack mode (false)
     pub fn ipv6_only(mut self, only: bool) -> io::Result<Self> {
         self.config.ipv6_only = Some(only);
@@ -268,9 +361,12 @@ impl UdpBuilder {
     pub fn low_latency(mut self) -> io::Result<Self> {
         let low_latency_config = NetConfig::low_latency();
         self.config.busy_poll = low_latency_config.busy_poll;
+        self.config.prefer_busy_poll = low_latency_config.prefer_busy_poll;
+        self.config.busy_poll_budget = low_latency_config.busy_poll_budget;
         self.config.recv_buf = low_latency_config.recv_buf;
         self.config.send_buf = low_latency_config.send_buf;
         self.config.tos = low_latency_config.tos;
+        self.config.so_priority = low_latency_config.so_priority;
         self.config.poll_timeout_ms = low_latency_config.poll_timeout_ms;
         Ok(self)
     }
@@ -285,9 +381,12 @@ impl UdpBuilder {
     pub fn high_throughput(mut self) -> io::Result<Self> {
         let high_throughput_config = NetConfig::high_throughput();
         self.config.busy_poll = high_throughput_config.busy_poll;
+        self.config.prefer_busy_poll = high_throughput_config.prefer_busy_poll;
+        self.config.busy_poll_budget = high_throughput_config.busy_poll_budget;
         self.config.recv_buf = high_throughput_config.recv_buf;
         self.config.send_buf = high_throughput_config.send_buf;
         self.config.tos = high_throughput_config.tos;
+        self.config.so_priority = high_throughput_config.so_priority;
         self.config.poll_timeout_ms = high_throughput_config.poll_timeout_ms;
         Ok(self)
     }
@@ -302,6 +401,8 @@ impl UdpBuilder {
     pub fn power_efficient(mut self) -> io::Result<Self> {
         let power_config = NetConfig::power_efficient();
         self.config.busy_poll = power_config.busy_poll;
+        self.config.prefer_busy_poll = power_config.prefer_busy_poll;
+        self.config.busy_poll_budget = power_config.busy_poll_budget;
         self.config.recv_buf = power_config.recv_buf;
         self.config.send_buf = power_config.send_buf;
         self.config.reuse_port = power_config.reuse_port;
@@ -388,7 +489,7 @@ impl Udp {
     /// // Bind with low-latency configuration
     /// let low_latency = NetConfig::low_latency();
     /// let socket = Udp::bind("[::]:8080".parse()?, &low_latency)?;
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Platform-Specific Optimizations
@@ -403,28 +504,20 @@ impl Udp {
     /// - Buffer sizes are critical for preventing packet loss under load
     /// - Busy polling (Linux) trades CPU for reduced latency
     pub fn bind(addr: SocketAddr, cfg: &NetConfig) -> io::Result<Self> {
-        // Use standard library binding for simplicity and compatibility
-        let std = StdUdpSocket::bind(addr)?;
-        std.set_nonblocking(true)?;
-
-        // Apply low-latency configurations if possible
-        cfg_if::cfg_if! {
-            if #[cfg(windows)] {
-                let os = std.as_raw_socket() as r::OsSocket;
-                let (domain, _, _) = r::to_sockaddr(addr);
-                let _ = apply_low_latency(os, domain, r::Type::Dgram, cfg);
-
-                // Configure IPv6 dual-stack if needed
-                if let (SocketAddr::V6(_), Some(v6only)) = (addr, cfg.ipv6_only) {
-                    let _ = r::set_ipv6_only(os, v6only);
-                }
-            } else {
-                // Unix platforms - would use as_raw_fd()
-                let _ = (addr, cfg); // suppress unused warnings
-            }
+        let _span = trace::span!(debug_span, "udp_bind", %addr);
+        let (domain, sa, len) = r::to_sockaddr(addr);
+        let os = r::socket(domain, r::Type::Dgram, r::Protocol::Udp)?;
+        // Take ownership right away so the handle is closed if configuration fails
+        let std = unsafe { r::udp_from_os(os) };
+        r::set_nonblocking(os, true)?;
+        // Options such as SO_REUSEPORT and IPV6_V6ONLY must be set before bind
+        apply_low_latency(os, domain, r::Type::Dgram, cfg)?;
+        let bound = unsafe { r::bind_raw(os, &sa, len) };
+        if bound.is_err() {
+            trace::event!(warn, result = ?bound, "bind failed");
         }
-
-        Ok(Self { inner: std })
+        bound.map_err(|source| Error::BindFailed { addr, source })?;
+        Ok(Self { _tracked: Tracked::new("udp", r::os_handle(&std)), inner: std, tuner: RecvBufTuner::from_config(cfg), recv_cache: RecvCache::default() })
     }
 
     /// Binds a dual-stack UDP socket on IPv6 with IPv4 compatibility
@@ -467,20 +560,23 @@ impl Udp {
     /// - All other optimizations from `cfg` are applied normally
     /// - Particularly important for servers that need to handle both protocol versions
     pub fn bind_dual_stack(port: u16, cfg: &NetConfig) -> io::Result<Self> {
+        let _span = trace::span!(debug_span, "udp_bind_dual_stack", port);
         let any6: SocketAddr = "[::]:0".parse().unwrap();
         let (_domain, mut sa, len) = r::to_sockaddr(any6);
         if let r::SockAddr::V6(ref mut s6) = sa {
-            s6.sin6_port = (port as u16).to_be();
+            s6.sin6_port = port.to_be();
         }
         let os = r::socket(r::Domain::Ipv6, r::Type::Dgram, r::Protocol::Udp)?;
         r::set_nonblocking(os, true)?;
         apply_low_latency(os, r::Domain::Ipv6, r::Type::Dgram, cfg)?;
         r::set_ipv6_only(os, cfg.ipv6_only.unwrap_or(false))?;
-        unsafe {
-            r::bind_raw(os, &sa, len)?;
+        let bound = unsafe { r::bind_raw(os, &sa, len) };
+        if bound.is_err() {
+            trace::event!(warn, result = ?bound, "bind failed");
         }
+        bound.map_err(|source| Error::BindFailed { addr: SocketAddr::new(any6.ip(), port), source })?;
         let std = unsafe { r::udp_from_os(os) };
-        Ok(Self { inner: std })
+        Ok(Self { _tracked: Tracked::new("udp", r::os_handle(&std)), inner: std, tuner: RecvBufTuner::from_config(cfg), recv_cache: RecvCache::default() })
     }
 
     /// Gets a reference to the underlying standard library UDP socket
@@ -507,7 +603,7 @@ impl Udp {
     ///
     /// // Set additional socket options if needed
     /// socket.socket().set_broadcast(true)?;
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Note
@@ -518,6 +614,110 @@ impl Udp {
         &self.inner
     }
 
+    /// Returns the local address this socket is bound to
+    ///
+    /// IPv4-mapped IPv6 addresses are reported as plain IPv4.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr().map(r::unmap_v4)
+    }
+
+    /// Returns the remote address of a connected socket
+    ///
+    /// Fails with `NotConnected` unless the socket was connected through
+    /// [`socket()`](Self::socket). IPv4-mapped IPv6 addresses are reported
+    /// as plain IPv4.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr().map(r::unmap_v4)
+    }
+
+    /// Returns the kernel's identifier for this socket (SO_COOKIE, Linux only)
+    ///
+    /// Stays unique until reboot even after the descriptor number is reused,
+    /// so logs and metrics can key on it across socket churn. The cookie
+    /// belongs to this socket: one recreated after a process restart gets a
+    /// new cookie. `Unsupported` on other platforms.
+    pub fn cookie(&self) -> io::Result<u64> {
+        r::get_cookie(r::os_handle(&self.inner))
+    }
+
+    /// Gives up the socket as an owned handle with a manifest of its options
+    ///
+    /// For passing the socket to another process; see [`export`](crate::export).
+    /// The manifest keeps the address family the socket really has, so
+    /// IPv4-mapped addresses are not unmapped there.
+    pub fn export(self) -> io::Result<Export> {
+        let (local, peer) = (self.inner.local_addr()?, self.inner.peer_addr().ok());
+        let manifest = Manifest::capture(r::os_handle(&self.inner), SocketKind::Udp, local, peer);
+        Ok(Export { socket: self.inner.into(), manifest })
+    }
+
+    /// Leases an IPv6 flow label and returns `dest` carrying it
+    ///
+    /// Packets sent to the returned address carry `label` in their IPv6
+    /// header, keeping them on one ECMP path; `0` lets the kernel pick a
+    /// label. The lease belongs to this socket and covers any destination.
+    /// IPv4 destinations are returned unchanged. Requires a socket bound
+    /// with [`NetConfig::flow_label`] set, which enables IPV6_FLOWINFO_SEND.
+    ///
+    /// # Errors
+    ///
+    /// `Unsupported` outside Linux; `InvalidInput` for labels over 20 bits or,
+    /// with `net.ipv6.flowlabel_state_ranges` set, at or above `0x80000`.
+    pub fn lease_flow_label(&self, dest: SocketAddr, label: u32) -> io::Result<SocketAddr> {
+        let SocketAddr::V6(dst) = dest else { return Ok(dest) };
+        let leased = r::lease_flow_label(r::os_handle(&self.inner), label, *dst.ip())?;
+        let cfg = NetConfig { flow_label: Some(leased), ..NetConfig::default() };
+        Ok(cfg.flow_dest(dest))
+    }
+
+    /// Creates a new handle referring to the same underlying socket
+    ///
+    /// The handle is duplicated with `dup` on Unix and `WSADuplicateSocket`
+    /// on Windows. Socket options live in the kernel socket, so the clone
+    /// shares every optimization applied at bind time, as well as the
+    /// non-blocking mode. This allows one thread to send while another
+    /// receives on the same port.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::Udp};
+    ///
+    /// let socket = Udp::bind("0.0.0.0:9000".parse()?, &NetConfig::low_latency())?;
+    /// let sender = socket.try_clone()?;
+    ///
+    /// std::thread::spawn(move || {
+    ///     let _ = sender.send_to(b"tick", "127.0.0.1:9001".parse().unwrap());
+    /// });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let inner = self.inner.try_clone()?;
+        Ok(Self { _tracked: Tracked::new("udp", r::os_handle(&inner)), inner, tuner: self.tuner.clone(), recv_cache: RecvCache::default() })
+    }
+
+    /// Returns the kernel's packet drop counter as last seen by `recv_batch`
+    ///
+    /// Only tracked when [`NetConfig::auto_tune_buffers`] is enabled (Linux);
+    /// `None` otherwise. The counter is cumulative since the socket was created.
+    pub fn rx_drops(&self) -> Option<u32> {
+        self.tuner.as_ref().map(|t| t.drops.load(Ordering::Relaxed))
+    }
+
+    /// Returns the receive buffer size currently requested by auto-tuning
+    ///
+    /// `None` when [`NetConfig::auto_tune_buffers`] is not enabled.
+    pub fn auto_tuned_recv_buf(&self) -> Option<usize> {
+        self.tuner.as_ref().map(|t| t.current.load(Ordering::Relaxed))
+    }
+
+    /// Closes the socket, reporting any error from the OS
+    ///
+    /// Dropping a `Udp` also closes it but silently ignores errors.
+    pub fn close(self) -> io::Result<()> {
+        r::close_owned(self.inner)
+    }
+
     /// Receives multiple UDP packets in a single batch operation
     ///
     /// This is the primary method for high-performance UDP receiving. On Linux,
@@ -562,10 +762,10 @@ impl Udp {
     ///             // No packets available
     ///             continue;
     ///         }
-    ///         Err(e) => return Err(e),
+    ///         Err(e) => return Err(e.into()),
     ///     }
     /// }
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Performance Notes
@@ -577,18 +777,210 @@ impl Udp {
     ///
     /// # Buffer Management
     ///
-    /// - Buffers are automatically resized to fit received data
-    /// - If a buffer has zero capacity, it's allocated to 2048 bytes
+    /// - Each buffer receives into its whole capacity, without zero-filling
+    ///   it first, and its length is set to the packet length; a buffer
+    ///   with zero capacity gets 2048 bytes reserved
     /// - Consider using `BufferPool` for efficient memory management
     pub fn recv_batch(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        let res = self.recv_batch_os(bufs, addrs);
+        trace::event!(trace, requested = bufs.len(), result = ?res, "udp recv_batch");
+        res
+    }
+
+    /// Receives a batch of packets into consecutive segments of one arena
+    ///
+    /// Like [`recv_batch`](Self::recv_batch), but every packet lands in the
+    /// same contiguous allocation, described by the arena's
+    /// [`segments`](crate::arena::RecvArena::segments). Previous contents of
+    /// the arena are discarded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, arena::RecvArena, udp::Udp};
+    ///
+    /// let socket = Udp::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+    /// let mut arena = RecvArena::new(64, 2048);
+    ///
+    /// let count = socket.recv_batch_arena(&mut arena)?;
+    /// for (packet, from) in arena.iter() {
+    ///     println!("{} bytes from {from}", packet.len());
+    /// }
+    /// // The whole batch is one region, ready to hand off as a unit
+    /// let region: &[u8] = arena.as_bytes();
+    /// # let _ = (count, region);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn recv_batch_arena(&self, arena: &mut RecvArena) -> io::Result<usize> {
+        arena.clear();
+        let res = self.recv_arena_os(arena);
+        trace::event!(trace, requested = arena.capacity(), result = ?res, "udp recv_batch_arena");
+        res
+    }
+
+    /// Receives a batch of packets into `bufs` using fixed-capacity header storage
+    ///
+    /// Like [`recv_batch`](Self::recv_batch), but nothing is allocated:
+    /// headers and sender addresses live in `batch`, and each packet lands
+    /// in the full length of one buffer. Read lengths and senders from
+    /// `batch` afterwards. At most `N` buffers are used.
+    pub fn recv_batch_fixed<const N: usize, B: AsMut<[u8]>>(&self, batch: &mut FixedRecvBatch<N>, bufs: &mut [B]) -> io::Result<usize> {
+        let res = self.recv_fixed_os(batch, bufs);
+        trace::event!(trace, requested = bufs.len().min(N), result = ?res, "udp recv_batch_fixed");
+        res
+    }
+
+    fn recv_fixed_os<const N: usize, B: AsMut<[u8]>>(&self, batch: &mut FixedRecvBatch<N>, bufs: &mut [B]) -> io::Result<usize> {
         cfg_if::cfg_if! {
             if #[cfg(any(target_os = "linux", target_os = "android"))] {
-                unsafe { recv_batch_linux(self, bufs, addrs) }
+                batch.recv_mmsg(self.inner.as_raw_fd(), bufs)
+            } else {
+                batch.received = 0;
+                for (i, buf) in bufs.iter_mut().take(N).enumerate() {
+                    match self.inner.recv_from(buf.as_mut()) {
+                        Ok((len, addr)) => {
+                            batch.lens[i] = len;
+                            batch.addrs[i] = addr;
+                            batch.received += 1;
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        // Later errors resurface on the next call
+                        Err(e) if i == 0 => return Err(e),
+                        Err(_) => break,
+                    }
+                }
+                Ok(batch.received)
+            }
+        }
+    }
+
+    /// Receives packets until the buffers are full or a time budget runs out
+    ///
+    /// Repeats [`recv_batch`](Self::recv_batch) into the remaining buffers,
+    /// waiting for more packets while the queue is empty, and returns
+    /// whatever accumulated once `budget` has elapsed. Tick-based servers
+    /// use this to drain input up to the frame boundary without overrunning
+    /// it. A zero budget makes a single non-waiting pass.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(count)` - Packets received, possibly 0 if none arrived in time
+    /// - `Err(e)` - A receive failed before any packet was received
+    ///
+    /// An error after the first packet ends the batch early and is not
+    /// reported: the packets received so far are returned instead. Errors
+    /// reported by the socket's error queue, such as `ConnectionRefused`
+    /// from an ICMP port unreachable, are consumed by the failed receive and
+    /// do not repeat on the next call. Use [`recv_batch`](Self::recv_batch)
+    /// when every error matters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::Udp};
+    /// use std::net::SocketAddr;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let socket = Udp::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+    /// let mut bufs: Vec<Vec<u8>> = (0..256).map(|_| Vec::with_capacity(1500)).collect();
+    /// let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 256];
+    /// let frame = Duration::from_micros(16_667);
+    ///
+    /// let next_tick = Instant::now() + frame;
+    /// let count = socket.recv_batch_budget(&mut bufs, &mut addrs, frame / 2)?;
+    /// // ... apply `count` inputs, simulate, then sleep until `next_tick`
+    /// # let _ = (count, next_tick);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Precision
+    ///
+    /// Waits use millisecond timeouts rounded up, so the call may return up
+    /// to a millisecond after the budget expires. Leave that much slack
+    /// before hard deadlines.
+    pub fn recv_batch_budget(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr], budget: std::time::Duration) -> io::Result<usize> {
+        let deadline = std::time::Instant::now() + budget;
+        let max = bufs.len().min(addrs.len());
+        let mut n = 0;
+        let res = loop {
+            if n == max {
+                break Ok(n);
+            }
+            match self.recv_batch_os(&mut bufs[n..max], &mut addrs[n..max]) {
+                Ok(k) if k > 0 => { n += k; continue; },
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) if n > 0 => break Ok(n),
+                Err(e) => break Err(e),
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                break Ok(n);
+            }
+            if let Err(e) = crate::poll::wait(&[self], crate::poll::Interest::READABLE, Some(remaining)) {
+                break if n > 0 { Ok(n) } else { Err(e) };
+            }
+        };
+        trace::event!(trace, requested = max, ?budget, result = ?res, "udp recv_batch_budget");
+        res
+    }
+
+    fn recv_arena_os(&self, arena: &mut RecvArena) -> io::Result<usize> {
+        let (stride, size) = (arena.stride(), arena.segment_size());
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let mut iovecs: Vec<libc::iovec> = arena
+                    .storage_mut()
+                    .chunks_exact_mut(stride)
+                    .map(|seg| libc::iovec { iov_base: seg.as_mut_ptr().cast(), iov_len: size })
+                    .collect();
+                // SAFETY: each iovec covers one segment of the arena, which outlives the call
+                unsafe {
+                    recvmmsg_batch(self, &mut iovecs, |batch| {
+                        let received_at = arena.now();
+                        let unknown = SocketAddr::from(([0, 0, 0, 0], 0));
+                        for i in 0..batch.received() {
+                            let (len, addr) = (batch.len(i), batch.addr(i).unwrap_or(unknown));
+                            arena.push(Segment { offset: i * stride, len, addr, received_at });
+                        }
+                        batch.received()
+                    })
+                }
+            } else {
+                let mut n = 0;
+                for i in 0..arena.capacity() {
+                    let offset = i * stride;
+                    let res = self.inner.recv_from(&mut arena.storage_mut()[offset..offset + size]);
+                    match res {
+                        Ok((len, addr)) => { arena.push(Segment { offset, len, addr, received_at: arena.now() }); n += 1; },
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    fn recv_batch_os(&self, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                recv_batch_linux(self, bufs, addrs)
             } else {
                 let mut n = 0;
-                for i in 0..bufs.len() {
-                    match self.inner.recv_from(&mut bufs[i]) {
-                        Ok((len, addr)) => { addrs[i] = addr; bufs[i].truncate(len); n += 1; },
+                for (buf, addr) in bufs.iter_mut().zip(addrs.iter_mut()) {
+                    // Receive into the whole capacity without zero-filling it first, as on Linux
+                    if buf.capacity() == 0 {
+                        buf.reserve_exact(2048);
+                    }
+                    buf.clear();
+                    match r::recv_from_uninit(r::os_handle(&self.inner), buf.spare_capacity_mut()) {
+                        Ok((len, from)) => {
+                            // SAFETY: recvfrom initialized the first len bytes of the spare capacity
+                            unsafe { buf.set_len(len) };
+                            *addr = from;
+                            n += 1;
+                        }
                         Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
                         Err(e) => return Err(e),
                     }
@@ -598,6 +990,120 @@ impl Udp {
         }
     }
 
+    /// Receives a single datagram without removing it from the queue
+    ///
+    /// A subsequent `peek_from` or receive call returns the same datagram.
+    /// Bytes that do not fit in `buf` are discarded from the returned copy
+    /// only; the queued datagram is left intact.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((len, addr))` - Bytes copied into `buf` and the sender address
+    /// - `Err(WouldBlock)` - No datagram is queued
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.peek_from(buf)
+    }
+
+    /// Receives a single datagram and reports its full length
+    ///
+    /// Uses `MSG_TRUNC`, so the returned length is the size of the datagram on
+    /// the wire even when `buf` was too small to hold it. If the length exceeds
+    /// `buf.len()`, only the first `buf.len()` bytes were copied and the rest
+    /// of the datagram was lost; callers can grow their buffers accordingly.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((datagram_len, addr))` - Full datagram length and the sender address
+    /// - `Err(WouldBlock)` - No datagram is queued
+    /// - `Err(Unsupported)` - The platform cannot report truncated lengths
+    ///
+    /// # Platform Support
+    ///
+    /// Linux and Android only.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::Udp};
+    ///
+    /// let socket = Udp::bind("0.0.0.0:8080".parse()?, &NetConfig::default())?;
+    /// let mut buf = [0u8; 512];
+    ///
+    /// let (len, addr) = socket.recv_truncated(&mut buf)?;
+    /// if len > buf.len() {
+    ///     eprintln!("{} sent a {} byte datagram; increase the buffer", addr, len);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn recv_truncated(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let mut ss: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+                let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                let rc = unsafe {
+                    libc::recvfrom(
+                        self.inner.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut _,
+                        buf.len(),
+                        libc::MSG_TRUNC | libc::MSG_DONTWAIT,
+                        &mut ss as *mut _ as *mut libc::sockaddr,
+                        &mut len,
+                    )
+                };
+                if rc < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let addr = r::from_sockaddr(&ss).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Unsupported sender address family")
+                })?;
+                Ok((rc as usize, addr))
+            } else {
+                let _ = buf;
+                Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "MSG_TRUNC length reporting is only available on Linux",
+                ))
+            }
+        }
+    }
+
+    /// Drains ICMP errors queued for this socket
+    ///
+    /// Requires [`NetConfig::recv_err`] to be enabled when the socket was
+    /// created. Each entry describes one error (port unreachable,
+    /// fragmentation needed with the path MTU, TTL exceeded, ...) along with
+    /// the destination and leading payload bytes of the packet that caused it.
+    ///
+    /// # Returns
+    ///
+    /// All currently queued errors, or an empty vector if there are none
+    ///
+    /// # Platform Support
+    ///
+    /// Linux and Android. Other platforms always return an empty vector.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::Udp};
+    ///
+    /// let cfg = NetConfig { recv_err: true, ..Default::default() };
+    /// let socket = Udp::bind("0.0.0.0:0".parse()?, &cfg)?;
+    /// for err in socket.drain_errors()? {
+    ///     println!("{:?} sending to {:?}", err.kind, err.destination);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn drain_errors(&self) -> io::Result<Vec<IcmpError>> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                crate::icmp::drain(self.inner.as_raw_fd())
+            } else {
+                Ok(Vec::new())
+            }
+        }
+    }
+
     /// Sends data to a specific address
     ///
     /// This method sends a single UDP packet to the specified destination address.
@@ -628,9 +1134,9 @@ impl Udp {
     ///     Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
     ///         println!("Send buffer full, retry later");
     ///     }
-    ///     Err(e) => return Err(e),
+    ///     Err(e) => return Err(e.into()),
     /// }
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Performance Notes
@@ -642,11 +1148,33 @@ impl Udp {
         self.inner.send_to(buf, addr)
     }
 
+    /// Sends the remaining bytes of `buf` as one datagram, advancing it on success
+    ///
+    /// `Bytes` and other contiguous buffers are sent without copying; a
+    /// chained `Buf` is gathered into one datagram first. On error `buf` is
+    /// left untouched, so a `WouldBlock` send can be retried.
+    #[cfg(feature = "bytes")]
+    pub fn send_buf_to<B: bytes::Buf>(&self, buf: &mut B, addr: SocketAddr) -> io::Result<usize> {
+        let sent = if buf.chunk().len() == buf.remaining() {
+            self.inner.send_to(buf.chunk(), addr)?
+        } else {
+            let mut iov = [io::IoSlice::new(&[]); 64];
+            let count = buf.chunks_vectored(&mut iov);
+            let mut datagram = Vec::with_capacity(buf.remaining());
+            for chunk in &iov[..count] {
+                datagram.extend_from_slice(chunk);
+            }
+            self.inner.send_to(&datagram, addr)?
+        };
+        buf.advance(sent.min(buf.remaining()));
+        Ok(sent)
+    }
+
     /// Sends multiple UDP packets in a batch operation
     ///
-    /// This method efficiently sends multiple packets by calling `send_to` in a loop
-    /// and stopping at the first `WouldBlock` error. This provides better performance
-    /// than individual send calls by reducing the overhead of error handling.
+    /// On Linux this uses `sendmmsg` to send up to 1024 packets per system
+    /// call; elsewhere it calls `send_to` in a loop. Either way it stops at
+    /// the first `WouldBlock` error.
     ///
     /// # Arguments
     ///
@@ -680,9 +1208,9 @@ impl Udp {
     ///             println!("Sent {}/{} packets (buffer full)", sent, packets.len());
     ///         }
     ///     }
-    ///     Err(e) => return Err(e),
+    ///     Err(e) => return Err(e.into()),
     /// }
-    /// # Ok::<(), std::io::Error>(())
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     ///
     /// # Performance Benefits
@@ -697,86 +1225,390 @@ impl Udp {
     /// - Returns count of successfully sent packets (may be less than input)
     /// - `WouldBlock` errors are handled internally, not returned to caller
     /// - Other errors (network unreachable, etc.) are returned immediately
+    /// - An error after some packets went out is returned as
+    ///   [`Error::PartialBatch`](crate::Error::PartialBatch) carrying the sent
+    ///   count; the packet that failed is the one at that index
     pub fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
-        let mut sent = 0;
-        for (buf, addr) in packets {
-            match self.send_to(buf, *addr) {
-                Ok(_) => sent += 1,
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                Err(e) => return Err(e),
+        let res = self.send_batch_os(packets);
+        trace::event!(trace, requested = packets.len(), result = ?res, "udp send_batch");
+        res
+    }
+
+    /// Sends the datagrams queued in a fixed-capacity batch without allocating
+    ///
+    /// Sent datagrams are removed from `batch`; whatever the socket could
+    /// not take stays queued, in order, for the next call. Errors follow
+    /// [`send_batch`](Self::send_batch).
+    ///
+    /// # Returns
+    ///
+    /// The number of datagrams sent
+    pub fn send_batch_fixed<const N: usize>(&self, batch: &mut FixedSendBatch<'_, N>) -> io::Result<usize> {
+        let res = self.send_fixed_os(batch);
+        trace::event!(trace, requested = batch.len(), result = ?res, "udp send_batch_fixed");
+        match &res {
+            Ok(sent) => batch.consume(*sent),
+            Err(e) => {
+                if let Some(Error::PartialBatch { sent, .. }) = Error::from_io(e) {
+                    batch.consume(*sent);
+                }
+            }
+        }
+        res
+    }
+
+    fn send_fixed_os<const N: usize>(&self, batch: &mut FixedSendBatch<'_, N>) -> io::Result<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                use std::os::fd::AsFd;
+                batch.send_mmsg(self.inner.as_fd())
+            } else {
+                let mut sent = 0;
+                for (buf, addr) in batch.iter() {
+                    match self.send_to(buf, addr) {
+                        Ok(_) => sent += 1,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(source) if sent > 0 => return Err(Error::PartialBatch { sent, source }.into()),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(sent)
+            }
+        }
+    }
+
+    fn send_batch_os(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                send_batch_linux(self, packets)
+            } else {
+                let mut sent = 0;
+                for (buf, addr) in packets {
+                    match self.send_to(buf, *addr) {
+                        Ok(_) => sent += 1,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(source) if sent > 0 => return Err(Error::PartialBatch { sent, source }.into()),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(sent)
+            }
+        }
+    }
+
+    /// Sends multiple UDP packets and reports the outcome of each one
+    ///
+    /// Unlike [`send_batch`](Self::send_batch), a hard error on one packet
+    /// (for example an unreachable destination) does not abort the batch:
+    /// it is recorded and the remaining packets are still attempted. Once the
+    /// socket reports `WouldBlock`, the send buffer is full, so that packet and
+    /// every packet after it are marked [`SendResult::WouldBlock`] without
+    /// further system calls.
+    ///
+    /// # Returns
+    ///
+    /// One [`SendResult`] per input packet, in the same order
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::{SendResult, Udp}};
+    /// use std::net::SocketAddr;
+    ///
+    /// let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+    /// let dest: SocketAddr = "127.0.0.1:8080".parse()?;
+    /// let packets = [(b"a".as_slice(), dest), (b"b".as_slice(), dest)];
+    ///
+    /// let mut retry = Vec::new();
+    /// for (pkt, result) in packets.iter().zip(socket.send_batch_status(&packets)) {
+    ///     match result {
+    ///         SendResult::Sent(_) => {}
+    ///         SendResult::WouldBlock => retry.push(*pkt),
+    ///         SendResult::Failed(e) => eprintln!("dropping packet to {}: {}", pkt.1, e),
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn send_batch_status(&self, packets: &[(&[u8], SocketAddr)]) -> Vec<SendResult> {
+        send_each_status(packets, |buf, addr| self.send_to(buf, addr))
+    }
+
+    /// Sends the same payload to every destination
+    ///
+    /// Fans a single packet out to many receivers, as in game state
+    /// broadcasts or market data distribution. On Linux all messages share
+    /// one iovec and go out through `sendmmsg`, up to 1024 destinations per
+    /// system call, so the payload is never copied in userspace.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(count)` - Destinations the packet was sent to, in order; fewer
+    ///   than `dests.len()` if the send buffer filled up
+    /// - `Err(other)` - System error for the first unsent destination
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use horizon_sockets::{NetConfig, udp::Udp};
+    /// use std::net::SocketAddr;
+    ///
+    /// let socket = Udp::bind("0.0.0.0:0".parse()?, &NetConfig::default())?;
+    /// let subscribers: Vec<SocketAddr> = vec!["10.0.0.2:7000".parse()?, "10.0.0.3:7000".parse()?];
+    ///
+    /// let snapshot = b"tick 42";
+    /// let sent = socket.send_to_many(snapshot, &subscribers)?;
+    /// if sent < subscribers.len() {
+    ///     // Retry subscribers[sent..] once the socket is writable again
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn send_to_many(&self, buf: &[u8], dests: &[SocketAddr]) -> io::Result<usize> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                send_to_many_linux(self, buf, dests)
+            } else {
+                let mut sent = 0;
+                for addr in dests {
+                    match self.send_to(buf, *addr) {
+                        Ok(_) => sent += 1,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(sent)
             }
         }
-        Ok(sent)
     }
 }
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
-unsafe fn recv_batch_linux(
-    sock: &Udp,
-    bufs: &mut [Vec<u8>],
-    addrs: &mut [SocketAddr],
-) -> io::Result<usize> {
-    use libc::*;
-    let fd = sock.inner.as_raw_fd();
-    let max = bufs.len().min(addrs.len());
+/// Creates two loopback UDP sockets connected to each other
+///
+/// Both sockets are bound to an ephemeral port on `127.0.0.1` with `cfg`
+/// applied and connected to the other, so datagrams can be exchanged with
+/// `socket().send`/`recv` and stray datagrams from other senders are
+/// filtered by the kernel. Useful for cross-thread signaling and tests.
+///
+/// # Examples
+///
+/// ```rust
+/// use horizon_sockets::{NetConfig, udp};
+///
+/// let (a, b) = udp::socket_pair(&NetConfig::default())?;
+/// a.socket().send(b"ping")?;
+/// assert_eq!(b.peer_addr()?, a.local_addr()?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn socket_pair(cfg: &NetConfig) -> io::Result<(Udp, Udp)> {
+    let loopback = SocketAddr::from(([127, 0, 0, 1], 0));
+    let a = Udp::bind(loopback, cfg)?;
+    let b = Udp::bind(loopback, cfg)?;
+    a.inner.connect(b.local_addr()?)?;
+    b.inner.connect(a.local_addr()?)?;
+    Ok((a, b))
+}
+
+/// Outcome of one packet in [`Udp::send_batch_status`]
+#[derive(Debug)]
+pub enum SendResult {
+    /// The packet was handed to the kernel; holds the number of bytes sent
+    Sent(usize),
+    /// The send buffer was full; the packet was not sent and can be retried
+    WouldBlock,
+    /// The send failed with a hard error; retrying is unlikely to help
+    Failed(io::Error),
+}
+
+impl SendResult {
+    /// Returns `true` if the packet was sent
+    pub fn is_sent(&self) -> bool {
+        matches!(self, SendResult::Sent(_))
+    }
+
+    /// Returns `true` if the packet hit backpressure and should be retried
+    pub fn is_would_block(&self) -> bool {
+        matches!(self, SendResult::WouldBlock)
+    }
+}
 
-    let mut hdrs: Vec<mmsghdr> = Vec::with_capacity(max);
-    let mut iovecs: Vec<iovec> = Vec::with_capacity(max);
-    let mut addrs_raw: Vec<sockaddr_storage> = Vec::with_capacity(max);
+/// Sends each packet with `send`, recording per-packet results
+///
+/// Shared by the socket types that implement per-packet batch status.
+pub(crate) fn send_each_status<F>(packets: &[(&[u8], SocketAddr)], mut send: F) -> Vec<SendResult>
+where
+    F: FnMut(&[u8], SocketAddr) -> io::Result<usize>,
+{
+    let mut results = Vec::with_capacity(packets.len());
+    for (buf, addr) in packets {
+        match send(buf, *addr) {
+            Ok(n) => results.push(SendResult::Sent(n)),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => results.push(SendResult::Failed(e)),
+        }
+    }
+    results.resize_with(packets.len(), || SendResult::WouldBlock);
+    results
+}
+
+impl From<Udp> for StdUdpSocket {
+    fn from(socket: Udp) -> Self {
+        socket.inner
+    }
+}
 
+impl TryFrom<StdUdpSocket> for Udp {
+    type Error = io::Error;
+
+    /// Wraps an existing socket, switching it to non-blocking mode
+    ///
+    /// Options already set on the socket are preserved; no `NetConfig` is applied.
+    fn try_from(socket: StdUdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self { _tracked: Tracked::new("udp", r::os_handle(&socket)), inner: socket, tuner: None, recv_cache: RecvCache::default() })
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Udp {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsFd for Udp {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for Udp {
+    fn as_raw_socket(&self) -> std::os::windows::io::RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsSocket for Udp {
+    fn as_socket(&self) -> std::os::windows::io::BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
+#[cfg(all(feature = "mio-runtime", unix))]
+impl mio::event::Source for Udp {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.inner.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn recv_batch_linux(sock: &Udp, bufs: &mut [Vec<u8>], addrs: &mut [SocketAddr]) -> io::Result<usize> {
+    let max = bufs.len().min(addrs.len());
+    // Receive into the whole capacity without zero-filling it first
+    let mut iovecs: Vec<libc::iovec> = bufs[..max]
+        .iter_mut()
+        .map(|buf| {
+            if buf.capacity() == 0 {
+                buf.reserve_exact(2048);
+            }
+            buf.clear();
+            let spare = buf.spare_capacity_mut();
+            libc::iovec { iov_base: spare.as_mut_ptr().cast(), iov_len: spare.len() }
+        })
+        .collect();
+    // SAFETY: each iovec covers the spare capacity of a buffer that outlives the call
     unsafe {
-        hdrs.set_len(max);
-        iovecs.set_len(max);
-        addrs_raw.set_len(max);
+        recvmmsg_batch(sock, &mut iovecs, |batch| {
+            for i in 0..batch.received() {
+                // SAFETY: the kernel initialized the first msg_len bytes, which fit the capacity
+                bufs[i].set_len(batch.len(i).min(bufs[i].capacity()));
+                if let Some(addr) = batch.addr(i) {
+                    addrs[i] = addr;
+                }
+            }
+            batch.received()
+        })
     }
+}
 
-    for i in 0..max {
-        let buf = &mut bufs[i];
-        if buf.capacity() == 0 {
-            buf.reserve_exact(2048);
-            buf.resize(2048, 0);
+/// Receives one packet per iovec with a single `recvmmsg` call and passes the result to `read`
+///
+/// Reuses the socket's cached headers unless another thread is receiving
+/// on it at the same time. Feeds the SO_RXQ_OVFL counter to the buffer tuner.
+///
+/// # Safety
+///
+/// Every iovec must describe memory valid for writes of `iov_len` bytes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn recvmmsg_batch<R>(
+    sock: &Udp,
+    iovecs: &mut [libc::iovec],
+    read: impl FnOnce(&crate::mmsg::RecvBatch) -> R,
+) -> io::Result<R> {
+    use crate::mmsg::{RecvBatch, MAX_BATCH};
+    use std::os::fd::AsFd;
+    use std::sync::TryLockError;
+
+    // Room for the SO_RXQ_OVFL control message when auto-tuning
+    let control_len = if sock.tuner.is_some() { 64 } else { 0 };
+    let wanted = iovecs.len().min(MAX_BATCH);
+    let mut cached = match sock.recv_cache.batch.try_lock() {
+        Ok(guard) => Some(guard),
+        // The storage holds no invariants a panic could break
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    let mut fresh = None;
+    let batch = match cached.as_deref_mut() {
+        Some(slot) => match slot {
+            Some(batch) if batch.capacity() >= wanted => batch,
+            _ => slot.insert(RecvBatch::new(wanted, control_len)),
+        },
+        None => fresh.insert(RecvBatch::new(wanted, control_len)),
+    };
+    unsafe { batch.recv_iovecs(sock.inner.as_fd(), iovecs, libc::MSG_DONTWAIT)? };
+
+    // The counter is cumulative, so the newest packet carries the latest value
+    #[cfg(target_os = "linux")]
+    if let (Some(tuner), Some(last)) = (sock.tuner.as_ref(), batch.received().checked_sub(1)) {
+        for cmsg in crate::parse::cmsgs(batch.control(last)) {
+            if cmsg.level == libc::SOL_SOCKET && cmsg.kind == libc::SO_RXQ_OVFL {
+                if let Some(drops) = cmsg.data.get(..4) {
+                    tuner.observe(sock.inner.as_raw_fd(), u32::from_ne_bytes(drops.try_into().unwrap()));
+                }
+            }
         }
-        let iov = iovec {
-            iov_base: buf.as_mut_ptr() as _,
-            iov_len: buf.len(),
-        };
-        iovecs[i] = iov;
-        hdrs[i].msg_hdr = msghdr {
-            msg_name: &mut addrs_raw[i] as *mut _ as *mut _,
-            msg_namelen: std::mem::size_of::<sockaddr_storage>() as _,
-            msg_iov: &mut iovecs[i] as *mut _,
-            msg_iovlen: 1,
-            msg_control: std::ptr::null_mut(),
-            msg_controllen: 0,
-            msg_flags: 0,
-        };
-        hdrs[i].msg_len = 0;
-    }
-
-    let rc = unsafe { recvmmsg(fd, hdrs.as_mut_ptr(), max as u32, MSG_DONTWAIT, std::ptr::null_mut()) };
-    if rc < 0 { return Err(std::io::Error::last_os_error()); }
-    let n = rc as usize;
-
-    for i in 0..n {
-        let len = hdrs[i].msg_len as usize;
-        bufs[i].truncate(len);
-        // Convert sockaddr_storage -> SocketAddr
-        let ss = &addrs_raw[i];
-        let sa = unsafe { &*(ss as *const _ as *const sockaddr) };
-        let addr = if sa.sa_family as i32 == AF_INET { 
-            let sin = unsafe { &*(ss as *const _ as *const sockaddr_in) };
-            let ip = std::net::Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
-            let port = u16::from_be(sin.sin_port);
-            SocketAddr::new(ip.into(), port)
-        } else {
-            let sin6 = unsafe { &*(ss as *const _ as *const sockaddr_in6) };
-            let ip = std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr);
-            let port = u16::from_be(sin6.sin6_port);
-            SocketAddr::new(ip.into(), port)
-        };
-        addrs[i] = addr;
     }
-    Ok(n)
+    Ok(read(batch))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_to_many_linux(sock: &Udp, buf: &[u8], dests: &[SocketAddr]) -> io::Result<usize> {
+    use std::os::fd::AsFd;
+    let mut batch = crate::mmsg::SendBatch::with_capacity(dests.len());
+    for dest in dests {
+        batch.push(buf, *dest);
+    }
+    batch.send(sock.inner.as_fd(), libc::MSG_DONTWAIT)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_batch_linux(sock: &Udp, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+    use std::os::fd::AsFd;
+    let mut batch = crate::mmsg::SendBatch::with_capacity(packets.len());
+    for (buf, dest) in packets {
+        batch.push(buf, *dest);
+    }
+    batch.send(sock.inner.as_fd(), libc::MSG_DONTWAIT)
 }
 
 #[cfg(test)]
@@ -787,8 +1619,7 @@ mod tests {
 
     #[test]
     fn test_udp_bind() {
-        let mut config = NetConfig::default();
-        config.ipv6_only = None; // Let system decide
+        let config = NetConfig { ipv6_only: None, ..Default::default() }; // Let system decide
         let result = Udp::bind("127.0.0.1:0".parse().unwrap(), &config);
         if let Err(e) = &result {
             eprintln!("UDP bind failed: {}", e);
@@ -806,8 +1637,7 @@ mod tests {
 
     #[test]
     fn test_send_to() {
-        let mut config = NetConfig::default();
-        config.ipv6_only = None;
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
         let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
 
         // Send to a likely unused port - this should succeed (UDP is connectionless)
@@ -817,8 +1647,7 @@ mod tests {
 
     #[test]
     fn test_recv_batch_empty() {
-        let mut config = NetConfig::default();
-        config.ipv6_only = None;
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
         let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
 
         let mut bufs: Vec<Vec<u8>> = Vec::new();
@@ -828,10 +1657,26 @@ mod tests {
         assert_eq!(result.unwrap(), 0);
     }
 
+    #[test]
+    fn test_send_to_many() {
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let receivers: Vec<_> = (0..3).map(|_| Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap()).collect();
+        let dests: Vec<SocketAddr> = receivers.iter().map(|r| r.local_addr().unwrap()).collect();
+
+        assert_eq!(tx.send_to_many(b"state", &dests).unwrap(), 3);
+        assert_eq!(tx.send_to_many(b"state", &[]).unwrap(), 0);
+        for rx in &receivers {
+            let mut buf = [0u8; 16];
+            let (len, from) = crate::retry::retry(&mut crate::retry::Backoff::new(), || rx.socket().recv_from(&mut buf)).unwrap();
+            assert_eq!(&buf[..len], b"state");
+            assert_eq!(from, tx.local_addr().unwrap());
+        }
+    }
+
     #[test]
     fn test_send_batch() {
-        let mut config = NetConfig::default();
-        config.ipv6_only = None;
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
         let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
 
         let dest = "127.0.0.1:9999".parse().unwrap();
@@ -841,4 +1686,316 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 2);
     }
+
+    #[cfg(all(feature = "mio-runtime", unix))]
+    #[test]
+    fn test_udp_mio_source_readable() {
+        use mio::{Events, Interest, Poll, Token};
+
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let mut rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+
+        let mut poll = Poll::new().unwrap();
+        poll.registry().register(&mut rx, Token(7), Interest::READABLE).unwrap();
+        tx.send_to(b"wake", rx.socket().local_addr().unwrap()).unwrap();
+
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(std::time::Duration::from_secs(1))).unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(7) && e.is_readable()));
+    }
+
+    #[test]
+    fn test_try_clone_shares_socket() {
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let clone = socket.try_clone().unwrap();
+        let addr = socket.socket().local_addr().unwrap();
+        assert_eq!(clone.socket().local_addr().unwrap(), addr);
+
+        clone.send_to(b"self", addr).unwrap();
+        let mut bufs = vec![vec![0u8; 16]];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0))];
+        for _ in 0..100 {
+            if let Ok(1) = socket.recv_batch(&mut bufs, &mut addrs) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(bufs[0], b"self");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cookie_identifies_socket_not_descriptor() {
+        let config = NetConfig::default();
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let cookie = socket.cookie().unwrap();
+        assert_ne!(cookie, 0);
+        // A duplicated descriptor refers to the same socket
+        assert_eq!(socket.try_clone().unwrap().cookie().unwrap(), cookie);
+
+        // A socket created later, even on a reused descriptor number, gets a new cookie
+        drop(socket);
+        let other = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        assert_ne!(other.cookie().unwrap(), cookie);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_peek_then_recv_truncated() {
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let from = tx.socket().local_addr().unwrap();
+        tx.send_to(&[7u8; 100], rx.socket().local_addr().unwrap()).unwrap();
+
+        let mut small = [0u8; 10];
+        let peeked = loop {
+            match rx.peek_from(&mut small) {
+                Ok(res) => break res,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(e) => panic!("peek failed: {}", e),
+            }
+        };
+        assert_eq!(peeked, (10, from));
+
+        assert_eq!(rx.recv_truncated(&mut small).unwrap(), (100, from));
+        assert_eq!(small, [7u8; 10]);
+        assert_eq!(rx.recv_truncated(&mut small).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_bind_applies_config() {
+        let config = NetConfig { ipv6_only: None, so_priority: Some(5), recv_buf: Some(64 * 1024), ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let getopt = |opt| {
+            let mut val: libc::c_int = 0;
+            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+            let fd = socket.socket().as_raw_fd();
+            assert_eq!(unsafe { libc::getsockopt(fd, libc::SOL_SOCKET, opt, &mut val as *mut _ as *mut _, &mut len) }, 0);
+            val
+        };
+        assert_eq!(getopt(libc::SO_PRIORITY), 5);
+        assert_eq!(getopt(libc::SO_REUSEPORT), 1);
+        // The kernel doubles the requested size for bookkeeping overhead
+        assert!(getopt(libc::SO_RCVBUF) >= 64 * 1024);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_drain_errors_reports_port_unreachable() {
+        use crate::icmp::IcmpErrorKind;
+
+        let config = NetConfig { ipv6_only: None, recv_err: true, ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        // Reserve a port, then close it so nothing is listening there
+        let closed = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap().local_addr().unwrap();
+
+        socket.send_to(b"anyone?", closed).unwrap();
+        let mut errors = Vec::new();
+        for _ in 0..100 {
+            errors = socket.drain_errors().unwrap();
+            if !errors.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, IcmpErrorKind::PortUnreachable);
+        assert_eq!(errors[0].destination, Some(closed));
+        assert_eq!(errors[0].payload, b"anyone?");
+        assert!(socket.drain_errors().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_send_batch_reports_partial_batch() {
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let ok = socket.local_addr().unwrap();
+        let bad: SocketAddr = "[::1]:9".parse().unwrap(); // wrong family for an IPv4 socket
+
+        let err = socket.send_batch(&[(b"one", ok), (b"two", ok), (b"three", bad)]).unwrap_err();
+        assert!(matches!(Error::from_io(&err), Some(Error::PartialBatch { sent: 2, .. })), "{err:?}");
+        assert!(Error::from_io(&socket.send_batch(&[(b"four", bad)]).unwrap_err()).is_none());
+    }
+
+    #[test]
+    fn test_send_batch_status_continues_after_error() {
+        let config = NetConfig { ipv6_only: None, ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let ok = socket.local_addr().unwrap();
+        let bad: SocketAddr = "[::1]:9".parse().unwrap(); // wrong family for an IPv4 socket
+
+        let results = socket.send_batch_status(&[(b"one", ok), (b"two", bad), (b"three", ok)]);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], SendResult::Sent(3)));
+        assert!(matches!(results[1], SendResult::Failed(_)));
+        assert!(matches!(results[2], SendResult::Sent(5)));
+    }
+
+    #[test]
+    fn test_send_each_status_marks_rest_would_block() {
+        let dest: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        let mut calls = 0;
+        let results = send_each_status(&[(b"a", dest), (b"b", dest), (b"c", dest)], |buf, _| {
+            calls += 1;
+            if calls == 2 { Err(io::ErrorKind::WouldBlock.into()) } else { Ok(buf.len()) }
+        });
+        assert_eq!(calls, 2);
+        assert!(results[0].is_sent());
+        assert!(results[1].is_would_block());
+        assert!(results[2].is_would_block());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_auto_tune_grows_on_drops() {
+        let cfg = NetConfig { ipv6_only: None, recv_buf: Some(4096), auto_tune_buffers: Some(1 << 20), ..Default::default() };
+        let socket = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        assert_eq!(socket.auto_tuned_recv_buf(), Some(4096));
+
+        // Overflow the tiny receive queue so the kernel drops packets
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap();
+        for _ in 0..200 {
+            sender.send_to(&[0u8; 1000], target).unwrap();
+        }
+
+        let mut bufs = vec![vec![0u8; 1500]; 8];
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 8];
+        while socket.recv_batch(&mut bufs, &mut addrs).is_ok() {
+            bufs.iter_mut().for_each(|b| b.resize(1500, 0));
+        }
+        // Packets carry the drop count at the time they were queued
+        sender.send_to(b"after", target).unwrap();
+        assert_eq!(socket.recv_batch(&mut bufs, &mut addrs).unwrap(), 1);
+        assert!(socket.rx_drops().unwrap() > 0);
+        assert_eq!(socket.auto_tuned_recv_buf(), Some(8192));
+        assert_eq!(socket.try_clone().unwrap().auto_tuned_recv_buf(), Some(8192));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_lease_flow_label_for_sends() {
+        let cfg = NetConfig { flow_label: Some(0x2345), ..Default::default() };
+        if StdUdpSocket::bind("[::1]:0").is_err() {
+            return; // No IPv6 loopback in this environment
+        }
+        let sender = Udp::bind("[::1]:0".parse().unwrap(), &cfg).unwrap();
+        let receiver = Udp::bind("[::1]:0".parse().unwrap(), &NetConfig::default()).unwrap();
+        let dest = receiver.local_addr().unwrap();
+
+        // Report the flow information of each packet received
+        let on: libc::c_int = 1;
+        let fd = receiver.as_raw_fd();
+        let rc = unsafe {
+            libc::setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_FLOWINFO, &on as *const _ as _, size_of_val(&on) as _)
+        };
+        assert_eq!(rc, 0);
+        let recv_label = || {
+            let mut buf = [0u8; 64];
+            let mut control = [0u64; 8];
+            let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+            let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = size_of_val(&control) as _;
+            crate::poll::wait(&[&receiver], crate::poll::Interest::READABLE, Some(std::time::Duration::from_secs(1))).unwrap();
+            assert!(unsafe { libc::recvmsg(fd, &mut msg, 0) } > 0);
+            let control = unsafe { std::slice::from_raw_parts(control.as_ptr().cast::<u8>(), msg.msg_controllen as usize) };
+            crate::parse::cmsgs(control)
+                .find(|c| c.level == libc::IPPROTO_IPV6 && c.kind == libc::IPV6_FLOWINFO)
+                .map(|c| u32::from_be_bytes(c.data[..4].try_into().unwrap()) & libc::IPV6_FLOWINFO_FLOWLABEL as u32)
+        };
+
+        let labeled = sender.lease_flow_label(dest, 0x2345).unwrap();
+        assert_eq!(labeled, cfg.flow_dest(dest));
+        sender.send_to(b"labeled", labeled).unwrap();
+        assert_eq!(recv_label(), Some(0x2345));
+        // Kernel-picked labels work the same way
+        let picked = sender.lease_flow_label(dest, 0).unwrap();
+        sender.send_to(b"picked", picked).unwrap();
+        let SocketAddr::V6(picked) = picked else { unreachable!() };
+        assert_eq!(recv_label(), Some(u32::from_be(picked.flowinfo())));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_send_buf_to_gathers_chained_buffers() {
+        use bytes::{Buf, Bytes};
+        let cfg = NetConfig::default();
+        let sender = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let receiver = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let dest = receiver.local_addr().unwrap();
+
+        let mut whole = Bytes::from_static(b"contiguous");
+        assert_eq!(sender.send_buf_to(&mut whole, dest).unwrap(), 10);
+        assert!(!whole.has_remaining());
+        let mut chain = Bytes::from_static(b"head:").chain(Bytes::from_static(b"tail"));
+        assert_eq!(sender.send_buf_to(&mut chain, dest).unwrap(), 9);
+
+        let mut buf = [0u8; 64];
+        receiver.socket().set_nonblocking(false).unwrap();
+        let (n, _) = receiver.socket().recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"contiguous");
+        let (n, _) = receiver.socket().recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"head:tail");
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_recv_batch_fills_buffer_capacity() {
+        let cfg = NetConfig::default();
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        tx.send_to(&[7u8; 1200], rx.local_addr().unwrap()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        // Empty buffers with spare capacity receive whole packets
+        let mut bufs: Vec<Vec<u8>> = (0..4).map(|_| Vec::with_capacity(2048)).collect();
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 4];
+        assert_eq!(rx.recv_batch(&mut bufs, &mut addrs).unwrap(), 1);
+        assert_eq!(bufs[0].len(), 1200);
+        assert_eq!(addrs[0], tx.local_addr().unwrap());
+
+        // A larger batch than the cached headers cover grows them
+        for i in 0..6u8 {
+            tx.send_to(&[i; 10], rx.local_addr().unwrap()).unwrap();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let mut bufs: Vec<Vec<u8>> = (0..8).map(|_| Vec::with_capacity(64)).collect();
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 8];
+        assert_eq!(rx.recv_batch(&mut bufs, &mut addrs).unwrap(), 6);
+        assert_eq!(bufs[5], [5u8; 10]);
+    }
+
+    #[test]
+    fn test_recv_batch_budget_returns_at_deadline() {
+        let cfg = NetConfig::default();
+        let rx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let tx = Udp::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let dest = rx.local_addr().unwrap();
+        let mut bufs: Vec<Vec<u8>> = (0..8).map(|_| Vec::with_capacity(64)).collect();
+        let mut addrs = vec![SocketAddr::from(([0, 0, 0, 0], 0)); 8];
+
+        // Nothing arrives: Ok(0) once the budget is spent, not WouldBlock
+        let start = std::time::Instant::now();
+        assert_eq!(rx.recv_batch_budget(&mut bufs, &mut addrs, std::time::Duration::from_millis(30)).unwrap(), 0);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(30));
+
+        // Packets arriving mid-budget are collected into later buffers
+        let sender = std::thread::spawn(move || {
+            tx.send_to(b"first", dest).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            tx.send_to(b"second", dest).unwrap();
+        });
+        let n = rx.recv_batch_budget(&mut bufs, &mut addrs, std::time::Duration::from_millis(200)).unwrap();
+        sender.join().unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(bufs[0], b"first");
+        assert_eq!(bufs[1], b"second");
+    }
 }
@@ -0,0 +1,238 @@
+//! Vectored writes of responses assembled from several buffers
+//!
+//! HTTP-like servers send a response made of pieces that live in different
+//! places: a static status line, formatted headers, and a body from a
+//! buffer pool. Copying them into one buffer costs a memcpy of the body per
+//! response; writing them one by one costs a system call (and possibly a
+//! small packet) per piece. [`WriteBatch`] keeps the pieces where they are
+//! and hands them to the kernel with one vectored write (`writev`/`WSASend`).
+//!
+//! On a nonblocking stream the kernel may take only part of the batch.
+//! The batch remembers how far it got, so the next
+//! [`write_to`](WriteBatch::write_to) after the socket becomes writable
+//! continues from the first unwritten byte.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use horizon_sockets::{NetConfig, buffer_pool::BufferPool, tcp::TcpStream};
+//! use horizon_sockets::write_batch::WriteBatch;
+//!
+//! let pool = BufferPool::new(64, 16 * 1024);
+//! let mut stream = TcpStream::connect("127.0.0.1:8080".parse()?, &NetConfig::default())?;
+//!
+//! let mut body = pool.acquire();
+//! body.extend_from_slice(b"{\"ok\":true}");
+//! let headers = format!("Content-Length: {}\r\n\r\n", body.len());
+//!
+//! let mut response = WriteBatch::new();
+//! response.push(&b"HTTP/1.1 200 OK\r\n"[..]).push(headers.into_bytes()).push(body);
+//!
+//! while !response.write_to(&mut stream)? {
+//!     // WouldBlock: wait for writability, then call write_to again
+//! }
+//! response.release_into(&pool);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::buffer_pool::BufferPool;
+use std::borrow::Cow;
+use std::io::{self, IoSlice, Write};
+
+/// Slices handed to one vectored write call
+const MAX_IOV: usize = 64;
+
+/// Byte slices written with vectored writes, resumable after `WouldBlock`
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatch<'a> {
+    chunks: Vec<Cow<'a, [u8]>>,
+    /// First chunk not completely written
+    next: usize,
+    /// Bytes of `chunks[next]` already written
+    offset: usize,
+}
+
+impl<'a> WriteBatch<'a> {
+    /// Creates an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty batch with room for `chunks` pieces
+    pub fn with_capacity(chunks: usize) -> Self {
+        Self { chunks: Vec::with_capacity(chunks), next: 0, offset: 0 }
+    }
+
+    /// Appends a piece: a borrowed slice or an owned (e.g. pooled) buffer
+    ///
+    /// Empty pieces are skipped.
+    pub fn push(&mut self, chunk: impl Into<Cow<'a, [u8]>>) -> &mut Self {
+        let chunk = chunk.into();
+        if !chunk.is_empty() {
+            self.chunks.push(chunk);
+        }
+        self
+    }
+
+    /// Returns the number of bytes not yet written
+    pub fn remaining(&self) -> usize {
+        self.chunks[self.next.min(self.chunks.len())..].iter().map(|c| c.len()).sum::<usize>() - self.offset
+    }
+
+    /// Returns `true` if every byte has been written
+    pub fn is_done(&self) -> bool {
+        self.next >= self.chunks.len()
+    }
+
+    /// Writes as much of the batch as `w` accepts
+    ///
+    /// # Returns
+    ///
+    /// `true` once the whole batch is written, `false` if the stream
+    /// returned `WouldBlock` with bytes still pending
+    ///
+    /// # Errors
+    /// - The stream accepts zero bytes (`WriteZero`)
+    /// - Any other write error; progress made before it is kept
+    pub fn write_to<W: Write + ?Sized>(&mut self, w: &mut W) -> io::Result<bool> {
+        while !self.is_done() {
+            let mut iov = [IoSlice::new(&[]); MAX_IOV];
+            let mut count = 0;
+            for (i, chunk) in self.chunks[self.next..].iter().take(MAX_IOV).enumerate() {
+                let skip = if i == 0 { self.offset } else { 0 };
+                iov[i] = IoSlice::new(&chunk[skip..]);
+                count += 1;
+            }
+            match w.write_vectored(&iov[..count]) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "Stream accepted no bytes")),
+                Ok(n) => self.advance(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drops all pieces and resets progress, keeping the allocation
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.next = 0;
+        self.offset = 0;
+    }
+
+    /// Returns owned pieces to `pool` and clears the batch
+    ///
+    /// Borrowed pieces, and owned ones smaller than the pool's
+    /// [`default_capacity`](BufferPool::default_capacity), are simply
+    /// dropped, so the pool never hands out an undersized buffer.
+    pub fn release_into(&mut self, pool: &BufferPool) {
+        for chunk in self.chunks.drain(..) {
+            match chunk {
+                Cow::Owned(buf) if buf.capacity() >= pool.default_capacity() => pool.release(buf),
+                _ => {}
+            }
+        }
+        self.clear();
+    }
+
+    /// Marks `n` more bytes as written
+    fn advance(&mut self, mut n: usize) {
+        while n > 0 && self.next < self.chunks.len() {
+            let left = self.chunks[self.next].len() - self.offset;
+            if n < left {
+                self.offset += n;
+                return;
+            }
+            n -= left;
+            self.next += 1;
+            self.offset = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer accepting at most `quota` bytes per call, then `WouldBlock` once
+    struct Trickle {
+        out: Vec<u8>,
+        quota: usize,
+        blocked: bool,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            if std::mem::take(&mut self.blocked) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.blocked = true;
+            let mut n = 0;
+            for buf in bufs {
+                let take = buf.len().min(self.quota - n);
+                self.out.extend_from_slice(&buf[..take]);
+                n += take;
+            }
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resumes_after_partial_write() {
+        let pool = BufferPool::new(1, 16);
+        let mut body = pool.acquire();
+        body.extend_from_slice(b"hello world");
+
+        let mut batch = WriteBatch::new();
+        batch.push(&b"HTTP/1.1 200 OK\r\n"[..]).push(Vec::new()).push(b"\r\n".to_vec()).push(body);
+        assert_eq!(batch.remaining(), 30);
+
+        let mut w = Trickle { out: Vec::new(), quota: 7, blocked: false };
+        let mut calls = 0;
+        while !batch.write_to(&mut w).unwrap() {
+            calls += 1;
+        }
+        assert_eq!(calls, 4);
+        assert_eq!(w.out, b"HTTP/1.1 200 OK\r\n\r\nhello world");
+        assert_eq!(batch.remaining(), 0);
+
+        let before = pool.available_count();
+        batch.release_into(&pool);
+        // Only the body is pool-sized; the small vectors are dropped
+        assert_eq!(pool.available_count(), before + 1);
+        assert!(pool.acquire().capacity() >= 16);
+        assert!(batch.is_done());
+    }
+
+    #[test]
+    fn test_tcp_single_writev() {
+        use crate::{tcp::TcpListener, tcp::TcpStream, NetConfig};
+        use std::io::Read;
+
+        let cfg = NetConfig::default();
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap(), &cfg).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap(), &cfg).unwrap();
+        let (mut server, _) = listener.as_std().accept().unwrap();
+
+        let chunks: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i; 3]).collect();
+        let mut batch = WriteBatch::with_capacity(chunks.len());
+        for chunk in &chunks {
+            batch.push(chunk.as_slice());
+        }
+        assert!(batch.write_to(&mut client).unwrap());
+        drop(client);
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).unwrap();
+        assert_eq!(received, chunks.concat());
+    }
+}